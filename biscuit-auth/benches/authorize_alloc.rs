@@ -0,0 +1,118 @@
+//! compares `authorize` and `authorize_in_place`
+//!
+//! `authorize_in_place` still allocates, to load the authorizer's block and to
+//! evaluate the checks, but it must not allocate more than `authorize`, and
+//! must allocate less when checks fail, since their source is not rendered.
+//! The allocations are counted with dhat before measuring the latency.
+extern crate biscuit_auth as biscuit;
+
+use std::time::Duration;
+
+use biscuit::{builder_ext::BuilderExt, Authorizer, AuthorizerLimits, Biscuit, KeyPair};
+use codspeed_bencher_compat::{benchmark_group, benchmark_main, Bencher};
+
+#[global_allocator]
+static ALLOC: dhat::Alloc = dhat::Alloc;
+
+fn authorizer(success: bool) -> Authorizer {
+    let root = KeyPair::new();
+
+    let mut builder = Biscuit::builder();
+    builder
+        .add_code(
+            r#"right("file1", "read");
+            right("file2", "read");
+            right("file1", "write");"#,
+        )
+        .unwrap();
+    let token = builder.build(&root).unwrap();
+
+    let mut block_builder = biscuit::builder::BlockBuilder::new();
+    block_builder.check_resource("file1");
+    block_builder.check_operation("read");
+    let token = token.append(block_builder).unwrap();
+
+    let mut authorizer = token.authorizer().unwrap();
+    authorizer.add_resource("file1");
+    authorizer.add_operation(if success { "read" } else { "write" });
+    authorizer
+        .add_code("allow if resource($r), operation($op), right($r, $op)")
+        .unwrap();
+    authorizer.set_limits(AuthorizerLimits {
+        max_time: Duration::from_secs(10),
+        ..Default::default()
+    });
+
+    authorizer
+}
+
+/// counts the allocations of one authorization, on a copy of the authorizer
+fn allocations<F: FnOnce(&mut Authorizer)>(template: &Authorizer, f: F) -> u64 {
+    let mut authorizer = template.clone();
+
+    let _profiler = dhat::Profiler::builder().testing().build();
+    let before = dhat::HeapStats::get();
+    f(&mut authorizer);
+    dhat::HeapStats::get().total_blocks - before.total_blocks
+}
+
+fn authorize_success(b: &mut Bencher) {
+    let template = authorizer(true);
+
+    b.iter(|| {
+        let mut authorizer = template.clone();
+        authorizer.authorize().unwrap();
+    });
+}
+
+fn authorize_in_place_success(b: &mut Bencher) {
+    let template = authorizer(true);
+
+    let authorize = allocations(&template, |a| {
+        a.authorize().unwrap();
+    });
+    let in_place = allocations(&template, |a| {
+        a.authorize_in_place().unwrap();
+    });
+    assert!(in_place <= authorize);
+
+    b.iter(|| {
+        let mut authorizer = template.clone();
+        authorizer.authorize_in_place().unwrap();
+    });
+}
+
+fn authorize_failure(b: &mut Bencher) {
+    let template = authorizer(false);
+
+    b.iter(|| {
+        let mut authorizer = template.clone();
+        authorizer.authorize().unwrap_err();
+    });
+}
+
+fn authorize_in_place_failure(b: &mut Bencher) {
+    let template = authorizer(false);
+
+    let authorize = allocations(&template, |a| {
+        a.authorize().unwrap_err();
+    });
+    let in_place = allocations(&template, |a| {
+        a.authorize_in_place().unwrap_err();
+    });
+    assert!(in_place < authorize);
+
+    b.iter(|| {
+        let mut authorizer = template.clone();
+        authorizer.authorize_in_place().unwrap_err();
+    });
+}
+
+benchmark_group!(
+    benchmarks,
+    authorize_success,
+    authorize_in_place_success,
+    authorize_failure,
+    authorize_in_place_failure
+);
+benchmark_main!(benchmarks);
@@ -602,6 +602,92 @@ fn checks_block_verify_only2(b: &mut Bencher) {
     });
 }
 
+fn checks_repeated_expressions(b: &mut Bencher) {
+    let mut rng: OsRng = OsRng;
+    let root = KeyPair::new_with_rng(&mut rng);
+
+    let allowed = (0..200)
+        .map(|i| format!("\"user{}\"", i))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let data = {
+        let mut builder = Biscuit::builder();
+        for i in 0..20 {
+            builder
+                .add_fact(fact("user", &[string(&format!("user{}", i))]))
+                .unwrap();
+            builder
+                .add_fact(fact("resource", &[string(&format!("file{}", i))]))
+                .unwrap();
+        }
+        builder
+            .add_check(
+                format!(
+                    "check all user($u), resource($r), [{}].contains($u), $r.starts_with(\"file\")",
+                    allowed
+                )
+                .as_str(),
+            )
+            .unwrap();
+
+        let token = builder
+            .build_with_rng(&root, SymbolTable::default(), &mut rng)
+            .unwrap();
+        token.to_vec().unwrap()
+    };
+
+    let token = Biscuit::from(&data, root.public()).unwrap();
+    b.iter(|| {
+        let mut verifier = token.authorizer().unwrap();
+        verifier.allow().unwrap();
+        verifier
+            .authorize_with_limits(AuthorizerLimits {
+                max_time: Duration::from_secs(10),
+                ..Default::default()
+            })
+            .unwrap();
+    });
+}
+
+fn checks_unique_bindings(b: &mut Bencher) {
+    let mut rng: OsRng = OsRng;
+    let root = KeyPair::new_with_rng(&mut rng);
+
+    let data = {
+        let mut builder = Biscuit::builder();
+        for i in 0..20 {
+            builder
+                .add_fact(fact("user", &[string(&format!("user{}", i))]))
+                .unwrap();
+            builder
+                .add_fact(fact("resource", &[string(&format!("file{}", i))]))
+                .unwrap();
+        }
+        // the expression uses all the variables of the check, each of
+        // its evaluations gets a different binding
+        builder
+            .add_check("check all user($u), resource($r), $u.starts_with(\"user\"), $r != $u")
+            .unwrap();
+
+        let token = builder
+            .build_with_rng(&root, SymbolTable::default(), &mut rng)
+            .unwrap();
+        token.to_vec().unwrap()
+    };
+
+    let token = Biscuit::from(&data, root.public()).unwrap();
+    b.iter(|| {
+        let mut verifier = token.authorizer().unwrap();
+        verifier.allow().unwrap();
+        verifier
+            .authorize_with_limits(AuthorizerLimits {
+                max_time: Duration::from_secs(10),
+                ..Default::default()
+            })
+            .unwrap();
+    });
+}
+
 benchmark_group!(
     benchmarks,
     create_block_1,
@@ -615,6 +701,8 @@ benchmark_group!(
     check_signature_5,
     checks_block_2,
     checks_block_create_verifier2,
-    checks_block_verify_only2
+    checks_block_verify_only2,
+    checks_repeated_expressions,
+    checks_unique_bindings
 );
 benchmark_main!(benchmarks);
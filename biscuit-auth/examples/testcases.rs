@@ -673,7 +673,7 @@ fn random_block(target: &str, root: &KeyPair, test: bool) -> TestResult {
         let serialized = biscuit2.container();
         let mut proto = serialized.to_proto();
         let arr: [u8; 32] = rng.gen();
-        proto.blocks[0].block = Vec::from(&arr[..]);
+        proto.blocks[0].block = Vec::from(&arr[..]).into();
         let mut data = Vec::new();
         proto.encode(&mut data).unwrap();
 
@@ -0,0 +1,369 @@
+//! standardized workloads and timing helpers
+//!
+//! A [`Workload`] generates a token and an authorizer with a given number of
+//! facts, rules and blocks. The generated Datalog only depends on these numbers
+//! and on the seed, and will not change between versions of this crate, so the
+//! measurements can be used to compare policy designs, or stored in CI and
+//! compared with the measurements of the next version to detect performance
+//! regressions:
+//!
+//! ```rust
+//! use biscuit_auth::bench::{compare, parse_report, run, to_report, Workload};
+//!
+//! let workload = Workload::new(100, 5, 2);
+//! let measurements = run(&workload, 3).unwrap();
+//!
+//! // this report would be stored by a previous CI run
+//! let baseline = parse_report(&to_report(&measurements)).unwrap();
+//! // timings are noisy, so only large differences should fail the build
+//! let regressions = compare(&baseline, &measurements, 10.0);
+//! assert!(regressions.is_empty());
+//! ```
+use std::{fmt, time::Duration};
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use crate::{error, time::Instant, Authorizer, AuthorizerLimits, Biscuit, KeyPair};
+
+/// token and authorizer generated from a number of facts, rules and blocks
+///
+/// * the authority block contains `facts` facts `right("file{i}", "read")`,
+///   and a chain of `rules` rules, `level0($f) <- right($f, "read")`,
+///   then `level{j}($f) <- level{j - 1}($f)`
+/// * each of the `blocks` attenuation blocks contains a fact and a check
+///   on the requested resource
+/// * the authorizer provides the requested resource and operation, checks
+///   the last level of the rules chain, and allows the rights on the resource
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Workload {
+    pub name: String,
+    pub facts: usize,
+    pub rules: usize,
+    pub blocks: usize,
+    /// seed of the RNG used to generate the keys
+    pub seed: u64,
+}
+
+impl Workload {
+    pub fn new(facts: usize, rules: usize, blocks: usize) -> Self {
+        Workload {
+            name: format!("{}_facts_{}_rules_{}_blocks", facts, rules, blocks),
+            facts,
+            rules,
+            blocks,
+            seed: 0,
+        }
+    }
+
+    /// Datalog code of the authority block, then of each attenuation block
+    pub fn blocks_code(&self) -> Vec<String> {
+        let mut authority = String::new();
+        for i in 0..self.facts {
+            authority.push_str(&format!("right(\"file{}\", \"read\");\n", i));
+        }
+        for j in 0..self.rules {
+            if j == 0 {
+                authority.push_str("level0($f) <- right($f, \"read\");\n");
+            } else {
+                authority.push_str(&format!("level{}($f) <- level{}($f);\n", j, j - 1));
+            }
+        }
+
+        std::iter::once(authority)
+            .chain((1..=self.blocks).map(|k| {
+                format!(
+                    "block({});\ncheck if resource($f), $f.starts_with(\"file\");\n",
+                    k
+                )
+            }))
+            .collect()
+    }
+
+    /// Datalog code of the authorizer
+    pub fn authorizer_code(&self) -> String {
+        let mut code = String::from("resource(\"file0\");\noperation(\"read\");\n");
+        if self.rules > 0 {
+            code.push_str(&format!(
+                "check if level{}($f), resource($f);\n",
+                self.rules - 1
+            ));
+        }
+        code.push_str("allow if resource($f), operation($op), right($f, $op);\n");
+        code
+    }
+
+    /// limits allowing the workload to run to completion
+    pub fn limits(&self) -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_facts: (self.facts as u64 + 1) * (self.rules as u64 + 1) + self.blocks as u64 + 10,
+            max_iterations: self.rules as u64 + 10,
+            max_time: Duration::from_secs(60),
+            ..Default::default()
+        }
+    }
+
+    /// creates the root key pair and the token
+    pub fn token(&self) -> Result<(KeyPair, Biscuit), error::Token> {
+        let mut rng = ChaCha8Rng::seed_from_u64(self.seed);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let token = Biscuit::from_blocks_code(&root, &self.blocks_code(), &mut rng)?;
+        Ok((root, token))
+    }
+
+    /// creates the authorizer for a token of this workload
+    pub fn authorizer(&self, token: &Biscuit) -> Result<Authorizer, error::Token> {
+        let mut authorizer = token.authorizer()?;
+        authorizer.add_code(self.authorizer_code())?;
+        authorizer.set_limits(self.limits());
+        Ok(authorizer)
+    }
+}
+
+/// workloads tracked by the project, from a small token to a large policy
+pub fn default_workloads() -> Vec<Workload> {
+    vec![
+        Workload::new(10, 1, 1),
+        Workload::new(1_000, 5, 5),
+        Workload::new(10_000, 10, 10),
+    ]
+}
+
+/// timings of a repeated operation
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Measurement {
+    pub name: String,
+    pub iterations: u32,
+    pub min: Duration,
+    pub median: Duration,
+    pub max: Duration,
+}
+
+impl fmt::Display for Measurement {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: median {:?}, min {:?}, max {:?} ({} iterations)",
+            self.name, self.median, self.min, self.max, self.iterations
+        )
+    }
+}
+
+/// measures `iterations` calls of `f`
+///
+/// the median is less sensitive than the mean to the pauses caused by the system
+pub fn measure<F: FnMut()>(name: &str, iterations: u32, mut f: F) -> Measurement {
+    let mut timings: Vec<Duration> = (0..iterations.max(1))
+        .map(|_| {
+            let start = Instant::now();
+            f();
+            Instant::now() - start
+        })
+        .collect();
+    timings.sort();
+
+    Measurement {
+        name: name.to_string(),
+        iterations: timings.len() as u32,
+        min: timings[0],
+        median: timings[timings.len() / 2],
+        max: timings[timings.len() - 1],
+    }
+}
+
+/// measures the creation, serialization, verification and authorization of a
+/// workload's token
+///
+/// the measurements are named `{workload}/{step}`
+pub fn run(workload: &Workload, iterations: u32) -> Result<Vec<Measurement>, error::Token> {
+    let (root, token) = workload.token()?;
+    let serialized = token.to_vec()?;
+    let authorizer = workload.authorizer(&token)?;
+    // the workload must be valid before being measured
+    authorizer.clone().authorize()?;
+
+    let name = |step: &str| format!("{}/{}", workload.name, step);
+    Ok(vec![
+        measure(&name("build"), iterations, || {
+            workload.token().unwrap();
+        }),
+        measure(&name("serialize"), iterations, || {
+            token.to_vec().unwrap();
+        }),
+        measure(&name("verify"), iterations, || {
+            Biscuit::from(&serialized, root.public()).unwrap();
+        }),
+        measure(&name("authorize"), iterations, || {
+            authorizer.clone().authorize().unwrap();
+        }),
+    ])
+}
+
+/// runs a list of workloads
+pub fn run_all(workloads: &[Workload], iterations: u32) -> Result<Vec<Measurement>, error::Token> {
+    let mut measurements = Vec::new();
+    for workload in workloads {
+        measurements.extend(run(workload, iterations)?);
+    }
+    Ok(measurements)
+}
+
+/// measurement slower than its baseline
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Regression {
+    pub name: String,
+    pub baseline: Duration,
+    pub current: Duration,
+}
+
+impl fmt::Display for Regression {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: median went from {:?} to {:?}",
+            self.name, self.baseline, self.current
+        )
+    }
+}
+
+/// compares the medians of measurements, matched by name
+///
+/// a measurement is a regression if its median is more than `tolerance` times
+/// slower than the baseline, like `0.2` for 20%. Measurements missing from one
+/// of the lists are ignored.
+pub fn compare(
+    baseline: &[Measurement],
+    current: &[Measurement],
+    tolerance: f64,
+) -> Vec<Regression> {
+    current
+        .iter()
+        .filter_map(|c| {
+            let b = baseline.iter().find(|b| b.name == c.name)?;
+            let limit = b.median.as_secs_f64() * (1.0 + tolerance);
+            if c.median.as_secs_f64() > limit {
+                Some(Regression {
+                    name: c.name.clone(),
+                    baseline: b.median,
+                    current: c.median,
+                })
+            } else {
+                None
+            }
+        })
+        .collect()
+}
+
+/// serializes measurements to a text report, one line per measurement with
+/// the name, iterations, then the minimum, median and maximum in nanoseconds
+///
+/// the name can contain spaces, the numbers are the last four fields
+pub fn to_report(measurements: &[Measurement]) -> String {
+    let mut report = String::new();
+    for m in measurements {
+        report.push_str(&format!(
+            "{} {} {} {} {}\n",
+            m.name,
+            m.iterations,
+            m.min.as_nanos(),
+            m.median.as_nanos(),
+            m.max.as_nanos()
+        ));
+    }
+    report
+}
+
+/// parses a text report created by [`to_report`]
+pub fn parse_report(report: &str) -> Result<Vec<Measurement>, String> {
+    report
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.is_empty())
+        .map(|(i, line)| {
+            let invalid = || format!("invalid line {}: {:?}", i + 1, line);
+            // the name comes first and can contain spaces
+            let fields: Vec<&str> = line.rsplitn(5, ' ').collect();
+            if fields.len() != 5 {
+                return Err(invalid());
+            }
+            let nanos = |s: &str| s.parse::<u64>().map(Duration::from_nanos);
+
+            Ok(Measurement {
+                name: fields[4].to_string(),
+                iterations: fields[3].parse().map_err(|_| invalid())?,
+                min: nanos(fields[2]).map_err(|_| invalid())?,
+                median: nanos(fields[1]).map_err(|_| invalid())?,
+                max: nanos(fields[0]).map_err(|_| invalid())?,
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bench() {
+        let workload = Workload::new(20, 3, 2);
+        let (_, token) = workload.token().unwrap();
+        assert_eq!(token.block_count(), 3);
+        // the RNG algorithm is pinned, the keys do not change between versions
+        let (root, _) = workload.token().unwrap();
+        assert_eq!(
+            root.public().to_string(),
+            "ed25519/b371e8d4c1a35a376a13772d763f207c892421ac237262451cdf448e1dd1b25f"
+        );
+        // workloads are deterministic
+        assert_eq!(
+            token.to_base64().unwrap(),
+            workload.token().unwrap().1.to_base64().unwrap()
+        );
+
+        let mut authorizer = workload.authorizer(&token).unwrap();
+        assert_eq!(authorizer.authorize(), Ok(0));
+        let facts: Vec<(String,)> = authorizer.query_all("data($f) <- level2($f)").unwrap();
+        assert_eq!(facts.len(), 20);
+
+        let measurements = run(&workload, 2).unwrap();
+        let names: Vec<_> = measurements.iter().map(|m| m.name.as_str()).collect();
+        assert_eq!(
+            names,
+            vec![
+                "20_facts_3_rules_2_blocks/build",
+                "20_facts_3_rules_2_blocks/serialize",
+                "20_facts_3_rules_2_blocks/verify",
+                "20_facts_3_rules_2_blocks/authorize",
+            ]
+        );
+        assert!(measurements
+            .iter()
+            .all(|m| m.iterations == 2 && m.min <= m.median && m.median <= m.max));
+
+        let parsed = parse_report(&to_report(&measurements)).unwrap();
+        assert_eq!(parsed, measurements);
+        assert!(compare(&measurements, &parsed, 0.0).is_empty());
+
+        let mut slower = parsed;
+        slower[3].median = measurements[3].median * 2 + Duration::from_nanos(1);
+        slower.remove(0);
+        assert_eq!(
+            compare(&measurements, &slower, 1.0),
+            vec![Regression {
+                name: "20_facts_3_rules_2_blocks/authorize".to_string(),
+                baseline: measurements[3].median,
+                current: slower[2].median,
+            }]
+        );
+
+        assert!(parse_report("name 1 2 3").is_err());
+        let mut named = Workload::new(1, 1, 1);
+        named.name = "policy with spaces".to_string();
+        let measurements = run(&named, 1).unwrap();
+        assert_eq!(
+            parse_report(&to_report(&measurements)).unwrap(),
+            measurements
+        );
+        assert!(Workload::new(0, 0, 0).token().is_ok());
+    }
+}
@@ -58,6 +58,31 @@ pub extern "C" fn error_message() -> *const c_char {
     })
 }
 
+/// stable code of the last error, see `biscuit_auth::error::ErrorCode`,
+/// like `revoked` or `check_failed_in_block(1)`
+#[no_mangle]
+pub extern "C" fn error_code() -> *const c_char {
+    thread_local! {
+        static LAST: RefCell<Option<CString>> = RefCell::new(None);
+    }
+    LAST_ERROR.with(|prev| match *prev.borrow() {
+        Some(ref err) => {
+            let code = match err {
+                Error::InvalidArgument => "invalid_argument".to_string(),
+                Error::Biscuit(e) => e.code().to_string(),
+            };
+            LAST.with(|ret| {
+                *ret.borrow_mut() = CString::new(code).ok();
+                ret.borrow()
+                    .as_ref()
+                    .map(|x| x.as_ptr())
+                    .unwrap_or(std::ptr::null())
+            })
+        }
+        None => std::ptr::null(),
+    })
+}
+
 #[repr(C)]
 pub enum ErrorKind {
     None,
@@ -97,6 +122,20 @@ pub enum ErrorKind {
     FormatSignatureInvalidSignatureGeneration,
     AlreadySealed,
     Execution,
+    Revoked,
+    ExpirationExtended,
+    LogicAudienceMismatch,
+    NotSealed,
+    LogicInvalidNamespace,
+    LogicNamespaceConflict,
+    LogicSealRequired,
+    FormatUnknownApplicationSymbols,
+    ThirdPartyHop,
+    TooManyFactsInBlock,
+    TooManyIterationsInBlock,
+    TooManyResults,
+    RecursiveAggregate,
+    LogicReservedPredicate,
 }
 
 #[no_mangle]
@@ -132,7 +171,9 @@ pub extern "C" fn error_kind() -> ErrorKind {
                     Token::Format(Format::BlockSerializationError(_)) => {
                         ErrorKind::FormatBlockSerializationError
                     }
-                    Token::Format(Format::Version { .. }) => ErrorKind::FormatVersion,
+                    Token::Format(Format::Version { .. } | Format::ExtensionVersion { .. }) => {
+                        ErrorKind::FormatVersion
+                    }
                     Token::Format(Format::InvalidKeySize(_)) => ErrorKind::FormatInvalidKeySize,
                     Token::Format(Format::InvalidSignatureSize(_)) => {
                         ErrorKind::FormatInvalidSignatureSize
@@ -158,8 +199,12 @@ pub extern "C" fn error_kind() -> ErrorKind {
                         ErrorKind::FormatUnknownExternalKey
                     }
                     Token::Format(Format::UnknownSymbol(_)) => ErrorKind::FormatUnknownSymbol,
+                    Token::Format(Format::UnknownApplicationSymbols(_)) => {
+                        ErrorKind::FormatUnknownApplicationSymbols
+                    }
                     Token::AppendOnSealed => ErrorKind::AppendOnSealed,
                     Token::AlreadySealed => ErrorKind::AlreadySealed,
+                    Token::NotSealed => ErrorKind::NotSealed,
                     Token::Language(_) => ErrorKind::LanguageError,
                     Token::FailedLogic(Logic::InvalidBlockRule(_, _)) => {
                         ErrorKind::LogicInvalidBlockRule
@@ -171,12 +216,36 @@ pub extern "C" fn error_kind() -> ErrorKind {
                     Token::FailedLogic(Logic::NoMatchingPolicy { .. }) => {
                         ErrorKind::LogicNoMatchingPolicy
                     }
+                    Token::FailedLogic(Logic::AudienceMismatch { .. }) => {
+                        ErrorKind::LogicAudienceMismatch
+                    }
+                    Token::FailedLogic(Logic::InvalidNamespace(_)) => {
+                        ErrorKind::LogicInvalidNamespace
+                    }
+                    Token::FailedLogic(Logic::NamespaceConflict { .. }) => {
+                        ErrorKind::LogicNamespaceConflict
+                    }
+                    Token::FailedLogic(Logic::SealRequired) => ErrorKind::LogicSealRequired,
+                    Token::FailedLogic(Logic::ReservedPredicate { .. }) => {
+                        ErrorKind::LogicReservedPredicate
+                    }
                     Token::RunLimit(RunLimit::TooManyFacts) => ErrorKind::TooManyFacts,
                     Token::RunLimit(RunLimit::TooManyIterations) => ErrorKind::TooManyIterations,
                     Token::RunLimit(RunLimit::Timeout) => ErrorKind::Timeout,
+                    Token::RunLimit(RunLimit::TooManyFactsInBlock { .. }) => {
+                        ErrorKind::TooManyFactsInBlock
+                    }
+                    Token::RunLimit(RunLimit::TooManyIterationsInBlock { .. }) => {
+                        ErrorKind::TooManyIterationsInBlock
+                    }
+                    Token::RunLimit(RunLimit::TooManyResults) => ErrorKind::TooManyResults,
                     Token::ConversionError(_) => ErrorKind::ConversionError,
                     Token::Base64(_) => ErrorKind::FormatDeserializationError,
                     Token::Execution(_) => ErrorKind::Execution,
+                    Token::RecursiveAggregate(_) => ErrorKind::RecursiveAggregate,
+                    Token::Revoked { .. } => ErrorKind::Revoked,
+                    Token::ExpirationExtended { .. } => ErrorKind::ExpirationExtended,
+                    Token::ThirdPartyHop { .. } => ErrorKind::ThirdPartyHop,
                 }
             }
         },
@@ -269,6 +338,37 @@ pub extern "C" fn error_check_rule(check_index: u64) -> *const c_char {
     })
 }
 
+/// returns the message attached to the check, or NULL if it has none
+/// deallocation is handled by Biscuit
+/// the string is overwritten on each call
+#[no_mangle]
+pub extern "C" fn error_check_message(check_index: u64) -> *const c_char {
+    use crate::error::*;
+    thread_local! {
+        static CHECK_MESSAGE: RefCell<Option<CString>> = RefCell::new(None);
+    }
+
+    LAST_ERROR.with(|prev| match *prev.borrow() {
+        Some(Error::Biscuit(Token::FailedLogic(Logic::Unauthorized { ref checks, .. })))
+        | Some(Error::Biscuit(Token::FailedLogic(Logic::NoMatchingPolicy { ref checks }))) => {
+            let message = match checks.get(check_index as usize) {
+                Some(FailedCheck::Block(FailedBlockCheck { message, .. })) => message,
+                Some(FailedCheck::Authorizer(FailedAuthorizerCheck { message, .. })) => message,
+                None => return std::ptr::null(),
+            };
+            let message = message.clone().and_then(|m| CString::new(m).ok());
+            CHECK_MESSAGE.with(|ret| {
+                *ret.borrow_mut() = message;
+                ret.borrow()
+                    .as_ref()
+                    .map(|x| x.as_ptr())
+                    .unwrap_or(std::ptr::null())
+            })
+        }
+        _ => std::ptr::null(),
+    })
+}
+
 #[no_mangle]
 pub extern "C" fn error_check_is_authorizer(check_index: u64) -> bool {
     use crate::error::*;
@@ -370,9 +470,16 @@ pub unsafe extern "C" fn public_key_serialize(
     }
     let kp = kp.unwrap();
 
+    // only Ed25519 keys fit in the 32 byte buffer
+    let bytes = kp.0.to_bytes();
+    if bytes.len() != 32 {
+        update_last_error(Error::InvalidArgument);
+        return 0;
+    }
+
     let output_slice = std::slice::from_raw_parts_mut(buffer_ptr, 32);
 
-    output_slice.copy_from_slice(&kp.0.to_bytes()[..]);
+    output_slice.copy_from_slice(&bytes[..]);
     32
 }
 
@@ -0,0 +1,394 @@
+//! aggregation of rule results, like `count($session)` in a rule head
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use super::{
+    CombineIt, Fact, MatchedVariables, Origin, Rule, RuleSet, SymbolIndex, SymbolTable, Term,
+    TrustedOrigins,
+};
+use crate::error;
+
+/// aggregation of the values of a variable in a rule head
+///
+/// the rule's results are grouped by the other terms of the head, and the
+/// aggregated variable is replaced by:
+/// - `count`: the number of distinct bindings of the rule body in the group
+/// - `sum`: the sum of the variable's values (integers only)
+/// - `min` and `max`: the smallest or largest value (integers or dates)
+///
+/// an empty group produces no fact: `count` never returns 0
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aggregate::Count => "count",
+            Aggregate::Sum => "sum",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max",
+        }
+    }
+
+    fn fold(&self, acc: Option<Term>, value: &Term) -> Result<Term, error::Expression> {
+        match (self, acc, value) {
+            (Aggregate::Count, None, _) => Ok(Term::Integer(1)),
+            (Aggregate::Count, Some(Term::Integer(i)), _) => i
+                .checked_add(1)
+                .map(Term::Integer)
+                .ok_or(error::Expression::Overflow),
+            (Aggregate::Sum, None, Term::Integer(i)) => Ok(Term::Integer(*i)),
+            (Aggregate::Sum, Some(Term::Integer(acc)), Term::Integer(i)) => acc
+                .checked_add(*i)
+                .map(Term::Integer)
+                .ok_or(error::Expression::Overflow),
+            (Aggregate::Min | Aggregate::Max, None, Term::Integer(_) | Term::Date(_)) => {
+                Ok(value.clone())
+            }
+            (Aggregate::Min, Some(Term::Integer(acc)), Term::Integer(i)) => {
+                Ok(Term::Integer(acc.min(*i)))
+            }
+            (Aggregate::Min, Some(Term::Date(acc)), Term::Date(d)) => Ok(Term::Date(acc.min(*d))),
+            (Aggregate::Max, Some(Term::Integer(acc)), Term::Integer(i)) => {
+                Ok(Term::Integer(acc.max(*i)))
+            }
+            (Aggregate::Max, Some(Term::Date(acc)), Term::Date(d)) => Ok(Term::Date(acc.max(*d))),
+            _ => Err(error::Expression::InvalidType),
+        }
+    }
+}
+
+/// aggregated values by position in the head, and the origin of the matched facts
+type Group = (Vec<Option<Term>>, Origin);
+
+/// rule with aggregates that was not applied yet in the current run
+#[derive(Clone, Debug)]
+pub(crate) struct PendingAggregate {
+    pub(crate) scope: TrustedOrigins,
+    pub(crate) origin: usize,
+    pub(crate) rule: Rule,
+    /// predicates that the rule's body depends on, directly or through other rules
+    dependencies: HashSet<SymbolIndex>,
+}
+
+/// order of application of the aggregates in a run
+///
+/// an aggregate needs all the facts matching its body, so it is applied once
+/// the other rules stop generating facts, and after the aggregates its body
+/// depends on. Aggregates depending on their own results are refused, since
+/// they would produce a fact for each intermediate value
+#[derive(Clone, Debug, Default)]
+pub(crate) struct AggregateRun {
+    pending: Vec<PendingAggregate>,
+    /// whether aggregates were applied in this run
+    applied: bool,
+}
+
+impl AggregateRun {
+    pub(crate) fn new(rules: &RuleSet, symbols: &SymbolTable) -> Result<Self, error::Execution> {
+        // predicates used to generate each predicate
+        let mut graph: HashMap<SymbolIndex, HashSet<SymbolIndex>> = HashMap::new();
+        for (_, rule) in rules.iter_all() {
+            graph
+                .entry(rule.head.name)
+                .or_default()
+                .extend(rule.body.iter().map(|p| p.name));
+        }
+
+        let mut pending = Vec::new();
+        for (scope, scope_rules) in rules.inner.iter() {
+            for (origin, rule) in scope_rules {
+                if rule.aggregates.is_empty() {
+                    continue;
+                }
+
+                let mut dependencies = HashSet::new();
+                let mut stack: Vec<SymbolIndex> = rule.body.iter().map(|p| p.name).collect();
+                while let Some(name) = stack.pop() {
+                    if dependencies.insert(name) {
+                        if let Some(names) = graph.get(&name) {
+                            stack.extend(names.iter().copied());
+                        }
+                    }
+                }
+
+                if dependencies.contains(&rule.head.name) {
+                    return Err(error::Execution::RecursiveAggregate(
+                        symbols.print_rule(rule),
+                    ));
+                }
+
+                pending.push(PendingAggregate {
+                    scope: scope.clone(),
+                    origin: *origin,
+                    rule: rule.clone(),
+                    dependencies,
+                });
+            }
+        }
+
+        Ok(AggregateRun {
+            pending,
+            applied: false,
+        })
+    }
+
+    pub(crate) fn is_done(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    pub(crate) fn applied(&self) -> bool {
+        self.applied
+    }
+
+    /// removes and returns the aggregates that do not depend on the results
+    /// of the other pending aggregates
+    pub(crate) fn take_ready(&mut self) -> Vec<PendingAggregate> {
+        let blocked: Vec<bool> = self
+            .pending
+            .iter()
+            .map(|aggregate| {
+                self.pending
+                    .iter()
+                    .any(|other| aggregate.dependencies.contains(&other.rule.head.name))
+            })
+            .collect();
+
+        let mut ready = Vec::new();
+        let mut blocked = blocked.into_iter();
+        self.pending.retain(|aggregate| {
+            if blocked.next() == Some(true) {
+                true
+            } else {
+                ready.push(aggregate.clone());
+                false
+            }
+        });
+        self.applied |= !ready.is_empty();
+        ready
+    }
+}
+
+impl Rule {
+    /// applies a rule with aggregates in its head
+    ///
+    /// unlike [`Rule::apply`], this needs all the rule's results before
+    /// generating facts, so the engine only runs it once the other rules
+    /// do not generate new facts. `rule_origin` is added to the origin of the
+    /// generated facts, if any
+    pub fn apply_aggregates<'a, IT>(
+        &'a self,
+        facts: IT,
+        rule_origin: Option<usize>,
+        symbols: &'a SymbolTable,
+    ) -> Result<Vec<(Origin, Fact)>, error::Expression>
+    where
+        IT: Iterator<Item = (&'a Origin, &'a Fact)> + Clone + 'a,
+    {
+        let variables = MatchedVariables::new(self.variables_set());
+        let mut expressions = self.cached_expressions(symbols);
+
+        // the same bindings can be found through different facts,
+        // they are only counted once
+        let mut bindings: BTreeMap<BTreeMap<u32, Term>, Origin> = BTreeMap::new();
+        'bindings: for (origin, variables) in CombineIt::new(variables, &self.body, facts, symbols)
+        {
+            for e in expressions.iter_mut() {
+                if !e.evaluate(&variables, symbols)? {
+                    continue 'bindings;
+                }
+            }
+
+            let origins = bindings.entry(variables.into_iter().collect()).or_default();
+            origins.extend(origin.inner.iter());
+        }
+
+        let aggregates: HashMap<usize, Aggregate> = self.aggregates.iter().copied().collect();
+        // results grouped by the values of the non aggregated head terms
+        let mut groups: BTreeMap<Vec<Option<Term>>, Group> = BTreeMap::new();
+
+        for (variables, origin) in bindings {
+            let mut key = Vec::with_capacity(self.head.terms.len());
+            let mut values = Vec::with_capacity(aggregates.len());
+            for (position, term) in self.head.terms.iter().enumerate() {
+                let value = match term {
+                    Term::Variable(i) => variables
+                        .get(i)
+                        .ok_or(error::Expression::UnknownVariable(*i))?,
+                    _ => term,
+                };
+
+                match aggregates.get(&position) {
+                    Some(aggregate) => {
+                        key.push(None);
+                        values.push((position, aggregate, value));
+                    }
+                    None => key.push(Some(value.clone())),
+                }
+            }
+
+            let (group, group_origin) = groups
+                .entry(key)
+                .or_insert_with(|| (vec![None; self.head.terms.len()], Origin::default()));
+            for (position, aggregate, value) in values {
+                group[position] = Some(aggregate.fold(group[position].take(), value)?);
+            }
+            group_origin.extend(origin.inner.iter());
+        }
+
+        Ok(groups
+            .into_iter()
+            .map(|(key, (values, mut origin))| {
+                let mut predicate = self.head.clone();
+                predicate.terms = key
+                    .into_iter()
+                    .zip(values)
+                    .filter_map(|(k, v)| k.or(v))
+                    .collect();
+                if let Some(rule_origin) = rule_origin {
+                    origin.insert(rule_origin);
+                }
+                (origin, Fact { predicate })
+            })
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::datalog::{
+        expressed_rule, fact, int, pred, rule, var, Binary, Expression, Op, RunLimits, World,
+    };
+    use crate::error::Execution;
+    use std::time::Duration;
+
+    fn limits() -> RunLimits {
+        RunLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
+    /// `name($user, aggregate($amount)) <- payment($user, $id, $amount), $amount > 0`
+    fn payments_rule(syms: &mut SymbolTable, name: &str, aggregate: Aggregate) -> Rule {
+        let mut rule = expressed_rule(
+            syms.insert(name),
+            &[var(syms, "user"), var(syms, "amount")],
+            &[pred(
+                syms.insert("payment"),
+                &[var(syms, "user"), var(syms, "id"), var(syms, "amount")],
+            )],
+            &[Expression {
+                ops: vec![
+                    Op::Value(var(syms, "amount")),
+                    Op::Value(int(0)),
+                    Op::Binary(Binary::GreaterThan),
+                ],
+            }],
+        );
+        rule.aggregates = vec![(1, aggregate)];
+        rule
+    }
+
+    #[test]
+    fn aggregates() {
+        let mut w = World::new();
+        let mut syms = SymbolTable::new();
+
+        let alice = syms.add("alice");
+        let bob = syms.add("bob");
+        let payment = syms.insert("payment");
+        let total = syms.insert("total");
+
+        for (user, id, amount) in [
+            (&alice, 1, 10),
+            (&alice, 2, 10),
+            (&alice, 3, 5),
+            (&alice, 4, -1),
+            (&bob, 5, 7),
+        ] {
+            w.add_fact(
+                &[0].iter().collect(),
+                fact(payment, &[user, &int(id), &int(amount)]),
+            );
+        }
+
+        let mut results = vec![];
+        for (name, aggregate) in [
+            ("count", Aggregate::Count),
+            ("sum", Aggregate::Sum),
+            ("min", Aggregate::Min),
+            ("max", Aggregate::Max),
+        ] {
+            let rule = payments_rule(&mut syms, name, aggregate);
+            let res = w.query_rule(rule, 0, &[0].iter().collect(), &syms).unwrap();
+            let mut facts = res
+                .iter_all()
+                .map(|(_, f)| syms.print_fact(f))
+                .collect::<Vec<_>>();
+            facts.sort();
+            results.push(facts);
+        }
+
+        assert_eq!(
+            results,
+            vec![
+                vec!["count(\"alice\", 3)", "count(\"bob\", 1)"],
+                vec!["sum(\"alice\", 25)", "sum(\"bob\", 7)"],
+                vec!["min(\"alice\", 5)", "min(\"bob\", 7)"],
+                vec!["max(\"alice\", 10)", "max(\"bob\", 7)"],
+            ]
+        );
+
+        // the other rules can use the aggregated facts
+        let r = payments_rule(&mut syms, "sum", Aggregate::Sum);
+        w.add_rule(0, &[0].iter().collect(), r);
+        let r = rule(
+            total,
+            &[var(&mut syms, "amount")],
+            &[pred(
+                syms.insert("sum"),
+                &[alice.clone(), var(&mut syms, "amount")],
+            )],
+        );
+        w.add_rule(0, &[0].iter().collect(), r);
+        w.run_with_limits(&syms, limits()).unwrap();
+
+        let res = w
+            .query_rule(
+                rule(
+                    total,
+                    &[var(&mut syms, "amount")],
+                    &[pred(total, &[var(&mut syms, "amount")])],
+                ),
+                0,
+                &[0].iter().collect(),
+                &syms,
+            )
+            .unwrap();
+        assert_eq!(
+            res.iter_all()
+                .map(|(_, f)| syms.print_fact(f))
+                .collect::<Vec<_>>(),
+            vec!["total(25)"]
+        );
+
+        // sum only works on integers
+        let mut w = World::new();
+        w.add_fact(
+            &[0].iter().collect(),
+            fact(payment, &[&alice, &int(1), &bob]),
+        );
+        let mut r = payments_rule(&mut syms, "sum", Aggregate::Sum);
+        r.expressions.clear();
+        assert_eq!(
+            w.query_rule(r, 0, &[0].iter().collect(), &syms).err(),
+            Some(Execution::Expression(error::Expression::InvalidType))
+        );
+    }
+}
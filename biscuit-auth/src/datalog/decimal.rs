@@ -0,0 +1,178 @@
+//! fixed point decimal arithmetic
+//!
+//! decimals are represented by a mantissa and a scale, the number of fractional
+//! digits: `12.3d` is `(123, 1)`. The trailing zeros of the fractional digits
+//! are removed when parsing, decoding and computing decimals, so that equal
+//! numbers are equal terms: `12.30d` is also `(123, 1)`
+use std::cmp::Ordering;
+
+use crate::error;
+
+pub use biscuit_parser::parser::{normalize_decimal as normalize, MAX_DECIMAL_SCALE};
+
+type Decimal = (i128, u8);
+
+fn rescale(mantissa: i128, from: u8, to: u8) -> Result<i128, error::Expression> {
+    10i128
+        .checked_pow(u32::from(to - from))
+        .and_then(|factor| mantissa.checked_mul(factor))
+        .ok_or(error::Expression::Overflow)
+}
+
+/// converts both decimals to the same scale
+fn align(left: Decimal, right: Decimal) -> Result<(i128, i128, u8), error::Expression> {
+    let scale = left.1.max(right.1);
+    Ok((
+        rescale(left.0, left.1, scale)?,
+        rescale(right.0, right.1, scale)?,
+        scale,
+    ))
+}
+
+/// compares two decimals. Unlike the arithmetic operations, it does not
+/// rescale the mantissas, so it cannot overflow
+pub fn compare(left: Decimal, right: Decimal) -> Ordering {
+    let (l, r) = (left.0.signum(), right.0.signum());
+    if l != r {
+        return l.cmp(&r);
+    }
+
+    let ordering = compare_magnitudes(
+        (left.0.unsigned_abs(), left.1),
+        (right.0.unsigned_abs(), right.1),
+    );
+    if l < 0 {
+        ordering.reverse()
+    } else {
+        ordering
+    }
+}
+
+/// compares the absolute values, dividing the mantissa with the most
+/// fractional digits instead of multiplying the other one
+fn compare_magnitudes(left: (u128, u8), right: (u128, u8)) -> Ordering {
+    if left.1 > right.1 {
+        return compare_magnitudes(right, left).reverse();
+    }
+
+    match 10u128.checked_pow(u32::from(right.1 - left.1)) {
+        Some(factor) => left
+            .0
+            .cmp(&(right.0 / factor))
+            .then(if right.0.is_multiple_of(factor) {
+                Ordering::Equal
+            } else {
+                Ordering::Less
+            }),
+        // the factor is larger than any mantissa
+        None if left.0 == 0 => 0.cmp(&right.0),
+        None => Ordering::Greater,
+    }
+}
+
+pub fn add(left: Decimal, right: Decimal) -> Result<Decimal, error::Expression> {
+    let (l, r, scale) = align(left, right)?;
+    l.checked_add(r)
+        .map(|m| normalize(m, scale))
+        .ok_or(error::Expression::Overflow)
+}
+
+pub fn sub(left: Decimal, right: Decimal) -> Result<Decimal, error::Expression> {
+    let (l, r, scale) = align(left, right)?;
+    l.checked_sub(r)
+        .map(|m| normalize(m, scale))
+        .ok_or(error::Expression::Overflow)
+}
+
+pub fn mul(left: Decimal, right: Decimal) -> Result<Decimal, error::Expression> {
+    let scale = left.1 + right.1;
+    if scale > MAX_DECIMAL_SCALE {
+        return Err(error::Expression::Overflow);
+    }
+    left.0
+        .checked_mul(right.0)
+        .map(|m| normalize(m, scale))
+        .ok_or(error::Expression::Overflow)
+}
+
+/// divides, with the fractional digits needed for an exact result. A result
+/// with more than [`MAX_DECIMAL_SCALE`] digits, or more than the mantissa can
+/// hold, is truncated towards zero
+pub fn div(left: Decimal, right: Decimal) -> Result<Decimal, error::Expression> {
+    if right.0 == 0 {
+        return Err(error::Expression::DivideByZero);
+    }
+    let mut scale = left.1.max(right.1);
+    let mut dividend = rescale(left.0, left.1, scale + right.1)?;
+    while dividend.checked_rem(right.0) != Some(0) && scale < MAX_DECIMAL_SCALE {
+        match dividend.checked_mul(10) {
+            Some(d) => {
+                dividend = d;
+                scale += 1;
+            }
+            None => break,
+        }
+    }
+    dividend
+        .checked_div(right.0)
+        .map(|m| normalize(m, scale))
+        .ok_or(error::Expression::Overflow)
+}
+
+/// prints a decimal in the Datalog syntax, like `12.30d`
+pub fn to_string(mantissa: i128, scale: u8) -> String {
+    let sign = if mantissa < 0 { "-" } else { "" };
+    let digits = mantissa.unsigned_abs().to_string();
+    let scale = usize::from(scale);
+
+    if scale == 0 {
+        return format!("{}{}d", sign, digits);
+    }
+
+    let digits = format!("{:0>width$}", digits, width = scale + 1);
+    let (integer, fraction) = digits.split_at(digits.len() - scale);
+    format!("{}{}.{}d", sign, integer, fraction)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decimal_arithmetic() {
+        assert_eq!(add((1230, 2), (5, 1)), Ok((128, 1)));
+        assert_eq!(add((125, 2), (25, 2)), Ok((15, 1)));
+        assert_eq!(sub((1230, 2), (2, 0)), Ok((103, 1)));
+        assert_eq!(mul((150, 2), (3, 1)), Ok((45, 2)));
+        assert_eq!(mul((25, 1), (4, 0)), Ok((10, 0)));
+        assert_eq!(div((123, 1), (4, 0)), Ok((3075, 3)));
+        assert_eq!(div((1, 0), (4, 1)), Ok((25, 1)));
+        let third: i128 = "3".repeat(38).parse().unwrap();
+        assert_eq!(div((100, 2), (3, 0)), Ok((third, 38)));
+        assert_eq!(div((-100, 2), (3, 0)), Ok((-third, 38)));
+        assert_eq!(div((i128::MAX, 0), (7, 0)), Ok((i128::MAX / 7, 0)));
+        assert_eq!(div((1, 0), (0, 2)), Err(error::Expression::DivideByZero));
+        assert_eq!(compare((1230, 2), (123, 1)), Ordering::Equal);
+        assert_eq!(compare((-1, 0), (1, 38)), Ordering::Less);
+        assert_eq!(compare((i128::MAX, 0), (1, 38)), Ordering::Greater);
+        assert_eq!(compare((i128::MAX, 38), (2, 0)), Ordering::Less);
+        assert_eq!(compare((i128::MAX, 38), (1, 0)), Ordering::Greater);
+        assert_eq!(compare((-i128::MAX, 0), (-1, 38)), Ordering::Less);
+        assert_eq!(compare((i128::MIN, 38), (i128::MIN, 37)), Ordering::Greater);
+        assert_eq!(compare((1, 0), (1, 200)), Ordering::Greater);
+        assert_eq!(
+            add((i128::MAX, 0), (1, 0)),
+            Err(error::Expression::Overflow)
+        );
+        assert_eq!(mul((1, 20), (1, 20)), Err(error::Expression::Overflow));
+
+        assert_eq!(to_string(1230, 2), "12.30d");
+        assert_eq!(to_string(-5, 3), "-0.005d");
+        assert_eq!(to_string(0, 1), "0.0d");
+        assert_eq!(to_string(42, 0), "42d");
+        assert_eq!(
+            to_string(i128::MIN, 38),
+            "-1.70141183460469231731687303715884105728d"
+        );
+    }
+}
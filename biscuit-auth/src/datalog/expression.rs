@@ -1,9 +1,11 @@
 use crate::error;
 
-use super::Term;
+use super::{decimal, Term};
 use super::{SymbolTable, TemporarySymbolTable};
 use regex::Regex;
+use std::collections::hash_map::DefaultHasher;
 use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
 pub struct Expression {
@@ -23,13 +25,15 @@ pub enum Unary {
     Negate,
     Parens,
     Length,
+    Lowercase,
+    Trim,
 }
 
 impl Unary {
     fn evaluate(
         &self,
         value: Term,
-        symbols: &TemporarySymbolTable,
+        symbols: &mut TemporarySymbolTable,
     ) -> Result<Term, error::Expression> {
         match (self, value) {
             (Unary::Negate, Term::Bool(b)) => Ok(Term::Bool(!b)),
@@ -40,6 +44,20 @@ impl Unary {
                 .ok_or(error::Expression::UnknownSymbol(i)),
             (Unary::Length, Term::Bytes(s)) => Ok(Term::Integer(s.len() as i64)),
             (Unary::Length, Term::Set(s)) => Ok(Term::Integer(s.len() as i64)),
+            (Unary::Lowercase, Term::Str(i)) => match symbols.get_symbol(i) {
+                Some(s) => {
+                    let s = s.to_lowercase();
+                    Ok(Term::Str(symbols.insert(&s)))
+                }
+                None => Err(error::Expression::UnknownSymbol(i)),
+            },
+            (Unary::Trim, Term::Str(i)) => match symbols.get_symbol(i) {
+                Some(s) => {
+                    let s = s.trim().to_string();
+                    Ok(Term::Str(symbols.insert(&s)))
+                }
+                None => Err(error::Expression::UnknownSymbol(i)),
+            },
             _ => {
                 //println!("unexpected value type on the stack");
                 Err(error::Expression::InvalidType)
@@ -52,6 +70,8 @@ impl Unary {
             Unary::Negate => format!("!{}", value),
             Unary::Parens => format!("({})", value),
             Unary::Length => format!("{}.length()", value),
+            Unary::Lowercase => format!("{}.to_lowercase()", value),
+            Unary::Trim => format!("{}.trim()", value),
         }
     }
 }
@@ -80,6 +100,12 @@ pub enum Binary {
     BitwiseOr,
     BitwiseXor,
     NotEqual,
+    Difference,
+    SymmetricDifference,
+    IsSubset,
+    IsSuperset,
+    Split,
+    Capture,
 }
 
 impl Binary {
@@ -117,6 +143,48 @@ impl Binary {
             (Binary::BitwiseOr, Term::Integer(i), Term::Integer(j)) => Ok(Term::Integer(i | j)),
             (Binary::BitwiseXor, Term::Integer(i), Term::Integer(j)) => Ok(Term::Integer(i ^ j)),
 
+            // decimal, integers are converted to decimals with a scale of 0
+            (
+                op @ (Binary::LessThan
+                | Binary::GreaterThan
+                | Binary::LessOrEqual
+                | Binary::GreaterOrEqual
+                | Binary::Equal
+                | Binary::NotEqual
+                | Binary::Add
+                | Binary::Sub
+                | Binary::Mul
+                | Binary::Div),
+                left @ (Term::Decimal(..) | Term::Integer(_)),
+                right @ (Term::Decimal(..) | Term::Integer(_)),
+            ) => {
+                let to_decimal = |term| match term {
+                    Term::Decimal(m, s) => (m, s),
+                    Term::Integer(i) => (i128::from(i), 0),
+                    _ => unreachable!(),
+                };
+                let (left, right) = (to_decimal(left), to_decimal(right));
+
+                match op {
+                    Binary::Add => decimal::add(left, right),
+                    Binary::Sub => decimal::sub(left, right),
+                    Binary::Mul => decimal::mul(left, right),
+                    Binary::Div => decimal::div(left, right),
+                    _ => {
+                        let ordering = decimal::compare(left, right);
+                        return Ok(Term::Bool(match op {
+                            Binary::LessThan => ordering.is_lt(),
+                            Binary::GreaterThan => ordering.is_gt(),
+                            Binary::LessOrEqual => ordering.is_le(),
+                            Binary::GreaterOrEqual => ordering.is_ge(),
+                            Binary::Equal => ordering.is_eq(),
+                            _ => ordering.is_ne(),
+                        }));
+                    }
+                }
+                .map(|(m, s)| Term::Decimal(m, s))
+            }
+
             // string
             (Binary::Prefix, Term::Str(s), Term::Str(pref)) => {
                 match (symbols.get_symbol(s), symbols.get_symbol(pref)) {
@@ -141,6 +209,47 @@ impl Binary {
                     _ => Err(error::Expression::UnknownSymbol(s)),
                 }
             }
+            // the first capture group, or the whole match if the regex has no group.
+            // Unlike `matches`, an invalid regex is an error, and so is a string
+            // that does not match: rules can test `matches` first
+            (Binary::Capture, Term::Str(s), Term::Str(r)) => {
+                match (symbols.get_symbol(s), symbols.get_symbol(r)) {
+                    (Some(s), Some(r)) => {
+                        let re = Regex::new(r).map_err(|_| error::Expression::InvalidRegex)?;
+                        let captured = re
+                            .captures(s)
+                            .and_then(|c| {
+                                if re.captures_len() > 1 {
+                                    c.get(1)
+                                } else {
+                                    c.get(0)
+                                }
+                            })
+                            .ok_or(error::Expression::NoMatch)?
+                            .as_str()
+                            .to_string();
+                        Ok(Term::Str(symbols.insert(&captured)))
+                    }
+                    (Some(_), None) => Err(error::Expression::UnknownSymbol(r)),
+                    _ => Err(error::Expression::UnknownSymbol(s)),
+                }
+            }
+            (Binary::Split, Term::Str(s), Term::Str(separator)) => {
+                match (symbols.get_symbol(s), symbols.get_symbol(separator)) {
+                    (Some(s), Some(separator)) => {
+                        let parts: Vec<String> =
+                            s.split(separator).map(|part| part.to_string()).collect();
+                        Ok(Term::Set(
+                            parts
+                                .iter()
+                                .map(|part| Term::Str(symbols.insert(part)))
+                                .collect(),
+                        ))
+                    }
+                    (Some(_), None) => Err(error::Expression::UnknownSymbol(separator)),
+                    _ => Err(error::Expression::UnknownSymbol(s)),
+                }
+            }
             (Binary::Contains, Term::Str(s), Term::Str(pattern)) => {
                 match (symbols.get_symbol(s), symbols.get_symbol(pattern)) {
                     (Some(s), Some(pattern)) => Ok(Term::Bool(s.contains(pattern))),
@@ -176,6 +285,14 @@ impl Binary {
             (Binary::Equal, Term::Bytes(i), Term::Bytes(j)) => Ok(Term::Bool(i == j)),
             (Binary::NotEqual, Term::Bytes(i), Term::Bytes(j)) => Ok(Term::Bool(i != j)),
 
+            // uuid, ordered like their bytes
+            (Binary::LessThan, Term::Uuid(i), Term::Uuid(j)) => Ok(Term::Bool(i < j)),
+            (Binary::GreaterThan, Term::Uuid(i), Term::Uuid(j)) => Ok(Term::Bool(i > j)),
+            (Binary::LessOrEqual, Term::Uuid(i), Term::Uuid(j)) => Ok(Term::Bool(i <= j)),
+            (Binary::GreaterOrEqual, Term::Uuid(i), Term::Uuid(j)) => Ok(Term::Bool(i >= j)),
+            (Binary::Equal, Term::Uuid(i), Term::Uuid(j)) => Ok(Term::Bool(i == j)),
+            (Binary::NotEqual, Term::Uuid(i), Term::Uuid(j)) => Ok(Term::Bool(i != j)),
+
             // set
             (Binary::Equal, Term::Set(set), Term::Set(s)) => Ok(Term::Bool(set == s)),
             (Binary::NotEqual, Term::Set(set), Term::Set(s)) => Ok(Term::Bool(set != s)),
@@ -185,6 +302,16 @@ impl Binary {
             (Binary::Union, Term::Set(set), Term::Set(s)) => {
                 Ok(Term::Set(set.union(&s).cloned().collect()))
             }
+            (Binary::Difference, Term::Set(set), Term::Set(s)) => {
+                Ok(Term::Set(set.difference(&s).cloned().collect()))
+            }
+            (Binary::SymmetricDifference, Term::Set(set), Term::Set(s)) => {
+                Ok(Term::Set(set.symmetric_difference(&s).cloned().collect()))
+            }
+            (Binary::IsSubset, Term::Set(set), Term::Set(s)) => Ok(Term::Bool(set.is_subset(&s))),
+            (Binary::IsSuperset, Term::Set(set), Term::Set(s)) => {
+                Ok(Term::Bool(set.is_superset(&s)))
+            }
             (Binary::Contains, Term::Set(set), Term::Set(s)) => Ok(Term::Bool(set.is_superset(&s))),
             (Binary::Contains, Term::Set(set), Term::Integer(i)) => {
                 Ok(Term::Bool(set.contains(&Term::Integer(i))))
@@ -201,6 +328,12 @@ impl Binary {
             (Binary::Contains, Term::Set(set), Term::Bytes(i)) => {
                 Ok(Term::Bool(set.contains(&Term::Bytes(i))))
             }
+            (Binary::Contains, Term::Set(set), Term::Decimal(m, s)) => {
+                Ok(Term::Bool(set.contains(&Term::Decimal(m, s))))
+            }
+            (Binary::Contains, Term::Set(set), Term::Uuid(u)) => {
+                Ok(Term::Bool(set.contains(&Term::Uuid(u))))
+            }
 
             // boolean
             (Binary::And, Term::Bool(i), Term::Bool(j)) => Ok(Term::Bool(i & j)),
@@ -238,6 +371,12 @@ impl Binary {
             Binary::BitwiseAnd => format!("{} & {}", left, right),
             Binary::BitwiseOr => format!("{} | {}", left, right),
             Binary::BitwiseXor => format!("{} ^ {}", left, right),
+            Binary::Difference => format!("{}.difference({})", left, right),
+            Binary::SymmetricDifference => format!("{}.symmetric_difference({})", left, right),
+            Binary::IsSubset => format!("{}.is_subset({})", left, right),
+            Binary::IsSuperset => format!("{}.is_superset({})", left, right),
+            Binary::Split => format!("{}.split({})", left, right),
+            Binary::Capture => format!("{}.capture({})", left, right),
         }
     }
 }
@@ -314,6 +453,188 @@ impl Expression {
     }
 }
 
+/// maximum number of results kept by a [`CachedExpression`]
+const MAX_CACHED_RESULTS: usize = 10_000;
+
+/// values of the variables of a [`CachedExpression`], and its result
+type CachedResult = (Vec<Term>, Result<bool, error::Expression>);
+
+/// evaluates a rule expression for each binding of the rule's variables
+///
+/// The sub-expressions that do not depend on variables, like a large set
+/// literal or a string concatenation, are evaluated once when the cache is
+/// created. Results are then memoized by the values of the variables used in
+/// the expression, so bindings that only differ by other variables do not
+/// evaluate it again.
+///
+/// Only boolean results are cached, since other values could refer to
+/// temporary symbols that are specific to one evaluation. An expression using
+/// all the variables of its rule gets a different binding each time, so its
+/// results are not cached.
+pub struct CachedExpression<'a> {
+    expression: std::borrow::Cow<'a, Expression>,
+    variables: Vec<u32>,
+    cache: bool,
+    /// results by hash of the values of `variables`, with these values
+    results: HashMap<u64, Vec<CachedResult>>,
+    cached_results: usize,
+}
+
+impl<'a> CachedExpression<'a> {
+    /// `rule_variables` is the number of variables of the rule
+    pub fn new(expression: &'a Expression, symbols: &SymbolTable, rule_variables: usize) -> Self {
+        let mut variables: Vec<u32> = expression
+            .ops
+            .iter()
+            .filter_map(|op| match op {
+                Op::Value(Term::Variable(i)) => Some(*i),
+                _ => None,
+            })
+            .collect();
+        variables.sort_unstable();
+        variables.dedup();
+
+        let expression = match expression.fold_constants(symbols) {
+            Some(folded) => std::borrow::Cow::Owned(folded),
+            None => std::borrow::Cow::Borrowed(expression),
+        };
+
+        CachedExpression {
+            cache: variables.len() < rule_variables,
+            expression,
+            variables,
+            results: HashMap::new(),
+            cached_results: 0,
+        }
+    }
+
+    /// evaluates the expression, which must return a boolean
+    pub fn evaluate(
+        &mut self,
+        values: &HashMap<u32, Term>,
+        symbols: &SymbolTable,
+    ) -> Result<bool, error::Expression> {
+        if !self.cache {
+            return self.evaluate_uncached(values, symbols);
+        }
+
+        // the values are borrowed for the lookup, and only copied to
+        // store a new result
+        let mut key = Vec::with_capacity(self.variables.len());
+        let mut hasher = DefaultHasher::new();
+        for variable in self.variables.iter() {
+            match values.get(variable) {
+                Some(term) => {
+                    term.hash(&mut hasher);
+                    key.push(term);
+                }
+                None => return Err(error::Expression::UnknownVariable(*variable)),
+            }
+        }
+        let hash = hasher.finish();
+
+        if let Some(results) = self.results.get(&hash) {
+            if let Some((_, result)) = results
+                .iter()
+                .find(|(terms, _)| terms.iter().eq(key.iter().copied()))
+            {
+                return result.clone();
+            }
+        }
+
+        let result = self.evaluate_uncached(values, symbols);
+
+        if self.cached_results < MAX_CACHED_RESULTS {
+            self.cached_results += 1;
+            self.results
+                .entry(hash)
+                .or_default()
+                .push((key.into_iter().cloned().collect(), result.clone()));
+        }
+        result
+    }
+
+    fn evaluate_uncached(
+        &self,
+        values: &HashMap<u32, Term>,
+        symbols: &SymbolTable,
+    ) -> Result<bool, error::Expression> {
+        let mut temporary_symbols = TemporarySymbolTable::new(symbols);
+        match self.expression.evaluate(values, &mut temporary_symbols) {
+            Ok(Term::Bool(b)) => Ok(b),
+            Ok(_) => Err(error::Expression::InvalidType),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Expression {
+    /// replaces the sub-expressions that do not contain variables with their value
+    ///
+    /// returns `None` if nothing could be folded. Sub-expressions that fail, or
+    /// that create temporary symbols, are left as is
+    fn fold_constants(&self, symbols: &SymbolTable) -> Option<Expression> {
+        // each element is the list of operations of a sub-expression, and
+        // whether it is constant
+        let mut stack: Vec<(Vec<Op>, bool)> = Vec::new();
+        let mut folded = false;
+
+        let mut fold = |ops: Vec<Op>| -> Vec<Op> {
+            if ops.len() == 1 {
+                return ops;
+            }
+            let expression = Expression { ops };
+            let mut temporary_symbols = TemporarySymbolTable::new(symbols);
+            match expression.evaluate(&HashMap::new(), &mut temporary_symbols) {
+                Ok(term) if !temporary_symbols.has_temporary_symbols() => {
+                    folded = true;
+                    vec![Op::Value(term)]
+                }
+                _ => expression.ops,
+            }
+        };
+
+        for op in self.ops.iter() {
+            match op {
+                Op::Value(Term::Variable(_)) => stack.push((vec![op.clone()], false)),
+                Op::Value(_) => stack.push((vec![op.clone()], true)),
+                Op::Unary(_) => {
+                    let (mut ops, constant) = stack.pop()?;
+                    ops.push(op.clone());
+                    stack.push((ops, constant));
+                }
+                Op::Binary(_) => {
+                    let (right, right_constant) = stack.pop()?;
+                    let (left, left_constant) = stack.pop()?;
+                    if left_constant && right_constant {
+                        let mut ops = left;
+                        ops.extend(right);
+                        ops.push(op.clone());
+                        stack.push((ops, true));
+                    } else {
+                        let mut ops = if left_constant { fold(left) } else { left };
+                        ops.extend(if right_constant { fold(right) } else { right });
+                        ops.push(op.clone());
+                        stack.push((ops, false));
+                    }
+                }
+            }
+        }
+
+        if stack.len() != 1 {
+            return None;
+        }
+        let (ops, constant) = stack.remove(0);
+        let ops = if constant { fold(ops) } else { ops };
+
+        if folded {
+            Some(Expression { ops })
+        } else {
+            None
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -469,4 +790,149 @@ mod tests {
         assert_eq!(e3.print(&symbols).unwrap(), "1 + 2 < 3");
         //panic!();
     }
+
+    #[test]
+    fn set_operations() {
+        let symbols = SymbolTable::new();
+        let mut tmp_symbols = TemporarySymbolTable::new(&symbols);
+        let set = |values: &[i64]| Term::Set(values.iter().map(|i| Term::Integer(*i)).collect());
+
+        for (op, left, right, expected, printed) in [
+            (
+                Binary::Difference,
+                set(&[1, 2, 3]),
+                set(&[2, 4]),
+                set(&[1, 3]),
+                "[1, 2, 3].difference([2, 4])",
+            ),
+            (
+                Binary::SymmetricDifference,
+                set(&[1, 2, 3]),
+                set(&[2, 4]),
+                set(&[1, 3, 4]),
+                "[1, 2, 3].symmetric_difference([2, 4])",
+            ),
+            (
+                Binary::IsSubset,
+                set(&[1, 2]),
+                set(&[1, 2, 3]),
+                Term::Bool(true),
+                "[1, 2].is_subset([1, 2, 3])",
+            ),
+            (
+                Binary::IsSubset,
+                set(&[1, 4]),
+                set(&[1, 2, 3]),
+                Term::Bool(false),
+                "[1, 4].is_subset([1, 2, 3])",
+            ),
+            (
+                Binary::IsSuperset,
+                set(&[1, 2, 3]),
+                set(&[]),
+                Term::Bool(true),
+                "[1, 2, 3].is_superset([])",
+            ),
+        ] {
+            let e = Expression {
+                ops: vec![Op::Value(left), Op::Value(right), Op::Binary(op)],
+            };
+            assert_eq!(e.print(&symbols).unwrap(), printed);
+            assert_eq!(e.evaluate(&HashMap::new(), &mut tmp_symbols), Ok(expected));
+        }
+
+        let e = Expression {
+            ops: vec![
+                Op::Value(set(&[1])),
+                Op::Value(Term::Integer(1)),
+                Op::Binary(Binary::IsSubset),
+            ],
+        };
+        assert_eq!(
+            e.evaluate(&HashMap::new(), &mut tmp_symbols),
+            Err(error::Expression::InvalidType)
+        );
+    }
+
+    #[test]
+    fn cached_expression() {
+        let mut symbols = SymbolTable::new();
+        let x = symbols.insert("x") as u32;
+        let a = symbols.insert("a");
+        let b = symbols.insert("b");
+
+        // $x + (1 + 2) == 4
+        let e = Expression {
+            ops: vec![
+                Op::Value(Term::Variable(x)),
+                Op::Value(Term::Integer(1)),
+                Op::Value(Term::Integer(2)),
+                Op::Binary(Binary::Add),
+                Op::Unary(Unary::Parens),
+                Op::Binary(Binary::Add),
+                Op::Value(Term::Integer(4)),
+                Op::Binary(Binary::Equal),
+            ],
+        };
+        assert_eq!(
+            e.fold_constants(&symbols).unwrap().ops,
+            vec![
+                Op::Value(Term::Variable(x)),
+                Op::Value(Term::Integer(3)),
+                Op::Binary(Binary::Add),
+                Op::Value(Term::Integer(4)),
+                Op::Binary(Binary::Equal),
+            ]
+        );
+
+        // in a rule with another variable
+        let mut cached = CachedExpression::new(&e, &symbols, 2);
+        let values = |i: i64| [(x, Term::Integer(i))].iter().cloned().collect();
+        assert_eq!(cached.evaluate(&values(1), &symbols), Ok(true));
+        assert_eq!(cached.evaluate(&values(2), &symbols), Ok(false));
+        assert_eq!(cached.evaluate(&values(1), &symbols), Ok(true));
+        assert_eq!(cached.cached_results, 2);
+
+        // `$x` is the only variable of the rule, each binding is different
+        let mut uncached = CachedExpression::new(&e, &symbols, 1);
+        assert_eq!(uncached.evaluate(&values(1), &symbols), Ok(true));
+        assert_eq!(uncached.evaluate(&values(2), &symbols), Ok(false));
+        assert!(uncached.results.is_empty());
+        assert_eq!(
+            cached.evaluate(&HashMap::new(), &symbols),
+            Err(error::Expression::UnknownVariable(x))
+        );
+
+        // concatenations create temporary symbols and are not folded
+        // "a" + "b" == $x
+        let e = Expression {
+            ops: vec![
+                Op::Value(Term::Str(a)),
+                Op::Value(Term::Str(b)),
+                Op::Binary(Binary::Add),
+                Op::Value(Term::Variable(x)),
+                Op::Binary(Binary::Equal),
+            ],
+        };
+        assert_eq!(e.fold_constants(&symbols), None);
+        let ab = symbols.insert("ab");
+        let mut cached = CachedExpression::new(&e, &symbols, 2);
+        assert_eq!(
+            cached.evaluate(&[(x, Term::Str(ab))].iter().cloned().collect(), &symbols),
+            Ok(true)
+        );
+        assert_eq!(
+            cached.evaluate(&[(x, Term::Str(a))].iter().cloned().collect(), &symbols),
+            Ok(false)
+        );
+
+        // non boolean results are rejected
+        let e = Expression {
+            ops: vec![Op::Value(Term::Integer(1))],
+        };
+        assert_eq!(
+            CachedExpression::new(&e, &symbols, 0).evaluate(&HashMap::new(), &symbols),
+            Err(error::Expression::InvalidType)
+        );
+    }
 }
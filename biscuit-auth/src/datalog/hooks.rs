@@ -0,0 +1,129 @@
+//! callbacks called by the Datalog engine while it runs
+use super::{Fact, FactSet, Origin, Rule, SymbolTable};
+
+/// observes the evaluation of rules in [`World::run_with_hooks`](super::World::run_with_hooks)
+///
+/// all methods do nothing by default. They are called synchronously from the
+/// engine, so an implementation can pause the evaluation (to step through
+/// iterations or stop on a breakpoint) by blocking in a callback.
+/// The execution limits still apply while the callbacks run.
+pub trait DebugHooks {
+    /// called before a rule is applied to the current facts
+    ///
+    /// `origin` is the index of the block that contains the rule
+    /// (`usize::MAX` for the authorizer)
+    fn on_rule_start(&mut self, _origin: usize, _rule: &Rule) {}
+
+    /// called for each fact produced by the rule being applied
+    ///
+    /// the fact may already be known: it is only added to the world
+    /// at the end of the iteration
+    fn on_fact_derived(&mut self, _origin: &Origin, _fact: &Fact) {}
+
+    /// called once all the facts produced by the rule were reported
+    fn on_rule_end(&mut self, _origin: usize, _rule: &Rule) {}
+
+    /// called with the facts generated by an iteration that were not known yet,
+    /// before they are added to the world
+    fn on_new_facts(&mut self, _iteration: u64, _facts: &FactSet) {}
+
+    /// called after the facts generated in an iteration are added to the world
+    ///
+    /// `iteration` starts at 0, `facts` contains all the facts known so far
+    fn on_iteration_end(&mut self, _iteration: u64, _facts: &FactSet) {}
+}
+
+impl DebugHooks for () {}
+
+impl<H: DebugHooks + ?Sized> DebugHooks for &mut H {
+    fn on_rule_start(&mut self, origin: usize, rule: &Rule) {
+        (**self).on_rule_start(origin, rule)
+    }
+
+    fn on_fact_derived(&mut self, origin: &Origin, fact: &Fact) {
+        (**self).on_fact_derived(origin, fact)
+    }
+
+    fn on_rule_end(&mut self, origin: usize, rule: &Rule) {
+        (**self).on_rule_end(origin, rule)
+    }
+
+    fn on_new_facts(&mut self, iteration: u64, facts: &FactSet) {
+        (**self).on_new_facts(iteration, facts)
+    }
+
+    fn on_iteration_end(&mut self, iteration: u64, facts: &FactSet) {
+        (**self).on_iteration_end(iteration, facts)
+    }
+}
+
+/// fact produced by a rule, reported to a [`Tracer`]
+pub struct RuleFiring<'a> {
+    /// index of the block that contains the rule (`usize::MAX` for the authorizer)
+    pub rule_origin: usize,
+    pub rule: &'a Rule,
+    /// blocks the facts matched by the rule come from
+    pub fact_origin: &'a Origin,
+    pub fact: &'a Fact,
+    pub symbols: &'a SymbolTable,
+}
+
+impl RuleFiring<'_> {
+    pub fn print_rule(&self) -> String {
+        self.symbols.print_rule(self.rule)
+    }
+
+    pub fn print_fact(&self) -> String {
+        self.symbols.print_fact(self.fact)
+    }
+}
+
+/// records which rule produced which fact, in [`World::run_with_tracer`](super::World::run_with_tracer)
+/// and [`Authorizer::authorize_with_tracer`](crate::Authorizer::authorize_with_tracer)
+///
+/// a rule can produce a fact that is already known, in which case it is
+/// reported again
+pub trait Tracer {
+    fn on_rule_fired(&mut self, firing: &RuleFiring);
+}
+
+impl<F: FnMut(&RuleFiring)> Tracer for F {
+    fn on_rule_fired(&mut self, firing: &RuleFiring) {
+        self(firing)
+    }
+}
+
+/// forwards the facts derived by the engine to a [`Tracer`]
+pub(crate) struct TracerHooks<'a> {
+    tracer: &'a mut dyn Tracer,
+    symbols: &'a SymbolTable,
+    rule: Option<(usize, Rule)>,
+}
+
+impl<'a> TracerHooks<'a> {
+    pub(crate) fn new(tracer: &'a mut dyn Tracer, symbols: &'a SymbolTable) -> Self {
+        TracerHooks {
+            tracer,
+            symbols,
+            rule: None,
+        }
+    }
+}
+
+impl DebugHooks for TracerHooks<'_> {
+    fn on_rule_start(&mut self, origin: usize, rule: &Rule) {
+        self.rule = Some((origin, rule.clone()));
+    }
+
+    fn on_fact_derived(&mut self, origin: &Origin, fact: &Fact) {
+        if let Some((rule_origin, rule)) = &self.rule {
+            self.tracer.on_rule_fired(&RuleFiring {
+                rule_origin: *rule_origin,
+                rule,
+                fact_origin: origin,
+                fact,
+                symbols: self.symbols,
+            });
+        }
+    }
+}
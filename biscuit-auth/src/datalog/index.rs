@@ -0,0 +1,119 @@
+//! fact index used to find the facts matching a rule's predicates
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use super::{Fact, MatchedVariables, Origin, Predicate, SymbolIndex, Term};
+
+/// statistics on the use of the fact index during Datalog evaluation
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IndexStats {
+    /// number of lookups by predicate name and first term, used when the first
+    /// term of a rule predicate is a value or an already bound variable
+    pub first_term_lookups: u64,
+    /// number of lookups by predicate name only
+    pub predicate_lookups: u64,
+    /// number of facts returned by lookups, that were then matched against the rule predicates
+    pub candidate_facts: u64,
+    /// largest number of facts sharing a predicate name and first term, a high
+    /// value indicates a first term with low selectivity
+    pub max_bucket_size: usize,
+}
+
+impl IndexStats {
+    pub(crate) fn merge(&mut self, other: &IndexStats) {
+        self.first_term_lookups += other.first_term_lookups;
+        self.predicate_lookups += other.predicate_lookups;
+        self.candidate_facts += other.candidate_facts;
+        self.max_bucket_size = self.max_bucket_size.max(other.max_bucket_size);
+    }
+}
+
+#[derive(Default)]
+struct PredicateIndex<'a> {
+    all: Vec<(&'a Origin, &'a Fact)>,
+    by_first_term: HashMap<&'a Term, Vec<(&'a Origin, &'a Fact)>>,
+}
+
+/// facts indexed by predicate name, then by predicate name and first term
+#[derive(Default)]
+pub struct FactIndex<'a> {
+    predicates: HashMap<SymbolIndex, PredicateIndex<'a>>,
+    first_term_lookups: Cell<u64>,
+    predicate_lookups: Cell<u64>,
+    candidate_facts: Cell<u64>,
+}
+
+impl<'a> FactIndex<'a> {
+    pub fn new(facts: impl Iterator<Item = (&'a Origin, &'a Fact)>) -> Self {
+        let mut predicates: HashMap<SymbolIndex, PredicateIndex<'a>> = HashMap::new();
+
+        for (origin, fact) in facts {
+            let index = predicates.entry(fact.predicate.name).or_default();
+            index.all.push((origin, fact));
+            if let Some(term) = fact.predicate.terms.first() {
+                index
+                    .by_first_term
+                    .entry(term)
+                    .or_default()
+                    .push((origin, fact));
+            }
+        }
+
+        FactIndex {
+            predicates,
+            ..Default::default()
+        }
+    }
+
+    /// returns the facts that can match `predicate`, knowing the values of
+    /// the variables that were already matched
+    pub fn candidates(
+        &self,
+        predicate: &Predicate,
+        variables: &MatchedVariables,
+    ) -> &[(&'a Origin, &'a Fact)] {
+        let index = match self.predicates.get(&predicate.name) {
+            Some(index) => index,
+            None => return &[],
+        };
+
+        let first_term = match predicate.terms.first() {
+            Some(Term::Variable(v)) => variables.variables.get(v).and_then(Option::as_ref),
+            term => term,
+        };
+
+        let candidates = match first_term {
+            Some(term) => {
+                self.first_term_lookups
+                    .set(self.first_term_lookups.get() + 1);
+                index
+                    .by_first_term
+                    .get(term)
+                    .map(Vec::as_slice)
+                    .unwrap_or(&[])
+            }
+            None => {
+                self.predicate_lookups.set(self.predicate_lookups.get() + 1);
+                index.all.as_slice()
+            }
+        };
+
+        self.candidate_facts
+            .set(self.candidate_facts.get() + candidates.len() as u64);
+        candidates
+    }
+
+    pub fn stats(&self) -> IndexStats {
+        IndexStats {
+            first_term_lookups: self.first_term_lookups.get(),
+            predicate_lookups: self.predicate_lookups.get(),
+            candidate_facts: self.candidate_facts.get(),
+            max_bucket_size: self
+                .predicates
+                .values()
+                .flat_map(|index| index.by_first_term.values().map(Vec::len))
+                .max()
+                .unwrap_or(0),
+        }
+    }
+}
@@ -1,18 +1,34 @@
 //! Logic language implementation for checks
 use crate::builder::{CheckKind, Convert};
 use crate::error::Execution;
-use crate::time::Instant;
-use crate::token::{Scope, MIN_SCHEMA_VERSION};
+use crate::time::{Clock, Instant, SystemClock};
+use crate::token::{
+    Scope, EXTENSION_SCHEMA_VERSION_1, EXTENSION_SCHEMA_VERSION_2, MIN_SCHEMA_VERSION,
+};
 use crate::{builder, error};
 use std::collections::{BTreeSet, HashMap, HashSet};
-use std::convert::AsRef;
+use std::convert::{AsRef, TryFrom};
 use std::fmt;
+use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+mod aggregate;
+pub(crate) mod decimal;
 mod expression;
+mod hooks;
+mod index;
 mod origin;
 mod symbol;
+pub use aggregate::Aggregate;
+use aggregate::AggregateRun;
 pub use expression::*;
+#[cfg(feature = "debug-hooks")]
+pub use hooks::DebugHooks;
+#[cfg(not(feature = "debug-hooks"))]
+pub(crate) use hooks::DebugHooks;
+use hooks::TracerHooks;
+pub use hooks::{RuleFiring, Tracer};
+pub use index::{FactIndex, IndexStats};
 pub use origin::*;
 pub use symbol::*;
 
@@ -25,6 +41,10 @@ pub enum Term {
     Bytes(Vec<u8>),
     Bool(bool),
     Set(BTreeSet<Term>),
+    /// fixed point decimal number, as a mantissa and a number of fractional digits
+    Decimal(i128, u8),
+    /// UUID, as its 16 bytes in big endian order
+    Uuid([u8; 16]),
 }
 
 impl From<&Term> for Term {
@@ -37,6 +57,8 @@ impl From<&Term> for Term {
             Term::Bytes(ref b) => Term::Bytes(b.clone()),
             Term::Bool(ref b) => Term::Bool(*b),
             Term::Set(ref s) => Term::Set(s.clone()),
+            Term::Decimal(m, s) => Term::Decimal(*m, *s),
+            Term::Uuid(u) => Term::Uuid(*u),
         }
     }
 }
@@ -87,6 +109,8 @@ pub struct Rule {
     pub body: Vec<Predicate>,
     pub expressions: Vec<Expression>,
     pub scopes: Vec<Scope>,
+    /// aggregations applied to head terms, by position in the head
+    pub aggregates: Vec<(usize, Aggregate)>,
 }
 
 impl AsRef<Expression> for Expression {
@@ -99,6 +123,7 @@ impl AsRef<Expression> for Expression {
 pub struct Check {
     pub queries: Vec<Rule>,
     pub kind: CheckKind,
+    pub message: Option<String>,
 }
 
 impl fmt::Display for Fact {
@@ -130,17 +155,118 @@ impl Rule {
     where
         IT: Iterator<Item = (&'a Origin, &'a Fact)> + Clone + 'a,
     {
-        let head = self.head.clone();
+        self.apply_from(facts, Some(rule_origin), symbols)
+    }
+
+    /// applies the rule like [`Rule::apply`], without adding the rule's
+    /// origin to the generated facts if `rule_origin` is `None`
+    fn apply_from<'a, IT>(
+        &'a self,
+        facts: IT,
+        rule_origin: Option<usize>,
+        symbols: &'a SymbolTable,
+    ) -> impl Iterator<Item = Result<(Origin, Fact), error::Expression>> + 'a
+    where
+        IT: Iterator<Item = (&'a Origin, &'a Fact)> + Clone + 'a,
+    {
+        let variables = MatchedVariables::new(self.variables_set());
+
+        self.apply_combinations(
+            CombineIt::new(variables, &self.body, facts, symbols),
+            rule_origin,
+            symbols,
+        )
+    }
+
+    /// applies the rule like [`Rule::apply`], using `index` to find the facts
+    /// that can match each predicate. `index` must contain the same facts as `facts`
+    pub fn apply_indexed<'a, IT>(
+        &'a self,
+        facts: IT,
+        index: &'a FactIndex<'a>,
+        rule_origin: usize,
+        symbols: &'a SymbolTable,
+    ) -> impl Iterator<Item = Result<(Origin, Fact), error::Expression>> + 'a
+    where
+        IT: Iterator<Item = (&'a Origin, &'a Fact)> + Clone + 'a,
+    {
         let variables = MatchedVariables::new(self.variables_set());
 
-        CombineIt::new(variables, &self.body, facts, symbols)
+        self.apply_combinations(
+            CombineIt::with_index(variables, &self.body, facts, index, symbols),
+            Some(rule_origin),
+            symbols,
+        )
+    }
+
+    /// the rule body once for each predicate, with that predicate moved first,
+    /// for [`Rule::apply_delta`]
+    pub fn delta_bodies(&self) -> Vec<Vec<Predicate>> {
+        (0..self.body.len())
+            .map(|position| {
+                let mut body = self.body.clone();
+                let predicate = body.remove(position);
+                body.insert(0, predicate);
+                body
+            })
+            .collect()
+    }
+
+    /// applies the rule like [`Rule::apply_indexed`], but only to the
+    /// combinations of facts containing at least one of the facts of `delta`
+    /// trusted by `scope`
+    ///
+    /// `bodies` comes from [`Rule::delta_bodies`]: each predicate takes its
+    /// turn matching the delta, before the other predicates match all the facts
+    #[allow(clippy::too_many_arguments)]
+    pub fn apply_delta<'a, IT>(
+        &'a self,
+        bodies: &'a [Vec<Predicate>],
+        facts: IT,
+        index: &'a FactIndex<'a>,
+        delta: &'a FactSet,
+        scope: &'a TrustedOrigins,
+        rule_origin: usize,
+        symbols: &'a SymbolTable,
+    ) -> impl Iterator<Item = Result<(Origin, Fact), error::Expression>> + 'a
+    where
+        IT: Iterator<Item = (&'a Origin, &'a Fact)> + Clone + 'a,
+    {
+        bodies.iter().flat_map(move |body| {
+            let variables = MatchedVariables::new(self.variables_set());
+            self.apply_combinations(
+                CombineIt::build(
+                    variables,
+                    body,
+                    facts.clone(),
+                    Some(index),
+                    Some((delta, scope)),
+                    symbols,
+                ),
+                Some(rule_origin),
+                symbols,
+            )
+        })
+    }
+
+    fn apply_combinations<'a, IT>(
+        &'a self,
+        combinations: CombineIt<'a, IT>,
+        rule_origin: Option<usize>,
+        symbols: &'a SymbolTable,
+    ) -> impl Iterator<Item = Result<(Origin, Fact), error::Expression>> + 'a
+    where
+        IT: Iterator<Item = (&'a Origin, &'a Fact)> + Clone + 'a,
+    {
+        let head = self.head.clone();
+        let mut expressions = self.cached_expressions(symbols);
+
+        combinations
         .map(move |(origin, variables)| {
-                    let mut temporary_symbols = TemporarySymbolTable::new(&symbols);
-                    for e in self.expressions.iter() {
-                        match e.evaluate(&variables, &mut temporary_symbols) {
-                            Ok(Term::Bool(true)) => {}
-                            Ok(Term::Bool(false)) => return Ok((origin, variables, false)),
-                            Ok(_) => return Err(error::Expression::InvalidType.into()),
+                    for e in expressions.iter_mut() {
+                        match e.evaluate(&variables, symbols) {
+                            Ok(true) => {}
+                            Ok(false) => return Ok((origin, variables, false)),
                             Err(e) => {
                                 //println!("expr returned {:?}", res);
                                 return Err(e);
@@ -166,7 +292,9 @@ impl Rule {
                         };
                     }
         
-                    origin.insert(rule_origin);
+                    if let Some(rule_origin) = rule_origin {
+                        origin.insert(rule_origin);
+                    }
                     Some(Ok((origin, Fact { predicate: p })))
                 } else {None}
                 },
@@ -176,6 +304,15 @@ impl Rule {
         })
     }
 
+    /// prepares the expressions for evaluation over the rule's bindings
+    fn cached_expressions<'a>(&'a self, symbols: &SymbolTable) -> Vec<CachedExpression<'a>> {
+        let rule_variables = self.variables_set().len();
+        self.expressions
+            .iter()
+            .map(|e| CachedExpression::new(e, symbols, rule_variables))
+            .collect()
+    }
+
     pub fn find_match(
         &self,
         facts: &FactSet,
@@ -203,19 +340,18 @@ impl Rule {
         let fact_it = facts.iterator(scope);
         let variables = MatchedVariables::new(self.variables_set());
         let mut found = false;
+        let mut expressions = self.cached_expressions(symbols);
 
         for (_, variables) in CombineIt::new(variables, &self.body, fact_it, symbols) {
             found = true;
 
-            let mut temporary_symbols = TemporarySymbolTable::new(&symbols);
-            for e in self.expressions.iter() {
-                match e.evaluate(&variables, &mut temporary_symbols) {
-                    Ok(Term::Bool(true)) => {}
-                    Ok(Term::Bool(false)) => {
+            for e in expressions.iter_mut() {
+                match e.evaluate(&variables, symbols) {
+                    Ok(true) => {}
+                    Ok(false) => {
                         //println!("expr returned {:?}", res);
                         return Ok(false);
                     },
-                    Ok(_) => return Err(error::Execution::Expression(error::Expression::InvalidType)),
                     Err(e) => {
                         return Err(error::Execution::Expression(e));
                     }
@@ -262,6 +398,7 @@ impl Rule {
                         .map(|s| s.convert(target_symbols))
                 })
                 .collect::<Result<Vec<_>, _>>()?,
+            aggregates: self.aggregates.clone(),
         })
     }
 
@@ -307,6 +444,7 @@ pub struct CombineIt<'a, IT> {
     variables: MatchedVariables,
     predicates: &'a [Predicate],
     all_facts: IT,
+    index: Option<&'a FactIndex<'a>>,
     symbols: &'a SymbolTable,
     current_facts: Box<dyn Iterator<Item = (&'a Origin, &'a Fact)> + 'a>,
     current_it: Option<Box<dyn Iterator<Item = (Origin, HashMap<u32, Term>)> + 'a>>,
@@ -321,23 +459,61 @@ where
         predicates: &'a [Predicate],
         facts: IT,
         symbols: &'a SymbolTable,
+    ) -> Self {
+        Self::build(variables, predicates, facts, None, None, symbols)
+    }
+
+    /// like [`CombineIt::new`], but looks up the facts matching each predicate
+    /// in `index` instead of going through all the facts
+    pub fn with_index(
+        variables: MatchedVariables,
+        predicates: &'a [Predicate],
+        facts: IT,
+        index: &'a FactIndex<'a>,
+        symbols: &'a SymbolTable,
+    ) -> Self {
+        Self::build(variables, predicates, facts, Some(index), None, symbols)
+    }
+
+    fn build(
+        variables: MatchedVariables,
+        predicates: &'a [Predicate],
+        facts: IT,
+        index: Option<&'a FactIndex<'a>>,
+        delta: Option<(&'a FactSet, &'a TrustedOrigins)>,
+        symbols: &'a SymbolTable,
     ) -> Self {
         let current_facts: Box<dyn Iterator<Item = (&'a Origin, &'a Fact)> + 'a> =
             if predicates.is_empty() {
                 Box::new(facts.clone())
             } else {
                 let p = predicates[0].clone();
-                Box::new(
-                    facts
-                        .clone()
-                        .filter(move |fact| match_preds(&p, &fact.1.predicate)),
-                )
+                match (delta, index) {
+                    (Some((delta, scope)), _) => Box::new(
+                        delta
+                            .iterator(scope)
+                            .filter(move |fact| match_preds(&p, &fact.1.predicate)),
+                    ),
+                    (_, Some(index)) => Box::new(
+                        index
+                            .candidates(&p, &variables)
+                            .iter()
+                            .copied()
+                            .filter(move |fact| match_preds(&p, &fact.1.predicate)),
+                    ),
+                    (_, None) => Box::new(
+                        facts
+                            .clone()
+                            .filter(move |fact| match_preds(&p, &fact.1.predicate)),
+                    ),
+                }
             };
 
         CombineIt {
             variables,
             predicates,
             all_facts: facts,
+            index,
             symbols,
             current_facts,
             current_it: None,
@@ -411,10 +587,12 @@ where
                             // create a new iterator with the matched variables, the rest of the predicates,
                             // and all of the facts
                             self.current_it = Some(Box::new(
-                                CombineIt::new(
+                                CombineIt::build(
                                     vars,
                                     &self.predicates[1..],
                                     self.all_facts.clone(),
+                                    self.index,
+                                    None,
                                     self.symbols,
                                 )
                                 .map(move |(origin, variables)| {
@@ -507,6 +685,7 @@ pub fn rule<I: AsRef<Term>, P: AsRef<Predicate>>(
         body: predicates.iter().map(|p| p.as_ref().clone()).collect(),
         expressions: Vec::new(),
         scopes: vec![],
+        aggregates: vec![],
     }
 }
 
@@ -521,6 +700,7 @@ pub fn expressed_rule<I: AsRef<Term>, P: AsRef<Predicate>, C: AsRef<Expression>>
         body: predicates.iter().map(|p| p.as_ref().clone()).collect(),
         expressions: expressions.iter().map(|c| c.as_ref().clone()).collect(),
         scopes: vec![],
+        aggregates: vec![],
     }
 }
 
@@ -537,6 +717,18 @@ pub fn date(t: &SystemTime) -> Term {
     Term::Date(dur.as_secs())
 }
 
+/// prints a date in RFC 3339, dates after `9999-12-31T23:59:59Z` cannot be printed
+pub(crate) fn print_date(date: u64) -> String {
+    i64::try_from(date)
+        .ok()
+        .and_then(|d| time::OffsetDateTime::from_unix_timestamp(d).ok())
+        .and_then(|t| {
+            t.format(&time::format_description::well_known::Rfc3339)
+                .ok()
+        })
+        .unwrap_or_else(|| "<invalid date>".to_string())
+}
+
 pub fn var(syms: &mut SymbolTable, name: &str) -> Term {
     let id = syms.insert(name);
     Term::Variable(id as u32)
@@ -559,6 +751,8 @@ pub fn match_preds(rule_pred: &Predicate, fact_pred: &Predicate) -> bool {
                 (Term::Bytes(i), Term::Bytes(j)) => i == j,
                 (Term::Bool(i), Term::Bool(j)) => i == j,
                 (Term::Set(i), Term::Set(j)) => i == j,
+                (Term::Decimal(i, s), Term::Decimal(j, t)) => i == j && s == t,
+                (Term::Uuid(i), Term::Uuid(j)) => i == j,
                 _ => false,
             })
 }
@@ -568,6 +762,31 @@ pub struct World {
     pub facts: FactSet,
     pub rules: RuleSet,
     pub iterations: u64,
+    pub index_stats: IndexStats,
+    /// number of facts generated by the rules of each block during the last
+    /// run, checked against [`RunLimits::max_facts_per_block`]
+    pub block_facts: HashMap<usize, u64>,
+    /// number of iterations in which the rules of each block generated new
+    /// facts during the last run, checked against
+    /// [`RunLimits::max_iterations_per_block`]
+    pub block_iterations: HashMap<usize, u64>,
+    /// facts generated by the current iteration. It is emptied after each
+    /// iteration but keeps its allocations, so that the following iterations
+    /// and runs do not allocate their sets again.
+    ///
+    /// The facts themselves are not allocated in an arena: they own their
+    /// terms and are returned by queries and snapshots, so an arena would add
+    /// a lifetime to `Fact` and `Term` in the whole public API
+    scratch: FactSet,
+    /// facts that the rules already evaluated have not been applied to: the
+    /// facts generated by the last iteration, and the facts added since
+    delta: FactSet,
+    /// aggregates that were not applied yet, if a run is in progress
+    aggregation: Option<AggregateRun>,
+    /// facts generated once aggregates were applied. They depend on all the
+    /// facts matched by the aggregates, so they are removed and generated
+    /// again when facts or rules are added
+    aggregated: FactSet,
 }
 
 impl World {
@@ -575,8 +794,36 @@ impl World {
         World::default()
     }
 
+    /// adds a fact, that will be used by the next run
+    ///
+    /// runs are incremental: rules that already reached a fixpoint are only
+    /// applied to the combinations including new facts. Facts inserted
+    /// directly in [`World::facts`] are not seen by those rules, unless
+    /// [`World::reset_evaluation`] is called.
     pub fn add_fact(&mut self, origin: &Origin, fact: Fact) {
-        self.facts.insert(origin, fact);
+        // until a rule is evaluated, the next run applies all the rules to all the facts
+        if self.rules.has_evaluated() {
+            self.aggregated.remove(origin, &fact);
+            if self.facts.insert(origin, fact.clone()) {
+                self.delta.insert(origin, fact);
+            }
+        } else {
+            self.facts.insert(origin, fact);
+        }
+    }
+
+    /// makes the next run apply all the rules to all the facts, as when they
+    /// were just added
+    ///
+    /// the facts generated from aggregates are removed, since they can be
+    /// different once the rules are applied again
+    pub fn reset_evaluation(&mut self) {
+        for (origin, fact) in self.aggregated.iter_all() {
+            self.facts.remove(origin, fact);
+        }
+        self.aggregated = FactSet::default();
+        self.rules.evaluated.clear();
+        self.delta = FactSet::default();
     }
 
     pub fn add_rule(&mut self, origin: usize, scope: &TrustedOrigins, rule: Rule) {
@@ -592,55 +839,335 @@ impl World {
         symbols: &SymbolTable,
         limits: RunLimits,
     ) -> Result<(), crate::error::Execution> {
-        let start = Instant::now();
+        self.run_with_clock(symbols, limits, &SystemClock)
+    }
+
+    /// runs the rules like [`World::run_with_limits`], measuring the execution
+    /// time with `clock`
+    pub fn run_with_clock(
+        &mut self,
+        symbols: &SymbolTable,
+        limits: RunLimits,
+        clock: &dyn Clock,
+    ) -> Result<(), crate::error::Execution> {
+        self.run_with_clock_and_hooks(symbols, limits, clock, &mut ())
+    }
+
+    /// runs the rules like [`World::run_with_limits`], reporting to `tracer`
+    /// each fact produced by a rule
+    pub fn run_with_tracer<T: Tracer>(
+        &mut self,
+        symbols: &SymbolTable,
+        limits: RunLimits,
+        tracer: &mut T,
+    ) -> Result<(), crate::error::Execution> {
+        self.run_with_clock_and_hooks(
+            symbols,
+            limits,
+            &SystemClock,
+            &mut TracerHooks::new(tracer, symbols),
+        )
+    }
+
+    pub(crate) fn run_with_clock_and_tracer(
+        &mut self,
+        symbols: &SymbolTable,
+        limits: RunLimits,
+        clock: &dyn Clock,
+        tracer: &mut dyn Tracer,
+    ) -> Result<(), crate::error::Execution> {
+        self.run_with_clock_and_hooks(
+            symbols,
+            limits,
+            clock,
+            &mut TracerHooks::new(tracer, symbols),
+        )
+    }
+
+    pub(crate) fn run_with_clock_and_hooks<H: DebugHooks>(
+        &mut self,
+        symbols: &SymbolTable,
+        limits: RunLimits,
+        clock: &dyn Clock,
+        hooks: &mut H,
+    ) -> Result<(), crate::error::Execution> {
+        let start = clock.instant();
         let time_limit = start + limits.max_time;
         let mut index = 0;
 
-        let res = loop {
-            let mut new_facts = FactSet::default();
+        while !self.run_iteration(symbols, &limits, clock, time_limit, &mut index, hooks)? {}
 
-            for (scope, rules) in self.rules.inner.iter() {
-                let it = self.facts.iterator(scope);
-                for (origin, rule) in rules {
-                    for res in rule.apply(it.clone(), *origin, symbols) {
-                        match res {
-                            Ok((origin,fact)) => {
-                                new_facts.insert(&origin, fact);
+        Ok(())
+    }
 
-                            },
-                            Err(e)  => {
-                                return Err(Execution::Expression(e));
+    /// runs the rules like [`World::run_with_limits`], calling `hooks`
+    /// when rules are applied, facts are generated and iterations end
+    #[cfg(feature = "debug-hooks")]
+    pub fn run_with_hooks<H: DebugHooks>(
+        &mut self,
+        symbols: &SymbolTable,
+        limits: RunLimits,
+        mut hooks: H,
+    ) -> Result<(), crate::error::Execution> {
+        self.run_with_clock_and_hooks(symbols, limits, &SystemClock, &mut hooks)
+    }
+
+    /// runs the rules until no new facts are generated, yielding to the async
+    /// executor between each iteration
+    #[cfg(feature = "async")]
+    pub async fn run_with_limits_async(
+        &mut self,
+        symbols: &SymbolTable,
+        limits: RunLimits,
+    ) -> Result<(), crate::error::Execution> {
+        self.run_with_clock_async(symbols, limits, &SystemClock)
+            .await
+    }
+
+    /// runs the rules like [`World::run_with_limits_async`], measuring the
+    /// execution time with `clock`
+    #[cfg(feature = "async")]
+    pub async fn run_with_clock_async(
+        &mut self,
+        symbols: &SymbolTable,
+        limits: RunLimits,
+        clock: &dyn Clock,
+    ) -> Result<(), crate::error::Execution> {
+        let start = clock.instant();
+        let time_limit = start + limits.max_time;
+        let mut index = 0;
+
+        while !self.run_iteration(symbols, &limits, clock, time_limit, &mut index, &mut ())? {
+            crate::future::yield_now().await;
+        }
+
+        Ok(())
+    }
+
+    /// applies all the rules once
+    ///
+    /// returns `true` once no new facts were generated. The first iteration of
+    /// a run fails with [`Execution::RecursiveAggregate`] if an aggregate
+    /// depends on its own results
+    pub(crate) fn run_iteration<H: DebugHooks>(
+        &mut self,
+        symbols: &SymbolTable,
+        limits: &RunLimits,
+        clock: &dyn Clock,
+        time_limit: Instant,
+        index: &mut u64,
+        hooks: &mut H,
+    ) -> Result<bool, crate::error::Execution> {
+        if self.aggregation.is_none() {
+            // the per block quotas apply to each run
+            self.block_facts.clear();
+            self.block_iterations.clear();
+
+            // the aggregates of the previous runs did not see the new facts
+            // and rules, so everything is evaluated again
+            if !self.aggregated.is_empty() && (!self.delta.is_empty() || !self.rules.is_evaluated())
+            {
+                self.reset_evaluation();
+            }
+            self.aggregation = Some(AggregateRun::new(&self.rules, symbols)?);
+        }
+
+        let result = self.iterate(symbols, limits, clock, time_limit, index, hooks);
+        // the run is over, the next one applies all the aggregates again
+        if !matches!(result, Ok(false)) {
+            self.aggregation = None;
+        }
+        result
+    }
+
+    fn iterate<H: DebugHooks>(
+        &mut self,
+        symbols: &SymbolTable,
+        limits: &RunLimits,
+        clock: &dyn Clock,
+        time_limit: Instant,
+        index: &mut u64,
+        hooks: &mut H,
+    ) -> Result<bool, crate::error::Execution> {
+        let mut new_facts = std::mem::take(&mut self.scratch);
+        // new facts generated by the rules of each block in this iteration
+        let mut block_facts: HashMap<usize, u64> = HashMap::new();
+
+        for (scope, rules) in self.rules.inner.iter() {
+            // the rules before this position reached a fixpoint in a previous
+            // iteration or run, and only need to see the new facts
+            let evaluated = self.rules.evaluated(scope);
+            let has_delta = self.delta.iterator(scope).next().is_some();
+            if evaluated == rules.len() && !has_delta {
+                continue;
+            }
+
+            let delta_bodies: Vec<Vec<Vec<Predicate>>> = rules
+                .iter()
+                .enumerate()
+                .map(|(position, (_, rule))| {
+                    if position < evaluated {
+                        rule.delta_bodies()
+                    } else {
+                        Vec::new()
+                    }
+                })
+                .collect();
+            let index = FactIndex::new(self.facts.iterator(scope));
+            let it = self.facts.iterator(scope);
+            for (position, (origin, rule)) in rules.iter().enumerate() {
+                // aggregates are applied once the other rules are done
+                if !rule.aggregates.is_empty() || (position < evaluated && !has_delta) {
+                    continue;
+                }
+
+                let block_id = *origin;
+                hooks.on_rule_start(*origin, rule);
+                let results: Box<dyn Iterator<Item = _>> = if position < evaluated {
+                    Box::new(rule.apply_delta(
+                        &delta_bodies[position],
+                        it.clone(),
+                        &index,
+                        &self.delta,
+                        scope,
+                        *origin,
+                        symbols,
+                    ))
+                } else {
+                    Box::new(rule.apply_indexed(it.clone(), &index, *origin, symbols))
+                };
+                for res in results {
+                    match res {
+                        Ok((origin, fact)) => {
+                            hooks.on_fact_derived(&origin, &fact);
+                            // known facts are dropped right away instead of
+                            // growing the set of new facts
+                            if !self.facts.contains(&origin, &fact)
+                                && new_facts.insert(&origin, fact)
+                            {
+                                *block_facts.entry(block_id).or_default() += 1;
                             }
                         }
+                        Err(e) => {
+                            return Err(Execution::Expression(e));
+                        }
                     }
-                    //println!("new_facts after applying {:?}:\n{:#?}", rule, new_facts);
                 }
+                hooks.on_rule_end(*origin, rule);
+                //println!("new_facts after applying {:?}:\n{:#?}", rule, new_facts);
             }
+            self.index_stats.merge(&index.stats());
+        }
 
-            let len = self.facts.len();
-            self.facts.merge(new_facts);
-            if self.facts.len() == len {
-                break Ok(());
+        // aggregates need all the results of their rule, so they run when the
+        // other rules stop generating facts, once the aggregates they depend
+        // on were applied. Each aggregate is applied once per run
+        if let Some(aggregation) = self.aggregation.as_mut() {
+            while new_facts.is_empty() && !aggregation.is_done() {
+                for aggregate in aggregation.take_ready() {
+                    let block_id = aggregate.origin;
+                    let rule = &aggregate.rule;
+                    hooks.on_rule_start(block_id, rule);
+                    let facts = rule
+                        .apply_aggregates(
+                            self.facts.iterator(&aggregate.scope),
+                            Some(block_id),
+                            symbols,
+                        )
+                        .map_err(Execution::Expression)?;
+                    for (origin, fact) in facts {
+                        hooks.on_fact_derived(&origin, &fact);
+                        if !self.facts.contains(&origin, &fact) && new_facts.insert(&origin, fact) {
+                            *block_facts.entry(block_id).or_default() += 1;
+                        }
+                    }
+                    hooks.on_rule_end(block_id, rule);
+                }
             }
+        }
 
-            index += 1;
-            if index == limits.max_iterations {
-                break Err(Execution::RunLimit( crate::error::RunLimit::TooManyIterations));
+        hooks.on_new_facts(*index, &new_facts);
+        let len = self.facts.len();
+        let aggregated = self
+            .aggregation
+            .as_ref()
+            .map(AggregateRun::applied)
+            .unwrap_or(false);
+        for (origin, fact) in new_facts.iter_all() {
+            if self.facts.insert(origin, fact.clone()) && aggregated {
+                self.aggregated.insert(origin, fact.clone());
             }
+        }
+        // the new facts are the delta of the next iteration
+        let mut delta = std::mem::replace(&mut self.delta, new_facts);
+        delta.clear();
+        self.scratch = delta;
+        self.rules.mark_evaluated();
+        hooks.on_iteration_end(*index, &self.facts);
+        if self.facts.len() == len {
+            return Ok(true);
+        }
 
-            if self.facts.len() >= limits.max_facts as usize {
-                break Err(Execution::RunLimit(crate::error::RunLimit::TooManyFacts));
-            }
+        *index += 1;
+        self.iterations += 1;
+        self.check_block_limits(limits, block_facts)?;
+
+        if *index == limits.max_iterations {
+            return Err(Execution::RunLimit(
+                crate::error::RunLimit::TooManyIterations,
+            ));
+        }
+
+        if self.facts.len() >= limits.max_facts as usize {
+            return Err(Execution::RunLimit(crate::error::RunLimit::TooManyFacts));
+        }
+
+        let now = clock.instant();
+        if now >= time_limit {
+            return Err(Execution::RunLimit(crate::error::RunLimit::Timeout));
+        }
+
+        Ok(false)
+    }
 
-            let now = Instant::now();
-            if now >= time_limit {
-                break Err(Execution::RunLimit(crate::error::RunLimit::Timeout));
+    /// adds the facts generated by each block in an iteration to their
+    /// total, then checks the per block limits
+    fn check_block_limits(
+        &mut self,
+        limits: &RunLimits,
+        block_facts: HashMap<usize, u64>,
+    ) -> Result<(), crate::error::Execution> {
+        // checked in block order, so that the error does not depend on the
+        // iteration order of the map
+        let mut block_facts: Vec<(usize, u64)> = block_facts
+            .into_iter()
+            .filter(|(block_id, _)| *block_id != usize::MAX)
+            .collect();
+        block_facts.sort_unstable();
+
+        for (block_id, count) in block_facts {
+            let facts = self.block_facts.entry(block_id).or_default();
+            *facts += count;
+            if matches!(limits.max_facts_per_block, Some(max) if *facts > max) {
+                return Err(Execution::RunLimit(
+                    crate::error::RunLimit::TooManyFactsInBlock {
+                        block_id: block_id as u32,
+                    },
+                ));
             }
-        };
 
-        self.iterations += index;
+            let iterations = self.block_iterations.entry(block_id).or_default();
+            *iterations += 1;
+            if matches!(limits.max_iterations_per_block, Some(max) if *iterations > max) {
+                return Err(Execution::RunLimit(
+                    crate::error::RunLimit::TooManyIterationsInBlock {
+                        block_id: block_id as u32,
+                    },
+                ));
+            }
+        }
 
-        res
+        Ok(())
     }
 
     /*pub fn query(&self, pred: Predicate) -> Vec<&Fact> {
@@ -673,14 +1200,62 @@ impl World {
         scope: &TrustedOrigins,
         symbols: &SymbolTable,
     ) -> Result<FactSet, Execution> {
+        self.query_rule_with_limit(rule, origin, scope, symbols, None)
+    }
+
+    /// applies a rule like [`World::query_rule`], stopping with
+    /// [`error::RunLimit::TooManyResults`] once it found more than `max_results` facts
+    pub fn query_rule_with_limit(
+        &self,
+        rule: Rule,
+        origin: usize,
+        scope: &TrustedOrigins,
+        symbols: &SymbolTable,
+        max_results: Option<u64>,
+    ) -> Result<FactSet, Execution> {
+        self.query_rule_from(rule, Some(origin), scope, symbols, max_results)
+    }
+
+    /// applies a rule like [`World::query_rule_with_limit`], without adding
+    /// the rule's origin to the generated facts if `origin` is `None`
+    fn query_rule_from(
+        &self,
+        rule: Rule,
+        origin: Option<usize>,
+        scope: &TrustedOrigins,
+        symbols: &SymbolTable,
+        max_results: Option<u64>,
+    ) -> Result<FactSet, Execution> {
+        let max_results = max_results.unwrap_or(u64::MAX);
+        let mut results = 0;
         let mut new_facts = FactSet::default();
         let it = self.facts.iterator(scope);
+
+        if !rule.aggregates.is_empty() {
+            for (origin, fact) in rule
+                .apply_aggregates(it, origin, symbols)
+                .map_err(Execution::Expression)?
+            {
+                if new_facts.insert(&origin, fact) {
+                    results += 1;
+                    if results > max_results {
+                        return Err(Execution::RunLimit(error::RunLimit::TooManyResults));
+                    }
+                }
+            }
+            return Ok(new_facts);
+        }
+
         //new_facts.extend(rule.apply(it, origin, symbols));
-        for res in rule.apply(it.clone(), origin, symbols) {
+        for res in rule.apply_from(it.clone(), origin, symbols) {
             match res {
                 Ok((origin,fact)) => {
-                    new_facts.insert(&origin, fact);
-
+                    if new_facts.insert(&origin, fact) {
+                        results += 1;
+                        if results > max_results {
+                            return Err(Execution::RunLimit(error::RunLimit::TooManyResults));
+                        }
+                    }
                 },
                 Err(e)  => {
                     return Err(Execution::Expression(e));
@@ -691,6 +1266,19 @@ impl World {
         Ok(new_facts)
     }
 
+    /// applies a rule like [`World::query_rule`], but the origin of each fact
+    /// only contains the origins of the facts it was generated from, without
+    /// the rule's own origin
+    pub fn query_rule_provenance(
+        &self,
+        rule: Rule,
+        scope: &TrustedOrigins,
+        symbols: &SymbolTable,
+        max_results: Option<u64>,
+    ) -> Result<FactSet, Execution> {
+        self.query_rule_from(rule, None, scope, symbols, max_results)
+    }
+
     pub fn query_match(
         &self,
         rule: Rule,
@@ -720,6 +1308,22 @@ pub struct RunLimits {
     pub max_iterations: u64,
     /// maximum execution time
     pub max_time: Duration,
+    /// maximum number of facts generated by the rules of each block
+    ///
+    /// this prevents a block from using the whole `max_facts` quota. The
+    /// authorizer's rules are not limited
+    pub max_facts_per_block: Option<u64>,
+    /// maximum number of iterations in which the rules of each block generate
+    /// new facts
+    ///
+    /// this prevents a block from using the whole `max_iterations` quota. The
+    /// authorizer's rules are not limited
+    pub max_iterations_per_block: Option<u64>,
+    /// maximum number of facts returned by a query
+    ///
+    /// this prevents a query from collecting a large number of facts in
+    /// memory. Authorization is not limited
+    pub max_results: Option<u64>,
 }
 
 impl std::default::Default for RunLimits {
@@ -728,26 +1332,34 @@ impl std::default::Default for RunLimits {
             max_facts: 1000,
             max_iterations: 100,
             max_time: Duration::from_millis(1),
+            max_facts_per_block: None,
+            max_iterations_per_block: None,
+            max_results: None,
         }
     }
 }
 
+/// facts grouped by the blocks they come from
+///
+/// the facts of each origin are shared between clones of the set, and
+/// copied the first time a clone modifies them: cloning a large set, like the
+/// facts of a pre-loaded authorizer, does not copy the facts
 #[derive(Clone, Debug, Default)]
 pub struct FactSet {
-    pub(crate) inner: HashMap<Origin, HashSet<Fact>>,
+    pub(crate) inner: HashMap<Origin, Arc<HashSet<Fact>>>,
 }
 
 impl FactSet {
-    pub fn insert(&mut self, origin: &Origin, fact: Fact) {
+    /// returns `true` if the fact was not already in the set
+    pub fn insert(&mut self, origin: &Origin, fact: Fact) -> bool {
         match self.inner.get_mut(origin) {
             None => {
                 let mut set = HashSet::new();
                 set.insert(fact);
-                self.inner.insert(origin.clone(), set);
-            }
-            Some(set) => {
-                set.insert(fact);
+                self.inner.insert(origin.clone(), Arc::new(set));
+                true
             }
+            Some(set) => !set.contains(&fact) && Arc::make_mut(set).insert(fact),
         }
     }
 
@@ -783,17 +1395,66 @@ impl FactSet {
 
     pub fn merge(&mut self, other: FactSet) {
         for (origin, facts) in other.inner {
-            let entry = self.inner.entry(origin).or_default();
-            entry.extend(facts.into_iter());
+            match self.inner.get_mut(&origin) {
+                Some(entry) => Arc::make_mut(entry).extend(unwrap_facts(facts)),
+                None => {
+                    self.inner.insert(origin, facts);
+                }
+            }
+        }
+    }
+
+    pub fn contains(&self, origin: &Origin, fact: &Fact) -> bool {
+        self.inner
+            .get(origin)
+            .map(|set| set.contains(fact))
+            .unwrap_or(false)
+    }
+
+    /// returns `true` if the fact was in the set
+    pub fn remove(&mut self, origin: &Origin, fact: &Fact) -> bool {
+        match self.inner.get_mut(origin) {
+            Some(set) if set.contains(fact) => Arc::make_mut(set).remove(fact),
+            _ => false,
+        }
+    }
+
+    /// removes all the facts, keeping the allocations
+    pub fn clear(&mut self) {
+        for facts in self.inner.values_mut() {
+            match Arc::get_mut(facts) {
+                Some(facts) => facts.clear(),
+                None => *facts = Arc::default(),
+            }
+        }
+    }
+
+    /// moves the facts of `other` to this set, keeping the allocations of `other`
+    pub fn drain_from(&mut self, other: &mut FactSet) {
+        for (origin, facts) in other.inner.iter_mut() {
+            if !facts.is_empty() {
+                let entry = Arc::make_mut(self.inner.entry(origin.clone()).or_default());
+                match Arc::get_mut(facts) {
+                    Some(facts) => entry.extend(facts.drain()),
+                    None => {
+                        entry.extend(facts.iter().cloned());
+                        *facts = Arc::default();
+                    }
+                }
+            }
         }
     }
 }
 
+/// takes the facts out of the `Arc`, copying them if they are shared
+pub(crate) fn unwrap_facts(facts: Arc<HashSet<Fact>>) -> HashSet<Fact> {
+    Arc::try_unwrap(facts).unwrap_or_else(|facts| (*facts).clone())
+}
+
 impl Extend<(Origin, Fact)> for FactSet {
     fn extend<T: IntoIterator<Item = (Origin, Fact)>>(&mut self, iter: T) {
         for (origin, fact) in iter {
-            let entry = self.inner.entry(origin).or_default();
-            entry.insert(fact);
+            self.insert(&origin, fact);
         }
     }
 }
@@ -804,28 +1465,71 @@ impl IntoIterator for FactSet {
     type IntoIter = Box<dyn Iterator<Item = (Origin, Fact)>>;
 
     fn into_iter(self) -> Self::IntoIter {
-        Box::new(
-            self.inner.into_iter().flat_map(move |(ids, facts)| {
-                facts.into_iter().map(move |fact| (ids.clone(), fact))
-            }),
-        )
+        Box::new(self.inner.into_iter().flat_map(move |(ids, facts)| {
+            unwrap_facts(facts)
+                .into_iter()
+                .map(move |fact| (ids.clone(), fact))
+        }))
     }
 }
 
 #[derive(Clone, Debug, Default)]
 pub struct RuleSet {
     pub inner: HashMap<TrustedOrigins, Vec<(usize, Rule)>>,
+    /// number of rules of each scope that were applied to all the facts
+    evaluated: HashMap<TrustedOrigins, usize>,
+    /// origin, scope and rule of each element of `inner`, to find duplicates
+    index: HashSet<(usize, TrustedOrigins, Rule)>,
 }
 
 impl RuleSet {
+    /// adds a rule, unless the same rule was already added with the same
+    /// origin and scope
     pub fn insert(&mut self, origin: usize, scope: &TrustedOrigins, rule: Rule) {
-        match self.inner.get_mut(scope) {
-            None => {
-                self.inner.insert(scope.clone(), vec![(origin, rule)]);
-            }
-            Some(set) => {
-                set.push((origin, rule));
+        if !self.index.insert((origin, scope.clone(), rule.clone())) {
+            return;
+        }
+
+        self.inner
+            .entry(scope.clone())
+            .or_default()
+            .push((origin, rule));
+    }
+
+    /// removes the rules added with this origin
+    pub fn remove_origin(&mut self, origin: usize) {
+        for (scope, rules) in self.inner.iter_mut() {
+            // the evaluated rules stay at the start of the list: only count
+            // the ones that are kept
+            if let Some(evaluated) = self.evaluated.get_mut(scope) {
+                *evaluated = rules[..*evaluated]
+                    .iter()
+                    .filter(|(o, _)| *o != origin)
+                    .count();
             }
+            rules.retain(|(o, _)| *o != origin);
+        }
+        self.index.retain(|(o, _, _)| *o != origin);
+    }
+
+    fn evaluated(&self, scope: &TrustedOrigins) -> usize {
+        self.evaluated.get(scope).copied().unwrap_or(0)
+    }
+
+    fn has_evaluated(&self) -> bool {
+        self.evaluated.values().any(|count| *count > 0)
+    }
+
+    /// whether all the rules were applied to all the facts
+    fn is_evaluated(&self) -> bool {
+        self.inner
+            .iter()
+            .all(|(scope, rules)| self.evaluated(scope) == rules.len())
+    }
+
+    fn mark_evaluated(&mut self) {
+        for (scope, rules) in self.inner.iter() {
+            self.evaluated.insert(scope.clone(), rules.len());
         }
     }
 
@@ -840,11 +1544,17 @@ pub struct SchemaVersion {
     contains_scopes: bool,
     contains_v4: bool,
     contains_check_all: bool,
+    contains_extension_1: bool,
+    contains_extension_2: bool,
 }
 
 impl SchemaVersion {
     pub fn version(&self) -> u32 {
-        if self.contains_scopes || self.contains_v4 || self.contains_check_all {
+        if self.contains_extension_2 {
+            EXTENSION_SCHEMA_VERSION_2
+        } else if self.contains_extension_1 {
+            EXTENSION_SCHEMA_VERSION_1
+        } else if self.contains_scopes || self.contains_v4 || self.contains_check_all {
             4
         } else {
             MIN_SCHEMA_VERSION
@@ -852,6 +1562,18 @@ impl SchemaVersion {
     }
 
     pub fn check_compatibility(&self, version: u32) -> Result<(), error::Format> {
+        if version < EXTENSION_SCHEMA_VERSION_2 && self.contains_extension_2 {
+            return Err(error::Format::DeserializationError(
+                "blocks before v1002 must not have aggregates, reject if, UUIDs or v1002 operators (to_lowercase, trim, split, capture)".to_string(),
+            ));
+        }
+
+        if version < EXTENSION_SCHEMA_VERSION_1 && self.contains_extension_1 {
+            return Err(error::Format::DeserializationError(
+                "blocks before v1001 must not have v1001 operators (difference, symmetric_difference, is_subset, is_superset) or decimal numbers".to_string(),
+            ));
+        }
+
         if version < 4 {
             if self.contains_scopes {
                 Err(error::Format::DeserializationError(
@@ -876,7 +1598,7 @@ impl SchemaVersion {
 
 /// Determine the schema version given the elements of a block.
 pub fn get_schema_version(
-    _facts: &[Fact],
+    facts: &[Fact],
     rules: &[Rule],
     checks: &[Check],
     scopes: &[Scope],
@@ -897,10 +1619,37 @@ pub fn get_schema_version(
                 .any(|query| contains_v4_op(&query.expressions))
         });
 
+    let contains_extension_1 = rules.iter().any(|rule| {
+        contains_extension_1_op(&rule.expressions) || rule_contains_term(rule, contains_decimal)
+    }) || checks.iter().any(|check| {
+        check.queries.iter().any(|query| {
+            contains_extension_1_op(&query.expressions)
+                || rule_contains_term(query, contains_decimal)
+        })
+    }) || facts
+        .iter()
+        .any(|fact| fact.predicate.terms.iter().any(contains_decimal));
+
+    let contains_extension_2 = rules.iter().any(|rule| {
+        !rule.aggregates.is_empty()
+            || contains_extension_2_op(&rule.expressions)
+            || rule_contains_term(rule, contains_uuid)
+    }) || checks.iter().any(|c: &Check| {
+        c.kind == CheckKind::Reject
+            || c.queries.iter().any(|query| {
+                contains_extension_2_op(&query.expressions)
+                    || rule_contains_term(query, contains_uuid)
+            })
+    }) || facts
+        .iter()
+        .any(|fact| fact.predicate.terms.iter().any(contains_uuid));
+
     SchemaVersion {
         contains_scopes,
         contains_v4,
         contains_check_all,
+        contains_extension_1,
+        contains_extension_2,
     }
 }
 
@@ -923,11 +1672,108 @@ pub fn contains_v4_op(expressions: &[Expression]) -> bool {
     })
 }
 
+/// Determine whether any of the expression contain a v1001 operator.
+/// Set difference, symmetric difference and subset tests are only supported in biscuits v1001+
+pub fn contains_extension_1_op(expressions: &[Expression]) -> bool {
+    expressions.iter().any(|expression| {
+        expression.ops.iter().any(|op| {
+            matches!(
+                op,
+                Op::Binary(
+                    Binary::Difference
+                        | Binary::SymmetricDifference
+                        | Binary::IsSubset
+                        | Binary::IsSuperset
+                )
+            )
+        })
+    })
+}
+
+/// Determine whether any of the expression contain a v1002 operator.
+/// String transformations, split and regex captures are only supported in biscuits v1002+
+pub fn contains_extension_2_op(expressions: &[Expression]) -> bool {
+    expressions.iter().any(|expression| {
+        expression.ops.iter().any(|op| {
+            matches!(
+                op,
+                Op::Unary(Unary::Lowercase | Unary::Trim)
+                    | Op::Binary(Binary::Split | Binary::Capture)
+            )
+        })
+    })
+}
+
+/// Determine whether a term is or contains a decimal number, only supported in biscuits v1001+
+fn contains_decimal(term: &Term) -> bool {
+    match term {
+        Term::Decimal(..) => true,
+        Term::Set(set) => set.iter().any(contains_decimal),
+        _ => false,
+    }
+}
+
+/// Determine whether a term is or contains a UUID, only supported in biscuits v1002+
+fn contains_uuid(term: &Term) -> bool {
+    match term {
+        Term::Uuid(_) => true,
+        Term::Set(set) => set.iter().any(contains_uuid),
+        _ => false,
+    }
+}
+
+fn rule_contains_term(rule: &Rule, contains: fn(&Term) -> bool) -> bool {
+    std::iter::once(&rule.head)
+        .chain(rule.body.iter())
+        .any(|predicate| predicate.terms.iter().any(contains))
+        || rule.expressions.iter().any(|expression| {
+            expression.ops.iter().any(|op| match op {
+                Op::Value(term) => contains(term),
+                _ => false,
+            })
+        })
+}
+
+/// escapes a string to print it between quotes, as the parser unescapes it:
+/// `\\`, `\"` and `\n`
+pub(crate) fn escape_string(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '\\' => escaped.push_str("\\\\"),
+            '"' => escaped.push_str("\\\""),
+            '\n' => escaped.push_str("\\n"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// prints a UUID in its hyphenated form, like `67e55044-10b1-426f-9247-bb680e5fe0c8`
+pub fn uuid_to_string(uuid: &[u8; 16]) -> String {
+    let hex = hex::encode(uuid);
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hex[..8],
+        &hex[8..12],
+        &hex[12..16],
+        &hex[16..20],
+        &hex[20..]
+    )
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use std::time::Duration;
 
+    fn limits() -> RunLimits {
+        RunLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn family() {
         let mut w = World::new();
@@ -1695,4 +2541,410 @@ mod tests {
         }
         assert!(res.len() == 0);
     }
+
+    #[cfg(feature = "debug-hooks")]
+    #[test]
+    fn debug_hooks() {
+        #[derive(Default)]
+        struct Recorder {
+            rules: usize,
+            ended_rules: usize,
+            derived: Vec<Fact>,
+            iterations: Vec<(u64, usize)>,
+        }
+
+        impl DebugHooks for Recorder {
+            fn on_rule_start(&mut self, origin: usize, _rule: &Rule) {
+                assert_eq!(origin, 0);
+                self.rules += 1;
+            }
+
+            fn on_fact_derived(&mut self, _origin: &Origin, fact: &Fact) {
+                self.derived.push(fact.clone());
+            }
+
+            fn on_rule_end(&mut self, _origin: usize, _rule: &Rule) {
+                self.ended_rules += 1;
+            }
+
+            fn on_iteration_end(&mut self, iteration: u64, facts: &FactSet) {
+                self.iterations.push((iteration, facts.len()));
+            }
+        }
+
+        let mut w = World::new();
+        let mut syms = SymbolTable::new();
+
+        let a = syms.add("A");
+        let b = syms.add("B");
+        let c = syms.add("C");
+        let parent = syms.insert("parent");
+        let ancestor = syms.insert("ancestor");
+
+        w.add_fact(&[0].iter().collect(), fact(parent, &[&a, &b]));
+        w.add_fact(&[0].iter().collect(), fact(parent, &[&b, &c]));
+
+        let x = var(&mut syms, "x");
+        let y = var(&mut syms, "y");
+        let z = var(&mut syms, "z");
+        w.add_rule(
+            0,
+            &[0].iter().collect(),
+            rule(ancestor, &[&x, &y], &[pred(parent, &[&x, &y])]),
+        );
+        w.add_rule(
+            0,
+            &[0].iter().collect(),
+            rule(
+                ancestor,
+                &[&x, &z],
+                &[pred(parent, &[&x, &y]), pred(ancestor, &[&y, &z])],
+            ),
+        );
+
+        let mut recorder = Recorder::default();
+        w.run_with_hooks(&syms, limits(), &mut recorder).unwrap();
+
+        // 2 parent facts, 2 ancestor facts, then ancestor(A, C)
+        assert_eq!(recorder.iterations, vec![(0, 4), (1, 5), (2, 5)]);
+        assert_eq!(recorder.rules, 6);
+        assert_eq!(recorder.ended_rules, 6);
+        assert!(recorder.derived.contains(&fact(ancestor, &[&a, &c])));
+        assert_eq!(w.facts.len(), 5);
+    }
+
+    #[test]
+    fn scratch_facts() {
+        let mut w = World::new();
+        let mut syms = SymbolTable::new();
+
+        let parent = syms.insert("parent");
+        let ancestor = syms.insert("ancestor");
+        for i in 0..10 {
+            w.add_fact(&[0].iter().collect(), fact(parent, &[&int(i), &int(i + 1)]));
+        }
+
+        let x = var(&mut syms, "x");
+        let y = var(&mut syms, "y");
+        let z = var(&mut syms, "z");
+        w.add_rule(
+            0,
+            &[0].iter().collect(),
+            rule(ancestor, &[&x, &y], &[pred(parent, &[&x, &y])]),
+        );
+        w.add_rule(
+            0,
+            &[0].iter().collect(),
+            rule(
+                ancestor,
+                &[&x, &z],
+                &[pred(ancestor, &[&x, &y]), pred(parent, &[&y, &z])],
+            ),
+        );
+
+        let limits = limits();
+        w.run_with_limits(&syms, limits.clone()).unwrap();
+        // 10 parent facts, 10 + 9 + ... + 1 ancestor facts
+        assert_eq!(w.facts.len(), 10 + 55);
+        assert!(w.scratch.inner.values().all(|set| set.is_empty()));
+        assert!(w.scratch.inner.values().any(|set| set.capacity() > 0));
+
+        // running again does not generate new facts
+        let iterations = w.iterations;
+        w.run_with_limits(&syms, limits).unwrap();
+        assert_eq!(w.facts.len(), 10 + 55);
+        assert_eq!(w.iterations, iterations);
+    }
+
+    #[test]
+    fn tracer() {
+        let mut w = World::new();
+        let mut syms = SymbolTable::new();
+
+        let a = syms.add("A");
+        let b = syms.add("B");
+        let parent = syms.insert("parent");
+        let ancestor = syms.insert("ancestor");
+
+        w.add_fact(&[0].iter().collect(), fact(parent, &[&a, &b]));
+
+        let x = var(&mut syms, "x");
+        let y = var(&mut syms, "y");
+        w.add_rule(
+            1,
+            &[0, 1].iter().collect(),
+            rule(ancestor, &[&x, &y], &[pred(parent, &[&x, &y])]),
+        );
+
+        let mut trace = Vec::new();
+        w.run_with_tracer(
+            &syms,
+            limits(),
+            &mut |firing: &RuleFiring| {
+                trace.push((
+                    firing.rule_origin,
+                    firing.print_rule(),
+                    firing.fact_origin.clone(),
+                    firing.print_fact(),
+                ))
+            },
+        )
+        .unwrap();
+
+        // the last iteration only applies the rule to the new facts, so the
+        // fact is derived once
+        assert_eq!(trace.len(), 1);
+        assert_eq!(
+            trace[0],
+            (
+                1,
+                "ancestor($x, $y) <- parent($x, $y)".to_string(),
+                [0, 1].iter().collect(),
+                "ancestor(\"A\", \"B\")".to_string()
+            )
+        );
+    }
+
+    #[test]
+    fn fact_index() {
+        let mut w = World::new();
+        let mut syms = SymbolTable::new();
+
+        let user = syms.insert("user");
+        let owner = syms.insert("owner");
+        let right = syms.insert("right");
+        let read = syms.add("read");
+
+        for i in 0..100 {
+            let u = syms.add(&format!("user{}", i));
+            let r = syms.add(&format!("file{}", i));
+            w.add_fact(&[0].iter().collect(), fact(user, &[&u]));
+            w.add_fact(&[0].iter().collect(), fact(owner, &[&u, &r]));
+        }
+
+        // the owner predicate is looked up by its first term, bound by user($u)
+        let u = var(&mut syms, "u");
+        let r = var(&mut syms, "r");
+        w.add_rule(
+            0,
+            &[0].iter().collect(),
+            rule(
+                right,
+                &[&u, &r, &read],
+                &[pred(user, &[&u]), pred(owner, &[&u, &r])],
+            ),
+        );
+
+        w.run_with_limits(&syms, limits()).unwrap();
+
+        let res = w.query_rule(
+            rule(right, &[&u, &r], &[pred(right, &[&u, &r, &read])]),
+            0,
+            &[0].iter().collect(),
+            &syms,
+        );
+        assert_eq!(res.unwrap().len(), 100);
+
+        let stats = &w.index_stats;
+        // one iteration generating the facts, then one where none of the new
+        // facts match the rule body, without any lookup
+        assert_eq!(stats.predicate_lookups, 1);
+        assert_eq!(stats.first_term_lookups, 100);
+        assert_eq!(stats.candidate_facts, 100 + 100);
+        assert_eq!(stats.max_bucket_size, 1);
+    }
+
+    #[test]
+    fn incremental_run() {
+        let mut w = World::new();
+        let mut syms = SymbolTable::new();
+
+        let a = syms.add("A");
+        let b = syms.add("B");
+        let c = syms.add("C");
+        let parent = syms.insert("parent");
+        let child = syms.insert("child");
+        let grandparent = syms.insert("grandparent");
+        let x = var(&mut syms, "x");
+        let y = var(&mut syms, "y");
+        let z = var(&mut syms, "z");
+
+        let limits = limits();
+
+        w.add_fact(&[0].iter().collect(), fact(parent, &[&a, &b]));
+        w.add_rule(
+            0,
+            &[0].iter().collect(),
+            rule(child, &[&y, &x], &[pred(parent, &[&x, &y])]),
+        );
+        w.run_with_limits(&syms, limits.clone()).unwrap();
+        assert_eq!(w.facts.len(), 2);
+
+        // a rule added after a run is applied to all the facts
+        w.add_rule(
+            0,
+            &[0].iter().collect(),
+            rule(
+                grandparent,
+                &[&x, &z],
+                &[pred(parent, &[&x, &y]), pred(parent, &[&y, &z])],
+            ),
+        );
+        // adding the same rule again has no effect
+        w.add_rule(
+            0,
+            &[0].iter().collect(),
+            rule(child, &[&y, &x], &[pred(parent, &[&x, &y])]),
+        );
+        w.add_fact(&[0].iter().collect(), fact(parent, &[&b, &c]));
+        w.run_with_limits(&syms, limits).unwrap();
+
+        assert_eq!(
+            w.rules
+                .inner
+                .values()
+                .map(|rules| rules.len())
+                .sum::<usize>(),
+            2
+        );
+        let res = w
+            .query_rule(
+                rule(grandparent, &[&x, &z], &[pred(grandparent, &[&x, &z])]),
+                0,
+                &[0].iter().collect(),
+                &syms,
+            )
+            .unwrap();
+        assert_eq!(res.len(), 1);
+        let res = w
+            .query_rule(
+                rule(child, &[&x, &y], &[pred(child, &[&x, &y])]),
+                0,
+                &[0].iter().collect(),
+                &syms,
+            )
+            .unwrap();
+        assert_eq!(res.len(), 2);
+
+        // rules removed by origin can be added again
+        w.rules.remove_origin(0);
+        assert!(w.rules.inner.values().all(|rules| rules.is_empty()));
+        w.add_rule(
+            0,
+            &[0].iter().collect(),
+            rule(child, &[&y, &x], &[pred(parent, &[&x, &y])]),
+        );
+        assert_eq!(w.rules.iter_all().count(), 1);
+    }
+
+    #[test]
+    fn add_rule_after_remove_origin() {
+        let mut w = World::new();
+        let mut syms = SymbolTable::new();
+
+        let a = syms.add("A");
+        let b = syms.add("B");
+        let parent = syms.insert("parent");
+        let child = syms.insert("child");
+        let ancestor = syms.insert("ancestor");
+        let x = var(&mut syms, "x");
+        let y = var(&mut syms, "y");
+
+        let limits = limits();
+        let scope: TrustedOrigins = [0, usize::MAX].iter().collect();
+
+        w.add_fact(&[0].iter().collect(), fact(parent, &[&a, &b]));
+        w.add_rule(
+            usize::MAX,
+            &scope,
+            rule(child, &[&y, &x], &[pred(parent, &[&x, &y])]),
+        );
+        w.add_rule(
+            0,
+            &scope,
+            rule(child, &[&x, &y], &[pred(parent, &[&x, &y])]),
+        );
+        w.run_with_limits(&syms, limits.clone()).unwrap();
+
+        w.rules.remove_origin(usize::MAX);
+        w.add_rule(
+            usize::MAX,
+            &scope,
+            rule(ancestor, &[&x, &y], &[pred(parent, &[&x, &y])]),
+        );
+        w.run_with_limits(&syms, limits).unwrap();
+
+        // the rule added after the removal is applied to the existing facts
+        let res = w
+            .query_rule(
+                rule(ancestor, &[&x, &y], &[pred(ancestor, &[&x, &y])]),
+                0,
+                &scope,
+                &syms,
+            )
+            .unwrap();
+        assert_eq!(res.len(), 1);
+    }
+
+    #[test]
+    fn shared_symbols() {
+        let mut syms = SymbolTable::with_capacity(4);
+        let a = syms.insert("a");
+        let b = syms.insert("b");
+        let unshared = syms.clone();
+        syms.share();
+        assert_eq!(syms, unshared);
+
+        let mut clone = syms.clone();
+        let c = clone.insert("c");
+        assert_eq!(c, b + 1);
+        assert_eq!(clone.insert("a"), a);
+        assert_eq!(clone.get("b"), Some(b));
+        assert_eq!(clone.get_symbol(c), Some("c"));
+        assert_eq!(clone.get_symbol(a), Some("a"));
+        assert_eq!(clone.get("c"), Some(c));
+        assert_eq!(syms.get("c"), None);
+        // the default symbols are not part of the table
+        assert_eq!(clone.insert("read"), 0);
+        assert_eq!(clone.strings(), vec!["a", "b", "c"]);
+
+        clone.share();
+        let d = clone.insert("d");
+        assert_eq!(clone.get_symbol(d), Some("d"));
+        assert_eq!(clone.current_offset(), 4);
+
+        let mut other = SymbolTable::new();
+        other.insert("d");
+        assert!(!clone.is_disjoint(&other));
+        assert!(syms.is_disjoint(&other));
+
+        // splitting inside the shared strings copies them back
+        let tail = clone.split_at(1);
+        assert_eq!(tail.strings(), vec!["b", "c", "d"]);
+        assert_eq!(clone.strings(), vec!["a"]);
+        assert_eq!(clone.get("b"), None);
+        assert_eq!(syms.strings(), vec!["a", "b"]);
+    }
+
+    #[test]
+    fn shared_facts() {
+        let mut syms = SymbolTable::new();
+        let origin: Origin = [0].iter().collect();
+        let mut facts = FactSet::default();
+        facts.insert(&origin, fact(syms.insert("a"), &[&int(1)]));
+
+        let mut clone = facts.clone();
+        assert!(Arc::ptr_eq(&facts.inner[&origin], &clone.inner[&origin]));
+        // inserting a fact that is already known does not copy the set
+        clone.insert(&origin, fact(syms.insert("a"), &[&int(1)]));
+        assert!(Arc::ptr_eq(&facts.inner[&origin], &clone.inner[&origin]));
+
+        clone.insert(&origin, fact(syms.insert("a"), &[&int(2)]));
+        assert_eq!(facts.len(), 1);
+        assert_eq!(clone.len(), 2);
+
+        clone.clear();
+        assert!(clone.is_empty());
+        assert_eq!(facts.len(), 1);
+    }
 }
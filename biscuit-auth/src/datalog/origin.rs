@@ -25,6 +25,17 @@ impl Origin {
     pub fn is_superset(&self, other: &Self) -> bool {
         self.inner.is_superset(&other.inner)
     }
+
+    /// returns true if a fact with this origin depends on block `i`,
+    /// the authorizer being `usize::MAX`
+    pub fn contains(&self, i: usize) -> bool {
+        self.inner.contains(&i)
+    }
+
+    /// block ids, in increasing order, the authorizer being `usize::MAX`
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.inner.iter().copied()
+    }
 }
 
 impl<'a> Extend<&'a usize> for Origin {
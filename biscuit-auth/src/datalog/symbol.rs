@@ -1,6 +1,7 @@
 //! Symbol table implementation
-use std::collections::HashSet;
-use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::sync::Arc;
 
 pub type SymbolIndex = u64;
 use crate::crypto::PublicKey;
@@ -9,12 +10,81 @@ use crate::{error, token::public_keys::PublicKeys};
 
 use super::{Check, Fact, Predicate, Rule, Term, World};
 
-#[derive(Clone, Debug, PartialEq, Eq)]
+/// interned strings, indexed by their position in the table
+///
+/// the strings are looked up through a hash index. A table can move its
+/// strings to a shared, read-only base with [`SymbolTable::share`]: clones then
+/// only copy the strings inserted after that.
+#[derive(Clone, Debug)]
 pub struct SymbolTable {
-    symbols: Vec<String>,
+    base: Option<Arc<Interner>>,
+    symbols: Interner,
     pub(crate) public_keys: PublicKeys,
 }
 
+/// list of strings with an index from string to position
+#[derive(Clone, Default, PartialEq, Eq)]
+struct Interner {
+    symbols: Vec<Arc<str>>,
+    index: HashMap<Arc<str>, usize>,
+}
+
+impl fmt::Debug for Interner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.symbols.iter()).finish()
+    }
+}
+
+impl Interner {
+    fn with_capacity(capacity: usize) -> Self {
+        Interner {
+            symbols: Vec::with_capacity(capacity),
+            index: HashMap::with_capacity(capacity),
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.symbols.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.symbols.is_empty()
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.symbols.reserve(additional);
+        self.index.reserve(additional);
+    }
+
+    fn position(&self, s: &str) -> Option<usize> {
+        self.index.get(s).copied()
+    }
+
+    fn get(&self, i: usize) -> Option<&str> {
+        self.symbols.get(i).map(|s| &**s)
+    }
+
+    /// adds a string at the end, even if it is already present
+    fn push(&mut self, s: Arc<str>) -> usize {
+        let position = self.symbols.len();
+        // lookups return the first occurrence
+        self.index.entry(s.clone()).or_insert(position);
+        self.symbols.push(s);
+        position
+    }
+
+    fn split_off(&mut self, at: usize) -> Interner {
+        let tail = self.symbols.split_off(at);
+        self.index.retain(|_, position| *position < at);
+
+        let mut interner = Interner::with_capacity(tail.len());
+        for s in tail {
+            interner.push(s);
+        }
+        interner
+    }
+}
+
 const DEFAULT_SYMBOLS: [&str; 28] = [
     "read",
     "write",
@@ -48,14 +118,156 @@ const DEFAULT_SYMBOLS: [&str; 28] = [
 
 const OFFSET: usize = 1024;
 
+/// symbols shared by the issuers and verifiers of an application's tokens
+///
+/// like the default symbols, they are not serialized in the tokens, which
+/// makes them smaller when they use strings common to the application. The
+/// authority block stores the table's `id`, so that verifiers can support
+/// multiple versions of the table, and reject tokens using an unknown one.
+/// A table must not change once tokens use it: add symbols in a new table,
+/// with a new id
+///
+/// ```rust
+/// use biscuit_auth::{datalog::ApplicationSymbols, Biscuit, KeyPair};
+///
+/// let symbols = ApplicationSymbols::new(1, vec!["subscription", "premium"]).unwrap();
+/// let root = KeyPair::new();
+/// let mut builder = Biscuit::builder();
+/// builder.add_fact(r#"subscription("premium")"#).unwrap();
+/// builder.set_application_symbols(symbols.clone());
+/// let token = builder.build(&root).unwrap().to_vec().unwrap();
+///
+/// assert!(Biscuit::from(&token, root.public()).is_err());
+/// let token = Biscuit::from_with_application_symbols(&token, root.public(), &[symbols]).unwrap();
+/// ```
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ApplicationSymbols {
+    id: u32,
+    symbols: SymbolTable,
+}
+
+impl ApplicationSymbols {
+    /// returns [`error::Format::SymbolTableOverlap`] if a symbol is repeated or
+    /// is one of the default symbols
+    pub fn new<I, S>(id: u32, symbols: I) -> Result<Self, error::Format>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let symbols: Vec<String> = symbols.into_iter().map(Into::into).collect();
+        if symbols.iter().collect::<HashSet<_>>().len() != symbols.len() {
+            return Err(error::Format::SymbolTableOverlap);
+        }
+
+        Ok(ApplicationSymbols {
+            id,
+            symbols: SymbolTable::from(symbols)?,
+        })
+    }
+
+    pub fn id(&self) -> u32 {
+        self.id
+    }
+
+    pub fn symbols(&self) -> Vec<String> {
+        self.symbols.strings()
+    }
+
+    pub(crate) fn symbol_table(&self) -> &SymbolTable {
+        &self.symbols
+    }
+}
+
 impl SymbolTable {
     pub fn new() -> Self {
         SymbolTable {
-            symbols: vec![],
+            base: None,
+            symbols: Interner::default(),
+            public_keys: PublicKeys::new(),
+        }
+    }
+
+    /// creates an empty table with space for `capacity` strings
+    pub fn with_capacity(capacity: usize) -> Self {
+        SymbolTable {
+            base: None,
+            symbols: Interner::with_capacity(capacity),
             public_keys: PublicKeys::new(),
         }
     }
 
+    /// reserves space for at least `additional` more strings
+    pub fn reserve(&mut self, additional: usize) {
+        self.symbols.reserve(additional);
+    }
+
+    /// moves the strings to a read-only base shared with the clones of this table
+    ///
+    /// the symbol indexes do not change. Cloning a table then only copies the
+    /// strings inserted after the last call to `share`, which makes clones
+    /// of a large table, like the authorizers created from a common one, cheap
+    pub fn share(&mut self) {
+        if self.symbols.is_empty() {
+            return;
+        }
+
+        let mut base = match self.base.take() {
+            Some(base) => Arc::try_unwrap(base).unwrap_or_else(|base| (*base).clone()),
+            None => Interner::default(),
+        };
+        base.reserve(self.symbols.len());
+        for s in std::mem::take(&mut self.symbols).symbols {
+            base.push(s);
+        }
+        self.base = Some(Arc::new(base));
+    }
+
+    /// empty table using the same shared base, see [`SymbolTable::share`]
+    pub(crate) fn shared_base(&self) -> SymbolTable {
+        SymbolTable {
+            base: self.base.clone(),
+            symbols: Interner::default(),
+            public_keys: PublicKeys::new(),
+        }
+    }
+
+    /// number of strings in the shared base
+    fn base_len(&self) -> usize {
+        self.base.as_ref().map(|base| base.len()).unwrap_or(0)
+    }
+
+    /// moves the strings of the shared base back in this table
+    fn unshare(&mut self) {
+        if let Some(base) = self.base.take() {
+            let mut symbols = Arc::try_unwrap(base).unwrap_or_else(|base| (*base).clone());
+            symbols.reserve(self.symbols.len());
+            for s in std::mem::take(&mut self.symbols).symbols {
+                symbols.push(s);
+            }
+            self.symbols = symbols;
+        }
+    }
+
+    /// position of a string, not counting the default symbols
+    fn position(&self, s: &str) -> Option<usize> {
+        match self.base.as_ref().and_then(|base| base.position(s)) {
+            Some(position) => Some(position),
+            None => self
+                .symbols
+                .position(s)
+                .map(|position| self.base_len() + position),
+        }
+    }
+
+    /// strings of the table, not counting the default symbols
+    fn iter(&self) -> impl Iterator<Item = &str> {
+        self.base
+            .iter()
+            .flat_map(|base| base.symbols.iter())
+            .chain(self.symbols.symbols.iter())
+            .map(|s| &**s)
+    }
+
     pub fn from(symbols: Vec<String>) -> Result<Self, error::Format> {
         let h1 = DEFAULT_SYMBOLS.iter().copied().collect::<HashSet<_>>();
         let h2 = symbols.iter().map(|s| s.as_str()).collect::<HashSet<_>>();
@@ -64,10 +276,11 @@ impl SymbolTable {
             return Err(error::Format::SymbolTableOverlap);
         }
 
-        Ok(SymbolTable {
-            symbols,
-            public_keys: PublicKeys::new(),
-        })
+        let mut table = SymbolTable::with_capacity(symbols.len());
+        for s in symbols {
+            table.symbols.push(Arc::from(s));
+        }
+        Ok(table)
     }
 
     pub fn from_symbols_and_public_keys(
@@ -83,7 +296,10 @@ impl SymbolTable {
         if !self.is_disjoint(other) {
             return Err(error::Format::SymbolTableOverlap);
         }
-        self.symbols.extend(other.symbols.iter().cloned());
+        self.symbols.reserve(other.current_offset());
+        for s in other.iter() {
+            self.symbols.push(Arc::from(s));
+        }
         self.public_keys.extend(&other.public_keys)?;
         Ok(())
     }
@@ -93,11 +309,11 @@ impl SymbolTable {
             return index as u64;
         }
 
-        match self.symbols.iter().position(|sym| sym.as_str() == s) {
+        match self.position(s) {
             Some(index) => (OFFSET + index) as u64,
             None => {
-                self.symbols.push(s.to_string());
-                (OFFSET + (self.symbols.len() - 1)) as u64
+                let index = self.base_len() + self.symbols.push(Arc::from(s));
+                (OFFSET + index) as u64
             }
         }
     }
@@ -112,38 +328,40 @@ impl SymbolTable {
             return Some(index as u64);
         }
 
-        self.symbols
-            .iter()
-            .position(|sym| sym.as_str() == s)
-            .map(|i| (OFFSET + i) as SymbolIndex)
+        self.position(s).map(|i| (OFFSET + i) as SymbolIndex)
     }
 
     pub fn strings(&self) -> Vec<String> {
-        self.symbols.clone()
+        self.iter().map(String::from).collect()
     }
 
     pub fn current_offset(&self) -> usize {
-        self.symbols.len()
+        self.base_len() + self.symbols.len()
     }
 
     pub fn split_at(&mut self, offset: usize) -> SymbolTable {
+        if offset < self.base_len() {
+            self.unshare();
+        }
+        let offset = offset - self.base_len();
+
         let mut table = SymbolTable::new();
         table.symbols = self.symbols.split_off(offset);
         table
     }
 
     pub fn is_disjoint(&self, other: &SymbolTable) -> bool {
-        let h1 = self.symbols.iter().collect::<HashSet<_>>();
-        let h2 = other.symbols.iter().collect::<HashSet<_>>();
-
-        h1.is_disjoint(&h2)
+        other.iter().all(|s| self.position(s).is_none())
     }
 
     pub fn get_symbol(&self, i: SymbolIndex) -> Option<&str> {
         if i >= OFFSET as u64 {
-            self.symbols
-                .get((i - OFFSET as u64) as usize)
-                .map(|s| s.as_str())
+            let i = (i - OFFSET as u64) as usize;
+            let base_len = self.base_len();
+            match &self.base {
+                Some(base) if i < base_len => base.get(i),
+                _ => self.symbols.get(i - base_len),
+            }
         } else {
             DEFAULT_SYMBOLS.get(i as usize).copied()
         }
@@ -185,10 +403,7 @@ impl SymbolTable {
             Term::Variable(i) => format!("${}", self.print_symbol_default(*i as u64)),
             Term::Integer(i) => i.to_string(),
             Term::Str(index) => format!("\"{}\"", self.print_symbol_default(*index as u64)),
-            Term::Date(d) => OffsetDateTime::from_unix_timestamp(*d as i64)
-                .ok()
-                .and_then(|t| t.format(&Rfc3339).ok())
-                .unwrap_or_else(|| "<invalid date>".to_string()),
+            Term::Date(d) => super::print_date(*d),
             Term::Bytes(s) => format!("hex:{}", hex::encode(s)),
             Term::Bool(b) => {
                 if *b {
@@ -204,6 +419,8 @@ impl SymbolTable {
                     .collect::<Vec<_>>();
                 format!("[{}]", terms.join(", "))
             }
+            Term::Decimal(m, s) => super::decimal::to_string(*m, *s),
+            Term::Uuid(u) => format!("uuid:{}", super::uuid_to_string(u)),
         }
     }
     pub fn print_fact(&self, f: &Fact) -> String {
@@ -256,7 +473,7 @@ impl SymbolTable {
                     crate::token::Scope::Previous => "previous".to_string(),
                     crate::token::Scope::PublicKey(key_id) => {
                         match self.public_keys.get_key(*key_id) {
-                            Some(key) => format!("ed25519/{}", hex::encode(key.to_bytes())),
+                            Some(key) => key.to_string(),
                             None => "<unknown public key id>".to_string(),
                         }
                     }
@@ -269,7 +486,29 @@ impl SymbolTable {
     }
 
     pub fn print_rule(&self, r: &Rule) -> String {
-        let res = self.print_predicate(&r.head);
+        let res = if r.aggregates.is_empty() {
+            self.print_predicate(&r.head)
+        } else {
+            let terms = r
+                .head
+                .terms
+                .iter()
+                .enumerate()
+                .map(
+                    |(position, term)| match r.aggregates.iter().find(|(p, _)| *p == position) {
+                        Some((_, aggregate)) => {
+                            format!("{}({})", aggregate.name(), self.print_term(term))
+                        }
+                        None => self.print_term(term),
+                    },
+                )
+                .collect::<Vec<_>>();
+            format!(
+                "{}({})",
+                self.get_symbol(r.head.name).unwrap_or("<?>"),
+                terms.join(", ")
+            )
+        };
 
         format!("{} <- {}", res, self.print_rule_body(r))
     }
@@ -281,17 +520,34 @@ impl SymbolTable {
             .map(|r| self.print_rule_body(r))
             .collect::<Vec<_>>();
 
+        let message = match &c.message {
+            Some(message) => format!(" @msg(\"{}\")", super::escape_string(message)),
+            None => String::new(),
+        };
+
         format!(
-            "check {} {}",
+            "{} {}{}",
             match c.kind {
-                crate::builder::CheckKind::One => "if",
-                crate::builder::CheckKind::All => "all",
+                crate::builder::CheckKind::One => "check if",
+                crate::builder::CheckKind::All => "check all",
+                crate::builder::CheckKind::Reject => "reject if",
             },
-            queries.join(" or ")
+            queries.join(" or "),
+            message
         )
     }
 }
 
+impl PartialEq for SymbolTable {
+    fn eq(&self, other: &Self) -> bool {
+        self.current_offset() == other.current_offset()
+            && self.iter().eq(other.iter())
+            && self.public_keys == other.public_keys
+    }
+}
+
+impl Eq for SymbolTable {}
+
 impl Default for SymbolTable {
     fn default() -> Self {
         default_symbol_table()
@@ -302,7 +558,7 @@ impl Default for SymbolTable {
 pub struct TemporarySymbolTable<'a> {
     base: &'a SymbolTable,
     offset: usize,
-    symbols: Vec<String>,
+    symbols: Interner,
 }
 
 impl<'a> TemporarySymbolTable<'a> {
@@ -312,15 +568,13 @@ impl<'a> TemporarySymbolTable<'a> {
         TemporarySymbolTable {
             base,
             offset,
-            symbols: vec![],
+            symbols: Interner::default(),
         }
     }
 
     pub fn get_symbol(&self, i: SymbolIndex) -> Option<&str> {
         if i as usize >= self.offset {
-            self.symbols
-                .get(i as usize - self.offset)
-                .map(|s| s.as_str())
+            self.symbols.get(i as usize - self.offset)
         } else {
             self.base.get_symbol(i)
         }
@@ -331,12 +585,123 @@ impl<'a> TemporarySymbolTable<'a> {
             return index as u64;
         }
 
-        match self.symbols.iter().position(|sym| sym.as_str() == s) {
+        match self.symbols.position(s) {
             Some(index) => (self.offset + index) as u64,
-            None => {
-                self.symbols.push(s.to_string());
-                (self.offset + (self.symbols.len() - 1)) as u64
+            None => (self.offset + self.symbols.push(Arc::from(s))) as u64,
+        }
+    }
+
+    /// returns true if strings were added on top of the base table
+    pub fn has_temporary_symbols(&self) -> bool {
+        !self.symbols.is_empty()
+    }
+}
+
+/// symbol table where a string was found, used in [SymbolReport]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SymbolSource {
+    /// the predefined symbols, available in every implementation
+    Default,
+    /// the symbol table of the token block at this index
+    Block(usize),
+    /// the strings used by the authorizer's facts, rules, checks and policies
+    Authorizer,
+}
+
+/// lists the strings defined in more than one symbol table
+///
+/// Every string defined in a block's symbol table is serialized in the token,
+/// so a string that is duplicated between blocks, or that could have been
+/// taken from the default symbols, makes the token larger.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SymbolReport {
+    occurrences: BTreeMap<String, Vec<SymbolSource>>,
+}
+
+impl SymbolReport {
+    /// creates a report containing the default symbols
+    pub fn new() -> Self {
+        let mut report = SymbolReport::default();
+        report.add(SymbolSource::Default, DEFAULT_SYMBOLS.iter().copied());
+        report
+    }
+
+    /// records the strings of a symbol table
+    pub fn add<I, S>(&mut self, source: SymbolSource, symbols: I)
+    where
+        I: IntoIterator<Item = S>,
+        S: AsRef<str>,
+    {
+        for symbol in symbols {
+            let sources = self
+                .occurrences
+                .entry(symbol.as_ref().to_string())
+                .or_default();
+            if !sources.contains(&source) {
+                sources.push(source);
             }
         }
     }
+
+    /// strings found in more than one symbol table, with the list of tables
+    pub fn duplicates(&self) -> impl Iterator<Item = (&str, &[SymbolSource])> {
+        self.occurrences
+            .iter()
+            .filter(|(_, sources)| sources.len() > 1)
+            .map(|(symbol, sources)| (symbol.as_str(), sources.as_slice()))
+    }
+
+    /// estimates the number of bytes that could be removed from the serialized token
+    /// if every duplicated string was only defined once
+    ///
+    /// This is an upper bound: third party blocks have their own symbol table,
+    /// so strings they use cannot be shared with other blocks, but they could
+    /// still be replaced with one of the default symbols
+    pub fn estimated_savings(&self) -> usize {
+        self.duplicates()
+            .map(|(symbol, sources)| {
+                let serialized = sources
+                    .iter()
+                    .filter(|s| matches!(s, SymbolSource::Block(_)))
+                    .count();
+                // if the string is not a default symbol, one of the blocks has to carry it
+                let redundant = if sources.contains(&SymbolSource::Default) {
+                    serialized
+                } else {
+                    serialized.saturating_sub(1)
+                };
+
+                redundant * serialized_string_size(symbol)
+            })
+            .sum()
+    }
+}
+
+impl fmt::Display for SymbolReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (symbol, sources) in self.duplicates() {
+            let sources = sources
+                .iter()
+                .map(|source| match source {
+                    SymbolSource::Default => "default".to_string(),
+                    SymbolSource::Block(i) => format!("block {}", i),
+                    SymbolSource::Authorizer => "authorizer".to_string(),
+                })
+                .collect::<Vec<_>>();
+            writeln!(f, "\"{}\": {}", symbol, sources.join(", "))?;
+        }
+        write!(f, "estimated savings: {} bytes", self.estimated_savings())
+    }
+}
+
+/// size of a string in a protobuf repeated field: tag, length varint, then the bytes
+fn serialized_string_size(s: &str) -> usize {
+    let mut len_size = 1;
+    let mut len = s.len() >> 7;
+    while len > 0 {
+        len_size += 1;
+        len >>= 7;
+    }
+
+    1 + len_size + s.len()
 }
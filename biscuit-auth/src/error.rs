@@ -1,5 +1,7 @@
 //! error types
 //!
+//! the error enums are `#[non_exhaustive]`: new versions can add variants,
+//! so matching on them needs a wildcard arm
 
 use std::convert::{From, Infallible};
 use thiserror::Error;
@@ -7,6 +9,7 @@ use thiserror::Error;
 /// the global error type for Biscuit
 #[derive(Error, Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum Token {
     #[error("internal error")]
     InternalError,
@@ -16,6 +19,8 @@ pub enum Token {
     AppendOnSealed,
     #[error("tried to seal an already sealed token")]
     AlreadySealed,
+    #[error("tried to counter sign a token that is not sealed")]
+    NotSealed,
     #[error("authorization failed")]
     FailedLogic(Logic),
     #[error("error generating Datalog: {0}")]
@@ -28,6 +33,272 @@ pub enum Token {
     Base64(Base64Error),
     #[error("Datalog  execution failure: {0}")]
     Execution(Expression),
+    #[error("a rule aggregates facts that depend on its own results: {0}")]
+    RecursiveAggregate(String),
+    #[error("the token was revoked (blocks {block_ids:?})")]
+    Revoked { block_ids: Vec<u32> },
+    #[error(
+        "the delegated token cannot expire after the original token ({requested} > {current})"
+    )]
+    ExpirationExtended {
+        /// expiration date of the original token, in seconds since the Unix epoch
+        current: u64,
+        /// requested expiration date, in seconds since the Unix epoch
+        requested: u64,
+    },
+    #[error("third party block {hop} ({name}) could not be appended: {error}")]
+    ThirdPartyHop {
+        /// position of the hop in the [`ThirdPartyPipeline`](crate::ThirdPartyPipeline)
+        hop: usize,
+        /// name given to the hop in [`ThirdPartyPipeline::hop`](crate::ThirdPartyPipeline::hop)
+        name: String,
+        /// error returned by the hop's signing function, or when appending its block
+        error: Box<Token>,
+    },
+}
+
+#[cfg(feature = "json")]
+impl Token {
+    /// serializes the error as JSON
+    ///
+    /// the error is represented with the variant name as key, and its
+    /// content as value, like this failed authorization:
+    ///
+    /// ```json
+    /// {
+    ///   "FailedLogic": {
+    ///     "Unauthorized": {
+    ///       "policy": { "Allow": 0 },
+    ///       "checks": [
+    ///         { "Block": { "block_id": 0, "check_id": 0, "rule": "check if true" } },
+    ///         { "Authorizer": { "check_id": 0, "rule": "check if false" } }
+    ///       ]
+    ///     }
+    ///   }
+    /// }
+    /// ```
+    ///
+    /// unit variants are serialized as strings, like `"AlreadySealed"`. Fails
+    /// with [`Format::SerializationError`] if the error cannot be converted to JSON
+    pub fn to_json(&self) -> Result<serde_json::Value, Token> {
+        serde_json::to_value(self)
+            .map_err(|e| Format::SerializationError(format!("serialization error: {:?}", e)).into())
+    }
+}
+
+impl Token {
+    /// returns a stable code classifying the error, to handle it without
+    /// matching on the error messages
+    ///
+    /// ```rust
+    /// use biscuit_auth::{error::ErrorCode, Biscuit, KeyPair};
+    ///
+    /// let root = KeyPair::new();
+    /// let token = Biscuit::builder().build(&root).unwrap();
+    /// let mut authorizer = token.authorizer().unwrap();
+    /// authorizer.add_code("check if false; allow if true").unwrap();
+    ///
+    /// let code = authorizer.authorize().unwrap_err().code();
+    /// assert_eq!(code, ErrorCode::CheckFailedInAuthorizer(0));
+    /// assert_eq!(code.as_str(), "check_failed_in_authorizer");
+    /// assert_eq!(code.http_status(), 403);
+    /// ```
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Token::InternalError => ErrorCode::Internal,
+            Token::Format(e) => e.code(),
+            Token::AppendOnSealed => ErrorCode::AppendOnSealed,
+            Token::AlreadySealed => ErrorCode::AlreadySealed,
+            Token::NotSealed => ErrorCode::NotSealed,
+            Token::FailedLogic(e) => e.code(),
+            Token::Language(_) => ErrorCode::Language,
+            Token::RunLimit(RunLimit::TooManyFacts) => ErrorCode::TooManyFacts,
+            Token::RunLimit(RunLimit::TooManyIterations) => ErrorCode::TooManyIterations,
+            Token::RunLimit(RunLimit::Timeout) => ErrorCode::Timeout,
+            Token::RunLimit(RunLimit::TooManyFactsInBlock { block_id }) => {
+                ErrorCode::TooManyFactsInBlock(*block_id)
+            }
+            Token::RunLimit(RunLimit::TooManyIterationsInBlock { block_id }) => {
+                ErrorCode::TooManyIterationsInBlock(*block_id)
+            }
+            Token::RunLimit(RunLimit::TooManyResults) => ErrorCode::TooManyResults,
+            Token::ConversionError(_) => ErrorCode::Conversion,
+            Token::Base64(_) => ErrorCode::Base64,
+            Token::Execution(_) => ErrorCode::Execution,
+            Token::RecursiveAggregate(_) => ErrorCode::RecursiveAggregate,
+            Token::Revoked { .. } => ErrorCode::Revoked,
+            Token::ExpirationExtended { .. } => ErrorCode::ExpirationExtended,
+            // the pipeline only adds the position of the failing service
+            Token::ThirdPartyHop { error, .. } => error.code(),
+        }
+    }
+}
+
+/// stable classification of [`Token`] errors
+///
+/// the details of the errors, like the failing rules, stay in the error itself.
+/// Codes can be added in new versions, but existing codes keep their meaning
+/// and their [`ErrorCode::as_str`] representation
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ErrorCode {
+    Internal,
+    /// a signature of the token, or the proof of a sealed token, is invalid
+    SignatureInvalid,
+    /// the root key of the token was not found
+    UnknownRootKey,
+    /// a third party block is signed by an unknown or refused key
+    UnknownExternalKey,
+    /// the token or one of its blocks could not be deserialized
+    Deserialization,
+    Serialization,
+    /// the token uses a format version that is not supported
+    UnsupportedVersion,
+    /// the token uses application symbols that are not known
+    UnknownApplicationSymbols,
+    AppendOnSealed,
+    AlreadySealed,
+    NotSealed,
+    /// the Datalog code could not be parsed
+    Language,
+    /// a block contains an invalid rule, with the block id
+    InvalidBlockRule(u32),
+    /// a check of a block failed, with the block id. This is the first failed
+    /// check, other failed checks are listed in the error
+    CheckFailedInBlock(u32),
+    /// a check of the authorizer failed, with the check id. This is the first
+    /// failed check, other failed checks are listed in the error
+    CheckFailedInAuthorizer(u32),
+    /// a deny policy matched, with the policy id
+    DenyPolicyMatched(u32),
+    NoMatchingPolicy,
+    AuthorizerNotEmpty,
+    AudienceMismatch,
+    SealRequired,
+    /// invalid fact namespace, or predicate reserved to a namespace
+    Namespace,
+    TooManyFacts,
+    TooManyIterations,
+    Timeout,
+    Conversion,
+    Base64,
+    /// an expression could not be evaluated
+    Execution,
+    Revoked,
+    ExpirationExtended,
+    /// the rules of a block exceeded their fact quota, with the block id
+    TooManyFactsInBlock(u32),
+    /// the rules of a block exceeded their iteration quota, with the block id
+    TooManyIterationsInBlock(u32),
+    /// a query returned more facts than allowed
+    TooManyResults,
+    /// a rule aggregates facts that depend on its own results
+    RecursiveAggregate,
+    /// a block defines a predicate provided by the authorizer, with the block id
+    ReservedPredicate(u32),
+}
+
+impl ErrorCode {
+    /// stable name of the code, in snake case
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ErrorCode::Internal => "internal",
+            ErrorCode::SignatureInvalid => "signature_invalid",
+            ErrorCode::UnknownRootKey => "unknown_root_key",
+            ErrorCode::UnknownExternalKey => "unknown_external_key",
+            ErrorCode::Deserialization => "deserialization",
+            ErrorCode::Serialization => "serialization",
+            ErrorCode::UnsupportedVersion => "unsupported_version",
+            ErrorCode::UnknownApplicationSymbols => "unknown_application_symbols",
+            ErrorCode::AppendOnSealed => "append_on_sealed",
+            ErrorCode::AlreadySealed => "already_sealed",
+            ErrorCode::NotSealed => "not_sealed",
+            ErrorCode::Language => "language",
+            ErrorCode::InvalidBlockRule(_) => "invalid_block_rule",
+            ErrorCode::CheckFailedInBlock(_) => "check_failed_in_block",
+            ErrorCode::CheckFailedInAuthorizer(_) => "check_failed_in_authorizer",
+            ErrorCode::DenyPolicyMatched(_) => "deny_policy_matched",
+            ErrorCode::NoMatchingPolicy => "no_matching_policy",
+            ErrorCode::AuthorizerNotEmpty => "authorizer_not_empty",
+            ErrorCode::AudienceMismatch => "audience_mismatch",
+            ErrorCode::SealRequired => "seal_required",
+            ErrorCode::Namespace => "namespace",
+            ErrorCode::TooManyFacts => "too_many_facts",
+            ErrorCode::TooManyIterations => "too_many_iterations",
+            ErrorCode::Timeout => "timeout",
+            ErrorCode::Conversion => "conversion",
+            ErrorCode::Base64 => "base64",
+            ErrorCode::Execution => "execution",
+            ErrorCode::Revoked => "revoked",
+            ErrorCode::ExpirationExtended => "expiration_extended",
+            ErrorCode::TooManyFactsInBlock(_) => "too_many_facts_in_block",
+            ErrorCode::TooManyIterationsInBlock(_) => "too_many_iterations_in_block",
+            ErrorCode::TooManyResults => "too_many_results",
+            ErrorCode::RecursiveAggregate => "recursive_aggregate",
+            ErrorCode::ReservedPredicate(_) => "reserved_predicate",
+        }
+    }
+
+    /// suggested HTTP status for a request carrying the token
+    ///
+    /// * 400 for tokens that cannot be decoded
+    /// * 401 for tokens that cannot be trusted: invalid signature, unknown key, revoked
+    /// * 403 for valid tokens refused by the authorization, or whose blocks
+    ///   exceed their quotas
+    /// * 500 for errors of the service: Datalog code, limits, token creation
+    pub fn http_status(&self) -> u16 {
+        match self {
+            ErrorCode::Deserialization
+            | ErrorCode::UnsupportedVersion
+            | ErrorCode::UnknownApplicationSymbols
+            | ErrorCode::Base64 => 400,
+            ErrorCode::SignatureInvalid
+            | ErrorCode::UnknownRootKey
+            | ErrorCode::UnknownExternalKey
+            | ErrorCode::Revoked => 401,
+            ErrorCode::InvalidBlockRule(_)
+            | ErrorCode::CheckFailedInBlock(_)
+            | ErrorCode::CheckFailedInAuthorizer(_)
+            | ErrorCode::DenyPolicyMatched(_)
+            | ErrorCode::NoMatchingPolicy
+            | ErrorCode::AudienceMismatch
+            | ErrorCode::SealRequired
+            | ErrorCode::ReservedPredicate(_)
+            | ErrorCode::TooManyFactsInBlock(_)
+            | ErrorCode::TooManyIterationsInBlock(_) => 403,
+            ErrorCode::Internal
+            | ErrorCode::Serialization
+            | ErrorCode::AppendOnSealed
+            | ErrorCode::AlreadySealed
+            | ErrorCode::NotSealed
+            | ErrorCode::Language
+            | ErrorCode::AuthorizerNotEmpty
+            | ErrorCode::Namespace
+            | ErrorCode::TooManyFacts
+            | ErrorCode::TooManyIterations
+            | ErrorCode::Timeout
+            | ErrorCode::TooManyResults
+            | ErrorCode::Conversion
+            | ErrorCode::Execution
+            | ErrorCode::RecursiveAggregate
+            | ErrorCode::ExpirationExtended => 500,
+        }
+    }
+}
+
+impl std::fmt::Display for ErrorCode {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ErrorCode::InvalidBlockRule(id)
+            | ErrorCode::CheckFailedInBlock(id)
+            | ErrorCode::CheckFailedInAuthorizer(id)
+            | ErrorCode::DenyPolicyMatched(id)
+            | ErrorCode::ReservedPredicate(id)
+            | ErrorCode::TooManyFactsInBlock(id)
+            | ErrorCode::TooManyIterationsInBlock(id) => write!(f, "{}({})", self.as_str(), id),
+            _ => write!(f, "{}", self.as_str()),
+        }
+    }
 }
 
 impl From<Infallible> for Token {
@@ -75,6 +346,7 @@ impl From<Execution> for Token {
         match e {
             Execution::RunLimit(limit) => Token::RunLimit(limit),
             Execution::Expression(e) => Token::Execution(e),
+            Execution::RecursiveAggregate(rule) => Token::RecursiveAggregate(rule),
         }
     }
 }
@@ -105,6 +377,7 @@ impl std::fmt::Display for Base64Error {
 /// signature
 #[derive(Error, Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum Format {
     #[error("failed verifying the signature")]
     Signature(Signature),
@@ -128,6 +401,14 @@ pub enum Format {
         minimum: u32,
         actual: u32,
     },
+    /// the block uses a version of the extensions of the format by this
+    /// library, from 1000, that is not supported
+    #[error("Block format extension version is higher than supported")]
+    ExtensionVersion {
+        maximum: u32,
+        minimum: u32,
+        actual: u32,
+    },
     #[error("invalid key size")]
     InvalidKeySize(usize),
     #[error("invalid signature size")]
@@ -150,11 +431,45 @@ pub enum Format {
     UnknownExternalKey,
     #[error("the symbol id was not in the table")]
     UnknownSymbol(u64),
+    #[error("the token uses unknown application symbols: {0}")]
+    UnknownApplicationSymbols(u32),
+}
+
+impl Format {
+    /// see [`Token::code`]
+    pub fn code(&self) -> ErrorCode {
+        match self {
+            Format::Signature(_) | Format::SealedSignature => ErrorCode::SignatureInvalid,
+            Format::UnknownPublicKey => ErrorCode::UnknownRootKey,
+            Format::UnknownExternalKey => ErrorCode::UnknownExternalKey,
+            Format::SerializationError(_) | Format::BlockSerializationError(_) => {
+                ErrorCode::Serialization
+            }
+            Format::Version { .. } | Format::ExtensionVersion { .. } => {
+                ErrorCode::UnsupportedVersion
+            }
+            Format::UnknownApplicationSymbols(_) => ErrorCode::UnknownApplicationSymbols,
+            Format::EmptyKeys
+            | Format::DeserializationError(_)
+            | Format::BlockDeserializationError(_)
+            | Format::InvalidKeySize(_)
+            | Format::InvalidSignatureSize(_)
+            | Format::InvalidKey(_)
+            | Format::SignatureDeserializationError(_)
+            | Format::BlockSignatureDeserializationError(_)
+            | Format::InvalidBlockId(_)
+            | Format::ExistingPublicKey(_)
+            | Format::SymbolTableOverlap
+            | Format::PublicKeyTableOverlap
+            | Format::UnknownSymbol(_) => ErrorCode::Deserialization,
+        }
+    }
 }
 
 /// Signature errors
 #[derive(Error, Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum Signature {
     #[error("could not parse the signature elements")]
     InvalidFormat,
@@ -167,6 +482,7 @@ pub enum Signature {
 /// errors in the Datalog evaluation
 #[derive(Error, Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum Logic {
     #[error("a rule provided by a block is generating facts with the authority or ambient tag, or has head variables not used in its body")]
     InvalidBlockRule(u32, String),
@@ -184,6 +500,64 @@ pub enum Logic {
         /// list of checks that failed validation
         checks: Vec<FailedCheck>,
     },
+    #[error("audience mismatch: expected one of {expected:?}, the token is for {actual:?}")]
+    AudienceMismatch {
+        /// audiences accepted by the authorizer
+        expected: Vec<String>,
+        /// audiences declared in the token's authority block
+        actual: Vec<String>,
+    },
+    #[error("the authorizer only accepts sealed tokens")]
+    SealRequired,
+    #[error("block {block_id} defines the reserved predicate {predicate}")]
+    ReservedPredicate {
+        /// block containing the fact or rule
+        block_id: u32,
+        /// predicate provided by the authorizer
+        predicate: String,
+    },
+    #[error("invalid fact namespace: {0}")]
+    InvalidNamespace(String),
+    #[error("the predicate {predicate} is reserved to the {namespace} namespace")]
+    NamespaceConflict {
+        /// namespace declared in the authorizer builder
+        namespace: String,
+        /// predicate that was not added through the namespace
+        predicate: String,
+    },
+}
+
+impl Logic {
+    /// see [`Token::code`]
+    pub fn code(&self) -> ErrorCode {
+        let failed_check = |checks: &[FailedCheck]| {
+            checks.first().map(|check| match check {
+                FailedCheck::Block(check) => ErrorCode::CheckFailedInBlock(check.block_id),
+                FailedCheck::Authorizer(check) => {
+                    ErrorCode::CheckFailedInAuthorizer(check.check_id)
+                }
+            })
+        };
+
+        match self {
+            Logic::InvalidBlockRule(block_id, _) => ErrorCode::InvalidBlockRule(*block_id),
+            Logic::Unauthorized { policy, checks } => {
+                failed_check(checks).unwrap_or(match policy {
+                    MatchedPolicy::Deny(id) => ErrorCode::DenyPolicyMatched(*id as u32),
+                    // an allow policy only fails with failed checks
+                    MatchedPolicy::Allow(_) => ErrorCode::Internal,
+                })
+            }
+            Logic::NoMatchingPolicy { checks } => {
+                failed_check(checks).unwrap_or(ErrorCode::NoMatchingPolicy)
+            }
+            Logic::AuthorizerNotEmpty => ErrorCode::AuthorizerNotEmpty,
+            Logic::AudienceMismatch { .. } => ErrorCode::AudienceMismatch,
+            Logic::SealRequired => ErrorCode::SealRequired,
+            Logic::ReservedPredicate { block_id, .. } => ErrorCode::ReservedPredicate(*block_id),
+            Logic::InvalidNamespace(_) | Logic::NamespaceConflict { .. } => ErrorCode::Namespace,
+        }
+    }
 }
 
 #[derive(Error, Clone, Debug, PartialEq, Eq)]
@@ -212,6 +586,12 @@ pub struct FailedBlockCheck {
     pub check_id: u32,
     /// pretty print of the rule that failed
     pub rule: String,
+    /// message attached to the check with `@msg("...")`
+    #[cfg_attr(
+        feature = "serde-error",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub message: Option<String>,
 }
 
 #[derive(Clone, Debug, PartialEq, Eq)]
@@ -220,21 +600,31 @@ pub struct FailedAuthorizerCheck {
     pub check_id: u32,
     /// pretty print of the rule that failed
     pub rule: String,
+    /// message attached to the check with `@msg("...")`
+    #[cfg_attr(
+        feature = "serde-error",
+        serde(default, skip_serializing_if = "Option::is_none")
+    )]
+    pub message: Option<String>,
 }
 
 /// Datalog execution errors
 #[derive(Error, Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum Execution {
     #[error("Reached Datalog execution limits")]
     RunLimit(RunLimit),
     #[error("Expression execution failure")]
     Expression(Expression),
+    #[error("the aggregate depends on its own results: {0}")]
+    RecursiveAggregate(String),
 }
 
 /// Datalog expression execution failure
 #[derive(Error, Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum Expression {
     #[error("Unknown symbol")]
     UnknownSymbol(u64),
@@ -248,11 +638,16 @@ pub enum Expression {
     DivideByZero,
     #[error("Wrong number of elements on stack")]
     InvalidStack,
+    #[error("Invalid regular expression")]
+    InvalidRegex,
+    #[error("The regular expression does not match")]
+    NoMatch,
 }
 
 /// runtime limits errors
 #[derive(Error, Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub enum RunLimit {
     #[error("too many facts generated")]
     TooManyFacts,
@@ -260,6 +655,12 @@ pub enum RunLimit {
     TooManyIterations,
     #[error("spent too much time verifying")]
     Timeout,
+    #[error("too many facts generated by the rules of block {block_id}")]
+    TooManyFactsInBlock { block_id: u32 },
+    #[error("too many engine iterations generating facts from the rules of block {block_id}")]
+    TooManyIterationsInBlock { block_id: u32 },
+    #[error("too many facts returned by the query")]
+    TooManyResults,
 }
 
 #[cfg(test)]
@@ -278,4 +679,63 @@ mod tests {
             "Cannot decode base64 token: Encoded text cannot have a 6-bit remainder."
         );
     }
+
+    #[test]
+    fn error_codes() {
+        let failed_block_check = FailedCheck::Block(FailedBlockCheck {
+            block_id: 1,
+            check_id: 0,
+            rule: "check if false".to_string(),
+            message: None,
+        });
+
+        let error = Token::FailedLogic(Logic::Unauthorized {
+            policy: MatchedPolicy::Deny(2),
+            checks: vec![failed_block_check.clone()],
+        });
+        assert_eq!(error.code(), ErrorCode::CheckFailedInBlock(1));
+        assert_eq!(error.code().to_string(), "check_failed_in_block(1)");
+
+        let error = Token::FailedLogic(Logic::Unauthorized {
+            policy: MatchedPolicy::Deny(2),
+            checks: vec![],
+        });
+        assert_eq!(error.code(), ErrorCode::DenyPolicyMatched(2));
+        assert_eq!(error.code().http_status(), 403);
+
+        let error = Token::FailedLogic(Logic::NoMatchingPolicy { checks: vec![] });
+        assert_eq!(error.code(), ErrorCode::NoMatchingPolicy);
+
+        let error = Token::FailedLogic(Logic::ReservedPredicate {
+            block_id: 1,
+            predicate: "biscuit::root_key".to_string(),
+        });
+        assert_eq!(error.code(), ErrorCode::ReservedPredicate(1));
+        assert_eq!(error.code().to_string(), "reserved_predicate(1)");
+        assert_eq!(error.code().http_status(), 403);
+
+        let error = Token::Format(Format::Signature(Signature::InvalidSignature(
+            "the signature did not match".to_string(),
+        )));
+        assert_eq!(error.code(), ErrorCode::SignatureInvalid);
+        assert_eq!(error.code().as_str(), "signature_invalid");
+        assert_eq!(error.code().http_status(), 401);
+
+        assert_eq!(
+            Token::Format(Format::BlockDeserializationError("".to_string())).code(),
+            ErrorCode::Deserialization
+        );
+        assert_eq!(
+            Token::RunLimit(RunLimit::Timeout).code(),
+            ErrorCode::Timeout
+        );
+        assert_eq!(ErrorCode::Timeout.http_status(), 500);
+
+        let error = Token::ThirdPartyHop {
+            hop: 0,
+            name: "service".to_string(),
+            error: Box::new(Token::Format(Format::UnknownExternalKey)),
+        };
+        assert_eq!(error.code(), ErrorCode::UnknownExternalKey);
+    }
 }
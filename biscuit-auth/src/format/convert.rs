@@ -10,7 +10,7 @@ use crate::error;
 use crate::token::public_keys::PublicKeys;
 use crate::token::Scope;
 use crate::token::{authorizer::AuthorizerPolicies, Block};
-use crate::token::{MAX_SCHEMA_VERSION, MIN_SCHEMA_VERSION};
+use crate::token::{check_schema_version, EXTENSION_SCHEMA_VERSION_2, MIN_SCHEMA_VERSION};
 
 pub fn token_block_to_proto_block(input: &Block) -> schema::Block {
     schema::Block {
@@ -43,6 +43,7 @@ pub fn token_block_to_proto_block(input: &Block) -> schema::Block {
             .iter()
             .map(|key| key.to_proto())
             .collect(),
+        application_symbols: input.application_symbols,
     }
 }
 
@@ -51,13 +52,7 @@ pub fn proto_block_to_token_block(
     external_key: Option<PublicKey>,
 ) -> Result<Block, error::Format> {
     let version = input.version.unwrap_or(0);
-    if !(MIN_SCHEMA_VERSION..=MAX_SCHEMA_VERSION).contains(&version) {
-        return Err(error::Format::Version {
-            minimum: crate::token::MIN_SCHEMA_VERSION,
-            maximum: crate::token::MAX_SCHEMA_VERSION,
-            actual: version,
-        });
-    }
+    check_schema_version(version)?;
 
     let mut facts = vec![];
     let mut rules = vec![];
@@ -97,6 +92,12 @@ pub fn proto_block_to_token_block(
 
     detected_schema_version.check_compatibility(version)?;
 
+    if version < EXTENSION_SCHEMA_VERSION_2 && input.application_symbols.is_some() {
+        return Err(error::Format::DeserializationError(
+            "blocks before v1002 must not use application symbols".to_string(),
+        ));
+    }
+
     let scopes: Result<Vec<Scope>, _> =
         input.scope.iter().map(proto_scope_to_token_scope).collect();
 
@@ -110,6 +111,7 @@ pub fn proto_block_to_token_block(
         external_key,
         public_keys,
         scopes: scopes?,
+        application_symbols: input.application_symbols,
     })
 }
 
@@ -145,13 +147,7 @@ pub fn proto_snapshot_block_to_token_block(
     input: &schema::SnapshotBlock,
 ) -> Result<Block, error::Format> {
     let version = input.version.unwrap_or(0);
-    if !(MIN_SCHEMA_VERSION..=MAX_SCHEMA_VERSION).contains(&version) {
-        return Err(error::Format::Version {
-            minimum: crate::token::MIN_SCHEMA_VERSION,
-            maximum: crate::token::MAX_SCHEMA_VERSION,
-            actual: version,
-        });
-    }
+    check_schema_version(version)?;
 
     let mut facts = vec![];
     let mut rules = vec![];
@@ -202,6 +198,7 @@ pub fn proto_snapshot_block_to_token_block(
         external_key,
         public_keys: PublicKeys::default(),
         scopes: scopes?,
+        application_symbols: None,
     })
 }
 pub fn authorizer_to_proto_authorizer(input: &AuthorizerPolicies) -> schema::AuthorizerPolicies {
@@ -248,13 +245,7 @@ pub fn proto_authorizer_to_authorizer(
     input: &schema::AuthorizerPolicies,
 ) -> Result<AuthorizerPolicies, error::Format> {
     let version = input.version.unwrap_or(0);
-    if !(MIN_SCHEMA_VERSION..=MAX_SCHEMA_VERSION).contains(&version) {
-        return Err(error::Format::Version {
-            minimum: crate::token::MIN_SCHEMA_VERSION,
-            maximum: crate::token::MAX_SCHEMA_VERSION,
-            actual: version,
-        });
-    }
+    check_schema_version(version)?;
 
     let symbols = SymbolTable::from(input.symbols.clone())?;
 
@@ -305,6 +296,7 @@ pub mod v2 {
     use crate::token::Scope;
     use crate::token::MIN_SCHEMA_VERSION;
     use std::collections::BTreeSet;
+    use std::convert::{TryFrom, TryInto};
 
     pub fn token_fact_to_proto_fact(input: &Fact) -> schema::FactV2 {
         schema::FactV2 {
@@ -326,7 +318,9 @@ pub mod v2 {
             kind: match input.kind {
                 crate::token::builder::CheckKind::One => None,
                 crate::token::builder::CheckKind::All => Some(Kind::All as i32),
+                crate::token::builder::CheckKind::Reject => Some(Kind::Reject as i32),
             },
+            message: input.message.clone(),
         }
     }
 
@@ -343,6 +337,7 @@ pub mod v2 {
         let kind = match input.kind {
             None | Some(0) => crate::token::builder::CheckKind::One,
             Some(1) => crate::token::builder::CheckKind::All,
+            Some(2) => crate::token::builder::CheckKind::Reject,
             _ => {
                 return Err(error::Format::DeserializationError(
                     "deserialization error: invalid check kind".to_string(),
@@ -350,7 +345,11 @@ pub mod v2 {
             }
         };
 
-        Ok(Check { queries, kind })
+        Ok(Check {
+            queries,
+            kind,
+            message: input.message.clone(),
+        })
     }
 
     pub fn policy_to_proto_policy(
@@ -419,6 +418,23 @@ pub mod v2 {
                 .iter()
                 .map(token_scope_to_proto_scope)
                 .collect(),
+            aggregates: input
+                .aggregates
+                .iter()
+                .map(|(position, aggregate)| {
+                    use schema::rule_aggregate::Kind;
+
+                    schema::RuleAggregate {
+                        position: *position as u32,
+                        kind: match aggregate {
+                            Aggregate::Count => Kind::Count,
+                            Aggregate::Sum => Kind::Sum,
+                            Aggregate::Min => Kind::Min,
+                            Aggregate::Max => Kind::Max,
+                        } as i32,
+                    }
+                })
+                .collect(),
         }
     }
 
@@ -448,12 +464,40 @@ pub mod v2 {
             input.scope.iter().map(proto_scope_to_token_scope).collect();
         let scopes = scopes?;
 
+        let head = proto_predicate_to_token_predicate(&input.head)?;
+        let mut aggregates = vec![];
+        for aggregate in input.aggregates.iter() {
+            use schema::rule_aggregate::Kind;
+
+            let position = aggregate.position as usize;
+            if !matches!(head.terms.get(position), Some(Term::Variable(_))) {
+                return Err(error::Format::DeserializationError(
+                    "deserialization error: aggregates must apply to a variable of the rule head"
+                        .to_string(),
+                ));
+            }
+
+            let kind = match Kind::from_i32(aggregate.kind) {
+                Some(Kind::Count) => Aggregate::Count,
+                Some(Kind::Sum) => Aggregate::Sum,
+                Some(Kind::Min) => Aggregate::Min,
+                Some(Kind::Max) => Aggregate::Max,
+                None => {
+                    return Err(error::Format::DeserializationError(
+                        "deserialization error: invalid aggregate kind".to_string(),
+                    ))
+                }
+            };
+            aggregates.push((position, kind));
+        }
+
         Ok((
             Rule {
-                head: proto_predicate_to_token_predicate(&input.head)?,
+                head,
                 body,
                 expressions,
                 scopes: scopes.clone(),
+                aggregates,
             },
             scopes,
         ))
@@ -508,6 +552,15 @@ pub mod v2 {
                     set: s.iter().map(token_term_to_proto_id).collect(),
                 })),
             },
+            Term::Decimal(m, s) => schema::TermV2 {
+                content: Some(Content::Decimal(schema::Decimal {
+                    mantissa: m.to_le_bytes().to_vec(),
+                    scale: u32::from(*s),
+                })),
+            },
+            Term::Uuid(u) => schema::TermV2 {
+                content: Some(Content::Uuid(u.to_vec())),
+            },
         }
     }
 
@@ -524,6 +577,29 @@ pub mod v2 {
             Some(Content::Date(i)) => Ok(Term::Date(*i)),
             Some(Content::Bytes(s)) => Ok(Term::Bytes(s.clone())),
             Some(Content::Bool(b)) => Ok(Term::Bool(*b)),
+            Some(Content::Decimal(d)) => {
+                let mantissa: [u8; 16] = d.mantissa[..].try_into().map_err(|_| {
+                    error::Format::DeserializationError(
+                        "deserialization error: invalid decimal mantissa".to_string(),
+                    )
+                })?;
+                let scale = u8::try_from(d.scale)
+                    .ok()
+                    .filter(|scale| *scale <= crate::datalog::decimal::MAX_DECIMAL_SCALE)
+                    .ok_or_else(|| {
+                        error::Format::DeserializationError(
+                            "deserialization error: invalid decimal scale".to_string(),
+                        )
+                    })?;
+                let (mantissa, scale) =
+                    crate::datalog::decimal::normalize(i128::from_le_bytes(mantissa), scale);
+                Ok(Term::Decimal(mantissa, scale))
+            }
+            Some(Content::Uuid(u)) => u[..].try_into().map(Term::Uuid).map_err(|_| {
+                error::Format::DeserializationError(
+                    "deserialization error: a UUID must have 16 bytes".to_string(),
+                )
+            }),
             Some(Content::Set(s)) => {
                 let mut kind: Option<u8> = None;
                 let mut set = BTreeSet::new();
@@ -540,6 +616,8 @@ pub mod v2 {
                         Some(Content::Date(_)) => 4,
                         Some(Content::Bytes(_)) => 5,
                         Some(Content::Bool(_)) => 6,
+                        Some(Content::Decimal(_)) => 8,
+                        Some(Content::Uuid(_)) => 9,
                         Some(Content::Set(_)) => {
                             return Err(error::Format::DeserializationError(
                                 "deserialization error: sets cannot contain other sets".to_string(),
@@ -587,6 +665,8 @@ pub mod v2 {
                                     Unary::Negate => Kind::Negate,
                                     Unary::Parens => Kind::Parens,
                                     Unary::Length => Kind::Length,
+                                    Unary::Lowercase => Kind::Lowercase,
+                                    Unary::Trim => Kind::Trim,
                                 } as i32,
                             })
                         }
@@ -616,6 +696,12 @@ pub mod v2 {
                                     Binary::BitwiseOr => Kind::BitwiseOr,
                                     Binary::BitwiseXor => Kind::BitwiseXor,
                                     Binary::NotEqual => Kind::NotEqual,
+                                    Binary::Difference => Kind::Difference,
+                                    Binary::SymmetricDifference => Kind::SymmetricDifference,
+                                    Binary::IsSubset => Kind::IsSubset,
+                                    Binary::IsSuperset => Kind::IsSuperset,
+                                    Binary::Split => Kind::Split,
+                                    Binary::Capture => Kind::Capture,
                                 } as i32,
                             })
                         }
@@ -642,6 +728,8 @@ pub mod v2 {
                     Some(op_unary::Kind::Negate) => Op::Unary(Unary::Negate),
                     Some(op_unary::Kind::Parens) => Op::Unary(Unary::Parens),
                     Some(op_unary::Kind::Length) => Op::Unary(Unary::Length),
+                    Some(op_unary::Kind::Lowercase) => Op::Unary(Unary::Lowercase),
+                    Some(op_unary::Kind::Trim) => Op::Unary(Unary::Trim),
                     None => {
                         return Err(error::Format::DeserializationError(
                             "deserialization error: unary operation is empty".to_string(),
@@ -670,6 +758,14 @@ pub mod v2 {
                     Some(op_binary::Kind::BitwiseOr) => Op::Binary(Binary::BitwiseOr),
                     Some(op_binary::Kind::BitwiseXor) => Op::Binary(Binary::BitwiseXor),
                     Some(op_binary::Kind::NotEqual) => Op::Binary(Binary::NotEqual),
+                    Some(op_binary::Kind::Difference) => Op::Binary(Binary::Difference),
+                    Some(op_binary::Kind::SymmetricDifference) => {
+                        Op::Binary(Binary::SymmetricDifference)
+                    }
+                    Some(op_binary::Kind::IsSubset) => Op::Binary(Binary::IsSubset),
+                    Some(op_binary::Kind::IsSuperset) => Op::Binary(Binary::IsSuperset),
+                    Some(op_binary::Kind::Split) => Op::Binary(Binary::Split),
+                    Some(op_binary::Kind::Capture) => Op::Binary(Binary::Capture),
                     None => {
                         return Err(error::Format::DeserializationError(
                             "deserialization error: binary operation is empty".to_string(),
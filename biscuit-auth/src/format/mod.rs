@@ -4,18 +4,19 @@
 //!
 //! - serialization of Biscuit blocks to Protobuf then `Vec<u8>`
 //! - serialization of a wrapper structure containing serialized blocks and the signature
-use super::crypto::{self, KeyPair, PrivateKey, PublicKey, TokenNext};
+use super::crypto::{
+    self, KeyPair, PrivateKey, PublicKey, SecurityPolicy, Signature, Signer, TokenNext,
+};
 
-use ed25519_dalek::Signer;
+use bytes::{Buf, Bytes};
 use prost::Message;
 
 use super::error;
 use super::token::Block;
 use crate::crypto::ExternalSignature;
-use crate::datalog::SymbolTable;
+use crate::datalog::{ApplicationSymbols, SymbolTable};
 use crate::token::RootKeyProvider;
 use std::collections::HashMap;
-use std::convert::TryInto;
 
 /// Structures generated from the Protobuf schema
 pub mod schema; /*{
@@ -23,8 +24,11 @@ pub mod schema; /*{
                 }*/
 
 pub mod convert;
+mod reader;
 
 use self::convert::*;
+pub(crate) use self::reader::read_token;
+pub use self::reader::TokenSizeLimits;
 
 /// Intermediate structure for token serialization
 ///
@@ -36,6 +40,8 @@ pub struct SerializedBiscuit {
     pub authority: crypto::Block,
     pub blocks: Vec<crypto::Block>,
     pub proof: crypto::TokenNext,
+    /// signatures added by verifiers on a sealed token, they are not covered by the token's signatures
+    pub counter_signatures: Vec<crypto::CounterSignature>,
 }
 
 impl SerializedBiscuit {
@@ -51,18 +57,29 @@ impl SerializedBiscuit {
         Ok(deser)
     }
 
-    pub(crate) fn deserialize(slice: &[u8]) -> Result<Self, error::Format> {
-        let data = schema::Biscuit::decode(slice).map_err(|e| {
+    /// deserializes a token and verifies its signatures, without copying the
+    /// block payloads: they are slices of `bytes`
+    pub fn from_bytes<KP>(bytes: Bytes, key_provider: KP) -> Result<Self, error::Format>
+    where
+        KP: RootKeyProvider,
+    {
+        let deser = SerializedBiscuit::deserialize(bytes)?;
+
+        let root = key_provider.choose(deser.root_key_id)?;
+        deser.verify(&root)?;
+
+        Ok(deser)
+    }
+
+    /// block payloads are copied from `buf`, unless it is a [`Bytes`]
+    pub(crate) fn deserialize<B: Buf>(buf: B) -> Result<Self, error::Format> {
+        let data = schema::Biscuit::decode(buf).map_err(|e| {
             error::Format::DeserializationError(format!("deserialization error: {:?}", e))
         })?;
 
         let next_key = PublicKey::from_proto(&data.authority.next_key)?;
 
-        let bytes: [u8; 64] = (&data.authority.signature[..])
-            .try_into()
-            .map_err(|_| error::Format::InvalidSignatureSize(data.authority.signature.len()))?;
-
-        let signature = ed25519_dalek::Signature::from_bytes(&bytes);
+        let signature = Signature::from_vec(data.authority.signature);
 
         if data.authority.external_signature.is_some() {
             return Err(error::Format::DeserializationError(
@@ -78,23 +95,15 @@ impl SerializedBiscuit {
         };
 
         let mut blocks = Vec::new();
-        for block in &data.blocks {
+        for block in data.blocks {
             let next_key = PublicKey::from_proto(&block.next_key)?;
 
-            let bytes: [u8; 64] = (&block.signature[..])
-                .try_into()
-                .map_err(|_| error::Format::InvalidSignatureSize(block.signature.len()))?;
-
-            let signature = ed25519_dalek::Signature::from_bytes(&bytes);
+            let signature = Signature::from_vec(block.signature);
 
-            let external_signature = if let Some(ex) = block.external_signature.as_ref() {
+            let external_signature = if let Some(ex) = block.external_signature {
                 let public_key = PublicKey::from_proto(&ex.public_key)?;
 
-                let bytes: [u8; 64] = (&ex.signature[..])
-                    .try_into()
-                    .map_err(|_| error::Format::InvalidSignatureSize(ex.signature.len()))?;
-
-                let signature = ed25519_dalek::Signature::from_bytes(&bytes);
+                let signature = Signature::from_vec(ex.signature);
 
                 Some(ExternalSignature {
                     public_key,
@@ -105,7 +114,7 @@ impl SerializedBiscuit {
             };
 
             blocks.push(crypto::Block {
-                data: block.block.clone(),
+                data: block.block,
                 next_key,
                 signature,
                 external_signature,
@@ -119,22 +128,32 @@ impl SerializedBiscuit {
                 ))
             }
             Some(schema::proof::Content::NextSecret(v)) => {
-                TokenNext::Secret(PrivateKey::from_bytes(&v)?)
+                // the secret key matches the last block's next key
+                let algorithm = blocks.last().unwrap_or(&authority).next_key.algorithm();
+                TokenNext::Secret(PrivateKey::from_bytes_with_algorithm(&v, algorithm)?)
             }
             Some(schema::proof::Content::FinalSignature(v)) => {
-                let bytes: [u8; 64] = (&v[..])
-                    .try_into()
-                    .map_err(|_| error::Format::InvalidSignatureSize(v.len()))?;
-                let signature = ed25519_dalek::Signature::from_bytes(&bytes);
-                TokenNext::Seal(signature)
+                TokenNext::Seal(Signature::from_vec(v))
             }
         };
 
+        let mut counter_signatures = Vec::new();
+        for counter_signature in &data.counter_signatures {
+            let public_key = PublicKey::from_proto(&counter_signature.public_key)?;
+
+            counter_signatures.push(crypto::CounterSignature {
+                public_key,
+                timestamp: counter_signature.timestamp,
+                signature: Signature::from_vec(counter_signature.signature.clone()),
+            });
+        }
+
         let deser = SerializedBiscuit {
             root_key_id: data.root_key_id,
             authority,
             blocks,
             proof,
+            counter_signatures,
         };
 
         Ok(deser)
@@ -143,6 +162,7 @@ impl SerializedBiscuit {
     pub(crate) fn extract_blocks(
         &self,
         symbols: &mut SymbolTable,
+        application_symbols: &[ApplicationSymbols],
     ) -> Result<
         (
             schema::Block,
@@ -160,6 +180,13 @@ impl SerializedBiscuit {
             )))
         })?;
 
+        if let Some(id) = authority.application_symbols {
+            let application_symbols = application_symbols
+                .iter()
+                .find(|s| s.id() == id)
+                .ok_or(error::Format::UnknownApplicationSymbols(id))?;
+            symbols.extend(application_symbols.symbol_table())?;
+        }
         symbols.extend(&SymbolTable::from(authority.symbols.clone())?)?;
 
         for pk in &authority.public_keys {
@@ -181,6 +208,13 @@ impl SerializedBiscuit {
                 )))
             })?;
 
+            if deser.application_symbols.is_some() {
+                return Err(error::Format::DeserializationError(
+                    "only the authority block can use application symbols".to_string(),
+                )
+                .into());
+            }
+
             if let Some(external_signature) = &block.external_signature {
                 symbols.public_keys.insert(&external_signature.public_key);
                 block_external_keys.push(Some(external_signature.public_key));
@@ -252,6 +286,15 @@ impl SerializedBiscuit {
                     )),
                 },
             },
+            counter_signatures: self
+                .counter_signatures
+                .iter()
+                .map(|counter_signature| schema::CounterSignature {
+                    public_key: counter_signature.public_key.to_proto(),
+                    timestamp: counter_signature.timestamp,
+                    signature: counter_signature.signature.to_bytes().to_vec(),
+                })
+                .collect(),
         }
     }
 
@@ -270,10 +313,21 @@ impl SerializedBiscuit {
             .map_err(|e| error::Format::SerializationError(format!("serialization error: {:?}", e)))
     }
 
+    /// algorithm of the key pair generated for the next block: the blocks
+    /// keep the algorithm of the previous key, so that a token only uses the
+    /// algorithm of its root key
+    pub(crate) fn next_key_algorithm(&self) -> crypto::Algorithm {
+        self.blocks
+            .last()
+            .unwrap_or(&self.authority)
+            .next_key
+            .algorithm()
+    }
+
     /// creates a new token
-    pub fn new(
+    pub fn new<S: Signer + ?Sized>(
         root_key_id: Option<u32>,
-        root_keypair: &KeyPair,
+        root_keypair: &S,
         next_keypair: &KeyPair,
         authority: &Block,
     ) -> Result<Self, error::Token> {
@@ -289,13 +343,14 @@ impl SerializedBiscuit {
         Ok(SerializedBiscuit {
             root_key_id,
             authority: crypto::Block {
-                data: v,
+                data: v.into(),
                 next_key: next_keypair.public(),
                 signature,
                 external_signature: None,
             },
             blocks: vec![],
             proof: TokenNext::Secret(next_keypair.private()),
+            counter_signatures: vec![],
         })
     }
 
@@ -315,7 +370,7 @@ impl SerializedBiscuit {
                 error::Format::SerializationError(format!("serialization error: {:?}", e))
             })?;
         if let Some(signature) = &external_signature {
-            v.extend_from_slice(&signature.signature.to_bytes());
+            v.extend_from_slice(signature.signature.to_bytes());
         }
 
         let signature = crypto::sign(&keypair, next_keypair, &v)?;
@@ -323,7 +378,7 @@ impl SerializedBiscuit {
         // Add new block
         let mut blocks = self.blocks.clone();
         blocks.push(crypto::Block {
-            data: v,
+            data: v.into(),
             next_key: next_keypair.public(),
             signature,
             external_signature,
@@ -334,6 +389,7 @@ impl SerializedBiscuit {
             authority: self.authority.clone(),
             blocks,
             proof: TokenNext::Secret(next_keypair.private()),
+            counter_signatures: vec![],
         })
     }
 
@@ -348,7 +404,7 @@ impl SerializedBiscuit {
 
         let mut v = block.clone();
         if let Some(signature) = &external_signature {
-            v.extend_from_slice(&signature.signature.to_bytes());
+            v.extend_from_slice(signature.signature.to_bytes());
         }
 
         let signature = crypto::sign(&keypair, next_keypair, &v)?;
@@ -356,7 +412,7 @@ impl SerializedBiscuit {
         // Add new block
         let mut blocks = self.blocks.clone();
         blocks.push(crypto::Block {
-            data: block,
+            data: block.into(),
             next_key: next_keypair.public(),
             signature,
             external_signature,
@@ -367,11 +423,22 @@ impl SerializedBiscuit {
             authority: self.authority.clone(),
             blocks,
             proof: TokenNext::Secret(next_keypair.private()),
+            counter_signatures: vec![],
         })
     }
 
     /// checks the signature on a deserialized token
     pub fn verify(&self, root: &PublicKey) -> Result<(), error::Format> {
+        self.verify_with_policy(root, &SecurityPolicy::default())
+    }
+
+    /// checks the signature on a deserialized token, with the hardening options
+    /// of `policy`
+    pub fn verify_with_policy(
+        &self,
+        root: &PublicKey,
+        policy: &SecurityPolicy,
+    ) -> Result<(), error::Format> {
         //FIXME: try batched signature verification
         let mut current_pub = root;
 
@@ -385,7 +452,13 @@ impl SerializedBiscuit {
 
         match &self.proof {
             TokenNext::Secret(private) => {
-                if current_pub != &private.public() {
+                let next_pub = private.public();
+                let matches = if policy.constant_time_comparisons {
+                    current_pub.constant_time_eq(&next_pub)
+                } else {
+                    current_pub == &next_pub
+                };
+                if !matches {
                     return Err(error::Format::Signature(
                         error::Signature::InvalidSignature(
                             "the last public key does not match the private key".to_string(),
@@ -403,18 +476,10 @@ impl SerializedBiscuit {
                     &self.blocks[self.blocks.len() - 1]
                 };
                 to_verify.extend(&block.data);
-                to_verify.extend(
-                    &(crate::format::schema::public_key::Algorithm::Ed25519 as i32).to_le_bytes(),
-                );
-                to_verify.extend(&block.next_key.to_bytes());
-                to_verify.extend(&block.signature.to_bytes());
-
-                current_pub
-                    .0
-                    .verify_strict(&to_verify, signature)
-                    .map_err(|s| s.to_string())
-                    .map_err(error::Signature::InvalidSignature)
-                    .map_err(error::Format::Signature)?;
+                block.next_key.write_signed_payload(&mut to_verify);
+                to_verify.extend(block.signature.to_bytes());
+
+                current_pub.verify_signature(&to_verify, signature)?;
             }
         }
 
@@ -432,25 +497,72 @@ impl SerializedBiscuit {
             &self.blocks[self.blocks.len() - 1]
         };
         to_sign.extend(&block.data);
-        to_sign
-            .extend(&(crate::format::schema::public_key::Algorithm::Ed25519 as i32).to_le_bytes());
-        to_sign.extend(&block.next_key.to_bytes());
-        to_sign.extend(&block.signature.to_bytes());
-
-        let signature = keypair
-            .kp
-            .try_sign(&to_sign)
-            .map_err(|s| s.to_string())
-            .map_err(error::Signature::InvalidSignatureGeneration)
-            .map_err(error::Format::Signature)?;
+        block.next_key.write_signed_payload(&mut to_sign);
+        to_sign.extend(block.signature.to_bytes());
+
+        let signature = keypair.sign(&to_sign)?;
 
         Ok(SerializedBiscuit {
             root_key_id: self.root_key_id,
             authority: self.authority.clone(),
             blocks: self.blocks.clone(),
             proof: TokenNext::Seal(signature),
+            counter_signatures: vec![],
         })
     }
+
+    /// signs a challenge with the private key of the last block
+    ///
+    /// returns the public key of the last block and the signature
+    pub fn prove_possession(
+        &self,
+        challenge: &[u8],
+    ) -> Result<(PublicKey, Signature), error::Token> {
+        let block = self.last_block();
+        let signature = crypto::sign_possession_proof(&self.proof, &block.signature, challenge)?;
+
+        Ok((block.next_key, signature))
+    }
+
+    /// verifies a challenge signature made by [`SerializedBiscuit::prove_possession`]
+    pub fn verify_possession(
+        &self,
+        challenge: &[u8],
+        signature: &Signature,
+    ) -> Result<(), error::Format> {
+        let block = self.last_block();
+        crypto::verify_possession_proof(&block.next_key, &block.signature, challenge, signature)
+    }
+
+    pub(crate) fn last_block(&self) -> &crypto::Block {
+        self.blocks.last().unwrap_or(&self.authority)
+    }
+
+    /// adds a verifier's signature to a sealed token
+    pub fn counter_sign(&self, keypair: &KeyPair, timestamp: u64) -> Result<Self, error::Token> {
+        let final_signature = match &self.proof {
+            TokenNext::Seal(signature) => signature,
+            TokenNext::Secret(_) => return Err(error::Token::NotSealed),
+        };
+
+        let mut token = self.clone();
+        token
+            .counter_signatures
+            .push(crypto::counter_sign(keypair, final_signature, timestamp)?);
+        Ok(token)
+    }
+
+    /// returns the counter signatures with a valid signature
+    pub fn verified_counter_signatures(&self) -> Vec<&crypto::CounterSignature> {
+        match &self.proof {
+            TokenNext::Seal(signature) => self
+                .counter_signatures
+                .iter()
+                .filter(|c| crypto::verify_counter_signature(c, signature).is_ok())
+                .collect(),
+            TokenNext::Secret(_) => Vec::new(),
+        }
+    }
 }
 
 #[cfg(test)]
@@ -464,7 +576,11 @@ mod tests {
             Ok(dir) => dir,
             Err(_) => return,
         };
-        prost_build::compile_protos(&["src/format/schema.proto"], &["src/"]).unwrap();
+        // block payloads are slices of the deserialized buffer
+        prost_build::Config::new()
+            .bytes([".biscuit.format.schema.SignedBlock.block"])
+            .compile_protos(&["src/format/schema.proto"], &["src/"])
+            .unwrap();
         let mut file = std::fs::File::open(&format!("{out_dir}/biscuit.format.schema.rs")).unwrap();
         let mut contents = String::new();
         file.read_to_string(&mut contents).unwrap();
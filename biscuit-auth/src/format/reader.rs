@@ -0,0 +1,140 @@
+//! incremental reading of serialized tokens
+use std::io::{self, Read};
+
+use crate::error;
+
+/// size limits checked while reading a token, see [`Biscuit::from_reader`](crate::Biscuit::from_reader)
+///
+/// the limits are checked before reading each field of the token, so a
+/// token exceeding them is refused without allocating its content
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TokenSizeLimits {
+    /// maximum size of the serialized token, in bytes
+    pub max_size: usize,
+    /// maximum number of blocks, including the authority block
+    pub max_blocks: usize,
+    /// maximum size of a signed block, in bytes
+    pub max_block_size: usize,
+}
+
+impl Default for TokenSizeLimits {
+    fn default() -> Self {
+        TokenSizeLimits {
+            max_size: 1024 * 1024,
+            max_blocks: 256,
+            max_block_size: 64 * 1024,
+        }
+    }
+}
+
+// field numbers of the `Biscuit` message
+const AUTHORITY: u64 = 2;
+const BLOCKS: u64 = 3;
+
+/// reads the top level fields of a serialized `Biscuit` message one by one,
+/// checking the limits before reading the content of each field
+pub(crate) fn read_token<R: Read>(
+    mut reader: R,
+    limits: &TokenSizeLimits,
+) -> Result<Vec<u8>, error::Format> {
+    let mut buf = Vec::new();
+    let mut blocks = 0;
+
+    loop {
+        let key = match read_varint(&mut reader, &mut buf)? {
+            Some(key) => key,
+            None => return Ok(buf),
+        };
+
+        let length = match key & 0x7 {
+            // varint
+            0 => {
+                read_varint(&mut reader, &mut buf)?.ok_or_else(truncated)?;
+                0
+            }
+            // 64 bits
+            1 => 8,
+            // length delimited
+            2 => {
+                let length = read_varint(&mut reader, &mut buf)?.ok_or_else(truncated)?;
+                if key >> 3 == AUTHORITY || key >> 3 == BLOCKS {
+                    blocks += 1;
+                    if blocks > limits.max_blocks {
+                        return Err(error::Format::DeserializationError(format!(
+                            "deserialization error: the token has more than {} blocks",
+                            limits.max_blocks
+                        )));
+                    }
+                    if length > limits.max_block_size as u64 {
+                        return Err(error::Format::DeserializationError(format!(
+                            "deserialization error: a block is larger than {} bytes",
+                            limits.max_block_size
+                        )));
+                    }
+                }
+                length
+            }
+            // 32 bits
+            5 => 4,
+            wire_type => {
+                return Err(error::Format::DeserializationError(format!(
+                    "deserialization error: unexpected wire type {}",
+                    wire_type
+                )))
+            }
+        };
+
+        // the length is read from the input, the addition must not overflow
+        let size = (buf.len() as u64).checked_add(length);
+        if !matches!(size, Some(size) if size <= limits.max_size as u64) {
+            return Err(error::Format::DeserializationError(format!(
+                "deserialization error: the token is larger than {} bytes",
+                limits.max_size
+            )));
+        }
+
+        let read = reader
+            .by_ref()
+            .take(length)
+            .read_to_end(&mut buf)
+            .map_err(io_error)?;
+        if (read as u64) < length {
+            return Err(truncated());
+        }
+    }
+}
+
+/// reads a varint and appends its bytes to `buf`, returns `None` at the end
+/// of the input
+fn read_varint<R: Read>(reader: &mut R, buf: &mut Vec<u8>) -> Result<Option<u64>, error::Format> {
+    let mut value = 0u64;
+    for i in 0..10 {
+        let mut byte = [0u8];
+        match reader.read_exact(&mut byte) {
+            Ok(()) => buf.push(byte[0]),
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                return if i == 0 { Ok(None) } else { Err(truncated()) };
+            }
+            Err(e) => return Err(io_error(e)),
+        }
+
+        value |= u64::from(byte[0] & 0x7f) << (7 * i);
+        if byte[0] & 0x80 == 0 {
+            return Ok(Some(value));
+        }
+    }
+
+    Err(error::Format::DeserializationError(
+        "deserialization error: invalid varint".to_string(),
+    ))
+}
+
+fn truncated() -> error::Format {
+    error::Format::DeserializationError(
+        "deserialization error: unexpected end of input".to_string(),
+    )
+}
+
+fn io_error(e: io::Error) -> error::Format {
+    error::Format::DeserializationError(format!("deserialization error: {}", e))
+}
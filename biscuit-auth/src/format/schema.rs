@@ -8,11 +8,14 @@ pub struct Biscuit {
     pub blocks: ::prost::alloc::vec::Vec<SignedBlock>,
     #[prost(message, required, tag="4")]
     pub proof: Proof,
+    /// extension of the specification by this library
+    #[prost(message, repeated, tag="1000")]
+    pub counter_signatures: ::prost::alloc::vec::Vec<CounterSignature>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct SignedBlock {
-    #[prost(bytes="vec", required, tag="1")]
-    pub block: ::prost::alloc::vec::Vec<u8>,
+    #[prost(bytes="bytes", required, tag="1")]
+    pub block: ::prost::bytes::Bytes,
     #[prost(message, required, tag="2")]
     pub next_key: PublicKey,
     #[prost(bytes="vec", required, tag="3")]
@@ -40,6 +43,7 @@ pub mod public_key {
     #[repr(i32)]
     pub enum Algorithm {
         Ed25519 = 0,
+        P256 = 1,
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -58,6 +62,15 @@ pub mod proof {
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CounterSignature {
+    #[prost(message, required, tag="1")]
+    pub public_key: PublicKey,
+    #[prost(uint64, required, tag="2")]
+    pub timestamp: u64,
+    #[prost(bytes="vec", required, tag="3")]
+    pub signature: ::prost::alloc::vec::Vec<u8>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Block {
     #[prost(string, repeated, tag="1")]
     pub symbols: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
@@ -75,6 +88,10 @@ pub struct Block {
     pub scope: ::prost::alloc::vec::Vec<Scope>,
     #[prost(message, repeated, tag="8")]
     pub public_keys: ::prost::alloc::vec::Vec<PublicKey>,
+    /// numbers from 1000 are extensions of the specification by this library,
+    /// used in blocks with a version from 1001
+    #[prost(uint32, optional, tag="1000")]
+    pub application_symbols: ::core::option::Option<u32>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct Scope {
@@ -112,6 +129,26 @@ pub struct RuleV2 {
     pub expressions: ::prost::alloc::vec::Vec<ExpressionV2>,
     #[prost(message, repeated, tag="4")]
     pub scope: ::prost::alloc::vec::Vec<Scope>,
+    #[prost(message, repeated, tag="1000")]
+    pub aggregates: ::prost::alloc::vec::Vec<RuleAggregate>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct RuleAggregate {
+    #[prost(uint32, required, tag="1")]
+    pub position: u32,
+    #[prost(enumeration="rule_aggregate::Kind", required, tag="2")]
+    pub kind: i32,
+}
+/// Nested message and enum types in `RuleAggregate`.
+pub mod rule_aggregate {
+    #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord, ::prost::Enumeration)]
+    #[repr(i32)]
+    pub enum Kind {
+        Count = 0,
+        Sum = 1,
+        Min = 2,
+        Max = 3,
+    }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct CheckV2 {
@@ -119,6 +156,9 @@ pub struct CheckV2 {
     pub queries: ::prost::alloc::vec::Vec<RuleV2>,
     #[prost(enumeration="check_v2::Kind", optional, tag="2")]
     pub kind: ::core::option::Option<i32>,
+    /// extension of the specification by this library
+    #[prost(string, optional, tag="1000")]
+    pub message: ::core::option::Option<::prost::alloc::string::String>,
 }
 /// Nested message and enum types in `CheckV2`.
 pub mod check_v2 {
@@ -127,6 +167,7 @@ pub mod check_v2 {
     pub enum Kind {
         One = 0,
         All = 1,
+        Reject = 2,
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -138,7 +179,7 @@ pub struct PredicateV2 {
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct TermV2 {
-    #[prost(oneof="term_v2::Content", tags="1, 2, 3, 4, 5, 6, 7")]
+    #[prost(oneof="term_v2::Content", tags="1, 2, 3, 4, 5, 6, 7, 1000, 1001")]
     pub content: ::core::option::Option<term_v2::Content>,
 }
 /// Nested message and enum types in `TermV2`.
@@ -159,6 +200,11 @@ pub mod term_v2 {
         Bool(bool),
         #[prost(message, tag="7")]
         Set(super::TermSet),
+        #[prost(message, tag="1000")]
+        Decimal(super::Decimal),
+        /// 16 bytes, big endian
+        #[prost(bytes, tag="1001")]
+        Uuid(::prost::alloc::vec::Vec<u8>),
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -167,6 +213,14 @@ pub struct TermSet {
     pub set: ::prost::alloc::vec::Vec<TermV2>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct Decimal {
+    /// 128 bits two's complement integer, little endian
+    #[prost(bytes="vec", required, tag="1")]
+    pub mantissa: ::prost::alloc::vec::Vec<u8>,
+    #[prost(uint32, required, tag="2")]
+    pub scale: u32,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ExpressionV2 {
     #[prost(message, repeated, tag="1")]
     pub ops: ::prost::alloc::vec::Vec<Op>,
@@ -201,6 +255,8 @@ pub mod op_unary {
         Negate = 0,
         Parens = 1,
         Length = 2,
+        Lowercase = 1000,
+        Trim = 1001,
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -234,6 +290,12 @@ pub mod op_binary {
         BitwiseOr = 18,
         BitwiseXor = 19,
         NotEqual = 20,
+        Difference = 1000,
+        SymmetricDifference = 1001,
+        IsSubset = 1002,
+        IsSuperset = 1003,
+        Split = 1004,
+        Capture = 1005,
     }
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
@@ -268,11 +330,33 @@ pub struct AuthorizerPolicies {
     pub policies: ::prost::alloc::vec::Vec<Policy>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
+pub struct CompiledPolicies {
+    #[prost(uint32, required, tag="1")]
+    pub version: u32,
+    #[prost(string, repeated, tag="2")]
+    pub symbols: ::prost::alloc::vec::Vec<::prost::alloc::string::String>,
+    #[prost(message, repeated, tag="3")]
+    pub public_keys: ::prost::alloc::vec::Vec<PublicKey>,
+    #[prost(message, repeated, tag="4")]
+    pub facts: ::prost::alloc::vec::Vec<FactV2>,
+    #[prost(message, repeated, tag="5")]
+    pub rules: ::prost::alloc::vec::Vec<RuleV2>,
+    #[prost(message, repeated, tag="6")]
+    pub checks: ::prost::alloc::vec::Vec<CheckV2>,
+    #[prost(message, repeated, tag="7")]
+    pub policies: ::prost::alloc::vec::Vec<Policy>,
+    #[prost(message, repeated, tag="8")]
+    pub scopes: ::prost::alloc::vec::Vec<Scope>,
+}
+#[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ThirdPartyBlockRequest {
     #[prost(message, required, tag="1")]
     pub previous_key: PublicKey,
     #[prost(message, repeated, tag="2")]
     pub public_keys: ::prost::alloc::vec::Vec<PublicKey>,
+    /// extension of the specification by this library
+    #[prost(uint32, optional, tag="1000")]
+    pub version: ::core::option::Option<u32>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct ThirdPartyBlockContents {
@@ -280,6 +364,9 @@ pub struct ThirdPartyBlockContents {
     pub payload: ::prost::alloc::vec::Vec<u8>,
     #[prost(message, required, tag="2")]
     pub external_signature: ExternalSignature,
+    /// extension of the specification by this library
+    #[prost(uint32, optional, tag="1000")]
+    pub version: ::core::option::Option<u32>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct AuthorizerSnapshot {
@@ -298,6 +385,15 @@ pub struct RunLimits {
     pub max_iterations: u64,
     #[prost(uint64, required, tag="3")]
     pub max_time: u64,
+    /// extension of the specification by this library
+    #[prost(uint64, optional, tag="1000")]
+    pub max_facts_per_block: ::core::option::Option<u64>,
+    /// extension of the specification by this library
+    #[prost(uint64, optional, tag="1001")]
+    pub max_iterations_per_block: ::core::option::Option<u64>,
+    /// extension of the specification by this library
+    #[prost(uint64, optional, tag="1002")]
+    pub max_results: ::core::option::Option<u64>,
 }
 #[derive(Clone, PartialEq, ::prost::Message)]
 pub struct AuthorizerWorld {
@@ -360,3 +456,20 @@ pub struct SnapshotBlock {
     #[prost(message, optional, tag="7")]
     pub external_key: ::core::option::Option<PublicKey>,
 }
+#[derive(Clone, PartialEq, ::prost::Message)]
+pub struct DecisionRecord {
+    #[prost(bytes="vec", optional, tag="1")]
+    pub token_hash: ::core::option::Option<::prost::alloc::vec::Vec<u8>>,
+    #[prost(message, required, tag="2")]
+    pub snapshot: AuthorizerSnapshot,
+    #[prost(uint64, optional, tag="3")]
+    pub policy: ::core::option::Option<u64>,
+    #[prost(string, optional, tag="4")]
+    pub error: ::core::option::Option<::prost::alloc::string::String>,
+    #[prost(uint64, required, tag="5")]
+    pub execution_time: u64,
+    #[prost(uint64, required, tag="6")]
+    pub iterations: u64,
+    #[prost(uint64, required, tag="7")]
+    pub facts: u64,
+}
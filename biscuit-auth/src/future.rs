@@ -0,0 +1,49 @@
+//! minimal executor-agnostic future helpers used by the async authorization path
+
+use futures_core::Stream;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+/// returns control to the executor once, then resumes
+///
+/// this does not depend on a specific runtime: the task's waker is notified
+/// immediately, so the executor will poll it again after running other tasks
+pub(crate) fn yield_now() -> YieldNow {
+    YieldNow { yielded: false }
+}
+
+pub(crate) struct YieldNow {
+    yielded: bool,
+}
+
+impl Future for YieldNow {
+    type Output = ();
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<()> {
+        if self.yielded {
+            Poll::Ready(())
+        } else {
+            self.yielded = true;
+            cx.waker().wake_by_ref();
+            Poll::Pending
+        }
+    }
+}
+
+/// returns the next item of a stream
+pub(crate) fn next<S: Stream + ?Sized>(stream: Pin<&mut S>) -> Next<'_, S> {
+    Next { stream }
+}
+
+pub(crate) struct Next<'a, S: ?Sized> {
+    stream: Pin<&'a mut S>,
+}
+
+impl<S: Stream + ?Sized> Future for Next<'_, S> {
+    type Output = Option<S::Item>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        self.stream.as_mut().poll_next(cx)
+    }
+}
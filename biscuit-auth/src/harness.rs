@@ -0,0 +1,354 @@
+//! cross platform test harness
+//!
+//! Runs the same token and policy scenarios on native and WebAssembly builds
+//! and compares the results, to detect divergences in time handling, random
+//! number generation or formatting between platforms.
+//!
+//! Scenarios are deterministic: keys are generated from a seeded RNG, and the
+//! time is provided by a [`FixedClock`]. Each platform runs the scenarios and
+//! produces a text report with [`to_report`], then the reports are parsed back
+//! with [`parse_report`] and compared with [`compare`]:
+//!
+//! ```rust
+//! use biscuit_auth::harness::{compare, default_scenarios, parse_report, run_all, to_report};
+//!
+//! let native = run_all(&default_scenarios());
+//! // this report would come from a wasm32 build
+//! let wasm = parse_report(&to_report(&native)).unwrap();
+//!
+//! assert!(compare(&native, &wasm).is_empty());
+//! ```
+use std::{
+    fmt,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use rand::{rngs::StdRng, SeedableRng};
+
+use crate::{error, AuthorizerLimits, Biscuit, FixedClock, KeyPair};
+
+/// token and authorizer to run on each platform
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Scenario {
+    pub name: String,
+    /// seed of the RNG used to generate the root key and the block keys
+    pub seed: u64,
+    /// Datalog code of the authority block, then of each attenuation block
+    pub blocks: Vec<String>,
+    /// Datalog code of the authorizer
+    pub authorizer: String,
+    /// current time, as seconds since the Unix epoch, provided with `Authorizer::set_time`
+    pub time: u64,
+}
+
+/// result of a scenario
+///
+/// values are normalized on a single line
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Outcome {
+    pub scenario: String,
+    /// base64 serialized token, or the error returned when creating it
+    pub token: String,
+    /// authorization result, as `Ok(policy index)` or the error
+    pub decision: String,
+    /// facts of the authorizer after authorization, sorted
+    pub facts: Vec<String>,
+}
+
+/// difference between the outcomes of a scenario on two platforms
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Divergence {
+    pub scenario: String,
+    /// name of the diverging value: `scenario`, `token`, `decision` or `facts`
+    pub field: &'static str,
+    pub expected: Option<String>,
+    pub actual: Option<String>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "scenario `{}`: {} differs, expected {:?}, got {:?}",
+            self.scenario, self.field, self.expected, self.actual
+        )
+    }
+}
+
+/// scenarios covering the platform dependent parts of token creation and authorization
+pub fn default_scenarios() -> Vec<Scenario> {
+    let scenario = |name: &str, blocks: &[&str], authorizer: &str| Scenario {
+        name: name.to_string(),
+        seed: 0,
+        blocks: blocks.iter().map(|s| s.to_string()).collect(),
+        authorizer: authorizer.to_string(),
+        time: 1_700_000_000,
+    };
+
+    vec![
+        scenario(
+            "expiration",
+            &[
+                r#"user("alice"); check if time($t), $t <= 2023-11-14T22:13:20Z;"#,
+                "check if time($t), $t <= 2023-11-14T22:13:19Z;",
+            ],
+            "allow if user($u);",
+        ),
+        scenario(
+            "dates",
+            &["date(1970-01-01T00:00:00Z); date(9999-12-31T23:59:59Z);"],
+            "yesterday($d) <- time($t), date($d), $d < $t; allow if yesterday($d);",
+        ),
+        Scenario {
+            seed: 42,
+            ..scenario(
+                "keys",
+                &["right(\"file1\", \"read\");", "check if operation(\"read\");"],
+                "operation(\"read\"); allow if right($f, $o), operation($o);",
+            )
+        },
+        scenario(
+            "integers",
+            &["value(9223372036854775807);"],
+            "check if value($v), $v + 1 > $v; check if -9223372036854775807 - 1 < 0; allow if true;",
+        ),
+        scenario(
+            "strings",
+            &[r#"name("José"); name("東京"); name("");"#],
+            r#"check if name($n), $n.length() < 10; upper($n) <- name($n), $n.matches("^[A-Z]"); allow if upper($n);"#,
+        ),
+        scenario(
+            "sets",
+            &["allowed([\"read\", \"write\"]); keys([hex:00ff, hex:0100]);"],
+            "operation(\"read\"); check if keys($k), $k.contains(hex:00ff); allow if allowed($a), operation($o), $a.contains($o);",
+        ),
+    ]
+}
+
+/// runs a scenario
+pub fn run(scenario: &Scenario) -> Outcome {
+    let mut outcome = Outcome {
+        scenario: normalize(&scenario.name),
+        ..Default::default()
+    };
+
+    let token = match build_token(scenario) {
+        Ok(token) => token,
+        Err(e) => {
+            outcome.token = normalize(&format!("{:?}", e));
+            return outcome;
+        }
+    };
+    outcome.token = match token.to_base64() {
+        Ok(token) => token,
+        Err(e) => normalize(&format!("{:?}", e)),
+    };
+
+    let mut authorizer = match token.authorizer() {
+        Ok(authorizer) => authorizer,
+        Err(e) => {
+            outcome.decision = normalize(&format!("{:?}", Err::<usize, _>(e)));
+            return outcome;
+        }
+    };
+    authorizer.set_clock(FixedClock(UNIX_EPOCH + Duration::from_secs(scenario.time)));
+    authorizer.set_time();
+
+    let result = authorizer.add_code(&scenario.authorizer).and_then(|()| {
+        authorizer.authorize_with_limits(AuthorizerLimits {
+            max_time: Duration::from_secs(1),
+            ..Default::default()
+        })
+    });
+    outcome.decision = normalize(&format!("{:?}", result));
+
+    let mut facts: Vec<String> = authorizer
+        .dump()
+        .0
+        .iter()
+        .map(|fact| normalize(&fact.to_string()))
+        .collect();
+    facts.sort();
+    outcome.facts = facts;
+
+    outcome
+}
+
+/// runs a list of scenarios
+pub fn run_all(scenarios: &[Scenario]) -> Vec<Outcome> {
+    scenarios.iter().map(run).collect()
+}
+
+fn build_token(scenario: &Scenario) -> Result<Biscuit, error::Token> {
+    let mut rng = StdRng::seed_from_u64(scenario.seed);
+    let root = KeyPair::new_with_rng(&mut rng);
+    Biscuit::from_blocks_code(&root, &scenario.blocks, &mut rng)
+}
+
+fn normalize(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+/// serializes outcomes to a text report
+pub fn to_report(outcomes: &[Outcome]) -> String {
+    let mut report = String::new();
+    for outcome in outcomes {
+        report.push_str(&format!("scenario: {}\n", outcome.scenario));
+        report.push_str(&format!("token: {}\n", outcome.token));
+        report.push_str(&format!("decision: {}\n", outcome.decision));
+        for fact in &outcome.facts {
+            report.push_str(&format!("fact: {}\n", fact));
+        }
+        report.push('\n');
+    }
+    report
+}
+
+/// parses a text report created by [`to_report`]
+pub fn parse_report(report: &str) -> Result<Vec<Outcome>, String> {
+    let mut outcomes: Vec<Outcome> = Vec::new();
+
+    for (i, line) in report.lines().enumerate() {
+        if line.is_empty() {
+            continue;
+        }
+
+        let (key, value) = line
+            .split_once(": ")
+            .ok_or_else(|| format!("invalid line {}: {:?}", i + 1, line))?;
+        let value = value.to_string();
+
+        if key == "scenario" {
+            outcomes.push(Outcome {
+                scenario: value,
+                ..Default::default()
+            });
+            continue;
+        }
+
+        let outcome = outcomes
+            .last_mut()
+            .ok_or_else(|| format!("line {} is not in a scenario", i + 1))?;
+        match key {
+            "token" => outcome.token = value,
+            "decision" => outcome.decision = value,
+            "fact" => outcome.facts.push(value),
+            _ => return Err(format!("unknown key `{}` on line {}", key, i + 1)),
+        }
+    }
+
+    Ok(outcomes)
+}
+
+/// compares outcomes from two platforms, matching scenarios by name
+pub fn compare(expected: &[Outcome], actual: &[Outcome]) -> Vec<Divergence> {
+    let mut divergences = Vec::new();
+
+    for e in expected {
+        let a = match actual.iter().find(|a| a.scenario == e.scenario) {
+            Some(a) => a,
+            None => {
+                divergences.push(Divergence {
+                    scenario: e.scenario.clone(),
+                    field: "scenario",
+                    expected: Some(e.scenario.clone()),
+                    actual: None,
+                });
+                continue;
+            }
+        };
+
+        let fields = [
+            ("token", &e.token, &a.token),
+            ("decision", &e.decision, &a.decision),
+        ];
+        for (field, expected, actual) in fields.iter() {
+            if expected != actual {
+                divergences.push(Divergence {
+                    scenario: e.scenario.clone(),
+                    field,
+                    expected: Some(expected.to_string()),
+                    actual: Some(actual.to_string()),
+                });
+            }
+        }
+
+        if e.facts != a.facts {
+            divergences.push(Divergence {
+                scenario: e.scenario.clone(),
+                field: "facts",
+                expected: Some(e.facts.join("; ")),
+                actual: Some(a.facts.join("; ")),
+            });
+        }
+    }
+
+    for a in actual {
+        if !expected.iter().any(|e| e.scenario == a.scenario) {
+            divergences.push(Divergence {
+                scenario: a.scenario.clone(),
+                field: "scenario",
+                expected: None,
+                actual: Some(a.scenario.clone()),
+            });
+        }
+    }
+
+    divergences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn harness() {
+        let outcomes = run_all(&default_scenarios());
+
+        let decisions: Vec<_> = outcomes
+            .iter()
+            .map(|o| (o.scenario.as_str(), o.decision.starts_with("Ok(")))
+            .collect();
+        assert_eq!(
+            decisions,
+            vec![
+                ("expiration", false),
+                ("dates", true),
+                ("keys", true),
+                ("integers", false),
+                ("strings", true),
+                ("sets", true),
+            ]
+        );
+        assert!(outcomes[1]
+            .facts
+            .contains(&"date(9999-12-31T23:59:59Z)".to_string()));
+
+        // scenarios are deterministic
+        assert_eq!(outcomes, run_all(&default_scenarios()));
+        assert_ne!(outcomes[0].token, outcomes[2].token);
+
+        let parsed = parse_report(&to_report(&outcomes)).unwrap();
+        assert_eq!(parsed, outcomes);
+        assert!(compare(&outcomes, &parsed).is_empty());
+
+        let mut diverging = parsed;
+        diverging[1].facts.pop();
+        diverging[3].decision = "Ok(0)".to_string();
+        diverging.remove(4);
+        let divergences: Vec<_> = compare(&outcomes, &diverging)
+            .into_iter()
+            .map(|d| (d.scenario, d.field))
+            .collect();
+        assert_eq!(
+            divergences,
+            vec![
+                ("dates".to_string(), "facts"),
+                ("integers".to_string(), "decision"),
+                ("strings".to_string(), "scenario"),
+            ]
+        );
+
+        assert!(parse_report("token: abc").is_err());
+    }
+}
@@ -218,6 +218,25 @@
 //!
 //! biscuit implementations come with a default symbol table to avoid transmitting
 //! frequent values with every token.
+//!
+//! ## WebAssembly
+//!
+//! Deserializing, verifying and authorizing tokens do not need any randomness,
+//! and read the time through the authorizer's [`Clock`]. On
+//! `wasm32-unknown-unknown`, the `wasm` feature reads it from the JavaScript
+//! `Date` and `performance` APIs. Hosts without them, like some edge runtimes,
+//! must instead provide a custom `Clock` with [`Authorizer::set_clock`], where
+//! [`Clock::instant`] builds monotonic time with `Instant::from_micros`: the
+//! default clock cannot read the date there, and its execution time limit
+//! always fails with [`error::RunLimit::Timeout`].
+//!
+//! Generating keys and signing blocks need a source of randomness:
+//! [`KeyPair::new`], [`Biscuit::append`] and similar methods use the operating
+//! system's generator through the `getrandom` crate, while their `_with_rng`
+//! variants accept any [`rand_core::CryptoRng`]. The `wasm` feature selects the
+//! JavaScript backend of `getrandom`. On other targets where it has no default
+//! backend, an application can register its own entropy source with
+//! `getrandom`'s `custom` feature.
 
 mod crypto;
 pub mod datalog;
@@ -226,14 +245,32 @@ pub mod format;
 pub mod parser;
 mod token;
 
-pub use crypto::{KeyPair, PrivateKey, PublicKey};
-pub use token::authorizer::{Authorizer, AuthorizerLimits};
+pub use crypto::{KeyPair, PrivateKey, PublicKey, SecurityPolicy, Signature, Signer};
+pub use format::TokenSizeLimits;
+pub use time::{Clock, FakeClock, FixedClock, Instant, SystemClock};
+pub use token::authorizer::{
+    read_journal, AuthorizationMetrics, AuthorizationReport, Authorizer, AuthorizerBuilder,
+    AuthorizerLimits, AuthorizerObserver, AuthorizerPool, AuthorizerProfile, CheckProfile,
+    CheckReport, CheckStatus, CompiledPolicies, DebugIteration, Debugger, DecisionJournal,
+    FrozenAuthorizer, JournalEntry, JournalSink, LimitsReport, MemoryJournal, PolicyReport,
+    PolicyStore, PolicyVersion, PoolStats, PooledAuthorizer, RuleProfile, ScopeWarning,
+    SnapshotDiff, Unauthenticated, WithToken, WriterJournal,
+};
+#[cfg(feature = "decision-cache")]
+pub use token::authorizer::{DecisionCache, DecisionCacheStats};
 pub use token::builder;
 pub use token::builder_ext;
 pub use token::unverified::UnverifiedBiscuit;
 pub use token::Biscuit;
+pub use token::CounterSignature;
+#[cfg(feature = "revocation-file")]
+pub use token::FileRevocationStore;
+pub use token::PossessionProof;
 pub use token::RootKeyProvider;
-pub use token::{ThirdPartyBlock, ThirdPartyRequest};
+pub use token::SealedBiscuit;
+pub use token::VerifiedRootKey;
+pub use token::{BloomRevocationChecker, MemoryRevocationStore, RevocationStore};
+pub use token::{ThirdPartyBlock, ThirdPartyPipeline, ThirdPartyRequest, ThirdPartyRestrictions};
 
 #[cfg(cargo_c)]
 mod capi;
@@ -248,7 +285,30 @@ pub use bwk::*;
 
 mod time;
 
+#[cfg(feature = "async")]
+mod future;
+
 /// Procedural macros to construct Datalog policies
 #[cfg(feature = "datalog-macro")]
 #[cfg_attr(feature = "docsrs", doc(cfg(feature = "datalog-macro")))]
 pub mod macros;
+
+#[cfg(feature = "rego")]
+#[cfg_attr(feature = "docsrs", doc(cfg(feature = "rego")))]
+pub mod rego;
+
+#[cfg(feature = "harness")]
+#[cfg_attr(feature = "docsrs", doc(cfg(feature = "harness")))]
+pub mod harness;
+
+#[cfg(feature = "testing")]
+#[cfg_attr(feature = "docsrs", doc(cfg(feature = "testing")))]
+pub mod testing;
+
+#[cfg(feature = "bench")]
+#[cfg_attr(feature = "docsrs", doc(cfg(feature = "bench")))]
+pub mod bench;
+
+#[cfg(feature = "trust-config")]
+#[cfg_attr(feature = "docsrs", doc(cfg(feature = "trust-config")))]
+pub mod trust;
@@ -179,6 +179,168 @@ pub use biscuit_quote::block;
 /// ```
 pub use biscuit_quote::block_merge;
 
+/// Create an `Authorizer` from a datalog file and optional parameters.
+/// The path is relative to the directory containing the crate's `Cargo.toml`.
+/// The file is read and parsed at compile time, and the crate is rebuilt
+/// when it changes.
+///
+/// ```rust
+/// use biscuit_auth::macros::authorizer_from_file;
+/// use std::time::SystemTime;
+///
+/// let a = authorizer_from_file!(
+///   "tests/policies/authorizer.datalog",
+///   now = SystemTime::now(),
+///   resource = "file1",
+///   operation = "read",
+/// );
+/// ```
+pub use biscuit_quote::authorizer_from_file;
+
+/// Create a `BiscuitBuilder` from a datalog file and optional parameters.
+/// The path is relative to the directory containing the crate's `Cargo.toml`.
+///
+/// ```rust
+/// use biscuit_auth::KeyPair;
+/// use biscuit_auth::macros::biscuit_from_file;
+///
+/// let root = KeyPair::new();
+/// let biscuit = biscuit_from_file!("tests/policies/biscuit.datalog", user_id = "1234")
+///     .build(&root);
+/// ```
+pub use biscuit_quote::biscuit_from_file;
+
+/// Create a `BlockBuilder` from a datalog file and optional parameters.
+/// The path is relative to the directory containing the crate's `Cargo.toml`.
+///
+/// ```rust
+/// use biscuit_auth::macros::block_from_file;
+///
+/// let b = block_from_file!("tests/policies/block.datalog");
+/// ```
+pub use biscuit_quote::block_from_file;
+
+/// Create an `Authorizer` from a datalog string containing runtime values
+/// that are not terms, like predicate names.
+///
+/// `{name}` is a parameter, bound to a term as with [`authorizer!`], while
+/// `{name:ident}` is replaced by the value of `name`, converted with `Display`.
+/// The datalog string is checked at compile time with a placeholder name in
+/// place of each `{name:ident}`, then the interpolated string is parsed at runtime.
+/// An interpolated value can only contain letters, digits, `_` and `:`, so it
+/// cannot add rules or policies to the authorizer.
+///
+/// Since parsing happens at runtime, this returns a `Result`.
+///
+/// ```rust
+/// use biscuit_auth::macros::authorizer_dyn;
+///
+/// let relation = "editor";
+/// let mut a = authorizer_dyn!(
+///   r#"
+///     {relation:ident}("alice", {document});
+///     allow if {relation:ident}($user, {document});
+///   "#,
+///   document = "doc1",
+/// ).unwrap();
+/// assert_eq!(a.authorize(), Ok(0));
+///
+/// let relation = "editor(\"alice\", \"doc1\"); allow if true; x";
+/// assert!(authorizer_dyn!(r#"allow if {relation:ident}("bob")"#).is_err());
+/// ```
+///
+/// The rest of the datalog string must be valid:
+///
+/// ```compile_fail
+/// use biscuit_auth::macros::authorizer_dyn;
+///
+/// let relation = "editor";
+/// let a = authorizer_dyn!(r#"allow if {relation:ident}($user"#);
+/// ```
+pub use biscuit_quote::authorizer_dyn;
+
+/// Create a `BiscuitBuilder` from a datalog string containing runtime values
+/// that are not terms, see [`authorizer_dyn!`].
+///
+/// ```rust
+/// use biscuit_auth::KeyPair;
+/// use biscuit_auth::macros::biscuit_dyn;
+///
+/// let root = KeyPair::new();
+/// let role = "admin";
+/// let biscuit = biscuit_dyn!(r#"{role:ident}({user_id});"#, user_id = "1234")
+///     .unwrap()
+///     .build(&root);
+/// ```
+pub use biscuit_quote::biscuit_dyn;
+
+/// Create a `BlockBuilder` from a datalog string containing runtime values
+/// that are not terms, see [`authorizer_dyn!`].
+///
+/// ```rust
+/// use biscuit_auth::macros::block_dyn;
+///
+/// let service = "billing";
+/// let b = block_dyn!(r#"check if {service:ident}_operation({operation})"#, operation = "read")
+///     .unwrap();
+/// ```
+pub use biscuit_quote::block_dyn;
+
+/// Evaluate a static authorizer at compile time and fail the build if the
+/// result of the authorization is not the expected one. This checks policy
+/// changes without writing runtime tests, and generates no code.
+///
+/// The facts and rules that would come from a token are given with
+/// `facts = "..."`. The expected result is one of:
+/// - `allow` or `allow(policy)`: no check failed and an allow policy matched
+/// - `deny` or `deny(policy)`: the authorization fails, or that deny policy matched
+/// - `no_match`: no policy matched
+/// - `failed_checks(check, ...)`: exactly these checks failed
+///
+/// Policies and checks are designated by their index in the authorizer.
+/// Since there is no token, scopes are ignored. Parameters, regular
+/// expressions, decimals and aggregations are not supported.
+///
+/// ```rust
+/// use biscuit_auth::macros::authorizer_assert;
+///
+/// authorizer_assert!(
+///   r#"
+///     check if operation("read");
+///     allow if right($resource, "read"), resource($resource);
+///     deny if true;
+///   "#,
+///   facts = r#"resource("file1"); operation("read"); right("file1", "read");"#,
+///   expect = allow(0),
+/// );
+///
+/// authorizer_assert!(
+///   r#"
+///     check if operation("read");
+///     allow if right($resource, "read"), resource($resource);
+///     deny if true;
+///   "#,
+///   facts = r#"resource("file2"); operation("write");"#,
+///   expect = failed_checks(0),
+/// );
+/// ```
+///
+/// A policy change that modifies the result does not compile:
+///
+/// ```compile_fail
+/// use biscuit_auth::macros::authorizer_assert;
+///
+/// authorizer_assert!(
+///   r#"
+///     allow if user($user), admin($user);
+///     deny if true;
+///   "#,
+///   facts = r#"user("alice");"#,
+///   expect = allow,
+/// );
+/// ```
+pub use biscuit_quote::authorizer_assert;
+
 /// Create a `Rule` from a datalog string and optional parameters.
 /// The datalog string is parsed at compile time and replaced by manual
 /// builder calls.
@@ -239,3 +401,276 @@ pub use biscuit_quote::check;
 /// );
 /// ```
 pub use biscuit_quote::policy;
+
+/// Derive conversions between a struct and a [`Fact`](crate::builder::Fact).
+///
+/// The fields are mapped, in order, to the terms of the fact. The predicate
+/// name is the struct name in snake case, unless set with `#[biscuit(name = "...")]`.
+/// Conversion from a fact fails if the name or the number of terms do not match.
+///
+/// The generated `query_rule()` function returns a rule querying these facts,
+/// like `user($name, $id) <- user($name, $id)`, for typed query results.
+///
+/// ```rust
+/// use biscuit_auth::{Biscuit, KeyPair};
+/// use biscuit_auth::macros::BiscuitFact;
+///
+/// #[derive(BiscuitFact, Debug, PartialEq)]
+/// #[biscuit(name = "user")]
+/// struct User {
+///     name: String,
+///     id: i64,
+/// }
+///
+/// let root = KeyPair::new();
+/// let mut builder = Biscuit::builder();
+/// builder.add_fact(User { name: "John Doe".to_string(), id: 42 }).unwrap();
+/// let biscuit = builder.build(&root).unwrap();
+///
+/// let mut authorizer = biscuit.authorizer().unwrap();
+/// let users: Vec<User> = authorizer.query(User::query_rule()).unwrap();
+/// assert_eq!(users, vec![User { name: "John Doe".to_string(), id: 42 }]);
+/// ```
+pub use biscuit_quote::BiscuitFact;
+
+/// Derive a conversion from a query result to a struct.
+///
+/// The terms of the fact are mapped, in order, to the fields, whatever the
+/// predicate name, so that the struct can be used with
+/// [`Authorizer::query`](crate::Authorizer::query). Conversion fails if the
+/// number of terms does not match.
+///
+/// The query can be declared with `#[from_fact(query = "...")]`. It is parsed at
+/// compile time, checked to return one term per field, and returned by the
+/// generated `query_rule()` function.
+///
+/// ```rust
+/// use biscuit_auth::{Biscuit, KeyPair};
+/// use biscuit_auth::macros::FromFact;
+///
+/// #[derive(FromFact, Debug, PartialEq)]
+/// #[from_fact(query = "data($name, $id) <- user($name, $id)")]
+/// struct User {
+///     name: String,
+///     id: i64,
+/// }
+///
+/// let root = KeyPair::new();
+/// let mut builder = Biscuit::builder();
+/// builder.add_fact(r#"user("John Doe", 42)"#).unwrap();
+/// let biscuit = builder.build(&root).unwrap();
+///
+/// let mut authorizer = biscuit.authorizer().unwrap();
+/// let users: Vec<User> = authorizer.query(User::query_rule()).unwrap();
+/// assert_eq!(users, vec![User { name: "John Doe".to_string(), id: 42 }]);
+/// ```
+///
+/// A query returning the wrong number of terms does not compile:
+///
+/// ```compile_fail
+/// use biscuit_auth::macros::FromFact;
+///
+/// #[derive(FromFact)]
+/// #[from_fact(query = "data($name) <- user($name, $id)")]
+/// struct User {
+///     name: String,
+///     id: i64,
+/// }
+/// ```
+pub use biscuit_quote::FromFact;
+
+/// Declare the ambient facts an authorizer expects for each request.
+///
+/// This creates a struct with one public field per fact, and an
+/// `apply(&self, &mut Authorizer)` method adding a fact named after each
+/// field, or `apply_to_builder` to add them to an
+/// [`AuthorizerBuilder`](crate::AuthorizerBuilder). Forgetting to provide one
+/// of the facts is then a compile time error.
+/// Field types must be cloneable and convertible to a [`Term`](crate::builder::Term).
+///
+/// ```rust
+/// use biscuit_auth::{AuthorizerBuilder, KeyPair, macros::{ambient, biscuit}};
+/// use std::time::SystemTime;
+///
+/// ambient! {
+///     pub struct RequestContext {
+///         time: SystemTime,
+///         method: String,
+///         path: String,
+///     }
+/// }
+///
+/// let root = KeyPair::new();
+/// let biscuit = biscuit!(r#"check if method("GET");"#).build(&root).unwrap();
+///
+/// let context = RequestContext {
+///     time: SystemTime::now(),
+///     method: "GET".to_string(),
+///     path: "/articles".to_string(),
+/// };
+///
+/// let mut authorizer = biscuit.authorizer().unwrap();
+/// context.apply(&mut authorizer).unwrap();
+/// authorizer.add_code("allow if path($path), $path.starts_with(\"/articles\")").unwrap();
+/// authorizer.authorize().unwrap();
+///
+/// let mut authorizer = context
+///     .apply_to_builder(AuthorizerBuilder::new())
+///     .unwrap()
+///     .code("allow if method(\"GET\")")
+///     .unwrap()
+///     .token(&biscuit)
+///     .unwrap()
+///     .build();
+/// authorizer.authorize().unwrap();
+/// ```
+pub use crate::ambient;
+
+#[doc(hidden)]
+#[macro_export]
+macro_rules! ambient {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$field_meta:meta])* $field:ident : $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        $vis struct $name {
+            $($(#[$field_meta])* pub $field: $ty,)*
+        }
+
+        impl $name {
+            /// adds the ambient facts to the authorizer
+            pub fn apply(
+                &self,
+                authorizer: &mut $crate::Authorizer,
+            ) -> ::std::result::Result<(), $crate::error::Token> {
+                $(
+                    authorizer.add_fact($crate::builder::fact(
+                        stringify!($field),
+                        &[$crate::builder::Term::from(::std::clone::Clone::clone(&self.$field))],
+                    ))?;
+                )*
+                Ok(())
+            }
+
+            /// adds the ambient facts to the authorizer builder
+            pub fn apply_to_builder<State>(
+                &self,
+                builder: $crate::AuthorizerBuilder<State>,
+            ) -> ::std::result::Result<$crate::AuthorizerBuilder<State>, $crate::error::Token> {
+                $(
+                    let builder = builder.fact($crate::builder::fact(
+                        stringify!($field),
+                        &[$crate::builder::Term::from(::std::clone::Clone::clone(&self.$field))],
+                    ))?;
+                )*
+                Ok(builder)
+            }
+        }
+    };
+}
+
+/// support code for the `*_dyn!` macros
+#[doc(hidden)]
+pub mod __dyn {
+    use std::collections::HashMap;
+    use std::fmt;
+
+    use biscuit_parser::error::{LanguageError, ParseError, ParseErrors};
+
+    use crate::builder::{AnyParam, BiscuitBuilder, BlockBuilder, Term};
+    use crate::{error, Authorizer, PublicKey};
+
+    pub enum Segment<'a> {
+        Source(&'static str),
+        Fragment(&'static str, &'a dyn fmt::Display),
+    }
+
+    pub trait Target {
+        fn add_code_with_params(
+            &mut self,
+            source: &str,
+            params: HashMap<String, Term>,
+            scope_params: HashMap<String, PublicKey>,
+        ) -> Result<(), error::Token>;
+    }
+
+    macro_rules! target {
+        ($ty:ty) => {
+            impl Target for $ty {
+                fn add_code_with_params(
+                    &mut self,
+                    source: &str,
+                    params: HashMap<String, Term>,
+                    scope_params: HashMap<String, PublicKey>,
+                ) -> Result<(), error::Token> {
+                    <$ty>::add_code_with_params(self, source, params, scope_params)
+                }
+            }
+        };
+    }
+
+    target!(BlockBuilder);
+    target!(BiscuitBuilder);
+    target!(Authorizer);
+
+    pub fn build<T: Target>(
+        mut target: T,
+        segments: &[Segment<'_>],
+        params: Vec<(&str, AnyParam)>,
+        scope_params: Vec<(&str, AnyParam)>,
+    ) -> Result<T, error::Token> {
+        let mut source = String::new();
+        for segment in segments {
+            match segment {
+                Segment::Source(s) => source.push_str(s),
+                Segment::Fragment(name, value) => {
+                    let value = value.to_string();
+                    if value.is_empty()
+                        || !value
+                            .chars()
+                            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == ':')
+                    {
+                        return Err(LanguageError::ParseError(ParseErrors {
+                            errors: vec![ParseError::new(
+                                value,
+                                Some(format!("`{name}` must be a name")),
+                            )],
+                        })
+                        .into());
+                    }
+                    source.push_str(&value);
+                }
+            }
+        }
+
+        let params = params
+            .into_iter()
+            .filter_map(|(name, param)| match param {
+                AnyParam::Term(term) => Some((name.to_string(), term)),
+                AnyParam::PublicKey(_) => None,
+            })
+            .collect();
+        let scope_params = scope_params
+            .into_iter()
+            .map(|(name, param)| {
+                let key = match param {
+                    AnyParam::PublicKey(key) => key,
+                    // like in the other macros, a string is parsed as a public key
+                    AnyParam::Term(Term::Str(key)) => key.parse()?,
+                    AnyParam::Term(_) => {
+                        return Err(error::Token::ConversionError(format!(
+                            "`{name}` must be a public key"
+                        )))
+                    }
+                };
+                Ok((name.to_string(), key))
+            })
+            .collect::<Result<_, error::Token>>()?;
+
+        target.add_code_with_params(&source, params, scope_params)?;
+        Ok(target)
+    }
+}
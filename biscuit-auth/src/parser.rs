@@ -14,6 +14,7 @@
 //! and [BlockBuilder](`crate::token::builder::BlockBuilder`) can take strings
 //! as arguments too
 
+pub use biscuit_parser::lint::{lint, lint_source, Lint};
 pub use biscuit_parser::parser::*;
 
 #[cfg(test)]
@@ -25,6 +26,7 @@ mod tests {
     };
     use biscuit_parser::parser::*;
     use nom::error::ErrorKind;
+    use std::convert::TryInto;
 
     #[derive(Debug, PartialEq)]
     enum Expr {
@@ -78,7 +80,7 @@ mod tests {
             biscuit_parser::parser::rule(
                 "right($0, \"read\") <- resource( $0), operation(\"read\")"
             )
-            .map(|(i, o)| (i, o.into())),
+            .map(|(i, o)| (i, o.try_into().unwrap())),
             Ok((
                 "",
                 builder::rule(
@@ -99,7 +101,7 @@ mod tests {
         use std::time::{Duration, SystemTime};
 
         assert_eq!(
-            biscuit_parser::parser::rule("valid_date(\"file1\") <- time($0 ), resource(\"file1\"), $0 <= 2019-12-04T09:46:41+00:00").map(|(i, o)| (i, o.into())),
+            biscuit_parser::parser::rule("valid_date(\"file1\") <- time($0 ), resource(\"file1\"), $0 <= 2019-12-04T09:46:41+00:00").map(|(i, o)| (i, o.try_into().unwrap())),
             Ok((
                 "",
                 builder::constrained_rule(
@@ -129,7 +131,7 @@ mod tests {
         use std::time::{Duration, SystemTime};
 
         assert_eq!(
-            biscuit_parser::parser::rule("valid_date(\"file1\") <- time( $0 ), $0 <= 2019-12-04T09:46:41+00:00, resource(\"file1\")").map(|(i, o)| (i, o.into())),
+            biscuit_parser::parser::rule("valid_date(\"file1\") <- time( $0 ), $0 <= 2019-12-04T09:46:41+00:00, resource(\"file1\")").map(|(i, o)| (i, o.try_into().unwrap())),
             Ok((
                 "",
                 builder::constrained_rule(
@@ -184,7 +186,7 @@ mod tests {
             biscuit_parser::parser::check(
                 "check if resource( $0), operation(\"read\") or admin(\"authority\")"
             )
-            .map(|(i, o)| (i, o.into())),
+            .map(|(i, o)| (i, o.try_into().unwrap())),
             Ok((
                 "",
                 builder::Check {
@@ -204,6 +206,7 @@ mod tests {
                         ),
                     ],
                     kind: CheckKind::One,
+                    message: None,
                 }
             ))
         );
@@ -488,6 +491,7 @@ mod tests {
                     }],
                 )],
                 kind: CheckKind::One,
+                message: None,
             },
             Check {
                 queries: vec![
@@ -506,6 +510,7 @@ mod tests {
                     ),
                 ],
                 kind: CheckKind::One,
+                message: None,
             },
             Check {
                 queries: vec![constrained_rule(
@@ -525,6 +530,7 @@ mod tests {
                     }],
                 )],
                 kind: CheckKind::One,
+                message: None,
             },
         ];
 
@@ -563,7 +569,7 @@ mod tests {
             result
                 .rules
                 .drain(..)
-                .map(|(_, r)| r.into())
+                .map(|(_, r)| r.try_into().unwrap())
                 .collect::<Vec<builder::Rule>>(),
             expected_rules
         );
@@ -571,7 +577,7 @@ mod tests {
             result
                 .checks
                 .drain(..)
-                .map(|(_, r)| r.into())
+                .map(|(_, r)| r.try_into().unwrap())
                 .collect::<Vec<builder::Check>>(),
             expected_checks
         );
@@ -579,7 +585,7 @@ mod tests {
             result
                 .policies
                 .drain(..)
-                .map(|(_, r)| r.into())
+                .map(|(_, r)| r.try_into().unwrap())
                 .collect::<Vec<builder::Policy>>(),
             expected_policies
         );
@@ -646,6 +652,7 @@ mod tests {
                     }],
                 )],
                 kind: CheckKind::One,
+                message: None,
             },
             Check {
                 queries: vec![
@@ -664,6 +671,7 @@ mod tests {
                     ),
                 ],
                 kind: CheckKind::One,
+                message: None,
             },
             Check {
                 queries: vec![constrained_rule(
@@ -683,6 +691,7 @@ mod tests {
                     }],
                 )],
                 kind: CheckKind::One,
+                message: None,
             },
         ];
 
@@ -699,7 +708,7 @@ mod tests {
             result
                 .rules
                 .drain(..)
-                .map(|(_, r)| r.into())
+                .map(|(_, r)| r.try_into().unwrap())
                 .collect::<Vec<builder::Rule>>(),
             expected_rules
         );
@@ -707,7 +716,7 @@ mod tests {
             result
                 .checks
                 .drain(..)
-                .map(|(_, r)| r.into())
+                .map(|(_, r)| r.try_into().unwrap())
                 .collect::<Vec<builder::Check>>(),
             expected_checks
         );
@@ -0,0 +1,573 @@
+//! experimental conversion of a subset of Rego policies to Datalog
+//!
+//! This helps migrating from OPA: the converted code can be loaded in an
+//! [`Authorizer`](crate::Authorizer) with [`Authorizer::add_code`](crate::Authorizer::add_code),
+//! and the request data that was provided to OPA as `input` must be added as facts.
+//!
+//! The supported subset is:
+//! - `allow` and `deny` rules, converted to policies (deny policies come first,
+//!   since they have priority over allow rules in Rego)
+//! - `default allow = true`, converted to a final `allow if true` policy. Other
+//!   defaults are ignored, since the authorizer denies the request when no policy matches
+//! - other rules without arguments or values, like `is_admin { ... }`, converted
+//!   to Datalog rules producing `is_admin(true)`
+//! - comparisons between an `input` field and a string, integer or boolean:
+//!   `input.user.name == "alice"` is converted to `user_name("alice")`,
+//!   `input.roles[_] == "admin"` to `roles("admin")` and
+//!   `input.age >= 18` to `age($age), $age >= 18`
+//! - `startswith`, `endswith`, `contains` and `regex.match` on `input` fields
+//! - references to other rules of the same file, and `input` fields as booleans
+//!
+//! A rule containing anything else is not converted at all, and reported in
+//! [`Conversion::untranslated`]: dropping only the unsupported conditions
+//! would make the policy more permissive. Rules using such a rule are not
+//! fully converted either. If this applies to a `deny` rule, the converted
+//! code ends with `deny if true` and refuses all requests, until the
+//! untranslated rules are converted by hand.
+use std::collections::HashSet;
+use std::fmt::Write;
+
+/// result of the conversion of a Rego policy
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Conversion {
+    /// Datalog rules and policies
+    pub code: String,
+    /// the parts of the policy that could not be converted
+    pub untranslated: Vec<Untranslated>,
+}
+
+/// part of a Rego policy that could not be converted
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Untranslated {
+    /// line number, starting at 1
+    pub line: usize,
+    /// the Rego source of the statement or rule
+    pub source: String,
+    /// why it could not be converted
+    pub reason: String,
+}
+
+struct RegoRule {
+    line: usize,
+    head: String,
+    body: Vec<(usize, String)>,
+}
+
+/// converts a Rego policy to Datalog
+pub fn convert(source: &str) -> Conversion {
+    let mut conversion = Conversion::default();
+    let (rules, statements) = split_rules(source, &mut conversion.untranslated);
+
+    let mut default_allow = false;
+    for (line, statement) in statements {
+        let words: Vec<&str> = statement.split_whitespace().collect();
+        match words.as_slice() {
+            ["package", ..] | ["import", "input"] | ["import", "future.keywords", ..] => {}
+            ["default", "allow", "=" | ":=", "true"] => default_allow = true,
+            ["default", ..] => {}
+            _ => conversion.untranslated.push(Untranslated {
+                line,
+                source: statement,
+                reason: "unsupported statement".to_string(),
+            }),
+        }
+    }
+
+    let rule_names: HashSet<String> = rules.iter().filter_map(|r| rule_name(&r.head)).collect();
+
+    let mut datalog_rules = Vec::new();
+    let mut deny = Vec::new();
+    let mut allow = Vec::new();
+    // rules that could not be converted, or only partially
+    let mut dropped = HashSet::new();
+    // converted rules with the rules they reference: the name, the line and
+    // head of the rule, and the referenced rule names
+    let mut references = Vec::new();
+
+    for rule in rules {
+        let name = match rule_name(&rule.head) {
+            Some(name) => name,
+            None => {
+                // `deny[msg]` and other deny rules with values
+                if head_identifier(&rule.head) == "deny" {
+                    dropped.insert("deny".to_string());
+                }
+                conversion.untranslated.push(Untranslated {
+                    line: rule.line,
+                    source: rule.head,
+                    reason: "only rules without arguments or values are supported".to_string(),
+                });
+                continue;
+            }
+        };
+
+        let mut body = Vec::new();
+        let mut errors = Vec::new();
+        for (line, statement) in &rule.body {
+            match convert_statement(statement, &rule_names) {
+                Ok(converted) => body.push(converted),
+                Err(reason) => errors.push(Untranslated {
+                    line: *line,
+                    source: statement.clone(),
+                    reason,
+                }),
+            }
+        }
+
+        if !errors.is_empty() {
+            dropped.insert(name);
+            conversion.untranslated.push(Untranslated {
+                line: rule.line,
+                source: rule.head,
+                reason: "the rule contains statements that could not be converted".to_string(),
+            });
+            conversion.untranslated.extend(errors);
+            continue;
+        }
+
+        let body = if body.is_empty() {
+            "true".to_string()
+        } else {
+            body.join(", ")
+        };
+
+        match name.as_str() {
+            "allow" => allow.push(format!("allow if {}", body)),
+            "deny" => deny.push(format!("deny if {}", body)),
+            _ => datalog_rules.push(format!("{}(true) <- {}", name, body)),
+        }
+
+        let referenced: Vec<String> = rule
+            .body
+            .iter()
+            .map(|(_, statement)| statement)
+            .filter(|statement| rule_names.contains(*statement))
+            .cloned()
+            .collect();
+        references.push((name, rule.line, rule.head, referenced));
+    }
+
+    // a rule using a rule that was not fully converted matches less often
+    // than in Rego, so it is not fully converted either
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (name, line, head, referenced) in &references {
+            if !dropped.contains(name) && referenced.iter().any(|r| dropped.contains(r)) {
+                dropped.insert(name.clone());
+                conversion.untranslated.push(Untranslated {
+                    line: *line,
+                    source: head.clone(),
+                    reason: "the rule uses rules that could not be converted".to_string(),
+                });
+                changed = true;
+            }
+        }
+    }
+
+    // without all the deny rules, the converted policy would allow requests
+    // that the Rego policy denies, so it denies all requests instead
+    if dropped.contains("deny") {
+        deny.push("deny if true".to_string());
+    }
+
+    if default_allow {
+        allow.push("allow if true".to_string());
+    }
+
+    for line in datalog_rules.iter().chain(&deny).chain(&allow) {
+        let _ = writeln!(conversion.code, "{};", line);
+    }
+    conversion.untranslated.sort_by_key(|u| u.line);
+
+    conversion
+}
+
+/// splits the source in rules (`head { body }`) and top level statements
+fn split_rules(
+    source: &str,
+    untranslated: &mut Vec<Untranslated>,
+) -> (Vec<RegoRule>, Vec<(usize, String)>) {
+    let mut rules = Vec::new();
+    let mut statements = Vec::new();
+    let mut current: Option<RegoRule> = None;
+
+    for (i, line) in source.lines().enumerate() {
+        let line_number = i + 1;
+        let line = match line.find('#') {
+            Some(position) if !line[..position].contains('"') => &line[..position],
+            _ => line,
+        };
+
+        let mut rest = line.trim();
+        while !rest.is_empty() {
+            match current.as_mut() {
+                None => match rest.find('{') {
+                    Some(position) => {
+                        current = Some(RegoRule {
+                            line: line_number,
+                            head: rest[..position].trim().to_string(),
+                            body: Vec::new(),
+                        });
+                        rest = rest[position + 1..].trim();
+                    }
+                    None => {
+                        statements.push((line_number, rest.to_string()));
+                        rest = "";
+                    }
+                },
+                Some(rule) => {
+                    let (body, closed) = match rest.find('}') {
+                        Some(position) => (&rest[..position], Some(position)),
+                        None => (rest, None),
+                    };
+                    rule.body.extend(
+                        body.split(';')
+                            .map(str::trim)
+                            .filter(|s| !s.is_empty())
+                            .map(|s| (line_number, s.to_string())),
+                    );
+
+                    match closed {
+                        Some(position) => {
+                            rules.extend(current.take());
+                            rest = rest[position + 1..].trim();
+                        }
+                        None => rest = "",
+                    }
+                }
+            }
+        }
+    }
+
+    if let Some(rule) = current {
+        untranslated.push(Untranslated {
+            line: rule.line,
+            source: rule.head,
+            reason: "unterminated rule".to_string(),
+        });
+    }
+
+    (rules, statements)
+}
+
+/// name of a rule written `name {` or `name if {`, or `allow = true {`
+fn rule_name(head: &str) -> Option<String> {
+    let head = head.strip_suffix(" if").unwrap_or(head).trim();
+    let head = match head.split_once('=') {
+        Some((name, "true")) | Some((name, " true")) => name.trim_end_matches(':').trim(),
+        Some(_) => return None,
+        None => head,
+    };
+
+    if is_identifier(head) {
+        Some(head.to_string())
+    } else {
+        None
+    }
+}
+
+fn convert_statement(statement: &str, rule_names: &HashSet<String>) -> Result<String, String> {
+    if statement.starts_with("not ") {
+        return Err("negation is not supported".to_string());
+    }
+
+    if let Some(arguments) = call(statement, "regex.match") {
+        return match arguments.as_slice() {
+            [pattern, field] => {
+                let (predicate, variable) = input_field(field)?;
+                Ok(format!(
+                    "{}(${}), ${}.matches({})",
+                    predicate,
+                    variable,
+                    variable,
+                    literal(pattern)?
+                ))
+            }
+            _ => Err("regex.match expects 2 arguments".to_string()),
+        };
+    }
+
+    for (function, method) in [
+        ("startswith", "starts_with"),
+        ("endswith", "ends_with"),
+        ("contains", "contains"),
+    ]
+    .iter()
+    {
+        if let Some(arguments) = call(statement, function) {
+            return match arguments.as_slice() {
+                [field, value] => {
+                    let (predicate, variable) = input_field(field)?;
+                    Ok(format!(
+                        "{}(${}), ${}.{}({})",
+                        predicate,
+                        variable,
+                        variable,
+                        method,
+                        literal(value)?
+                    ))
+                }
+                _ => Err(format!("{} expects 2 arguments", function)),
+            };
+        }
+    }
+
+    for operator in ["==", "!=", "<=", ">=", "<", ">"].iter() {
+        if let Some((left, right)) = statement.split_once(operator) {
+            let (left, right) = (left.trim(), right.trim());
+            let (field, value, operator) = if left.starts_with("input.") {
+                (left, right, *operator)
+            } else if right.starts_with("input.") {
+                (right, left, reverse(operator))
+            } else {
+                return Err("comparisons must use an input field".to_string());
+            };
+
+            let (predicate, variable) = input_field(field)?;
+            let value = literal(value)?;
+            return Ok(if operator == "==" {
+                format!("{}({})", predicate, value)
+            } else {
+                format!(
+                    "{}(${}), ${} {} {}",
+                    predicate, variable, variable, operator, value
+                )
+            });
+        }
+    }
+
+    if statement.starts_with("input.") {
+        let (predicate, _) = input_field(statement)?;
+        return Ok(format!("{}(true)", predicate));
+    }
+
+    if rule_names.contains(statement) {
+        return Ok(format!("{}(true)", statement));
+    }
+
+    Err("unsupported expression".to_string())
+}
+
+fn reverse(operator: &str) -> &str {
+    match operator {
+        "<" => ">",
+        ">" => "<",
+        "<=" => ">=",
+        ">=" => "<=",
+        o => o,
+    }
+}
+
+/// arguments of `function(a, b)`
+fn call<'a>(statement: &'a str, function: &str) -> Option<Vec<&'a str>> {
+    let arguments = statement
+        .strip_prefix(function)?
+        .trim_start()
+        .strip_prefix('(')?
+        .strip_suffix(')')?;
+    Some(arguments.split(',').map(str::trim).collect())
+}
+
+/// converts `input.a.b` or `input.a.b[_]` to the predicate and variable names `a_b`
+fn input_field(field: &str) -> Result<(String, String), String> {
+    let path = field
+        .strip_prefix("input.")
+        .ok_or_else(|| format!("expected an input field, got `{}`", field))?;
+    let path = path.strip_suffix("[_]").unwrap_or(path);
+
+    if path.split('.').all(is_identifier) {
+        let name = path.replace('.', "_");
+        Ok((name.clone(), name))
+    } else {
+        Err(format!("unsupported input field `{}`", field))
+    }
+}
+
+fn literal(value: &str) -> Result<String, String> {
+    let is_string = value.len() >= 2
+        && value.starts_with('"')
+        && value.ends_with('"')
+        && !value[1..value.len() - 1].contains('"');
+
+    if is_string || value == "true" || value == "false" || value.parse::<i64>().is_ok() {
+        Ok(value.to_string())
+    } else {
+        Err(format!("unsupported value `{}`", value))
+    }
+}
+
+/// identifier at the start of a rule head, like `deny` in `deny[msg]`
+fn head_identifier(head: &str) -> &str {
+    let end = head
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+        .unwrap_or(head.len());
+    &head[..end]
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    matches!(chars.next(), Some(c) if c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{Authorizer, AuthorizerLimits};
+    use std::time::Duration;
+
+    fn limits() -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
+    const POLICY: &str = r#"
+package authz
+
+import input
+
+default allow = false
+
+# admins can do everything
+is_admin {
+    input.roles[_] == "admin"
+}
+
+allow { is_admin }
+
+allow {
+    input.user.name == "alice"
+    input.operation == "read"
+    startswith(input.path, "/public/")
+}
+
+deny {
+    input.age < 18
+}
+
+allow {
+    not is_admin
+    input.operation == "write"
+}
+"#;
+
+    fn authorize(code: &str, facts: &str) -> Result<usize, crate::error::Token> {
+        let mut authorizer = Authorizer::new();
+        authorizer.add_code(facts).unwrap();
+        authorizer.add_code(code).unwrap();
+        authorizer.authorize_with_limits(limits())
+    }
+
+    #[test]
+    fn convert_policy() {
+        let conversion = convert(POLICY);
+
+        assert_eq!(
+            conversion.code,
+            r#"is_admin(true) <- roles("admin");
+deny if age($age), $age < 18;
+allow if is_admin(true);
+allow if user_name("alice"), operation("read"), path($path), $path.starts_with("/public/");
+"#
+        );
+
+        assert_eq!(
+            conversion
+                .untranslated
+                .iter()
+                .map(|u| (u.line, u.source.as_str()))
+                .collect::<Vec<_>>(),
+            vec![(25, "allow"), (26, "not is_admin")]
+        );
+
+        let authorize = |facts: &str| authorize(&conversion.code, facts);
+        assert!(authorize(r#"roles("admin"); age(30);"#).is_ok());
+        assert!(authorize(r#"roles("admin"); age(12);"#).is_err());
+        assert!(
+            authorize(r#"user_name("alice"); operation("read"); path("/public/a"); age(30);"#)
+                .is_ok()
+        );
+        assert!(authorize(
+            r#"user_name("alice"); operation("write"); path("/public/a"); age(30);"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn untranslated_deny() {
+        let conversion = convert(&format!(
+            "{}\ndeny {{\n    input.ip == input.blocked_ip\n}}\n",
+            POLICY
+        ));
+
+        assert_eq!(
+            conversion.code,
+            r#"is_admin(true) <- roles("admin");
+deny if age($age), $age < 18;
+deny if true;
+allow if is_admin(true);
+allow if user_name("alice"), operation("read"), path($path), $path.starts_with("/public/");
+"#
+        );
+        assert_eq!(
+            conversion
+                .untranslated
+                .iter()
+                .map(|u| (u.line, u.source.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (25, "allow"),
+                (26, "not is_admin"),
+                (30, "deny"),
+                (31, "input.ip == input.blocked_ip"),
+            ]
+        );
+        assert!(authorize(&conversion.code, r#"roles("admin"); age(30);"#).is_err());
+
+        // deny rules using a rule that could not be converted
+        let conversion = convert(
+            "banned { input.user.name == \"eve\" }\n\
+             banned { count(input.strikes) > 3 }\n\
+             blocked { banned }\n\
+             deny { blocked }\n\
+             allow { input.user.name == \"alice\" }",
+        );
+        assert_eq!(
+            conversion.code,
+            "banned(true) <- user_name(\"eve\");\n\
+             blocked(true) <- banned(true);\n\
+             deny if blocked(true);\n\
+             deny if true;\n\
+             allow if user_name(\"alice\");\n"
+        );
+        assert_eq!(
+            conversion
+                .untranslated
+                .iter()
+                .map(|u| (u.line, u.source.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                (2, "banned"),
+                (2, "count(input.strikes) > 3"),
+                (3, "blocked"),
+                (4, "deny"),
+            ]
+        );
+
+        // deny rules with values
+        let conversion =
+            convert("deny[msg] { input.blocked; msg := \"blocked\" }\ndefault allow = true");
+        assert_eq!(conversion.code, "deny if true;\nallow if true;\n");
+    }
+
+    #[test]
+    fn default_allow() {
+        let conversion = convert("default allow := true\ndeny { input.blocked }\nf(x) { true }");
+        assert_eq!(conversion.code, "deny if blocked(true);\nallow if true;\n");
+        assert_eq!(conversion.untranslated.len(), 1);
+        assert_eq!(conversion.untranslated[0].line, 3);
+    }
+}
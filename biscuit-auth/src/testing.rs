@@ -0,0 +1,309 @@
+//! table driven tests of authorization policies
+//!
+//! A list of [`PolicyCase`] describes tokens and the expected decision of the
+//! authorizer policies for each of them. [`assert_policies`] runs all the
+//! cases, and panics with a description of each unexpected decision: the
+//! matched policy, the failed checks and the facts they use.
+//!
+//! ```rust
+//! use biscuit_auth::testing::{assert_policies, PolicyCase};
+//!
+//! let policies = r#"
+//!     check if operation($op), right($op);
+//!     allow if user($u);
+//! "#;
+//!
+//! assert_policies(
+//!     policies,
+//!     &[
+//!         PolicyCase::allow("reader", r#"user("alice"); right("read");"#)
+//!             .request(r#"operation("read");"#),
+//!         PolicyCase::deny("no right", r#"user("alice");"#).request(r#"operation("read");"#),
+//!         PolicyCase::deny("attenuated", r#"user("alice"); right("read");"#)
+//!             .block(r#"check if operation("write");"#)
+//!             .request(r#"operation("read");"#),
+//!     ],
+//! );
+//! ```
+use std::collections::HashSet;
+use std::fmt;
+use std::time::{Duration, UNIX_EPOCH};
+
+use crate::{
+    builder::{Fact, Rule},
+    error, Authorizer, AuthorizerLimits, Biscuit, FixedClock, KeyPair,
+};
+
+/// expected decision of the authorizer
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Expected {
+    Allow,
+    Deny,
+}
+
+/// token and request to authorize, with the expected decision
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PolicyCase {
+    pub name: String,
+    /// blocks of the token, see [`PolicyCase::block`]
+    pub blocks: Vec<String>,
+    /// Datalog code added to the authorizer for this case, like the facts of the request
+    pub request: String,
+    /// see [`PolicyCase::time`]
+    pub time: Option<u64>,
+    pub expected: Expected,
+}
+
+impl PolicyCase {
+    /// a case where the token with this authority block must be authorized
+    pub fn allow(name: &str, authority: &str) -> Self {
+        Self::new(name, authority, Expected::Allow)
+    }
+
+    /// a case where the token with this authority block must be refused
+    pub fn deny(name: &str, authority: &str) -> Self {
+        Self::new(name, authority, Expected::Deny)
+    }
+
+    fn new(name: &str, authority: &str, expected: Expected) -> Self {
+        PolicyCase {
+            name: name.to_string(),
+            blocks: vec![authority.to_string()],
+            request: String::new(),
+            time: None,
+            expected,
+        }
+    }
+
+    /// appends an attenuation block to the token, after the authority block
+    /// and the blocks added before
+    pub fn block(mut self, code: &str) -> Self {
+        self.blocks.push(code.to_string());
+        self
+    }
+
+    /// adds Datalog code to the authorizer for this case
+    pub fn request(mut self, code: &str) -> Self {
+        self.request.push_str(code);
+        self.request.push('\n');
+        self
+    }
+
+    /// sets the current time, as seconds since the Unix epoch, provided to
+    /// the authorizer with [`Authorizer::set_time`]
+    ///
+    /// without it, the authorizer has no `time` fact
+    pub fn time(mut self, time: u64) -> Self {
+        self.time = Some(time);
+        self
+    }
+}
+
+/// unexpected decision for a [`PolicyCase`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolicyFailure {
+    pub case: String,
+    pub expected: Expected,
+    /// result of the authorization, or the error returned when creating the
+    /// token, the authorizer or the report
+    pub result: Result<usize, error::Token>,
+    /// policy that matched
+    pub policy: Option<String>,
+    /// failed checks, with their block
+    pub failed_checks: Vec<String>,
+    /// facts of the authorizer used by the failed checks, or by the policies
+    /// if no check failed, sorted
+    pub facts: Vec<String>,
+}
+
+impl fmt::Display for PolicyFailure {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(
+            f,
+            "case `{}`: expected {:?}, got {:?}",
+            self.case, self.expected, self.result
+        )?;
+        if let Some(policy) = &self.policy {
+            writeln!(f, "  matched policy: {}", policy)?;
+        }
+        if !self.failed_checks.is_empty() {
+            writeln!(f, "  failed checks:")?;
+            for check in &self.failed_checks {
+                writeln!(f, "    {}", check)?;
+            }
+        }
+        if !self.facts.is_empty() {
+            writeln!(f, "  facts:")?;
+            for fact in &self.facts {
+                writeln!(f, "    {}", fact)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// runs a case against the authorizer code in `policies`, and returns the
+/// description of the decision if it was not the expected one
+pub fn run(policies: &str, case: &PolicyCase) -> Option<PolicyFailure> {
+    let failure = |result| PolicyFailure {
+        case: case.name.clone(),
+        expected: case.expected,
+        result,
+        policy: None,
+        failed_checks: vec![],
+        facts: vec![],
+    };
+
+    let mut authorizer = match authorizer(policies, case) {
+        Ok(authorizer) => authorizer,
+        Err(e) => return Some(failure(Err(e))),
+    };
+    let report = match authorizer.authorize_report() {
+        Ok(report) => report,
+        Err(e) => return Some(failure(Err(e))),
+    };
+
+    let authorized = report.result.is_ok();
+    if authorized == (case.expected == Expected::Allow) {
+        return None;
+    }
+
+    let mut predicates = HashSet::new();
+    let failed_checks = report
+        .failed_checks()
+        .map(|c| {
+            add_predicates(&mut predicates, &c.check.queries);
+            match c.block_id {
+                Some(block_id) => format!("block {}, check {}: {}", block_id, c.check_id, c.check),
+                None => format!("authorizer, check {}: {}", c.check_id, c.check),
+            }
+        })
+        .collect();
+
+    let (facts, _, _, all_policies) = authorizer.dump();
+    if predicates.is_empty() {
+        for policy in &all_policies {
+            add_predicates(&mut predicates, &policy.queries);
+        }
+    }
+    let mut facts: Vec<String> = facts
+        .iter()
+        .filter(|fact| predicates.contains(fact.predicate.name.as_str()))
+        .map(Fact::to_string)
+        .collect();
+    facts.sort();
+    facts.dedup();
+
+    Some(PolicyFailure {
+        policy: report.policy.as_ref().map(|p| p.policy.to_string()),
+        failed_checks,
+        facts,
+        ..failure(report.result)
+    })
+}
+
+/// runs all the cases, and returns the unexpected decisions
+pub fn run_all(policies: &str, cases: &[PolicyCase]) -> Vec<PolicyFailure> {
+    cases
+        .iter()
+        .filter_map(|case| run(policies, case))
+        .collect()
+}
+
+/// runs all the cases, and panics with the description of the unexpected decisions
+#[track_caller]
+pub fn assert_policies(policies: &str, cases: &[PolicyCase]) {
+    let failures = run_all(policies, cases);
+    if !failures.is_empty() {
+        let mut message = format!(
+            "{} of {} policy cases failed\n",
+            failures.len(),
+            cases.len()
+        );
+        for failure in &failures {
+            message.push_str(&failure.to_string());
+        }
+        panic!("{}", message);
+    }
+}
+
+fn authorizer(policies: &str, case: &PolicyCase) -> Result<Authorizer, error::Token> {
+    let root = KeyPair::new();
+    let token = Biscuit::from_blocks_code(&root, &case.blocks, &mut rand::rngs::OsRng)?;
+
+    let mut authorizer = token.authorizer()?;
+    if let Some(time) = case.time {
+        authorizer.set_clock(FixedClock(UNIX_EPOCH + Duration::from_secs(time)));
+        authorizer.set_time();
+    }
+    authorizer.add_code(&case.request)?;
+    authorizer.add_code(policies)?;
+    authorizer.set_limits(AuthorizerLimits {
+        max_time: Duration::from_secs(1),
+        ..Default::default()
+    });
+
+    Ok(authorizer)
+}
+
+fn add_predicates<'a>(predicates: &mut HashSet<&'a str>, queries: &'a [Rule]) {
+    for query in queries {
+        for predicate in &query.body {
+            predicates.insert(predicate.name.as_str());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn policy_failures() {
+        let policies = r#"
+            check if operation($op), right($op);
+            allow if user($u);
+        "#;
+
+        let failures = run_all(
+            policies,
+            &[
+                PolicyCase::allow("reader", r#"user("alice"); right("read");"#)
+                    .request(r#"operation("read");"#),
+                PolicyCase::allow("no right", r#"user("alice"); right("read");"#)
+                    .request(r#"operation("write");"#),
+                PolicyCase::deny("no user", r#"right("read");"#).request(r#"operation("read");"#),
+                PolicyCase::deny("invalid", "user(").request(r#"operation("read");"#),
+            ],
+        );
+
+        assert_eq!(failures.len(), 2);
+        assert_eq!(failures[0].case, "no right");
+        assert_eq!(
+            failures[0].failed_checks,
+            vec!["authorizer, check 0: check if operation($op), right($op)"]
+        );
+        assert_eq!(
+            failures[0].facts,
+            vec!["operation(\"write\")", "right(\"read\")"]
+        );
+        assert!(failures[0].to_string().contains("failed checks:"));
+
+        assert_eq!(failures[1].case, "invalid");
+        assert!(matches!(failures[1].result, Err(error::Token::Language(_))));
+    }
+
+    #[test]
+    fn deny_matching_allow_policy() {
+        let failure = run(
+            "allow if user($u)",
+            &PolicyCase::deny("user", r#"user("alice"); other(1);"#).time(1_700_000_000),
+        )
+        .unwrap();
+
+        assert_eq!(failure.result, Ok(0));
+        assert_eq!(failure.policy.as_deref(), Some("allow if user($u)"));
+        assert!(failure.failed_checks.is_empty());
+        assert_eq!(failure.facts, vec!["user(\"alice\")"]);
+    }
+}
@@ -2,7 +2,7 @@
 //!
 //! code from <https://github.com/rust-lang/rust/issues/48564#issuecomment-698712971>
 
-#[cfg(feature = "wasm")]
+#[cfg(target_arch = "wasm32")]
 use std::convert::TryInto;
 use std::ops::{Add, AddAssign, Sub, SubAssign};
 #[cfg(feature = "wasm")]
@@ -10,6 +10,7 @@ use wasm_bindgen::prelude::*;
 
 pub use std::time::*;
 
+/// a measurement of a monotonic clock, wrapping [`std::time::Instant`]
 #[cfg(not(target_arch = "wasm32"))]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Instant(std::time::Instant);
@@ -44,24 +45,42 @@ extern "C" {
     fn performance_now() -> f64;
 }
 
+/// a measurement of a monotonic clock, in microseconds
+///
+/// with the `wasm` feature, [`Instant::now`] reads `performance.now()`.
+/// Without it, there is no time source on `wasm32-unknown-unknown`: `now`
+/// returns an instant that is always past any deadline, so Datalog runs fail
+/// with [`crate::error::RunLimit::Timeout`] unless the authorizer's [`Clock`]
+/// provides [`Clock::instant`], built with [`Instant::from_micros`] from the
+/// host's monotonic time
 #[cfg(target_arch = "wasm32")]
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Instant(u64);
 
 #[cfg(target_arch = "wasm32")]
 impl Instant {
+    #[cfg(feature = "wasm")]
     pub fn now() -> Self {
         Self((performance_now() * 1000.0) as u64)
     }
+    /// without a time source, execution time limits fail closed
+    #[cfg(not(feature = "wasm"))]
+    pub fn now() -> Self {
+        Self(u64::MAX)
+    }
+    /// creates an instant from a monotonic time in microseconds, from any origin
+    pub fn from_micros(micros: u64) -> Self {
+        Self(micros)
+    }
     pub fn duration_since(&self, earlier: Instant) -> Duration {
-        Duration::from_micros(self.0 - earlier.0)
+        Duration::from_micros(self.0.saturating_sub(earlier.0))
     }
     pub fn elapsed(&self) -> Duration {
         Self::now().duration_since(*self)
     }
     pub fn checked_add(&self, duration: Duration) -> Option<Self> {
         match duration.as_micros().try_into() {
-            Ok(duration) => self.0.checked_add(duration).map(|i| Self(i)),
+            Ok(duration) => Some(Self(self.0.saturating_add(duration))),
             Err(_) => None,
         }
     }
@@ -101,3 +120,156 @@ impl SubAssign<Duration> for Instant {
         *self = *self - other;
     }
 }
+
+/// source of the current date used by the authorizer
+///
+/// [`crate::Authorizer::set_time`] reads the date from a `Clock`, which
+/// is what expiration checks like
+/// [`crate::builder_ext::BuilderExt::check_expiration_date`] compare against.
+/// Replacing it lets tests and WebAssembly hosts control the time seen
+/// by the authorizer.
+///
+/// On `wasm32-unknown-unknown` without the `wasm` feature, neither
+/// [`SystemClock`] nor the default [`Clock::instant`] have access to a time
+/// source, so hosts like edge runtimes should implement both methods from
+/// their own APIs and install the clock with [`crate::Authorizer::set_clock`].
+pub trait Clock: Send + Sync {
+    fn now(&self) -> SystemTime;
+
+    /// monotonic time used to enforce the authorizer's `max_time` limit
+    ///
+    /// defaults to [`Instant::now`]
+    fn instant(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// the default [`Clock`], reading the system time
+///
+/// On `wasm32` targets with the `wasm` feature, the time comes from `Date.now()`.
+///
+/// # Panics
+///
+/// On `wasm32` targets without the `wasm` feature, there is no system time:
+/// [`Clock::now`] panics, and a custom clock must be installed with
+/// [`crate::Authorizer::set_clock`] before calling [`crate::Authorizer::set_time`]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SystemClock;
+
+#[cfg(not(target_arch = "wasm32"))]
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        SystemTime::now()
+    }
+}
+
+#[cfg(all(target_arch = "wasm32", not(feature = "wasm")))]
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        panic!(
+            "no system time on this target: install a clock with `Authorizer::set_clock` \
+             or enable the `wasm` feature"
+        )
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+#[cfg(feature = "wasm")]
+#[wasm_bindgen(inline_js = r#"
+export function date_now() {
+  return Date.now();
+}"#)]
+extern "C" {
+    fn date_now() -> f64;
+}
+
+#[cfg(all(target_arch = "wasm32", feature = "wasm"))]
+impl Clock for SystemClock {
+    fn now(&self) -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(date_now() as u64)
+    }
+}
+
+/// a [`Clock`] always returning the same date
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FixedClock(pub SystemTime);
+
+impl Clock for FixedClock {
+    fn now(&self) -> SystemTime {
+        self.0
+    }
+}
+
+/// a [`Clock`] controlled by the test code
+///
+/// both the date and the monotonic time used for execution time limits are
+/// frozen, and only move forward with [`FakeClock::advance`]. Clones share the
+/// same time, so a copy can be kept to advance the clock given to an authorizer
+#[derive(Clone, Debug)]
+pub struct FakeClock(std::sync::Arc<std::sync::Mutex<(SystemTime, Instant)>>);
+
+impl FakeClock {
+    pub fn new(time: SystemTime) -> Self {
+        FakeClock(std::sync::Arc::new(std::sync::Mutex::new((
+            time,
+            Self::start_instant(),
+        ))))
+    }
+
+    #[cfg(not(all(target_arch = "wasm32", not(feature = "wasm"))))]
+    fn start_instant() -> Instant {
+        Instant::now()
+    }
+
+    // `Instant::now` is always past the deadlines without a time source
+    #[cfg(all(target_arch = "wasm32", not(feature = "wasm")))]
+    fn start_instant() -> Instant {
+        Instant::from_micros(0)
+    }
+
+    /// moves the date and the monotonic time forward
+    pub fn advance(&self, duration: Duration) {
+        let mut time = self.0.lock().unwrap();
+        time.0 += duration;
+        time.1 += duration;
+    }
+}
+
+impl Clock for FakeClock {
+    fn now(&self) -> SystemTime {
+        self.0.lock().unwrap().0
+    }
+
+    fn instant(&self) -> Instant {
+        self.0.lock().unwrap().1
+    }
+}
+
+impl<F> Clock for F
+where
+    F: Fn() -> SystemTime + Send + Sync,
+{
+    fn now(&self) -> SystemTime {
+        self()
+    }
+}
+
+impl Clock for Box<dyn Clock> {
+    fn now(&self) -> SystemTime {
+        self.as_ref().now()
+    }
+
+    fn instant(&self) -> Instant {
+        self.as_ref().instant()
+    }
+}
+
+impl Clock for std::sync::Arc<dyn Clock> {
+    fn now(&self) -> SystemTime {
+        self.as_ref().now()
+    }
+
+    fn instant(&self) -> Instant {
+        self.as_ref().instant()
+    }
+}
@@ -1,19 +1,23 @@
 //! Authorizer structure and associated functions
 use super::builder::{
-    constrained_rule, date, fact, pred, rule, string, var, Binary, BlockBuilder, Check, Expression,
-    Fact, Op, Policy, PolicyKind, Rule, Scope, Term,
+    bytes, constrained_rule, date, fact, pred, rule, string, var, Binary, BlockBuilder, Check,
+    Expression, Fact, Op, Policy, PolicyKind, Predicate, Rule, Scope, Term,
 };
 use super::builder_ext::{AuthorizerExt, BuilderExt};
-use super::{Biscuit, Block};
+use super::{Biscuit, Block, RevocationStore};
 use crate::builder::{CheckKind, Convert};
-use crate::crypto::PublicKey;
-use crate::datalog::{self, Origin, RunLimits, SymbolTable, TrustedOrigins};
+use crate::crypto::{PublicKey, SecurityPolicy};
+use crate::datalog::{
+    self, IndexStats, Origin, RunLimits, SymbolReport, SymbolSource, SymbolTable, Tracer,
+    TrustedOrigins,
+};
 use crate::error;
-use crate::time::Instant;
+use crate::time::{Clock, Instant, SystemClock};
 use crate::token;
 use biscuit_parser::parser::parse_source;
 use prost::Message;
 use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
 use std::time::Duration;
 use std::{
     collections::HashMap,
@@ -23,7 +27,42 @@ use std::{
     time::SystemTime,
 };
 
+#[cfg(feature = "decision-cache")]
+mod cache;
+mod compiled;
+mod debugger;
+mod export;
+mod frozen;
+mod journal;
+#[cfg(feature = "json")]
+mod json;
+mod observer;
+mod pool;
+mod profile;
+mod report;
+mod scopes;
 mod snapshot;
+mod store;
+mod typestate;
+
+#[cfg(feature = "decision-cache")]
+pub use cache::{DecisionCache, DecisionCacheStats};
+pub use compiled::CompiledPolicies;
+pub use debugger::{DebugIteration, Debugger};
+pub use frozen::FrozenAuthorizer;
+pub use journal::{
+    read_journal, DecisionJournal, JournalEntry, JournalSink, LimitsReport, MemoryJournal,
+    WriterJournal,
+};
+pub use observer::{AuthorizationMetrics, AuthorizerObserver};
+pub use pool::{AuthorizerPool, PoolStats, PooledAuthorizer};
+use profile::ProfileHooks;
+pub use profile::{AuthorizerProfile, CheckProfile, RuleProfile};
+pub use report::{AuthorizationReport, CheckReport, CheckStatus, PolicyReport};
+pub use scopes::ScopeWarning;
+pub use snapshot::SnapshotDiff;
+pub use store::{PolicyStore, PolicyVersion};
+pub use typestate::{AuthorizerBuilder, Unauthenticated, WithToken};
 
 /// used to check authorization policies on a token
 ///
@@ -39,6 +78,21 @@ pub struct Authorizer {
     public_key_to_block_id: HashMap<usize, Vec<usize>>,
     limits: AuthorizerLimits,
     execution_time: Duration,
+    revocation_ids: Vec<Vec<u8>>,
+    revocation_store: Option<Arc<dyn RevocationStore>>,
+    security_policy: SecurityPolicy,
+    expected_audiences: Vec<String>,
+    require_sealed: bool,
+    token_sealed: bool,
+    /// authorizer facts of the Datalog world before the token was added:
+    /// [`Authorizer::reset_token_data`] restores them, dropping the
+    /// `biscuit::root_key` fact and the facts generated during authorization
+    token_free_facts: Option<Arc<HashSet<datalog::Fact>>>,
+    clock: Arc<dyn Clock>,
+    observer: Option<Arc<dyn AuthorizerObserver>>,
+    /// rule and trusted origins of the last [`Authorizer::query_iter`], kept
+    /// here because its iterator borrows them
+    streamed_query: Option<(datalog::Rule, TrustedOrigins)>,
 }
 
 impl Authorizer {
@@ -73,6 +127,16 @@ impl Authorizer {
             public_key_to_block_id: HashMap::new(),
             limits: AuthorizerLimits::default(),
             execution_time: Duration::default(),
+            revocation_ids: vec![],
+            revocation_store: None,
+            security_policy: SecurityPolicy::default(),
+            expected_audiences: vec![],
+            require_sealed: false,
+            token_sealed: false,
+            token_free_facts: None,
+            clock: Arc::new(SystemClock),
+            observer: None,
+            streamed_query: None,
         }
     }
 
@@ -99,6 +163,15 @@ impl Authorizer {
                 .insert(new_key_id as usize, block_ids.clone());
         }
 
+        self.token_free_facts = Some(
+            self.world
+                .facts
+                .inner
+                .get(&authorizer_origin())
+                .cloned()
+                .unwrap_or_default(),
+        );
+
         let mut blocks = Vec::new();
 
         for i in 0..token.block_count() {
@@ -109,7 +182,37 @@ impl Authorizer {
             blocks.push(block);
         }
 
+        // the key is known by the authorizer, not declared by the token, so the
+        // blocks cannot define the fact
+        if let Some(root_key) = token.verified_root_key() {
+            if let Some(name) = self.symbols.get(ROOT_KEY_PREDICATE) {
+                for (i, block) in blocks.iter().enumerate() {
+                    if block.facts.iter().any(|fact| fact.predicate.name == name)
+                        || block.rules.iter().any(|rule| rule.head.name == name)
+                    {
+                        return Err(error::Logic::ReservedPredicate {
+                            block_id: i as u32,
+                            predicate: ROOT_KEY_PREDICATE.to_string(),
+                        }
+                        .into());
+                    }
+                }
+            }
+
+            let root_key_fact = fact(
+                ROOT_KEY_PREDICATE,
+                &[
+                    Term::Integer(root_key.index as i64),
+                    string(&root_key.public_key.to_string()),
+                ],
+            )
+            .convert(&mut self.symbols);
+            self.world.add_fact(&authorizer_origin(), root_key_fact);
+        }
+
         self.blocks = Some(blocks);
+        self.token_sealed = token.is_sealed();
+        self.revocation_ids = token.revocation_identifiers();
         self.token_origins = TrustedOrigins::from_scopes(
             &[token::Scope::Previous],
             &TrustedOrigins::default(),
@@ -120,6 +223,58 @@ impl Authorizer {
         Ok(())
     }
 
+    /// removes the token and the data derived from it, so that another token
+    /// can be added with [`Authorizer::add_token`]
+    ///
+    /// the authorizer's facts, rules, checks, policies and configuration are
+    /// kept. Facts and rules coming from the token, the `biscuit::root_key`
+    /// fact, the facts generated during authorization and the symbols they
+    /// introduced are removed, and the execution time and iterations are
+    /// reset: a reused authorizer does not grow with each token.
+    pub fn reset_token_data(&mut self) -> Result<(), error::Token> {
+        let authorizer_origin = authorizer_origin();
+
+        // the facts generated from aggregates can depend on the token
+        self.world.reset_evaluation();
+
+        // generated facts carry the authorizer origin when their rule only
+        // matched authorizer facts, including `biscuit::root_key`, so they
+        // cannot be told apart from the loaded facts by their origin
+        let authorizer_facts = match self.token_free_facts.take() {
+            Some(facts) => Some(facts),
+            None => self.world.facts.inner.get(&authorizer_origin).cloned(),
+        };
+
+        let mut symbols = self.symbols.shared_base();
+        let mut facts = datalog::FactSet::default();
+        if let Some(authorizer_facts) = authorizer_facts {
+            for fact in authorizer_facts.iter() {
+                let fact = Fact::convert_from(fact, &self.symbols)?.convert(&mut symbols);
+                facts.insert(&authorizer_origin, fact);
+            }
+        }
+        for fact in &self.authorizer_block_builder.facts {
+            facts.insert(&authorizer_origin, fact.convert(&mut symbols));
+        }
+
+        self.symbols = symbols;
+        self.world.facts = facts;
+        // the authorizer rules are loaded again on authorization
+        self.world.rules = datalog::RuleSet::default();
+        self.world.iterations = 0;
+        self.world.block_facts.clear();
+        self.world.block_iterations.clear();
+        self.world.index_stats = IndexStats::default();
+        self.blocks = None;
+        self.public_key_to_block_id.clear();
+        self.token_origins = TrustedOrigins::default();
+        self.revocation_ids.clear();
+        self.token_sealed = false;
+        self.execution_time = Duration::default();
+
+        Ok(())
+    }
+
     /// we need to modify the block loaded from the token, because the authorizer's and th token's symbol table can differ
     fn load_and_translate_block(
         &mut self,
@@ -148,7 +303,7 @@ impl Authorizer {
 
         for fact in block.facts.iter_mut() {
             *fact = Fact::convert_from(fact, &block_symbols)?.convert(&mut self.symbols);
-            self.world.facts.insert(&block_origin, fact.clone());
+            self.world.add_fact(&block_origin, fact.clone());
         }
 
         for rule in block.rules.iter_mut() {
@@ -179,17 +334,44 @@ impl Authorizer {
         Ok(())
     }
 
+    /// returns the facts added to the authorizer, including the ones already
+    /// loaded in the Datalog world, like the facts of [`CompiledPolicies`] or
+    /// the facts loaded by [`Authorizer::freeze`]
+    ///
+    /// without a token, the authorizer's facts generated by its rules are
+    /// returned too: they are derived again on authorization. Once a token is
+    /// added, only the facts loaded before it are returned
+    pub(crate) fn authorizer_facts(&self) -> Result<Vec<Fact>, error::Format> {
+        let mut facts = self.authorizer_block_builder.facts.clone();
+
+        let loaded = match &self.token_free_facts {
+            Some(facts) => Some(facts),
+            None => self.world.facts.inner.get(&authorizer_origin()),
+        };
+        if let Some(loaded) = loaded {
+            let mut symbols = self.symbols.clone();
+            let added: HashSet<datalog::Fact> = facts
+                .iter()
+                .map(|fact| fact.convert(&mut symbols))
+                .collect();
+
+            let mut loaded: Vec<&datalog::Fact> =
+                loaded.iter().filter(|fact| !added.contains(fact)).collect();
+            loaded.sort();
+            for fact in loaded {
+                facts.push(Fact::convert_from(fact, &self.symbols)?);
+            }
+        }
+
+        Ok(facts)
+    }
+
     /// serializes a authorizer's content
     ///
     /// you can use this to save a set of policies and load them quickly before
     /// verification. This will not store data obtained or generated from a token.
     pub fn save(&self) -> Result<AuthorizerPolicies, error::Token> {
-        let facts = self
-            .authorizer_block_builder
-            .facts
-            .iter()
-            .cloned()
-            .collect();
+        let facts = self.authorizer_facts()?;
 
         let rules = self
             .authorizer_block_builder
@@ -233,6 +415,53 @@ impl Authorizer {
         self.authorizer_block_builder.add_fact(fact)
     }
 
+    /// adds facts from an iterator, converting them to their Datalog
+    /// representation as they are read
+    ///
+    /// unlike [`Authorizer::add_fact`], which keeps the facts until
+    /// authorization, each fact is loaded directly in the Datalog world: its
+    /// strings are interned in the symbol table, and duplicate facts are
+    /// stored once. The memory used does not depend on the length of the
+    /// iterator, only on the distinct facts loaded, in their compact form.
+    ///
+    /// If a fact is invalid, the facts read before it stay loaded.
+    ///
+    /// ```rust
+    /// use biscuit_auth::{builder::fact, builder::string, Authorizer};
+    ///
+    /// let mut authorizer = Authorizer::new();
+    /// authorizer
+    ///     .add_facts_from_iter((0..1_000).map(|i| fact("user", &[string(&i.to_string())])))
+    ///     .unwrap();
+    /// authorizer.add_code(r#"allow if user("999")"#).unwrap();
+    /// assert_eq!(authorizer.authorize(), Ok(0));
+    /// ```
+    pub fn add_facts_from_iter<I, F>(&mut self, facts: I) -> Result<(), error::Token>
+    where
+        I: IntoIterator<Item = F>,
+        F: TryInto<Fact>,
+        error::Token: From<<F as TryInto<Fact>>::Error>,
+    {
+        let origin = authorizer_origin();
+        for fact in facts {
+            self.load_fact(&origin, fact.try_into()?)?;
+        }
+        Ok(())
+    }
+
+    /// converts a fact and inserts it in the Datalog world
+    fn load_fact(&mut self, origin: &Origin, fact: Fact) -> Result<(), error::Token> {
+        fact.validate()?;
+        let fact = fact.convert(&mut self.symbols);
+        if let Some(token_free_facts) = self.token_free_facts.as_mut() {
+            if *origin == authorizer_origin() {
+                Arc::make_mut(token_free_facts).insert(fact.clone());
+            }
+        }
+        self.world.add_fact(origin, fact);
+        Ok(())
+    }
+
     pub fn add_rule<Ru: TryInto<Rule>>(&mut self, rule: Ru) -> Result<(), error::Token>
     where
         error::Token: From<<Ru as TryInto<Rule>>::Error>,
@@ -278,8 +507,9 @@ impl Authorizer {
         let source = source.as_ref();
 
         let source_result = parse_source(source).map_err(|e| {
-            let e2: biscuit_parser::error::LanguageError = e.into();
-            e2
+            biscuit_parser::error::LanguageError::ParseError(
+                biscuit_parser::error::ParseErrors::from_source(source, e),
+            )
         })?;
 
         for (_, fact) in source_result.facts.into_iter() {
@@ -301,7 +531,7 @@ impl Authorizer {
         }
 
         for (_, rule) in source_result.rules.into_iter() {
-            let mut rule: Rule = rule.into();
+            let mut rule: Rule = rule.try_into()?;
             for (name, value) in &params {
                 let res = match rule.set(name, value) {
                     Ok(_) => Ok(()),
@@ -331,7 +561,7 @@ impl Authorizer {
         }
 
         for (_, check) in source_result.checks.into_iter() {
-            let mut check: Check = check.into();
+            let mut check: Check = check.try_into()?;
             for (name, value) in &params {
                 let res = match check.set(name, value) {
                     Ok(_) => Ok(()),
@@ -360,7 +590,7 @@ impl Authorizer {
             self.authorizer_block_builder.checks.push(check);
         }
         for (_, policy) in source_result.policies.into_iter() {
-            let mut policy: Policy = policy.into();
+            let mut policy: Policy = policy.try_into()?;
             for (name, value) in &params {
                 let res = match policy.set(name, value) {
                     Ok(_) => Ok(()),
@@ -396,6 +626,51 @@ impl Authorizer {
         self.authorizer_block_builder.add_scope(scope);
     }
 
+    /// checks the token's revocation identifiers against a [`RevocationStore`]
+    ///
+    /// The store is queried with all of the token's identifiers at once, before
+    /// running the Datalog evaluation, and the authorization fails with
+    /// [`error::Token::Revoked`] if any of them was revoked.
+    ///
+    /// The revocation identifiers are not part of snapshots, so an authorizer
+    /// restored from a snapshot cannot perform this check.
+    pub fn revocation_check<S: RevocationStore + 'static>(&mut self, store: S) {
+        self.revocation_store = Some(Arc::new(store));
+    }
+
+    /// sets the hardening options, see [`SecurityPolicy`]
+    ///
+    /// with `constant_time_comparisons`, the store registered with
+    /// [`Authorizer::revocation_check`] is queried with
+    /// [`RevocationStore::revoked_constant_time`]
+    pub fn set_security_policy(&mut self, policy: SecurityPolicy) {
+        self.security_policy = policy;
+    }
+
+    /// only accepts tokens created for this audience
+    ///
+    /// The token's audiences are the `audience` facts of its authority block, as
+    /// added by [`BiscuitBuilder::audience`](crate::builder::BiscuitBuilder::audience).
+    /// This can be called multiple times to accept multiple audiences. If none of
+    /// the token's audiences is accepted, the authorization fails with
+    /// [`error::Logic::AudienceMismatch`].
+    ///
+    /// The expected audiences are not part of snapshots.
+    pub fn expect_audience(&mut self, audience: &str) {
+        self.expected_audiences.push(audience.to_string());
+    }
+
+    /// only accepts sealed tokens
+    ///
+    /// sealed tokens cannot be attenuated, see [`Biscuit::seal`]. If the token
+    /// is not sealed, or if there is no token, the authorization fails with
+    /// [`error::Logic::SealRequired`].
+    ///
+    /// This requirement is not part of snapshots.
+    pub fn require_sealed(&mut self) {
+        self.require_sealed = true;
+    }
+
     /// Returns the runtime limits of the authorizer
     ///
     /// Those limits cover all the executions under the `authorize`, `query` and `query_all` methods
@@ -410,6 +685,43 @@ impl Authorizer {
         self.limits = limits;
     }
 
+    /// returns the values of the variables of a single predicate pattern, in order of appearance
+    ///
+    /// this is a shortcut for [`Authorizer::query`] with a rule whose head contains
+    /// the pattern's variables, and has the same visibility: only facts from the
+    /// authorizer and the authority block are matched.
+    ///
+    /// ```rust
+    /// # use biscuit_auth::KeyPair;
+    /// # use biscuit_auth::Biscuit;
+    /// let keypair = KeyPair::new();
+    /// let mut builder = Biscuit::builder();
+    /// builder.add_fact("right(\"file1\", \"read\")");
+    /// builder.add_fact("right(\"file2\", \"write\")");
+    ///
+    /// let biscuit = builder.build(&keypair).unwrap();
+    ///
+    /// let mut authorizer = biscuit.authorizer().unwrap();
+    /// let res: Vec<(String,)> = authorizer.match_facts("right($file, \"read\")").unwrap();
+    /// assert_eq!(res, vec![("file1".to_string(),)]);
+    /// ```
+    pub fn match_facts<T: TryFrom<Fact, Error = E>, E: Into<error::Token>>(
+        &mut self,
+        pattern: &str,
+    ) -> Result<Vec<T>, error::Token> {
+        let mut rule = Rule::try_from(format!("{} <- {}", pattern, pattern).as_str())?;
+
+        let mut variables = Vec::new();
+        for term in &rule.head.terms {
+            if matches!(term, Term::Variable(_)) && !variables.contains(term) {
+                variables.push(term.clone());
+            }
+        }
+        rule.head = Predicate::new("match".to_string(), variables);
+
+        self.query(rule)
+    }
+
     /// run a query over the authorizer's Datalog engine to gather data
     ///
     /// ```rust
@@ -445,6 +757,53 @@ impl Authorizer {
         self.query_with_limits(rule, limits)
     }
 
+    /// run a query over the authorizer's Datalog engine, after replacing the
+    /// parameters of the rule
+    ///
+    /// like [`Authorizer::add_code_with_params`], parameters that do not appear
+    /// in the rule are ignored, and an error is returned if a parameter of the
+    /// rule is missing
+    ///
+    /// ```rust
+    /// # use biscuit_auth::{Biscuit, KeyPair};
+    /// # use biscuit_auth::builder::string;
+    /// # use std::collections::HashMap;
+    /// let keypair = KeyPair::new();
+    /// let mut builder = Biscuit::builder();
+    /// builder.add_fact("user(\"John Doe\", 42)").unwrap();
+    /// let biscuit = builder.build(&keypair).unwrap();
+    ///
+    /// let mut params = HashMap::new();
+    /// params.insert("name".to_string(), string("John Doe"));
+    ///
+    /// let mut authorizer = biscuit.authorizer().unwrap();
+    /// let res: Vec<(i64,)> = authorizer
+    ///     .query_with_params("data($id) <- user({name}, $id)", params)
+    ///     .unwrap();
+    /// assert_eq!(res, vec![(42,)]);
+    /// ```
+    pub fn query_with_params<T: TryFrom<Fact, Error = E>, E: Into<error::Token>>(
+        &mut self,
+        rule: &str,
+        params: HashMap<String, Term>,
+    ) -> Result<Vec<T>, error::Token> {
+        let mut rule: Rule = rule.try_into()?;
+        for (name, value) in &params {
+            let res = match rule.set(name, value) {
+                Ok(_) => Ok(()),
+                Err(error::Token::Language(biscuit_parser::error::LanguageError::Parameters {
+                    missing_parameters,
+                    ..
+                })) if missing_parameters.is_empty() => Ok(()),
+                Err(e) => Err(e),
+            };
+            res?;
+        }
+        rule.validate_parameters()?;
+
+        self.query(rule)
+    }
+
     /// run a query over the authorizer's Datalog engine to gather data
     ///
     /// this only sees facts from the authorizer and the authority block
@@ -460,9 +819,9 @@ impl Authorizer {
     {
         let rule = rule.try_into()?.convert(&mut self.symbols);
 
-        let start = Instant::now();
+        let start = self.clock.instant();
         let result = self.query_inner(rule, limits);
-        self.execution_time += start.elapsed();
+        self.execution_time += self.clock.instant() - start;
 
         result
     }
@@ -482,14 +841,20 @@ impl Authorizer {
             &self.public_key_to_block_id,
         );
 
-        self.world.run_with_limits(&self.symbols, limits)?;
-        let res = self
-            .world
-            .query_rule(rule, usize::MAX, &rule_trusted_origins, &self.symbols)?;
+        let max_results = limits.max_results;
+        self.world
+            .run_with_clock(&self.symbols, limits, self.clock.as_ref())?;
+        let res = self.world.query_rule_with_limit(
+            rule,
+            usize::MAX,
+            &rule_trusted_origins,
+            &self.symbols,
+            max_results,
+        )?;
 
         res.inner
-            .into_iter()
-            .flat_map(|(_, set)| set.into_iter())
+            .into_values()
+            .flat_map(datalog::unwrap_facts)
             .map(|f| Fact::convert_from(&f, &self.symbols))
             .map(|fact| {
                 fact.map_err(error::Token::Format)
@@ -498,6 +863,43 @@ impl Authorizer {
             .collect()
     }
 
+    /// runs a query on the facts of a namespace declared with
+    /// [`AuthorizerBuilder::facts_namespaced`](crate::AuthorizerBuilder::facts_namespaced)
+    ///
+    /// the predicates of the rule body are prefixed with `namespace::`, so
+    /// `data($u) <- user($u)` queries `db::user` in the `db` namespace. The
+    /// query is then run like [`Authorizer::query`]
+    pub fn query_namespaced<R: TryInto<Rule>, T: TryFrom<Fact, Error = E>, E: Into<error::Token>>(
+        &mut self,
+        namespace: &str,
+        rule: R,
+    ) -> Result<Vec<T>, error::Token>
+    where
+        error::Token: From<<R as TryInto<Rule>>::Error>,
+    {
+        let rule = namespaced_rule(namespace, rule.try_into()?);
+        self.query::<Rule, T, E>(rule)
+    }
+
+    /// runs a query on the facts of a namespace, like [`Authorizer::query_all`]
+    ///
+    /// see [`Authorizer::query_namespaced`]
+    pub fn query_all_namespaced<
+        R: TryInto<Rule>,
+        T: TryFrom<Fact, Error = E>,
+        E: Into<error::Token>,
+    >(
+        &mut self,
+        namespace: &str,
+        rule: R,
+    ) -> Result<Vec<T>, error::Token>
+    where
+        error::Token: From<<R as TryInto<Rule>>::Error>,
+    {
+        let rule = namespaced_rule(namespace, rule.try_into()?);
+        self.query_all::<Rule, T, E>(rule)
+    }
+
     /// run a query over the authorizer's Datalog engine to gather data
     ///
     /// this has access to the facts generated when evaluating all the blocks
@@ -553,9 +955,9 @@ impl Authorizer {
     {
         let rule = rule.try_into()?.convert(&mut self.symbols);
 
-        let start = Instant::now();
+        let start = self.clock.instant();
         let result = self.query_all_inner(rule, limits);
-        self.execution_time += start.elapsed();
+        self.execution_time += self.clock.instant() - start;
 
         result
     }
@@ -565,9 +967,46 @@ impl Authorizer {
         rule: datalog::Rule,
         limits: AuthorizerLimits,
     ) -> Result<Vec<T>, error::Token> {
-        self.world.run_with_limits(&self.symbols, limits)?;
+        let r = self.query_all_facts(rule, limits)?;
 
-        let rule_trusted_origins = if rule.scopes.is_empty() {
+        r.into_iter()
+            .map(|f| Fact::convert_from(&f, &self.symbols))
+            .map(|fact| {
+                fact.map_err(error::Token::Format)
+                    .and_then(|f| f.try_into().map_err(Into::into))
+            })
+            .collect::<Result<Vec<T>, _>>()
+    }
+
+    fn query_all_facts(
+        &mut self,
+        rule: datalog::Rule,
+        limits: AuthorizerLimits,
+    ) -> Result<HashSet<datalog::Fact>, error::Token> {
+        let max_results = limits.max_results;
+        let rule_trusted_origins = self.prepare_query(&rule, limits)?;
+
+        let res = self.world.query_rule_with_limit(
+            rule,
+            0,
+            &rule_trusted_origins,
+            &self.symbols,
+            max_results,
+        )?;
+
+        Ok(res.into_iter().map(|(_, fact)| fact).collect())
+    }
+
+    /// runs the rules of the world, then returns the origins trusted by a query
+    fn prepare_query(
+        &mut self,
+        rule: &datalog::Rule,
+        limits: AuthorizerLimits,
+    ) -> Result<TrustedOrigins, error::Token> {
+        self.world
+            .run_with_clock(&self.symbols, limits, self.clock.as_ref())?;
+
+        Ok(if rule.scopes.is_empty() {
             self.token_origins.clone()
         } else {
             TrustedOrigins::from_scopes(
@@ -579,47 +1018,303 @@ impl Authorizer {
                 usize::MAX,
                 &self.public_key_to_block_id,
             )
+        })
+    }
+
+    /// run a query like [`Authorizer::query_all`], returning an iterator over
+    /// the results
+    ///
+    /// the rules of the authorizer run when this is called, then the query
+    /// is evaluated as the iterator advances: facts are produced and
+    /// converted to `T` one at a time, and stopping the iteration early
+    /// stops the query. Only the facts already returned are kept, to skip
+    /// duplicates. Once [`AuthorizerLimits::max_results`] facts were
+    /// returned, the next item is `RunLimit::TooManyResults` and the
+    /// iteration ends.
+    ///
+    /// Rules with aggregates need all their matches, so their results are
+    /// gathered before the first item is returned
+    ///
+    /// ```rust
+    /// # use biscuit_auth::{AuthorizerLimits, Biscuit, KeyPair};
+    /// # use std::time::Duration;
+    /// let mut builder = Biscuit::builder();
+    /// builder.add_code(r#"user("alice"); user("bob"); user("carol");"#).unwrap();
+    /// let biscuit = builder.build(&KeyPair::new()).unwrap();
+    ///
+    /// let mut authorizer = biscuit.authorizer().unwrap();
+    /// authorizer.set_limits(AuthorizerLimits {
+    ///     max_results: Some(10),
+    ///     max_time: Duration::from_secs(1),
+    ///     ..Default::default()
+    /// });
+    ///
+    /// let mut users = Vec::new();
+    /// for user in authorizer.query_iter("data($u) <- user($u)").unwrap() {
+    ///     let (user,): (String,) = user.unwrap();
+    ///     users.push(user);
+    /// }
+    /// users.sort();
+    /// assert_eq!(users, vec!["alice", "bob", "carol"]);
+    /// ```
+    pub fn query_iter<R: TryInto<Rule>, T: TryFrom<Fact, Error = E>, E: Into<error::Token>>(
+        &mut self,
+        rule: R,
+    ) -> Result<impl Iterator<Item = Result<T, error::Token>> + '_, error::Token>
+    where
+        error::Token: From<<R as TryInto<Rule>>::Error>,
+    {
+        let limits = self.remaining_limits()?;
+        let max_results = limits.max_results.unwrap_or(u64::MAX);
+        let rule = rule.try_into()?.convert(&mut self.symbols);
+
+        let start = self.clock.instant();
+        let facts: Box<dyn Iterator<Item = Result<datalog::Fact, error::Token>> + '_> = if rule
+            .aggregates
+            .is_empty()
+        {
+            let origins = self.prepare_query(&rule, limits);
+            self.execution_time += self.clock.instant() - start;
+
+            let (rule, origins) = &*self.streamed_query.insert((rule, origins?));
+            let mut returned = HashSet::new();
+            Box::new(
+                rule.apply(self.world.facts.iterator(origins), 0, &self.symbols)
+                    .filter_map(move |res| match res {
+                        Ok((_, fact)) => {
+                            if returned.contains(&fact) {
+                                None
+                            } else if returned.len() as u64 >= max_results {
+                                Some(Err(error::Token::RunLimit(error::RunLimit::TooManyResults)))
+                            } else {
+                                returned.insert(fact.clone());
+                                Some(Ok(fact))
+                            }
+                        }
+                        Err(e) => Some(Err(error::Execution::Expression(e).into())),
+                    }),
+            )
+        } else {
+            let result = self.query_all_facts(rule, limits);
+            self.execution_time += self.clock.instant() - start;
+            Box::new(result?.into_iter().map(Ok))
         };
 
-        let res = self
-            .world
-            .query_rule(rule, 0, &rule_trusted_origins, &self.symbols)?;
+        let symbols = &self.symbols;
+        let mut failed = false;
+        Ok(facts
+            .take_while(move |res| !std::mem::replace(&mut failed, res.is_err()))
+            .map(move |res| {
+                res.and_then(|f| {
+                    Fact::convert_from(&f, symbols)
+                        .map_err(error::Token::Format)
+                        .and_then(|f| f.try_into().map_err(Into::into))
+                })
+            }))
+    }
+
+    /// run a query like [`Authorizer::query`], returning the origin of each fact
+    ///
+    /// the origin contains the ids of the blocks the fact was generated from,
+    /// `0` being the authority block and `usize::MAX` the authorizer. A fact
+    /// produced from facts of several blocks is returned once per origin.
+    ///
+    /// ```rust
+    /// # use biscuit_auth::{Biscuit, KeyPair};
+    /// let keypair = KeyPair::new();
+    /// let mut builder = Biscuit::builder();
+    /// builder.add_fact("user(\"alice\")").unwrap();
+    /// let biscuit = builder.build(&keypair).unwrap();
+    ///
+    /// let mut authorizer = biscuit.authorizer().unwrap();
+    /// authorizer.add_code("user(\"bob\"); allow if true").unwrap();
+    /// authorizer.authorize().unwrap();
+    ///
+    /// let mut res: Vec<_> = authorizer
+    ///     .query_with_origin("data($u) <- user($u)")
+    ///     .unwrap()
+    ///     .into_iter()
+    ///     .map(|(origin, (user,)): (_, (String,))| (origin.to_string(), user))
+    ///     .collect();
+    /// res.sort();
+    /// assert_eq!(
+    ///     res,
+    ///     vec![
+    ///         ("0".to_string(), "alice".to_string()),
+    ///         ("authorizer".to_string(), "bob".to_string()),
+    ///     ]
+    /// );
+    /// ```
+    pub fn query_with_origin<R, T, E>(&mut self, rule: R) -> Result<Vec<(Origin, T)>, error::Token>
+    where
+        R: TryInto<Rule>,
+        T: TryFrom<Fact, Error = E>,
+        E: Into<error::Token>,
+        error::Token: From<<R as TryInto<Rule>>::Error>,
+    {
+        let limits = self.remaining_limits()?;
+        let rule = rule.try_into()?.convert(&mut self.symbols);
+        let rule_trusted_origins = TrustedOrigins::from_scopes(
+            &rule.scopes,
+            &TrustedOrigins::default(),
+            usize::MAX,
+            &self.public_key_to_block_id,
+        );
 
-        let r: HashSet<_> = res.into_iter().map(|(_, fact)| fact).collect();
+        self.query_with_origin_inner(rule, &rule_trusted_origins, limits)
+    }
 
-        r.into_iter()
-            .map(|f| Fact::convert_from(&f, &self.symbols))
-            .map(|fact| {
-                fact.map_err(error::Token::Format)
-                    .and_then(|f| f.try_into().map_err(Into::into))
+    /// run a query like [`Authorizer::query_all`], returning the origin of each
+    /// fact, see [`Authorizer::query_with_origin`]
+    pub fn query_all_with_origin<R, T, E>(
+        &mut self,
+        rule: R,
+    ) -> Result<Vec<(Origin, T)>, error::Token>
+    where
+        R: TryInto<Rule>,
+        T: TryFrom<Fact, Error = E>,
+        E: Into<error::Token>,
+        error::Token: From<<R as TryInto<Rule>>::Error>,
+    {
+        let limits = self.remaining_limits()?;
+        let rule = rule.try_into()?.convert(&mut self.symbols);
+        let rule_trusted_origins = if rule.scopes.is_empty() {
+            self.token_origins.clone()
+        } else {
+            TrustedOrigins::from_scopes(
+                &rule.scopes,
+                &TrustedOrigins::default(),
+                usize::MAX,
+                &self.public_key_to_block_id,
+            )
+        };
+
+        self.query_with_origin_inner(rule, &rule_trusted_origins, limits)
+    }
+
+    fn query_with_origin_inner<T: TryFrom<Fact, Error = E>, E: Into<error::Token>>(
+        &mut self,
+        rule: datalog::Rule,
+        rule_trusted_origins: &TrustedOrigins,
+        limits: AuthorizerLimits,
+    ) -> Result<Vec<(Origin, T)>, error::Token> {
+        let start = self.clock.instant();
+        let result = self
+            .world
+            .run_with_clock(&self.symbols, limits.clone(), self.clock.as_ref())
+            .and_then(|_| {
+                self.world.query_rule_provenance(
+                    rule,
+                    rule_trusted_origins,
+                    &self.symbols,
+                    limits.max_results,
+                )
+            });
+        self.execution_time += self.clock.instant() - start;
+
+        result?
+            .into_iter()
+            .map(|(origin, fact)| {
+                let fact = Fact::convert_from(&fact, &self.symbols)?;
+                Ok((origin, fact.try_into().map_err(Into::into)?))
             })
-            .collect::<Result<Vec<T>, _>>()
+            .collect()
     }
 
     /// adds a fact with the current time
+    ///
+    /// the time is read from the authorizer's [`Clock`], see [`Authorizer::set_clock`].
+    /// The default [`SystemClock`] panics on `wasm32` targets without the `wasm` feature
     pub fn set_time(&mut self) {
-        let fact = fact("time", &[date(&SystemTime::now())]);
+        let fact = fact("time", &[date(&self.clock.now())]);
         self.authorizer_block_builder.add_fact(fact).unwrap();
     }
 
-    /// add a policy to the authorizer
-    pub fn add_policy<P: TryInto<Policy>>(&mut self, policy: P) -> Result<(), error::Token>
-    where
-        error::Token: From<<P as TryInto<Policy>>::Error>,
-    {
-        let policy = policy.try_into()?;
-        policy.validate_parameters()?;
-        self.policies.push(policy);
-        Ok(())
+    /// adds a `challenge` fact, accepting tokens bound to this challenge
+    ///
+    /// see [`Biscuit::bind_challenge`]. The time must be provided too, with
+    /// [`Authorizer::set_time`] or a `time` fact, to verify the challenge's expiration
+    pub fn add_challenge(&mut self, challenge: &[u8]) {
+        let fact = fact("challenge", &[bytes(challenge)]);
+        self.authorizer_block_builder.add_fact(fact).unwrap();
     }
 
-    /// todo remove, it's covered in BuilderExt
-    /// adds a `allow if true` policy
-    pub fn allow(&mut self) -> Result<(), error::Token> {
-        self.add_policy("allow if true")
+    /// reserves space in the symbol table for `additional` more strings
+    ///
+    /// this avoids growing the table repeatedly before loading a large
+    /// number of facts with unique strings
+    pub fn reserve_symbols(&mut self, additional: usize) {
+        self.symbols.reserve(additional);
     }
 
-    /// adds a `deny if true` policy
+    /// converts the authorizer's facts, rules, checks and policies, and moves
+    /// their symbols to a table shared with the clones of this authorizer
+    ///
+    /// servers usually create an authorizer with their facts and policies,
+    /// then clone it for each request: with shared symbols, the clones do not
+    /// copy or intern these strings again. The shared symbols are kept by
+    /// [`Authorizer::reset_token_data`]. See also [`SymbolTable::share`]
+    ///
+    /// ```rust
+    /// use biscuit_auth::{Authorizer, Biscuit, KeyPair};
+    ///
+    /// let mut base = Authorizer::new();
+    /// base.add_code(r#"group("admins"); allow if user($u), member($u, "admins");"#)
+    ///     .unwrap();
+    /// base.share_symbols();
+    ///
+    /// let root = KeyPair::new();
+    /// let mut builder = Biscuit::builder();
+    /// builder
+    ///     .add_code(r#"user("alice"); member("alice", "admins");"#)
+    ///     .unwrap();
+    /// let token = builder.build(&root).unwrap();
+    ///
+    /// let mut authorizer = base.clone();
+    /// authorizer.add_token(&token).unwrap();
+    /// assert_eq!(authorizer.authorize(), Ok(0));
+    /// ```
+    pub fn share_symbols(&mut self) {
+        self.prepare_authorizer_block();
+        self.symbols.share();
+    }
+
+    /// creates a template authorizer that can be shared between threads and
+    /// cheaply copied for each request, see [`FrozenAuthorizer`]
+    ///
+    /// fails if the authorizer already contains a token
+    pub fn freeze(self) -> Result<FrozenAuthorizer, error::Token> {
+        FrozenAuthorizer::try_from(self)
+    }
+
+    /// sets the [`Clock`] used by [`Authorizer::set_time`] and to measure
+    /// the execution time against [`AuthorizerLimits::max_time`]
+    ///
+    /// by default, the authorizer uses the system time. A custom clock makes
+    /// expiration checks deterministic in tests, or lets WebAssembly hosts
+    /// provide the time
+    pub fn set_clock<C: Clock + 'static>(&mut self, clock: C) {
+        self.clock = Arc::new(clock);
+    }
+
+    /// add a policy to the authorizer
+    pub fn add_policy<P: TryInto<Policy>>(&mut self, policy: P) -> Result<(), error::Token>
+    where
+        error::Token: From<<P as TryInto<Policy>>::Error>,
+    {
+        let policy = policy.try_into()?;
+        policy.validate_parameters()?;
+        self.policies.push(policy);
+        Ok(())
+    }
+
+    /// todo remove, it's covered in BuilderExt
+    /// adds a `allow if true` policy
+    pub fn allow(&mut self) -> Result<(), error::Token> {
+        self.add_policy("allow if true")
+    }
+
+    /// adds a `deny if true` policy
     pub fn deny(&mut self) -> Result<(), error::Token> {
         self.add_policy("deny if true")
     }
@@ -634,6 +1329,14 @@ impl Authorizer {
         self.world.iterations
     }
 
+    /// returns statistics on the fact index used when applying rules
+    ///
+    /// facts are indexed by predicate name and first term: rules are faster when the
+    /// first term of their predicates is a value or a variable bound by a previous predicate
+    pub fn index_stats(&self) -> IndexStats {
+        self.world.index_stats.clone()
+    }
+
     /// returns the number of facts
     pub fn fact_count(&self) -> usize {
         self.world.facts.len()
@@ -644,16 +1347,98 @@ impl Authorizer {
     /// on error, this can return a list of all the failed checks or deny policy
     /// on success, it returns the index of the policy that matched
     pub fn authorize(&mut self) -> Result<usize, error::Token> {
-        let mut limits = self.limits.clone();
-        limits.max_iterations -= self.world.iterations;
-        if self.execution_time >= limits.max_time {
-            return Err(error::Token::RunLimit(error::RunLimit::Timeout));
-        }
-        limits.max_time -= self.execution_time;
+        let limits = self.remaining_limits()?;
 
         self.authorize_with_limits(limits)
     }
 
+    /// authorizes a list of tokens against this authorizer's facts, rules, checks and policies
+    ///
+    /// this is equivalent to cloning the authorizer and calling [`Authorizer::add_token`]
+    /// then [`Authorizer::authorize`] for each token, but the authorizer's symbols and
+    /// facts are only converted once, instead of once per token.
+    /// The authorizer must not contain a token already.
+    pub fn authorize_batch(&self, tokens: &[Biscuit]) -> Vec<Result<usize, error::Token>> {
+        let mut prepared = self.clone();
+        if prepared.blocks.is_none() {
+            prepared.prepare_authorizer_block();
+        }
+
+        tokens
+            .iter()
+            .map(|token| {
+                let mut authorizer = prepared.clone();
+                authorizer.add_token(token)?;
+                authorizer.authorize()
+            })
+            .collect()
+    }
+
+    /// creates a copy of this authorizer specialized for a set of constant facts
+    ///
+    /// the constant facts are added to the authorizer's facts, then the authorizer's
+    /// rules are evaluated on them: the generated facts are stored in the copy, so
+    /// that they are not derived again for each request. The copy can then be cloned
+    /// and loaded with a token for each request, as with [`Authorizer::new`].
+    ///
+    /// The evaluation uses the authorizer's limits, but its iterations and
+    /// execution time are not counted in the copy. The authorizer must not
+    /// contain a token.
+    pub fn specialize<I, F>(&self, facts: I) -> Result<Authorizer, error::Token>
+    where
+        I: IntoIterator<Item = F>,
+        F: TryInto<Fact>,
+        error::Token: From<<F as TryInto<Fact>>::Error>,
+    {
+        if self.blocks.is_some() {
+            return Err(error::Logic::AuthorizerNotEmpty.into());
+        }
+
+        let mut specialized = self.clone();
+        for fact in facts {
+            specialized.add_fact(fact)?;
+        }
+
+        specialized.prepare_authorizer_block();
+        specialized.load_authorizer_block();
+        specialized.world.run_with_clock(
+            &specialized.symbols,
+            specialized.limits.clone(),
+            specialized.clock.as_ref(),
+        )?;
+
+        // the rules are loaded again on authorization
+        specialized.world.rules = datalog::RuleSet::default();
+        specialized.world.iterations = 0;
+
+        Ok(specialized)
+    }
+
+    /// loads the authorizer facts in the world, and adds the symbols of the
+    /// authorizer rules, checks and policies to the symbol table, so that they
+    /// are not converted again by each copy of this authorizer
+    fn prepare_authorizer_block(&mut self) {
+        let mut authorizer_origin = Origin::default();
+        authorizer_origin.insert(usize::MAX);
+
+        for fact in std::mem::take(&mut self.authorizer_block_builder.facts) {
+            self.world
+                .add_fact(&authorizer_origin, fact.convert(&mut self.symbols));
+        }
+
+        for rule in &self.authorizer_block_builder.rules {
+            rule.convert(&mut self.symbols);
+        }
+        for check in &self.authorizer_block_builder.checks {
+            check.convert(&mut self.symbols);
+        }
+        for policy in &self.policies {
+            for query in &policy.queries {
+                query.convert(&mut self.symbols);
+            }
+        }
+    }
+
     /// TODO: consume the input to prevent further direct use
     /// verifies the checks and policies
     ///
@@ -664,43 +1449,405 @@ impl Authorizer {
         &mut self,
         limits: AuthorizerLimits,
     ) -> Result<usize, error::Token> {
-        let start = Instant::now();
-        let result = self.authorize_inner(limits);
-        self.execution_time += start.elapsed();
+        let start = self.clock.instant();
+        let result = self.authorize_inner(limits, true, None, None);
+        self.execution_time += self.clock.instant() - start;
+
+        result
+    }
+
+    /// verifies the checks and policies, without rendering the source of failed checks
+    ///
+    /// this is meant for latency sensitive callers: the checks are evaluated
+    /// directly on the loaded Datalog, and failed checks are only reported by
+    /// position, with an empty `rule` field. The source of the checks can then
+    /// be rendered on demand with [`Authorizer::render_failed_checks`].
+    /// Loading the authorizer's block and evaluating the checks still allocate.
+    ///
+    /// The result is otherwise the same as [`Authorizer::authorize`].
+    pub fn authorize_in_place(&mut self) -> Result<usize, error::Token> {
+        let limits = self.remaining_limits()?;
+
+        let start = self.clock.instant();
+        let result = self.authorize_inner(limits, false, None, None);
+        self.execution_time += self.clock.instant() - start;
+
+        result
+    }
+
+    /// verifies the checks and policies like [`Authorizer::authorize`], reporting
+    /// to `tracer` each fact produced by a rule while the Datalog is evaluated
+    ///
+    /// ```rust
+    /// use biscuit_auth::{datalog::RuleFiring, Biscuit, KeyPair};
+    ///
+    /// let root = KeyPair::new();
+    /// let mut builder = Biscuit::builder();
+    /// builder.add_code(r#"user("alice"); admin($u) <- user($u);"#).unwrap();
+    /// let token = builder.build(&root).unwrap();
+    ///
+    /// let mut authorizer = token.authorizer().unwrap();
+    /// authorizer.add_code("allow if admin($u)").unwrap();
+    ///
+    /// let mut trace = Vec::new();
+    /// authorizer
+    ///     .authorize_with_tracer(&mut |firing: &RuleFiring| {
+    ///         trace.push((firing.print_rule(), firing.print_fact()))
+    ///     })
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     trace[0],
+    ///     ("admin($u) <- user($u)".to_string(), "admin(\"alice\")".to_string())
+    /// );
+    /// ```
+    pub fn authorize_with_tracer<T: Tracer>(
+        &mut self,
+        tracer: &mut T,
+    ) -> Result<usize, error::Token> {
+        let limits = self.remaining_limits()?;
+
+        let start = self.clock.instant();
+        let result = self.authorize_inner(limits, true, Some(tracer), None);
+        self.execution_time += self.clock.instant() - start;
+
+        result
+    }
+
+    /// verifies the checks and policies like [`Authorizer::authorize`], measuring
+    /// the evaluation of each rule and check
+    ///
+    /// the profile is returned even if the authorization failed, to find which
+    /// rule made the execution hit the time or iteration limits
+    ///
+    /// ```rust
+    /// use biscuit_auth::Authorizer;
+    ///
+    /// let mut authorizer = Authorizer::new();
+    /// authorizer
+    ///     .add_code(
+    ///         r#"parent("a", "b"); parent("b", "c");
+    ///         ancestor($x, $y) <- parent($x, $y);
+    ///         ancestor($x, $z) <- ancestor($x, $y), parent($y, $z);
+    ///         check if ancestor("a", "c");
+    ///         allow if true;"#,
+    ///     )
+    ///     .unwrap();
+    ///
+    /// let (result, profile) = authorizer.profile();
+    /// assert_eq!(result, Ok(0));
+    /// assert_eq!(profile.rules.len(), 2);
+    /// assert_eq!(profile.rules[1].rule, "ancestor($x, $z) <- ancestor($x, $y), parent($y, $z)");
+    /// assert_eq!(profile.rules[1].facts, 1);
+    /// assert_eq!(profile.checks[0].check, r#"check if ancestor("a", "c")"#);
+    /// ```
+    pub fn profile(&mut self) -> (Result<usize, error::Token>, AuthorizerProfile) {
+        let mut profile = AuthorizerProfile::default();
+        let limits = match self.remaining_limits() {
+            Ok(limits) => limits,
+            Err(e) => return (Err(e), profile),
+        };
+
+        let start = self.clock.instant();
+        let result = self.authorize_inner(limits, true, None, Some(&mut profile));
+        self.execution_time += self.clock.instant() - start;
+
+        (result, profile)
+    }
+
+    /// fills the `rule` field of the failed checks returned by [`Authorizer::authorize_in_place`]
+    pub fn render_failed_checks(&self, error: &mut error::Token) {
+        let checks = match error {
+            error::Token::FailedLogic(error::Logic::Unauthorized { checks, .. })
+            | error::Token::FailedLogic(error::Logic::NoMatchingPolicy { checks }) => checks,
+            _ => return,
+        };
+
+        let mut symbols = self.symbols.clone();
+        for check in checks.iter_mut() {
+            match check {
+                error::FailedCheck::Authorizer(error::FailedAuthorizerCheck {
+                    check_id,
+                    rule,
+                    ..
+                }) if rule.is_empty() => {
+                    if let Some(c) = self.authorizer_block_builder.checks.get(*check_id as usize) {
+                        let c = c.convert(&mut symbols);
+                        *rule = symbols.print_check(&c);
+                    }
+                }
+                error::FailedCheck::Block(error::FailedBlockCheck {
+                    block_id,
+                    check_id,
+                    rule,
+                    ..
+                }) if rule.is_empty() => {
+                    if let Some(c) = self
+                        .blocks
+                        .as_ref()
+                        .and_then(|blocks| blocks.get(*block_id as usize))
+                        .and_then(|block| block.checks.get(*check_id as usize))
+                    {
+                        *rule = self.symbols.print_check(c);
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// verifies the checks and policies, yielding to the async executor
+    /// between Datalog iterations
+    ///
+    /// this behaves like [`Authorizer::authorize`], but will not hold the
+    /// executor's thread for the whole Datalog evaluation, so it can be
+    /// awaited from a request handler without a dedicated blocking thread.
+    /// It does not depend on a specific async runtime.
+    ///
+    /// The time limit is measured on the wall clock, so time spent waiting
+    /// to be polled again counts against it.
+    #[cfg(feature = "async")]
+    #[cfg_attr(feature = "docsrs", doc(cfg(feature = "async")))]
+    pub async fn authorize_async(&mut self) -> Result<usize, error::Token> {
+        let limits = self.remaining_limits()?;
+
+        self.authorize_with_limits_async(limits).await
+    }
+
+    /// verifies the checks and policies, yielding to the async executor
+    /// between Datalog iterations
+    ///
+    /// this method overrides the authorizer's runtime limits, just for this calls
+    #[cfg(feature = "async")]
+    #[cfg_attr(feature = "docsrs", doc(cfg(feature = "async")))]
+    pub async fn authorize_with_limits_async(
+        &mut self,
+        limits: AuthorizerLimits,
+    ) -> Result<usize, error::Token> {
+        let start = self.clock.instant();
+        let result = self.authorize_inner_async(limits).await;
+        self.execution_time += self.clock.instant() - start;
+
+        result
+    }
+
+    /// runtime limits minus what was already consumed by previous executions
+    fn remaining_limits(&self) -> Result<AuthorizerLimits, error::Token> {
+        let mut limits = self.limits.clone();
+        limits.max_iterations -= self.world.iterations;
+        if self.execution_time >= limits.max_time {
+            return Err(error::Token::RunLimit(error::RunLimit::Timeout));
+        }
+        limits.max_time -= self.execution_time;
+
+        Ok(limits)
+    }
+
+    fn authorize_inner(
+        &mut self,
+        limits: AuthorizerLimits,
+        render_checks: bool,
+        tracer: Option<&mut dyn Tracer>,
+        profile: Option<&mut AuthorizerProfile>,
+    ) -> Result<usize, error::Token> {
+        let start = self.clock.instant();
+        let start_iterations = self.world.iterations;
+
+        let result = self.authorize_world(limits, render_checks, tracer, profile);
+        self.notify_observer(start, start_iterations, &result);
 
         result
     }
 
-    fn authorize_inner(&mut self, mut limits: AuthorizerLimits) -> Result<usize, error::Token> {
-        let start = Instant::now();
+    fn authorize_world(
+        &mut self,
+        mut limits: AuthorizerLimits,
+        render_checks: bool,
+        mut tracer: Option<&mut dyn Tracer>,
+        mut profile: Option<&mut AuthorizerProfile>,
+    ) -> Result<usize, error::Token> {
+        self.check_revocation()?;
+        self.check_audience()?;
+        self.check_sealed()?;
+
+        let start = self.clock.instant();
         let time_limit = start + limits.max_time;
         let mut current_iterations = self.world.iterations;
 
         let mut errors = vec![];
-        let mut policy_result: Option<Result<usize, usize>> = None;
 
+        self.load_authorizer_block();
+
+        limits.max_time = time_limit - self.clock.instant();
+        self.run_world(limits.clone(), &mut tracer, &mut profile)?;
+
+        let policy_result = self.check_authority(
+            time_limit,
+            render_checks,
+            &mut errors,
+            profile.as_deref_mut(),
+        )?;
+
+        for i in 1..self.blocks.as_ref().map(|b| b.len()).unwrap_or(0) {
+            limits.max_time = time_limit - self.clock.instant();
+            limits.max_iterations -= self.world.iterations - current_iterations;
+            current_iterations = self.world.iterations;
+
+            self.run_world(limits.clone(), &mut tracer, &mut profile)?;
+
+            self.check_block(
+                i,
+                time_limit,
+                render_checks,
+                &mut errors,
+                profile.as_deref_mut(),
+            )?;
+        }
+
+        authorization_result(policy_result, errors)
+    }
+
+    fn run_world(
+        &mut self,
+        limits: AuthorizerLimits,
+        tracer: &mut Option<&mut dyn Tracer>,
+        profile: &mut Option<&mut AuthorizerProfile>,
+    ) -> Result<(), error::Execution> {
+        match (tracer, profile) {
+            (Some(tracer), _) => self.world.run_with_clock_and_tracer(
+                &self.symbols,
+                limits,
+                self.clock.as_ref(),
+                &mut **tracer,
+            ),
+            (None, Some(profile)) => self.world.run_with_clock_and_hooks(
+                &self.symbols,
+                limits,
+                self.clock.as_ref(),
+                &mut ProfileHooks::new(profile, &self.symbols, self.clock.as_ref()),
+            ),
+            (None, None) => self
+                .world
+                .run_with_clock(&self.symbols, limits, self.clock.as_ref()),
+        }
+    }
+
+    #[cfg(feature = "async")]
+    async fn authorize_inner_async(
+        &mut self,
+        limits: AuthorizerLimits,
+    ) -> Result<usize, error::Token> {
+        let start = self.clock.instant();
+        let start_iterations = self.world.iterations;
+
+        let result = self.authorize_world_async(limits).await;
+        self.notify_observer(start, start_iterations, &result);
+
+        result
+    }
+
+    #[cfg(feature = "async")]
+    async fn authorize_world_async(
+        &mut self,
+        mut limits: AuthorizerLimits,
+    ) -> Result<usize, error::Token> {
+        self.check_revocation()?;
+        self.check_audience()?;
+        self.check_sealed()?;
+
+        let start = self.clock.instant();
+        let time_limit = start + limits.max_time;
+        let mut current_iterations = self.world.iterations;
+
+        let mut errors = vec![];
+
+        self.load_authorizer_block();
+
+        limits.max_time = time_limit - self.clock.instant();
+        self.world
+            .run_with_clock_async(&self.symbols, limits.clone(), self.clock.as_ref())
+            .await?;
+
+        let policy_result = self.check_authority(time_limit, true, &mut errors, None)?;
+
+        for i in 1..self.blocks.as_ref().map(|b| b.len()).unwrap_or(0) {
+            crate::future::yield_now().await;
+
+            limits.max_time = time_limit - self.clock.instant();
+            limits.max_iterations -= self.world.iterations - current_iterations;
+            current_iterations = self.world.iterations;
+
+            self.world
+                .run_with_clock_async(&self.symbols, limits.clone(), self.clock.as_ref())
+                .await?;
+
+            self.check_block(i, time_limit, true, &mut errors, None)?;
+        }
+
+        authorization_result(policy_result, errors)
+    }
+
+    fn check_sealed(&self) -> Result<(), error::Token> {
+        if self.require_sealed && !self.token_sealed {
+            Err(error::Logic::SealRequired.into())
+        } else {
+            Ok(())
+        }
+    }
+
+    fn check_audience(&self) -> Result<(), error::Token> {
+        if self.expected_audiences.is_empty() {
+            return Ok(());
+        }
+
+        let mut actual = vec![];
+        if let (Some(blocks), Some(name)) = (self.blocks.as_ref(), self.symbols.get("audience")) {
+            for fact in &blocks[0].facts {
+                if let (true, [datalog::Term::Str(s)]) =
+                    (fact.predicate.name == name, fact.predicate.terms.as_slice())
+                {
+                    actual.push(self.symbols.print_symbol(*s)?);
+                }
+            }
+        }
+
+        if actual.iter().any(|a| self.expected_audiences.contains(a)) {
+            Ok(())
+        } else {
+            Err(error::Logic::AudienceMismatch {
+                expected: self.expected_audiences.clone(),
+                actual,
+            }
+            .into())
+        }
+    }
+
+    fn check_revocation(&self) -> Result<(), error::Token> {
+        if let Some(store) = &self.revocation_store {
+            let revoked = if self.security_policy.constant_time_comparisons {
+                store.revoked_constant_time(&self.revocation_ids)
+            } else {
+                store.revoked(&self.revocation_ids)
+            };
+            if !revoked.is_empty() {
+                return Err(error::Token::Revoked {
+                    block_ids: revoked.into_iter().map(|i| i as u32).collect(),
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// loads the authorizer's facts and rules in the Datalog world
+    fn load_authorizer_block(&mut self) {
         let mut authorizer_origin = Origin::default();
         authorizer_origin.insert(usize::MAX);
 
-        let authorizer_scopes: Vec<token::Scope> = self
-            .authorizer_block_builder
-            .scopes
-            .clone()
-            .iter()
-            .map(|s| s.convert(&mut self.symbols))
-            .collect();
-
-        let authorizer_trusted_origins = TrustedOrigins::from_scopes(
-            &authorizer_scopes,
-            &TrustedOrigins::default(),
-            usize::MAX,
-            &self.public_key_to_block_id,
-        );
+        let authorizer_trusted_origins = self.authorizer_trusted_origins();
 
         for fact in &self.authorizer_block_builder.facts {
             self.world
-                .facts
-                .insert(&authorizer_origin, fact.convert(&mut self.symbols));
+                .add_fact(&authorizer_origin, fact.convert(&mut self.symbols));
         }
 
         for rule in &self.authorizer_block_builder.rules {
@@ -717,10 +1864,9 @@ impl Authorizer {
                 .rules
                 .insert(usize::MAX, &rule_trusted_origins, rule);
         }
+    }
 
-        limits.max_time = time_limit - Instant::now();
-        self.world.run_with_limits(&self.symbols, limits.clone())?;
-
+    fn authorizer_trusted_origins(&mut self) -> TrustedOrigins {
         let authorizer_scopes: Vec<token::Scope> = self
             .authorizer_block_builder
             .scopes
@@ -729,16 +1875,33 @@ impl Authorizer {
             .map(|s| s.convert(&mut self.symbols))
             .collect();
 
-        let authorizer_trusted_origins = TrustedOrigins::from_scopes(
+        TrustedOrigins::from_scopes(
             &authorizer_scopes,
             &TrustedOrigins::default(),
             usize::MAX,
             &self.public_key_to_block_id,
-        );
+        )
+    }
+
+    /// runs the authorizer checks, the authority block checks, then the policies
+    ///
+    /// failed checks are added to `errors`, and the matching policy is returned
+    fn check_authority(
+        &mut self,
+        time_limit: Instant,
+        render_checks: bool,
+        errors: &mut Vec<error::FailedCheck>,
+        mut profile: Option<&mut AuthorizerProfile>,
+    ) -> Result<Option<Result<usize, usize>>, error::Token> {
+        let mut policy_result: Option<Result<usize, usize>> = None;
+
+        let authorizer_trusted_origins = self.authorizer_trusted_origins();
 
         for (i, check) in self.authorizer_block_builder.checks.iter().enumerate() {
-            let c = check.convert(&mut self.symbols);
-            let mut successful = false;
+            let start = self.clock.instant();
+            // `reject if` succeeds unless one of its queries matches
+            let reject = check.kind == CheckKind::Reject;
+            let mut successful = reject;
 
             for query in check.queries.iter() {
                 let query = query.convert(&mut self.symbols);
@@ -749,7 +1912,7 @@ impl Authorizer {
                     &self.public_key_to_block_id,
                 );
                 let res = match check.kind {
-                    CheckKind::One => self.world.query_match(
+                    CheckKind::One | CheckKind::Reject => self.world.query_match(
                         query,
                         usize::MAX,
                         &rule_trusted_origins,
@@ -761,79 +1924,48 @@ impl Authorizer {
                     }
                 };
 
-                let now = Instant::now();
+                let now = self.clock.instant();
                 if now >= time_limit {
                     return Err(error::Token::RunLimit(error::RunLimit::Timeout));
                 }
 
                 if res {
-                    successful = true;
+                    successful = !reject;
                     break;
                 }
             }
 
+            if let Some(profile) = profile.as_deref_mut() {
+                let c = check.convert(&mut self.symbols);
+                profile.checks.push(CheckProfile {
+                    origin: usize::MAX,
+                    check_id: i,
+                    check: self.symbols.print_check(&c),
+                    time: self.clock.instant() - start,
+                });
+            }
+
             if !successful {
+                let rule = if render_checks {
+                    let c = check.convert(&mut self.symbols);
+                    self.symbols.print_check(&c)
+                } else {
+                    String::new()
+                };
+
                 errors.push(error::FailedCheck::Authorizer(
                     error::FailedAuthorizerCheck {
                         check_id: i as u32,
-                        rule: self.symbols.print_check(&c),
+                        rule,
+                        message: check.message.clone(),
                     },
                 ));
             }
         }
 
-        if let Some(blocks) = self.blocks.as_ref() {
-            for (j, check) in blocks[0].checks.iter().enumerate() {
-                let mut successful = false;
-
-                let authority_trusted_origins = TrustedOrigins::from_scopes(
-                    &blocks[0].scopes,
-                    &TrustedOrigins::default(),
-                    0,
-                    &self.public_key_to_block_id,
-                );
-
-                for query in check.queries.iter() {
-                    let rule_trusted_origins = TrustedOrigins::from_scopes(
-                        &query.scopes,
-                        &authority_trusted_origins,
-                        0,
-                        &self.public_key_to_block_id,
-                    );
-                    let res = match check.kind {
-                        CheckKind::One => self.world.query_match(
-                            query.clone(),
-                            0,
-                            &rule_trusted_origins,
-                            &self.symbols,
-                        )?,
-                        CheckKind::All => self.world.query_match_all(
-                            query.clone(),
-                            &rule_trusted_origins,
-                            &self.symbols,
-                        )?,
-                    };
-
-                    let now = Instant::now();
-                    if now >= time_limit {
-                        return Err(error::Token::RunLimit(error::RunLimit::Timeout));
-                    }
-
-                    if res {
-                        successful = true;
-                        break;
-                    }
-                }
-
-                if !successful {
-                    errors.push(error::FailedCheck::Block(error::FailedBlockCheck {
-                        block_id: 0u32,
-                        check_id: j as u32,
-                        rule: self.symbols.print_check(&check),
-                    }));
-                }
-            }
-        }
+        if self.blocks.is_some() {
+            self.check_block(0, time_limit, render_checks, errors, profile)?;
+        }
 
         'policies_test: for (i, policy) in self.policies.iter().enumerate() {
             for query in policy.queries.iter() {
@@ -852,7 +1984,7 @@ impl Authorizer {
                     &self.symbols,
                 )?;
 
-                let now = Instant::now();
+                let now = self.clock.instant();
                 if now >= time_limit {
                     return Err(error::Token::RunLimit(error::RunLimit::Timeout));
                 }
@@ -867,82 +1999,106 @@ impl Authorizer {
             }
         }
 
-        if let Some(blocks) = self.blocks.as_ref() {
-            for (i, block) in (&blocks[1..]).iter().enumerate() {
-                let block_trusted_origins = TrustedOrigins::from_scopes(
-                    &block.scopes,
-                    &TrustedOrigins::default(),
-                    i + 1,
+        Ok(policy_result)
+    }
+
+    /// runs the checks of the token block at index `i`, adding failed checks to `errors`
+    fn check_block(
+        &self,
+        i: usize,
+        time_limit: Instant,
+        render_checks: bool,
+        errors: &mut Vec<error::FailedCheck>,
+        mut profile: Option<&mut AuthorizerProfile>,
+    ) -> Result<(), error::Token> {
+        let block = match self.blocks.as_ref().and_then(|blocks| blocks.get(i)) {
+            Some(block) => block,
+            None => return Ok(()),
+        };
+
+        let block_trusted_origins = TrustedOrigins::from_scopes(
+            &block.scopes,
+            &TrustedOrigins::default(),
+            i,
+            &self.public_key_to_block_id,
+        );
+
+        for (j, check) in block.checks.iter().enumerate() {
+            let start = self.clock.instant();
+            let reject = check.kind == CheckKind::Reject;
+            let mut successful = reject;
+
+            for query in check.queries.iter() {
+                let rule_trusted_origins = TrustedOrigins::from_scopes(
+                    &query.scopes,
+                    &block_trusted_origins,
+                    i,
                     &self.public_key_to_block_id,
                 );
 
-                limits.max_time = time_limit - Instant::now();
-                limits.max_iterations -= self.world.iterations - current_iterations;
-                current_iterations = self.world.iterations;
-
-                self.world.run_with_limits(&self.symbols, limits.clone())?;
-
-                for (j, check) in block.checks.iter().enumerate() {
-                    let mut successful = false;
-
-                    for query in check.queries.iter() {
-                        let rule_trusted_origins = TrustedOrigins::from_scopes(
-                            &query.scopes,
-                            &block_trusted_origins,
-                            i + 1,
-                            &self.public_key_to_block_id,
-                        );
-
-                        let res = match check.kind {
-                            CheckKind::One => self.world.query_match(
-                                query.clone(),
-                                i + 1,
-                                &rule_trusted_origins,
-                                &self.symbols,
-                            )?,
-                            CheckKind::All => self.world.query_match_all(
-                                query.clone(),
-                                &rule_trusted_origins,
-                                &self.symbols,
-                            )?,
-                        };
-
-                        let now = Instant::now();
-                        if now >= time_limit {
-                            return Err(error::Token::RunLimit(error::RunLimit::Timeout));
-                        }
+                let res = match check.kind {
+                    CheckKind::One | CheckKind::Reject => query.find_match(
+                        &self.world.facts,
+                        i,
+                        &rule_trusted_origins,
+                        &self.symbols,
+                    )?,
+                    CheckKind::All => query.check_match_all(
+                        &self.world.facts,
+                        &rule_trusted_origins,
+                        &self.symbols,
+                    )?,
+                };
 
-                        if res {
-                            successful = true;
-                            break;
-                        }
-                    }
+                let now = self.clock.instant();
+                if now >= time_limit {
+                    return Err(error::Token::RunLimit(error::RunLimit::Timeout));
+                }
 
-                    if !successful {
-                        errors.push(error::FailedCheck::Block(error::FailedBlockCheck {
-                            block_id: (i + 1) as u32,
-                            check_id: j as u32,
-                            rule: self.symbols.print_check(&check),
-                        }));
-                    }
+                if res {
+                    successful = !reject;
+                    break;
                 }
             }
+
+            if let Some(profile) = profile.as_deref_mut() {
+                profile.checks.push(CheckProfile {
+                    origin: i,
+                    check_id: j,
+                    check: self.symbols.print_check(check),
+                    time: self.clock.instant() - start,
+                });
+            }
+
+            if !successful {
+                errors.push(error::FailedCheck::Block(error::FailedBlockCheck {
+                    block_id: i as u32,
+                    check_id: j as u32,
+                    rule: if render_checks {
+                        self.symbols.print_check(check)
+                    } else {
+                        String::new()
+                    },
+                    message: check.message.clone(),
+                }));
+            }
         }
 
-        match (policy_result, errors.is_empty()) {
-            (Some(Ok(i)), true) => Ok(i),
-            (None, _) => Err(error::Token::FailedLogic(error::Logic::NoMatchingPolicy {
-                checks: errors,
-            })),
-            (Some(Ok(i)), _) => Err(error::Token::FailedLogic(error::Logic::Unauthorized {
-                policy: error::MatchedPolicy::Allow(i),
-                checks: errors,
-            })),
-            (Some(Err(i)), _) => Err(error::Token::FailedLogic(error::Logic::Unauthorized {
-                policy: error::MatchedPolicy::Deny(i),
-                checks: errors,
-            })),
+        Ok(())
+    }
+
+    /// returns the ids of the token blocks signed by each third party public key
+    ///
+    /// block ids start at 0 for the authority block. The map is empty until
+    /// a token is added
+    pub fn public_key_to_block_id(&self) -> HashMap<PublicKey, Vec<usize>> {
+        let mut res: HashMap<PublicKey, Vec<usize>> = HashMap::new();
+        for (block_id, block) in self.blocks.iter().flatten().enumerate() {
+            if let Some(key) = block.external_key {
+                res.entry(key).or_default().push(block_id);
+            }
         }
+        res
     }
 
     /// prints the content of the authorizer
@@ -985,6 +2141,37 @@ impl Authorizer {
         (facts, rules, checks, self.policies.clone())
     }
 
+    /// lists the strings that are defined in more than one of the token blocks'
+    /// symbol tables, the default symbols, or the authorizer's code
+    pub fn symbol_report(&self) -> SymbolReport {
+        let mut report = SymbolReport::new();
+
+        if let Some(blocks) = &self.blocks {
+            for (i, block) in blocks.iter().enumerate() {
+                report.add(SymbolSource::Block(i), block.symbols.strings());
+            }
+        }
+
+        let mut symbols = SymbolTable::new();
+        for fact in &self.authorizer_block_builder.facts {
+            fact.convert(&mut symbols);
+        }
+        for rule in &self.authorizer_block_builder.rules {
+            rule.convert(&mut symbols);
+        }
+        for check in &self.authorizer_block_builder.checks {
+            check.convert(&mut symbols);
+        }
+        for policy in &self.policies {
+            for query in &policy.queries {
+                query.convert(&mut symbols);
+            }
+        }
+        report.add(SymbolSource::Authorizer, symbols.strings());
+
+        report
+    }
+
     pub fn dump_code(&self) -> String {
         let (facts, rules, checks, policies) = self.dump();
         let mut f = String::new();
@@ -1016,13 +2203,39 @@ impl Authorizer {
     }
 }
 
+fn authorization_result(
+    policy_result: Option<Result<usize, usize>>,
+    errors: Vec<error::FailedCheck>,
+) -> Result<usize, error::Token> {
+    match (policy_result, errors.is_empty()) {
+        (Some(Ok(i)), true) => Ok(i),
+        (None, _) => Err(error::Token::FailedLogic(error::Logic::NoMatchingPolicy {
+            checks: errors,
+        })),
+        (Some(Ok(i)), _) => Err(error::Token::FailedLogic(error::Logic::Unauthorized {
+            policy: error::MatchedPolicy::Allow(i),
+            checks: errors,
+        })),
+        (Some(Err(i)), _) => Err(error::Token::FailedLogic(error::Logic::Unauthorized {
+            policy: error::MatchedPolicy::Deny(i),
+            checks: errors,
+        })),
+    }
+}
+
+fn namespaced_rule(namespace: &str, mut rule: Rule) -> Rule {
+    for predicate in rule.body.iter_mut() {
+        predicate.name = format!("{}::{}", namespace, predicate.name);
+    }
+    rule
+}
 impl std::fmt::Display for Authorizer {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         let mut has_facts = false;
         let mut all_facts = BTreeMap::new();
         for (origin, factset) in &self.world.facts.inner {
             let mut facts = HashSet::new();
-            for fact in factset {
+            for fact in factset.iter() {
                 facts.insert(self.symbols.print_fact(fact));
             }
 
@@ -1206,6 +2419,7 @@ impl TryFrom<AuthorizerPolicies> for Authorizer {
 }
 
 #[derive(Debug, Clone)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct AuthorizerPolicies {
     pub version: u32,
     /// list of facts provided by this block
@@ -1256,6 +2470,7 @@ impl BuilderExt for Authorizer {
                 &[pred("resource", &[string(name)])],
             )],
             kind: CheckKind::One,
+            message: None,
         })
         .unwrap();
     }
@@ -1271,6 +2486,7 @@ impl BuilderExt for Authorizer {
                 &[pred("operation", &[string(name)])],
             )],
             kind: CheckKind::One,
+            message: None,
         })
         .unwrap();
     }
@@ -1291,6 +2507,7 @@ impl BuilderExt for Authorizer {
         self.add_check(Check {
             queries: vec![check],
             kind: CheckKind::One,
+            message: None,
         })
         .unwrap();
     }
@@ -1312,6 +2529,7 @@ impl BuilderExt for Authorizer {
         self.add_check(Check {
             queries: vec![check],
             kind: CheckKind::One,
+            message: None,
         })
         .unwrap();
     }
@@ -1321,18 +2539,74 @@ impl BuilderExt for Authorizer {
             "expiration",
             &[var("time")],
             &[pred("time", &[var("time")])],
-            &[Expression {
-                ops: vec![
-                    Op::Value(var("time")),
-                    Op::Value(date(&exp)),
-                    Op::Binary(Binary::LessOrEqual),
-                ],
-            }],
+            &[super::time_comparison(exp, Binary::LessOrEqual)],
+        );
+
+        self.add_check(Check {
+            queries: vec![check],
+            kind: CheckKind::One,
+            message: None,
+        })
+        .unwrap();
+    }
+
+    fn check_not_before(&mut self, start: SystemTime) {
+        let check = constrained_rule(
+            "not_before",
+            &[var("time")],
+            &[pred("time", &[var("time")])],
+            &[super::time_comparison(start, Binary::GreaterOrEqual)],
         );
 
         self.add_check(Check {
             queries: vec![check],
             kind: CheckKind::One,
+            message: None,
+        })
+        .unwrap();
+    }
+
+    fn check_all_operations(&mut self, operations: &[&str]) {
+        self.add_check(Check {
+            kind: CheckKind::All,
+            ..super::check_in_set("operation", operations)
+        })
+        .unwrap();
+    }
+
+    fn check_all_resources(&mut self, resources: &[&str]) {
+        self.add_check(Check {
+            kind: CheckKind::All,
+            ..super::check_in_set("resource", resources)
+        })
+        .unwrap();
+    }
+
+    fn reject_operation(&mut self, name: &str) {
+        self.add_check(Check {
+            queries: vec![rule(
+                "operation_check",
+                &[string("operation_check")],
+                &[pred("operation", &[string(name)])],
+            )],
+            kind: CheckKind::Reject,
+            message: None,
+        })
+        .unwrap();
+    }
+
+    fn reject_if_expired_before(&mut self, exp: SystemTime) {
+        let check = constrained_rule(
+            "expiration",
+            &[var("time")],
+            &[pred("time", &[var("time")])],
+            &[super::time_comparison(exp, Binary::GreaterThan)],
+        );
+
+        self.add_check(Check {
+            queries: vec![check],
+            kind: CheckKind::Reject,
+            message: None,
         })
         .unwrap();
     }
@@ -1347,6 +2621,17 @@ impl AuthorizerExt for Authorizer {
     }
 }
 
+/// predicate of the fact added for tokens verified with
+/// [`Biscuit::from_with_keys`]: `biscuit::root_key($index, $public_key)`
+const ROOT_KEY_PREDICATE: &str = "biscuit::root_key";
+
+/// origin of the facts and rules of the authorizer
+fn authorizer_origin() -> Origin {
+    let mut origin = Origin::default();
+    origin.insert(usize::MAX);
+    origin
+}
+
 #[cfg(test)]
 mod tests {
     use std::time::Duration;
@@ -1358,6 +2643,13 @@ mod tests {
 
     use super::*;
 
+    fn limits() -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn empty_authorizer() {
         let mut authorizer = Authorizer::new();
@@ -1785,4 +3077,1076 @@ allow if true;
         let authorizer = Authorizer::new();
         assert_eq!("", authorizer.to_string())
     }
+
+    #[test]
+    fn authorize_in_place_renders_checks_lazily() {
+        let keypair = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(r#"right("file1", "read"); check if operation("write");"#)
+            .unwrap();
+        let biscuit = builder.build(&keypair).unwrap();
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        authorizer
+            .add_code(
+                r#"resource("file1"); operation("read");
+                check if right("file2", "read");
+                allow if right("file1", "read");"#,
+            )
+            .unwrap();
+        authorizer.set_limits(limits());
+        let mut rendering_authorizer = authorizer.clone();
+
+        let mut res = authorizer.authorize_in_place().unwrap_err();
+        assert_eq!(
+            res,
+            error::Token::FailedLogic(error::Logic::Unauthorized {
+                policy: error::MatchedPolicy::Allow(0),
+                checks: vec![
+                    error::FailedCheck::Authorizer(error::FailedAuthorizerCheck {
+                        check_id: 0,
+                        rule: String::new(),
+                        message: None,
+                    }),
+                    error::FailedCheck::Block(error::FailedBlockCheck {
+                        block_id: 0,
+                        check_id: 0,
+                        rule: String::new(),
+                        message: None,
+                    }),
+                ]
+            })
+        );
+
+        authorizer.render_failed_checks(&mut res);
+        assert_eq!(Err(res), rendering_authorizer.authorize());
+    }
+
+    #[test]
+    fn revocation_check() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let keypair = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.add_fact("user(\"John Doe\")").unwrap();
+        let biscuit = builder.build(&keypair).unwrap();
+        let biscuit = biscuit.append(BlockBuilder::new()).unwrap();
+        let revocation_ids = biscuit.revocation_identifiers();
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        authorizer.allow().unwrap();
+        authorizer.set_limits(limits());
+
+        let mut not_revoked = authorizer.clone();
+        not_revoked.revocation_check(|_: &[u8]| false);
+        assert_eq!(not_revoked.authorize(), Ok(0));
+
+        let mut revoked = authorizer.clone();
+        let store: HashSet<Vec<u8>> = std::iter::once(revocation_ids[1].clone()).collect();
+        revoked.revocation_check(store);
+        assert_eq!(
+            revoked.authorize(),
+            Err(error::Token::Revoked { block_ids: vec![1] })
+        );
+
+        // stores can look up all the identifiers in one call
+        struct BatchStore(AtomicUsize);
+        impl RevocationStore for BatchStore {
+            fn is_revoked(&self, _revocation_id: &[u8]) -> bool {
+                unreachable!()
+            }
+
+            fn revoked(&self, revocation_ids: &[Vec<u8>]) -> Vec<usize> {
+                self.0.fetch_add(1, Ordering::Relaxed);
+                (0..revocation_ids.len()).collect()
+            }
+
+            fn revoked_constant_time(&self, _revocation_ids: &[Vec<u8>]) -> Vec<usize> {
+                unreachable!()
+            }
+        }
+
+        let store = Arc::new(BatchStore(AtomicUsize::new(0)));
+        let mut revoked = authorizer.clone();
+        revoked.revocation_check(store.clone() as Arc<dyn RevocationStore>);
+        assert_eq!(
+            revoked.authorize(),
+            Err(error::Token::Revoked {
+                block_ids: vec![0, 1]
+            })
+        );
+        assert_eq!(store.0.load(Ordering::Relaxed), 1);
+    }
+
+    #[test]
+    fn audience() {
+        let keypair = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.audience("svc-a");
+        let biscuit = builder.build(&keypair).unwrap();
+        // attenuation blocks cannot add audiences
+        let mut block = BlockBuilder::new();
+        block.add_fact("audience(\"svc-b\")").unwrap();
+        let biscuit = biscuit.append(block).unwrap();
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        authorizer.allow().unwrap();
+        authorizer.set_limits(limits());
+
+        let mut svc_a = authorizer.clone();
+        svc_a.expect_audience("svc-a");
+        assert_eq!(svc_a.authorize(), Ok(0));
+
+        let mut svc_b = authorizer.clone();
+        svc_b.expect_audience("svc-b");
+        let res = svc_b.authorize();
+        assert_eq!(
+            res,
+            Err(error::Token::FailedLogic(error::Logic::AudienceMismatch {
+                expected: vec!["svc-b".to_string()],
+                actual: vec!["svc-a".to_string()],
+            }))
+        );
+        assert_eq!(res.unwrap_err().to_string(), "authorization failed");
+
+        let mut no_audience = Biscuit::builder()
+            .build(&keypair)
+            .unwrap()
+            .authorizer()
+            .unwrap();
+        no_audience.expect_audience("svc-a");
+        no_audience.allow().unwrap();
+        let err = no_audience.authorize().unwrap_err();
+        match err {
+            error::Token::FailedLogic(logic) => assert_eq!(
+                logic.to_string(),
+                "audience mismatch: expected one of [\"svc-a\"], the token is for []"
+            ),
+            e => panic!("unexpected error: {:?}", e),
+        }
+    }
+
+    #[test]
+    fn require_sealed() {
+        let root = KeyPair::new();
+        let token = Biscuit::builder().build(&root).unwrap();
+        assert!(!token.is_sealed());
+        let sealed = Biscuit::builder().build_sealed(&root).unwrap();
+        assert!(sealed.is_sealed());
+        let unverified = crate::UnverifiedBiscuit::from(sealed.to_vec().unwrap()).unwrap();
+        assert!(unverified.is_sealed());
+
+        let limits = limits();
+        let mut authorizer = sealed.authorizer().unwrap();
+        authorizer.set_limits(limits.clone());
+        authorizer.require_sealed();
+        authorizer.allow().unwrap();
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        let mut authorizer = token.authorizer().unwrap();
+        authorizer.set_limits(limits);
+        authorizer.require_sealed();
+        authorizer.allow().unwrap();
+        assert_eq!(
+            authorizer.authorize(),
+            Err(error::Token::FailedLogic(error::Logic::SealRequired))
+        );
+    }
+
+    #[test]
+    fn authorize_batch() {
+        let root = KeyPair::new();
+        let token = |user: &str| {
+            let mut builder = Biscuit::builder();
+            builder.add_fact(fact("user", &[string(user)])).unwrap();
+            builder.build(&root).unwrap()
+        };
+        let tokens = vec![token("alice"), token("bob"), token("alice")];
+
+        let mut authorizer = Authorizer::new();
+        authorizer.add_fact("admin(\"alice\")").unwrap();
+        authorizer
+            .add_rule("is_admin($u) <- user($u), admin($u)")
+            .unwrap();
+        authorizer.add_check("check if user($u)").unwrap();
+        authorizer.add_policy("allow if is_admin($u)").unwrap();
+        authorizer.set_limits(limits());
+
+        let results = authorizer.authorize_batch(&tokens);
+        assert_eq!(results.len(), 3);
+        for (token, result) in tokens.iter().zip(results) {
+            assert_eq!(result, token.authorize(&authorizer));
+        }
+        assert_eq!(authorizer.authorize_batch(&tokens)[0], Ok(0));
+        assert!(authorizer.authorize_batch(&tokens)[1].is_err());
+
+        // the authorizer already contains a token
+        let full = tokens[0].authorizer().unwrap();
+        assert_eq!(
+            full.authorize_batch(&tokens[..1]),
+            vec![Err(error::Logic::AuthorizerNotEmpty.into())]
+        );
+    }
+
+    #[test]
+    fn specialize() {
+        let root = KeyPair::new();
+        let token = |user: &str| {
+            let mut builder = Biscuit::builder();
+            builder.add_fact(fact("user", &[string(user)])).unwrap();
+            builder.build(&root).unwrap()
+        };
+
+        let mut authorizer = Authorizer::new();
+        authorizer
+            .add_code(
+                r#"member("alice", "acme"); member("bob", "other");
+                role("alice", "acme", "admin"); role("bob", "other", "admin");
+                tenant_member($u) <- tenant($t), member($u, $t);
+                tenant_admin($u) <- tenant_member($u), tenant($t), role($u, $t, "admin");
+                allow if user($u), tenant_admin($u);"#,
+            )
+            .unwrap();
+        authorizer.set_limits(limits());
+
+        let specialized = authorizer.specialize(["tenant(\"acme\")"]).unwrap();
+        assert_eq!(specialized.world.iterations, 0);
+        let admins: Vec<(String,)> = specialized
+            .clone()
+            .query_all("data($u) <- tenant_admin($u)")
+            .unwrap();
+        assert_eq!(admins, vec![("alice".to_string(),)]);
+
+        let mut generic = authorizer.clone();
+        generic.add_fact("tenant(\"acme\")").unwrap();
+        for user in ["alice", "bob"] {
+            let mut expected = generic.clone();
+            expected.add_token(&token(user)).unwrap();
+            let mut request = specialized.clone();
+            request.add_token(&token(user)).unwrap();
+
+            assert_eq!(request.authorize(), expected.authorize());
+            assert!(request.iterations() < expected.iterations());
+        }
+
+        let full = token("alice").authorizer().unwrap();
+        assert_eq!(
+            full.specialize(["tenant(\"acme\")"]).err(),
+            Some(error::Logic::AuthorizerNotEmpty.into())
+        );
+    }
+
+    #[cfg(feature = "json")]
+    #[test]
+    fn dump_json() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.add_fact("user(\"alice\")").unwrap();
+        builder.add_check("check if operation(\"read\")").unwrap();
+        let biscuit = builder.build(&root).unwrap();
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        authorizer.add_fact("operation(\"write\")").unwrap();
+        authorizer.add_rule("can($u) <- user($u)").unwrap();
+        authorizer.add_check("check if can(\"alice\")").unwrap();
+        authorizer.allow().unwrap();
+        authorizer.set_limits(limits());
+        let err = authorizer.authorize().unwrap_err();
+
+        assert_eq!(
+            authorizer.dump_json().unwrap(),
+            serde_json::json!({
+                "facts": [
+                    { "origin": [0], "fact": "user(\"alice\")" },
+                    { "origin": [0, null], "fact": "can(\"alice\")" },
+                    { "origin": [null], "fact": "operation(\"write\")" },
+                ],
+                "rules": [
+                    { "origin": null, "rule": "can($u) <- user($u)" },
+                ],
+                "checks": [
+                    { "origin": 0, "check": "check if operation(\"read\")" },
+                    { "origin": null, "check": "check if can(\"alice\")" },
+                ],
+                "policies": ["allow if true"],
+            })
+        );
+
+        assert_eq!(
+            err.to_json().unwrap(),
+            serde_json::json!({
+                "FailedLogic": {
+                    "Unauthorized": {
+                        "policy": { "Allow": 0 },
+                        "checks": [
+                            { "Block": {
+                                "block_id": 0,
+                                "check_id": 0,
+                                "rule": "check if operation(\"read\")",
+                            } },
+                        ],
+                    }
+                }
+            })
+        );
+    }
+
+    #[test]
+    fn authorizer_pool() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.add_fact("user(\"alice\")").unwrap();
+        let alice = builder.build(&root).unwrap();
+        let mut builder = Biscuit::builder();
+        builder.add_fact("user(\"bob\")").unwrap();
+        let bob = builder.build(&root).unwrap();
+
+        let mut base = Authorizer::new();
+        base.set_limits(limits());
+        base.add_code("admin(\"alice\"); allow if user($u), admin($u);")
+            .unwrap();
+        let pool = AuthorizerPool::new(base, 2);
+
+        {
+            let mut authorizer = pool.get();
+            authorizer.add_token(&alice).unwrap();
+            assert_eq!(authorizer.authorize(), Ok(0));
+        }
+        assert_eq!(
+            pool.stats(),
+            PoolStats {
+                hits: 0,
+                misses: 1,
+                available: 1
+            }
+        );
+
+        // the returned authorizer does not contain the previous token
+        let mut authorizer = pool.get();
+        authorizer.add_token(&bob).unwrap();
+        assert!(authorizer.authorize().is_err());
+        drop(authorizer);
+
+        pool.warm_up();
+        let authorizers: Vec<_> = (0..3).map(|_| pool.get()).collect();
+        drop(authorizers);
+        let stats = pool.stats();
+        assert_eq!((stats.hits, stats.misses, stats.available), (3, 2, 2));
+        assert_eq!(stats.hit_rate(), 0.6);
+
+        let tokens = [alice, bob];
+        let results: Vec<bool> = std::thread::scope(|scope| {
+            let handles: Vec<_> = tokens
+                .iter()
+                .map(|token| {
+                    let pool = &pool;
+                    scope.spawn(move || {
+                        let mut authorizer = pool.get();
+                        authorizer.add_token(token).unwrap();
+                        authorizer.authorize().is_ok()
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|handle| handle.join().unwrap())
+                .collect()
+        });
+        assert_eq!(results, vec![true, false]);
+        assert_eq!(pool.stats().available, 2);
+    }
+
+    #[test]
+    fn match_facts() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(
+                r#"right("alice", "file1", "read");
+                right("alice", "file2", "write");
+                right("bob", "file1", "read");
+                pair("a", "a");
+                pair("a", "b");"#,
+            )
+            .unwrap();
+        let biscuit = builder.build(&root).unwrap();
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        authorizer.set_limits(limits());
+
+        let mut res: Vec<(String, String)> = authorizer
+            .match_facts(r#"right($user, $file, "read")"#)
+            .unwrap();
+        res.sort();
+        assert_eq!(
+            res,
+            vec![
+                ("alice".to_string(), "file1".to_string()),
+                ("bob".to_string(), "file1".to_string())
+            ]
+        );
+
+        let res: Vec<(String,)> = authorizer.match_facts("pair($x, $x)").unwrap();
+        assert_eq!(res, vec![("a".to_string(),)]);
+
+        assert!(authorizer
+            .match_facts::<(String,), _>("right($u, $f, $o), pair($u, $u)")
+            .is_err());
+    }
+
+    #[test]
+    fn query_with_params() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(r#"right("file1", "read"); right("file2", "write");"#)
+            .unwrap();
+        let biscuit = builder.build(&root).unwrap();
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        authorizer.set_limits(limits());
+
+        let mut params = HashMap::new();
+        params.insert("op".to_string(), Term::Str("read".to_string()));
+        params.insert("unused".to_string(), Term::Integer(1));
+        let res: Vec<(String,)> = authorizer
+            .query_with_params("data($f) <- right($f, {op})", params.clone())
+            .unwrap();
+        assert_eq!(res, vec![("file1".to_string(),)]);
+
+        assert_eq!(
+            authorizer.query_with_params::<(String,), _>(
+                "data($f) <- right($f, {op}), resource({resource})",
+                params
+            ),
+            Err(error::Token::Language(
+                biscuit_parser::error::LanguageError::Parameters {
+                    missing_parameters: vec!["resource".to_string()],
+                    unused_parameters: vec![],
+                }
+            ))
+        );
+    }
+
+    #[test]
+    fn parse_error_position() {
+        use biscuit_parser::error::LanguageError;
+
+        let mut authorizer = Authorizer::new();
+        let res = authorizer.add_code("user(\"alice\");\nallow if user($u) and;\n");
+        let errors = match res {
+            Err(error::Token::Language(LanguageError::ParseError(e))) => e.errors,
+            other => panic!("unexpected result: {:?}", other),
+        };
+        let position = errors[0].position.as_ref().unwrap();
+        assert_eq!(
+            (position.line, position.column, position.snippet.as_str()),
+            (2, 1, "allow if user($u) and;")
+        );
+
+        let mut builder = BlockBuilder::new();
+        let res = builder.add_code("check if\n  resource($r),,;");
+        match res {
+            Err(error::Token::Language(LanguageError::ParseError(e))) => {
+                assert_eq!(e.errors[0].position.as_ref().map(|p| p.line), Some(2))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn clock() {
+        use crate::time::FixedClock;
+        use std::time::UNIX_EPOCH;
+
+        let keypair = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.check_expiration_date(UNIX_EPOCH + Duration::from_secs(1_000));
+        let biscuit = builder.build(&keypair).unwrap();
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        authorizer.allow().unwrap();
+        authorizer.set_limits(limits());
+
+        let mut before = authorizer.clone();
+        before.set_clock(FixedClock(UNIX_EPOCH + Duration::from_secs(999)));
+        before.set_time();
+        assert_eq!(before.authorize(), Ok(0));
+
+        let mut after = authorizer.clone();
+        after.set_clock(|| UNIX_EPOCH + Duration::from_secs(1_001));
+        after.set_time();
+        assert!(after.authorize().is_err());
+
+        // the default clock uses the system time
+        let mut now = authorizer.clone();
+        now.set_time();
+        assert!(now.authorize().is_err());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn authorize_async_yields() {
+        use std::future::Future;
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake};
+
+        struct NoopWaker;
+        impl Wake for NoopWaker {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        let keypair = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(
+                r#"edge(1, 2); edge(2, 3); edge(3, 4); edge(4, 5);
+                path($a, $b) <- edge($a, $b);
+                path($a, $c) <- path($a, $b), edge($b, $c);"#,
+            )
+            .unwrap();
+        let biscuit = builder.build(&keypair).unwrap();
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        authorizer
+            .add_code("check if path(1, 5); allow if true;")
+            .unwrap();
+        authorizer.set_limits(limits());
+        let mut sync_authorizer = authorizer.clone();
+
+        let waker = Arc::new(NoopWaker).into();
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = Box::pin(authorizer.authorize_async());
+        let mut pending = 0;
+        let res = loop {
+            match fut.as_mut().poll(&mut cx) {
+                Poll::Ready(res) => break res,
+                Poll::Pending => pending += 1,
+            }
+        };
+        drop(fut);
+
+        assert!(pending > 0);
+        assert_eq!(res, Ok(0));
+        assert_eq!(res, sync_authorizer.authorize());
+        assert_eq!(authorizer.iterations(), sync_authorizer.iterations());
+    }
+
+    #[test]
+    fn reset_token_data() {
+        let root = KeyPair::new();
+        let token = |user: &str| {
+            let mut builder = Biscuit::builder();
+            builder.add_fact(fact("user", &[string(user)])).unwrap();
+            builder
+                .add_rule(format!("owner(\"{user}\", $f) <- file($f)").as_str())
+                .unwrap();
+            builder.build(&root).unwrap()
+        };
+
+        let mut authorizer = Authorizer::new();
+        authorizer
+            .add_code(
+                r#"file("file1");
+                admin("alice");
+                is_admin($u) <- user($u), admin($u);
+                allow if is_admin($u);"#,
+            )
+            .unwrap();
+        authorizer.set_limits(limits());
+        let fresh = authorizer.clone();
+
+        authorizer.add_token(&token("alice")).unwrap();
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        for user in ["bob", "alice", "charlie"] {
+            authorizer.reset_token_data().unwrap();
+            assert!(authorizer.blocks.is_none());
+            assert_eq!(authorizer.iterations(), 0);
+            let facts: Vec<(String,)> = authorizer.query_all("data($u) <- user($u)").unwrap();
+            assert!(facts.is_empty());
+
+            authorizer.add_token(&token(user)).unwrap();
+            let mut expected = fresh.clone();
+            expected.add_token(&token(user)).unwrap();
+            assert_eq!(authorizer.authorize(), expected.authorize());
+
+            let owners: Vec<(String, String)> = authorizer
+                .query_all("data($u, $f) <- owner($u, $f)")
+                .unwrap();
+            assert_eq!(owners, vec![(user.to_string(), "file1".to_string())]);
+        }
+
+        // the symbols of the tokens are removed
+        authorizer.reset_token_data().unwrap();
+        let strings = authorizer.symbols.strings();
+        assert!(!strings.contains(&"charlie".to_string()));
+        assert!(strings.contains(&"alice".to_string()));
+    }
+
+    #[test]
+    fn reset_token_data_removes_root_key() {
+        let trusted = KeyPair::new();
+        let other = KeyPair::new();
+        let token = |root: &KeyPair| {
+            let serialized = Biscuit::builder().build(root).unwrap().to_vec().unwrap();
+            Biscuit::from_with_keys(serialized, &[trusted.public(), other.public()]).unwrap()
+        };
+
+        let mut authorizer = Authorizer::new();
+        authorizer
+            .add_code(format!(
+                r#"current($k) <- biscuit::root_key(0, $k);
+                allow if current("{}");"#,
+                trusted.public()
+            ))
+            .unwrap();
+
+        authorizer.add_token(&token(&trusted)).unwrap();
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        // the facts generated from the first token's root key are removed
+        authorizer.reset_token_data().unwrap();
+        let facts: Vec<(String,)> = authorizer.query_all("data($k) <- current($k)").unwrap();
+        assert!(facts.is_empty());
+
+        authorizer.add_token(&token(&other)).unwrap();
+        assert!(authorizer.authorize().is_err());
+    }
+
+    #[test]
+    fn query_with_origin() {
+        let root = KeyPair::new();
+        let external = KeyPair::new();
+
+        let mut builder = Biscuit::builder();
+        builder.add_fact(r#"user("alice")"#).unwrap();
+        // the external key must be known by the token to be trusted
+        builder
+            .add_check(format!("check if true trusting {}", external.public()).as_str())
+            .unwrap();
+        let token = builder.build(&root).unwrap();
+
+        let mut block = BlockBuilder::new();
+        block.add_fact(r#"user("bob")"#).unwrap();
+        let token = token.append(block).unwrap();
+
+        let mut block = BlockBuilder::new();
+        block.add_fact(r#"user("carol")"#).unwrap();
+        let req = token.third_party_request().unwrap();
+        let res = req.create_block(&external.private(), block).unwrap();
+        let token = token.append_third_party(external.public(), res).unwrap();
+
+        let mut authorizer = token.authorizer().unwrap();
+        authorizer
+            .add_code(
+                r#"user("dave");
+                admin($u) <- user($u), $u == "alice";
+                allow if true"#,
+            )
+            .unwrap();
+        authorizer.set_limits(limits());
+        authorizer.authorize().unwrap();
+
+        let print = |res: Vec<(Origin, (String,))>| {
+            let mut res: Vec<(Vec<usize>, String)> = res
+                .into_iter()
+                .map(|(origin, (user,))| (origin.iter().collect(), user))
+                .collect();
+            res.sort();
+            res
+        };
+
+        let res = authorizer
+            .query_with_origin("data($u) <- user($u)")
+            .unwrap();
+        assert_eq!(
+            print(res),
+            vec![
+                (vec![0], "alice".to_string()),
+                (vec![usize::MAX], "dave".to_string()),
+            ]
+        );
+
+        let res = authorizer
+            .query_all_with_origin("data($u) <- user($u)")
+            .unwrap();
+        assert_eq!(
+            print(res),
+            vec![
+                (vec![0], "alice".to_string()),
+                (vec![1], "bob".to_string()),
+                (vec![2], "carol".to_string()),
+                (vec![usize::MAX], "dave".to_string()),
+            ]
+        );
+
+        let res = authorizer
+            .query_with_origin(
+                format!("data($u) <- user($u) trusting {}", external.public()).as_str(),
+            )
+            .unwrap();
+        assert_eq!(
+            print(res),
+            vec![
+                (vec![2], "carol".to_string()),
+                (vec![usize::MAX], "dave".to_string()),
+            ]
+        );
+
+        // the origin of generated facts includes the rule's origin
+        let res = authorizer
+            .query_with_origin("data($u) <- admin($u)")
+            .unwrap();
+        assert_eq!(print(res), vec![(vec![0, usize::MAX], "alice".to_string())]);
+    }
+
+    #[test]
+    fn block_limits() {
+        use crate::builder::int;
+        use crate::{Biscuit, KeyPair};
+
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        for i in 0..10 {
+            builder
+                .add_fact(fact("edge", &[int(i), int(i + 1)]))
+                .unwrap();
+        }
+        let authority = builder.build(&root).unwrap();
+
+        let mut block = BlockBuilder::new();
+        block
+            .add_code("path($x, $y) <- edge($x, $y); path($x, $z) <- path($x, $y), edge($y, $z);")
+            .unwrap();
+        let token = authority.append(block).unwrap();
+
+        let authorize =
+            |token: &Biscuit, code: &str, facts: Option<u64>, iterations: Option<u64>| {
+                let mut authorizer = token.authorizer().unwrap();
+                authorizer.add_code(code).unwrap();
+                authorizer.set_limits(AuthorizerLimits {
+                    max_facts_per_block: facts,
+                    max_iterations_per_block: iterations,
+                    ..limits()
+                });
+                authorizer.authorize()
+            };
+
+        // 55 paths generated in 10 iterations
+        assert_eq!(authorize(&token, "allow if true", None, None), Ok(0));
+        assert_eq!(
+            authorize(&token, "allow if true", Some(55), Some(10)),
+            Ok(0)
+        );
+
+        let err = authorize(&token, "allow if true", Some(20), None).unwrap_err();
+        assert_eq!(
+            err,
+            error::Token::RunLimit(error::RunLimit::TooManyFactsInBlock { block_id: 1 })
+        );
+        assert_eq!(err.code(), error::ErrorCode::TooManyFactsInBlock(1));
+        assert_eq!(
+            authorize(&token, "allow if true", None, Some(3)),
+            Err(error::Token::RunLimit(
+                error::RunLimit::TooManyIterationsInBlock { block_id: 1 }
+            ))
+        );
+
+        // the quotas are not shared between the tokens of a reused authorizer
+        let mut authorizer = token.authorizer().unwrap();
+        authorizer.add_code("allow if true").unwrap();
+        authorizer.set_limits(AuthorizerLimits {
+            max_facts_per_block: Some(55),
+            max_iterations_per_block: Some(10),
+            ..limits()
+        });
+        for _ in 0..3 {
+            assert_eq!(authorizer.authorize(), Ok(0));
+            authorizer.reset_token_data().unwrap();
+            authorizer.add_token(&token).unwrap();
+        }
+
+        // the authorizer's rules are not limited
+        assert_eq!(
+            authorize(
+                &authority,
+                "path($x, $y) <- edge($x, $y); path($x, $z) <- path($x, $y), edge($y, $z); allow if true",
+                Some(1),
+                Some(1)
+            ),
+            Ok(0)
+        );
+    }
+
+    #[test]
+    fn incremental_authorization() {
+        use crate::datalog::RuleFiring;
+
+        let mut authorizer = Authorizer::new();
+        authorizer
+            .add_code(
+                r#"
+                parent("a", "b");
+                parent("b", "c");
+                ancestor($x, $y) <- parent($x, $y);
+                ancestor($x, $z) <- ancestor($x, $y), parent($y, $z);
+                allow if ancestor("a", "d");
+                "#,
+            )
+            .unwrap();
+        authorizer.set_limits(limits());
+
+        let mut derived = Vec::new();
+        let res = authorizer
+            .authorize_with_tracer(&mut |firing: &RuleFiring| derived.push(firing.print_fact()));
+        assert!(res.is_err());
+        assert_eq!(derived.len(), 3);
+
+        // only the facts depending on the new fact are derived
+        authorizer.add_fact(r#"parent("c", "d")"#).unwrap();
+        let mut derived = Vec::new();
+        let res = authorizer
+            .authorize_with_tracer(&mut |firing: &RuleFiring| derived.push(firing.print_fact()));
+        assert_eq!(res, Ok(0));
+        derived.sort();
+        assert_eq!(
+            derived,
+            vec![
+                "ancestor(\"a\", \"d\")",
+                "ancestor(\"b\", \"d\")",
+                "ancestor(\"c\", \"d\")"
+            ]
+        );
+
+        let mut derived = 0;
+        authorizer
+            .authorize_with_tracer(&mut |_: &RuleFiring| derived += 1)
+            .unwrap();
+        assert_eq!(derived, 0);
+    }
+
+    #[test]
+    fn profile() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(
+                r#"
+                user("alice");
+                member($u, "admins") <- user($u);
+                check if member("alice", "admins");
+                "#,
+            )
+            .unwrap();
+        let token = builder.build(&root).unwrap();
+
+        let mut authorizer = token.authorizer().unwrap();
+        authorizer
+            .add_code(
+                r#"
+                admin($u) <- member($u, "admins");
+                check if admin("alice");
+                allow if true;
+                "#,
+            )
+            .unwrap();
+        authorizer.set_limits(limits());
+
+        let (result, profile) = authorizer.profile();
+        assert_eq!(result, Ok(0));
+
+        let mut rules: Vec<_> = profile
+            .rules
+            .iter()
+            .map(|r| (r.origin, r.rule.as_str(), r.facts))
+            .collect();
+        rules.sort();
+        assert_eq!(
+            rules,
+            vec![
+                (0, "member($u, \"admins\") <- user($u)", 1),
+                (usize::MAX, "admin($u) <- member($u, \"admins\")", 1),
+            ]
+        );
+        assert!(profile.rules.iter().all(|r| r.iterations >= 1));
+
+        let checks: Vec<_> = profile
+            .checks
+            .iter()
+            .map(|c| (c.origin, c.check_id, c.check.as_str()))
+            .collect();
+        assert_eq!(
+            checks,
+            vec![
+                (usize::MAX, 0, "check if admin(\"alice\")"),
+                (0, 0, "check if member(\"alice\", \"admins\")"),
+            ]
+        );
+        assert_eq!(profile.slowest_checks().len(), 2);
+    }
+
+    #[test]
+    fn share_symbols() {
+        let root = KeyPair::new();
+        let token = |user: &str| {
+            let mut builder = Biscuit::builder();
+            builder.add_fact(fact("user", &[string(user)])).unwrap();
+            builder.build(&root).unwrap()
+        };
+
+        let mut base = Authorizer::new();
+        base.add_code(
+            r#"admin("alice");
+            is_admin($u) <- user($u), admin($u);
+            allow if is_admin($u);"#,
+        )
+        .unwrap();
+        base.set_limits(limits());
+        base.share_symbols();
+        assert!(base.symbols.strings().contains(&"is_admin".to_string()));
+
+        let mut authorizer = base.clone();
+        authorizer.add_token(&token("alice")).unwrap();
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        authorizer.reset_token_data().unwrap();
+        authorizer.add_token(&token("carol")).unwrap();
+        assert!(authorizer.authorize().is_err());
+        assert!(authorizer.symbols.strings().contains(&"carol".to_string()));
+        assert!(!base.symbols.strings().contains(&"carol".to_string()));
+
+        // the shared symbols are kept, the token's are removed
+        authorizer.reset_token_data().unwrap();
+        let strings = authorizer.symbols.strings();
+        assert!(strings.contains(&"is_admin".to_string()));
+        assert!(!strings.contains(&"carol".to_string()));
+        authorizer.add_token(&token("alice")).unwrap();
+        assert_eq!(authorizer.authorize(), Ok(0));
+    }
+
+    #[test]
+    fn query_max_results() {
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(r#"user("alice"); user("bob"); user("carol");"#)
+            .unwrap();
+        let biscuit = builder.build(&KeyPair::new()).unwrap();
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        authorizer.add_code("allow if true").unwrap();
+        authorizer.set_limits(AuthorizerLimits {
+            max_results: Some(2),
+            ..limits()
+        });
+
+        let too_many = Err(error::Token::RunLimit(error::RunLimit::TooManyResults));
+        assert_eq!(
+            authorizer.query::<_, (String,), _>("data($u) <- user($u)"),
+            too_many
+        );
+        assert_eq!(
+            authorizer.query_all::<_, (String,), _>("data($u) <- user($u)"),
+            too_many
+        );
+        let mut users = authorizer
+            .query_iter::<_, (String,), _>("data($u) <- user($u)")
+            .unwrap();
+        assert!(users.next().unwrap().is_ok());
+        assert!(users.next().unwrap().is_ok());
+        assert_eq!(
+            users.next().unwrap(),
+            Err(error::Token::RunLimit(error::RunLimit::TooManyResults))
+        );
+        assert!(users.next().is_none());
+        drop(users);
+        assert_eq!(
+            authorizer
+                .query_with_origin::<_, (String,), _>("data($u) <- user($u)")
+                .map(|_| ()),
+            Err(error::Token::RunLimit(error::RunLimit::TooManyResults))
+        );
+
+        // the limit does not apply to authorization
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        let users: Vec<(String,)> = authorizer
+            .query_iter(r#"data($u) <- user($u), $u != "carol""#)
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(users.len(), 2);
+    }
+
+    #[test]
+    fn stratified_aggregates() {
+        let limits = limits();
+
+        // `big_users` is only applied once `per_user` generated its facts
+        let mut authorizer = Authorizer::new();
+        authorizer
+            .add_code(
+                r#"
+                payment("alice", 8);
+                payment("alice", 7);
+                payment("bob", 3);
+                big("carol");
+                per_user($u, sum($a)) <- payment($u, $a);
+                big($u) <- per_user($u, $s), $s > 10;
+                big_users(count($u)) <- big($u);
+                allow if true;
+                "#,
+            )
+            .unwrap();
+        authorizer.set_limits(limits.clone());
+        assert_eq!(authorizer.authorize(), Ok(0));
+        let res: Vec<(i64,)> = authorizer.query_all("data($n) <- big_users($n)").unwrap();
+        assert_eq!(res, vec![(2,)]);
+
+        // an aggregate cannot depend on its own results
+        let mut authorizer = Authorizer::new();
+        authorizer
+            .add_code(
+                r#"
+                active("a");
+                count_active(count($x)) <- active($x);
+                active($n) <- count_active($n);
+                allow if true;
+                "#,
+            )
+            .unwrap();
+        authorizer.set_limits(limits);
+        assert_eq!(
+            authorizer.authorize(),
+            Err(error::Token::RecursiveAggregate(
+                "count_active(count($x)) <- active($x)".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn incremental_aggregates() {
+        let mut authorizer = Authorizer::new();
+        authorizer
+            .add_code(
+                r#"
+                session("a");
+                session("b");
+                total(count($s)) <- session($s);
+                check if total($n), $n < 3;
+                allow if true;
+                "#,
+            )
+            .unwrap();
+        authorizer.set_limits(limits());
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        // the aggregate is computed again with the new facts
+        authorizer.add_fact(r#"session("c")"#).unwrap();
+        authorizer.add_fact(r#"session("d")"#).unwrap();
+        assert!(authorizer.authorize().is_err());
+        let res: Vec<(i64,)> = authorizer.query_all("data($n) <- total($n)").unwrap();
+        assert_eq!(res, vec![(4,)]);
+    }
 }
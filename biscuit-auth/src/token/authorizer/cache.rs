@@ -0,0 +1,316 @@
+use std::collections::hash_map::RandomState;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hash, Hasher};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use super::Authorizer;
+use crate::error;
+
+/// memoizes authorization results, for tokens that are authorized many times
+///
+/// a decision is stored under the token's revocation identifiers, a hash of the
+/// authorizer's rules, checks, policies and configuration, and a hash of the
+/// authorizer's facts. The cached result is reused by
+/// [`Authorizer::authorize_cached`] when all three match. Facts that change on
+/// each request, like the current time, make the cache useless unless they are
+/// rounded, as in `time(2024-01-01T12:00:00Z)` truncated to the minute.
+///
+/// Only successful authorizations and failed checks or policies are cached:
+/// errors like run limits can depend on the load of the server. Decisions are
+/// not invalidated on their own, the application must call
+/// [`DecisionCache::invalidate`] when a token is revoked, or
+/// [`DecisionCache::clear`] when the data behind the authorizer facts changes.
+/// The authorizer's revocation, audience and seal checks still run on cache hits.
+///
+/// ```rust
+/// use biscuit_auth::{Authorizer, Biscuit, DecisionCache, KeyPair};
+///
+/// let root = KeyPair::new();
+/// let mut builder = Biscuit::builder();
+/// builder.add_fact(r#"user("alice")"#).unwrap();
+/// let token = builder.build(&root).unwrap();
+///
+/// let cache = DecisionCache::new(1000);
+/// for _ in 0..3 {
+///     let mut authorizer = token.authorizer().unwrap();
+///     authorizer.add_code(r#"allow if user("alice")"#).unwrap();
+///     assert_eq!(authorizer.authorize_cached(&cache), Ok(0));
+/// }
+/// assert_eq!(cache.stats().hits, 2);
+///
+/// cache.invalidate(&token.revocation_identifiers()[0]);
+/// assert!(cache.is_empty());
+/// ```
+pub struct DecisionCache {
+    entries: Mutex<HashMap<DecisionKey, Result<usize, error::Token>>>,
+    hasher: RandomState,
+    capacity: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// usage statistics of a [`DecisionCache`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DecisionCacheStats {
+    /// number of decisions returned from the cache
+    pub hits: u64,
+    /// number of authorizations that were not in the cache
+    pub misses: u64,
+    /// number of decisions currently in the cache
+    pub decisions: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+struct DecisionKey {
+    revocation_ids: Vec<Vec<u8>>,
+    policies: u64,
+    facts: u64,
+}
+
+impl DecisionCache {
+    /// creates a cache keeping up to `capacity` decisions
+    pub fn new(capacity: usize) -> Self {
+        DecisionCache {
+            entries: Mutex::new(HashMap::new()),
+            // the hashes are keyed per cache, so that collisions cannot be
+            // precomputed to get another token's decision
+            hasher: RandomState::new(),
+            capacity,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// removes the decisions of the tokens with this revocation identifier
+    pub fn invalidate(&self, revocation_id: &[u8]) {
+        self.lock()
+            .retain(|key, _| !key.revocation_ids.iter().any(|id| id == revocation_id));
+    }
+
+    /// removes all the decisions
+    pub fn clear(&self) {
+        self.lock().clear();
+    }
+
+    pub fn len(&self) -> usize {
+        self.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lock().is_empty()
+    }
+
+    /// returns the usage statistics of the cache
+    pub fn stats(&self) -> DecisionCacheStats {
+        DecisionCacheStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            decisions: self.len(),
+        }
+    }
+
+    fn get(&self, key: &DecisionKey) -> Option<Result<usize, error::Token>> {
+        let result = self.lock().get(key).cloned();
+        match result {
+            Some(_) => self.hits.fetch_add(1, Ordering::Relaxed),
+            None => self.misses.fetch_add(1, Ordering::Relaxed),
+        };
+        result
+    }
+
+    fn insert(&self, key: DecisionKey, result: &Result<usize, error::Token>) {
+        if !matches!(result, Ok(_) | Err(error::Token::FailedLogic(_))) {
+            return;
+        }
+
+        let mut entries = self.lock();
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            // no recency tracking: any entry can make room
+            let evicted = entries.keys().next().cloned();
+            if let Some(evicted) = evicted {
+                entries.remove(&evicted);
+            }
+        }
+        if self.capacity > 0 {
+            entries.insert(key, result.clone());
+        }
+    }
+
+    fn hash<I: IntoIterator<Item = String>>(&self, items: I) -> u64 {
+        let mut hasher = self.hasher.build_hasher();
+        for item in items {
+            item.hash(&mut hasher);
+        }
+        hasher.finish()
+    }
+
+    fn lock(&self) -> MutexGuard<'_, HashMap<DecisionKey, Result<usize, error::Token>>> {
+        // entries are inserted whole, so the map is still usable
+        // if a thread panicked while holding the lock
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Authorizer {
+    /// verifies the checks and policies, or returns the decision cached for the
+    /// same token and authorizer content, see [`DecisionCache`]
+    ///
+    /// on a cache hit, the Datalog is not evaluated: the authorizer cannot be
+    /// queried afterwards, and the observer is not called
+    pub fn authorize_cached(&mut self, cache: &DecisionCache) -> Result<usize, error::Token> {
+        // these checks do not depend on the Datalog, they run before
+        // the lookup as in `authorize`
+        self.check_revocation()?;
+        self.check_audience()?;
+        self.check_sealed()?;
+
+        let key = self.decision_key(cache);
+        if let Some(result) = cache.get(&key) {
+            return result;
+        }
+
+        let result = self.authorize();
+        cache.insert(key, &result);
+        result
+    }
+
+    fn decision_key(&self, cache: &DecisionCache) -> DecisionKey {
+        let builder = &self.authorizer_block_builder;
+        let policies = cache.hash(
+            builder
+                .rules
+                .iter()
+                .map(|r| r.to_string())
+                .chain(builder.checks.iter().map(|c| c.to_string()))
+                .chain(self.policies.iter().map(|p| p.to_string()))
+                .chain(builder.scopes.iter().map(|s| format!("scope {s}")))
+                .chain(
+                    self.expected_audiences
+                        .iter()
+                        .map(|a| format!("audience {a}")),
+                )
+                .chain(std::iter::once(format!(
+                    "sealed {} {}",
+                    self.require_sealed, self.token_sealed
+                ))),
+        );
+
+        // the authorizer facts, already loaded or not, sorted so that the
+        // insertion order does not matter
+        let mut facts: Vec<String> = builder.facts.iter().map(|f| f.to_string()).collect();
+        facts.extend(
+            self.world
+                .facts
+                .iter_all()
+                .filter(|(origin, _)| origin.inner.iter().eq(std::iter::once(&usize::MAX)))
+                .map(|(_, fact)| self.symbols.print_fact(fact)),
+        );
+        facts.sort();
+
+        DecisionKey {
+            revocation_ids: self.revocation_ids.clone(),
+            policies,
+            facts: cache.hash(facts),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::BlockBuilder;
+    use crate::{AuthorizerLimits, Biscuit, KeyPair};
+    use std::time::Duration;
+
+    fn limits() -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn decision_cache() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.add_fact(r#"user("alice")"#).unwrap();
+        let token = builder.build(&root).unwrap();
+        let mut block = BlockBuilder::new();
+        block.add_check(r#"check if operation("read")"#).unwrap();
+        let attenuated = token.append(block).unwrap();
+
+        let cache = DecisionCache::new(10);
+        let authorize = |token: &Biscuit, code: &str| {
+            let mut authorizer = token.authorizer().unwrap();
+            authorizer.add_code(code).unwrap();
+            authorizer.set_limits(limits());
+            authorizer.authorize_cached(&cache)
+        };
+
+        let allow = r#"operation("read"); allow if user("alice")"#;
+        assert_eq!(authorize(&token, allow), Ok(0));
+        assert_eq!(authorize(&token, allow), Ok(0));
+        assert_eq!(authorize(&attenuated, allow), Ok(0));
+        // same facts in another order
+        assert_eq!(
+            authorize(&token, r#"allow if user("alice"); operation("read");"#),
+            Ok(0)
+        );
+        assert_eq!(cache.stats().hits, 2);
+        assert_eq!(cache.len(), 2);
+
+        // other facts or policies are other decisions
+        let write = r#"operation("write"); allow if user("alice")"#;
+        assert!(matches!(
+            authorize(&attenuated, write),
+            Err(error::Token::FailedLogic(_))
+        ));
+        assert!(authorize(&attenuated, write).is_err());
+        assert_eq!(
+            authorize(&token, r#"operation("read"); deny if true; allow if true"#).err(),
+            authorize(&token, r#"operation("read"); deny if true; allow if true"#).err()
+        );
+        assert_eq!(cache.stats().hits, 4);
+        assert_eq!(cache.len(), 4);
+
+        cache.invalidate(&attenuated.revocation_identifiers()[1]);
+        assert_eq!(cache.len(), 2);
+        assert_eq!(authorize(&attenuated, allow), Ok(0));
+        assert_eq!(cache.stats().misses, 5);
+
+        // the revocation check runs before the lookup
+        let revoked = attenuated.revocation_identifiers()[0].clone();
+        let mut authorizer = attenuated.authorizer().unwrap();
+        authorizer.add_code(allow).unwrap();
+        authorizer.revocation_check(move |id: &[u8]| id == revoked.as_slice());
+        assert!(authorizer.authorize_cached(&cache).is_err());
+
+        cache.clear();
+        assert!(cache.is_empty());
+
+        // a sealed token has the same revocation ids as the unsealed one
+        let sealed = token.seal().unwrap();
+        for (token, sealed) in [(&sealed, true), (&token, false)] {
+            let mut authorizer = token.authorizer().unwrap();
+            authorizer.add_code(allow).unwrap();
+            authorizer.require_sealed();
+            authorizer.set_limits(limits());
+            let result = authorizer.authorize_cached(&cache);
+            assert_eq!(result.is_ok(), sealed);
+            assert_eq!(result.is_ok(), authorizer.authorize().is_ok());
+        }
+        cache.clear();
+
+        let small = DecisionCache::new(1);
+        for code in [allow, write] {
+            let mut authorizer = token.authorizer().unwrap();
+            authorizer.add_code(code).unwrap();
+            authorizer.set_limits(limits());
+            assert_eq!(authorizer.authorize_cached(&small), Ok(0));
+        }
+        assert_eq!(small.len(), 1);
+    }
+}
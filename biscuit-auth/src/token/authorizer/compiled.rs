@@ -0,0 +1,372 @@
+use std::convert::TryFrom;
+
+use prost::Message;
+
+use super::Authorizer;
+use crate::builder::{Check, Convert, Policy, Rule, Scope};
+use crate::crypto::PublicKey;
+use crate::datalog::{self, Origin, SymbolTable};
+use crate::error;
+use crate::format::convert::v2::{
+    policy_to_proto_policy, proto_check_to_token_check, proto_fact_to_token_fact,
+    proto_policy_to_policy, proto_rule_to_token_rule, proto_scope_to_token_scope,
+    token_check_to_proto_check, token_fact_to_proto_fact, token_rule_to_proto_rule,
+    token_scope_to_proto_scope,
+};
+use crate::format::schema;
+use crate::token::{check_schema_version, default_symbol_table, MAX_SCHEMA_VERSION};
+
+/// authorizer facts converted to Datalog ahead of time, with the authorizer's
+/// rules, checks and policies
+///
+/// unlike [`AuthorizerPolicies`](super::AuthorizerPolicies), the artifact stores
+/// the symbol table of the authorizer: loading it with [`Authorizer::from_compiled`]
+/// restores the table as is, and the facts are inserted directly in the Datalog
+/// world, without converting or interning them again. Only the facts are
+/// precompiled: the rules, checks and policies are converted when the
+/// authorizer runs, as with [`Authorizer::from`], so the artifact mostly
+/// helps authorizers with many facts.
+///
+/// ```rust
+/// use biscuit_auth::{Authorizer, CompiledPolicies};
+///
+/// let mut authorizer = Authorizer::new();
+/// authorizer
+///     .add_code(r#"admin("alice"); allow if user($u), admin($u);"#)
+///     .unwrap();
+/// let data = authorizer.compile().unwrap().to_vec().unwrap();
+///
+/// // at service startup
+/// let compiled = CompiledPolicies::from_bytes(&data).unwrap();
+/// let mut authorizer = Authorizer::from_compiled(&compiled);
+/// authorizer.add_fact(r#"user("alice")"#).unwrap();
+/// assert_eq!(authorizer.authorize(), Ok(0));
+/// ```
+#[derive(Clone, Debug)]
+pub struct CompiledPolicies {
+    symbols: SymbolTable,
+    facts: Vec<datalog::Fact>,
+    rules: Vec<Rule>,
+    checks: Vec<Check>,
+    policies: Vec<Policy>,
+    scopes: Vec<Scope>,
+}
+
+impl CompiledPolicies {
+    /// number of facts
+    pub fn fact_count(&self) -> usize {
+        self.facts.len()
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>, error::Format> {
+        let mut symbols = self.symbols.clone();
+
+        let rules = self
+            .rules
+            .iter()
+            .map(|rule| token_rule_to_proto_rule(&rule.convert(&mut symbols)))
+            .collect();
+        let checks = self
+            .checks
+            .iter()
+            .map(|check| token_check_to_proto_check(&check.convert(&mut symbols)))
+            .collect();
+        let policies = self
+            .policies
+            .iter()
+            .map(|policy| policy_to_proto_policy(policy, &mut symbols))
+            .collect();
+        let scopes = self
+            .scopes
+            .iter()
+            .map(|scope| token_scope_to_proto_scope(&scope.convert(&mut symbols)))
+            .collect();
+
+        let proto = schema::CompiledPolicies {
+            version: MAX_SCHEMA_VERSION,
+            symbols: symbols.strings(),
+            public_keys: symbols
+                .public_keys
+                .keys
+                .iter()
+                .map(|key| key.to_proto())
+                .collect(),
+            facts: self.facts.iter().map(token_fact_to_proto_fact).collect(),
+            rules,
+            checks,
+            policies,
+            scopes,
+        };
+
+        let mut v = Vec::new();
+        proto.encode(&mut v).map_err(|e| {
+            error::Format::SerializationError(format!("serialization error: {:?}", e))
+        })?;
+        Ok(v)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, error::Format> {
+        let proto = schema::CompiledPolicies::decode(data).map_err(|e| {
+            error::Format::DeserializationError(format!("deserialization error: {:?}", e))
+        })?;
+
+        let version = proto.version;
+        check_schema_version(version)?;
+
+        let public_keys = proto
+            .public_keys
+            .iter()
+            .map(PublicKey::from_proto)
+            .collect::<Result<Vec<_>, _>>()?;
+        let symbols = SymbolTable::from_symbols_and_public_keys(proto.symbols, public_keys)?;
+
+        // the facts are inserted in the world as is, so their symbols must
+        // exist in the table
+        let facts = proto
+            .facts
+            .iter()
+            .map(|fact| {
+                let fact = proto_fact_to_token_fact(fact)?;
+                check_symbol(fact.predicate.name, &symbols)?;
+                for term in &fact.predicate.terms {
+                    check_term_symbols(term, &symbols)?;
+                }
+                Ok(fact)
+            })
+            .collect::<Result<Vec<_>, error::Format>>()?;
+        let rules = proto
+            .rules
+            .iter()
+            .map(|rule| Rule::convert_from(&proto_rule_to_token_rule(rule, version)?.0, &symbols))
+            .collect::<Result<Vec<_>, _>>()?;
+        let checks = proto
+            .checks
+            .iter()
+            .map(|check| {
+                Check::convert_from(&proto_check_to_token_check(check, version)?, &symbols)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let policies = proto
+            .policies
+            .iter()
+            .map(|policy| proto_policy_to_policy(policy, &symbols, version))
+            .collect::<Result<Vec<_>, _>>()?;
+        let scopes = proto
+            .scopes
+            .iter()
+            .map(|scope| Scope::convert_from(&proto_scope_to_token_scope(scope)?, &symbols))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(CompiledPolicies {
+            symbols,
+            facts,
+            rules,
+            checks,
+            policies,
+            scopes,
+        })
+    }
+}
+
+fn check_symbol(index: u64, symbols: &SymbolTable) -> Result<(), error::Format> {
+    match symbols.get_symbol(index) {
+        Some(_) => Ok(()),
+        None => Err(error::Format::UnknownSymbol(index)),
+    }
+}
+
+fn check_term_symbols(term: &datalog::Term, symbols: &SymbolTable) -> Result<(), error::Format> {
+    match term {
+        datalog::Term::Str(index) => check_symbol(*index, symbols),
+        datalog::Term::Set(terms) => terms
+            .iter()
+            .try_for_each(|term| check_term_symbols(term, symbols)),
+        _ => Ok(()),
+    }
+}
+
+impl Authorizer {
+    /// converts the facts, rules, checks and policies of the authorizer to a
+    /// [`CompiledPolicies`] artifact
+    ///
+    /// the authorizer must not contain a token
+    pub fn compile(&self) -> Result<CompiledPolicies, error::Token> {
+        if self.blocks.is_some() {
+            return Err(error::Logic::AuthorizerNotEmpty.into());
+        }
+
+        let mut symbols = default_symbol_table();
+        let facts: Vec<datalog::Fact> = self
+            .authorizer_facts()?
+            .iter()
+            .map(|fact| fact.convert(&mut symbols))
+            .collect();
+
+        let b = &self.authorizer_block_builder;
+        for rule in &b.rules {
+            rule.convert(&mut symbols);
+        }
+        for check in &b.checks {
+            check.convert(&mut symbols);
+        }
+        for policy in &self.policies {
+            for query in &policy.queries {
+                query.convert(&mut symbols);
+            }
+        }
+        for scope in &b.scopes {
+            scope.convert(&mut symbols);
+        }
+
+        Ok(CompiledPolicies {
+            symbols,
+            facts,
+            rules: b.rules.clone(),
+            checks: b.checks.clone(),
+            policies: self.policies.clone(),
+            scopes: b.scopes.clone(),
+        })
+    }
+
+    /// creates an authorizer from policies compiled with [`Authorizer::compile`]
+    ///
+    /// the facts are loaded in the Datalog world, and are still returned by
+    /// [`Authorizer::save`] and stored in snapshots
+    pub fn from_compiled(compiled: &CompiledPolicies) -> Self {
+        let mut authorizer = Authorizer::new();
+        authorizer.symbols = compiled.symbols.clone();
+
+        let mut authorizer_origin = Origin::default();
+        authorizer_origin.insert(usize::MAX);
+        for fact in &compiled.facts {
+            authorizer.world.add_fact(&authorizer_origin, fact.clone());
+        }
+
+        authorizer.authorizer_block_builder.rules = compiled.rules.clone();
+        authorizer.authorizer_block_builder.checks = compiled.checks.clone();
+        authorizer.authorizer_block_builder.scopes = compiled.scopes.clone();
+        authorizer.policies = compiled.policies.clone();
+
+        authorizer
+    }
+}
+
+impl TryFrom<&Authorizer> for CompiledPolicies {
+    type Error = error::Token;
+
+    fn try_from(authorizer: &Authorizer) -> Result<Self, Self::Error> {
+        authorizer.compile()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AuthorizerLimits, Biscuit, KeyPair};
+    use std::time::Duration;
+
+    fn limits() -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn compiled_policies() {
+        let root = KeyPair::new();
+        let external = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.add_fact(r#"user("alice")"#).unwrap();
+        let token = builder.build(&root).unwrap();
+
+        let mut authorizer = Authorizer::new();
+        authorizer
+            .add_code(format!(
+                r#"admin("alice");
+                is_admin($u) <- user($u), admin($u);
+                check if is_admin($u) or group("admin") trusting authority, {};
+                deny if revoked(true);
+                allow if is_admin($u);"#,
+                external.public()
+            ))
+            .unwrap();
+
+        let compiled = authorizer.compile().unwrap();
+        assert_eq!(compiled.fact_count(), 1);
+        let compiled = CompiledPolicies::from_bytes(&compiled.to_vec().unwrap()).unwrap();
+
+        let mut loaded = Authorizer::from_compiled(&compiled);
+
+        // the compiled facts are saved with the other policies
+        let saved = loaded.save().unwrap();
+        assert_eq!(
+            saved
+                .facts
+                .iter()
+                .map(|fact| fact.to_string())
+                .collect::<Vec<_>>(),
+            vec!["admin(\"alice\")"]
+        );
+        assert_eq!(saved.rules.len(), 1);
+        let snapshot = loaded.snapshot().unwrap();
+        assert_eq!(snapshot.world.authorizer_block.facts_v2.len(), 1);
+
+        loaded.add_token(&token).unwrap();
+        loaded.set_limits(limits());
+        assert_eq!(loaded.authorize(), Ok(1));
+        assert_eq!(
+            loaded
+                .query_all("data($u) <- is_admin($u)")
+                .map(|facts: Vec<(String,)>| facts),
+            Ok(vec![("alice".to_string(),)])
+        );
+
+        let mut expected = authorizer.clone();
+        expected.add_token(&token).unwrap();
+        expected.set_limits(limits());
+        assert_eq!(expected.authorize(), Ok(1));
+        assert_eq!(loaded.print_world(), expected.print_world());
+
+        assert_eq!(
+            CompiledPolicies::try_from(&loaded).unwrap_err(),
+            error::Token::FailedLogic(error::Logic::AuthorizerNotEmpty)
+        );
+        assert!(CompiledPolicies::from_bytes(&[1, 2, 3]).is_err());
+    }
+
+    #[test]
+    fn unknown_fact_symbols() {
+        let mut authorizer = Authorizer::new();
+        authorizer
+            .add_code(r#"admin("alice"); group(["admin"]); allow if true;"#)
+            .unwrap();
+        let data = authorizer.compile().unwrap().to_vec().unwrap();
+        let proto = schema::CompiledPolicies::decode(&data[..]).unwrap();
+        let unknown = 1024 + proto.symbols.len() as u64;
+
+        let mut invalid = proto.clone();
+        invalid.facts[0].predicate.name = unknown;
+        assert_eq!(
+            CompiledPolicies::from_bytes(&invalid.encode_to_vec()).unwrap_err(),
+            error::Format::UnknownSymbol(unknown)
+        );
+
+        for fact in 0..2 {
+            let mut invalid = proto.clone();
+            let mut term = invalid.facts[fact].predicate.terms[0].clone();
+            match &mut term.content {
+                Some(schema::term_v2::Content::String(index)) => *index = unknown,
+                Some(schema::term_v2::Content::Set(set)) => {
+                    set.set[0].content = Some(schema::term_v2::Content::String(unknown))
+                }
+                _ => unreachable!(),
+            }
+            invalid.facts[fact].predicate.terms[0] = term;
+            assert_eq!(
+                CompiledPolicies::from_bytes(&invalid.encode_to_vec()).unwrap_err(),
+                error::Format::UnknownSymbol(unknown)
+            );
+        }
+    }
+}
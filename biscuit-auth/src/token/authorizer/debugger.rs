@@ -0,0 +1,241 @@
+use std::time::Duration;
+
+use super::Authorizer;
+use crate::builder::{Convert, Fact};
+use crate::datalog::{DebugHooks, FactSet};
+use crate::error;
+
+/// step by step evaluation of the authorizer's Datalog, created by
+/// [`Authorizer::debugger`]
+///
+/// each call to [`Debugger::step`] runs one iteration of the rules and returns
+/// the facts it generated. The evaluation is paused between steps: the time
+/// spent waiting is not counted in the execution time limit. Once the
+/// evaluation is done, [`Debugger::finish`] runs the checks and policies.
+///
+/// ```rust
+/// use biscuit_auth::Authorizer;
+///
+/// let mut authorizer = Authorizer::new();
+/// authorizer
+///     .add_code(
+///         r#"parent("a", "b"); parent("b", "c");
+///         ancestor($x, $y) <- parent($x, $y);
+///         ancestor($x, $z) <- ancestor($x, $y), parent($y, $z);
+///         allow if ancestor("a", "c");"#,
+///     )
+///     .unwrap();
+///
+/// let mut debugger = authorizer.debugger().unwrap();
+/// let first = debugger.step().unwrap().unwrap();
+/// assert_eq!(first.index, 0);
+/// assert_eq!(first.facts.len(), 2);
+///
+/// let second = debugger.step().unwrap().unwrap();
+/// assert_eq!(second.facts[0].1.to_string(), r#"ancestor("a", "c")"#);
+///
+/// assert!(debugger.step().unwrap().is_none());
+/// assert_eq!(debugger.finish(), Ok(0));
+/// ```
+pub struct Debugger<'a> {
+    authorizer: &'a mut Authorizer,
+    iteration: u64,
+    finished: bool,
+}
+
+/// facts generated by one iteration of the Datalog engine
+#[derive(Clone, Debug, PartialEq)]
+pub struct DebugIteration {
+    /// index of the iteration, starting at 0
+    pub index: u64,
+    /// new facts, with the blocks they were derived from (`usize::MAX` for the authorizer)
+    pub facts: Vec<(Vec<usize>, Fact)>,
+}
+
+#[derive(Default)]
+struct NewFacts(FactSet);
+
+impl DebugHooks for NewFacts {
+    fn on_new_facts(&mut self, _iteration: u64, facts: &FactSet) {
+        self.0 = facts.clone();
+    }
+}
+
+impl Authorizer {
+    /// starts a step by step evaluation of the Datalog rules, see [`Debugger`]
+    pub fn debugger(&mut self) -> Result<Debugger<'_>, error::Token> {
+        self.remaining_limits()?;
+        self.load_authorizer_block();
+
+        Ok(Debugger {
+            authorizer: self,
+            iteration: 0,
+            finished: false,
+        })
+    }
+}
+
+impl<'a> Debugger<'a> {
+    /// runs one iteration of the rules
+    ///
+    /// returns `None` once the rules do not generate new facts
+    pub fn step(&mut self) -> Result<Option<DebugIteration>, error::Token> {
+        if self.finished {
+            return Ok(None);
+        }
+
+        let authorizer = &mut *self.authorizer;
+        let limits = authorizer.remaining_limits()?;
+        let start = authorizer.clock.instant();
+        let mut index = 0;
+        let mut hooks = NewFacts::default();
+
+        let result = authorizer.world.run_iteration(
+            &authorizer.symbols,
+            &limits,
+            authorizer.clock.as_ref(),
+            start + limits.max_time,
+            &mut index,
+            &mut hooks,
+        );
+        authorizer.execution_time += authorizer.clock.instant() - start;
+
+        if result? {
+            self.finished = true;
+            return Ok(None);
+        }
+
+        let mut facts = hooks
+            .0
+            .iter_all()
+            .map(|(origin, fact)| {
+                Ok((
+                    origin.inner.iter().copied().collect(),
+                    Fact::convert_from(fact, &authorizer.symbols)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, error::Format>>()?;
+        facts.sort_by_key(|(_, fact)| fact.to_string());
+
+        let iteration = DebugIteration {
+            index: self.iteration,
+            facts,
+        };
+        self.iteration += 1;
+        Ok(Some(iteration))
+    }
+
+    /// runs the remaining iterations, and returns them
+    pub fn resume(&mut self) -> Result<Vec<DebugIteration>, error::Token> {
+        let mut iterations = vec![];
+        while let Some(iteration) = self.step()? {
+            iterations.push(iteration);
+        }
+        Ok(iterations)
+    }
+
+    /// returns true once the rules do not generate new facts
+    pub fn is_finished(&self) -> bool {
+        self.finished
+    }
+
+    /// facts known at this step of the evaluation
+    pub fn facts(&self) -> Result<Vec<Fact>, error::Format> {
+        self.authorizer
+            .world
+            .facts
+            .iter_all()
+            .map(|(_, fact)| Fact::convert_from(fact, &self.authorizer.symbols))
+            .collect()
+    }
+
+    /// time spent evaluating the rules, including previous runs of the authorizer
+    pub fn execution_time(&self) -> Duration {
+        self.authorizer.execution_time
+    }
+
+    /// runs the remaining iterations, then the checks and policies, like
+    /// [`Authorizer::authorize`]
+    pub fn finish(mut self) -> Result<usize, error::Token> {
+        self.resume()?;
+
+        // the authorizer rules are loaded again by the authorization
+        self.authorizer.world.rules.remove_origin(usize::MAX);
+        self.authorizer.authorize()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AuthorizerLimits, Biscuit, KeyPair};
+
+    fn limits() -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn debugger() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(r#"edge(1, 2); edge(2, 3); edge(3, 4);"#)
+            .unwrap();
+        let token = builder.build(&root).unwrap();
+
+        let mut authorizer = token.authorizer().unwrap();
+        authorizer
+            .add_code(
+                r#"path($x, $y) <- edge($x, $y);
+                path($x, $z) <- path($x, $y), edge($y, $z);
+                check if path(1, 4);
+                allow if true;"#,
+            )
+            .unwrap();
+        authorizer.set_limits(limits());
+
+        let mut debugger = authorizer.debugger().unwrap();
+        let first = debugger.step().unwrap().unwrap();
+        assert_eq!(first.index, 0);
+        assert_eq!(
+            first
+                .facts
+                .iter()
+                .map(|(_, f)| f.to_string())
+                .collect::<Vec<_>>(),
+            vec!["path(1, 2)", "path(2, 3)", "path(3, 4)"]
+        );
+        assert_eq!(first.facts[0].0, vec![0, usize::MAX]);
+        assert_eq!(debugger.facts().unwrap().len(), 6);
+        assert!(!debugger.is_finished());
+
+        let rest = debugger.resume().unwrap();
+        assert_eq!(
+            rest.iter().map(|i| i.facts.len()).collect::<Vec<_>>(),
+            vec![2, 1]
+        );
+        assert_eq!(rest[1].facts[0].1.to_string(), "path(1, 4)");
+        assert!(debugger.is_finished());
+        assert_eq!(debugger.step(), Ok(None));
+        assert_eq!(debugger.finish(), Ok(0));
+
+        // iterations run by the debugger are counted in the limits
+        let mut authorizer = token.authorizer().unwrap();
+        authorizer
+            .add_code("path($x, $y) <- edge($x, $y); path($x, $z) <- path($x, $y), edge($y, $z);")
+            .unwrap();
+        authorizer.set_limits(AuthorizerLimits {
+            max_iterations: 2,
+            ..limits()
+        });
+        let mut debugger = authorizer.debugger().unwrap();
+        assert!(debugger.step().unwrap().is_some());
+        assert_eq!(
+            debugger.step(),
+            Err(error::Token::RunLimit(error::RunLimit::TooManyIterations))
+        );
+    }
+}
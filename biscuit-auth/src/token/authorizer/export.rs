@@ -0,0 +1,224 @@
+use std::collections::HashSet;
+use std::convert::TryInto;
+
+use super::{Authorizer, AuthorizerLimits};
+use crate::builder::{BlockBuilder, Convert, Fact, Rule, Scope};
+use crate::datalog::{self, FactSet, TrustedOrigins};
+use crate::{error, token};
+
+impl Authorizer {
+    /// adds the facts generated by a query to a block, to attenuate the token
+    /// with data computed during authorization, like resolved roles
+    ///
+    /// this is meant to be called after [`Authorizer::authorize`], which loads
+    /// the authorizer's facts and rules. The query is run like
+    /// [`Authorizer::query`], then a fact is only
+    /// exported if it was derived from the authority block, the authorizer,
+    /// and the blocks trusted by `scopes`: facts depending on attenuation or
+    /// third party blocks are skipped, even if the query trusts them, unless
+    /// they are listed in `scopes`. The facts are added sorted by their
+    /// Datalog source. Returns the number of exported facts.
+    ///
+    /// ```rust
+    /// use biscuit_auth::{builder::BlockBuilder, Biscuit, KeyPair};
+    ///
+    /// let root = KeyPair::new();
+    /// let mut builder = Biscuit::builder();
+    /// builder.add_fact(r#"user("alice")"#).unwrap();
+    /// let token = builder.build(&root).unwrap();
+    ///
+    /// let mut authorizer = token.authorizer().unwrap();
+    /// authorizer
+    ///     .add_code(r#"
+    ///         member("alice", "admins");
+    ///         role($u, "admin") <- user($u), member($u, "admins");
+    ///         allow if role($u, "admin");
+    ///     "#)
+    ///     .unwrap();
+    /// authorizer.authorize().unwrap();
+    ///
+    /// let mut block = BlockBuilder::new();
+    /// authorizer
+    ///     .export_facts(r#"role($u, $r) <- role($u, $r)"#, &[], &mut block)
+    ///     .unwrap();
+    /// let token = token.append(block).unwrap();
+    /// assert_eq!(token.print_block_source(1).unwrap(), "role(\"alice\", \"admin\");\n");
+    /// ```
+    pub fn export_facts<R: TryInto<Rule>>(
+        &mut self,
+        rule: R,
+        scopes: &[Scope],
+        block: &mut BlockBuilder,
+    ) -> Result<usize, error::Token>
+    where
+        error::Token: From<<R as TryInto<Rule>>::Error>,
+    {
+        let limits = self.remaining_limits()?;
+        let rule = rule.try_into()?.convert(&mut self.symbols);
+
+        let mut export_scopes = vec![token::Scope::Authority];
+        export_scopes.extend(scopes.iter().map(|s| s.convert(&mut self.symbols)));
+        let exported_origins = TrustedOrigins::from_scopes(
+            &export_scopes,
+            &TrustedOrigins::default(),
+            usize::MAX,
+            &self.public_key_to_block_id,
+        );
+
+        let start = self.clock.instant();
+        let result = self.query_facts(rule, limits);
+        self.execution_time += self.clock.instant() - start;
+
+        let facts: HashSet<datalog::Fact> = result?
+            .inner
+            .into_iter()
+            .filter(|(origin, _)| exported_origins.contains(origin))
+            .flat_map(|(_, facts)| datalog::unwrap_facts(facts))
+            .collect();
+
+        let mut exported = facts
+            .iter()
+            .map(|fact| Fact::convert_from(fact, &self.symbols))
+            .collect::<Result<Vec<_>, _>>()?;
+        // the facts are added in the order of their source, not in the
+        // iteration order of the set
+        exported.sort_by_cached_key(|fact| fact.to_string());
+
+        let count = exported.len();
+        block.facts.extend(exported);
+        Ok(count)
+    }
+
+    /// runs a query like [`Authorizer::query`], keeping the origin of the facts
+    fn query_facts(
+        &mut self,
+        rule: datalog::Rule,
+        limits: AuthorizerLimits,
+    ) -> Result<FactSet, error::Token> {
+        let rule_trusted_origins = TrustedOrigins::from_scopes(
+            &rule.scopes,
+            &TrustedOrigins::default(),
+            usize::MAX,
+            &self.public_key_to_block_id,
+        );
+
+        let max_results = limits.max_results;
+        self.world
+            .run_with_clock(&self.symbols, limits, self.clock.as_ref())?;
+        Ok(self.world.query_rule_with_limit(
+            rule,
+            usize::MAX,
+            &rule_trusted_origins,
+            &self.symbols,
+            max_results,
+        )?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::{BlockBuilder, Scope};
+    use crate::{AuthorizerLimits, Biscuit, KeyPair};
+    use std::collections::HashMap;
+    use std::time::Duration;
+
+    fn limits() -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn export_facts() {
+        let root = KeyPair::new();
+        let external = KeyPair::new();
+
+        let mut builder = Biscuit::builder();
+        builder.add_fact(r#"user("alice")"#).unwrap();
+        // the external key must be known by the token to be trusted
+        builder
+            .add_rule(
+                format!(
+                    "groups($g) <- member(\"alice\", $g) trusting {}",
+                    external.public()
+                )
+                .as_str(),
+            )
+            .unwrap();
+        let token = builder.build(&root).unwrap();
+
+        let mut block = BlockBuilder::new();
+        block.add_fact(r#"member("alice", "auditors")"#).unwrap();
+        let token = token.append(block).unwrap();
+
+        let mut block = BlockBuilder::new();
+        block.add_fact(r#"member("alice", "support")"#).unwrap();
+        let req = token.third_party_request().unwrap();
+        let res = req.create_block(&external.private(), block).unwrap();
+        let token = token.append_third_party(external.public(), res).unwrap();
+
+        let mut scope_params = HashMap::new();
+        scope_params.insert("external".to_string(), external.public());
+        let mut authorizer = token.authorizer().unwrap();
+        authorizer
+            .add_code_with_params(
+                r#"member("alice", "admins");
+                role($u, $g) <- user($u), member($u, $g) trusting authority, {external};
+                allow if true;"#,
+                HashMap::new(),
+                scope_params,
+            )
+            .unwrap();
+        authorizer.set_limits(limits());
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        let query = format!(
+            "role($u, $g) <- role($u, $g) trusting authority, {}",
+            external.public()
+        );
+
+        // the third party fact is not exported by default
+        let mut block = BlockBuilder::new();
+        assert_eq!(
+            authorizer.export_facts(query.as_str(), &[], &mut block),
+            Ok(1)
+        );
+        assert_eq!(block.to_string(), "role(\"alice\", \"admins\");\n");
+
+        let mut block = BlockBuilder::new();
+        assert_eq!(
+            authorizer.export_facts(
+                query.as_str(),
+                &[Scope::PublicKey(external.public())],
+                &mut block
+            ),
+            Ok(2)
+        );
+        let token = token.append(block).unwrap();
+        let facts = token
+            .block_facts(3)
+            .unwrap()
+            .iter()
+            .map(|f| f.to_string())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            facts,
+            vec![
+                "role(\"alice\", \"admins\")",
+                "role(\"alice\", \"support\")"
+            ]
+        );
+
+        // the query only sees the facts it trusts
+        let mut block = BlockBuilder::new();
+        assert_eq!(
+            authorizer.export_facts(
+                "role($u, $g) <- role($u, $g)",
+                &[Scope::PublicKey(external.public())],
+                &mut block
+            ),
+            Ok(1)
+        );
+    }
+}
@@ -0,0 +1,129 @@
+use super::Authorizer;
+use crate::error;
+use crate::token::Biscuit;
+use std::convert::TryFrom;
+
+/// authorizer with facts, rules, checks and policies that cannot be modified,
+/// used as a template to authorize requests
+///
+/// it is created by [`Authorizer::freeze`], which loads the authorizer facts
+/// and moves the symbols to a shared table. Creating an [`Authorizer`] from it
+/// is cheap: the symbols and the fact sets are shared with the template, and
+/// are only copied when the new authorizer modifies them. It can be shared
+/// between threads, in an `Arc` or a `static`.
+///
+/// The frozen authorizer must not contain a token: freezing an authorizer
+/// with a token fails with [`error::Logic::AuthorizerNotEmpty`].
+///
+/// ```rust
+/// use biscuit_auth::{Authorizer, Biscuit, KeyPair};
+///
+/// let mut base = Authorizer::new();
+/// base.add_code(r#"group("admins"); allow if user($u), member($u, "admins");"#)
+///     .unwrap();
+/// let frozen = base.freeze().unwrap();
+///
+/// let root = KeyPair::new();
+/// let mut builder = Biscuit::builder();
+/// builder
+///     .add_code(r#"user("alice"); member("alice", "admins");"#)
+///     .unwrap();
+/// let token = builder.build(&root).unwrap();
+///
+/// let mut authorizer = frozen.authorizer_with_token(&token).unwrap();
+/// assert_eq!(authorizer.authorize(), Ok(0));
+/// ```
+#[derive(Clone)]
+pub struct FrozenAuthorizer(Authorizer);
+
+impl FrozenAuthorizer {
+    pub(super) fn new(mut authorizer: Authorizer) -> Result<Self, error::Token> {
+        if authorizer.blocks.is_some() {
+            return Err(error::Logic::AuthorizerNotEmpty.into());
+        }
+
+        authorizer.share_symbols();
+        Ok(FrozenAuthorizer(authorizer))
+    }
+
+    /// creates an authorizer from the template, without a token
+    pub fn authorizer(&self) -> Authorizer {
+        self.0.clone()
+    }
+
+    /// creates an authorizer from the template and adds the token
+    pub fn authorizer_with_token(&self, token: &Biscuit) -> Result<Authorizer, error::Token> {
+        let mut authorizer = self.0.clone();
+        authorizer.add_token(token)?;
+        Ok(authorizer)
+    }
+
+    /// returns the template authorizer, to print or serialize it
+    pub fn as_authorizer(&self) -> &Authorizer {
+        &self.0
+    }
+
+    /// returns the template authorizer, so it can be modified and frozen again
+    pub fn into_authorizer(self) -> Authorizer {
+        self.0
+    }
+}
+
+impl TryFrom<Authorizer> for FrozenAuthorizer {
+    type Error = error::Token;
+
+    fn try_from(authorizer: Authorizer) -> Result<Self, Self::Error> {
+        FrozenAuthorizer::new(authorizer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AuthorizerLimits, KeyPair};
+    use std::time::Duration;
+
+    fn limits() -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn frozen_authorizer() {
+        fn assert_send_sync<T: Send + Sync>() {}
+        assert_send_sync::<FrozenAuthorizer>();
+
+        let mut base = Authorizer::new();
+        base.set_limits(limits());
+        base.add_code(r#"role("admin"); allow if user($u), role("admin");"#)
+            .unwrap();
+        let frozen = base.freeze().unwrap();
+
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.add_fact(r#"user("alice")"#).unwrap();
+        let token = builder.build(&root).unwrap();
+
+        let mut authorizer = frozen.authorizer_with_token(&token).unwrap();
+        authorizer.add_fact(r#"request("read")"#).unwrap();
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        // the facts added to a request authorizer are not visible in the template
+        let mut authorizer = frozen.authorizer();
+        assert!(authorizer.add_token(&token).is_ok());
+        let requests: Vec<(String,)> = authorizer.query_all(r#"data($r) <- request($r)"#).unwrap();
+        assert!(requests.is_empty());
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        assert!(frozen.authorizer().authorize().is_err());
+
+        // an authorizer with a token cannot be used as a template
+        let authorizer = frozen.authorizer_with_token(&token).unwrap();
+        assert_eq!(
+            authorizer.freeze().err(),
+            Some(error::Token::FailedLogic(error::Logic::AuthorizerNotEmpty))
+        );
+    }
+}
@@ -0,0 +1,363 @@
+use std::io::{Read, Write};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use prost::Message;
+use sha2::{Digest, Sha256};
+
+use super::Authorizer;
+use crate::format::schema;
+use crate::{error, Biscuit};
+
+/// authorization decision recorded by a [`DecisionJournal`]
+///
+/// the snapshot is taken before running the authorizer, so the decision can be
+/// replayed with [`JournalEntry::authorizer`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct JournalEntry {
+    /// SHA256 hash of the serialized token
+    pub token_hash: Option<Vec<u8>>,
+    /// authorizer state before the decision
+    pub snapshot: schema::AuthorizerSnapshot,
+    /// index of the matched allow policy, or the error message
+    pub decision: Result<usize, String>,
+    /// resources used by the decision
+    pub limits: LimitsReport,
+}
+
+/// resources used by an authorization
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LimitsReport {
+    pub execution_time: Duration,
+    pub iterations: u64,
+    pub facts: u64,
+}
+
+impl JournalEntry {
+    /// recreates the authorizer as it was before the decision
+    ///
+    /// calling [`Authorizer::authorize`] on it replays the decision. The
+    /// revocation store and clock are not part of the snapshot
+    pub fn authorizer(&self) -> Result<Authorizer, error::Token> {
+        Authorizer::from_snapshot(self.snapshot.clone())
+    }
+
+    pub fn to_proto(&self) -> schema::DecisionRecord {
+        let (policy, error) = match &self.decision {
+            Ok(policy) => (Some(*policy as u64), None),
+            Err(e) => (None, Some(e.clone())),
+        };
+
+        schema::DecisionRecord {
+            token_hash: self.token_hash.clone(),
+            snapshot: self.snapshot.clone(),
+            policy,
+            error,
+            execution_time: self.limits.execution_time.as_nanos() as u64,
+            iterations: self.limits.iterations,
+            facts: self.limits.facts,
+        }
+    }
+
+    pub fn from_proto(record: schema::DecisionRecord) -> Result<Self, error::Format> {
+        let decision = match (record.policy, record.error) {
+            (Some(policy), None) => Ok(policy as usize),
+            (None, Some(e)) => Err(e),
+            _ => return Err(error::Format::DeserializationError(
+                "deserialization error: a decision record must contain either a policy or an error"
+                    .to_string(),
+            )),
+        };
+
+        Ok(JournalEntry {
+            token_hash: record.token_hash,
+            snapshot: record.snapshot,
+            decision,
+            limits: LimitsReport {
+                execution_time: Duration::from_nanos(record.execution_time),
+                iterations: record.iterations,
+                facts: record.facts,
+            },
+        })
+    }
+
+    pub fn to_vec(&self) -> Result<Vec<u8>, error::Format> {
+        let mut v = Vec::new();
+        self.to_proto().encode(&mut v).map_err(|e| {
+            error::Format::SerializationError(format!("serialization error: {:?}", e))
+        })?;
+        Ok(v)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, error::Format> {
+        let record = schema::DecisionRecord::decode(data).map_err(|e| {
+            error::Format::DeserializationError(format!("deserialization error: {:?}", e))
+        })?;
+        Self::from_proto(record)
+    }
+}
+
+/// destination of the entries recorded by a [`DecisionJournal`]
+///
+/// errors are left to the sink: recording a decision never changes
+/// the result of the authorization
+pub trait JournalSink: Send + Sync {
+    fn append(&self, entry: JournalEntry);
+}
+
+/// keeps the journal entries in memory
+#[derive(Clone, Debug, Default)]
+pub struct MemoryJournal(Arc<Mutex<Vec<JournalEntry>>>);
+
+impl MemoryJournal {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// returns the recorded entries
+    pub fn entries(&self) -> Vec<JournalEntry> {
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl JournalSink for MemoryJournal {
+    fn append(&self, entry: JournalEntry) {
+        self.0.lock().unwrap().push(entry);
+    }
+}
+
+/// writes the journal entries to a file or any other writer
+///
+/// each entry is serialized with [`JournalEntry::to_vec`], prefixed by its
+/// length as a 4 bytes little endian integer. The entries are read back with
+/// [`read_journal`]. Write errors are ignored
+pub struct WriterJournal<W: Write + Send>(Mutex<W>);
+
+impl<W: Write + Send> WriterJournal<W> {
+    pub fn new(writer: W) -> Self {
+        WriterJournal(Mutex::new(writer))
+    }
+
+    pub fn into_inner(self) -> W {
+        self.0.into_inner().unwrap()
+    }
+}
+
+impl<W: Write + Send> JournalSink for WriterJournal<W> {
+    fn append(&self, entry: JournalEntry) {
+        if let Ok(data) = entry.to_vec() {
+            let mut writer = self.0.lock().unwrap();
+            let _ = writer
+                .write_all(&(data.len() as u32).to_le_bytes())
+                .and_then(|()| writer.write_all(&data))
+                .and_then(|()| writer.flush());
+        }
+    }
+}
+
+/// reads the entries written by a [`WriterJournal`]
+pub fn read_journal<R: Read>(mut reader: R) -> Result<Vec<JournalEntry>, error::Format> {
+    let io_error =
+        |e: std::io::Error| error::Format::DeserializationError(format!("read error: {}", e));
+    let mut entries = Vec::new();
+
+    loop {
+        let mut len = [0u8; 4];
+        match reader.read_exact(&mut len) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(io_error(e)),
+        }
+
+        // the length is not trusted: the buffer grows with the data actually read
+        let len = u32::from_le_bytes(len) as u64;
+        let mut data = Vec::new();
+        (&mut reader)
+            .take(len)
+            .read_to_end(&mut data)
+            .map_err(io_error)?;
+        if data.len() as u64 != len {
+            return Err(error::Format::DeserializationError(
+                "truncated journal entry".to_string(),
+            ));
+        }
+        entries.push(JournalEntry::from_bytes(&data)?);
+    }
+
+    Ok(entries)
+}
+
+/// records authorization decisions, with the context needed to replay them
+///
+/// decisions are sampled: by default all of them are recorded, and
+/// [`DecisionJournal::sample_one_in`] keeps only one in `n`. Failed
+/// authorizations can be recorded regardless of the sampling with
+/// [`DecisionJournal::always_record_failures`].
+///
+/// ```rust
+/// use biscuit_auth::{Biscuit, DecisionJournal, KeyPair, MemoryJournal};
+///
+/// let root = KeyPair::new();
+/// let mut builder = Biscuit::builder();
+/// builder.add_fact(r#"user("alice")"#).unwrap();
+/// let token = builder.build(&root).unwrap();
+///
+/// let sink = MemoryJournal::new();
+/// let journal = DecisionJournal::new(sink.clone());
+///
+/// let mut authorizer = token.authorizer().unwrap();
+/// authorizer.add_code("allow if user($u)").unwrap();
+/// journal.authorize(&token, &mut authorizer).unwrap();
+///
+/// let entry = &sink.entries()[0];
+/// let mut replay = entry.authorizer().unwrap();
+/// assert_eq!(replay.authorize(), Ok(0));
+/// ```
+pub struct DecisionJournal {
+    sink: Arc<dyn JournalSink>,
+    sample_rate: u64,
+    always_record_failures: bool,
+    count: AtomicU64,
+}
+
+impl DecisionJournal {
+    pub fn new<S: JournalSink + 'static>(sink: S) -> Self {
+        DecisionJournal {
+            sink: Arc::new(sink),
+            sample_rate: 1,
+            always_record_failures: false,
+            count: AtomicU64::new(0),
+        }
+    }
+
+    /// records one decision in `n`
+    pub fn sample_one_in(mut self, n: u64) -> Self {
+        self.sample_rate = n.max(1);
+        self
+    }
+
+    /// records all failed authorizations, even when they are not sampled
+    pub fn always_record_failures(mut self, always: bool) -> Self {
+        self.always_record_failures = always;
+        self
+    }
+
+    /// runs [`Authorizer::authorize`] and records the decision
+    ///
+    /// `token` is the token loaded in the authorizer, used to compute the hash
+    /// stored in the entry
+    pub fn authorize(
+        &self,
+        token: &Biscuit,
+        authorizer: &mut Authorizer,
+    ) -> Result<usize, error::Token> {
+        let sampled = self
+            .count
+            .fetch_add(1, Ordering::Relaxed)
+            .checked_rem(self.sample_rate)
+            == Some(0);
+        if !sampled && !self.always_record_failures {
+            return authorizer.authorize();
+        }
+
+        let before = authorizer.clone();
+        let result = authorizer.authorize();
+
+        if sampled || result.is_err() {
+            if let Ok(snapshot) = before.snapshot() {
+                let token_hash = token
+                    .to_vec()
+                    .ok()
+                    .map(|data| Sha256::digest(&data).to_vec());
+
+                self.sink.append(JournalEntry {
+                    token_hash,
+                    snapshot,
+                    decision: result.clone().map_err(|e| e.to_string()),
+                    limits: LimitsReport {
+                        execution_time: authorizer.execution_time(),
+                        iterations: authorizer.iterations(),
+                        facts: authorizer.world.facts.len() as u64,
+                    },
+                });
+            }
+        }
+
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AuthorizerLimits;
+    use crate::KeyPair;
+
+    fn limits() -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn decision_journal() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.add_fact(r#"user("alice")"#).unwrap();
+        let token = builder.build(&root).unwrap();
+
+        let sink = MemoryJournal::new();
+        let journal = DecisionJournal::new(sink.clone())
+            .sample_one_in(2)
+            .always_record_failures(true);
+
+        let limits = limits();
+        let authorize = |code: &str| {
+            let mut authorizer = token.authorizer().unwrap();
+            authorizer.set_limits(limits.clone());
+            authorizer.add_code(code).unwrap();
+            journal.authorize(&token, &mut authorizer)
+        };
+
+        assert_eq!(authorize("allow if user($u)"), Ok(0));
+        // not sampled
+        assert_eq!(authorize("allow if user($u)"), Ok(0));
+        // failures are always recorded
+        assert!(authorize(r#"allow if user("bob")"#).is_err());
+
+        let entries = sink.entries();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].decision, Ok(0));
+        assert!(entries[1].decision.is_err());
+        assert_eq!(
+            entries[0].token_hash.as_deref(),
+            Some(&Sha256::digest(&token.to_vec().unwrap())[..])
+        );
+        assert_eq!(entries[0].limits.facts, 1);
+
+        let mut replay = entries[1].authorizer().unwrap();
+        assert_eq!(
+            replay.authorize().map_err(|e| e.to_string()),
+            entries[1].decision
+        );
+
+        let writer = WriterJournal::new(Vec::new());
+        for entry in &entries {
+            writer.append(entry.clone());
+        }
+        let data = writer.into_inner();
+        assert_eq!(read_journal(&data[..]).unwrap(), entries);
+
+        // the length prefix does not decide the allocation
+        let mut truncated = u32::MAX.to_le_bytes().to_vec();
+        truncated.extend_from_slice(&data[4..20]);
+        assert_eq!(
+            read_journal(&truncated[..]),
+            Err(error::Format::DeserializationError(
+                "truncated journal entry".to_string()
+            ))
+        );
+    }
+}
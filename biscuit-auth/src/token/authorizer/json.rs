@@ -0,0 +1,134 @@
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Serialize;
+
+use crate::error;
+
+/// origin of a fact, rule or check: a block index, or `None` for the authorizer
+fn origin(i: usize) -> Option<usize> {
+    if i == usize::MAX {
+        None
+    } else {
+        Some(i)
+    }
+}
+
+#[derive(Serialize)]
+struct AuthorizerJson {
+    facts: Vec<FactJson>,
+    rules: Vec<RuleJson>,
+    checks: Vec<CheckJson>,
+    policies: Vec<String>,
+}
+
+#[derive(Serialize)]
+struct FactJson {
+    origin: Vec<Option<usize>>,
+    fact: String,
+}
+
+#[derive(Serialize)]
+struct RuleJson {
+    origin: Option<usize>,
+    rule: String,
+}
+
+#[derive(Serialize)]
+struct CheckJson {
+    origin: Option<usize>,
+    check: String,
+}
+
+impl super::Authorizer {
+    /// returns the content of the authorizer as JSON
+    ///
+    /// the schema is:
+    ///
+    /// ```json
+    /// {
+    ///   "facts": [{ "origin": [0, null], "fact": "user(\"alice\")" }],
+    ///   "rules": [{ "origin": 0, "rule": "right($r) <- owner($r)" }],
+    ///   "checks": [{ "origin": null, "check": "check if user($u)" }],
+    ///   "policies": ["allow if true"]
+    /// }
+    /// ```
+    ///
+    /// origins are block indexes, where `null` stands for the authorizer. A fact
+    /// generated by rules has the origins of all the facts and rules used to produce it.
+    /// Facts, rules and checks are sorted by origin, then facts and rules are sorted
+    /// by their Datalog representation, while checks and policies keep their order.
+    ///
+    /// fails with [`error::Format::SerializationError`] if the content cannot be
+    /// converted to JSON
+    pub fn dump_json(&self) -> Result<serde_json::Value, error::Token> {
+        let mut facts: BTreeSet<(Vec<usize>, String)> = BTreeSet::new();
+        for (fact_origin, fact) in self.world.facts.iter_all() {
+            facts.insert((
+                fact_origin.inner.iter().copied().collect(),
+                self.symbols.print_fact(fact),
+            ));
+        }
+        for fact in &self.authorizer_block_builder.facts {
+            facts.insert((vec![usize::MAX], fact.to_string()));
+        }
+
+        let mut rules: BTreeSet<(usize, String)> = BTreeSet::new();
+        for ruleset in self.world.rules.inner.values() {
+            for (rule_origin, rule) in ruleset {
+                rules.insert((*rule_origin, self.symbols.print_rule(rule)));
+            }
+        }
+        for rule in &self.authorizer_block_builder.rules {
+            rules.insert((usize::MAX, rule.to_string()));
+        }
+
+        let mut checks_map: BTreeMap<usize, Vec<String>> = BTreeMap::new();
+        if let Some(blocks) = &self.blocks {
+            for (i, block) in blocks.iter().enumerate() {
+                checks_map.entry(i).or_default().extend(
+                    block
+                        .checks
+                        .iter()
+                        .map(|check| self.symbols.print_check(check)),
+                );
+            }
+        }
+        checks_map.entry(usize::MAX).or_default().extend(
+            self.authorizer_block_builder
+                .checks
+                .iter()
+                .map(|check| check.to_string()),
+        );
+
+        let json = AuthorizerJson {
+            facts: facts
+                .into_iter()
+                .map(|(ids, fact)| FactJson {
+                    origin: ids.into_iter().map(origin).collect(),
+                    fact,
+                })
+                .collect(),
+            rules: rules
+                .into_iter()
+                .map(|(i, rule)| RuleJson {
+                    origin: origin(i),
+                    rule,
+                })
+                .collect(),
+            checks: checks_map
+                .into_iter()
+                .flat_map(|(i, checks)| {
+                    checks.into_iter().map(move |check| CheckJson {
+                        origin: origin(i),
+                        check,
+                    })
+                })
+                .collect(),
+            policies: self.policies.iter().map(|p| p.to_string()).collect(),
+        };
+
+        serde_json::to_value(json).map_err(|e| {
+            error::Format::SerializationError(format!("serialization error: {:?}", e)).into()
+        })
+    }
+}
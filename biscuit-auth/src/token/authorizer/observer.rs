@@ -0,0 +1,210 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use super::{Authorizer, LimitsReport};
+use crate::error;
+use crate::time::Instant;
+
+/// receives metrics on each authorization, to export them to a monitoring system
+///
+/// the observer is called synchronously at the end of [`Authorizer::authorize`]
+/// and the other authorization methods, including when the authorization
+/// fails. It should not block, as it adds to the request latency.
+///
+/// ```rust
+/// use biscuit_auth::{AuthorizationMetrics, Authorizer, AuthorizerObserver};
+/// use std::sync::atomic::{AtomicU64, Ordering};
+/// use std::sync::Arc;
+///
+/// #[derive(Default)]
+/// struct Denied(AtomicU64);
+///
+/// impl AuthorizerObserver for Denied {
+///     fn on_authorization(&self, metrics: &AuthorizationMetrics) {
+///         if metrics.outcome() != "allow" {
+///             self.0.fetch_add(1, Ordering::Relaxed);
+///         }
+///     }
+/// }
+///
+/// let denied = Arc::new(Denied::default());
+/// let mut authorizer = Authorizer::new();
+/// authorizer.set_observer(denied.clone());
+/// authorizer.add_code("deny if true").unwrap();
+/// assert!(authorizer.authorize().is_err());
+/// assert_eq!(denied.0.load(Ordering::Relaxed), 1);
+/// ```
+pub trait AuthorizerObserver: Send + Sync {
+    fn on_authorization(&self, metrics: &AuthorizationMetrics);
+}
+
+impl<O: AuthorizerObserver + ?Sized> AuthorizerObserver for Arc<O> {
+    fn on_authorization(&self, metrics: &AuthorizationMetrics) {
+        (**self).on_authorization(metrics)
+    }
+}
+
+/// metrics of an authorization, reported to an [`AuthorizerObserver`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuthorizationMetrics<'a> {
+    /// result of the authorization
+    pub result: &'a Result<usize, error::Token>,
+    /// time, iterations and facts used by this authorization
+    pub usage: LimitsReport,
+    /// number of failed checks by block index, the authorizer's checks are
+    /// counted under `None`
+    pub failed_checks: BTreeMap<Option<u32>, u64>,
+}
+
+impl<'a> AuthorizationMetrics<'a> {
+    /// short label of the result, to use in metric names or labels
+    ///
+    /// one of `allow`, `deny` (a deny policy matched), `failed_checks` (an allow
+    /// policy matched but some checks failed), `no_matching_policy`, `run_limit`
+    /// or `error`
+    pub fn outcome(&self) -> &'static str {
+        match self.result {
+            Ok(_) => "allow",
+            Err(error::Token::FailedLogic(error::Logic::Unauthorized {
+                policy: error::MatchedPolicy::Deny(_),
+                ..
+            })) => "deny",
+            Err(error::Token::FailedLogic(error::Logic::Unauthorized { .. })) => "failed_checks",
+            Err(error::Token::FailedLogic(error::Logic::NoMatchingPolicy { .. })) => {
+                "no_matching_policy"
+            }
+            Err(error::Token::RunLimit(_)) => "run_limit",
+            Err(_) => "error",
+        }
+    }
+}
+
+impl Authorizer {
+    /// reports metrics on each authorization to `observer`, see [`AuthorizerObserver`]
+    ///
+    /// the observer is shared by the clones of this authorizer
+    pub fn set_observer<O: AuthorizerObserver + 'static>(&mut self, observer: O) {
+        self.observer = Some(Arc::new(observer));
+    }
+
+    pub(super) fn notify_observer(
+        &self,
+        start: Instant,
+        start_iterations: u64,
+        result: &Result<usize, error::Token>,
+    ) {
+        let observer = match &self.observer {
+            Some(observer) => observer,
+            None => return,
+        };
+
+        let mut failed_checks = BTreeMap::new();
+        if let Err(error::Token::FailedLogic(
+            error::Logic::Unauthorized { checks, .. } | error::Logic::NoMatchingPolicy { checks },
+        )) = result
+        {
+            for check in checks {
+                let block_id = match check {
+                    error::FailedCheck::Block(check) => Some(check.block_id),
+                    error::FailedCheck::Authorizer(_) => None,
+                };
+                *failed_checks.entry(block_id).or_insert(0) += 1;
+            }
+        }
+
+        observer.on_authorization(&AuthorizationMetrics {
+            result,
+            usage: LimitsReport {
+                execution_time: self.clock.instant() - start,
+                iterations: self.world.iterations - start_iterations,
+                facts: self.world.facts.len() as u64,
+            },
+            failed_checks,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::BlockBuilder;
+    use crate::{AuthorizerLimits, Biscuit, KeyPair};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    fn limits() -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
+    /// outcome, iterations and failed checks
+    type Record = (&'static str, u64, BTreeMap<Option<u32>, u64>);
+
+    #[derive(Default)]
+    struct Recorder(Mutex<Vec<Record>>);
+
+    impl AuthorizerObserver for Recorder {
+        fn on_authorization(&self, metrics: &AuthorizationMetrics) {
+            self.0.lock().unwrap().push((
+                metrics.outcome(),
+                metrics.usage.iterations,
+                metrics.failed_checks.clone(),
+            ));
+        }
+    }
+
+    #[test]
+    fn observer() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(r#"right("file1", "read"); check if operation("read");"#)
+            .unwrap();
+        let token = builder.build(&root).unwrap();
+        let mut builder = BlockBuilder::new();
+        builder.add_check(r#"check if resource("file2")"#).unwrap();
+        let token = token.append(builder).unwrap();
+
+        let recorder = Arc::new(Recorder::default());
+        let mut authorizer = token.authorizer().unwrap();
+        authorizer.set_limits(limits());
+        authorizer.set_observer(recorder.clone());
+        authorizer
+            .add_code(
+                r#"resource("file1"); operation("write");
+                can_read($f) <- right($f, "read");
+                check if can_read("file1");
+                check if resource("file3");
+                allow if true;"#,
+            )
+            .unwrap();
+        assert!(authorizer.authorize().is_err());
+
+        let mut authorizer = Authorizer::new();
+        authorizer.set_limits(limits());
+        authorizer.set_observer(recorder.clone());
+        authorizer.add_code("deny if true").unwrap();
+        assert!(authorizer.authorize().is_err());
+
+        let mut authorizer = Authorizer::new();
+        authorizer.set_limits(limits());
+        authorizer.set_observer(recorder.clone());
+        authorizer.add_code("allow if true").unwrap();
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        let recorded = recorder.0.lock().unwrap();
+        assert_eq!(recorded.len(), 3);
+        assert_eq!(recorded[0].0, "failed_checks");
+        assert_eq!(recorded[0].1, 1);
+        assert_eq!(
+            recorded[0].2,
+            vec![(None, 1), (Some(0), 1), (Some(1), 1)]
+                .into_iter()
+                .collect()
+        );
+        assert_eq!(recorded[1].0, "deny");
+        assert_eq!(recorded[2], ("allow", 0, BTreeMap::new()));
+    }
+}
@@ -0,0 +1,161 @@
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+
+use super::Authorizer;
+
+/// pool of authorizers sharing the same facts, rules, checks and policies
+///
+/// servers usually create a base authorizer with their policies, then clone it
+/// for each request before adding the token. The pool keeps a list of copies of
+/// the base, with the authorizer facts already loaded and the symbols converted:
+/// [`AuthorizerPool::get`] hands out one of them, and it is reset and returned to
+/// the pool when the [`PooledAuthorizer`] is dropped. A copy of the base is
+/// created when the pool is empty.
+///
+/// The base authorizer must not contain a token.
+///
+/// ```rust
+/// use biscuit_auth::{Authorizer, AuthorizerPool, KeyPair, Biscuit};
+///
+/// let mut base = Authorizer::new();
+/// base.add_code(r#"allow if user($u)"#).unwrap();
+/// let pool = AuthorizerPool::new(base, 16);
+///
+/// let root = KeyPair::new();
+/// let mut builder = Biscuit::builder();
+/// builder.add_fact(r#"user("alice")"#).unwrap();
+/// let token = builder.build(&root).unwrap();
+///
+/// let mut authorizer = pool.get();
+/// authorizer.add_token(&token).unwrap();
+/// authorizer.authorize().unwrap();
+/// ```
+pub struct AuthorizerPool {
+    base: Authorizer,
+    available: Mutex<Vec<Authorizer>>,
+    capacity: usize,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+/// usage statistics of an [`AuthorizerPool`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PoolStats {
+    /// number of authorizers taken from the pool
+    pub hits: u64,
+    /// number of authorizers created because the pool was empty
+    pub misses: u64,
+    /// number of authorizers currently in the pool
+    pub available: usize,
+}
+
+impl PoolStats {
+    /// ratio of authorizers taken from the pool, between 0 and 1
+    pub fn hit_rate(&self) -> f64 {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            0.0
+        } else {
+            self.hits as f64 / total as f64
+        }
+    }
+}
+
+impl AuthorizerPool {
+    /// creates a pool keeping up to `capacity` authorizers
+    pub fn new(mut base: Authorizer, capacity: usize) -> Self {
+        base.share_symbols();
+
+        AuthorizerPool {
+            available: Mutex::new(Vec::with_capacity(capacity)),
+            base,
+            capacity,
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// fills the pool with copies of the base authorizer
+    pub fn warm_up(&self) {
+        let mut available = self.lock();
+        while available.len() < self.capacity {
+            available.push(self.base.clone());
+        }
+    }
+
+    /// takes an authorizer from the pool, or creates one if the pool is empty
+    pub fn get(&self) -> PooledAuthorizer<'_> {
+        let authorizer = match self.lock().pop() {
+            Some(authorizer) => {
+                self.hits.fetch_add(1, Ordering::Relaxed);
+                authorizer
+            }
+            None => {
+                self.misses.fetch_add(1, Ordering::Relaxed);
+                self.base.clone()
+            }
+        };
+
+        PooledAuthorizer {
+            pool: self,
+            authorizer: Some(authorizer),
+        }
+    }
+
+    /// returns the usage statistics of the pool
+    pub fn stats(&self) -> PoolStats {
+        PoolStats {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            available: self.lock().len(),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Vec<Authorizer>> {
+        // authorizers in the pool are always reset, so they are still usable
+        // if a thread panicked while holding the lock
+        self.available
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// authorizer taken from an [`AuthorizerPool`], returned to the pool when dropped
+pub struct PooledAuthorizer<'a> {
+    pool: &'a AuthorizerPool,
+    authorizer: Option<Authorizer>,
+}
+
+impl PooledAuthorizer<'_> {
+    /// takes the authorizer out of the pool, it will not be returned
+    pub fn into_inner(mut self) -> Authorizer {
+        self.authorizer.take().unwrap()
+    }
+}
+
+impl Deref for PooledAuthorizer<'_> {
+    type Target = Authorizer;
+
+    fn deref(&self) -> &Authorizer {
+        self.authorizer.as_ref().unwrap()
+    }
+}
+
+impl DerefMut for PooledAuthorizer<'_> {
+    fn deref_mut(&mut self) -> &mut Authorizer {
+        self.authorizer.as_mut().unwrap()
+    }
+}
+
+impl Drop for PooledAuthorizer<'_> {
+    fn drop(&mut self) {
+        if let Some(mut authorizer) = self.authorizer.take() {
+            let mut available = self.pool.lock();
+            if available.len() < self.pool.capacity {
+                authorizer.clone_from(&self.pool.base);
+                available.push(authorizer);
+            }
+        }
+    }
+}
@@ -0,0 +1,127 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use crate::datalog::{DebugHooks, Fact, Origin, Rule, SymbolTable};
+use crate::time::{Clock, Instant};
+
+/// measurements of each rule and check evaluated by [`Authorizer::profile`](super::Authorizer::profile)
+///
+/// the measurements include the cost of profiling itself, so the times are
+/// only meaningful relative to each other
+#[derive(Clone, Default)]
+pub struct AuthorizerProfile {
+    /// rules, in the order of their first evaluation
+    pub rules: Vec<RuleProfile>,
+    /// authorizer and token checks, in the order of their evaluation
+    pub checks: Vec<CheckProfile>,
+    rule_ids: HashMap<usize, HashMap<Rule, usize>>,
+}
+
+/// evaluation of a rule, see [`AuthorizerProfile`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuleProfile {
+    /// index of the block that contains the rule (`usize::MAX` for the authorizer)
+    pub origin: usize,
+    pub rule: String,
+    /// number of iterations in which the rule was applied
+    pub iterations: u64,
+    /// facts produced by the rule, including the ones that were already known
+    pub facts: u64,
+    pub time: Duration,
+}
+
+/// evaluation of a check, see [`AuthorizerProfile`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CheckProfile {
+    /// index of the block that contains the check (`usize::MAX` for the authorizer)
+    pub origin: usize,
+    pub check_id: usize,
+    pub check: String,
+    pub time: Duration,
+}
+
+impl AuthorizerProfile {
+    /// rules sorted by decreasing evaluation time
+    pub fn slowest_rules(&self) -> Vec<&RuleProfile> {
+        let mut rules: Vec<&RuleProfile> = self.rules.iter().collect();
+        rules.sort_by_key(|r| std::cmp::Reverse(r.time));
+        rules
+    }
+
+    /// checks sorted by decreasing evaluation time
+    pub fn slowest_checks(&self) -> Vec<&CheckProfile> {
+        let mut checks: Vec<&CheckProfile> = self.checks.iter().collect();
+        checks.sort_by_key(|c| std::cmp::Reverse(c.time));
+        checks
+    }
+}
+
+impl fmt::Debug for AuthorizerProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuthorizerProfile")
+            .field("rules", &self.rules)
+            .field("checks", &self.checks)
+            .finish()
+    }
+}
+
+/// records the rules applied by the Datalog engine in an [`AuthorizerProfile`]
+pub(super) struct ProfileHooks<'a> {
+    profile: &'a mut AuthorizerProfile,
+    symbols: &'a SymbolTable,
+    clock: &'a dyn Clock,
+    current: Option<(usize, Instant)>,
+}
+
+impl<'a> ProfileHooks<'a> {
+    pub(super) fn new(
+        profile: &'a mut AuthorizerProfile,
+        symbols: &'a SymbolTable,
+        clock: &'a dyn Clock,
+    ) -> Self {
+        ProfileHooks {
+            profile,
+            symbols,
+            clock,
+            current: None,
+        }
+    }
+}
+
+impl DebugHooks for ProfileHooks<'_> {
+    fn on_rule_start(&mut self, origin: usize, rule: &Rule) {
+        let profile = &mut *self.profile;
+        let ids = profile.rule_ids.entry(origin).or_default();
+        let id = match ids.get(rule) {
+            Some(id) => *id,
+            None => {
+                let id = profile.rules.len();
+                profile.rules.push(RuleProfile {
+                    origin,
+                    rule: self.symbols.print_rule(rule),
+                    iterations: 0,
+                    facts: 0,
+                    time: Duration::default(),
+                });
+                ids.insert(rule.clone(), id);
+                id
+            }
+        };
+
+        profile.rules[id].iterations += 1;
+        self.current = Some((id, self.clock.instant()));
+    }
+
+    fn on_fact_derived(&mut self, _origin: &Origin, _fact: &Fact) {
+        if let Some((id, _)) = self.current {
+            self.profile.rules[id].facts += 1;
+        }
+    }
+
+    fn on_rule_end(&mut self, _origin: usize, _rule: &Rule) {
+        if let Some((id, start)) = self.current.take() {
+            self.profile.rules[id].time += self.clock.instant() - start;
+        }
+    }
+}
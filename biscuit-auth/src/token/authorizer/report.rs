@@ -0,0 +1,263 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use super::Authorizer;
+use crate::builder::{Check, Convert, Policy, PolicyKind};
+use crate::error::{self, FailedCheck, MatchedPolicy};
+
+/// result of a check in an [`AuthorizationReport`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CheckStatus {
+    Success,
+    Failure,
+    /// the authorization stopped before the checks were evaluated,
+    /// because of a run limit or a revoked token
+    NotEvaluated,
+}
+
+/// status of a check after authorization
+#[derive(Clone, Debug, PartialEq)]
+pub struct CheckReport {
+    /// block containing the check, `None` for checks of the authorizer
+    pub block_id: Option<usize>,
+    /// index of the check in its block or in the authorizer
+    pub check_id: usize,
+    pub check: Check,
+    pub status: CheckStatus,
+}
+
+/// policy that matched during authorization
+#[derive(Clone, Debug, PartialEq)]
+pub struct PolicyReport {
+    /// index of the policy in the authorizer
+    pub policy_id: usize,
+    pub kind: PolicyKind,
+    pub policy: Policy,
+}
+
+/// structured result of [`Authorizer::authorize_report`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct AuthorizationReport {
+    /// authorizer checks, then the checks of each block in order
+    pub checks: Vec<CheckReport>,
+    /// matched policy, `None` if no policy matched or the policies were not evaluated
+    pub policy: Option<PolicyReport>,
+    /// total execution time of the authorizer
+    pub execution_time: Duration,
+    /// number of fact generation iterations
+    pub iterations: u64,
+    /// result returned by [`Authorizer::authorize`]
+    pub result: Result<usize, error::Token>,
+}
+
+impl AuthorizationReport {
+    pub fn is_authorized(&self) -> bool {
+        self.result.is_ok()
+    }
+
+    /// checks that did not succeed
+    pub fn failed_checks(&self) -> impl Iterator<Item = &CheckReport> {
+        self.checks
+            .iter()
+            .filter(|c| c.status == CheckStatus::Failure)
+    }
+}
+
+impl Authorizer {
+    /// verifies the checks and policies like [`Authorizer::authorize`], and
+    /// returns the status of every check and the matched policy
+    ///
+    /// the result of the authorization is in [`AuthorizationReport::result`].
+    /// This returns an error if a token check cannot be converted back from
+    /// the symbol table, instead of leaving it out of the report
+    ///
+    /// ```rust
+    /// use biscuit_auth::{Biscuit, CheckStatus, KeyPair};
+    ///
+    /// let root = KeyPair::new();
+    /// let mut builder = Biscuit::builder();
+    /// builder.add_check(r#"check if operation("read")"#).unwrap();
+    /// let token = builder.build(&root).unwrap();
+    ///
+    /// let mut authorizer = token.authorizer().unwrap();
+    /// authorizer.add_code(r#"operation("write"); allow if true;"#).unwrap();
+    ///
+    /// let report = authorizer.authorize_report().unwrap();
+    /// assert!(!report.is_authorized());
+    /// assert_eq!(report.checks[0].block_id, Some(0));
+    /// assert_eq!(report.checks[0].status, CheckStatus::Failure);
+    /// assert_eq!(report.policy.unwrap().policy_id, 0);
+    /// ```
+    pub fn authorize_report(&mut self) -> Result<AuthorizationReport, error::Token> {
+        let result = self.authorize();
+
+        let (evaluated, failed, matched) = match &result {
+            Ok(i) => (true, &[][..], Some(*i)),
+            Err(error::Token::FailedLogic(error::Logic::Unauthorized { policy, checks })) => {
+                let i = match policy {
+                    MatchedPolicy::Allow(i) | MatchedPolicy::Deny(i) => *i,
+                };
+                (true, &checks[..], Some(i))
+            }
+            Err(error::Token::FailedLogic(error::Logic::NoMatchingPolicy { checks })) => {
+                (true, &checks[..], None)
+            }
+            Err(_) => (false, &[][..], None),
+        };
+
+        let failed: HashSet<(Option<usize>, usize)> = failed
+            .iter()
+            .map(|check| match check {
+                FailedCheck::Block(c) => (Some(c.block_id as usize), c.check_id as usize),
+                FailedCheck::Authorizer(c) => (None, c.check_id as usize),
+            })
+            .collect();
+        let status = |block_id, check_id| {
+            if !evaluated {
+                CheckStatus::NotEvaluated
+            } else if failed.contains(&(block_id, check_id)) {
+                CheckStatus::Failure
+            } else {
+                CheckStatus::Success
+            }
+        };
+
+        let mut checks = Vec::new();
+        for (check_id, check) in self.authorizer_block_builder.checks.iter().enumerate() {
+            checks.push(CheckReport {
+                block_id: None,
+                check_id,
+                check: check.clone(),
+                status: status(None, check_id),
+            });
+        }
+        for (block_id, block) in self.blocks.iter().flatten().enumerate() {
+            for (check_id, check) in block.checks.iter().enumerate() {
+                checks.push(CheckReport {
+                    block_id: Some(block_id),
+                    check_id,
+                    check: Check::convert_from(check, &self.symbols)?,
+                    status: status(Some(block_id), check_id),
+                });
+            }
+        }
+
+        let policy = matched.and_then(|policy_id| {
+            self.policies.get(policy_id).map(|policy| PolicyReport {
+                policy_id,
+                kind: policy.kind.clone(),
+                policy: policy.clone(),
+            })
+        });
+
+        Ok(AuthorizationReport {
+            checks,
+            policy,
+            execution_time: self.execution_time,
+            iterations: self.world.iterations,
+            result,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AuthorizerLimits, Biscuit, KeyPair};
+
+    fn limits() -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn authorize_report() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(r#"right("file1", "read"); check if resource("file1");"#)
+            .unwrap();
+        let token = builder
+            .build(&root)
+            .unwrap()
+            .append({
+                let mut block = crate::builder::BlockBuilder::new();
+                block.add_check(r#"check if operation("read")"#).unwrap();
+                block
+            })
+            .unwrap();
+
+        let mut authorizer = token.authorizer().unwrap();
+        authorizer.set_limits(limits());
+        authorizer
+            .add_code(
+                r#"resource("file1"); operation("write");
+                check if resource($r), right($r, "read");
+                deny if operation("delete");
+                allow if true;"#,
+            )
+            .unwrap();
+
+        let report = authorizer.authorize_report().unwrap();
+        assert!(!report.is_authorized());
+        assert_eq!(
+            report
+                .checks
+                .iter()
+                .map(|c| (c.block_id, c.check_id, c.check.to_string(), c.status))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    None,
+                    0,
+                    "check if resource($r), right($r, \"read\")".to_string(),
+                    CheckStatus::Success
+                ),
+                (
+                    Some(0),
+                    0,
+                    "check if resource(\"file1\")".to_string(),
+                    CheckStatus::Success
+                ),
+                (
+                    Some(1),
+                    0,
+                    "check if operation(\"read\")".to_string(),
+                    CheckStatus::Failure
+                ),
+            ]
+        );
+        assert_eq!(report.failed_checks().count(), 1);
+        let policy = report.policy.unwrap();
+        assert_eq!(policy.policy_id, 1);
+        assert_eq!(policy.kind, PolicyKind::Allow);
+        assert_eq!(report.iterations, authorizer.iterations());
+
+        let mut authorizer = token.authorizer().unwrap();
+        authorizer.set_limits(AuthorizerLimits {
+            max_time: Duration::from_secs(0),
+            ..Default::default()
+        });
+        let report = authorizer.authorize_report().unwrap();
+        assert_eq!(
+            report.result,
+            Err(error::Token::RunLimit(error::RunLimit::Timeout))
+        );
+        assert!(report
+            .checks
+            .iter()
+            .all(|c| c.status == CheckStatus::NotEvaluated));
+        assert_eq!(report.policy, None);
+
+        // a check that cannot be printed is an error, not a missing report
+        if let Some(blocks) = authorizer.blocks.as_mut() {
+            blocks[1].checks[0].queries[0].head.name = u64::MAX;
+        }
+        assert!(matches!(
+            authorizer.authorize_report(),
+            Err(error::Token::Format(_))
+        ));
+    }
+}
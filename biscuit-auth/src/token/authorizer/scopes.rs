@@ -0,0 +1,183 @@
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use super::Authorizer;
+use crate::builder::{PolicyKind, Rule, Scope};
+
+/// allow policy accepting facts derived from attenuation or third party blocks
+///
+/// returned by [`Authorizer::lint_scopes`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ScopeWarning {
+    /// index of the policy in the authorizer
+    pub policy: usize,
+    /// authorizer rule reading facts from other blocks than the authority
+    pub rule: Rule,
+    /// scopes of the rule trusting `previous` or a public key
+    pub scopes: Vec<Scope>,
+}
+
+impl fmt::Display for ScopeWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let scopes: Vec<_> = self.scopes.iter().map(|s| s.to_string()).collect();
+        write!(
+            f,
+            "policy {} uses facts generated by `{}`, trusting {}, without restricting their origin",
+            self.policy,
+            self.rule,
+            scopes.join(", ")
+        )
+    }
+}
+
+impl Authorizer {
+    /// looks for confused deputy patterns in the authorizer
+    ///
+    /// An authorizer rule trusting `previous` or a third party public key
+    /// generates facts from the content of attenuation blocks. If an allow
+    /// policy uses those facts and trusts the same blocks, any holder of
+    /// the token can append a block that makes the policy match.
+    ///
+    /// A warning is returned for each allow policy using, directly or through
+    /// other rules, the facts of such a rule, unless the policy does not trust
+    /// `previous` and explicitly trusts the public keys trusted by the rule.
+    ///
+    /// ```rust
+    /// use biscuit_auth::Authorizer;
+    ///
+    /// let mut authorizer = Authorizer::new();
+    /// authorizer.add_code(r#"
+    ///   admin($u) <- role($u, "admin") trusting previous;
+    ///   allow if admin($u) trusting previous;
+    /// "#).unwrap();
+    ///
+    /// let warnings = authorizer.lint_scopes();
+    /// assert_eq!(warnings.len(), 1);
+    /// assert_eq!(warnings[0].policy, 0);
+    /// ```
+    pub fn lint_scopes(&self) -> Vec<ScopeWarning> {
+        let block_scopes = &self.authorizer_block_builder.scopes;
+        let wide_scopes = |scopes: &[Scope]| -> Vec<Scope> {
+            let scopes = if scopes.is_empty() {
+                block_scopes
+            } else {
+                scopes
+            };
+            scopes
+                .iter()
+                .filter(|s| matches!(s, Scope::Previous | Scope::PublicKey(_)))
+                .cloned()
+                .collect()
+        };
+
+        let rules = &self.authorizer_block_builder.rules;
+        // predicate name -> indexes of the rules trusting other blocks it depends on
+        let mut sources: HashMap<&str, BTreeSet<usize>> = HashMap::new();
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for (index, rule) in rules.iter().enumerate() {
+                let mut rule_sources = BTreeSet::new();
+                if !wide_scopes(&rule.scopes).is_empty() {
+                    rule_sources.insert(index);
+                }
+                for predicate in &rule.body {
+                    if let Some(s) = sources.get(predicate.name.as_str()) {
+                        rule_sources.extend(s.iter().cloned());
+                    }
+                }
+
+                let head = sources.entry(rule.head.name.as_str()).or_default();
+                let len = head.len();
+                head.extend(rule_sources);
+                changed |= head.len() != len;
+            }
+        }
+
+        let mut warnings = Vec::new();
+        for (policy_index, policy) in self.policies.iter().enumerate() {
+            if policy.kind != PolicyKind::Allow {
+                continue;
+            }
+
+            let mut flagged = BTreeSet::new();
+            for query in &policy.queries {
+                let policy_scopes = wide_scopes(&query.scopes);
+                if policy_scopes.is_empty() {
+                    continue;
+                }
+
+                for predicate in &query.body {
+                    for index in sources.get(predicate.name.as_str()).into_iter().flatten() {
+                        let rule_scopes = wide_scopes(&rules[*index].scopes);
+                        let restricted = !policy_scopes.contains(&Scope::Previous)
+                            && rule_scopes.iter().all(|s| policy_scopes.contains(s));
+                        if !restricted && flagged.insert(*index) {
+                            warnings.push(ScopeWarning {
+                                policy: policy_index,
+                                rule: rules[*index].clone(),
+                                scopes: rule_scopes,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+
+        warnings
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::KeyPair;
+
+    #[test]
+    fn lint_scopes() {
+        let external = KeyPair::new().public();
+
+        let mut authorizer = Authorizer::new();
+        authorizer
+            .add_code(format!(
+                r#"
+                admin($u) <- role($u, "admin") trusting previous;
+                can_delete($u) <- admin($u);
+                partner($u) <- partner_of($u) trusting ed25519/{key};
+                user($u) <- account($u);
+
+                allow if can_delete($u) trusting previous;
+                allow if partner($u) trusting ed25519/{key};
+                allow if admin($u);
+                allow if user($u) trusting previous;
+                deny if can_delete($u) trusting previous;
+                "#,
+                key = hex::encode(external.to_bytes())
+            ))
+            .unwrap();
+
+        let warnings = authorizer.lint_scopes();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].policy, 0);
+        assert_eq!(
+            warnings[0].rule.to_string(),
+            "admin($u) <- role($u, \"admin\") trusting previous"
+        );
+        assert_eq!(warnings[0].scopes, vec![Scope::Previous]);
+
+        let mut authorizer = Authorizer::new();
+        authorizer
+            .add_code(format!(
+                r#"
+                partner($u) <- partner_of($u) trusting ed25519/{key};
+                allow if partner($u) trusting ed25519/{other};
+                "#,
+                key = hex::encode(external.to_bytes()),
+                other = hex::encode(KeyPair::new().public().to_bytes())
+            ))
+            .unwrap();
+        let warnings = authorizer.lint_scopes();
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].scopes, vec![Scope::PublicKey(external)]);
+    }
+}
@@ -1,10 +1,13 @@
 use prost::Message;
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Duration,
+};
 
 use crate::{
-    builder::{BlockBuilder, Convert, Policy},
+    builder::{BlockBuilder, Convert, Fact, Policy},
     datalog::{Origin, RunLimits, TrustedOrigins},
-    error,
+    error::{self, FailedCheck},
     format::{
         convert::{
             proto_snapshot_block_to_token_block, token_block_to_proto_snapshot_block,
@@ -15,7 +18,7 @@ use crate::{
         },
         schema::{self, GeneratedFacts},
     },
-    token::{default_symbol_table, MAX_SCHEMA_VERSION, MIN_SCHEMA_VERSION},
+    token::{check_schema_version, default_symbol_table, MAX_SCHEMA_VERSION},
     PublicKey,
 };
 
@@ -31,19 +34,15 @@ impl super::Authorizer {
             max_facts: limits.max_facts,
             max_iterations: limits.max_iterations,
             max_time: Duration::from_nanos(limits.max_time),
+            max_facts_per_block: limits.max_facts_per_block,
+            max_iterations_per_block: limits.max_iterations_per_block,
+            max_results: limits.max_results,
         };
 
         let execution_time = Duration::from_nanos(execution_time);
 
         let version = world.version.unwrap_or(0);
-        if !(MIN_SCHEMA_VERSION..=MAX_SCHEMA_VERSION).contains(&version) {
-            return Err(error::Format::Version {
-                minimum: crate::token::MIN_SCHEMA_VERSION,
-                maximum: crate::token::MAX_SCHEMA_VERSION,
-                actual: version,
-            }
-            .into());
-        }
+        check_schema_version(version)?;
 
         let mut symbols = default_symbol_table();
         for symbol in world.symbols {
@@ -105,6 +104,9 @@ impl super::Authorizer {
                 &authorizer.public_key_to_block_id,
             );
             authorizer.blocks = Some(blocks);
+            // the authorizer facts are stored in its block, the world only
+            // holds the `root_key` fact and the generated facts
+            authorizer.token_free_facts = Some(Default::default());
         }
 
         for GeneratedFacts { origins, facts } in world.generated_facts {
@@ -113,7 +115,7 @@ impl super::Authorizer {
             for fact in &facts {
                 let fact = proto_fact_to_token_fact(fact)?;
                 //let fact = Fact::convert_from(&fact, &symbols)?.convert(&mut authorizer.symbols);
-                authorizer.world.facts.insert(&origin, fact);
+                authorizer.world.add_fact(&origin, fact);
             }
         }
 
@@ -143,7 +145,9 @@ impl super::Authorizer {
             .map(|policy| policy_to_proto_policy(policy, &mut symbols))
             .collect();
 
-        let authorizer_block = self.authorizer_block_builder.clone().build(symbols.clone());
+        let mut authorizer_block_builder = self.authorizer_block_builder.clone();
+        authorizer_block_builder.facts = self.authorizer_facts()?;
+        let authorizer_block = authorizer_block_builder.build(symbols.clone());
         symbols.extend(&authorizer_block.symbols)?;
         symbols.public_keys.extend(&authorizer_block.public_keys)?;
 
@@ -205,6 +209,9 @@ impl super::Authorizer {
                 max_facts: self.limits.max_facts,
                 max_iterations: self.limits.max_iterations,
                 max_time: self.limits.max_time.as_nanos() as u64,
+                max_facts_per_block: self.limits.max_facts_per_block,
+                max_iterations_per_block: self.limits.max_iterations_per_block,
+                max_results: self.limits.max_results,
             },
         })
     }
@@ -224,6 +231,144 @@ impl super::Authorizer {
     }
 }
 
+/// differences between two authorizer snapshots, returned by
+/// [`AuthorizerSnapshot::diff`](schema::AuthorizerSnapshot::diff)
+#[derive(Clone, Debug, PartialEq)]
+pub struct SnapshotDiff {
+    /// facts present in the new snapshot but not in the old one
+    pub added_facts: Vec<Fact>,
+    /// facts present in the old snapshot but not in the new one
+    pub removed_facts: Vec<Fact>,
+    /// checks failing with the new snapshot, that did not fail with the old one
+    pub newly_failing_checks: Vec<FailedCheck>,
+    /// checks failing with the old snapshot, that do not fail with the new one
+    pub newly_passing_checks: Vec<FailedCheck>,
+    /// authorization result of the old snapshot
+    pub before: Result<usize, error::Token>,
+    /// authorization result of the new snapshot
+    pub after: Result<usize, error::Token>,
+}
+
+impl SnapshotDiff {
+    /// returns true if both snapshots have the same facts, checks and result
+    pub fn is_empty(&self) -> bool {
+        self.added_facts.is_empty()
+            && self.removed_facts.is_empty()
+            && self.newly_failing_checks.is_empty()
+            && self.newly_passing_checks.is_empty()
+            && self.before == self.after
+    }
+}
+
+/// facts and failed checks of a snapshot, after running the authorizer
+struct Evaluation {
+    facts: BTreeMap<String, Fact>,
+    // failed checks indexed by block and source, since check ids
+    // change when checks are added or removed
+    failed_checks: BTreeMap<(Option<u32>, String), FailedCheck>,
+    result: Result<usize, error::Token>,
+}
+
+impl Evaluation {
+    fn new(snapshot: &schema::AuthorizerSnapshot) -> Result<Self, error::Token> {
+        let mut authorizer = super::Authorizer::from_snapshot(snapshot.clone())?;
+        let limits = authorizer.limits.clone();
+        let result = authorizer.authorize_with_limits(limits);
+
+        let failed_checks = match &result {
+            Err(error::Token::FailedLogic(error::Logic::Unauthorized { checks, .. }))
+            | Err(error::Token::FailedLogic(error::Logic::NoMatchingPolicy { checks })) => checks
+                .iter()
+                .map(|check| {
+                    let key = match check {
+                        FailedCheck::Block(c) => (Some(c.block_id), c.rule.clone()),
+                        FailedCheck::Authorizer(c) => (None, c.rule.clone()),
+                    };
+                    (key, check.clone())
+                })
+                .collect(),
+            _ => BTreeMap::new(),
+        };
+
+        let facts = authorizer
+            .dump()
+            .0
+            .into_iter()
+            .map(|fact| (fact.to_string(), fact))
+            .collect();
+
+        Ok(Evaluation {
+            facts,
+            failed_checks,
+            result,
+        })
+    }
+}
+
+impl schema::AuthorizerSnapshot {
+    /// compares the facts, failed checks and result of two snapshots
+    ///
+    /// both snapshots are restored and authorized with their own limits,
+    /// then the generated facts and failed checks are compared. Checks are
+    /// matched by block and source, so this can be used to see the effect
+    /// of a new version of the authorizer policies on the same token.
+    ///
+    /// ```rust
+    /// use biscuit_auth::{Authorizer, AuthorizerLimits};
+    /// use std::time::Duration;
+    ///
+    /// let limits = AuthorizerLimits {
+    ///     max_time: Duration::from_secs(10),
+    ///     ..Default::default()
+    /// };
+    ///
+    /// let mut v1 = Authorizer::new();
+    /// v1.set_limits(limits.clone());
+    /// v1.add_code(r#"user("alice"); allow if user($u);"#).unwrap();
+    ///
+    /// let mut v2 = Authorizer::new();
+    /// v2.set_limits(limits);
+    /// v2.add_code(r#"user("alice"); check if admin($u); allow if user($u);"#).unwrap();
+    ///
+    /// let diff = v1.snapshot().unwrap().diff(&v2.snapshot().unwrap()).unwrap();
+    /// assert_eq!(diff.newly_failing_checks.len(), 1);
+    /// assert!(diff.after.is_err());
+    /// ```
+    pub fn diff(&self, other: &schema::AuthorizerSnapshot) -> Result<SnapshotDiff, error::Token> {
+        let before = Evaluation::new(self)?;
+        let after = Evaluation::new(other)?;
+
+        Ok(SnapshotDiff {
+            added_facts: after
+                .facts
+                .iter()
+                .filter(|(key, _)| !before.facts.contains_key(*key))
+                .map(|(_, fact)| fact.clone())
+                .collect(),
+            removed_facts: before
+                .facts
+                .iter()
+                .filter(|(key, _)| !after.facts.contains_key(*key))
+                .map(|(_, fact)| fact.clone())
+                .collect(),
+            newly_failing_checks: after
+                .failed_checks
+                .iter()
+                .filter(|(key, _)| !before.failed_checks.contains_key(*key))
+                .map(|(_, check)| check.clone())
+                .collect(),
+            newly_passing_checks: before
+                .failed_checks
+                .iter()
+                .filter(|(key, _)| !after.failed_checks.contains_key(*key))
+                .map(|(_, check)| check.clone())
+                .collect(),
+            before: before.result,
+            after: after.result,
+        })
+    }
+}
+
 fn authorizer_origin_to_proto_origin(origin: &Origin) -> Vec<schema::Origin> {
     origin
         .inner
@@ -261,3 +406,71 @@ fn proto_origin_to_authorizer_origin(origins: &[schema::Origin]) -> Result<Origi
 
     Ok(new_origin)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AuthorizerLimits;
+    use crate::{Biscuit, KeyPair};
+
+    fn limits() -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn snapshot_diff() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(r#"user("alice"); check if operation("read");"#)
+            .unwrap();
+        let token = builder.build(&root).unwrap();
+
+        let limits = limits();
+        let snapshot = |code: &str| {
+            let mut authorizer = token.authorizer().unwrap();
+            authorizer.set_limits(limits.clone());
+            authorizer.add_code(code).unwrap();
+            authorizer.snapshot().unwrap()
+        };
+
+        let v1 = snapshot(r#"operation("write"); allow if user($u);"#);
+        let v2 = snapshot(r#"operation("read"); right($u) <- user($u); allow if right($u);"#);
+
+        assert!(v1.diff(&v1).unwrap().is_empty());
+
+        let diff = v1.diff(&v2).unwrap();
+        assert_eq!(
+            diff.added_facts
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>(),
+            vec![r#"operation("read")"#, r#"right("alice")"#]
+        );
+        assert_eq!(
+            diff.removed_facts
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>(),
+            vec![r#"operation("write")"#]
+        );
+        assert!(diff.newly_failing_checks.is_empty());
+        assert_eq!(diff.newly_passing_checks.len(), 1);
+        assert!(diff.before.is_err());
+        assert_eq!(diff.after, Ok(0));
+
+        let diff = v2.diff(&v1).unwrap();
+        assert_eq!(
+            diff.newly_failing_checks,
+            vec![FailedCheck::Block(error::FailedBlockCheck {
+                block_id: 0,
+                check_id: 0,
+                rule: String::from("check if operation(\"read\")"),
+                message: None,
+            })]
+        );
+    }
+}
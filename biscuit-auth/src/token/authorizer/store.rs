@@ -0,0 +1,249 @@
+use std::convert::TryFrom;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, RwLock};
+
+use super::{Authorizer, AuthorizerPolicies};
+use crate::error;
+
+/// policies shared by a long running service, that can be replaced without
+/// restarting it
+///
+/// the store holds a base authorizer, with its facts already loaded and its
+/// symbols converted like in an [`AuthorizerPool`](super::AuthorizerPool).
+/// [`PolicyStore::swap`] builds the new base before taking the lock, then
+/// replaces the current version at once: requests that called
+/// [`PolicyStore::load`] before the swap keep the version they loaded until
+/// they drop it, and the next requests get the new one.
+///
+/// A base authorizer that already contains a token is rejected with
+/// [`error::Logic::AuthorizerNotEmpty`].
+///
+/// ```rust
+/// use biscuit_auth::{Authorizer, Biscuit, KeyPair, PolicyStore};
+///
+/// let mut base = Authorizer::new();
+/// base.add_code(r#"allow if user("alice")"#).unwrap();
+/// let store = PolicyStore::new(base).unwrap();
+/// let updates = store.subscribe();
+///
+/// let root = KeyPair::new();
+/// let mut builder = Biscuit::builder();
+/// builder.add_fact(r#"user("bob")"#).unwrap();
+/// let token = builder.build(&root).unwrap();
+///
+/// // a request in flight during the reload
+/// let in_flight = store.load();
+///
+/// let mut base = Authorizer::new();
+/// base.add_code(r#"allow if user("bob")"#).unwrap();
+/// store.swap(base).unwrap();
+/// assert_eq!(updates.try_recv().unwrap().version(), 1);
+///
+/// let mut authorizer = in_flight.authorizer();
+/// authorizer.add_token(&token).unwrap();
+/// assert!(authorizer.authorize().is_err());
+///
+/// let mut authorizer = store.authorizer();
+/// authorizer.add_token(&token).unwrap();
+/// assert!(authorizer.authorize().is_ok());
+/// ```
+pub struct PolicyStore {
+    current: RwLock<Arc<PolicyVersion>>,
+    next_version: AtomicU64,
+    subscribers: Mutex<Vec<Sender<Arc<PolicyVersion>>>>,
+}
+
+/// version of the policies of a [`PolicyStore`]
+pub struct PolicyVersion {
+    version: u64,
+    base: Authorizer,
+}
+
+impl PolicyVersion {
+    /// starts at 0 and is incremented by each swap
+    pub fn version(&self) -> u64 {
+        self.version
+    }
+
+    /// returns a copy of the base authorizer, to add a token
+    pub fn authorizer(&self) -> Authorizer {
+        self.base.clone()
+    }
+
+    /// the base authorizer of this version
+    pub fn base(&self) -> &Authorizer {
+        &self.base
+    }
+}
+
+impl PolicyStore {
+    /// creates a store from a base authorizer, which must not contain a token
+    pub fn new(base: Authorizer) -> Result<Self, error::Token> {
+        Ok(PolicyStore {
+            current: RwLock::new(Arc::new(PolicyVersion::new(0, base)?)),
+            next_version: AtomicU64::new(1),
+            subscribers: Mutex::new(Vec::new()),
+        })
+    }
+
+    /// creates a store from serialized policies
+    pub fn from_policies(policies: AuthorizerPolicies) -> Result<Self, error::Token> {
+        Self::new(Authorizer::try_from(policies)?)
+    }
+
+    /// returns the current version of the policies
+    ///
+    /// the version stays usable after a swap, for requests that must be
+    /// authorized with the same policies from start to end
+    pub fn load(&self) -> Arc<PolicyVersion> {
+        self.current
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// returns a copy of the current base authorizer
+    pub fn authorizer(&self) -> Authorizer {
+        self.load().authorizer()
+    }
+
+    /// current version number
+    pub fn version(&self) -> u64 {
+        self.load().version
+    }
+
+    /// replaces the policies with a new base authorizer, and returns the
+    /// version in use after the swap
+    ///
+    /// the subscribers are notified with the new version, in increasing
+    /// version order. If another swap started later finished first, its
+    /// version is kept and returned, and the subscribers are not notified.
+    /// The base authorizer must not contain a token
+    pub fn swap(&self, base: Authorizer) -> Result<u64, error::Token> {
+        // the base is prepared before taking the lock, so that requests
+        // are not blocked while the policies are loaded
+        let mut new = PolicyVersion::new(0, base)?;
+        new.version = self.next_version.fetch_add(1, Ordering::Relaxed);
+        let new = Arc::new(new);
+
+        let mut current = self
+            .current
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        // concurrent swaps can finish out of order, the latest
+        // version is kept
+        if current.version > new.version {
+            return Ok(current.version);
+        }
+        *current = new.clone();
+
+        // subscribers are notified while the lock is held, so that a
+        // concurrent swap cannot send its version before this one
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .retain(|subscriber| subscriber.send(new.clone()).is_ok());
+
+        Ok(new.version)
+    }
+
+    /// replaces the policies with serialized policies, see [`PolicyStore::swap`]
+    ///
+    /// the current version is kept if the policies cannot be loaded
+    pub fn swap_policies(&self, policies: AuthorizerPolicies) -> Result<u64, error::Token> {
+        self.swap(Authorizer::try_from(policies)?)
+    }
+
+    /// returns a channel receiving each new version after a swap
+    ///
+    /// the channel is removed from the store when the receiver is dropped
+    pub fn subscribe(&self) -> Receiver<Arc<PolicyVersion>> {
+        let (sender, receiver) = mpsc::channel();
+        self.subscribers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .push(sender);
+        receiver
+    }
+}
+
+impl PolicyVersion {
+    fn new(version: u64, mut base: Authorizer) -> Result<Self, error::Token> {
+        if base.blocks.is_some() {
+            return Err(error::Logic::AuthorizerNotEmpty.into());
+        }
+
+        base.prepare_authorizer_block();
+        Ok(PolicyVersion { version, base })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{AuthorizerLimits, Biscuit, KeyPair};
+    use std::time::Duration;
+
+    fn limits() -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
+    fn base(code: &str) -> Authorizer {
+        let mut authorizer = Authorizer::new();
+        authorizer.add_code(code).unwrap();
+        authorizer.set_limits(limits());
+        authorizer
+    }
+
+    #[test]
+    fn policy_store() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.add_fact(r#"user("alice")"#).unwrap();
+        let token = builder.build(&root).unwrap();
+
+        let store = Arc::new(PolicyStore::new(base(r#"allow if user("bob")"#)).unwrap());
+        let updates = store.subscribe();
+        let dropped = store.subscribe();
+        drop(dropped);
+
+        let old = store.load();
+        assert_eq!(old.version(), 0);
+
+        let handle = {
+            let store = store.clone();
+            std::thread::spawn(move || {
+                let policies = base(r#"allow if user("alice")"#).save().unwrap();
+                store.swap_policies(policies).unwrap()
+            })
+        };
+        assert_eq!(handle.join().unwrap(), 1);
+        assert_eq!(store.version(), 1);
+        assert_eq!(updates.try_recv().unwrap().version(), 1);
+        assert_eq!(store.subscribers.lock().unwrap().len(), 1);
+
+        let mut authorizer = old.authorizer();
+        authorizer.add_token(&token).unwrap();
+        assert!(authorizer.authorize().is_err());
+
+        let mut authorizer = store.authorizer();
+        authorizer.add_token(&token).unwrap();
+        authorizer.set_limits(limits());
+        assert_eq!(authorizer.authorize(), Ok(0));
+        assert!(updates.try_recv().is_err());
+
+        let mut with_token = base(r#"allow if user("alice")"#);
+        with_token.add_token(&token).unwrap();
+        assert_eq!(
+            store.swap(with_token.clone()).unwrap_err(),
+            error::Token::FailedLogic(error::Logic::AuthorizerNotEmpty)
+        );
+        assert_eq!(store.version(), 1);
+        assert!(updates.try_recv().is_err());
+        assert!(PolicyStore::new(with_token).is_err());
+    }
+}
@@ -0,0 +1,759 @@
+use std::collections::{HashMap, HashSet};
+use std::convert::TryInto;
+use std::marker::PhantomData;
+
+use super::{authorizer_origin, Authorizer, AuthorizerLimits, AuthorizerObserver};
+use crate::builder::{Check, Fact, Policy, Rule, Scope, Term};
+use crate::parser::{lint_source, Lint};
+use crate::time::SystemTime;
+use crate::{error, Biscuit, Clock, FakeClock, PublicKey, RevocationStore};
+
+/// state of an [`AuthorizerBuilder`] that has not received a token yet
+#[derive(Clone, Debug)]
+pub struct Unauthenticated;
+
+/// state of an [`AuthorizerBuilder`] that received a token
+#[derive(Clone, Debug)]
+pub struct WithToken;
+
+/// consuming builder for an [`Authorizer`], tracking the token in its type
+///
+/// The token can only be added once, and [`AuthorizerBuilder::build`] is only
+/// available once a token was added. Authorizers without a token are created
+/// explicitly with [`AuthorizerBuilder::build_unauthenticated`].
+///
+/// ```rust
+/// use biscuit_auth::{AuthorizerBuilder, Biscuit, KeyPair};
+///
+/// let root = KeyPair::new();
+/// let mut builder = Biscuit::builder();
+/// builder.add_fact(r#"user("alice")"#).unwrap();
+/// let token = builder.build(&root).unwrap();
+///
+/// let mut authorizer = AuthorizerBuilder::new()
+///     .code(r#"operation("read"); allow if user($u);"#)
+///     .unwrap()
+///     .token(&token)
+///     .unwrap()
+///     .build();
+/// authorizer.authorize().unwrap();
+/// ```
+///
+/// Forgetting the token does not compile:
+///
+/// ```compile_fail
+/// use biscuit_auth::AuthorizerBuilder;
+///
+/// let authorizer = AuthorizerBuilder::new().code("allow if true").unwrap().build();
+/// ```
+///
+/// Neither does adding a second token:
+///
+/// ```compile_fail
+/// use biscuit_auth::{AuthorizerBuilder, Biscuit, KeyPair};
+///
+/// let token = Biscuit::builder().build(&KeyPair::new()).unwrap();
+/// let builder = AuthorizerBuilder::new().token(&token).unwrap().token(&token);
+/// ```
+#[derive(Clone)]
+pub struct AuthorizerBuilder<State = Unauthenticated> {
+    authorizer: Authorizer,
+    namespaces: HashSet<String>,
+    state: PhantomData<State>,
+}
+
+impl AuthorizerBuilder<Unauthenticated> {
+    pub fn new() -> Self {
+        AuthorizerBuilder {
+            authorizer: Authorizer::new(),
+            namespaces: HashSet::new(),
+            state: PhantomData,
+        }
+    }
+
+    /// adds the token to authorize
+    pub fn token(mut self, token: &Biscuit) -> Result<AuthorizerBuilder<WithToken>, error::Token> {
+        self.authorizer.add_token(token)?;
+        self.check_token()?;
+        Ok(AuthorizerBuilder {
+            authorizer: self.authorizer,
+            namespaces: self.namespaces,
+            state: PhantomData,
+        })
+    }
+
+    /// creates an authorizer without a token, to query or authorize the
+    /// authorizer's own facts
+    pub fn build_unauthenticated(self) -> Authorizer {
+        self.authorizer
+    }
+}
+
+impl Default for AuthorizerBuilder<Unauthenticated> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AuthorizerBuilder<WithToken> {
+    pub fn build(self) -> Authorizer {
+        self.authorizer
+    }
+}
+
+impl<State> AuthorizerBuilder<State> {
+    pub fn fact<F: TryInto<Fact>>(self, fact: F) -> Result<Self, error::Token>
+    where
+        error::Token: From<<F as TryInto<Fact>>::Error>,
+    {
+        self.add_checked(|authorizer| authorizer.add_fact(fact))
+    }
+
+    /// adds facts under a namespace
+    ///
+    /// the predicates are prefixed with `namespace::`: `user("alice")` added to
+    /// the `db` namespace becomes `db::user("alice")`, and can be queried with
+    /// [`Authorizer::query_namespaced`].
+    ///
+    /// Once a namespace is declared, only this method can add facts to it: facts
+    /// and rules from the builder or from the token that generate a predicate
+    /// in the namespace are rejected with [`error::Logic::NamespaceConflict`].
+    ///
+    /// ```rust
+    /// use biscuit_auth::AuthorizerBuilder;
+    ///
+    /// let mut authorizer = AuthorizerBuilder::new()
+    ///     .facts_namespaced("db", vec![r#"user("alice")"#])
+    ///     .unwrap()
+    ///     .facts_namespaced("request", vec![r#"user("bob")"#])
+    ///     .unwrap()
+    ///     .code(r#"allow if db::user($u), request::user($u)"#)
+    ///     .unwrap()
+    ///     .build_unauthenticated();
+    /// assert!(authorizer.authorize().is_err());
+    ///
+    /// let users: Vec<(String,)> = authorizer
+    ///     .query_namespaced("db", "data($u) <- user($u)")
+    ///     .unwrap();
+    /// assert_eq!(users, vec![("alice".to_string(),)]);
+    ///
+    /// // facts outside of `facts_namespaced` cannot use the namespace
+    /// assert!(AuthorizerBuilder::new()
+    ///     .facts_namespaced("db", vec![r#"user("alice")"#])
+    ///     .unwrap()
+    ///     .fact(r#"db::user("mallory")"#)
+    ///     .is_err());
+    /// ```
+    pub fn facts_namespaced<I, F>(mut self, namespace: &str, facts: I) -> Result<Self, error::Token>
+    where
+        I: IntoIterator<Item = F>,
+        F: TryInto<Fact>,
+        error::Token: From<<F as TryInto<Fact>>::Error>,
+    {
+        // same characters as Datalog names
+        let valid = namespace.starts_with(|c: char| c.is_ascii_alphabetic())
+            && namespace
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if !valid {
+            return Err(error::Logic::InvalidNamespace(namespace.to_string()).into());
+        }
+
+        if !self.namespaces.contains(namespace) {
+            // the namespace is new, content already added must not use it
+            let b = &self.authorizer.authorizer_block_builder;
+            let conflict = b
+                .facts
+                .iter()
+                .map(|fact| &fact.predicate.name)
+                .chain(b.rules.iter().map(|rule| &rule.head.name))
+                .find(|name| in_namespace(namespace, name));
+            if let Some(predicate) = conflict {
+                return Err(error::Logic::NamespaceConflict {
+                    namespace: namespace.to_string(),
+                    predicate: predicate.clone(),
+                }
+                .into());
+            }
+
+            self.namespaces.insert(namespace.to_string());
+            self.check_token()?;
+        }
+
+        for fact in facts {
+            let mut fact: Fact = fact.try_into()?;
+            fact.predicate.name = format!("{}::{}", namespace, fact.predicate.name);
+            self.authorizer.add_fact::<Fact>(fact)?;
+        }
+        Ok(self)
+    }
+
+    /// adds facts from an iterator, loading them as they are read, see
+    /// [`Authorizer::add_facts_from_iter`]
+    ///
+    /// ```rust
+    /// use biscuit_auth::{builder::fact, builder::string, AuthorizerBuilder};
+    ///
+    /// // rows read from a database cursor
+    /// let rows = (0..1_000).map(|i| format!("user{}", i));
+    /// let mut authorizer = AuthorizerBuilder::new()
+    ///     .facts_from_iter(rows.map(|name| fact("user", &[string(&name)])))
+    ///     .unwrap()
+    ///     .code(r#"allow if user("user42")"#)
+    ///     .unwrap()
+    ///     .build_unauthenticated();
+    /// assert_eq!(authorizer.authorize(), Ok(0));
+    /// ```
+    pub fn facts_from_iter<I, F>(mut self, facts: I) -> Result<Self, error::Token>
+    where
+        I: IntoIterator<Item = F>,
+        F: TryInto<Fact>,
+        error::Token: From<<F as TryInto<Fact>>::Error>,
+    {
+        let origin = authorizer_origin();
+        for fact in facts {
+            let fact: Fact = fact.try_into()?;
+            self.check_predicate(&fact.predicate.name)?;
+            self.authorizer.load_fact(&origin, fact)?;
+        }
+        Ok(self)
+    }
+
+    /// adds facts from a stream, loading them as they are received
+    ///
+    /// this is the async version of [`AuthorizerBuilder::facts_from_iter`],
+    /// for facts read from an async database client. Only one fact is held
+    /// at a time: the memory used does not depend on the length of the
+    /// stream, only on the distinct facts loaded, in their compact form.
+    #[cfg(feature = "async")]
+    #[cfg_attr(feature = "docsrs", doc(cfg(feature = "async")))]
+    pub async fn facts_from_stream<S, F>(mut self, facts: S) -> Result<Self, error::Token>
+    where
+        S: futures_core::Stream<Item = F>,
+        F: TryInto<Fact>,
+        error::Token: From<<F as TryInto<Fact>>::Error>,
+    {
+        let origin = authorizer_origin();
+        let mut facts = Box::pin(facts);
+        while let Some(fact) = crate::future::next(facts.as_mut()).await {
+            let fact: Fact = fact.try_into()?;
+            self.check_predicate(&fact.predicate.name)?;
+            self.authorizer.load_fact(&origin, fact)?;
+        }
+        Ok(self)
+    }
+
+    pub fn rule<Ru: TryInto<Rule>>(self, rule: Ru) -> Result<Self, error::Token>
+    where
+        error::Token: From<<Ru as TryInto<Rule>>::Error>,
+    {
+        self.add_checked(|authorizer| authorizer.add_rule(rule))
+    }
+
+    pub fn check<C: TryInto<Check>>(mut self, check: C) -> Result<Self, error::Token>
+    where
+        error::Token: From<<C as TryInto<Check>>::Error>,
+    {
+        self.authorizer.add_check(check)?;
+        Ok(self)
+    }
+
+    pub fn policy<P: TryInto<Policy>>(mut self, policy: P) -> Result<Self, error::Token>
+    where
+        error::Token: From<<P as TryInto<Policy>>::Error>,
+    {
+        self.authorizer.add_policy(policy)?;
+        Ok(self)
+    }
+
+    /// adds some datalog code, see [`Authorizer::add_code`]
+    pub fn code<T: AsRef<str>>(self, source: T) -> Result<Self, error::Token> {
+        self.add_checked(|authorizer| authorizer.add_code(source))
+    }
+
+    pub fn code_with_params<T: AsRef<str>>(
+        self,
+        source: T,
+        params: HashMap<String, Term>,
+        scope_params: HashMap<String, PublicKey>,
+    ) -> Result<Self, error::Token> {
+        self.add_checked(|authorizer| authorizer.add_code_with_params(source, params, scope_params))
+    }
+
+    pub fn scope(mut self, scope: Scope) -> Self {
+        self.authorizer.add_scope(scope);
+        self
+    }
+
+    /// only accepts sealed tokens, see [`Authorizer::require_sealed`]
+    pub fn require_sealed(mut self) -> Self {
+        self.authorizer.require_sealed();
+        self
+    }
+
+    pub fn limits(mut self, limits: AuthorizerLimits) -> Self {
+        self.authorizer.set_limits(limits);
+        self
+    }
+
+    /// adds the current time, see [`Authorizer::set_time`]
+    pub fn time(mut self) -> Self {
+        self.authorizer.set_time();
+        self
+    }
+
+    pub fn clock<C: Clock + 'static>(mut self, clock: C) -> Self {
+        self.authorizer.set_clock(clock);
+        self
+    }
+
+    /// rejects tokens with a revoked identifier, see [`Authorizer::revocation_check`]
+    ///
+    /// ```rust
+    /// use biscuit_auth::{error, AuthorizerBuilder, Biscuit, KeyPair, MemoryRevocationStore};
+    ///
+    /// let root = KeyPair::new();
+    /// let token = Biscuit::builder().build(&root).unwrap();
+    ///
+    /// let store = MemoryRevocationStore::new();
+    /// store.revoke(&token.revocation_identifiers()[0]);
+    ///
+    /// let mut authorizer = AuthorizerBuilder::new()
+    ///     .code("allow if true")
+    ///     .unwrap()
+    ///     .revocation_check(store)
+    ///     .token(&token)
+    ///     .unwrap()
+    ///     .build();
+    /// assert_eq!(
+    ///     authorizer.authorize(),
+    ///     Err(error::Token::Revoked { block_ids: vec![0] })
+    /// );
+    /// ```
+    pub fn revocation_check<S: RevocationStore + 'static>(mut self, store: S) -> Self {
+        self.authorizer.revocation_check(store);
+        self
+    }
+
+    /// reports metrics on each authorization, see [`Authorizer::set_observer`]
+    pub fn observer<O: AuthorizerObserver + 'static>(mut self, observer: O) -> Self {
+        self.authorizer.set_observer(observer);
+        self
+    }
+
+    /// adds a `time` fact with a fixed date, and freezes the clock used for
+    /// the execution time limits
+    ///
+    /// this makes expiration checks deterministic in tests. To test the time
+    /// limits, use [`AuthorizerBuilder::clock`] with a [`FakeClock`] instead,
+    /// and advance it
+    pub fn frozen_time(mut self, time: SystemTime) -> Self {
+        self.authorizer.set_clock(FakeClock::new(time));
+        self.authorizer.set_time();
+        self
+    }
+
+    /// reports dead code in the authorizer: unused facts and predicates, rules
+    /// that can never fire, redundant checks and shadowed policies
+    ///
+    /// before [`AuthorizerBuilder::token`], the facts and checks of the token
+    /// are unknown, so the predicates they provide or use can be reported
+    ///
+    /// ```rust
+    /// use biscuit_auth::{parser::Lint, AuthorizerBuilder};
+    ///
+    /// let lints = AuthorizerBuilder::new()
+    ///     .code(r#"allow if true; deny if user("mallory");"#)
+    ///     .unwrap()
+    ///     .lint()
+    ///     .unwrap();
+    /// assert_eq!(
+    ///     lints,
+    ///     vec![Lint::ShadowedPolicy {
+    ///         policy: r#"deny if user("mallory")"#.to_string(),
+    ///         by: "allow if true".to_string(),
+    ///     }]
+    /// );
+    /// ```
+    pub fn lint(&self) -> Result<Vec<Lint>, error::Token> {
+        Ok(lint_source(&self.authorizer.dump_code())?)
+    }
+
+    /// adds facts or rules, then verifies that they do not use a declared namespace
+    fn add_checked<A>(mut self, add: A) -> Result<Self, error::Token>
+    where
+        A: FnOnce(&mut Authorizer) -> Result<(), error::Token>,
+    {
+        let facts = self.authorizer.authorizer_block_builder.facts.len();
+        let rules = self.authorizer.authorizer_block_builder.rules.len();
+        add(&mut self.authorizer)?;
+
+        let b = &self.authorizer.authorizer_block_builder;
+        for fact in &b.facts[facts..] {
+            self.check_predicate(&fact.predicate.name)?;
+        }
+        for rule in &b.rules[rules..] {
+            self.check_predicate(&rule.head.name)?;
+        }
+        Ok(self)
+    }
+
+    /// the token's facts and rules are already loaded in the Datalog world
+    fn check_token(&self) -> Result<(), error::Token> {
+        if self.namespaces.is_empty() {
+            return Ok(());
+        }
+
+        let symbols = &self.authorizer.symbols;
+        for (_, fact) in self.authorizer.world.facts.iter_all() {
+            self.check_predicate(&symbols.print_symbol_default(fact.predicate.name))?;
+        }
+        for (_, rule) in self.authorizer.world.rules.iter_all() {
+            self.check_predicate(&symbols.print_symbol_default(rule.head.name))?;
+        }
+        Ok(())
+    }
+
+    fn check_predicate(&self, name: &str) -> Result<(), error::Token> {
+        for namespace in &self.namespaces {
+            if in_namespace(namespace, name) {
+                return Err(error::Logic::NamespaceConflict {
+                    namespace: namespace.clone(),
+                    predicate: name.to_string(),
+                }
+                .into());
+            }
+        }
+        Ok(())
+    }
+}
+
+fn in_namespace(namespace: &str, predicate: &str) -> bool {
+    predicate.starts_with(namespace) && predicate[namespace.len()..].starts_with("::")
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::KeyPair;
+
+    fn limits() -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn authorizer_builder() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(r#"right("file1", "read"); check if operation("read");"#)
+            .unwrap();
+        let token = builder.build(&root).unwrap();
+
+        let limits = limits();
+
+        let mut authorizer = AuthorizerBuilder::new()
+            .fact(r#"resource("file1")"#)
+            .unwrap()
+            .limits(limits.clone())
+            .token(&token)
+            .unwrap()
+            .fact(r#"operation("read")"#)
+            .unwrap()
+            .policy("allow if resource($r), operation($o), right($r, $o)")
+            .unwrap()
+            .build();
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        let mut authorizer = AuthorizerBuilder::new()
+            .code(r#"operation("write"); allow if true;"#)
+            .unwrap()
+            .limits(limits.clone())
+            .token(&token)
+            .unwrap()
+            .build();
+        assert!(authorizer.authorize().is_err());
+
+        let mut authorizer = AuthorizerBuilder::new()
+            .code(r#"admin(true); allow if admin(true);"#)
+            .unwrap()
+            .limits(limits)
+            .build_unauthenticated();
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        assert!(AuthorizerBuilder::new().code("allow if").is_err());
+    }
+
+    #[test]
+    fn facts_namespaced() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.add_fact(r#"user("alice")"#).unwrap();
+        let token = builder.build(&root).unwrap();
+
+        let limits = limits();
+
+        let mut authorizer = AuthorizerBuilder::new()
+            .facts_namespaced("db", vec![r#"user("alice")"#, r#"admin("alice")"#])
+            .unwrap()
+            .limits(limits.clone())
+            .token(&token)
+            .unwrap()
+            .policy("allow if user($u), db::admin($u)")
+            .unwrap()
+            .build();
+        assert_eq!(authorizer.authorize(), Ok(0));
+        let admins: Vec<(String,)> = authorizer
+            .query_namespaced("db", "data($u) <- user($u), admin($u)")
+            .unwrap();
+        assert_eq!(admins, vec![("alice".to_string(),)]);
+        let admins: Vec<(String,)> = authorizer
+            .query_all_namespaced("db", "data($u) <- admin($u)")
+            .unwrap();
+        assert_eq!(admins, vec![("alice".to_string(),)]);
+
+        let conflict = Err(error::Token::FailedLogic(error::Logic::NamespaceConflict {
+            namespace: "db".to_string(),
+            predicate: "db::admin".to_string(),
+        }));
+        let namespaced = AuthorizerBuilder::new()
+            .facts_namespaced("db", vec![r#"user("alice")"#])
+            .unwrap();
+        assert_eq!(
+            namespaced.clone().fact(r#"db::admin("bob")"#).map(|_| ()),
+            conflict
+        );
+        assert_eq!(
+            namespaced
+                .clone()
+                .code(r#"db::admin($u) <- user($u)"#)
+                .map(|_| ()),
+            conflict
+        );
+        assert!(namespaced.clone().fact(r#"dbx::admin("bob")"#).is_ok());
+
+        // content added before the namespace is declared
+        assert_eq!(
+            AuthorizerBuilder::new()
+                .fact(r#"db::admin("bob")"#)
+                .unwrap()
+                .facts_namespaced("db", Vec::<Fact>::new())
+                .map(|_| ()),
+            conflict
+        );
+
+        // the token cannot forge namespaced facts
+        let mut builder = Biscuit::builder();
+        builder.add_fact(r#"db::admin("mallory")"#).unwrap();
+        let token = builder.build(&root).unwrap();
+        assert_eq!(namespaced.token(&token).map(|_| ()), conflict);
+
+        assert_eq!(
+            AuthorizerBuilder::new()
+                .facts_namespaced("db::x", vec![r#"user("alice")"#])
+                .map(|_| ()),
+            Err(error::Token::FailedLogic(error::Logic::InvalidNamespace(
+                "db::x".to_string()
+            )))
+        );
+        assert_eq!(
+            AuthorizerBuilder::new()
+                .facts_namespaced("données", vec![r#"user("alice")"#])
+                .map(|_| ()),
+            Err(error::Token::FailedLogic(error::Logic::InvalidNamespace(
+                "données".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn frozen_time() {
+        use crate::builder_ext::BuilderExt;
+        use crate::time::UNIX_EPOCH;
+
+        let expiration = UNIX_EPOCH + Duration::from_secs(1_000);
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.check_expiration_date(expiration);
+        let token = builder.build(&root).unwrap();
+
+        // the execution time does not move with a frozen clock
+        let limits = AuthorizerLimits {
+            max_time: Duration::from_nanos(1),
+            ..Default::default()
+        };
+
+        let mut authorizer = AuthorizerBuilder::new()
+            .token(&token)
+            .unwrap()
+            .limits(limits.clone())
+            .frozen_time(expiration)
+            .policy("allow if true")
+            .unwrap()
+            .build();
+        assert_eq!(authorizer.authorize(), Ok(0));
+        assert_eq!(authorizer.execution_time(), Duration::from_secs(0));
+
+        let clock = FakeClock::new(expiration);
+        clock.advance(Duration::from_secs(1));
+        let mut authorizer = AuthorizerBuilder::new()
+            .token(&token)
+            .unwrap()
+            .limits(limits)
+            .clock(clock.clone())
+            .time()
+            .policy("allow if true")
+            .unwrap()
+            .build();
+        assert!(authorizer.authorize().is_err());
+        assert_eq!(clock.now(), expiration + Duration::from_secs(1));
+    }
+
+    #[test]
+    fn lint() {
+        let builder = AuthorizerBuilder::new()
+            .code(
+                r#"resource("file1"); operation("read");
+                is_admin($u) <- admin($u);
+                allow if user($u), resource("file1");"#,
+            )
+            .unwrap();
+        assert_eq!(
+            builder.lint().unwrap(),
+            vec![
+                Lint::UnusedFact {
+                    fact: r#"operation("read")"#.to_string()
+                },
+                Lint::UnusedPredicate {
+                    name: "is_admin".to_string(),
+                    arity: 1
+                },
+                Lint::UnreachableRule {
+                    rule: "is_admin($u) <- admin($u)".to_string(),
+                    predicate: "admin".to_string()
+                },
+            ]
+        );
+
+        // the token provides facts and checks
+        let root = KeyPair::new();
+        let mut token = Biscuit::builder();
+        token
+            .add_code(
+                r#"user("alice"); admin("alice");
+                check if is_admin($u);
+                check if operation("read");"#,
+            )
+            .unwrap();
+        let token = token.build(&root).unwrap();
+        assert_eq!(builder.token(&token).unwrap().lint().unwrap(), vec![]);
+    }
+
+    #[test]
+    fn facts_from_iter() {
+        use crate::builder::{fact, string};
+
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.add_fact(r#"right("user7", "read")"#).unwrap();
+        let token = builder.build(&root).unwrap();
+
+        let users = (0..100).chain(0..100).map(|i| format!("user{}", i));
+        let mut authorizer = AuthorizerBuilder::new()
+            .facts_from_iter(users.map(|u| fact("user", &[string(&u)])))
+            .unwrap()
+            .token(&token)
+            .unwrap()
+            .code(r#"allow if user($u), right($u, "read")"#)
+            .unwrap()
+            .limits(limits())
+            .build();
+        // duplicates are stored once, along with the token's fact
+        assert_eq!(authorizer.world.facts.len(), 101);
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        // invalid facts and namespace conflicts are rejected
+        assert!(AuthorizerBuilder::new()
+            .facts_from_iter(vec!["user({name})"])
+            .is_err());
+        assert!(AuthorizerBuilder::new()
+            .facts_namespaced("db", vec![r#"user("alice")"#])
+            .unwrap()
+            .facts_from_iter(vec![r#"db::user("mallory")"#])
+            .is_err());
+        assert!(AuthorizerBuilder::new()
+            .facts_from_iter(vec![r#"db::user("mallory")"#])
+            .unwrap()
+            .facts_namespaced("db", vec![r#"user("alice")"#])
+            .is_err());
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn facts_from_stream() {
+        use std::future::Future;
+        use std::pin::Pin;
+        use std::sync::Arc;
+        use std::task::{Context, Poll, Wake};
+
+        struct NoopWaker;
+        impl Wake for NoopWaker {
+            fn wake(self: Arc<Self>) {}
+        }
+
+        // a stream that is pending before each item
+        struct Rows {
+            next: usize,
+            ready: bool,
+        }
+        impl futures_core::Stream for Rows {
+            type Item = Fact;
+
+            fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Fact>> {
+                if !self.ready {
+                    self.ready = true;
+                    cx.waker().wake_by_ref();
+                    return Poll::Pending;
+                }
+                self.ready = false;
+                self.next += 1;
+                if self.next > 50 {
+                    Poll::Ready(None)
+                } else {
+                    Poll::Ready(Some(crate::builder::fact(
+                        "user",
+                        &[crate::builder::int(self.next as i64)],
+                    )))
+                }
+            }
+        }
+
+        let waker = Arc::new(NoopWaker).into();
+        let mut cx = Context::from_waker(&waker);
+        let mut fut = Box::pin(AuthorizerBuilder::new().facts_from_stream(Rows {
+            next: 0,
+            ready: false,
+        }));
+        let mut pending = 0;
+        let builder = loop {
+            match fut.as_mut().poll(&mut cx) {
+                Poll::Ready(res) => break res.unwrap(),
+                Poll::Pending => pending += 1,
+            }
+        };
+
+        assert_eq!(pending, 51);
+        let mut authorizer = builder
+            .code("allow if user(50)")
+            .unwrap()
+            .limits(limits())
+            .build_unauthenticated();
+        assert_eq!(authorizer.world.facts.len(), 50);
+        assert_eq!(authorizer.authorize(), Ok(0));
+    }
+}
@@ -29,6 +29,9 @@ pub struct Block {
     pub public_keys: PublicKeys,
     /// list of scopes defining which blocks are trusted by this block
     pub scopes: Vec<Scope>,
+    /// identifier of the [`ApplicationSymbols`](crate::datalog::ApplicationSymbols)
+    /// used by the token, only set in the authority block
+    pub application_symbols: Option<u32>,
 }
 
 impl Block {
@@ -53,7 +56,20 @@ impl Block {
             .map(|check| symbols.print_check(check))
             .collect();
 
-        let mut res = facts.join(";\n");
+        let mut res = String::new();
+        if let Some(context) = &self.context {
+            for (i, line) in context.lines().enumerate() {
+                if i == 0 {
+                    res.push_str("// context: ");
+                } else {
+                    res.push_str("// ");
+                }
+                res.push_str(line);
+                res.push('\n');
+            }
+        }
+
+        res.push_str(&facts.join(";\n"));
         if !facts.is_empty() {
             res.push_str(";\n");
         }
@@ -108,6 +124,7 @@ impl Block {
                     builder::Scope::convert_from(s, from_symbols).map(|s| s.convert(to_symbols))
                 })
                 .collect::<Result<Vec<Scope>, error::Format>>()?,
+            application_symbols: self.application_symbols,
         })
     }
 }
@@ -1,7 +1,8 @@
 //! helper functions and structure to create tokens and blocks
-use super::{default_symbol_table, Biscuit, Block};
-use crate::crypto::{KeyPair, PublicKey};
-use crate::datalog::{self, get_schema_version, SymbolTable};
+use super::{default_symbol_table, Biscuit, Block, EXTENSION_SCHEMA_VERSION_2};
+pub use crate::crypto::Algorithm;
+use crate::crypto::{PublicKey, Signer};
+use crate::datalog::{self, get_schema_version, ApplicationSymbols, SymbolTable};
 use crate::error;
 use crate::token::builder_ext::BuilderExt;
 use biscuit_parser::parser::parse_block_source;
@@ -9,14 +10,17 @@ use nom::Finish;
 use rand_core::{CryptoRng, RngCore};
 use std::str::FromStr;
 use std::{
-    collections::{BTreeSet, HashMap},
+    collections::{BTreeSet, HashMap, HashSet},
     convert::{TryFrom, TryInto},
     fmt::{self, Write},
+    hash::Hash,
     time::{Duration, SystemTime, UNIX_EPOCH},
 };
 
 // reexport those because the builder uses the same definitions
-pub use crate::datalog::{Binary, Expression as DatalogExpression, Op as DatalogOp, Unary};
+pub use crate::datalog::{
+    Aggregate, Binary, Expression as DatalogExpression, Op as DatalogOp, Unary,
+};
 
 /// creates a Block content to append to an existing token
 #[derive(Clone, Debug, Default)]
@@ -89,8 +93,9 @@ impl BlockBuilder {
         let input = source.as_ref();
 
         let source_result = parse_block_source(input).map_err(|e| {
-            let e2: biscuit_parser::error::LanguageError = e.into();
-            e2
+            biscuit_parser::error::LanguageError::ParseError(
+                biscuit_parser::error::ParseErrors::from_source(input, e),
+            )
         })?;
 
         for (_, fact) in source_result.facts.into_iter() {
@@ -112,7 +117,7 @@ impl BlockBuilder {
         }
 
         for (_, rule) in source_result.rules.into_iter() {
-            let mut rule: Rule = rule.into();
+            let mut rule: Rule = rule.try_into()?;
             for (name, value) in &params {
                 let res = match rule.set(name, value) {
                     Ok(_) => Ok(()),
@@ -142,7 +147,7 @@ impl BlockBuilder {
         }
 
         for (_, check) in source_result.checks.into_iter() {
-            let mut check: Check = check.into();
+            let mut check: Check = check.try_into()?;
             for (name, value) in &params {
                 let res = match check.set(name, value) {
                     Ok(_) => Ok(()),
@@ -174,6 +179,41 @@ impl BlockBuilder {
         Ok(())
     }
 
+    /// binds the block to a challenge, like a nonce sent by a server for a session
+    ///
+    /// this adds the check `check if challenge(<challenge>), time($time), $time <= <expiration>`:
+    /// the token is only valid until `expiration`, for authorizers providing the same
+    /// challenge with [`Authorizer::add_challenge`](crate::Authorizer::add_challenge)
+    pub fn check_challenge(
+        &mut self,
+        challenge: &[u8],
+        expiration: SystemTime,
+    ) -> Result<(), error::Token> {
+        let empty: Vec<Term> = Vec::new();
+        let check = constrained_rule(
+            "query",
+            &empty,
+            &[
+                pred("challenge", &[bytes(challenge)]),
+                pred("time", &[var("time")]),
+            ],
+            &[Expression {
+                ops: vec![
+                    Op::Value(var("time")),
+                    Op::Value(checked_date(&expiration)?),
+                    Op::Binary(Binary::LessOrEqual),
+                ],
+            }],
+        );
+
+        self.checks.push(Check {
+            queries: vec![check],
+            kind: CheckKind::One,
+            message: None,
+        });
+        Ok(())
+    }
+
     pub fn add_scope(&mut self, scope: Scope) {
         self.scopes.push(scope);
     }
@@ -182,6 +222,15 @@ impl BlockBuilder {
         self.context = Some(context);
     }
 
+    /// sets the context of the block, a free form text field in which
+    /// application specific data, like the provenance of the block, can be stored
+    ///
+    /// the context can be read with [`Biscuit::block_context`](crate::Biscuit::block_context)
+    pub fn context(mut self, context: String) -> Self {
+        self.set_context(context);
+        self
+    }
+
     pub(crate) fn build(self, mut symbols: SymbolTable) -> Block {
         let symbols_start = symbols.current_offset();
         let public_keys_start = symbols.public_keys.current_offset();
@@ -220,6 +269,7 @@ impl BlockBuilder {
             external_key: None,
             public_keys,
             scopes,
+            application_symbols: None,
         }
     }
 
@@ -292,6 +342,7 @@ impl fmt::Display for BlockBuilder {
 pub struct BiscuitBuilder {
     inner: BlockBuilder,
     root_key_id: Option<u32>,
+    application_symbols: Option<ApplicationSymbols>,
 }
 
 impl BiscuitBuilder {
@@ -299,6 +350,7 @@ impl BiscuitBuilder {
         BiscuitBuilder {
             inner: BlockBuilder::new(),
             root_key_id: None,
+            application_symbols: None,
         }
     }
 
@@ -359,6 +411,25 @@ impl BiscuitBuilder {
         self.root_key_id = Some(root_key_id);
     }
 
+    /// builds the token with application symbols, that are not serialized in
+    /// the token, see [`ApplicationSymbols`]
+    ///
+    /// the token can only be deserialized by verifiers knowing the symbols,
+    /// with [`Biscuit::from_with_application_symbols`], and that support
+    /// the schema version [`EXTENSION_SCHEMA_VERSION_2`](crate::token::EXTENSION_SCHEMA_VERSION_2)
+    pub fn set_application_symbols(&mut self, symbols: ApplicationSymbols) {
+        self.application_symbols = Some(symbols);
+    }
+
+    /// restricts the token to an audience, by adding an `audience` fact to the authority block
+    ///
+    /// this can be called multiple times to create a token valid for multiple
+    /// audiences. Authorizers declare the audiences they accept with
+    /// [`Authorizer::expect_audience`](crate::Authorizer::expect_audience)
+    pub fn audience(&mut self, audience: &str) {
+        self.inner.facts.push(fact("audience", &[string(audience)]));
+    }
+
     /// returns all of the datalog loaded in the biscuit builder
     pub fn dump(&self) -> (Vec<Fact>, Vec<Rule>, Vec<Check>) {
         (
@@ -383,25 +454,52 @@ impl BiscuitBuilder {
         f
     }
 
-    pub fn build(self, root_key: &KeyPair) -> Result<Biscuit, error::Token> {
+    /// builds the token, signing it with the root key
+    ///
+    /// the root key can be a [`KeyPair`](crate::KeyPair), or any other [`Signer`] implementation
+    pub fn build<S: Signer + ?Sized>(self, root_key: &S) -> Result<Biscuit, error::Token> {
         self.build_with_symbols(root_key, default_symbol_table())
     }
 
-    pub fn build_with_symbols(
+    /// builds the token, then seals it so that it cannot be attenuated
+    ///
+    /// ```rust
+    /// use biscuit_auth::{macros::biscuit, KeyPair};
+    ///
+    /// let root = KeyPair::new();
+    /// let token = biscuit!(r#"user("alice")"#).build_sealed(&root).unwrap();
+    /// assert!(token.is_sealed());
+    /// assert!(token.append(biscuit_auth::builder::BlockBuilder::new()).is_err());
+    /// ```
+    pub fn build_sealed<S: Signer + ?Sized>(self, root_key: &S) -> Result<Biscuit, error::Token> {
+        self.build(root_key)?.seal()
+    }
+
+    pub fn build_with_symbols<S: Signer + ?Sized>(
         self,
-        root_key: &KeyPair,
+        root_key: &S,
         symbols: SymbolTable,
     ) -> Result<Biscuit, error::Token> {
         self.build_with_rng(root_key, symbols, &mut rand::rngs::OsRng)
     }
 
-    pub fn build_with_rng<R: RngCore + CryptoRng>(
+    pub fn build_with_rng<R: RngCore + CryptoRng, S: Signer + ?Sized>(
         self,
-        root: &KeyPair,
+        root: &S,
         symbols: SymbolTable,
         rng: &mut R,
     ) -> Result<Biscuit, error::Token> {
-        let authority_block = self.inner.build(symbols.clone());
+        let mut symbols = symbols;
+        if let Some(application_symbols) = &self.application_symbols {
+            symbols.extend(application_symbols.symbol_table())?;
+        }
+
+        let mut authority_block = self.inner.build(symbols.clone());
+        if let Some(application_symbols) = &self.application_symbols {
+            authority_block.application_symbols = Some(application_symbols.id());
+            // older verifiers would ignore the id and misread the symbols
+            authority_block.version = authority_block.version.max(EXTENSION_SCHEMA_VERSION_2);
+        }
         Biscuit::new_with_rng(rng, self.root_key_id, root, symbols, authority_block)
     }
 }
@@ -429,6 +527,10 @@ pub enum Term {
     Bool(bool),
     Set(BTreeSet<Term>),
     Parameter(String),
+    /// fixed point decimal number, as a mantissa and a number of fractional digits
+    Decimal(i128, u8),
+    /// UUID, as its 16 bytes in big endian order
+    Uuid([u8; 16]),
 }
 
 impl Convert<datalog::Term> for Term {
@@ -441,6 +543,11 @@ impl Convert<datalog::Term> for Term {
             Term::Bytes(s) => datalog::Term::Bytes(s.clone()),
             Term::Bool(b) => datalog::Term::Bool(*b),
             Term::Set(s) => datalog::Term::Set(s.iter().map(|i| i.convert(symbols)).collect()),
+            Term::Decimal(m, s) => {
+                let (m, s) = datalog::decimal::normalize(*m, *s);
+                datalog::Term::Decimal(m, s)
+            }
+            Term::Uuid(u) => datalog::Term::Uuid(*u),
             // The error is caught in the `add_xxx` functions, so this should
             // not happen™
             Term::Parameter(s) => panic!("Remaining parameter {}", &s),
@@ -460,6 +567,8 @@ impl Convert<datalog::Term> for Term {
                     .map(|i| Term::convert_from(i, symbols))
                     .collect::<Result<BTreeSet<_>, error::Format>>()?,
             ),
+            datalog::Term::Decimal(m, s) => Term::Decimal(*m, *s),
+            datalog::Term::Uuid(u) => Term::Uuid(*u),
         })
     }
 }
@@ -475,6 +584,8 @@ impl From<&Term> for Term {
             Term::Bool(b) => Term::Bool(*b),
             Term::Set(ref s) => Term::Set(s.clone()),
             Term::Parameter(ref p) => Term::Parameter(p.clone()),
+            Term::Decimal(m, s) => Term::Decimal(*m, *s),
+            Term::Uuid(u) => Term::Uuid(*u),
         }
     }
 }
@@ -492,6 +603,8 @@ impl From<biscuit_parser::builder::Term> for Term {
                 Term::Set(s.into_iter().map(|t| t.into()).collect())
             }
             biscuit_parser::builder::Term::Parameter(ref p) => Term::Parameter(p.clone()),
+            biscuit_parser::builder::Term::Decimal(m, s) => Term::Decimal(m, s),
+            biscuit_parser::builder::Term::Uuid(u) => Term::Uuid(u),
         }
     }
 }
@@ -508,17 +621,7 @@ impl fmt::Display for Term {
             Term::Variable(i) => write!(f, "${}", i),
             Term::Integer(i) => write!(f, "{}", i),
             Term::Str(s) => write!(f, "\"{}\"", s),
-            Term::Date(d) => {
-                let date = time::OffsetDateTime::from_unix_timestamp(*d as i64)
-                    .ok()
-                    .and_then(|t| {
-                        t.format(&time::format_description::well_known::Rfc3339)
-                            .ok()
-                    })
-                    .unwrap_or_else(|| "<invalid date>".to_string());
-
-                write!(f, "{}", date)
-            }
+            Term::Date(d) => write!(f, "{}", datalog::print_date(*d)),
             Term::Bytes(s) => write!(f, "hex:{}", hex::encode(s)),
             Term::Bool(b) => {
                 if *b {
@@ -534,6 +637,8 @@ impl fmt::Display for Term {
             Term::Parameter(s) => {
                 write!(f, "{{{}}}", s)
             }
+            Term::Decimal(m, s) => write!(f, "{}", datalog::decimal::to_string(*m, *s)),
+            Term::Uuid(u) => write!(f, "uuid:{}", datalog::uuid_to_string(u)),
         }
     }
 }
@@ -584,7 +689,7 @@ impl fmt::Display for Scope {
         match self {
             Scope::Authority => write!(f, "authority"),
             Scope::Previous => write!(f, "previous"),
-            Scope::PublicKey(pk) => write!(f, "ed25519/{}", hex::encode(pk.to_bytes())),
+            Scope::PublicKey(pk) => write!(f, "{}", pk),
             Scope::Parameter(s) => {
                 write!(f, "{{{}}}", s)
             }
@@ -592,19 +697,39 @@ impl fmt::Display for Scope {
     }
 }
 
-impl From<biscuit_parser::builder::Scope> for Scope {
-    fn from(scope: biscuit_parser::builder::Scope) -> Self {
-        match scope {
+impl TryFrom<biscuit_parser::builder::Scope> for Scope {
+    type Error = error::Token;
+
+    fn try_from(scope: biscuit_parser::builder::Scope) -> Result<Self, Self::Error> {
+        Ok(match scope {
             biscuit_parser::builder::Scope::Authority => Scope::Authority,
             biscuit_parser::builder::Scope::Previous => Scope::Previous,
             biscuit_parser::builder::Scope::PublicKey(pk) => {
-                Scope::PublicKey(PublicKey::from_bytes(&pk).expect("invalid public key"))
+                Scope::PublicKey(parsed_public_key(pk)?)
             }
             biscuit_parser::builder::Scope::Parameter(s) => Scope::Parameter(s),
-        }
+        })
     }
 }
 
+/// converts a public key written in Datalog: the parser only reads its bytes,
+/// which may not be a valid key for the algorithm
+fn parsed_public_key(pk: biscuit_parser::builder::PublicKey) -> Result<PublicKey, error::Token> {
+    PublicKey::from_bytes_with_algorithm(&pk.key, pk.algorithm.into()).map_err(|_| {
+        let algorithm = match pk.algorithm {
+            biscuit_parser::builder::Algorithm::Ed25519 => "ed25519",
+            biscuit_parser::builder::Algorithm::P256 => "p256",
+        };
+        biscuit_parser::error::LanguageError::ParseError(biscuit_parser::error::ParseErrors {
+            errors: vec![biscuit_parser::error::ParseError::new(
+                format!("{}/{}", algorithm, hex::encode(&pk.key)),
+                Some("invalid public key".to_string()),
+            )],
+        })
+        .into()
+    })
+}
+
 /// Builder for a Datalog dicate, used in facts and rules
 #[derive(Debug, Clone, PartialEq, Hash, Eq)]
 pub struct Predicate {
@@ -940,6 +1065,8 @@ impl From<biscuit_parser::builder::Unary> for Unary {
             biscuit_parser::builder::Unary::Negate => Unary::Negate,
             biscuit_parser::builder::Unary::Parens => Unary::Parens,
             biscuit_parser::builder::Unary::Length => Unary::Length,
+            biscuit_parser::builder::Unary::Lowercase => Unary::Lowercase,
+            biscuit_parser::builder::Unary::Trim => Unary::Trim,
         }
     }
 }
@@ -968,6 +1095,12 @@ impl From<biscuit_parser::builder::Binary> for Binary {
             biscuit_parser::builder::Binary::BitwiseOr => Binary::BitwiseOr,
             biscuit_parser::builder::Binary::BitwiseXor => Binary::BitwiseXor,
             biscuit_parser::builder::Binary::NotEqual => Binary::NotEqual,
+            biscuit_parser::builder::Binary::Difference => Binary::Difference,
+            biscuit_parser::builder::Binary::SymmetricDifference => Binary::SymmetricDifference,
+            biscuit_parser::builder::Binary::IsSubset => Binary::IsSubset,
+            biscuit_parser::builder::Binary::IsSuperset => Binary::IsSuperset,
+            biscuit_parser::builder::Binary::Split => Binary::Split,
+            biscuit_parser::builder::Binary::Capture => Binary::Capture,
         }
     }
 }
@@ -981,6 +1114,8 @@ pub struct Rule {
     pub parameters: Option<HashMap<String, Option<Term>>>,
     pub scopes: Vec<Scope>,
     pub scope_parameters: Option<HashMap<String, Option<PublicKey>>>,
+    /// aggregations applied to head terms, by position in the head
+    pub aggregates: Vec<(usize, Aggregate)>,
 }
 
 impl Rule {
@@ -1027,6 +1162,7 @@ impl Rule {
             parameters: Some(parameters),
             scopes,
             scope_parameters: Some(scope_parameters),
+            aggregates: vec![],
         }
     }
 
@@ -1213,7 +1349,25 @@ impl Rule {
         name: &str,
         param: T,
     ) -> Result<(), error::Token> {
-        match param.to_any_param() {
+        self.set_any_param(name, param.to_any_param())
+    }
+
+    /// a string argument for a scope parameter is parsed as a public key,
+    /// like `ed25519/<hex>`
+    #[cfg(feature = "datalog-macro")]
+    fn set_any_param(&mut self, name: &str, param: AnyParam) -> Result<(), error::Token> {
+        match param {
+            AnyParam::Term(Term::Str(key))
+                if self
+                    .scope_parameters
+                    .as_ref()
+                    .map(|parameters| parameters.contains_key(name))
+                    .unwrap_or(false) =>
+            {
+                let pubkey = key.parse()?;
+                self.set_lenient(name, Term::Str(key))?;
+                self.set_scope_lenient(name, pubkey)
+            }
             AnyParam::Term(t) => self.set_lenient(name, t),
             AnyParam::PublicKey(pubkey) => self.set_scope_lenient(name, pubkey),
         }
@@ -1318,6 +1472,7 @@ impl Convert<datalog::Rule> for Rule {
             body,
             expressions,
             scopes,
+            aggregates: r.aggregates,
         }
     }
 
@@ -1341,6 +1496,7 @@ impl Convert<datalog::Rule> for Rule {
                 .map(|scope| Scope::convert_from(scope, symbols))
                 .collect::<Result<Vec<Scope>, error::Format>>()?,
             scope_parameters: None,
+            aggregates: r.aggregates.clone(),
         })
     }
 }
@@ -1389,15 +1545,33 @@ impl fmt::Display for Rule {
         let mut r = self.clone();
         r.apply_parameters();
 
-        write!(f, "{} <- ", r.head)?;
+        if r.aggregates.is_empty() {
+            write!(f, "{} <- ", r.head)?;
+        } else {
+            let terms = r
+                .head
+                .terms
+                .iter()
+                .enumerate()
+                .map(
+                    |(position, term)| match r.aggregates.iter().find(|(p, _)| *p == position) {
+                        Some((_, aggregate)) => format!("{}({})", aggregate.name(), term),
+                        None => term.to_string(),
+                    },
+                )
+                .collect::<Vec<_>>();
+            write!(f, "{}({}) <- ", r.head.name, terms.join(", "))?;
+        }
 
         display_rule_body(&r, f)
     }
 }
 
-impl From<biscuit_parser::builder::Rule> for Rule {
-    fn from(r: biscuit_parser::builder::Rule) -> Self {
-        Rule {
+impl TryFrom<biscuit_parser::builder::Rule> for Rule {
+    type Error = error::Token;
+
+    fn try_from(r: biscuit_parser::builder::Rule) -> Result<Self, Self::Error> {
+        Ok(Rule {
             head: r.head.into(),
             body: r.body.into_iter().map(|p| p.into()).collect(),
             expressions: r.expressions.into_iter().map(|e| e.into()).collect(),
@@ -1406,19 +1580,35 @@ impl From<biscuit_parser::builder::Rule> for Rule {
                     .map(|(k, v)| (k, v.map(|term| term.into())))
                     .collect()
             }),
-            scopes: r.scopes.into_iter().map(|s| s.into()).collect(),
-            scope_parameters: r.scope_parameters.map(|h| {
-                h.into_iter()
-                    .map(|(k, v)| {
-                        (
-                            k,
-                            v.map(|bytes| {
-                                PublicKey::from_bytes(&bytes).expect("invalid public key")
-                            }),
-                        )
-                    })
-                    .collect()
-            }),
+            scopes: r
+                .scopes
+                .into_iter()
+                .map(Scope::try_from)
+                .collect::<Result<_, _>>()?,
+            scope_parameters: r
+                .scope_parameters
+                .map(|h| {
+                    h.into_iter()
+                        .map(|(k, v)| Ok((k, v.map(parsed_public_key).transpose()?)))
+                        .collect::<Result<_, error::Token>>()
+                })
+                .transpose()?,
+            aggregates: r
+                .aggregates
+                .into_iter()
+                .map(|(position, aggregate)| (position, aggregate.into()))
+                .collect(),
+        })
+    }
+}
+
+impl From<biscuit_parser::builder::Aggregate> for Aggregate {
+    fn from(a: biscuit_parser::builder::Aggregate) -> Self {
+        match a {
+            biscuit_parser::builder::Aggregate::Count => Aggregate::Count,
+            biscuit_parser::builder::Aggregate::Sum => Aggregate::Sum,
+            biscuit_parser::builder::Aggregate::Min => Aggregate::Min,
+            biscuit_parser::builder::Aggregate::Max => Aggregate::Max,
         }
     }
 }
@@ -1428,6 +1618,8 @@ impl From<biscuit_parser::builder::Rule> for Rule {
 pub struct Check {
     pub queries: Vec<Rule>,
     pub kind: CheckKind,
+    /// message returned in the error when the check fails
+    pub message: Option<String>,
 }
 
 /// Builder for a Biscuit check
@@ -1435,6 +1627,8 @@ pub struct Check {
 pub enum CheckKind {
     One,
     All,
+    /// `reject if`: fails if the query matches
+    Reject,
 }
 
 impl Check {
@@ -1510,10 +1704,11 @@ impl Check {
         name: &str,
         param: T,
     ) -> Result<(), error::Token> {
-        match param.to_any_param() {
-            AnyParam::Term(t) => self.set_lenient(name, t),
-            AnyParam::PublicKey(p) => self.set_scope_lenient(name, p),
+        let param = param.to_any_param();
+        for query in &mut self.queries {
+            query.set_any_param(name, param.clone())?;
         }
+        Ok(())
     }
 
     pub fn validate_parameters(&self) -> Result<(), error::Token> {
@@ -1541,6 +1736,7 @@ impl Convert<datalog::Check> for Check {
         datalog::Check {
             queries,
             kind: self.kind.clone(),
+            message: self.message.clone(),
         }
     }
 
@@ -1553,6 +1749,7 @@ impl Convert<datalog::Check> for Check {
         Ok(Check {
             queries,
             kind: r.kind.clone(),
+            message: r.message.clone(),
         })
     }
 }
@@ -1564,6 +1761,7 @@ impl TryFrom<Rule> for Check {
         Ok(Check {
             queries: vec![value],
             kind: CheckKind::One,
+            message: None,
         })
     }
 }
@@ -1575,6 +1773,7 @@ impl TryFrom<&[Rule]> for Check {
         Ok(Check {
             queries: values.to_vec(),
             kind: CheckKind::One,
+            message: None,
         })
     }
 }
@@ -1584,6 +1783,7 @@ impl fmt::Display for Check {
         match self.kind {
             CheckKind::One => write!(f, "check if ")?,
             CheckKind::All => write!(f, "check all ")?,
+            CheckKind::Reject => write!(f, "reject if ")?,
         };
 
         if !self.queries.is_empty() {
@@ -1601,19 +1801,31 @@ impl fmt::Display for Check {
             }
         }
 
+        if let Some(message) = &self.message {
+            write!(f, " @msg(\"{}\")", datalog::escape_string(message))?;
+        }
+
         Ok(())
     }
 }
 
-impl From<biscuit_parser::builder::Check> for Check {
-    fn from(c: biscuit_parser::builder::Check) -> Self {
-        Check {
-            queries: c.queries.into_iter().map(|q| q.into()).collect(),
+impl TryFrom<biscuit_parser::builder::Check> for Check {
+    type Error = error::Token;
+
+    fn try_from(c: biscuit_parser::builder::Check) -> Result<Self, Self::Error> {
+        Ok(Check {
+            queries: c
+                .queries
+                .into_iter()
+                .map(Rule::try_from)
+                .collect::<Result<_, _>>()?,
             kind: match c.kind {
                 biscuit_parser::builder::CheckKind::One => CheckKind::One,
                 biscuit_parser::builder::CheckKind::All => CheckKind::All,
+                biscuit_parser::builder::CheckKind::Reject => CheckKind::Reject,
             },
-        }
+            message: c.message,
+        })
     }
 }
 
@@ -1701,10 +1913,11 @@ impl Policy {
         name: &str,
         param: T,
     ) -> Result<(), error::Token> {
-        match param.to_any_param() {
-            AnyParam::Term(t) => self.set_lenient(name, t),
-            AnyParam::PublicKey(p) => self.set_scope_lenient(name, p),
+        let param = param.to_any_param();
+        for query in &mut self.queries {
+            query.set_any_param(name, param.clone())?;
         }
+        Ok(())
     }
 
     pub fn validate_parameters(&self) -> Result<(), error::Token> {
@@ -1745,15 +1958,21 @@ impl fmt::Display for Policy {
     }
 }
 
-impl From<biscuit_parser::builder::Policy> for Policy {
-    fn from(p: biscuit_parser::builder::Policy) -> Self {
-        Policy {
-            queries: p.queries.into_iter().map(|q| q.into()).collect(),
+impl TryFrom<biscuit_parser::builder::Policy> for Policy {
+    type Error = error::Token;
+
+    fn try_from(p: biscuit_parser::builder::Policy) -> Result<Self, Self::Error> {
+        Ok(Policy {
+            queries: p
+                .queries
+                .into_iter()
+                .map(Rule::try_from)
+                .collect::<Result<_, _>>()?,
             kind: match p.kind {
                 biscuit_parser::builder::PolicyKind::Allow => PolicyKind::Allow,
                 biscuit_parser::builder::PolicyKind::Deny => PolicyKind::Deny,
             },
-        }
+        })
     }
 }
 
@@ -1811,6 +2030,7 @@ pub fn check<P: AsRef<Predicate>>(predicates: &[P], kind: CheckKind) -> Check {
             vec![],
         )],
         kind,
+        message: None,
     }
 }
 
@@ -1819,19 +2039,64 @@ pub fn int(i: i64) -> Term {
     Term::Integer(i)
 }
 
+/// creates a decimal number, equal to `mantissa * 10^-scale`
+///
+/// the scale is the number of fractional digits. Trailing zeros are removed, so
+/// that equal numbers are the same term: `decimal(1230, 2)` is `12.3d`
+pub fn decimal(mantissa: i128, scale: u8) -> Term {
+    let (mantissa, scale) = datalog::decimal::normalize(mantissa, scale);
+    Term::Decimal(mantissa, scale)
+}
+
+/// creates a UUID from its 16 bytes, in big endian order
+///
+/// with the `uuid` feature, `uuid::Uuid` values convert to byte arrays, as in
+/// previous versions: use `uuid(id.into_bytes())` to get a UUID term, which
+/// requires the schema version 1002
+pub fn uuid(bytes: [u8; 16]) -> Term {
+    Term::Uuid(bytes)
+}
+
 /// creates a string
 pub fn string(s: &str) -> Term {
     Term::Str(s.to_string())
 }
 
+/// latest date that can be written in Datalog (`9999-12-31T23:59:59Z`), in seconds since UNIX_EPOCH
+pub const MAX_DATE: u64 = 253_402_300_799;
+
 /// creates a date
 ///
 /// internally the date will be stored as seconds since UNIX_EPOCH
+///
+/// # Panics
+///
+/// panics if the date is before UNIX_EPOCH, see [`checked_date`] for a fallible version
 pub fn date(t: &SystemTime) -> Term {
     let dur = t.duration_since(UNIX_EPOCH).unwrap();
     Term::Date(dur.as_secs())
 }
 
+/// creates a date, checking that it is between UNIX_EPOCH and [`MAX_DATE`]
+///
+/// dates after [`MAX_DATE`] can be stored in a token, but they cannot
+/// be printed as Datalog and parsed back
+pub fn checked_date(t: &SystemTime) -> Result<Term, error::Token> {
+    let secs = t
+        .duration_since(UNIX_EPOCH)
+        .map_err(|_| error::Token::ConversionError("date is before UNIX_EPOCH".to_string()))?
+        .as_secs();
+
+    if secs > MAX_DATE {
+        return Err(error::Token::ConversionError(format!(
+            "date is after 9999-12-31T23:59:59Z ({} seconds since UNIX_EPOCH)",
+            secs
+        )));
+    }
+
+    Ok(Term::Date(secs))
+}
+
 /// creates a variable for a rule
 pub fn var(s: &str) -> Term {
     Term::Variable(s.to_string())
@@ -1863,6 +2128,7 @@ pub fn parameter(p: &str) -> Term {
 }
 
 #[cfg(feature = "datalog-macro")]
+#[derive(Clone)]
 pub enum AnyParam {
     Term(Term),
     PublicKey(PublicKey),
@@ -1873,6 +2139,13 @@ pub trait ToAnyParam {
     fn to_any_param(&self) -> AnyParam;
 }
 
+#[cfg(feature = "datalog-macro")]
+impl ToAnyParam for Term {
+    fn to_any_param(&self) -> AnyParam {
+        AnyParam::Term(self.clone())
+    }
+}
+
 impl From<i64> for Term {
     fn from(i: i64) -> Self {
         Term::Integer(i)
@@ -2003,10 +2276,34 @@ impl ToAnyParam for [u8] {
     }
 }
 
+#[cfg(feature = "uuid")]
+impl From<uuid::Uuid> for Term {
+    fn from(u: uuid::Uuid) -> Self {
+        Term::Bytes(u.as_bytes().to_vec())
+    }
+}
+
 #[cfg(feature = "uuid")]
 impl ToAnyParam for uuid::Uuid {
     fn to_any_param(&self) -> AnyParam {
-        AnyParam::Term(Term::Bytes(self.as_bytes().to_vec()))
+        AnyParam::Term((*self).into())
+    }
+}
+
+#[cfg(feature = "uuid")]
+impl TryFrom<Term> for uuid::Uuid {
+    type Error = error::Token;
+    fn try_from(value: Term) -> Result<Self, Self::Error> {
+        match value {
+            Term::Uuid(u) => Ok(uuid::Uuid::from_bytes(u)),
+            Term::Bytes(ref b) => uuid::Uuid::from_slice(b).map_err(|_| {
+                error::Token::ConversionError(format!("expected UUID, got {:?}", value))
+            }),
+            _ => Err(error::Token::ConversionError(format!(
+                "expected UUID, got {:?}",
+                value
+            ))),
+        }
     }
 }
 
@@ -2028,7 +2325,11 @@ impl TryFrom<Term> for SystemTime {
     type Error = error::Token;
     fn try_from(value: Term) -> Result<Self, Self::Error> {
         match value {
-            Term::Date(d) => Ok(UNIX_EPOCH + Duration::from_secs(d)),
+            Term::Date(d) => UNIX_EPOCH
+                .checked_add(Duration::from_secs(d))
+                .ok_or_else(|| {
+                    error::Token::ConversionError(format!("date {} is out of range", d))
+                }),
             _ => Err(error::Token::ConversionError(format!(
                 "expected date, got {:?}",
                 value
@@ -2063,6 +2364,77 @@ impl<T: Ord + TryFrom<Term, Error = error::Token>> TryFrom<Term> for BTreeSet<T>
     }
 }
 
+/// values that can be elements of a set
+///
+/// collections of these values are converted to a set term, so they can be
+/// used as parameters of the Datalog macros, like `{ids}` for a
+/// `HashSet<String>`. Datalog sets cannot contain sets, so collections do
+/// not implement this trait, and a `Vec<Vec<String>>` parameter is rejected
+/// at compile time. `Vec<u8>` stays a byte array.
+pub trait SetElement: Clone + Into<Term> {}
+
+impl SetElement for i64 {}
+impl SetElement for bool {}
+impl SetElement for String {}
+impl SetElement for &str {}
+impl SetElement for SystemTime {}
+impl SetElement for Vec<u8> {}
+#[cfg(feature = "uuid")]
+impl SetElement for uuid::Uuid {}
+
+impl<T: SetElement> From<BTreeSet<T>> for Term {
+    fn from(value: BTreeSet<T>) -> Term {
+        set(value.into_iter().map(Into::into).collect())
+    }
+}
+
+#[cfg(feature = "datalog-macro")]
+impl<T: SetElement> ToAnyParam for BTreeSet<T> {
+    fn to_any_param(&self) -> AnyParam {
+        AnyParam::Term((self.clone()).into())
+    }
+}
+
+impl<T: SetElement> From<HashSet<T>> for Term {
+    fn from(value: HashSet<T>) -> Term {
+        set(value.into_iter().map(Into::into).collect())
+    }
+}
+
+#[cfg(feature = "datalog-macro")]
+impl<T: SetElement> ToAnyParam for HashSet<T> {
+    fn to_any_param(&self) -> AnyParam {
+        AnyParam::Term((self.clone()).into())
+    }
+}
+
+impl<T: Eq + Hash + TryFrom<Term, Error = error::Token>> TryFrom<Term> for HashSet<T> {
+    type Error = error::Token;
+    fn try_from(value: Term) -> Result<Self, Self::Error> {
+        match value {
+            Term::Set(d) => d.iter().cloned().map(TryFrom::try_from).collect(),
+            _ => Err(error::Token::ConversionError(format!(
+                "expected set, got {:?}",
+                value
+            ))),
+        }
+    }
+}
+
+/// a list is converted to a set: duplicates are removed and the order is lost
+impl<T: SetElement> From<Vec<T>> for Term {
+    fn from(value: Vec<T>) -> Term {
+        set(value.into_iter().map(Into::into).collect())
+    }
+}
+
+#[cfg(feature = "datalog-macro")]
+impl<T: SetElement> ToAnyParam for Vec<T> {
+    fn to_any_param(&self) -> AnyParam {
+        AnyParam::Term((self.clone()).into())
+    }
+}
+
 macro_rules! tuple_try_from(
     ($ty1:ident, $ty2:ident, $($ty:ident),*) => (
         tuple_try_from!(__impl $ty1, $ty2; $($ty),*);
@@ -2133,10 +2505,10 @@ impl TryFrom<&str> for Rule {
     type Error = error::Token;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        Ok(biscuit_parser::parser::rule(value)
+        let (_, rule) = biscuit_parser::parser::rule(value)
             .finish()
-            .map(|(_, o)| o.into())
-            .map_err(biscuit_parser::error::LanguageError::from)?)
+            .map_err(biscuit_parser::error::LanguageError::from)?;
+        rule.try_into()
     }
 }
 
@@ -2155,10 +2527,10 @@ impl FromStr for Rule {
     type Err = error::Token;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(biscuit_parser::parser::rule(s)
+        let (_, rule) = biscuit_parser::parser::rule(s)
             .finish()
-            .map(|(_, o)| o.into())
-            .map_err(biscuit_parser::error::LanguageError::from)?)
+            .map_err(biscuit_parser::error::LanguageError::from)?;
+        rule.try_into()
     }
 }
 
@@ -2166,10 +2538,10 @@ impl TryFrom<&str> for Check {
     type Error = error::Token;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        Ok(biscuit_parser::parser::check(value)
+        let (_, check) = biscuit_parser::parser::check(value)
             .finish()
-            .map(|(_, o)| o.into())
-            .map_err(biscuit_parser::error::LanguageError::from)?)
+            .map_err(biscuit_parser::error::LanguageError::from)?;
+        check.try_into()
     }
 }
 
@@ -2177,10 +2549,10 @@ impl FromStr for Check {
     type Err = error::Token;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(biscuit_parser::parser::check(s)
+        let (_, check) = biscuit_parser::parser::check(s)
             .finish()
-            .map(|(_, o)| o.into())
-            .map_err(biscuit_parser::error::LanguageError::from)?)
+            .map_err(biscuit_parser::error::LanguageError::from)?;
+        check.try_into()
     }
 }
 
@@ -2188,10 +2560,10 @@ impl TryFrom<&str> for Policy {
     type Error = error::Token;
 
     fn try_from(value: &str) -> Result<Self, Self::Error> {
-        Ok(biscuit_parser::parser::policy(value)
+        let (_, policy) = biscuit_parser::parser::policy(value)
             .finish()
-            .map(|(_, o)| o.into())
-            .map_err(biscuit_parser::error::LanguageError::from)?)
+            .map_err(biscuit_parser::error::LanguageError::from)?;
+        policy.try_into()
     }
 }
 
@@ -2199,10 +2571,10 @@ impl FromStr for Policy {
     type Err = error::Token;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        Ok(biscuit_parser::parser::policy(s)
+        let (_, policy) = biscuit_parser::parser::policy(s)
             .finish()
-            .map(|(_, o)| o.into())
-            .map_err(biscuit_parser::error::LanguageError::from)?)
+            .map_err(biscuit_parser::error::LanguageError::from)?;
+        policy.try_into()
     }
 }
 
@@ -2218,6 +2590,7 @@ impl BuilderExt for BlockBuilder {
                 &[pred("resource", &[string(name)])],
             )],
             kind: CheckKind::One,
+            message: None,
         });
     }
     fn add_operation(&mut self, name: &str) {
@@ -2231,6 +2604,7 @@ impl BuilderExt for BlockBuilder {
                 &[pred("operation", &[string(name)])],
             )],
             kind: CheckKind::One,
+            message: None,
         });
     }
     fn check_resource_prefix(&mut self, prefix: &str) {
@@ -2250,6 +2624,7 @@ impl BuilderExt for BlockBuilder {
         self.checks.push(Check {
             queries: vec![check],
             kind: CheckKind::One,
+            message: None,
         });
     }
 
@@ -2270,6 +2645,7 @@ impl BuilderExt for BlockBuilder {
         self.checks.push(Check {
             queries: vec![check],
             kind: CheckKind::One,
+            message: None,
         });
     }
 
@@ -2279,18 +2655,71 @@ impl BuilderExt for BlockBuilder {
             "query",
             &empty,
             &[pred("time", &[var("time")])],
-            &[Expression {
-                ops: vec![
-                    Op::Value(var("time")),
-                    Op::Value(date(&exp)),
-                    Op::Binary(Binary::LessOrEqual),
-                ],
-            }],
+            &[super::time_comparison(exp, Binary::LessOrEqual)],
         );
 
         self.checks.push(Check {
             queries: vec![check],
             kind: CheckKind::One,
+            message: None,
+        });
+    }
+
+    fn check_not_before(&mut self, start: SystemTime) {
+        let empty: Vec<Term> = Vec::new();
+        let check = constrained_rule(
+            "query",
+            &empty,
+            &[pred("time", &[var("time")])],
+            &[super::time_comparison(start, Binary::GreaterOrEqual)],
+        );
+
+        self.checks.push(Check {
+            queries: vec![check],
+            kind: CheckKind::One,
+            message: None,
+        });
+    }
+
+    fn check_all_operations(&mut self, operations: &[&str]) {
+        self.checks.push(Check {
+            kind: CheckKind::All,
+            ..super::check_in_set("operation", operations)
+        });
+    }
+
+    fn check_all_resources(&mut self, resources: &[&str]) {
+        self.checks.push(Check {
+            kind: CheckKind::All,
+            ..super::check_in_set("resource", resources)
+        });
+    }
+
+    fn reject_operation(&mut self, name: &str) {
+        self.checks.push(Check {
+            queries: vec![rule(
+                "operation_check",
+                &[string("operation_check")],
+                &[pred("operation", &[string(name)])],
+            )],
+            kind: CheckKind::Reject,
+            message: None,
+        });
+    }
+
+    fn reject_if_expired_before(&mut self, exp: SystemTime) {
+        let empty: Vec<Term> = Vec::new();
+        let check = constrained_rule(
+            "query",
+            &empty,
+            &[pred("time", &[var("time")])],
+            &[super::time_comparison(exp, Binary::GreaterThan)],
+        );
+
+        self.checks.push(Check {
+            queries: vec![check],
+            kind: CheckKind::Reject,
+            message: None,
         });
     }
 }
@@ -2327,6 +2756,21 @@ impl BuilderExt for BiscuitBuilder {
     fn check_expiration_date(&mut self, date: SystemTime) {
         self.inner.check_expiration_date(date);
     }
+    fn check_not_before(&mut self, date: SystemTime) {
+        self.inner.check_not_before(date);
+    }
+    fn check_all_operations(&mut self, operations: &[&str]) {
+        self.inner.check_all_operations(operations);
+    }
+    fn check_all_resources(&mut self, resources: &[&str]) {
+        self.inner.check_all_resources(resources);
+    }
+    fn reject_operation(&mut self, name: &str) {
+        self.inner.reject_operation(name);
+    }
+    fn reject_if_expired_before(&mut self, date: SystemTime) {
+        self.inner.reject_if_expired_before(date);
+    }
 }
 
 #[cfg(test)]
@@ -2476,4 +2920,70 @@ check if true trusting ed25519/6e9e6d5a75cf0c0e87ec1256b4dfed0ca3ba452912d213fcc
             ))
         )
     }
+
+    #[test]
+    fn checked_dates() {
+        let max = UNIX_EPOCH + Duration::from_secs(MAX_DATE);
+        assert_eq!(checked_date(&max), Ok(Term::Date(MAX_DATE)));
+        assert_eq!(
+            checked_date(&max).unwrap().to_string(),
+            "9999-12-31T23:59:59Z"
+        );
+        assert!(checked_date(&(max + Duration::from_secs(1))).is_err());
+        assert!(checked_date(&(UNIX_EPOCH - Duration::from_secs(1))).is_err());
+        assert_eq!(Term::Date(MAX_DATE + 1).to_string(), "<invalid date>");
+
+        let mut builder = BlockBuilder::new();
+        assert!(builder
+            .try_check_expiration_date(UNIX_EPOCH - Duration::from_secs(1))
+            .is_err());
+        assert!(builder.checks.is_empty());
+        builder.try_check_expiration_date(max).unwrap();
+        assert_eq!(
+            builder.checks[0].to_string(),
+            "check if time($time), $time <= 9999-12-31T23:59:59Z"
+        );
+
+        // dates that cannot be represented are not converted to a wrong date
+        assert!(SystemTime::try_from(Term::Date(u64::MAX)).is_err());
+
+        // checks on dates before UNIX_EPOCH do not panic, and give the same
+        // result as with that date
+        let before = UNIX_EPOCH - Duration::from_secs(1);
+        let mut builder = BlockBuilder::new();
+        builder.check_expiration_date(before);
+        builder.check_not_before(before);
+        builder.reject_if_expired_before(before);
+        assert_eq!(
+            builder
+                .checks
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "check if time($time), $time < 1970-01-01T00:00:00Z",
+                "check if time($time), $time >= 1970-01-01T00:00:00Z",
+                "reject if time($time), $time >= 1970-01-01T00:00:00Z",
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_scope_keys() {
+        let invalid_key = |e| {
+            matches!(e, error::Token::Language(biscuit_parser::error::LanguageError::ParseError(e))
+                if e.errors[0].message.as_deref() == Some("invalid public key"))
+        };
+        // too short for the algorithm, and 33 bytes that are not a point of the curve
+        let p256 = format!("p256/02{}", "ff".repeat(32));
+        for key in ["ed25519/00", "p256/00", p256.as_str()] {
+            let mut builder = BlockBuilder::new();
+            let code = format!("check if true trusting {key}");
+            assert!(invalid_key(builder.add_code(&code).unwrap_err()));
+            let code = format!("right($r) <- owner($r) trusting authority, {key}");
+            assert!(invalid_key(Rule::try_from(code.as_str()).unwrap_err()));
+            let code = format!("allow if true trusting {key}");
+            assert!(invalid_key(Policy::try_from(code.as_str()).unwrap_err()));
+        }
+    }
 }
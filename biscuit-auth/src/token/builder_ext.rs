@@ -1,5 +1,9 @@
 use std::time::SystemTime;
 
+use super::check_in_set;
+use crate::builder::{checked_date, BlockBuilder};
+use crate::error;
+
 pub trait BuilderExt {
     fn add_resource(&mut self, name: &str);
     fn check_resource(&mut self, name: &str);
@@ -7,10 +11,132 @@ pub trait BuilderExt {
     fn check_resource_suffix(&mut self, suffix: &str);
     fn add_operation(&mut self, name: &str);
     fn check_operation(&mut self, name: &str);
+    /// `check if time($time), $time <= date`: the `time` fact must not be
+    /// after this date. The check always fails for a date before UNIX_EPOCH
     fn check_expiration_date(&mut self, date: SystemTime);
+    /// `check if time($time), $time >= date`: the `time` fact must not be
+    /// before this date
+    fn check_not_before(&mut self, date: SystemTime);
+    /// `check all operation($value), [..].contains($value)`: every
+    /// `operation` fact must be part of the list
+    fn check_all_operations(&mut self, operations: &[&str]);
+    /// `check all resource($value), [..].contains($value)`: every
+    /// `resource` fact must be part of the list
+    fn check_all_resources(&mut self, resources: &[&str]);
+    /// `reject if operation("name")`, only supported by the schema version 1002
+    fn reject_operation(&mut self, name: &str);
+    /// `reject if time($time), $time > date`, only supported by the schema
+    /// version 1002
+    fn reject_if_expired_before(&mut self, date: SystemTime);
+
+    /// adds the checks of `check_not_before(start)` and
+    /// `check_expiration_date(end)`: the `time` fact must be between these dates
+    fn check_validity_window(&mut self, start: SystemTime, end: SystemTime) {
+        self.check_not_before(start);
+        self.check_expiration_date(end);
+    }
+
+    /// like `check_expiration_date`, but returns an error if the date is
+    /// before UNIX_EPOCH, instead of adding a check that always fails, or
+    /// after [`MAX_DATE`](crate::builder::MAX_DATE)
+    fn try_check_expiration_date(&mut self, date: SystemTime) -> Result<(), error::Token> {
+        checked_date(&date)?;
+        self.check_expiration_date(date);
+        Ok(())
+    }
 }
 
 pub trait AuthorizerExt {
     fn add_allow_all(&mut self);
     fn add_deny_all(&mut self);
 }
+
+/// common restrictions of an attenuation block, see [`Biscuit::attenuate_with`](crate::Biscuit::attenuate_with)
+///
+/// each restriction that is set adds a check to the block:
+/// - `expiration`: the `time` fact must be before this date
+/// - `resource_prefix`: the `resource` fact must start with this prefix
+/// - `resources` and `operations`: the `resource` and `operation` facts must be
+///   part of the list, so an empty list allows nothing
+///
+/// ```rust
+/// use biscuit_auth::{builder_ext::TemplateBlock, Biscuit, KeyPair};
+/// use std::time::{Duration, SystemTime};
+///
+/// let root = KeyPair::new();
+/// let token = Biscuit::builder().build(&root).unwrap();
+///
+/// let attenuated = token
+///     .attenuate_with(
+///         &TemplateBlock::new()
+///             .expiration(SystemTime::now() + Duration::from_secs(60))
+///             .resource_prefix("/files/")
+///             .operations(&["read"]),
+///     )
+///     .unwrap();
+/// assert_eq!(attenuated.block_count(), 2);
+/// ```
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TemplateBlock {
+    pub expiration: Option<SystemTime>,
+    pub resource_prefix: Option<String>,
+    pub resources: Option<Vec<String>>,
+    pub operations: Option<Vec<String>>,
+    pub context: Option<String>,
+}
+
+impl TemplateBlock {
+    pub fn new() -> Self {
+        TemplateBlock::default()
+    }
+
+    pub fn expiration(mut self, date: SystemTime) -> Self {
+        self.expiration = Some(date);
+        self
+    }
+
+    pub fn resource_prefix(mut self, prefix: &str) -> Self {
+        self.resource_prefix = Some(prefix.to_string());
+        self
+    }
+
+    pub fn resources(mut self, resources: &[&str]) -> Self {
+        self.resources = Some(resources.iter().map(|r| r.to_string()).collect());
+        self
+    }
+
+    pub fn operations(mut self, operations: &[&str]) -> Self {
+        self.operations = Some(operations.iter().map(|o| o.to_string()).collect());
+        self
+    }
+
+    pub fn context(mut self, context: &str) -> Self {
+        self.context = Some(context.to_string());
+        self
+    }
+
+    /// creates the block containing the checks for these restrictions
+    pub fn to_block_builder(&self) -> Result<BlockBuilder, error::Token> {
+        let mut block = BlockBuilder::new();
+
+        if let Some(resources) = &self.resources {
+            let resources = resources.iter().map(|r| r.as_str()).collect::<Vec<_>>();
+            block.add_check(check_in_set("resource", &resources))?;
+        }
+        if let Some(prefix) = &self.resource_prefix {
+            block.check_resource_prefix(prefix);
+        }
+        if let Some(operations) = &self.operations {
+            let operations = operations.iter().map(|o| o.as_str()).collect::<Vec<_>>();
+            block.add_check(check_in_set("operation", &operations))?;
+        }
+        if let Some(expiration) = self.expiration {
+            block.try_check_expiration_date(expiration)?;
+        }
+        if let Some(context) = &self.context {
+            block.set_context(context.clone());
+        }
+
+        Ok(block)
+    }
+}
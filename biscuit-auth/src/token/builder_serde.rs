@@ -0,0 +1,171 @@
+//! `Serialize` and `Deserialize` implementations for the builder types
+//!
+//! facts, rules, checks and policies are represented by their Datalog source,
+//! like `"right(\"file1\", \"read\")"`, so that they stay readable in JSON or
+//! YAML configuration files. Authorizer snapshots are represented by their
+//! base64 encoded protobuf, as returned by [`Authorizer::to_base64_snapshot`](crate::Authorizer::to_base64_snapshot)
+use std::fmt;
+use std::str::FromStr;
+
+use prost::Message;
+use serde::de::{self, Deserialize, Deserializer, Visitor};
+use serde::{Serialize, Serializer};
+
+use super::builder::{Check, Fact, Policy, Rule};
+use crate::format::schema::AuthorizerSnapshot;
+
+macro_rules! datalog_serde {
+    ($ty:ty, $expecting:literal) => {
+        impl Serialize for $ty {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.collect_str(self)
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $ty {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                deserializer.deserialize_str(FromStrVisitor::<$ty>::new($expecting))
+            }
+        }
+    };
+}
+
+datalog_serde!(Fact, "a Datalog fact");
+datalog_serde!(Rule, "a Datalog rule");
+datalog_serde!(Check, "a Datalog check");
+datalog_serde!(Policy, "a Datalog policy");
+
+struct FromStrVisitor<T> {
+    expecting: &'static str,
+    marker: std::marker::PhantomData<T>,
+}
+
+impl<T> FromStrVisitor<T> {
+    fn new(expecting: &'static str) -> Self {
+        FromStrVisitor {
+            expecting,
+            marker: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<'de, T> Visitor<'de> for FromStrVisitor<T>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    type Value = T;
+
+    fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter.write_str(self.expecting)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<T, E> {
+        v.parse().map_err(E::custom)
+    }
+}
+
+impl Serialize for AuthorizerSnapshot {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&base64::encode_config(
+            self.encode_to_vec(),
+            base64::URL_SAFE,
+        ))
+    }
+}
+
+impl<'de> Deserialize<'de> for AuthorizerSnapshot {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let encoded = String::deserialize(deserializer)?;
+        let bytes = base64::decode_config(encoded, base64::URL_SAFE).map_err(de::Error::custom)?;
+        AuthorizerSnapshot::decode(&bytes[..]).map_err(de::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::{Check, Fact, Policy, Rule};
+    use crate::format::schema::AuthorizerSnapshot;
+    use crate::token::authorizer::AuthorizerPolicies;
+    use crate::{Authorizer, AuthorizerLimits};
+    use std::convert::TryInto;
+    use std::time::Duration;
+
+    fn limits() -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn builder_types() {
+        let fact: Fact = "right(\"file1\", \"read\")".try_into().unwrap();
+        let json = serde_json::to_string(&fact).unwrap();
+        assert_eq!(json, r#""right(\"file1\", \"read\")""#);
+        assert_eq!(serde_json::from_str::<Fact>(&json).unwrap(), fact);
+
+        let rule: Rule = "valid($r) <- right($r, \"read\"), $r.starts_with(\"file\")"
+            .try_into()
+            .unwrap();
+        let check: Check = "check all operation($op), [\"read\"].contains($op)"
+            .try_into()
+            .unwrap();
+        let policy: Policy = "deny if valid(\"file2\") or valid(\"file3\")"
+            .try_into()
+            .unwrap();
+        let json = serde_json::to_value((&rule, &check, &policy)).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!([
+                "valid($r) <- right($r, \"read\"), $r.starts_with(\"file\")",
+                "check all operation($op), [\"read\"].contains($op)",
+                "deny if valid(\"file2\") or valid(\"file3\")",
+            ])
+        );
+        assert_eq!(
+            serde_json::from_value::<(Rule, Check, Policy)>(json).unwrap(),
+            (rule, check, policy)
+        );
+
+        let err = serde_json::from_str::<Rule>(r#""valid($r) <- ""#).unwrap_err();
+        assert!(err.to_string().contains("error"));
+        assert!(serde_json::from_str::<Fact>("12").is_err());
+    }
+
+    #[test]
+    fn authorizer_policies() {
+        let mut authorizer = Authorizer::new();
+        authorizer
+            .add_code(
+                r#"resource("file1");
+                is_file($r) <- resource($r), $r.starts_with("file");
+                check if is_file($r);
+                allow if resource("file1");"#,
+            )
+            .unwrap();
+
+        let policies = authorizer.save().unwrap();
+        let json = serde_json::to_value(&policies).unwrap();
+        assert_eq!(json["facts"], serde_json::json!(["resource(\"file1\")"]));
+        assert_eq!(
+            json["policies"],
+            serde_json::json!(["allow if resource(\"file1\")"])
+        );
+
+        let policies: AuthorizerPolicies = serde_json::from_value(json).unwrap();
+        let mut authorizer: Authorizer = policies.try_into().unwrap();
+        assert_eq!(authorizer.authorize_with_limits(limits()), Ok(0));
+
+        let snapshot = authorizer.snapshot().unwrap();
+        let json = serde_json::to_string(&snapshot).unwrap();
+        assert_eq!(
+            json,
+            format!("\"{}\"", authorizer.to_base64_snapshot().unwrap())
+        );
+        assert_eq!(
+            serde_json::from_str::<AuthorizerSnapshot>(&json).unwrap(),
+            snapshot
+        );
+    }
+}
@@ -0,0 +1,226 @@
+use serde::Serialize;
+
+use super::{builder, unverified::UnverifiedBiscuit, Biscuit, Block};
+use crate::builder::Convert;
+use crate::datalog::SymbolTable;
+use crate::error;
+
+#[derive(Serialize)]
+struct TokenJson {
+    root_key_id: Option<u32>,
+    sealed: bool,
+    blocks: Vec<BlockJson>,
+}
+
+#[derive(Serialize)]
+struct BlockJson {
+    index: usize,
+    version: u32,
+    context: Option<String>,
+    external_key: Option<String>,
+    revocation_id: String,
+    facts: Vec<String>,
+    rules: Vec<String>,
+    checks: Vec<String>,
+    scopes: Vec<String>,
+}
+
+impl BlockJson {
+    fn new(
+        index: usize,
+        block: &Block,
+        symbols: &SymbolTable,
+        revocation_id: &[u8],
+    ) -> Result<Self, error::Format> {
+        Ok(BlockJson {
+            index,
+            version: block.version,
+            context: block.context.clone(),
+            external_key: block.external_key.map(|key| key.to_string()),
+            revocation_id: hex::encode(revocation_id),
+            facts: to_strings(&block.facts, symbols, builder::Fact::convert_from)?,
+            rules: to_strings(&block.rules, symbols, builder::Rule::convert_from)?,
+            checks: to_strings(&block.checks, symbols, builder::Check::convert_from)?,
+            scopes: to_strings(&block.scopes, symbols, builder::Scope::convert_from)?,
+        })
+    }
+}
+
+fn to_strings<T, U: ToString>(
+    elements: &[T],
+    symbols: &SymbolTable,
+    convert: fn(&T, &SymbolTable) -> Result<U, error::Format>,
+) -> Result<Vec<String>, error::Format> {
+    elements
+        .iter()
+        .map(|element| convert(element, symbols).map(|element| element.to_string()))
+        .collect()
+}
+
+/// builds the inspection JSON shared by [`Biscuit`] and [`UnverifiedBiscuit`]
+///
+/// `block` converts the block at an index, with its revocation id
+fn inspection_json<F>(
+    root_key_id: Option<u32>,
+    sealed: bool,
+    revocation_ids: Vec<Vec<u8>>,
+    block: F,
+) -> Result<serde_json::Value, error::Token>
+where
+    F: Fn(usize, &[u8]) -> Result<BlockJson, error::Token>,
+{
+    let blocks = revocation_ids
+        .iter()
+        .enumerate()
+        .map(|(i, revocation_id)| block(i, revocation_id))
+        .collect::<Result<Vec<_>, error::Token>>()?;
+
+    serde_json::to_value(TokenJson {
+        root_key_id,
+        sealed,
+        blocks,
+    })
+    .map_err(|e| error::Format::SerializationError(format!("serialization error: {:?}", e)).into())
+}
+
+impl Biscuit {
+    /// returns the content of the token as JSON, for command line tools and
+    /// debuggers
+    ///
+    /// the schema is:
+    ///
+    /// ```json
+    /// {
+    ///   "root_key_id": null,
+    ///   "sealed": false,
+    ///   "blocks": [
+    ///     {
+    ///       "index": 0,
+    ///       "version": 4,
+    ///       "context": null,
+    ///       "external_key": null,
+    ///       "revocation_id": "<hex>",
+    ///       "facts": ["user(\"alice\")"],
+    ///       "rules": ["right($r) <- owner($r)"],
+    ///       "checks": ["check if time($t), $t <= 2030-01-01T00:00:00Z"],
+    ///       "scopes": ["authority", "ed25519/<hex>"]
+    ///     }
+    ///   ]
+    /// }
+    /// ```
+    ///
+    /// blocks are in token order, starting with the authority block. The
+    /// external key is set for third party blocks, and the scopes are the
+    /// ones trusted by default by the rules and checks of the block.
+    pub fn to_inspection_json(&self) -> Result<serde_json::Value, error::Token> {
+        inspection_json(
+            self.root_key_id(),
+            self.is_sealed(),
+            self.revocation_identifiers(),
+            |i, revocation_id| {
+                self.convert_block(i, |block, symbols| {
+                    BlockJson::new(i, block, symbols, revocation_id)
+                })
+            },
+        )
+    }
+}
+
+impl UnverifiedBiscuit {
+    /// returns the content of the token as JSON, without verifying its
+    /// signatures
+    ///
+    /// see [`Biscuit::to_inspection_json`] for the schema
+    pub fn to_inspection_json(&self) -> Result<serde_json::Value, error::Token> {
+        inspection_json(
+            self.root_key_id(),
+            self.is_sealed(),
+            self.revocation_identifiers(),
+            |i, revocation_id| {
+                self.convert_block(i, |block, symbols| {
+                    BlockJson::new(i, block, symbols, revocation_id)
+                })
+            },
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::builder::{BlockBuilder, Scope};
+    use crate::{Biscuit, KeyPair, UnverifiedBiscuit};
+
+    #[test]
+    fn inspection_json() {
+        let root = KeyPair::new();
+        let external = KeyPair::new();
+
+        let mut builder = Biscuit::builder();
+        builder.add_fact(r#"user("alice")"#).unwrap();
+        builder
+            .add_rule(
+                format!(
+                    "right($r) <- owner($r) trusting authority, {}",
+                    external.public()
+                )
+                .as_str(),
+            )
+            .unwrap();
+        builder.set_context("session".to_string());
+        builder.set_root_key_id(1);
+        let token = builder.build(&root).unwrap();
+
+        let mut block = BlockBuilder::new();
+        block.add_fact(r#"owner("file1")"#).unwrap();
+        block.add_check(r#"check if resource("file1")"#).unwrap();
+        block.add_scope(Scope::Previous);
+        let req = token.third_party_request().unwrap();
+        let res = req.create_block(&external.private(), block).unwrap();
+        let token = token
+            .append_third_party(external.public(), res)
+            .unwrap()
+            .seal()
+            .unwrap();
+
+        let ids: Vec<String> = token
+            .revocation_identifiers()
+            .iter()
+            .map(hex::encode)
+            .collect();
+        let json = token.to_inspection_json().unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "root_key_id": 1,
+                "sealed": true,
+                "blocks": [
+                    {
+                        "index": 0,
+                        "version": 4,
+                        "context": "session",
+                        "external_key": null,
+                        "revocation_id": ids[0],
+                        "facts": ["user(\"alice\")"],
+                        "rules": [format!("right($r) <- owner($r) trusting authority, {}", external.public())],
+                        "checks": [],
+                        "scopes": [],
+                    },
+                    {
+                        "index": 1,
+                        "version": 4,
+                        "context": null,
+                        "external_key": external.public().to_string(),
+                        "revocation_id": ids[1],
+                        "facts": ["owner(\"file1\")"],
+                        "rules": [],
+                        "checks": ["check if resource(\"file1\")"],
+                        "scopes": ["previous"],
+                    },
+                ]
+            })
+        );
+
+        let unverified = UnverifiedBiscuit::from(token.to_vec().unwrap()).unwrap();
+        assert_eq!(unverified.to_inspection_json().unwrap(), json);
+    }
+}
@@ -1,38 +1,103 @@
 //! main structures to interact with Biscuit tokens
 use std::collections::HashMap;
-use std::convert::TryInto;
+use std::convert::TryFrom;
 use std::fmt::Display;
+use std::io::Read;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
 use self::public_keys::PublicKeys;
 
-use super::crypto::{KeyPair, PublicKey};
-use super::datalog::SymbolTable;
+use super::crypto::{KeyPair, PublicKey, SecurityPolicy, Signer};
+use super::datalog::{
+    self, ApplicationSymbols, Binary, Op, SymbolIndex, SymbolReport, SymbolSource, SymbolTable,
+};
 use super::error;
-use super::format::SerializedBiscuit;
-use builder::{BiscuitBuilder, BlockBuilder};
+use super::format::{read_token, SerializedBiscuit, TokenSizeLimits};
+use builder::{BiscuitBuilder, BlockBuilder, CheckKind, Convert};
+use builder_ext::TemplateBlock;
+use bytes::Bytes;
 use prost::Message;
 use rand_core::{CryptoRng, RngCore};
 
 use crate::crypto::{self};
 use crate::format::convert::proto_block_to_token_block;
 use crate::format::schema::{self, ThirdPartyBlockContents};
+use crate::time::{Clock, SystemClock};
 use authorizer::Authorizer;
 
 pub mod authorizer;
 pub(crate) mod block;
 pub mod builder;
 pub mod builder_ext;
+#[cfg(feature = "serde")]
+mod builder_serde;
+#[cfg(feature = "json")]
+mod inspect;
 pub(crate) mod public_keys;
+pub(crate) mod revocation;
+mod sealed;
 pub(crate) mod third_party;
 pub mod unverified;
 
 pub use block::Block;
+#[cfg(feature = "revocation-file")]
+pub use revocation::FileRevocationStore;
+pub use revocation::{BloomRevocationChecker, MemoryRevocationStore, RevocationStore};
+pub use sealed::SealedBiscuit;
 pub use third_party::*;
 
 /// minimum supported version of the serialization format
 pub const MIN_SCHEMA_VERSION: u32 = 3;
+/// maximum supported version of the serialization format defined by the
+/// specification
+pub const MAX_SPEC_SCHEMA_VERSION: u32 = 4;
+/// version of the blocks using the first extensions of the serialization
+/// format by this library: set difference and inclusion, and decimal numbers
+///
+/// the extensions use versions, field numbers and operator numbers from 1000,
+/// out of the ranges of the specification, so that other implementations
+/// refuse these blocks instead of reading them with another meaning
+pub const EXTENSION_SCHEMA_VERSION_1: u32 = 1001;
+/// version of the blocks using aggregates, `reject if` checks, UUIDs, string
+/// transformations or application symbols
+pub const EXTENSION_SCHEMA_VERSION_2: u32 = 1002;
 /// maximum supported version of the serialization format
-pub const MAX_SCHEMA_VERSION: u32 = 4;
+pub const MAX_SCHEMA_VERSION: u32 = EXTENSION_SCHEMA_VERSION_2;
+
+/// returns `true` if blocks with this version of the serialization format can be read
+pub(crate) fn is_supported_schema_version(version: u32) -> bool {
+    (MIN_SCHEMA_VERSION..=MAX_SPEC_SCHEMA_VERSION).contains(&version)
+        || (EXTENSION_SCHEMA_VERSION_1..=MAX_SCHEMA_VERSION).contains(&version)
+}
+
+/// fails if blocks with this version of the serialization format cannot be read
+///
+/// the versions of the specification and of the extensions by this library
+/// are reported with their own ranges
+pub(crate) fn check_schema_version(version: u32) -> Result<(), error::Format> {
+    if is_supported_schema_version(version) {
+        Ok(())
+    } else {
+        Err(unsupported_schema_version(MIN_SCHEMA_VERSION, version))
+    }
+}
+
+/// error for a version of the serialization format that cannot be read, from `minimum`
+pub(crate) fn unsupported_schema_version(minimum: u32, actual: u32) -> error::Format {
+    if actual >= 1000 {
+        error::Format::ExtensionVersion {
+            minimum: EXTENSION_SCHEMA_VERSION_1,
+            maximum: MAX_SCHEMA_VERSION,
+            actual,
+        }
+    } else {
+        error::Format::Version {
+            minimum,
+            maximum: MAX_SPEC_SCHEMA_VERSION,
+            actual,
+        }
+    }
+}
 
 /// some symbols are predefined and available in every implementation, to avoid
 /// transmitting them with every token
@@ -40,6 +105,47 @@ pub fn default_symbol_table() -> SymbolTable {
     SymbolTable::new()
 }
 
+/// attestation that a verifier verified a sealed token, see [`Biscuit::counter_sign`]
+#[derive(Clone, Debug, PartialEq)]
+pub struct CounterSignature {
+    /// public key of the verifier
+    pub public_key: PublicKey,
+    /// verification time
+    pub time: SystemTime,
+}
+
+/// proof that the holder of a token has the private key of its last block,
+/// see [`Biscuit::prove_possession`]
+///
+/// the proof is much smaller than the token: a service that already verified
+/// the token can store its last revocation identifier and public key, then
+/// authenticate the holder by sending a new challenge for each request
+#[derive(Clone, Debug, PartialEq)]
+pub struct PossessionProof {
+    /// revocation identifier of the token's last block
+    pub revocation_id: Vec<u8>,
+    /// public key of the token's last block
+    pub public_key: PublicKey,
+    /// signature of the challenge
+    pub signature: crypto::Signature,
+}
+
+impl PossessionProof {
+    /// verifies the signature of the challenge
+    ///
+    /// this does not check which token the proof was made for: the revocation
+    /// identifier and public key must be compared with the ones of the
+    /// expected token, or the proof verified with [`Biscuit::verify_possession`]
+    pub fn verify(&self, challenge: &[u8]) -> Result<(), error::Format> {
+        crypto::verify_possession_proof(
+            &self.public_key,
+            &crypto::Signature::from_vec(self.revocation_id.clone()),
+            challenge,
+            &self.signature,
+        )
+    }
+}
+
 /// This structure represents a valid Biscuit token
 ///
 /// It contains multiple `Block` elements, the associated symbol table,
@@ -79,6 +185,7 @@ pub struct Biscuit {
     pub(crate) symbols: SymbolTable,
     pub(crate) container: SerializedBiscuit,
     pub(crate) public_key_to_block_id: HashMap<usize, Vec<usize>>,
+    pub(crate) verified_root_key: Option<VerifiedRootKey>,
 }
 
 impl Biscuit {
@@ -90,12 +197,237 @@ impl Biscuit {
     }
 
     /// deserializes a token and validates the signature using the root public key
+    /// chosen by `key_provider` from the token's root key id
+    ///
+    /// A `HashMap<u32, PublicKey>` can be used as provider, to accept tokens signed
+    /// by all the root keys of a rotation:
+    ///
+    /// ```rust
+    /// # use biscuit_auth::{Biscuit, KeyPair};
+    /// # use std::collections::HashMap;
+    /// let (old_root, new_root) = (KeyPair::new(), KeyPair::new());
+    /// let mut builder = Biscuit::builder();
+    /// builder.set_root_key_id(1);
+    /// let token = builder.build(&new_root).unwrap().to_vec().unwrap();
+    ///
+    /// let mut root_keys = HashMap::new();
+    /// root_keys.insert(0, old_root.public());
+    /// root_keys.insert(1, new_root.public());
+    /// assert!(Biscuit::from(&token, &root_keys).is_ok());
+    /// ```
     pub fn from<T, KP>(slice: T, key_provider: KP) -> Result<Self, error::Token>
     where
         T: AsRef<[u8]>,
         KP: RootKeyProvider,
     {
-        Biscuit::from_with_symbols(slice.as_ref(), key_provider, default_symbol_table())
+        Biscuit::from_with_symbols(slice.as_ref(), key_provider, default_symbol_table(), &[])
+    }
+
+    /// deserializes a token and validates the signature using the root public key,
+    /// for tokens built with [`ApplicationSymbols`]
+    ///
+    /// the table is chosen by the id stored in the token, so `application_symbols`
+    /// can contain all the versions of the table. Tokens without application
+    /// symbols are accepted too
+    pub fn from_with_application_symbols<T, KP>(
+        slice: T,
+        key_provider: KP,
+        application_symbols: &[ApplicationSymbols],
+    ) -> Result<Self, error::Token>
+    where
+        T: AsRef<[u8]>,
+        KP: RootKeyProvider,
+    {
+        Biscuit::from_with_symbols(
+            slice.as_ref(),
+            key_provider,
+            default_symbol_table(),
+            application_symbols,
+        )
+    }
+
+    /// deserializes a token and validates the signature using the root public key,
+    /// with the hardening options of `policy`, see [`SecurityPolicy`]
+    pub fn from_with_security_policy<T, KP>(
+        slice: T,
+        key_provider: KP,
+        policy: &SecurityPolicy,
+    ) -> Result<Self, error::Token>
+    where
+        T: AsRef<[u8]>,
+        KP: RootKeyProvider,
+    {
+        let container =
+            SerializedBiscuit::deserialize(slice.as_ref()).map_err(error::Token::Format)?;
+        let root = key_provider.choose(container.root_key_id)?;
+        container.verify_with_policy(&root, policy)?;
+
+        Biscuit::from_serialized_container(container, default_symbol_table(), &[])
+    }
+
+    /// deserializes a token and validates the signature with one of `root_keys`,
+    /// for staged root key rotations
+    ///
+    /// the token's root key id, if any, is used as an index in `root_keys`: that
+    /// key is tried first, then the other keys in order. The key that verified the
+    /// token is returned by [`Biscuit::verified_root_key`], and the authorizer
+    /// created from the token gets a `biscuit::root_key($index, $public_key)` fact,
+    /// with the key's position and its text representation, so that policies can
+    /// treat the keys differently. The fact belongs to the authorizer: it is
+    /// visible to the authorizer's rules, checks and policies. The
+    /// `biscuit::root_key` predicate is reserved: the authorizer refuses tokens
+    /// whose blocks define it with [`error::Logic::ReservedPredicate`]. Fails with
+    /// the last signature error if no key verifies the token, or with
+    /// [`error::Format::UnknownPublicKey`] if `root_keys` is empty.
+    ///
+    /// ```rust
+    /// # use biscuit_auth::{Biscuit, KeyPair};
+    /// let (old_root, new_root) = (KeyPair::new(), KeyPair::new());
+    /// let token = Biscuit::builder().build(&old_root).unwrap().to_vec().unwrap();
+    ///
+    /// let token = Biscuit::from_with_keys(&token, &[new_root.public(), old_root.public()]).unwrap();
+    /// assert_eq!(token.verified_root_key().unwrap().index, 1);
+    ///
+    /// let mut authorizer = token.authorizer().unwrap();
+    /// authorizer.add_code("deny if biscuit::root_key(1, $key); allow if true").unwrap();
+    /// assert!(authorizer.authorize().is_err());
+    /// ```
+    pub fn from_with_keys<T>(slice: T, root_keys: &[PublicKey]) -> Result<Self, error::Token>
+    where
+        T: AsRef<[u8]>,
+    {
+        let container =
+            SerializedBiscuit::deserialize(slice.as_ref()).map_err(error::Token::Format)?;
+
+        let hint = container
+            .root_key_id
+            .map(|id| id as usize)
+            .filter(|id| *id < root_keys.len());
+        let candidates = hint
+            .into_iter()
+            .chain((0..root_keys.len()).filter(|index| Some(*index) != hint));
+
+        let mut error = error::Format::UnknownPublicKey;
+        for index in candidates {
+            match container.verify(&root_keys[index]) {
+                Ok(()) => {
+                    let mut token =
+                        Biscuit::from_serialized_container(container, default_symbol_table(), &[])?;
+                    token.verified_root_key = Some(VerifiedRootKey {
+                        index,
+                        public_key: root_keys[index],
+                    });
+                    return Ok(token);
+                }
+                Err(e) => error = e,
+            }
+        }
+
+        Err(error::Token::Format(error))
+    }
+
+    /// deserializes a token and validates the signature using the root public key,
+    /// then checks the positions of third party blocks against `restrictions`
+    ///
+    /// returns [`error::Format::InvalidBlockId`] for a third party block at a forbidden
+    /// position, and [`error::Format::UnknownExternalKey`] for a block signed by a
+    /// key refused by the restrictions
+    pub fn from_with_restrictions<T, KP>(
+        slice: T,
+        key_provider: KP,
+        restrictions: &ThirdPartyRestrictions,
+    ) -> Result<Self, error::Token>
+    where
+        T: AsRef<[u8]>,
+        KP: RootKeyProvider,
+    {
+        let token = Biscuit::from(slice, key_provider)?;
+        restrictions
+            .check(&token.public_key_to_block_id())
+            .map_err(error::Token::Format)?;
+        Ok(token)
+    }
+
+    /// deserializes a token and validates the signature using the root public key,
+    /// then rejects the token with [`error::Token::Revoked`] if one of its
+    /// revocation identifiers is listed in `store`
+    pub fn from_with_revocation<T, KP, S>(
+        slice: T,
+        key_provider: KP,
+        store: &S,
+    ) -> Result<Self, error::Token>
+    where
+        T: AsRef<[u8]>,
+        KP: RootKeyProvider,
+        S: RevocationStore + ?Sized,
+    {
+        let token = Biscuit::from(slice, key_provider)?;
+        let revoked = store.revoked(&token.revocation_identifiers());
+        if !revoked.is_empty() {
+            return Err(error::Token::Revoked {
+                block_ids: revoked.into_iter().map(|i| i as u32).collect(),
+            });
+        }
+        Ok(token)
+    }
+
+    /// deserializes a token and validates the signature using the root public key,
+    /// without storing copies of the blocks
+    ///
+    /// the serialized blocks kept in the token reference `bytes` instead of copying
+    /// it, so large blocks, like blocks carrying a big context, are not stored
+    /// twice. Verifying the signatures still copies each block in a temporary
+    /// buffer. A `Vec<u8>` is converted to [`Bytes`] without copying it:
+    ///
+    /// ```rust
+    /// # use biscuit_auth::{Biscuit, KeyPair};
+    /// # let root = KeyPair::new();
+    /// # let serialized = Biscuit::builder().build(&root).unwrap().to_vec().unwrap();
+    /// let token = Biscuit::from_bytes(serialized.into(), root.public()).unwrap();
+    /// ```
+    pub fn from_bytes<KP>(bytes: Bytes, key_provider: KP) -> Result<Self, error::Token>
+    where
+        KP: RootKeyProvider,
+    {
+        let container =
+            SerializedBiscuit::from_bytes(bytes, key_provider).map_err(error::Token::Format)?;
+
+        Biscuit::from_serialized_container(container, default_symbol_table(), &[])
+    }
+
+    /// reads a token from `reader` and validates the signature using the root public key
+    ///
+    /// the token is read incrementally, and refused as soon as it exceeds the
+    /// default [`TokenSizeLimits`]: a client sending a huge token cannot make
+    /// the service allocate it. The reader is read until its end, and should
+    /// be buffered, like a [`std::io::BufReader`]
+    ///
+    /// ```rust
+    /// # use biscuit_auth::{Biscuit, KeyPair};
+    /// # let root = KeyPair::new();
+    /// # let serialized = Biscuit::builder().build(&root).unwrap().to_vec().unwrap();
+    /// let token = Biscuit::from_reader(&serialized[..], root.public()).unwrap();
+    /// ```
+    pub fn from_reader<R, KP>(reader: R, key_provider: KP) -> Result<Self, error::Token>
+    where
+        R: Read,
+        KP: RootKeyProvider,
+    {
+        Biscuit::from_reader_with_limits(reader, key_provider, &TokenSizeLimits::default())
+    }
+
+    /// reads a token from `reader` like [`Biscuit::from_reader`], with custom size limits
+    pub fn from_reader_with_limits<R, KP>(
+        reader: R,
+        key_provider: KP,
+        limits: &TokenSizeLimits,
+    ) -> Result<Self, error::Token>
+    where
+        R: Read,
+        KP: RootKeyProvider,
+    {
+        let data = read_token(reader, limits)?;
+        Biscuit::from_bytes(data.into(), key_provider)
     }
 
     /// deserializes a token and validates the signature using the root public key
@@ -137,6 +469,99 @@ impl Biscuit {
         Ok(token)
     }
 
+    /// seals the token, returning a type that cannot be attenuated
+    ///
+    /// unlike [`Biscuit::seal`], appending blocks to the result is rejected at
+    /// compile time. Tokens that are already sealed are returned unchanged
+    pub fn into_sealed(self) -> Result<SealedBiscuit, error::Token> {
+        let token = if self.is_sealed() { self } else { self.seal()? };
+
+        SealedBiscuit::try_from(token)
+    }
+
+    /// returns true if the token is sealed and cannot be attenuated
+    pub fn is_sealed(&self) -> bool {
+        self.container.proof.is_sealed()
+    }
+
+    /// adds a counter signature to a sealed token, attesting that it was verified
+    /// at `time` by the holder of `keypair`
+    ///
+    /// counter signatures are not part of the token's signature chain: the token
+    /// stays valid for other verifiers, which can ignore them. Downstream systems
+    /// can check them with [`Biscuit::counter_signatures`] to know that an upstream
+    /// service already verified the token
+    pub fn counter_sign(
+        &self,
+        keypair: &KeyPair,
+        time: SystemTime,
+    ) -> Result<Biscuit, error::Token> {
+        let container = self
+            .container
+            .counter_sign(keypair, unix_timestamp(&time))?;
+
+        let mut token = self.clone();
+        token.container = container;
+
+        Ok(token)
+    }
+
+    /// returns the counter signatures added by verifiers with [`Biscuit::counter_sign`]
+    ///
+    /// counter signatures with an invalid signature are ignored. Anybody can add a
+    /// counter signature, so the public key must be compared with the expected
+    /// verifier's key
+    pub fn counter_signatures(&self) -> Vec<CounterSignature> {
+        counter_signatures(&self.container)
+    }
+
+    /// signs a challenge with the private key of the token's last block, to
+    /// prove possession of the token without sending it
+    ///
+    /// the challenge should be a random nonce generated by the service for
+    /// each request, to prevent replays. Sealed tokens cannot make proofs,
+    /// since they do not carry that private key anymore.
+    ///
+    /// ```rust
+    /// use biscuit_auth::{Biscuit, KeyPair};
+    ///
+    /// let root = KeyPair::new();
+    /// let mut builder = Biscuit::builder();
+    /// builder.add_fact(r#"user("alice")"#).unwrap();
+    /// let token = builder.build(&root).unwrap();
+    ///
+    /// let proof = token.prove_possession(b"server nonce").unwrap();
+    /// assert!(token.verify_possession(b"server nonce", &proof).is_ok());
+    /// assert!(token.verify_possession(b"other nonce", &proof).is_err());
+    /// ```
+    pub fn prove_possession(&self, challenge: &[u8]) -> Result<PossessionProof, error::Token> {
+        let (public_key, signature) = self.container.prove_possession(challenge)?;
+
+        Ok(PossessionProof {
+            revocation_id: self.container.last_block().signature.to_bytes().to_vec(),
+            public_key,
+            signature,
+        })
+    }
+
+    /// verifies that a possession proof was made for this token with this challenge
+    pub fn verify_possession(
+        &self,
+        challenge: &[u8],
+        proof: &PossessionProof,
+    ) -> Result<(), error::Format> {
+        if self.container.last_block().signature.to_bytes() != proof.revocation_id.as_slice() {
+            return Err(error::Format::Signature(
+                error::Signature::InvalidSignature(
+                    "the possession proof was made for another token".to_string(),
+                ),
+            ));
+        }
+
+        self.container
+            .verify_possession(challenge, &proof.signature)
+    }
+
     /// creates a authorizer from this token
     pub fn authorizer(&self) -> Result<Authorizer, error::Token> {
         Authorizer::from_token(self)
@@ -154,10 +579,106 @@ impl Biscuit {
     /// since the public key is integrated into the token, the keypair can be
     /// discarded right after calling this function
     pub fn append(&self, block_builder: BlockBuilder) -> Result<Self, error::Token> {
-        let keypair = KeyPair::new_with_rng(&mut rand::rngs::OsRng);
+        self.append_with_rng(block_builder, &mut rand::rngs::OsRng)
+    }
+
+    /// adds a new block to the token, generating the next key pair with `rng`
+    ///
+    /// with a seeded RNG, and [`BiscuitBuilder::build_with_rng`] for the authority
+    /// block, the serialized token is reproducible, for snapshot tests or to
+    /// compare with other implementations. Use [`Biscuit::append`] otherwise
+    pub fn append_with_rng<R: RngCore + CryptoRng>(
+        &self,
+        block_builder: BlockBuilder,
+        rng: &mut R,
+    ) -> Result<Self, error::Token> {
+        let keypair = KeyPair::new_with_algorithm_and_rng(self.container.next_key_algorithm(), rng);
         self.append_with_keypair(&keypair, block_builder)
     }
 
+    /// creates a delegated token, restricted to a subset of the resources and
+    /// operations, that expires at `expiration`
+    ///
+    /// the new block checks that the `resource` and `operation` facts provided
+    /// by the authorizer are part of the lists (so an empty list allows nothing),
+    /// and that the `time` fact is before `expiration`.
+    ///
+    /// returns an error if the token already expires before `expiration`,
+    /// since the delegated token would not be usable for that long
+    pub fn delegate(
+        &self,
+        resources: &[&str],
+        operations: &[&str],
+        expiration: SystemTime,
+    ) -> Result<Self, error::Token> {
+        self.attenuate_with(
+            &TemplateBlock::new()
+                .resources(resources)
+                .operations(operations)
+                .expiration(expiration),
+        )
+    }
+
+    /// adds a block restricting the token with the checks described by `template`
+    ///
+    /// like [`Biscuit::delegate`], this returns an error if the template's
+    /// expiration date is after the token's current expiration
+    pub fn attenuate_with(&self, template: &TemplateBlock) -> Result<Self, error::Token> {
+        if let Some(expiration) = &template.expiration {
+            let requested = unix_timestamp(expiration);
+            builder::checked_date(expiration)?;
+            if let Some(current) = self.expiration()? {
+                let current = unix_timestamp(&current);
+                if requested > current {
+                    return Err(error::Token::ExpirationExtended { current, requested });
+                }
+            }
+        }
+
+        self.append(template.to_block_builder()?)
+    }
+
+    /// binds the token to a challenge for `ttl`, by appending a block checking for it
+    ///
+    /// the challenge is typically a nonce generated by a server for a session or a
+    /// connection: the new token is only accepted by authorizers providing the same
+    /// challenge with [`Authorizer::add_challenge`], until `ttl` has elapsed.
+    /// See [`BlockBuilder::check_challenge`]
+    pub fn bind_challenge(&self, challenge: &[u8], ttl: Duration) -> Result<Self, error::Token> {
+        self.bind_challenge_with_clock(challenge, ttl, &SystemClock)
+    }
+
+    /// binds the token to a challenge for `ttl`, starting from the time given by `clock`
+    ///
+    /// see [`Biscuit::bind_challenge`]
+    pub fn bind_challenge_with_clock(
+        &self,
+        challenge: &[u8],
+        ttl: Duration,
+        clock: &dyn Clock,
+    ) -> Result<Self, error::Token> {
+        let expiration = clock
+            .now()
+            .checked_add(ttl)
+            .ok_or_else(|| error::Token::ConversionError("invalid challenge TTL".to_string()))?;
+
+        let mut block = BlockBuilder::new();
+        block.check_challenge(challenge, expiration)?;
+
+        self.append(block)
+    }
+
+    /// returns the earliest expiration date set by the token's blocks
+    ///
+    /// this looks for checks of the form `check if time($time), $time <= <date>`,
+    /// as generated by [`BuilderExt::check_expiration_date`](crate::builder_ext::BuilderExt::check_expiration_date)
+    pub fn expiration(&self) -> Result<Option<SystemTime>, error::Token> {
+        expiration(
+            (0..self.block_count()).map(|i| self.block(i)),
+            &self.symbols,
+        )
+    }
+
     /// returns the list of context elements of each block
     ///
     /// the context is a free form text field in which application specific data
@@ -172,11 +693,25 @@ impl Biscuit {
         res
     }
 
+    /// returns the context of a block
+    ///
+    /// block ids start at 0 for the authority block, an unknown block id
+    /// returns [`error::Format::InvalidBlockId`]
+    pub fn block_context(&self, index: usize) -> Result<Option<String>, error::Token> {
+        schema_block(&self.authority, &self.blocks, index).map(|block| block.context.clone())
+    }
+
     /// returns an (optional) root key identifier. It provides a hint for public key selection during verification
     pub fn root_key_id(&self) -> Option<u32> {
         self.root_key_id
     }
 
+    /// returns the root key that verified the token, if it was deserialized
+    /// with [`Biscuit::from_with_keys`]
+    pub fn verified_root_key(&self) -> Option<VerifiedRootKey> {
+        self.verified_root_key
+    }
+
     /// returns a list of revocation identifiers for each block, in order
     ///
     /// revocation identifiers are unique: tokens generated separately with
@@ -206,12 +741,27 @@ impl Biscuit {
         res
     }
 
+    /// returns the ids of the blocks signed by each third party public key
+    ///
+    /// block ids start at 0 for the authority block
+    pub fn public_key_to_block_id(&self) -> HashMap<PublicKey, Vec<usize>> {
+        let mut res: HashMap<PublicKey, Vec<usize>> = HashMap::new();
+        for (block_id, key) in self.external_public_keys().into_iter().enumerate() {
+            if let Some(key) = key {
+                res.entry(key).or_default().push(block_id);
+            }
+        }
+        res
+    }
+
     /// pretty printer for this token
     pub fn print(&self) -> String {
         format!("{}", &self)
     }
 
     /// prints the content of a block as Datalog source code
+    ///
+    /// the context of the block, if any, is printed first as a comment
     pub fn print_block_source(&self, index: usize) -> Result<String, error::Token> {
         self.block(index).map(|block| {
             let symbols = if block.external_key.is_some() {
@@ -223,13 +773,71 @@ impl Biscuit {
         })
     }
 
+    /// returns the facts of a block
+    pub fn block_facts(&self, index: usize) -> Result<Vec<builder::Fact>, error::Token> {
+        self.convert_block(index, |block, symbols| {
+            block
+                .facts
+                .iter()
+                .map(|f| builder::Fact::convert_from(f, symbols))
+                .collect()
+        })
+    }
+
+    /// returns the rules of a block
+    pub fn block_rules(&self, index: usize) -> Result<Vec<builder::Rule>, error::Token> {
+        self.convert_block(index, |block, symbols| {
+            block
+                .rules
+                .iter()
+                .map(|r| builder::Rule::convert_from(r, symbols))
+                .collect()
+        })
+    }
+
+    /// returns the checks of a block
+    pub fn block_checks(&self, index: usize) -> Result<Vec<builder::Check>, error::Token> {
+        self.convert_block(index, |block, symbols| {
+            block
+                .checks
+                .iter()
+                .map(|c| builder::Check::convert_from(c, symbols))
+                .collect()
+        })
+    }
+
+    /// returns the scopes trusted by default by the rules and checks of a block
+    pub fn block_scopes(&self, index: usize) -> Result<Vec<builder::Scope>, error::Token> {
+        self.convert_block(index, |block, symbols| {
+            block
+                .scopes
+                .iter()
+                .map(|s| builder::Scope::convert_from(s, symbols))
+                .collect()
+        })
+    }
+
+    /// converts the content of a block, with the symbols it was written with
+    pub(crate) fn convert_block<T, F>(&self, index: usize, convert: F) -> Result<T, error::Token>
+    where
+        F: FnOnce(&Block, &SymbolTable) -> Result<T, error::Format>,
+    {
+        let block = self.block(index)?;
+        let symbols = if block.external_key.is_some() {
+            &block.symbols
+        } else {
+            &self.symbols
+        };
+        convert(&block, symbols).map_err(error::Token::Format)
+    }
+
     /// creates a new token, using a provided CSPRNG
     ///
     /// the public part of the root keypair must be used for verification
-    pub(crate) fn new_with_rng<T: RngCore + CryptoRng>(
+    pub(crate) fn new_with_rng<T: RngCore + CryptoRng, S: Signer + ?Sized>(
         rng: &mut T,
         root_key_id: Option<u32>,
-        root: &KeyPair,
+        root: &S,
         mut symbols: SymbolTable,
         authority: Block,
     ) -> Result<Biscuit, error::Token> {
@@ -241,7 +849,7 @@ impl Biscuit {
 
         let blocks = vec![];
 
-        let next_keypair = KeyPair::new_with_rng(rng);
+        let next_keypair = KeyPair::new_with_algorithm_and_rng(root.public().algorithm(), rng);
         let container = SerializedBiscuit::new(root_key_id, root, &next_keypair, &authority)?;
 
         symbols.public_keys.extend(&authority.public_keys)?;
@@ -260,6 +868,7 @@ impl Biscuit {
             symbols,
             container,
             public_key_to_block_id: HashMap::new(),
+            verified_root_key: None,
         })
     }
 
@@ -268,6 +877,7 @@ impl Biscuit {
         slice: &[u8],
         key_provider: KP,
         symbols: SymbolTable,
+        application_symbols: &[ApplicationSymbols],
     ) -> Result<Self, error::Token>
     where
         KP: RootKeyProvider,
@@ -275,14 +885,16 @@ impl Biscuit {
         let container =
             SerializedBiscuit::from_slice(slice, key_provider).map_err(error::Token::Format)?;
 
-        Biscuit::from_serialized_container(container, symbols)
+        Biscuit::from_serialized_container(container, symbols, application_symbols)
     }
 
     fn from_serialized_container(
         container: SerializedBiscuit,
         mut symbols: SymbolTable,
+        application_symbols: &[ApplicationSymbols],
     ) -> Result<Self, error::Token> {
-        let (authority, blocks, public_key_to_block_id) = container.extract_blocks(&mut symbols)?;
+        let (authority, blocks, public_key_to_block_id) =
+            container.extract_blocks(&mut symbols, application_symbols)?;
 
         let root_key_id = container.root_key_id;
 
@@ -293,6 +905,7 @@ impl Biscuit {
             symbols,
             container,
             public_key_to_block_id,
+            verified_root_key: None,
         })
     }
 
@@ -307,7 +920,7 @@ impl Biscuit {
         KP: RootKeyProvider,
     {
         let decoded = base64::decode_config(slice, base64::URL_SAFE)?;
-        Biscuit::from_with_symbols(&decoded, key_provider, symbols)
+        Biscuit::from_with_symbols(&decoded, key_provider, symbols, &[])
     }
 
     /// returns the internal representation of the token
@@ -315,6 +928,33 @@ impl Biscuit {
         &self.container
     }
 
+    /// creates a token from the Datalog code of its authority block, then of
+    /// each attenuation block, with block keys generated from `rng`
+    ///
+    /// shared by the fixtures of the `harness`, `testing` and `bench` modules
+    #[cfg(any(feature = "harness", feature = "testing", feature = "bench"))]
+    pub(crate) fn from_blocks_code<R: RngCore + CryptoRng, C: AsRef<str>>(
+        root: &KeyPair,
+        blocks: &[C],
+        rng: &mut R,
+    ) -> Result<Self, error::Token> {
+        let mut blocks = blocks.iter();
+
+        let mut builder = BiscuitBuilder::new();
+        if let Some(code) = blocks.next() {
+            builder.add_code(code.as_ref())?;
+        }
+        let mut token = builder.build_with_rng(root, default_symbol_table(), rng)?;
+
+        for code in blocks {
+            let mut block = BlockBuilder::new();
+            block.add_code(code.as_ref())?;
+            token = token.append_with_keypair(&KeyPair::new_with_rng(rng), block)?;
+        }
+
+        Ok(token)
+    }
+
     /// adds a new block to the token, using the provided CSPRNG
     ///
     /// since the public key is integrated into the token, the keypair can be
@@ -372,6 +1012,7 @@ impl Biscuit {
             symbols,
             container,
             public_key_to_block_id,
+            verified_root_key: self.verified_root_key,
         })
     }
 
@@ -384,10 +1025,22 @@ impl Biscuit {
         external_key: PublicKey,
         response: ThirdPartyBlock,
     ) -> Result<Self, error::Token> {
-        let next_keypair = KeyPair::new_with_rng(&mut rand::rngs::OsRng);
+        self.append_third_party_with_rng(external_key, response, &mut rand::rngs::OsRng)
+    }
+
+    /// adds a third party block to the token, generating the next key pair with `rng`
+    pub fn append_third_party_with_rng<R: RngCore + CryptoRng>(
+        &self,
+        external_key: PublicKey,
+        response: ThirdPartyBlock,
+        rng: &mut R,
+    ) -> Result<Self, error::Token> {
+        let next_keypair =
+            KeyPair::new_with_algorithm_and_rng(self.container.next_key_algorithm(), rng);
 
         self.append_third_party_with_keypair(external_key, response, next_keypair)
     }
+
     pub fn append_third_party_with_keypair(
         &self,
         external_key: PublicKey,
@@ -397,10 +1050,10 @@ impl Biscuit {
         let ThirdPartyBlockContents {
             payload,
             external_signature,
+            ..
         } = response.0;
 
-        if external_signature.public_key.algorithm != schema::public_key::Algorithm::Ed25519 as i32
-        {
+        if external_signature.public_key.algorithm != external_key.algorithm().to_proto() as i32 {
             return Err(error::Token::Format(error::Format::DeserializationError(
                 format!(
                     "deserialization error: unexpected key algorithm {}",
@@ -408,28 +1061,18 @@ impl Biscuit {
                 ),
             )));
         }
-        let bytes: [u8; 64] = (&external_signature.signature[..])
-            .try_into()
-            .map_err(|_| error::Format::InvalidSignatureSize(external_signature.signature.len()))?;
 
-        let signature = ed25519_dalek::Signature::from_bytes(&bytes);
-        let previous_key = self
+        let signature = crypto::Signature::from_vec(external_signature.signature);
+        let previous_key = &self
             .container
             .blocks
             .last()
             .unwrap_or(&self.container.authority)
             .next_key;
         let mut to_verify = payload.clone();
-        to_verify
-            .extend(&(crate::format::schema::public_key::Algorithm::Ed25519 as i32).to_le_bytes());
-        to_verify.extend(&previous_key.to_bytes());
+        previous_key.write_signed_payload(&mut to_verify);
 
-        external_key
-            .0
-            .verify_strict(&to_verify, &signature)
-            .map_err(|s| s.to_string())
-            .map_err(error::Signature::InvalidSignature)
-            .map_err(error::Format::Signature)?;
+        external_key.verify_signature(&to_verify, &signature)?;
 
         let block = schema::Block::decode(&payload[..]).map_err(|e| {
             error::Token::Format(error::Format::DeserializationError(format!(
@@ -476,6 +1119,7 @@ impl Biscuit {
             symbols,
             container,
             public_key_to_block_id,
+            verified_root_key: self.verified_root_key,
         })
     }
 
@@ -493,23 +1137,17 @@ impl Biscuit {
         Ok(block.symbols.clone())
     }
 
+    /// lists the strings that are defined in more than one of the blocks'
+    /// symbol tables, or that are already part of the default symbols
+    ///
+    /// this can be used to choose predicate and string names that reduce the token's size
+    pub fn symbol_report(&self) -> SymbolReport {
+        symbol_report(&self.authority, &self.blocks)
+    }
+
     /// gets the list of public keys from a block
     pub fn block_public_keys(&self, index: usize) -> Result<PublicKeys, error::Token> {
-        let block = if index == 0 {
-            &self.authority
-        } else {
-            match self.blocks.get(index - 1) {
-                None => return Err(error::Token::Format(error::Format::InvalidBlockId(index))),
-                Some(block) => block,
-            }
-        };
-
-        let mut public_keys = PublicKeys::new();
-
-        for pk in &block.public_keys {
-            public_keys.insert(&PublicKey::from_proto(pk)?);
-        }
-        Ok(public_keys)
+        block_public_keys(schema_block(&self.authority, &self.blocks, index)?)
     }
 
     /// gets the list of public keys from a block
@@ -546,7 +1184,7 @@ impl Biscuit {
             )
             .map_err(error::Token::Format)?
         } else {
-            if index > self.blocks.len() + 1 {
+            if index > self.blocks.len() {
                 return Err(error::Token::Format(
                     error::Format::BlockDeserializationError("invalid block index".to_string()),
                 ));
@@ -593,16 +1231,187 @@ impl Display for Biscuit {
     )
     }
 }
-fn print_block(symbols: &SymbolTable, block: &Block) -> String {
-    let facts: Vec<_> = block.facts.iter().map(|f| symbols.print_fact(f)).collect();
-    let rules: Vec<_> = block.rules.iter().map(|r| symbols.print_rule(r)).collect();
-    let checks: Vec<_> = block
-        .checks
-        .iter()
-        .map(|r| symbols.print_check(r))
-        .collect();
+fn unix_timestamp(date: &SystemTime) -> u64 {
+    date.duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
 
-    let facts = if facts.is_empty() {
+/// converts the valid counter signatures of a token
+///
+/// the timestamp is chosen by the verifier: counter signatures with a time that
+/// cannot be represented as a `SystemTime` are ignored
+fn counter_signatures(container: &SerializedBiscuit) -> Vec<CounterSignature> {
+    container
+        .verified_counter_signatures()
+        .into_iter()
+        .filter_map(|c| {
+            UNIX_EPOCH
+                .checked_add(Duration::from_secs(c.timestamp))
+                .map(|time| CounterSignature {
+                    public_key: c.public_key,
+                    time,
+                })
+        })
+        .collect()
+}
+
+/// recognizes checks generated by `check_expiration_date`, and returns their date
+fn expiration_date(check: &datalog::Check, time: SymbolIndex) -> Option<u64> {
+    if check.kind != CheckKind::One || check.queries.len() != 1 {
+        return None;
+    }
+
+    let query = &check.queries[0];
+    if query.body.len() != 1 || query.expressions.len() != 1 || query.body[0].name != time {
+        return None;
+    }
+
+    let var = match query.body[0].terms.as_slice() {
+        [datalog::Term::Variable(var)] => *var,
+        _ => return None,
+    };
+
+    match query.expressions[0].ops.as_slice() {
+        [Op::Value(datalog::Term::Variable(v)), Op::Value(datalog::Term::Date(date)), Op::Binary(Binary::LessOrEqual | Binary::LessThan)]
+            if *v == var =>
+        {
+            Some(*date)
+        }
+        _ => None,
+    }
+}
+
+/// earliest date of the checks generated by `check_expiration_date`, in the
+/// blocks of a token
+fn expiration<I>(blocks: I, symbols: &SymbolTable) -> Result<Option<SystemTime>, error::Token>
+where
+    I: Iterator<Item = Result<Block, error::Token>>,
+{
+    let mut expiration: Option<u64> = None;
+
+    for block in blocks {
+        let block = block?;
+        let symbols = if block.external_key.is_some() {
+            &block.symbols
+        } else {
+            symbols
+        };
+
+        let time = match symbols.get("time") {
+            Some(time) => time,
+            None => continue,
+        };
+
+        for date in block
+            .checks
+            .iter()
+            .filter_map(|check| expiration_date(check, time))
+        {
+            expiration = Some(expiration.map_or(date, |current| current.min(date)));
+        }
+    }
+
+    expiration
+        .map(|date| SystemTime::try_from(builder::Term::Date(date)))
+        .transpose()
+}
+
+/// serialized block at an index, starting at 0 for the authority block
+fn schema_block<'a>(
+    authority: &'a schema::Block,
+    blocks: &'a [schema::Block],
+    index: usize,
+) -> Result<&'a schema::Block, error::Token> {
+    if index == 0 {
+        Ok(authority)
+    } else {
+        blocks
+            .get(index - 1)
+            .ok_or(error::Token::Format(error::Format::InvalidBlockId(index)))
+    }
+}
+
+fn symbol_report(authority: &schema::Block, blocks: &[schema::Block]) -> SymbolReport {
+    let mut report = SymbolReport::new();
+    report.add(SymbolSource::Block(0), &authority.symbols);
+    for (i, block) in blocks.iter().enumerate() {
+        report.add(SymbolSource::Block(i + 1), &block.symbols);
+    }
+
+    report
+}
+
+fn block_public_keys(block: &schema::Block) -> Result<PublicKeys, error::Token> {
+    let mut public_keys = PublicKeys::new();
+
+    for pk in &block.public_keys {
+        public_keys.insert(&PublicKey::from_proto(pk)?);
+    }
+    Ok(public_keys)
+}
+
+/// `check if <name>($value), {values}.contains($value)`
+fn check_in_set(name: &str, values: &[&str]) -> builder::Check {
+    let values = values.iter().map(|v| builder::string(v)).collect();
+    builder::Check {
+        queries: vec![builder::constrained_rule(
+            "query",
+            &[] as &[builder::Term],
+            &[builder::pred(name, &[builder::var("value")])],
+            &[builder::Expression {
+                ops: vec![
+                    builder::Op::Value(builder::set(values)),
+                    builder::Op::Value(builder::var("value")),
+                    builder::Op::Binary(builder::Binary::Contains),
+                ],
+            }],
+        )],
+        kind: CheckKind::One,
+        message: None,
+    }
+}
+
+/// `$time <operator> date`, for the checks on the `time` fact
+///
+/// dates before UNIX_EPOCH cannot be stored, but all the `time` facts are
+/// after them: the expression compares with UNIX_EPOCH instead, with an
+/// operator giving the same result
+fn time_comparison(date: SystemTime, operator: builder::Binary) -> builder::Expression {
+    let (date, operator) = match date.duration_since(UNIX_EPOCH) {
+        Ok(date) => (date.as_secs(), operator),
+        // never true
+        Err(_)
+            if matches!(
+                operator,
+                builder::Binary::LessThan | builder::Binary::LessOrEqual
+            ) =>
+        {
+            (0, builder::Binary::LessThan)
+        }
+        // always true
+        Err(_) => (0, builder::Binary::GreaterOrEqual),
+    };
+
+    builder::Expression {
+        ops: vec![
+            builder::Op::Value(builder::var("time")),
+            builder::Op::Value(builder::Term::Date(date)),
+            builder::Op::Binary(operator),
+        ],
+    }
+}
+
+fn print_block(symbols: &SymbolTable, block: &Block) -> String {
+    let facts: Vec<_> = block.facts.iter().map(|f| symbols.print_fact(f)).collect();
+    let rules: Vec<_> = block.rules.iter().map(|r| symbols.print_rule(r)).collect();
+    let checks: Vec<_> = block
+        .checks
+        .iter()
+        .map(|r| symbols.print_check(r))
+        .collect();
+
+    let facts = if facts.is_empty() {
         String::new()
     } else {
         format!(
@@ -649,6 +1458,15 @@ pub enum Scope {
     PublicKey(u64),
 }
 
+/// root key that verified a token, among the candidates given to
+/// [`Biscuit::from_with_keys`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct VerifiedRootKey {
+    /// position of the key in the list of candidates
+    pub index: usize,
+    pub public_key: PublicKey,
+}
+
 /// Chooses a root public key to verify the token
 ///
 /// In case of key rotation, it is possible to add a root key id
@@ -689,6 +1507,23 @@ impl RootKeyProvider for &PublicKey {
     }
 }
 
+/// chooses the key matching the token's root key id, and fails
+/// if the token does not have a root key id
+impl RootKeyProvider for HashMap<u32, PublicKey> {
+    fn choose(&self, key_id: Option<u32>) -> Result<PublicKey, error::Format> {
+        key_id
+            .and_then(|id| self.get(&id))
+            .copied()
+            .ok_or(error::Format::UnknownPublicKey)
+    }
+}
+
+impl RootKeyProvider for &HashMap<u32, PublicKey> {
+    fn choose(&self, key_id: Option<u32>) -> Result<PublicKey, error::Format> {
+        (**self).choose(key_id)
+    }
+}
+
 impl<F: Fn(Option<u32>) -> Result<PublicKey, error::Format>> RootKeyProvider for F {
     fn choose(&self, root_key_id: Option<u32>) -> Result<PublicKey, error::Format> {
         self(root_key_id)
@@ -702,10 +1537,17 @@ mod tests {
     use super::*;
     use crate::builder::CheckKind;
     use crate::crypto::KeyPair;
-    use crate::{error::*, AuthorizerLimits};
+    use crate::{error::*, AuthorizerLimits, UnverifiedBiscuit};
     use rand::prelude::*;
     use std::time::{Duration, SystemTime};
 
+    fn limits() -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
     #[test]
     fn basic() {
         let mut rng: StdRng = SeedableRng::seed_from_u64(0);
@@ -862,8 +1704,8 @@ mod tests {
               Err(Token::FailedLogic(Logic::Unauthorized {
                   policy: MatchedPolicy::Allow(0),
                   checks: vec![
-                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 0, rule: String::from("check if resource($resource), operation(\"read\"), right($resource, \"read\")") }),
-                FailedCheck::Block(FailedBlockCheck { block_id: 2, check_id: 0, rule: String::from("check if resource(\"file1\")") })
+                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 0, rule: String::from("check if resource($resource), operation(\"read\"), right($resource, \"read\")"), message: None }),
+                FailedCheck::Block(FailedBlockCheck { block_id: 2, check_id: 0, rule: String::from("check if resource(\"file1\")"), message: None })
               ]
               })));
         }
@@ -931,7 +1773,8 @@ mod tests {
                         check_id: 0,
                         rule: String::from(
                             "check if resource($resource), $resource.starts_with(\"/folder1/\")"
-                        )
+                        ),
+                        message: None,
                     }),]
                 }))
             );
@@ -947,8 +1790,8 @@ mod tests {
             assert_eq!(res,
               Err(Token::FailedLogic(Logic::NoMatchingPolicy {
                   checks: vec![
-                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 0, rule: String::from("check if resource($resource), $resource.starts_with(\"/folder1/\")") }),
-                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 1, rule: String::from("check if resource($resource_name), operation(\"read\"), right($resource_name, \"read\")") }),
+                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 0, rule: String::from("check if resource($resource), $resource.starts_with(\"/folder1/\")"), message: None }),
+                FailedCheck::Block(FailedBlockCheck { block_id: 1, check_id: 1, rule: String::from("check if resource($resource_name), operation(\"read\"), right($resource_name, \"read\")"), message: None }),
               ]})));
         }
     }
@@ -1118,7 +1961,8 @@ mod tests {
             Err(Token::FailedLogic(Logic::NoMatchingPolicy {
                 checks: vec![FailedCheck::Authorizer(FailedAuthorizerCheck {
                     check_id: 0,
-                    rule: String::from("check if right(\"file2\", \"write\")")
+                    rule: String::from("check if right(\"file2\", \"write\")"),
+                    message: None,
                 }),]
             }))
         );
@@ -1265,6 +2109,7 @@ mod tests {
                         block_id: 0,
                         check_id: 0,
                         rule: String::from("check if resource(\"hello\")"),
+                        message: None,
                     }),]
                 }))
             );
@@ -1297,6 +2142,7 @@ mod tests {
                     block_id: 0,
                     check_id: 0,
                     rule: String::from("check if name($name)"),
+                    message: None,
                 }),
             ])))
         );
@@ -1511,9 +2357,1880 @@ mod tests {
                         block_id: 0,
                         check_id: 0,
                         rule: String::from("check all fact($v), $v < 1"),
+                        message: None,
                     }),]
                 }))
             );
         }
     }
+
+    #[test]
+    fn symbol_report() {
+        use crate::datalog::SymbolSource;
+
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let external = KeyPair::new_with_rng(&mut rng);
+
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(format!(
+                "user(\"alice\"); check if external_fact(\"hello\") trusting {}",
+                external.public()
+            ))
+            .unwrap();
+        let biscuit1 = builder
+            .build_with_rng(&root, default_symbol_table(), &mut rng)
+            .unwrap();
+
+        let req = biscuit1.third_party_request().unwrap();
+        let mut builder = BlockBuilder::new();
+        builder
+            .add_code("external_fact(\"hello\"); group(\"admin\")")
+            .unwrap();
+        let res = req.create_block(&external.private(), builder).unwrap();
+        let biscuit2 = biscuit1.append_third_party(external.public(), res).unwrap();
+
+        let report = biscuit2.symbol_report();
+        assert_eq!(
+            report.duplicates().collect::<Vec<_>>(),
+            vec![
+                (
+                    "external_fact",
+                    &[SymbolSource::Block(0), SymbolSource::Block(1)][..]
+                ),
+                (
+                    "hello",
+                    &[SymbolSource::Block(0), SymbolSource::Block(1)][..]
+                ),
+            ]
+        );
+        // one copy of each string could be removed
+        assert_eq!(report.estimated_savings(), (2 + 13) + (2 + 5));
+
+        let mut authorizer = biscuit2.authorizer().unwrap();
+        authorizer.add_code("allow if user(\"alice\")").unwrap();
+        let report = authorizer.symbol_report();
+        assert_eq!(
+            report.duplicates().next(),
+            Some((
+                "alice",
+                &[SymbolSource::Block(0), SymbolSource::Authorizer][..]
+            ))
+        );
+        assert_eq!(report.estimated_savings(), (2 + 13) + (2 + 5));
+    }
+
+    #[test]
+    fn delegate() {
+        let root = KeyPair::new();
+        let expiration = SystemTime::now() + Duration::from_secs(3600);
+
+        let mut builder = Biscuit::builder();
+        builder.add_fact("right(\"file1\", \"read\")").unwrap();
+        builder.check_expiration_date(expiration);
+        let biscuit = builder.build(&root).unwrap();
+        assert_eq!(
+            biscuit.expiration().unwrap().as_ref().map(unix_timestamp),
+            Some(unix_timestamp(&expiration))
+        );
+        assert_eq!(
+            Biscuit::builder().build(&root).unwrap().expiration(),
+            Ok(None)
+        );
+
+        let later = expiration + Duration::from_secs(60);
+        assert_eq!(
+            biscuit.delegate(&["file1"], &["read"], later).unwrap_err(),
+            Token::ExpirationExtended {
+                current: unix_timestamp(&expiration),
+                requested: unix_timestamp(&later),
+            }
+        );
+
+        let sooner = SystemTime::now() + Duration::from_secs(60);
+        let delegated = biscuit.delegate(&["file1"], &["read"], sooner).unwrap();
+        assert_eq!(delegated.block_count(), 2);
+        assert_eq!(
+            delegated.expiration().unwrap().as_ref().map(unix_timestamp),
+            Some(unix_timestamp(&sooner))
+        );
+
+        let authorize = |resource: &str, operation: &str| {
+            let mut authorizer = delegated.authorizer().unwrap();
+            authorizer
+                .add_fact(fact("resource", &[string(resource)]))
+                .unwrap();
+            authorizer
+                .add_fact(fact("operation", &[string(operation)]))
+                .unwrap();
+            authorizer.set_time();
+            authorizer.allow().unwrap();
+            authorizer.authorize_with_limits(limits())
+        };
+        assert_eq!(authorize("file1", "read"), Ok(0));
+        assert!(authorize("file1", "write").is_err());
+        assert!(authorize("file2", "read").is_err());
+    }
+
+    #[test]
+    fn attenuate_with() {
+        let root = KeyPair::new();
+        let biscuit = Biscuit::builder().build(&root).unwrap();
+
+        let template = TemplateBlock::new()
+            .resource_prefix("/files/")
+            .operations(&["read", "list"])
+            .context("read only");
+        let attenuated = biscuit.attenuate_with(&template).unwrap();
+        assert_eq!(
+            attenuated.context(),
+            vec![None, Some("read only".to_string())]
+        );
+        assert_eq!(attenuated.expiration(), Ok(None));
+
+        let authorize = |token: &Biscuit, resource: &str, operation: &str| {
+            let mut authorizer = token.authorizer().unwrap();
+            authorizer
+                .add_fact(fact("resource", &[string(resource)]))
+                .unwrap();
+            authorizer
+                .add_fact(fact("operation", &[string(operation)]))
+                .unwrap();
+            authorizer.set_time();
+            authorizer.allow().unwrap();
+            authorizer.authorize_with_limits(limits())
+        };
+        assert_eq!(authorize(&attenuated, "/files/a.txt", "list"), Ok(0));
+        assert!(authorize(&attenuated, "/files/a.txt", "write").is_err());
+        assert!(authorize(&attenuated, "/etc/passwd", "read").is_err());
+
+        let expiration = SystemTime::now() - Duration::from_secs(60);
+        let expired = attenuated
+            .attenuate_with(&TemplateBlock::new().expiration(expiration))
+            .unwrap();
+        assert_eq!(
+            expired.expiration().unwrap().as_ref().map(unix_timestamp),
+            Some(unix_timestamp(&expiration))
+        );
+        assert!(authorize(&expired, "/files/a.txt", "list").is_err());
+
+        assert!(matches!(
+            expired.attenuate_with(&TemplateBlock::new().expiration(SystemTime::now())),
+            Err(Token::ExpirationExtended { .. })
+        ));
+    }
+
+    #[test]
+    fn root_key_rotation() {
+        let old_root = KeyPair::new();
+        let new_root = KeyPair::new();
+
+        let mut root_keys = HashMap::new();
+        root_keys.insert(0, old_root.public());
+        root_keys.insert(1, new_root.public());
+
+        let mut builder = Biscuit::builder();
+        builder.set_root_key_id(0);
+        let old_token = builder.build(&old_root).unwrap().to_vec().unwrap();
+        let mut builder = Biscuit::builder();
+        builder.set_root_key_id(1);
+        let new_token = builder.build(&new_root).unwrap().to_vec().unwrap();
+
+        assert_eq!(
+            Biscuit::from(&old_token, &root_keys).unwrap().root_key_id(),
+            Some(0)
+        );
+        assert_eq!(
+            Biscuit::from(&new_token, &root_keys).unwrap().root_key_id(),
+            Some(1)
+        );
+
+        // the key was removed after the rotation
+        root_keys.remove(&0);
+        assert_eq!(
+            Biscuit::from(&old_token, root_keys.clone()).unwrap_err(),
+            Token::Format(Format::UnknownPublicKey)
+        );
+
+        // tokens without a root key id are rejected
+        let token = Biscuit::builder()
+            .build(&new_root)
+            .unwrap()
+            .to_vec()
+            .unwrap();
+        assert_eq!(
+            Biscuit::from(&token, &root_keys).unwrap_err(),
+            Token::Format(Format::UnknownPublicKey)
+        );
+    }
+
+    #[test]
+    fn set_operations_schema_version() {
+        let root = KeyPair::new();
+
+        let mut builder = Biscuit::builder();
+        builder
+            .add_check("check if [1, 2].union([3]).contains(3)")
+            .unwrap();
+        let biscuit = builder.build(&root).unwrap();
+        assert_eq!(biscuit.authority.version, Some(3));
+
+        let mut builder = Biscuit::builder();
+        builder
+            .add_check("check if operations($ops), $ops.is_subset([\"read\", \"write\"])")
+            .unwrap();
+        builder
+            .add_check("check if [1, 2].difference([2]) == [1]")
+            .unwrap();
+        let biscuit = builder.build(&root).unwrap();
+        assert_eq!(biscuit.authority.version, Some(EXTENSION_SCHEMA_VERSION_1));
+
+        let biscuit = Biscuit::from(biscuit.to_vec().unwrap(), root.public()).unwrap();
+        assert_eq!(
+            biscuit.print_block_source(0).unwrap(),
+            "check if operations($ops), $ops.is_subset([\"read\", \"write\"]);\ncheck if [1, 2].difference([2]) == [1];\n"
+        );
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        authorizer.add_fact("operations([\"read\"])").unwrap();
+        authorizer.allow().unwrap();
+        assert_eq!(authorizer.authorize_with_limits(limits()), Ok(0));
+    }
+
+    #[test]
+    fn aggregates_schema_version() {
+        let root = KeyPair::new();
+
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(
+                r#"session("alice", 1, "active");
+                session("alice", 2, "active");
+                session("alice", 3, "closed");
+                session("bob", 4, "active");
+                active_sessions($user, count($id)) <- session($user, $id, "active");"#,
+            )
+            .unwrap();
+        let biscuit = builder.build(&root).unwrap();
+        assert_eq!(biscuit.authority.version, Some(EXTENSION_SCHEMA_VERSION_2));
+
+        let biscuit = Biscuit::from(biscuit.to_vec().unwrap(), root.public()).unwrap();
+        assert!(biscuit
+            .print_block_source(0)
+            .unwrap()
+            .contains("active_sessions($user, count($id)) <- session($user, $id, \"active\");"));
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        authorizer.set_limits(limits());
+        authorizer
+            .add_code(
+                r#"check if active_sessions("alice", 2);
+                deny if active_sessions($user, $n), $n >= 2, $user == "bob";
+                allow if true;"#,
+            )
+            .unwrap();
+        assert_eq!(authorizer.authorize_with_limits(limits()), Ok(1));
+
+        let res: Vec<(String, i64)> = authorizer
+            .query("data($user, $n) <- active_sessions($user, $n)")
+            .unwrap();
+        assert_eq!(res.len(), 2);
+        assert!(res.contains(&("bob".to_string(), 1)));
+    }
+
+    #[test]
+    fn counter_signature() {
+        let root = KeyPair::new();
+        let gateway = KeyPair::new();
+        let time = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+
+        let mut builder = Biscuit::builder();
+        builder.add_fact("user(\"alice\")").unwrap();
+        let biscuit = builder.build(&root).unwrap();
+
+        assert_eq!(
+            biscuit.counter_sign(&gateway, time).unwrap_err(),
+            Token::NotSealed
+        );
+
+        let sealed = biscuit.seal().unwrap();
+        let counter_signed = sealed.counter_sign(&gateway, time).unwrap();
+        assert_eq!(
+            counter_signed.counter_signatures(),
+            vec![CounterSignature {
+                public_key: gateway.public(),
+                time,
+            }]
+        );
+
+        let deserialized = Biscuit::from(counter_signed.to_vec().unwrap(), root.public()).unwrap();
+        assert_eq!(
+            deserialized.counter_signatures(),
+            counter_signed.counter_signatures()
+        );
+        assert_eq!(deserialized.block_count(), 1);
+
+        // counter signatures are the field 1000 of the token, after the fields
+        // of the specification
+        let serialized = counter_signed.to_vec().unwrap();
+        let mut proto = schema::Biscuit::decode(&serialized[..]).unwrap();
+        assert_eq!(proto.counter_signatures.len(), 1);
+        proto.counter_signatures.clear();
+        let without = proto.encode_to_vec();
+        assert_eq!(serialized[..without.len()], without[..]);
+        assert_eq!(serialized[without.len()..without.len() + 2], [0xc2, 0x3e]);
+
+        // counter signatures are bound to the sealed token
+        let mut other = biscuit.append(BlockBuilder::new()).unwrap().seal().unwrap();
+        other.container.counter_signatures = counter_signed.container.counter_signatures.clone();
+        let other = Biscuit::from(other.to_vec().unwrap(), root.public()).unwrap();
+        assert!(other.counter_signatures().is_empty());
+
+        // a validly signed counter signature with a time out of the range of
+        // `SystemTime` is ignored
+        let mut overflow = counter_signed.clone();
+        overflow.container = overflow.container.counter_sign(&gateway, u64::MAX).unwrap();
+        let overflow = Biscuit::from(overflow.to_vec().unwrap(), root.public()).unwrap();
+        assert_eq!(overflow.container.verified_counter_signatures().len(), 2);
+        assert_eq!(
+            overflow.counter_signatures(),
+            counter_signed.counter_signatures()
+        );
+        let unverified = UnverifiedBiscuit::from(overflow.to_vec().unwrap()).unwrap();
+        assert_eq!(
+            unverified.counter_signatures(),
+            counter_signed.counter_signatures()
+        );
+    }
+
+    #[test]
+    fn possession_proof() {
+        let root = KeyPair::new();
+
+        let mut builder = Biscuit::builder();
+        builder.add_fact("user(\"alice\")").unwrap();
+        let biscuit = builder.build(&root).unwrap();
+
+        let proof = biscuit.prove_possession(b"nonce").unwrap();
+        assert_eq!(proof.revocation_id, biscuit.revocation_identifiers()[0]);
+        assert!(biscuit.verify_possession(b"nonce", &proof).is_ok());
+        assert!(proof.verify(b"nonce").is_ok());
+        assert!(biscuit.verify_possession(b"other", &proof).is_err());
+        assert!(proof.verify(b"other").is_err());
+
+        // the proof uses the key of the last block
+        let attenuated = biscuit.append(BlockBuilder::new()).unwrap();
+        assert!(attenuated.verify_possession(b"nonce", &proof).is_err());
+        let proof = attenuated.prove_possession(b"nonce").unwrap();
+        assert_eq!(proof.revocation_id, attenuated.revocation_identifiers()[1]);
+        assert!(biscuit.verify_possession(b"nonce", &proof).is_err());
+
+        let deserialized = Biscuit::from(attenuated.to_vec().unwrap(), root.public()).unwrap();
+        assert!(deserialized.verify_possession(b"nonce", &proof).is_ok());
+
+        // the proof is bound to the token, not only to the key
+        let mut forged = proof.clone();
+        forged.revocation_id = biscuit.revocation_identifiers()[0].clone();
+        assert!(forged.verify(b"nonce").is_err());
+
+        let sealed = attenuated.seal().unwrap();
+        assert_eq!(
+            sealed.prove_possession(b"nonce").unwrap_err(),
+            Token::AlreadySealed
+        );
+        assert!(sealed.verify_possession(b"nonce", &proof).is_ok());
+    }
+
+    #[test]
+    fn challenge() {
+        let root = KeyPair::new();
+        let limits = limits();
+
+        let mut builder = Biscuit::builder();
+        builder.add_fact("user(\"alice\")").unwrap();
+        let biscuit = builder.build(&root).unwrap();
+
+        let bound = biscuit
+            .bind_challenge(b"nonce", Duration::from_secs(60))
+            .unwrap();
+        assert_eq!(bound.block_count(), 2);
+
+        let authorize = |challenge: Option<&[u8]>, time: Option<SystemTime>| {
+            let mut authorizer = bound.authorizer().unwrap();
+            if let Some(challenge) = challenge {
+                authorizer.add_challenge(challenge);
+            }
+            if let Some(time) = time {
+                authorizer.set_clock(crate::FixedClock(time));
+                authorizer.set_time();
+            }
+            authorizer.allow().unwrap();
+            authorizer.authorize_with_limits(limits.clone())
+        };
+
+        let now = SystemTime::now();
+        assert_eq!(authorize(Some(b"nonce"), Some(now)), Ok(0));
+        assert!(authorize(Some(b"other"), Some(now)).is_err());
+        assert!(authorize(None, Some(now)).is_err());
+        assert!(authorize(Some(b"nonce"), None).is_err());
+        assert!(authorize(Some(b"nonce"), Some(now + Duration::from_secs(120))).is_err());
+
+        let start = UNIX_EPOCH + Duration::from_secs(1_000_000);
+        let bound = biscuit
+            .bind_challenge_with_clock(b"nonce", Duration::from_secs(60), &crate::FixedClock(start))
+            .unwrap();
+        let mut authorizer = bound.authorizer().unwrap();
+        authorizer.add_challenge(b"nonce");
+        authorizer.set_clock(crate::FixedClock(start + Duration::from_secs(30)));
+        authorizer.set_time();
+        authorizer.allow().unwrap();
+        assert_eq!(authorizer.authorize_with_limits(limits.clone()), Ok(0));
+
+        let mut block = BlockBuilder::new();
+        assert!(block
+            .check_challenge(b"nonce", UNIX_EPOCH - Duration::from_secs(1))
+            .is_err());
+    }
+
+    #[test]
+    fn decimals() {
+        let root = KeyPair::new();
+
+        let mut builder = Biscuit::builder();
+        builder.add_fact("price(12.30d)").unwrap();
+        builder
+            .add_check("check if price($p), $p * 2 <= 25d, $p == 12.3d, $p != 12.31d")
+            .unwrap();
+        builder
+            .add_fact(fact("limit", &[builder::decimal(-5, 3)]))
+            .unwrap();
+        let biscuit = builder.build(&root).unwrap();
+        assert_eq!(biscuit.authority.version, Some(EXTENSION_SCHEMA_VERSION_1));
+
+        let biscuit = Biscuit::from(biscuit.to_vec().unwrap(), root.public()).unwrap();
+        assert_eq!(
+            biscuit.print_block_source(0).unwrap(),
+            "price(12.3d);\nlimit(-0.005d);\ncheck if price($p), $p * 2 <= 25d, $p == 12.3d, $p != 12.31d;\n"
+        );
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        authorizer
+            .add_code(
+                "check if price($p), $p / 4 == 3.075d; allow if price($p), [12.30d].contains($p);",
+            )
+            .unwrap();
+        assert_eq!(authorizer.authorize_with_limits(limits()), Ok(0));
+
+        // trailing zeros do not change the number
+        let mut builder = Biscuit::builder();
+        builder.add_fact("price(1.50d)").unwrap();
+        let token = builder.build(&root).unwrap();
+        for policy in [
+            "allow if price(1.5d)",
+            "allow if price($p), $p == 1.5d",
+            "allow if [1.50d].contains(1.5d)",
+            "allow if price($p), $p * 2 == 3d, [3d].contains($p * 2)",
+        ]
+        .iter()
+        {
+            let mut authorizer = token.authorizer().unwrap();
+            authorizer.add_code(policy).unwrap();
+            assert_eq!(
+                authorizer.authorize_with_limits(limits()),
+                Ok(0),
+                "{}",
+                policy
+            );
+        }
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        authorizer
+            .add_code("check if price($p), $p * 10000000000000000000000000000000000000d > 0; allow if true;")
+            .unwrap();
+        assert_eq!(
+            authorizer.authorize_with_limits(limits()),
+            Err(Token::Execution(Expression::Overflow))
+        );
+    }
+
+    #[test]
+    fn check_messages() {
+        let root = KeyPair::new();
+
+        let mut builder = Biscuit::builder();
+        builder
+            .add_check(
+                r#"check if time($t), $t < 2020-01-01T00:00:00Z @msg("subscription expired")"#,
+            )
+            .unwrap();
+        builder.add_check(r#"check if user($u)"#).unwrap();
+        let biscuit = builder.build(&root).unwrap();
+
+        let biscuit = Biscuit::from(biscuit.to_vec().unwrap(), root.public()).unwrap();
+        assert_eq!(
+            biscuit.print_block_source(0).unwrap(),
+            "check if time($t), $t < 2020-01-01T00:00:00Z @msg(\"subscription expired\");\ncheck if user($u);\n"
+        );
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        authorizer
+            .add_code(
+                r#"user("alice");
+                check if operation("write") @msg("read only token");
+                allow if true;"#,
+            )
+            .unwrap();
+        authorizer.set_time();
+        authorizer.set_limits(limits());
+
+        assert_eq!(
+            authorizer.authorize(),
+            Err(Token::FailedLogic(Logic::Unauthorized {
+                policy: MatchedPolicy::Allow(0),
+                checks: vec![
+                    FailedCheck::Authorizer(FailedAuthorizerCheck {
+                        check_id: 0,
+                        rule: String::from(
+                            "check if operation(\"write\") @msg(\"read only token\")"
+                        ),
+                        message: Some(String::from("read only token")),
+                    }),
+                    FailedCheck::Block(FailedBlockCheck {
+                        block_id: 0,
+                        check_id: 0,
+                        rule: String::from(
+                            "check if time($t), $t < 2020-01-01T00:00:00Z @msg(\"subscription expired\")"
+                        ),
+                        message: Some(String::from("subscription expired")),
+                    }),
+                ]
+            }))
+        );
+
+        // messages are escaped when printed, so that they can be parsed again
+        let source = r#"check if true @msg("say \"hi\"\n\\o/")"#;
+        let check = builder::Check::try_from(source).unwrap();
+        assert_eq!(check.message.as_deref(), Some("say \"hi\"\n\\o/"));
+        assert_eq!(check.to_string(), source);
+        assert_eq!(
+            builder::Check::try_from(check.to_string().as_str()),
+            Ok(check.clone())
+        );
+
+        let mut builder = Biscuit::builder();
+        builder.add_check(check).unwrap();
+        let biscuit = builder.build(&root).unwrap();
+        assert_eq!(
+            biscuit.print_block_source(0).unwrap(),
+            format!("{source};\n")
+        );
+    }
+
+    #[test]
+    fn third_party_restrictions() {
+        let root = KeyPair::new();
+        let external = KeyPair::new();
+
+        let mut builder = Biscuit::builder();
+        builder.add_fact("user(\"alice\")").unwrap();
+        let biscuit1 = builder.build(&root).unwrap();
+
+        let mut builder = BlockBuilder::new();
+        builder.add_fact("group(\"admin\")").unwrap();
+        let req = biscuit1.third_party_request().unwrap();
+        let res = req.create_block(&external.private(), builder).unwrap();
+        let biscuit2 = biscuit1.append_third_party(external.public(), res).unwrap();
+        let biscuit3 = biscuit2.append(BlockBuilder::new()).unwrap();
+
+        let mut expected = HashMap::new();
+        expected.insert(external.public(), vec![1]);
+        assert_eq!(biscuit3.public_key_to_block_id(), expected);
+        assert!(biscuit1.public_key_to_block_id().is_empty());
+
+        let authorizer = biscuit3.authorizer().unwrap();
+        assert_eq!(authorizer.public_key_to_block_id(), expected);
+
+        let serialized = biscuit3.to_vec().unwrap();
+        let from = |restrictions: ThirdPartyRestrictions| {
+            Biscuit::from_with_restrictions(&serialized, root.public(), &restrictions).map(|_| ())
+        };
+
+        assert_eq!(from(ThirdPartyRestrictions::new()), Ok(()));
+        assert_eq!(
+            from(ThirdPartyRestrictions::new().third_party_from(2)),
+            Err(Token::Format(error::Format::InvalidBlockId(1)))
+        );
+        assert_eq!(
+            from(ThirdPartyRestrictions::new().allow_key(external.public(), 1..2)),
+            Ok(())
+        );
+        assert_eq!(
+            from(ThirdPartyRestrictions::new().allow_key(external.public(), 2..)),
+            Err(Token::Format(error::Format::InvalidBlockId(1)))
+        );
+        assert_eq!(
+            from(ThirdPartyRestrictions::new().deny_unknown_keys()),
+            Err(Token::Format(error::Format::UnknownExternalKey))
+        );
+        assert_eq!(
+            from(
+                ThirdPartyRestrictions::new()
+                    .allow_key(external.public(), ..=1)
+                    .deny_unknown_keys()
+            ),
+            Ok(())
+        );
+
+        // with several keys, the first rejected block is reported
+        let other = KeyPair::new();
+        let mut mapping = HashMap::new();
+        for (i, key) in [external.public(), other.public()].iter().enumerate() {
+            mapping.insert(*key, vec![i + 1, i + 3]);
+        }
+        assert_eq!(
+            ThirdPartyRestrictions::new()
+                .third_party_from(3)
+                .check(&mapping),
+            Err(error::Format::InvalidBlockId(1))
+        );
+    }
+
+    #[test]
+    fn p256() {
+        use crate::builder::Algorithm;
+
+        let root = KeyPair::new_with_algorithm(Algorithm::P256);
+        let external = KeyPair::new_with_algorithm(Algorithm::P256);
+        assert_eq!(root.public().algorithm(), Algorithm::P256);
+
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(format!(
+                "user(\"alice\"); check if group(\"admin\") trusting {}",
+                external.public()
+            ))
+            .unwrap();
+        let biscuit1 = builder.build(&root).unwrap();
+
+        let req = biscuit1.third_party_request().unwrap();
+        let mut builder = BlockBuilder::new();
+        builder.add_fact("group(\"admin\")").unwrap();
+        let res = req.create_block(&external.private(), builder).unwrap();
+        let biscuit2 = biscuit1
+            .append_third_party_with_keypair(
+                external.public(),
+                res,
+                KeyPair::new_with_algorithm(Algorithm::P256),
+            )
+            .unwrap();
+
+        let serialized = biscuit2.to_vec().unwrap();
+        let biscuit3 = Biscuit::from(&serialized, root.public()).unwrap();
+        assert!(biscuit3.print_block_source(0).unwrap().contains(&format!(
+            "trusting p256/{}",
+            hex::encode(external.public().to_bytes())
+        )));
+
+        let mut authorizer = biscuit3.authorizer().unwrap();
+        authorizer.add_code("allow if user(\"alice\")").unwrap();
+        authorizer.set_limits(limits());
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        let sealed = biscuit3.seal().unwrap().to_vec().unwrap();
+        Biscuit::from(&sealed, root.public()).unwrap();
+
+        // a valid signature from another key is refused
+        let other = KeyPair::new_with_algorithm(Algorithm::P256);
+        assert!(Biscuit::from(&serialized, other.public()).is_err());
+    }
+
+    #[test]
+    fn p256_signature_malleability() {
+        use crate::builder::Algorithm;
+
+        let root = KeyPair::new_with_algorithm(Algorithm::P256);
+        let mut builder = Biscuit::builder();
+        builder.add_fact("user(\"alice\")").unwrap();
+        let token = builder.build(&root).unwrap();
+
+        let signature =
+            p256::ecdsa::Signature::from_der(token.container.authority.signature.to_bytes())
+                .unwrap();
+        assert!(signature.normalize_s().is_none());
+
+        // `(r, n - s)` is a valid ECDSA signature of the same payload, with
+        // another revocation identifier
+        let high_s = p256::ecdsa::Signature::from_scalars(
+            signature.r().to_bytes(),
+            (-*signature.s()).to_bytes(),
+        )
+        .unwrap();
+        let mut forged = token.clone();
+        forged.container.authority.signature =
+            crate::crypto::Signature::from_vec(high_s.to_der().as_bytes().to_vec());
+        assert_ne!(
+            forged.revocation_identifiers(),
+            token.revocation_identifiers()
+        );
+        assert!(matches!(
+            Biscuit::from(forged.to_vec().unwrap(), root.public()),
+            Err(Token::Format(Format::Signature(
+                crate::error::Signature::InvalidSignature(message)
+            ))) if message.contains("low S")
+        ));
+
+        Biscuit::from(token.to_vec().unwrap(), root.public()).unwrap();
+    }
+
+    #[test]
+    fn from_with_revocation() {
+        let root = KeyPair::new();
+        let biscuit1 = Biscuit::builder().build(&root).unwrap();
+        let biscuit2 = biscuit1.append(BlockBuilder::new()).unwrap();
+        let serialized = biscuit2.to_vec().unwrap();
+
+        let store = crate::MemoryRevocationStore::new();
+        assert!(Biscuit::from_with_revocation(&serialized, root.public(), &store).is_ok());
+
+        let mut authorizer = biscuit2.authorizer().unwrap();
+        authorizer.revocation_check(store.clone());
+        authorizer.add_code("allow if true").unwrap();
+
+        store.revoke(&biscuit2.revocation_identifiers()[1]);
+        assert_eq!(
+            Biscuit::from_with_revocation(&serialized, root.public(), &store).unwrap_err(),
+            Token::Revoked { block_ids: vec![1] }
+        );
+        assert_eq!(
+            authorizer.authorize(),
+            Err(Token::Revoked { block_ids: vec![1] })
+        );
+    }
+
+    #[test]
+    fn verify_with_resolver() {
+        use crate::UnverifiedBiscuit;
+
+        let old = KeyPair::new();
+        let new = KeyPair::new();
+        let mut keys = HashMap::new();
+        keys.insert(1, old.public());
+        keys.insert(2, new.public());
+        let resolver: Box<dyn RootKeyProvider> = Box::new(keys);
+
+        let mut builder = Biscuit::builder();
+        builder.set_root_key_id(2);
+        let serialized = builder.build(&new).unwrap().to_vec().unwrap();
+
+        let token = UnverifiedBiscuit::from(&serialized).unwrap();
+        assert_eq!(token.root_key_id(), Some(2));
+        let biscuit = token
+            .clone()
+            .verify_with_resolver(resolver.as_ref())
+            .unwrap();
+        assert_eq!(biscuit.root_key_id(), Some(2));
+
+        let mut builder = Biscuit::builder();
+        builder.set_root_key_id(1);
+        let serialized = builder.build(&new).unwrap().to_vec().unwrap();
+        let token = UnverifiedBiscuit::from(&serialized).unwrap();
+        assert!(token.verify_with_resolver(resolver.as_ref()).is_err());
+
+        #[cfg(feature = "async")]
+        {
+            use std::future::Future;
+            use std::sync::Arc;
+            use std::task::{Context, Poll, Wake};
+
+            struct NoopWaker;
+            impl Wake for NoopWaker {
+                fn wake(self: Arc<Self>) {}
+            }
+
+            let mut builder = Biscuit::builder();
+            builder.set_root_key_id(2);
+            let serialized = builder.build(&new).unwrap().to_vec().unwrap();
+            let token = UnverifiedBiscuit::from(&serialized).unwrap();
+
+            let resolver = |key_id: Option<u32>| {
+                let key = resolver.choose(key_id);
+                async move { key }
+            };
+            let mut future = Box::pin(token.verify_with_resolver_async(resolver));
+            let waker = Arc::new(NoopWaker).into();
+            let mut cx = Context::from_waker(&waker);
+            match future.as_mut().poll(&mut cx) {
+                Poll::Ready(biscuit) => assert_eq!(biscuit.unwrap().root_key_id(), Some(2)),
+                Poll::Pending => panic!("the resolver future is ready"),
+            }
+        }
+    }
+
+    #[test]
+    fn third_party_serialization() {
+        let root = KeyPair::new();
+        let external = KeyPair::new();
+
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(format!(
+                "check if verified(true) trusting {}",
+                external.public()
+            ))
+            .unwrap();
+        let biscuit1 = builder.build(&root).unwrap();
+
+        let request = biscuit1
+            .third_party_request()
+            .unwrap()
+            .serialize_base64()
+            .unwrap();
+        let request = ThirdPartyRequest::deserialize_base64(request).unwrap();
+        let mut builder = BlockBuilder::new();
+        builder.add_fact("verified(true)").unwrap();
+        let response = request
+            .create_block(&external.private(), builder)
+            .unwrap()
+            .serialize_base64()
+            .unwrap();
+
+        let block = ThirdPartyBlock::deserialize_base64(response).unwrap();
+        assert_eq!(block.external_key(), Ok(external.public()));
+        let biscuit2 = biscuit1
+            .append_third_party(block.external_key().unwrap(), block)
+            .unwrap();
+
+        let mut authorizer = biscuit2.authorizer().unwrap();
+        authorizer.add_code("allow if true").unwrap();
+        authorizer.set_limits(limits());
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        assert!(ThirdPartyBlock::deserialize(&[1, 2, 3]).is_err());
+        assert!(ThirdPartyRequest::deserialize_base64("not base64!").is_err());
+    }
+
+    #[test]
+    fn third_party_versions() {
+        let root = KeyPair::new();
+        let external = KeyPair::new();
+        let biscuit1 = Biscuit::builder().build(&root).unwrap();
+
+        let request = biscuit1.third_party_request().unwrap();
+        assert_eq!(request.version(), MAX_SCHEMA_VERSION);
+        let block = request
+            .create_block(&external.private(), BlockBuilder::new())
+            .unwrap();
+        assert_eq!(block.version(), Some(4));
+        let block = ThirdPartyBlock::deserialize(&block.serialize().unwrap()).unwrap();
+        assert_eq!(block.version(), Some(4));
+
+        let encode = |version: Option<u32>| {
+            let mut v = Vec::new();
+            schema::ThirdPartyBlockRequest {
+                previous_key: root.public().to_proto(),
+                public_keys: vec![],
+                version,
+            }
+            .encode(&mut v)
+            .unwrap();
+            v
+        };
+
+        // requests from older implementations have no version
+        let request = ThirdPartyRequest::deserialize(&encode(None)).unwrap();
+        assert_eq!(request.version(), MAX_SCHEMA_VERSION);
+
+        // the specification uses the field 3 for the previous signature
+        let mut spec_request = encode(None);
+        spec_request.extend_from_slice(&[0x1a, 2, 0xab, 0xcd]);
+        let request = ThirdPartyRequest::deserialize(&spec_request).unwrap();
+        assert_eq!(request.version(), MAX_SCHEMA_VERSION);
+        assert_eq!(&encode(Some(4))[encode(None).len()..], &[0xc0, 0x3e, 4]);
+
+        assert_eq!(
+            ThirdPartyRequest::deserialize(&encode(Some(3))).unwrap_err(),
+            error::Token::Format(error::Format::Version {
+                minimum: 4,
+                maximum: MAX_SPEC_SCHEMA_VERSION,
+                actual: 3,
+            })
+        );
+        assert_eq!(
+            ThirdPartyRequest::deserialize(&encode(Some(1003))).unwrap_err(),
+            error::Token::Format(error::Format::ExtensionVersion {
+                minimum: EXTENSION_SCHEMA_VERSION_1,
+                maximum: MAX_SCHEMA_VERSION,
+                actual: 1003,
+            })
+        );
+
+        // `reject if` needs v1002, the token holder only supports v4
+        let request = ThirdPartyRequest::deserialize(&encode(Some(4))).unwrap();
+        let mut builder = BlockBuilder::new();
+        builder.add_code("reject if admin(true)").unwrap();
+        assert_eq!(
+            request
+                .create_block(&external.private(), builder)
+                .unwrap_err(),
+            error::Token::Format(error::Format::ExtensionVersion {
+                minimum: EXTENSION_SCHEMA_VERSION_1,
+                maximum: 4,
+                actual: EXTENSION_SCHEMA_VERSION_2,
+            })
+        );
+    }
+
+    #[test]
+    fn reproducible_with_seeded_rng() {
+        let build = |seed: u64| {
+            let mut rng: StdRng = SeedableRng::seed_from_u64(seed);
+            let root = KeyPair::new_with_rng(&mut rng);
+            let external = KeyPair::new_with_rng(&mut rng);
+
+            let mut builder = Biscuit::builder();
+            builder.add_fact("right(\"file1\", \"read\")").unwrap();
+            let token = builder
+                .build_with_rng(&root, default_symbol_table(), &mut rng)
+                .unwrap();
+
+            let mut block = BlockBuilder::new();
+            block.add_check("check if operation(\"read\")").unwrap();
+            let token = token.append_with_rng(block, &mut rng).unwrap();
+
+            let request = token.third_party_request().unwrap();
+            let response = request
+                .create_block(&external.private(), BlockBuilder::new())
+                .unwrap();
+            let token = token
+                .append_third_party_with_rng(external.public(), response, &mut rng)
+                .unwrap();
+
+            let mut block = BlockBuilder::new();
+            block.add_fact("user(\"alice\")").unwrap();
+            let unverified = crate::UnverifiedBiscuit::from(token.to_vec().unwrap())
+                .unwrap()
+                .append_with_rng(block, &mut rng)
+                .unwrap();
+
+            (token.to_vec().unwrap(), unverified.to_vec().unwrap())
+        };
+
+        assert_eq!(build(0), build(0));
+        assert_ne!(build(0).0, build(1).0);
+    }
+
+    #[test]
+    fn check_kinds() {
+        use crate::builder_ext::BuilderExt;
+
+        let root = KeyPair::new();
+        let expiration = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+
+        let mut builder = Biscuit::builder();
+        builder.check_all_operations(&["read", "list"]);
+        builder.reject_operation("delete");
+        builder.reject_if_expired_before(expiration);
+        let biscuit = builder.build(&root).unwrap();
+        assert_eq!(biscuit.authority.version, Some(EXTENSION_SCHEMA_VERSION_2));
+
+        let biscuit = Biscuit::from(biscuit.to_vec().unwrap(), root.public()).unwrap();
+        assert_eq!(
+            biscuit.print_block_source(0).unwrap(),
+            "check all operation($value), [\"read\", \"list\"].contains($value);\n\
+            reject if operation(\"delete\");\n\
+            reject if time($time), $time > 1970-01-01T00:16:40Z;\n"
+        );
+
+        let authorize = |code: &str| {
+            let mut authorizer = biscuit.authorizer().unwrap();
+            authorizer.add_code(code).unwrap();
+            authorizer.add_policy("allow if true").unwrap();
+            authorizer.authorize_with_limits(limits())
+        };
+
+        assert_eq!(
+            authorize(r#"operation("read"); operation("list"); time(1970-01-01T00:10:00Z);"#),
+            Ok(0)
+        );
+        // every operation must be in the list
+        assert!(authorize(r#"operation("read"); operation("write");"#).is_err());
+        assert!(authorize(r#"operation("read"); operation("delete");"#).is_err());
+        assert!(authorize(r#"operation("read"); time(1970-01-01T00:20:00Z);"#).is_err());
+
+        // `reject if` in the authorizer
+        assert!(authorize(r#"operation("read"); reject if operation("read");"#).is_err());
+        assert_eq!(
+            authorize(r#"operation("read"); reject if operation("write") or admin(true);"#),
+            Ok(0)
+        );
+        assert!(authorize(
+            r#"operation("read"); admin(true); reject if operation("write") or admin(true);"#
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn from_bytes() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.set_context("a".repeat(10_000));
+        let token = builder.build(&root).unwrap();
+        let mut block = BlockBuilder::new();
+        block.set_context("b".repeat(10_000));
+        let token = token.append(block).unwrap();
+        let serialized = Bytes::from(token.to_vec().unwrap());
+
+        let deserialized = Biscuit::from_bytes(serialized.clone(), root.public()).unwrap();
+        assert_eq!(deserialized.to_vec().unwrap(), serialized);
+        assert_eq!(
+            deserialized.context(),
+            vec![Some("a".repeat(10_000)), Some("b".repeat(10_000))]
+        );
+
+        // the block payloads are slices of the serialized token
+        let range = serialized.as_ptr_range();
+        assert!(range.contains(&deserialized.container.authority.data.as_ptr()));
+        assert!(range.contains(&deserialized.container.blocks[0].data.as_ptr()));
+
+        assert!(Biscuit::from_bytes(serialized, KeyPair::new().public()).is_err());
+    }
+
+    #[test]
+    fn application_symbols() {
+        let root = KeyPair::new();
+        let symbols =
+            ApplicationSymbols::new(1, vec!["subscription", "premium", "organization"]).unwrap();
+        let code = r#"subscription("premium"); organization("acme");"#;
+
+        let mut builder = Biscuit::builder();
+        builder.add_code(code).unwrap();
+        let plain = builder.build(&root).unwrap().to_vec().unwrap();
+
+        let mut builder = Biscuit::builder();
+        builder.add_code(code).unwrap();
+        builder.set_application_symbols(symbols.clone());
+        let token = builder.build(&root).unwrap();
+        let serialized = token.to_vec().unwrap();
+        assert!(serialized.len() < plain.len());
+        assert_eq!(token.block(0).unwrap().version, EXTENSION_SCHEMA_VERSION_2);
+
+        assert_eq!(
+            Biscuit::from(&serialized, root.public()).err(),
+            Some(Token::Format(Format::UnknownApplicationSymbols(1)))
+        );
+        let other = ApplicationSymbols::new(2, vec!["premium"]).unwrap();
+        assert!(Biscuit::from_with_application_symbols(
+            &serialized,
+            root.public(),
+            std::slice::from_ref(&other)
+        )
+        .is_err());
+
+        let token = Biscuit::from_with_application_symbols(
+            &serialized,
+            root.public(),
+            &[other, symbols.clone()],
+        )
+        .unwrap();
+        assert_eq!(
+            token.print_block_source(0).unwrap(),
+            "subscription(\"premium\");\norganization(\"acme\");\n"
+        );
+
+        // appended blocks use the application symbols too
+        let mut block = BlockBuilder::new();
+        block
+            .add_check(r#"check if subscription("premium")"#)
+            .unwrap();
+        let serialized = token.append(block).unwrap().to_vec().unwrap();
+        let unverified = UnverifiedBiscuit::from_with_application_symbols(
+            &serialized,
+            std::slice::from_ref(&symbols),
+        )
+        .unwrap();
+        assert_eq!(
+            unverified.print_block_source(1).unwrap(),
+            "check if subscription(\"premium\");\n"
+        );
+
+        let token =
+            Biscuit::from_with_application_symbols(&serialized, root.public(), &[symbols]).unwrap();
+        assert!(token.container.blocks[0].data.len() < 30);
+        let mut authorizer = token.authorizer().unwrap();
+        authorizer
+            .add_policy("allow if organization(\"acme\")")
+            .unwrap();
+        authorizer.set_limits(limits());
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        assert_eq!(
+            ApplicationSymbols::new(3, vec!["read"]),
+            Err(Format::SymbolTableOverlap)
+        );
+        assert_eq!(
+            ApplicationSymbols::new(3, vec!["a", "a"]),
+            Err(Format::SymbolTableOverlap)
+        );
+    }
+
+    #[test]
+    fn block_accessors() {
+        let root = KeyPair::new();
+        let external = KeyPair::new();
+
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(
+                r#"user("alice");
+                right($f) <- owner("alice", $f);
+                check if operation("read");"#,
+            )
+            .unwrap();
+        let biscuit1 = builder.build(&root).unwrap();
+
+        let mut builder = BlockBuilder::new();
+        builder.add_fact(r#"group("admin")"#).unwrap();
+        builder.add_scope(builder::Scope::Authority);
+        let req = biscuit1.third_party_request().unwrap();
+        let res = req.create_block(&external.private(), builder).unwrap();
+        let biscuit2 = biscuit1.append_third_party(external.public(), res).unwrap();
+
+        let unverified = UnverifiedBiscuit::from(biscuit2.to_vec().unwrap()).unwrap();
+        for i in 0..2 {
+            assert_eq!(
+                biscuit2.block_facts(i).unwrap(),
+                unverified.block_facts(i).unwrap()
+            );
+            assert_eq!(
+                biscuit2.block_checks(i).unwrap(),
+                unverified.block_checks(i).unwrap()
+            );
+            assert_eq!(
+                biscuit2.block_external_key(i).unwrap(),
+                unverified.block_external_key(i).unwrap()
+            );
+        }
+
+        assert_eq!(
+            biscuit2
+                .block_facts(0)
+                .unwrap()
+                .iter()
+                .map(|f| f.to_string())
+                .collect::<Vec<_>>(),
+            vec![r#"user("alice")"#]
+        );
+        assert_eq!(
+            biscuit2
+                .block_rules(0)
+                .unwrap()
+                .iter()
+                .map(|r| r.to_string())
+                .collect::<Vec<_>>(),
+            vec![r#"right($f) <- owner("alice", $f)"#]
+        );
+        assert_eq!(
+            unverified.block_rules(0).unwrap(),
+            biscuit2.block_rules(0).unwrap()
+        );
+        assert_eq!(
+            biscuit2
+                .block_checks(0)
+                .unwrap()
+                .iter()
+                .map(|c| c.to_string())
+                .collect::<Vec<_>>(),
+            vec![r#"check if operation("read")"#]
+        );
+        assert!(biscuit2.block_scopes(0).unwrap().is_empty());
+
+        // the third party block uses its own symbols
+        assert_eq!(
+            biscuit2.block_facts(1).unwrap()[0].to_string(),
+            r#"group("admin")"#
+        );
+        assert_eq!(
+            unverified.block_scopes(1).unwrap(),
+            vec![builder::Scope::Authority]
+        );
+        assert_eq!(
+            biscuit2.block_external_key(1).unwrap(),
+            Some(external.public())
+        );
+
+        assert!(biscuit2.block_facts(2).is_err());
+        assert!(unverified.block_checks(2).is_err());
+        assert!(unverified.block_external_key(2).is_err());
+    }
+
+    #[test]
+    fn uuids() {
+        let root = KeyPair::new();
+        let id = "67e55044-10b1-426f-9247-bb680e5fe0c8";
+
+        let mut builder = Biscuit::builder();
+        builder
+            .add_fact(format!("resource(uuid:{id})").as_str())
+            .unwrap();
+        builder
+            .add_fact(fact("owner", &[builder::uuid([0xff; 16])]))
+            .unwrap();
+        let biscuit = builder.build(&root).unwrap();
+        assert_eq!(biscuit.authority.version, Some(EXTENSION_SCHEMA_VERSION_2));
+
+        let biscuit = Biscuit::from(biscuit.to_vec().unwrap(), root.public()).unwrap();
+        assert_eq!(
+            biscuit.print_block_source(0).unwrap(),
+            format!("resource(uuid:{id});\nowner(uuid:ffffffff-ffff-ffff-ffff-ffffffffffff);\n")
+        );
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        authorizer
+            .add_code(
+                format!(
+                    "check if resource($r), owner($o), $r < $o, $r != $o;
+                    check if resource($r), [uuid:{id}].contains($r);
+                    allow if resource(uuid:{id});"
+                )
+                .as_str(),
+            )
+            .unwrap();
+        assert_eq!(authorizer.authorize_with_limits(limits()), Ok(0));
+
+        let mut authorizer = biscuit.authorizer().unwrap();
+        authorizer
+            .add_code(
+                "check if resource($r), $r == hex:67e5504410b1426f9247bb680e5fe0c8; allow if true;",
+            )
+            .unwrap();
+        assert_eq!(
+            authorizer.authorize_with_limits(limits()),
+            Err(Token::Execution(Expression::InvalidType))
+        );
+    }
+
+    #[test]
+    fn string_operations() {
+        let root = KeyPair::new();
+
+        let mut builder = Biscuit::builder();
+        builder
+            .add_fact(r#"resource("/tenants/Acme/files/1")"#)
+            .unwrap();
+        builder
+            .add_check(
+                r#"check if resource($r), tenant($t), $r.capture("^/tenants/([^/]+)/").to_lowercase() == $t"#,
+            )
+            .unwrap();
+        let biscuit = builder.build(&root).unwrap();
+        assert_eq!(biscuit.authority.version, Some(EXTENSION_SCHEMA_VERSION_2));
+
+        let biscuit = Biscuit::from(biscuit.to_vec().unwrap(), root.public()).unwrap();
+        assert_eq!(
+            biscuit.print_block_source(0).unwrap(),
+            "resource(\"/tenants/Acme/files/1\");\ncheck if resource($r), tenant($t), $r.capture(\"^/tenants/([^/]+)/\").to_lowercase() == $t;\n"
+        );
+
+        let authorize = |code: &str| {
+            let mut authorizer = biscuit.authorizer().unwrap();
+            authorizer.add_code(code).unwrap();
+            authorizer.authorize_with_limits(limits())
+        };
+        assert_eq!(authorize(r#"tenant("acme"); allow if true;"#), Ok(0));
+        assert!(authorize(r#"tenant("other"); allow if true;"#).is_err());
+        assert_eq!(
+            authorize(
+                r#"tenant("acme");
+                roles(" admin,reader ");
+                allow if roles($r), $r.trim().split(",") == ["admin", "reader"],
+                  resource($p), $p.capture("[0-9]+") == "1";"#
+            ),
+            Ok(0)
+        );
+        // a rule can check that the string matches before capturing
+        assert_eq!(
+            authorize(
+                r#"tenant("acme");
+                user($p) <- resource($p), $p.matches("^/users/"), $p.capture("^/users/(.*)") != "";
+                deny if user($p);
+                allow if true;"#
+            ),
+            Ok(1)
+        );
+        assert_eq!(
+            authorize(
+                r#"tenant("acme"); allow if resource($p), $p.capture("^/users/(.*)") == "";"#
+            ),
+            Err(Token::Execution(Expression::NoMatch))
+        );
+        assert_eq!(
+            authorize(r#"tenant("acme"); allow if resource($p), $p.capture("([0-9]+") == "1";"#),
+            Err(Token::Execution(Expression::InvalidRegex))
+        );
+    }
+
+    #[test]
+    fn third_party_pipeline() {
+        let root = KeyPair::new();
+        let first = KeyPair::new();
+        let second = KeyPair::new();
+
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(format!(
+                "check if first(true) trusting {}; check if second(true) trusting {}",
+                first.public(),
+                second.public()
+            ))
+            .unwrap();
+        let biscuit1 = builder.build(&root).unwrap();
+
+        let sign = |keypair: &KeyPair, fact: &str, request: ThirdPartyRequest| {
+            let mut builder = BlockBuilder::new();
+            builder.add_fact(fact)?;
+            request.create_block(&keypair.private(), builder)
+        };
+
+        let mut calls = 0;
+        let mut pipeline = ThirdPartyPipeline::new()
+            .hop("first", first.public(), |request| {
+                calls += 1;
+                sign(&first, "first(true)", request)
+            })
+            .hop("second", second.public(), |request| {
+                // the request is for the token with the first block
+                let request = ThirdPartyRequest::deserialize(&request.serialize()?)?;
+                sign(&second, "second(true)", request)
+            });
+        assert_eq!(pipeline.len(), 2);
+        let biscuit2 = pipeline.run(&biscuit1).unwrap();
+        drop(pipeline);
+        assert_eq!(calls, 1);
+        assert_eq!(biscuit2.block_count(), 3);
+        assert_eq!(biscuit2.external_public_keys()[1], Some(first.public()));
+        assert_eq!(biscuit2.external_public_keys()[2], Some(second.public()));
+
+        let mut authorizer = biscuit2.authorizer().unwrap();
+        authorizer.add_code("allow if true").unwrap();
+        authorizer.set_limits(limits());
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        // the second hop signs with the wrong key
+        let mut pipeline = ThirdPartyPipeline::new()
+            .hop("first", first.public(), |request| {
+                sign(&first, "first(true)", request)
+            })
+            .hop("second", second.public(), |request| {
+                sign(&first, "second(true)", request)
+            });
+        assert_eq!(
+            pipeline.run(&biscuit1).unwrap_err(),
+            Token::ThirdPartyHop {
+                hop: 1,
+                name: "second".to_string(),
+                error: Box::new(Token::Format(Format::UnknownExternalKey)),
+            }
+        );
+
+        let mut pipeline = ThirdPartyPipeline::new().hop("first", first.public(), |request| {
+            sign(&first, "first(", request)
+        });
+        assert!(matches!(
+            pipeline.run(&biscuit1),
+            Err(Token::ThirdPartyHop { hop: 0, .. })
+        ));
+        assert_eq!(
+            ThirdPartyPipeline::new()
+                .run(&biscuit1)
+                .unwrap()
+                .block_count(),
+            1
+        );
+    }
+
+    #[test]
+    fn from_with_keys() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let keys: Vec<KeyPair> = (0..3).map(|_| KeyPair::new_with_rng(&mut rng)).collect();
+        let candidates: Vec<PublicKey> = keys.iter().map(|k| k.public()).collect();
+
+        let token = Biscuit::builder()
+            .build(&keys[2])
+            .unwrap()
+            .to_vec()
+            .unwrap();
+        let verified = Biscuit::from_with_keys(&token, &candidates).unwrap();
+        assert_eq!(
+            verified.verified_root_key(),
+            Some(VerifiedRootKey {
+                index: 2,
+                public_key: keys[2].public()
+            })
+        );
+        assert_eq!(
+            Biscuit::from(&token, keys[2].public())
+                .unwrap()
+                .verified_root_key(),
+            None
+        );
+
+        // the root key id is tried first
+        let mut builder = Biscuit::builder();
+        builder.set_root_key_id(1);
+        let hinted = builder.build(&keys[1]).unwrap();
+        let hinted = Biscuit::from_with_keys(hinted.to_vec().unwrap(), &candidates).unwrap();
+        assert_eq!(hinted.verified_root_key().unwrap().index, 1);
+
+        // the verified key is kept when attenuating
+        let attenuated = verified.append(BlockBuilder::new()).unwrap();
+        assert_eq!(attenuated.verified_root_key().unwrap().index, 2);
+
+        let mut authorizer = attenuated.authorizer().unwrap();
+        authorizer.set_limits(limits());
+        authorizer.add_code("allow if true").unwrap();
+        authorizer.authorize().unwrap();
+        let facts: Vec<(i64, String)> = authorizer
+            .query("data($i, $k) <- biscuit::root_key($i, $k)")
+            .unwrap();
+        assert_eq!(facts, vec![(2, keys[2].public().to_string())]);
+
+        // the blocks cannot forge the fact
+        let forged_fact = format!(r#"biscuit::root_key(0, "{}")"#, keys[0].public());
+        let mut builder = Biscuit::builder();
+        builder.add_fact(forged_fact.as_str()).unwrap();
+        let forged = builder.build(&keys[1]).unwrap();
+        let forged = Biscuit::from_with_keys(forged.to_vec().unwrap(), &candidates).unwrap();
+        assert_eq!(
+            forged.authorizer().err(),
+            Some(Token::FailedLogic(Logic::ReservedPredicate {
+                block_id: 0,
+                predicate: "biscuit::root_key".to_string(),
+            }))
+        );
+
+        let mut block = BlockBuilder::new();
+        block
+            .add_rule(format!("{} <- true", forged_fact).as_str())
+            .unwrap();
+        let forged = verified.append(block).unwrap();
+        assert!(matches!(
+            forged.authorizer(),
+            Err(Token::FailedLogic(Logic::ReservedPredicate {
+                block_id: 1,
+                ..
+            }))
+        ));
+
+        // tokens can use their own `root_key` facts
+        let mut builder = Biscuit::builder();
+        builder.add_fact(r#"root_key(0, "key")"#).unwrap();
+        let own = builder.build(&keys[2]).unwrap();
+        let own = Biscuit::from_with_keys(own.to_vec().unwrap(), &candidates).unwrap();
+        let mut authorizer = own.authorizer().unwrap();
+        authorizer.set_limits(limits());
+        authorizer
+            .add_code(r#"allow if root_key(0, "key"), biscuit::root_key(2, $k)"#)
+            .unwrap();
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        // the fact is removed with the token
+        let mut authorizer = attenuated.authorizer().unwrap();
+        authorizer.reset_token_data().unwrap();
+        let facts: Vec<(i64, String)> = authorizer
+            .query("data($i, $k) <- biscuit::root_key($i, $k)")
+            .unwrap();
+        assert!(facts.is_empty());
+
+        let res = Biscuit::from_with_keys(&token, &candidates[..2]);
+        assert!(matches!(
+            res,
+            Err(error::Token::Format(error::Format::Signature(_)))
+        ));
+        assert_eq!(
+            Biscuit::from_with_keys(&token, &[]).unwrap_err(),
+            error::Token::Format(error::Format::UnknownPublicKey)
+        );
+    }
+
+    #[test]
+    fn security_policy() {
+        let mut rng: StdRng = SeedableRng::seed_from_u64(0);
+        let root = KeyPair::new_with_rng(&mut rng);
+        let policy = SecurityPolicy::hardened();
+
+        let mut block = BlockBuilder::new();
+        block.add_fact(r#"data("a")"#).unwrap();
+        let token = Biscuit::builder()
+            .build_with_rng(&root, default_symbol_table(), &mut rng)
+            .unwrap()
+            .append_with_keypair(&KeyPair::new_with_rng(&mut rng), block)
+            .unwrap();
+        let serialized = token.to_vec().unwrap();
+
+        let token =
+            Biscuit::from_with_security_policy(&serialized, root.public(), &policy).unwrap();
+        assert!(Biscuit::from_with_security_policy(
+            &serialized,
+            KeyPair::new_with_rng(&mut rng).public(),
+            &policy
+        )
+        .is_err());
+
+        // a proof that does not match the last block
+        let mut forged = token.container().clone();
+        forged.proof = crypto::TokenNext::Secret(KeyPair::new_with_rng(&mut rng).private());
+        let forged = forged.to_vec().unwrap();
+        for policy in [SecurityPolicy::default(), policy] {
+            assert!(matches!(
+                Biscuit::from_with_security_policy(&forged, root.public(), &policy),
+                Err(error::Token::Format(error::Format::Signature(
+                    error::Signature::InvalidSignature(_)
+                )))
+            ));
+        }
+
+        let ids = token.revocation_identifiers();
+        let mut revoked = std::collections::BTreeSet::new();
+        revoked.insert(ids[1].clone());
+        revoked.insert(vec![0; 64]);
+        assert_eq!(revoked.revoked_constant_time(&ids), vec![1]);
+        let store = MemoryRevocationStore::new();
+        store.revoke(&ids[0]);
+        assert_eq!(store.revoked_constant_time(&ids), store.revoked(&ids));
+
+        let mut authorizer = token.authorizer().unwrap();
+        authorizer.set_security_policy(policy);
+        authorizer.revocation_check(revoked);
+        authorizer.add_code("allow if true").unwrap();
+        assert_eq!(
+            authorizer.authorize(),
+            Err(error::Token::Revoked { block_ids: vec![1] })
+        );
+    }
+
+    #[test]
+    fn validity_window() {
+        use crate::builder_ext::BuilderExt;
+
+        let root = KeyPair::new();
+        let start = SystemTime::UNIX_EPOCH + Duration::from_secs(1_000);
+        let end = SystemTime::UNIX_EPOCH + Duration::from_secs(2_000);
+
+        let mut builder = Biscuit::builder();
+        builder.check_validity_window(start, end);
+        let biscuit = builder.build(&root).unwrap();
+        assert_eq!(
+            biscuit.print_block_source(0).unwrap(),
+            "check if time($time), $time >= 1970-01-01T00:16:40Z;\n\
+            check if time($time), $time <= 1970-01-01T00:33:20Z;\n"
+        );
+
+        let authorize = |time: &str| {
+            let mut authorizer = biscuit.authorizer().unwrap();
+            authorizer
+                .add_code(format!("time({time}); allow if true;"))
+                .unwrap();
+            authorizer.authorize_with_limits(limits())
+        };
+
+        assert!(authorize("1970-01-01T00:10:00Z").is_err());
+        assert_eq!(authorize("1970-01-01T00:16:40Z"), Ok(0));
+        assert_eq!(authorize("1970-01-01T00:30:00Z"), Ok(0));
+        assert!(authorize("1970-01-01T00:40:00Z").is_err());
+
+        // the same checks in the authorizer
+        let mut authorizer = Authorizer::new();
+        authorizer.check_validity_window(start, end);
+        authorizer
+            .add_code("time(1970-01-01T00:10:00Z); allow if true;")
+            .unwrap();
+        assert!(authorizer.authorize_with_limits(limits()).is_err());
+    }
+
+    #[test]
+    fn external_signer() {
+        use crate::crypto::{Signature, Signer};
+        use std::cell::Cell;
+
+        // stands for a key stored in a hardware security module
+        struct CountingSigner {
+            keypair: KeyPair,
+            signatures: Cell<usize>,
+        }
+
+        impl Signer for CountingSigner {
+            fn public(&self) -> PublicKey {
+                self.keypair.public()
+            }
+
+            fn sign(&self, data: &[u8]) -> Result<Signature, error::Format> {
+                self.signatures.set(self.signatures.get() + 1);
+                Signer::sign(&self.keypair, data)
+            }
+        }
+
+        let root = CountingSigner {
+            keypair: KeyPair::new_with_algorithm(crate::builder::Algorithm::P256),
+            signatures: Cell::new(0),
+        };
+        let external = CountingSigner {
+            keypair: KeyPair::new(),
+            signatures: Cell::new(0),
+        };
+        let mut builder = Biscuit::builder();
+        builder
+            .add_code(format!(
+                r#"user("alice"); check if group("admin") trusting {}"#,
+                external.public()
+            ))
+            .unwrap();
+        let biscuit1 = builder.build(&root).unwrap();
+        assert_eq!(root.signatures.get(), 1);
+        let biscuit1 = Biscuit::from(biscuit1.to_vec().unwrap(), root.public()).unwrap();
+
+        let mut block = BlockBuilder::new();
+        block.add_fact(r#"group("admin")"#).unwrap();
+        let block = biscuit1
+            .third_party_request()
+            .unwrap()
+            .create_block_with_signer(&external, block)
+            .unwrap();
+        assert_eq!(external.signatures.get(), 1);
+        let biscuit2 = biscuit1
+            .append_third_party(external.public(), block)
+            .unwrap();
+        let biscuit2 = Biscuit::from(biscuit2.to_vec().unwrap(), root.public()).unwrap();
+
+        let mut authorizer = biscuit2.authorizer().unwrap();
+        authorizer.add_code(r#"allow if user("alice")"#).unwrap();
+        assert_eq!(authorizer.authorize_with_limits(limits()), Ok(0));
+
+        // smart pointers to a key pair are signers too
+        let root = std::sync::Arc::new(KeyPair::new());
+        assert!(Biscuit::builder().build(&root).is_ok());
+        let root = Box::new(KeyPair::new());
+        assert!(Biscuit::builder().build(&root).is_ok());
+    }
+
+    #[test]
+    fn from_reader() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.add_fact(r#"user("alice")"#).unwrap();
+        let token = builder.build(&root).unwrap();
+        let token = token.append(BlockBuilder::new()).unwrap();
+        let token = token.append(BlockBuilder::new()).unwrap();
+        let serialized = token.to_vec().unwrap();
+
+        let read = Biscuit::from_reader(&serialized[..], root.public()).unwrap();
+        assert_eq!(read.to_vec().unwrap(), serialized);
+
+        let read_with = |limits: TokenSizeLimits| {
+            Biscuit::from_reader_with_limits(&serialized[..], root.public(), &limits)
+        };
+        assert!(read_with(TokenSizeLimits {
+            max_size: serialized.len(),
+            max_blocks: 3,
+            ..Default::default()
+        })
+        .is_ok());
+        assert!(matches!(
+            read_with(TokenSizeLimits {
+                max_size: serialized.len() - 1,
+                ..Default::default()
+            }),
+            Err(error::Token::Format(error::Format::DeserializationError(_)))
+        ));
+        assert!(read_with(TokenSizeLimits {
+            max_blocks: 2,
+            ..Default::default()
+        })
+        .is_err());
+        assert!(read_with(TokenSizeLimits {
+            max_block_size: 16,
+            ..Default::default()
+        })
+        .is_err());
+
+        // a truncated token is refused
+        assert!(Biscuit::from_reader(&serialized[..serialized.len() - 10], root.public()).is_err());
+        // the announced length of a block is checked before reading it
+        let mut huge = vec![0x12];
+        huge.extend([0xff, 0xff, 0xff, 0xff, 0x0f]);
+        assert!(Biscuit::from_reader(&huge[..], root.public()).is_err());
+        // a field announcing a length close to `u64::MAX` does not overflow
+        // the size check, and the rest of the input is not read
+        let overflow = [
+            0x0a, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01,
+        ];
+        let mut input = std::io::Read::chain(&overflow[..], std::io::repeat(0));
+        assert!(matches!(
+            Biscuit::from_reader(&mut input, root.public()),
+            Err(error::Token::Format(error::Format::DeserializationError(_)))
+        ));
+    }
+
+    #[test]
+    fn block_context() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.add_fact(r#"user("alice")"#).unwrap();
+        let token = builder.build(&root).unwrap();
+
+        let mut block = BlockBuilder::new().context("issued by\nthe gateway".to_string());
+        block.add_check(r#"check if operation("read")"#).unwrap();
+        let token = token.append(block).unwrap();
+
+        let token = Biscuit::from(token.to_vec().unwrap(), root.public()).unwrap();
+        assert_eq!(token.block_context(0), Ok(None));
+        assert_eq!(
+            token.block_context(1),
+            Ok(Some("issued by\nthe gateway".to_string()))
+        );
+        assert_eq!(
+            token.block_context(2),
+            Err(Token::Format(Format::InvalidBlockId(2)))
+        );
+
+        assert_eq!(token.print_block_source(0).unwrap(), "user(\"alice\");\n");
+        let source = token.print_block_source(1).unwrap();
+        assert_eq!(
+            source,
+            "// context: issued by\n// the gateway\ncheck if operation(\"read\");\n"
+        );
+
+        // the printed source can be parsed again
+        let mut block = BlockBuilder::new();
+        block.add_code(&source).unwrap();
+        assert_eq!(block.checks.len(), 1);
+    }
+
+    #[test]
+    fn unverified_introspection() {
+        let root = KeyPair::new();
+        let external = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.add_fact(r#"user("alice")"#).unwrap();
+        builder.set_context("authority".to_string());
+        let token = builder.build(&root).unwrap();
+
+        let mut block = BlockBuilder::new().context("attenuation".to_string());
+        block.check_expiration_date(UNIX_EPOCH + Duration::from_secs(1_800_000_000));
+        let token = token.append(block).unwrap();
+
+        let req = token.third_party_request().unwrap();
+        let mut builder = BlockBuilder::new();
+        builder.add_fact(r#"group("admins")"#).unwrap();
+        let res = req.create_block(&external.private(), builder).unwrap();
+        let token = token.append_third_party(external.public(), res).unwrap();
+        let token = token
+            .seal()
+            .unwrap()
+            .counter_sign(&KeyPair::new(), UNIX_EPOCH)
+            .unwrap();
+
+        let unverified = UnverifiedBiscuit::from(token.to_vec().unwrap()).unwrap();
+        assert_eq!(unverified.context(), token.context());
+        assert_eq!(
+            unverified.block_context(1),
+            Ok(Some("attenuation".to_string()))
+        );
+        assert_eq!(
+            unverified.block_context(3),
+            Err(Token::Format(Format::InvalidBlockId(3)))
+        );
+        assert_eq!(unverified.expiration(), token.expiration());
+        assert!(unverified.expiration().unwrap().is_some());
+        assert_eq!(
+            unverified.public_key_to_block_id(),
+            token.public_key_to_block_id()
+        );
+        assert_eq!(unverified.counter_signatures(), token.counter_signatures());
+        assert_eq!(unverified.serialized_size(), token.serialized_size());
+        for i in 0..token.block_count() {
+            assert_eq!(unverified.block_symbols(i), token.block_symbols(i));
+            assert_eq!(unverified.block_public_keys(i), token.block_public_keys(i));
+        }
+        assert_eq!(unverified.symbol_report(), token.symbol_report());
+    }
+
+    #[test]
+    fn extension_schema_numbers() {
+        use crate::format::{convert::proto_block_to_token_block, schema};
+        use prost::Message;
+
+        // the versions 5 and 6 of the specification are not supported
+        for version in [5, 6].iter() {
+            let block = schema::Block {
+                version: Some(*version),
+                ..Default::default()
+            };
+            assert_eq!(
+                proto_block_to_token_block(&block, None).unwrap_err(),
+                error::Format::Version {
+                    minimum: MIN_SCHEMA_VERSION,
+                    maximum: MAX_SPEC_SCHEMA_VERSION,
+                    actual: *version,
+                }
+            );
+        }
+        let block = schema::Block {
+            version: Some(1003),
+            ..Default::default()
+        };
+        assert_eq!(
+            proto_block_to_token_block(&block, None).unwrap_err(),
+            error::Format::ExtensionVersion {
+                minimum: EXTENSION_SCHEMA_VERSION_1,
+                maximum: MAX_SCHEMA_VERSION,
+                actual: 1003,
+            }
+        );
+
+        // a decimal is the field 1000 of the term, the field 8 being `null` in
+        // the specification
+        let term = schema::TermV2 {
+            content: Some(schema::term_v2::Content::Decimal(schema::Decimal {
+                mantissa: vec![0; 16],
+                scale: 0,
+            })),
+        };
+        let mut v = Vec::new();
+        term.encode(&mut v).unwrap();
+        assert_eq!(v[..2], [0xc2, 0x3e]);
+
+        // so is the message of a check
+        let check = schema::CheckV2 {
+            queries: vec![],
+            kind: None,
+            message: Some("m".to_string()),
+        };
+        assert_eq!(check.encode_to_vec(), [0xc2, 0x3e, 1, b'm']);
+
+        // and the limits of a snapshot added by this library
+        let limits = schema::RunLimits {
+            max_facts: 1,
+            max_iterations: 2,
+            max_time: 3,
+            max_facts_per_block: Some(4),
+            max_iterations_per_block: Some(5),
+            max_results: Some(6),
+        };
+        assert_eq!(
+            limits.encode_to_vec(),
+            [8, 1, 16, 2, 24, 3, 0xc0, 0x3e, 4, 0xc8, 0x3e, 5, 0xd0, 0x3e, 6]
+        );
+    }
+
+    #[test]
+    fn key_pair_zeroize_on_drop() {
+        fn zeroize_on_drop<T: zeroize::ZeroizeOnDrop>() {}
+
+        // `KeyPair` relies on the signing keys erasing their secret
+        zeroize_on_drop::<ed25519_dalek::SigningKey>();
+        zeroize_on_drop::<p256::ecdsa::SigningKey>();
+        zeroize_on_drop::<KeyPair>();
+        zeroize_on_drop::<crate::crypto::PrivateKey>();
+    }
 }
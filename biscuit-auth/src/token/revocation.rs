@@ -0,0 +1,640 @@
+//! revocation identifiers lookup during authorization
+use std::collections::{BTreeSet, HashSet};
+use std::convert::TryInto;
+use std::sync::{Arc, RwLock};
+
+use sha2::{Digest, Sha256};
+
+use crate::crypto::constant_time_eq;
+use crate::error;
+
+/// Looks up revoked tokens during authorization
+///
+/// Every block of a token has a unique revocation identifier (see
+/// [`Biscuit::revocation_identifiers`](crate::Biscuit::revocation_identifiers)).
+/// Revoking one of them invalidates the block and every token derived from it.
+///
+/// Once registered with [`Authorizer::revocation_check`](crate::Authorizer::revocation_check),
+/// the store is queried before the Datalog evaluation, and the authorization fails
+/// with [`error::Token::Revoked`](crate::error::Token::Revoked) if any of the
+/// token's identifiers is revoked.
+///
+/// If the store cannot answer (network error, etc), implementors should consider
+/// the identifier as revoked.
+pub trait RevocationStore: Send + Sync {
+    /// returns true if this revocation identifier was revoked
+    fn is_revoked(&self, revocation_id: &[u8]) -> bool;
+
+    /// returns the positions of the revoked identifiers in the list
+    ///
+    /// the default implementation calls [`RevocationStore::is_revoked`] for
+    /// each identifier. Stores that can look up multiple identifiers in one
+    /// request should override this method
+    fn revoked(&self, revocation_ids: &[Vec<u8>]) -> Vec<usize> {
+        revocation_ids
+            .iter()
+            .enumerate()
+            .filter(|(_, id)| self.is_revoked(id))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    /// returns the positions of the revoked identifiers in the list, comparing
+    /// them in constant time
+    ///
+    /// this is used instead of [`RevocationStore::revoked`] by authorizers with a
+    /// hardened [`SecurityPolicy`](crate::SecurityPolicy). There is no default
+    /// implementation, so that a store cannot silently fall back to variable
+    /// time lookups: stores keeping the identifiers in memory should compare
+    /// every identifier, without hash or tree lookups, and remote stores
+    /// should delegate the comparison to a service doing so
+    fn revoked_constant_time(&self, revocation_ids: &[Vec<u8>]) -> Vec<usize>;
+}
+
+/// positions of the identifiers found in `revoked`, looking at every element of
+/// `revoked` for each identifier
+fn scan_revoked<'a, I>(revoked: I, revocation_ids: &[Vec<u8>]) -> Vec<usize>
+where
+    I: Iterator<Item = &'a Vec<u8>> + Clone,
+{
+    revocation_ids
+        .iter()
+        .enumerate()
+        .filter(|(_, id)| {
+            revoked
+                .clone()
+                .fold(false, |found, r| found | constant_time_eq(r, id))
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+impl RevocationStore for Box<dyn RevocationStore> {
+    fn is_revoked(&self, revocation_id: &[u8]) -> bool {
+        self.as_ref().is_revoked(revocation_id)
+    }
+
+    fn revoked(&self, revocation_ids: &[Vec<u8>]) -> Vec<usize> {
+        self.as_ref().revoked(revocation_ids)
+    }
+    fn revoked_constant_time(&self, revocation_ids: &[Vec<u8>]) -> Vec<usize> {
+        self.as_ref().revoked_constant_time(revocation_ids)
+    }
+}
+
+impl RevocationStore for Arc<dyn RevocationStore> {
+    fn is_revoked(&self, revocation_id: &[u8]) -> bool {
+        self.as_ref().is_revoked(revocation_id)
+    }
+
+    fn revoked(&self, revocation_ids: &[Vec<u8>]) -> Vec<usize> {
+        self.as_ref().revoked(revocation_ids)
+    }
+    fn revoked_constant_time(&self, revocation_ids: &[Vec<u8>]) -> Vec<usize> {
+        self.as_ref().revoked_constant_time(revocation_ids)
+    }
+}
+
+impl RevocationStore for HashSet<Vec<u8>> {
+    fn is_revoked(&self, revocation_id: &[u8]) -> bool {
+        self.contains(revocation_id)
+    }
+    fn revoked_constant_time(&self, revocation_ids: &[Vec<u8>]) -> Vec<usize> {
+        scan_revoked(self.iter(), revocation_ids)
+    }
+}
+
+impl RevocationStore for BTreeSet<Vec<u8>> {
+    fn is_revoked(&self, revocation_id: &[u8]) -> bool {
+        self.contains(revocation_id)
+    }
+    fn revoked_constant_time(&self, revocation_ids: &[Vec<u8>]) -> Vec<usize> {
+        scan_revoked(self.iter(), revocation_ids)
+    }
+}
+
+/// the closure is called for each identifier: comparing in constant time is
+/// up to the closure
+impl<F: Fn(&[u8]) -> bool + Send + Sync> RevocationStore for F {
+    fn is_revoked(&self, revocation_id: &[u8]) -> bool {
+        self(revocation_id)
+    }
+    fn revoked_constant_time(&self, revocation_ids: &[Vec<u8>]) -> Vec<usize> {
+        self.revoked(revocation_ids)
+    }
+}
+
+/// In-memory list of revoked identifiers
+///
+/// Clones share the same list, so identifiers revoked after the store was
+/// registered in an authorizer are taken into account.
+///
+/// ```rust
+/// use biscuit_auth::{Biscuit, KeyPair, MemoryRevocationStore};
+///
+/// let root = KeyPair::new();
+/// let token = Biscuit::builder().build(&root).unwrap();
+/// let serialized = token.to_vec().unwrap();
+///
+/// let store = MemoryRevocationStore::new();
+/// store.revoke(&token.revocation_identifiers()[0]);
+///
+/// assert!(Biscuit::from_with_revocation(&serialized, root.public(), &store).is_err());
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct MemoryRevocationStore(Arc<RwLock<HashSet<Vec<u8>>>>);
+
+impl MemoryRevocationStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// adds a revoked identifier
+    pub fn revoke(&self, revocation_id: &[u8]) {
+        self.0
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(revocation_id.to_vec());
+    }
+
+    /// removes a revoked identifier, returns false if it was not revoked
+    pub fn unrevoke(&self, revocation_id: &[u8]) -> bool {
+        self.0
+            .write()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(revocation_id)
+    }
+
+    /// number of revoked identifiers
+    pub fn len(&self) -> usize {
+        self.0.read().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl RevocationStore for MemoryRevocationStore {
+    fn is_revoked(&self, revocation_id: &[u8]) -> bool {
+        self.0
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .contains(revocation_id)
+    }
+
+    fn revoked(&self, revocation_ids: &[Vec<u8>]) -> Vec<usize> {
+        let revoked = self.0.read().unwrap_or_else(|e| e.into_inner());
+        revocation_ids
+            .iter()
+            .enumerate()
+            .filter(|(_, id)| revoked.contains(*id))
+            .map(|(i, _)| i)
+            .collect()
+    }
+
+    fn revoked_constant_time(&self, revocation_ids: &[Vec<u8>]) -> Vec<usize> {
+        let revoked = self.0.read().unwrap_or_else(|e| e.into_inner());
+        scan_revoked(revoked.iter(), revocation_ids)
+    }
+}
+
+/// Revoked identifiers persisted in a file
+///
+/// The file contains one hex encoded identifier per line. It is loaded when the
+/// store is opened, and revoked identifiers are appended to it, so that they
+/// survive a restart. Other processes writing to the same file are not observed:
+/// reopen the store to reload it.
+#[cfg(feature = "revocation-file")]
+#[cfg_attr(feature = "docsrs", doc(cfg(feature = "revocation-file")))]
+#[derive(Debug)]
+pub struct FileRevocationStore {
+    memory: MemoryRevocationStore,
+    file: std::sync::Mutex<std::fs::File>,
+}
+
+#[cfg(feature = "revocation-file")]
+impl FileRevocationStore {
+    /// opens the file, creating it if it does not exist
+    pub fn open<P: AsRef<std::path::Path>>(path: P) -> std::io::Result<Self> {
+        use std::io::{BufRead, BufReader, Seek, SeekFrom};
+
+        let mut file = std::fs::OpenOptions::new()
+            .read(true)
+            .append(true)
+            .create(true)
+            .open(path)?;
+
+        let memory = MemoryRevocationStore::new();
+        for line in BufReader::new(&mut file).lines() {
+            let line = line?;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+            let id = hex::decode(line)
+                .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))?;
+            memory.revoke(&id);
+        }
+        file.seek(SeekFrom::End(0))?;
+
+        Ok(FileRevocationStore {
+            memory,
+            file: std::sync::Mutex::new(file),
+        })
+    }
+
+    /// adds a revoked identifier and writes it to the file
+    pub fn revoke(&self, revocation_id: &[u8]) -> std::io::Result<()> {
+        use std::io::Write;
+
+        if self.memory.is_revoked(revocation_id) {
+            return Ok(());
+        }
+
+        let mut file = self.file.lock().unwrap_or_else(|e| e.into_inner());
+        writeln!(file, "{}", hex::encode(revocation_id))?;
+        file.flush()?;
+        self.memory.revoke(revocation_id);
+        Ok(())
+    }
+
+    /// number of revoked identifiers
+    pub fn len(&self) -> usize {
+        self.memory.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.memory.is_empty()
+    }
+}
+
+#[cfg(feature = "revocation-file")]
+impl RevocationStore for FileRevocationStore {
+    fn is_revoked(&self, revocation_id: &[u8]) -> bool {
+        self.memory.is_revoked(revocation_id)
+    }
+
+    fn revoked(&self, revocation_ids: &[Vec<u8>]) -> Vec<usize> {
+        self.memory.revoked(revocation_ids)
+    }
+    fn revoked_constant_time(&self, revocation_ids: &[Vec<u8>]) -> Vec<usize> {
+        self.memory.revoked_constant_time(revocation_ids)
+    }
+}
+
+/// Bloom filter of revoked identifiers
+///
+/// The filter answers in constant time and memory, with a configurable rate
+/// of false positives: an identifier that was never revoked may be reported
+/// as revoked. If an exact store is set with [`BloomRevocationChecker::with_fallback`],
+/// it is queried when the filter matches, so the false positives only cost a
+/// lookup in that store.
+///
+/// Clones share the same filter: identifiers added to the checker after it was
+/// registered in an authorizer are taken into account. Nodes can exchange
+/// snapshots of their filter with [`BloomRevocationChecker::to_bytes`], and
+/// combine them with [`BloomRevocationChecker::merge`].
+///
+/// ```rust
+/// use biscuit_auth::BloomRevocationChecker;
+///
+/// let checker = BloomRevocationChecker::new(10_000, 0.001);
+/// checker.insert(b"revoked id");
+///
+/// let other_node = BloomRevocationChecker::new(10_000, 0.001);
+/// other_node.merge(&checker.to_bytes()).unwrap();
+/// assert!(other_node.might_contain(b"revoked id"));
+/// ```
+#[derive(Clone)]
+pub struct BloomRevocationChecker {
+    bits: Arc<RwLock<Vec<u64>>>,
+    num_bits: u64,
+    num_hashes: u32,
+    fallback: Option<Arc<dyn RevocationStore>>,
+}
+
+const BLOOM_FORMAT_VERSION: u8 = 1;
+const BLOOM_HEADER_SIZE: usize = 13;
+/// upper bound on the number of hashes, so that a snapshot cannot make every
+/// lookup arbitrarily slow
+const BLOOM_MAX_HASHES: u32 = 64;
+
+impl BloomRevocationChecker {
+    /// creates a filter sized for `expected_items` identifiers and the requested
+    /// rate of false positives
+    ///
+    /// the rate is clamped between 1e-9 and 0.5
+    pub fn new(expected_items: usize, false_positive_rate: f64) -> Self {
+        let n = expected_items.max(1) as f64;
+        let p = false_positive_rate.clamp(1e-9, 0.5);
+        let ln2 = std::f64::consts::LN_2;
+
+        let num_bits = ((-n * p.ln() / (ln2 * ln2)).ceil() as u64).max(64);
+        let num_hashes = ((num_bits as f64 / n * ln2).round() as u32).clamp(1, BLOOM_MAX_HASHES);
+
+        Self::with_parameters(num_bits, num_hashes)
+    }
+
+    fn with_parameters(num_bits: u64, num_hashes: u32) -> Self {
+        let words = num_bits.div_ceil(64) as usize;
+        BloomRevocationChecker {
+            bits: Arc::new(RwLock::new(vec![0; words])),
+            num_bits: words as u64 * 64,
+            num_hashes,
+            fallback: None,
+        }
+    }
+
+    /// queries an exact store when the filter matches an identifier
+    pub fn with_fallback<S: RevocationStore + 'static>(mut self, store: S) -> Self {
+        self.fallback = Some(Arc::new(store));
+        self
+    }
+
+    /// number of bits of the filter
+    pub fn num_bits(&self) -> u64 {
+        self.num_bits
+    }
+
+    /// number of hash functions used for each identifier
+    pub fn num_hashes(&self) -> u32 {
+        self.num_hashes
+    }
+
+    /// adds a revoked identifier
+    pub fn insert(&self, revocation_id: &[u8]) {
+        let positions = self.positions(revocation_id);
+        let mut bits = self.bits.write().unwrap_or_else(|e| e.into_inner());
+        for position in positions {
+            let (word, mask) = Self::bit(position);
+            bits[word] |= mask;
+        }
+    }
+
+    /// returns true if the identifier may have been revoked, false if it was
+    /// certainly not revoked
+    pub fn might_contain(&self, revocation_id: &[u8]) -> bool {
+        let mut positions = self.positions(revocation_id);
+        let bits = self.bits.read().unwrap_or_else(|e| e.into_inner());
+        positions.all(|position| {
+            let (word, mask) = Self::bit(position);
+            bits[word] & mask != 0
+        })
+    }
+
+    /// index of the word containing a bit position, and the mask of the bit
+    fn bit(position: u64) -> (usize, u64) {
+        ((position / 64) as usize, 1 << (position % 64))
+    }
+
+    /// positions of the identifiers matching the filter, confirmed with
+    /// `lookup` on the fallback store if there is one
+    fn revoked_with<F>(&self, revocation_ids: &[Vec<u8>], lookup: F) -> Vec<usize>
+    where
+        F: FnOnce(&dyn RevocationStore, &[Vec<u8>]) -> Vec<usize>,
+    {
+        let candidates: Vec<usize> = revocation_ids
+            .iter()
+            .enumerate()
+            .filter(|(_, id)| self.might_contain(id))
+            .map(|(i, _)| i)
+            .collect();
+
+        match &self.fallback {
+            None => candidates,
+            Some(store) => {
+                let ids: Vec<Vec<u8>> = candidates
+                    .iter()
+                    .map(|i| revocation_ids[*i].clone())
+                    .collect();
+                lookup(store.as_ref(), &ids)
+                    .into_iter()
+                    .map(|i| candidates[i])
+                    .collect()
+            }
+        }
+    }
+
+    /// bit positions of an identifier, with double hashing over SHA256, so that
+    /// all nodes agree on them
+    fn positions(&self, revocation_id: &[u8]) -> impl Iterator<Item = u64> {
+        let hash = Sha256::digest(revocation_id);
+        let h1 = u64::from_le_bytes(hash[0..8].try_into().unwrap());
+        let h2 = u64::from_le_bytes(hash[8..16].try_into().unwrap()) | 1;
+        let num_bits = self.num_bits;
+        (0..u64::from(self.num_hashes)).map(move |i| h1.wrapping_add(i.wrapping_mul(h2)) % num_bits)
+    }
+
+    /// serializes the filter, to distribute it to other nodes
+    ///
+    /// the fallback store is not part of the snapshot
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let bits = self.bits.read().unwrap_or_else(|e| e.into_inner());
+        let mut v = Vec::with_capacity(BLOOM_HEADER_SIZE + bits.len() * 8);
+        v.push(BLOOM_FORMAT_VERSION);
+        v.extend_from_slice(&self.num_hashes.to_le_bytes());
+        v.extend_from_slice(&self.num_bits.to_le_bytes());
+        for word in bits.iter() {
+            v.extend_from_slice(&word.to_le_bytes());
+        }
+        v
+    }
+
+    /// deserializes a filter created by [`BloomRevocationChecker::to_bytes`]
+    ///
+    /// snapshots using more than 64 hashes are refused
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, error::Format> {
+        let (num_hashes, num_bits, words) = Self::parse(bytes)?;
+        let checker = Self::with_parameters(num_bits, num_hashes);
+        *checker.bits.write().unwrap_or_else(|e| e.into_inner()) = words;
+        Ok(checker)
+    }
+
+    /// adds the identifiers of a snapshot from another node
+    ///
+    /// the snapshot must come from a filter with the same size and number of hashes
+    pub fn merge(&self, snapshot: &[u8]) -> Result<(), error::Format> {
+        let (num_hashes, num_bits, words) = Self::parse(snapshot)?;
+        if num_hashes != self.num_hashes || num_bits != self.num_bits {
+            return Err(error::Format::DeserializationError(
+                "bloom filter parameters do not match".to_string(),
+            ));
+        }
+
+        let mut bits = self.bits.write().unwrap_or_else(|e| e.into_inner());
+        for (word, other) in bits.iter_mut().zip(words) {
+            *word |= other;
+        }
+        Ok(())
+    }
+
+    fn parse(bytes: &[u8]) -> Result<(u32, u64, Vec<u64>), error::Format> {
+        let invalid = || error::Format::DeserializationError("invalid bloom filter".to_string());
+
+        if bytes.len() < BLOOM_HEADER_SIZE || bytes[0] != BLOOM_FORMAT_VERSION {
+            return Err(invalid());
+        }
+        let num_hashes = u32::from_le_bytes(bytes[1..5].try_into().unwrap());
+        let num_bits = u64::from_le_bytes(bytes[5..13].try_into().unwrap());
+        let data = &bytes[BLOOM_HEADER_SIZE..];
+        if num_hashes == 0
+            || num_hashes > BLOOM_MAX_HASHES
+            || num_bits == 0
+            || num_bits % 64 != 0
+            || data.len() as u64 * 8 != num_bits
+        {
+            return Err(invalid());
+        }
+
+        let words = data
+            .chunks_exact(8)
+            .map(|chunk| u64::from_le_bytes(chunk.try_into().unwrap()))
+            .collect();
+        Ok((num_hashes, num_bits, words))
+    }
+}
+
+impl std::fmt::Debug for BloomRevocationChecker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("BloomRevocationChecker")
+            .field("num_bits", &self.num_bits)
+            .field("num_hashes", &self.num_hashes)
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
+
+impl RevocationStore for BloomRevocationChecker {
+    fn is_revoked(&self, revocation_id: &[u8]) -> bool {
+        self.might_contain(revocation_id)
+            && self
+                .fallback
+                .as_ref()
+                .map(|store| store.is_revoked(revocation_id))
+                .unwrap_or(true)
+    }
+
+    fn revoked(&self, revocation_ids: &[Vec<u8>]) -> Vec<usize> {
+        self.revoked_with(revocation_ids, |store, ids| store.revoked(ids))
+    }
+
+    /// the filter lookups depend on the hashes of the identifiers, and the
+    /// candidates are checked with [`RevocationStore::revoked_constant_time`]
+    /// on the fallback store
+    fn revoked_constant_time(&self, revocation_ids: &[Vec<u8>]) -> Vec<usize> {
+        self.revoked_with(revocation_ids, |store, ids| {
+            store.revoked_constant_time(ids)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn bloom_revocation_checker() {
+        let checker = BloomRevocationChecker::new(1000, 0.01);
+        assert_eq!(checker.num_hashes(), 7);
+        assert!(checker.num_bits() >= 9586);
+
+        for i in 0..1000u32 {
+            checker.insert(&i.to_le_bytes());
+        }
+        assert!((0..1000u32).all(|i| checker.might_contain(&i.to_le_bytes())));
+        let false_positives = (1000..11000u32)
+            .filter(|i| checker.might_contain(&i.to_le_bytes()))
+            .count();
+        assert!(false_positives < 200, "{} false positives", false_positives);
+
+        // snapshots
+        let copy = BloomRevocationChecker::from_bytes(&checker.to_bytes()).unwrap();
+        assert_eq!(copy.to_bytes(), checker.to_bytes());
+        let other = BloomRevocationChecker::new(1000, 0.01);
+        other.insert(b"other");
+        copy.merge(&other.to_bytes()).unwrap();
+        assert!(copy.might_contain(b"other"));
+        assert!(copy.might_contain(&5u32.to_le_bytes()));
+        assert!(copy
+            .merge(&BloomRevocationChecker::new(10, 0.01).to_bytes())
+            .is_err());
+        assert!(BloomRevocationChecker::from_bytes(&[1, 2, 3]).is_err());
+
+        // the number of hashes is bounded
+        let mut snapshot = BloomRevocationChecker::new(10, 0.01).to_bytes();
+        snapshot[1..5].copy_from_slice(&64u32.to_le_bytes());
+        let checker_64 = BloomRevocationChecker::from_bytes(&snapshot).unwrap();
+        assert_eq!(checker_64.num_hashes(), 64);
+        snapshot[1..5].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(BloomRevocationChecker::from_bytes(&snapshot).is_err());
+        assert!(checker_64.merge(&snapshot).is_err());
+        assert!(BloomRevocationChecker::new(1, 0.5).num_hashes() <= 64);
+
+        // clones share the filter
+        let shared = checker.clone();
+        checker.insert(b"late");
+        assert!(shared.is_revoked(b"late"));
+
+        // the exact store is only queried on filter hits
+        let lookups = Arc::new(AtomicUsize::new(0));
+        let counter = lookups.clone();
+        let exact = BloomRevocationChecker::new(1000, 0.01).with_fallback(move |id: &[u8]| {
+            counter.fetch_add(1, Ordering::Relaxed);
+            id == b"revoked"
+        });
+        exact.insert(b"revoked");
+        exact.insert(b"false positive");
+        assert_eq!(
+            exact.revoked(&[
+                b"unknown".to_vec(),
+                b"revoked".to_vec(),
+                b"false positive".to_vec()
+            ]),
+            vec![1]
+        );
+        assert_eq!(lookups.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn memory_revocation_store() {
+        let store = MemoryRevocationStore::new();
+        let shared = store.clone();
+        store.revoke(b"a");
+        store.revoke(b"c");
+
+        assert!(shared.is_revoked(b"a"));
+        assert_eq!(
+            shared.revoked(&[b"a".to_vec(), b"b".to_vec(), b"c".to_vec()]),
+            vec![0, 2]
+        );
+        assert!(store.unrevoke(b"a"));
+        assert!(!store.unrevoke(b"a"));
+        assert!(!shared.is_revoked(b"a"));
+        assert_eq!(shared.len(), 1);
+    }
+
+    #[cfg(feature = "revocation-file")]
+    #[test]
+    fn file_revocation_store() {
+        let path = std::env::temp_dir().join(format!("biscuit-revocation-{}", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let store = FileRevocationStore::open(&path).unwrap();
+        assert!(store.is_empty());
+        store.revoke(b"a").unwrap();
+        store.revoke(b"a").unwrap();
+        store.revoke(&[0, 255]).unwrap();
+        assert!(store.is_revoked(b"a"));
+        drop(store);
+
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "61\n00ff\n");
+        let store = FileRevocationStore::open(&path).unwrap();
+        assert_eq!(store.len(), 2);
+        assert!(store.is_revoked(&[0, 255]));
+        assert!(!store.is_revoked(b"b"));
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
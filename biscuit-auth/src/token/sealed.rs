@@ -0,0 +1,195 @@
+use std::convert::TryFrom;
+use std::fmt;
+use std::time::SystemTime;
+
+use super::authorizer::Authorizer;
+use super::{Biscuit, CounterSignature, RootKeyProvider};
+use crate::crypto::KeyPair;
+use crate::error;
+
+/// a token that was sealed and cannot be attenuated
+///
+/// unlike a sealed [`Biscuit`], which only fails with
+/// [`error::Token::AlreadySealed`] once a block is appended, this type does
+/// not provide any method to add blocks. It is created by
+/// [`Biscuit::into_sealed`], or by deserializing a token that must be sealed.
+///
+/// ```rust
+/// use biscuit_auth::{Biscuit, KeyPair, SealedBiscuit};
+///
+/// let root = KeyPair::new();
+/// let mut builder = Biscuit::builder();
+/// builder.add_fact(r#"user("alice")"#).unwrap();
+/// let sealed = builder.build(&root).unwrap().into_sealed().unwrap();
+///
+/// let data = sealed.to_vec().unwrap();
+/// let sealed = SealedBiscuit::from(&data, root.public()).unwrap();
+///
+/// let mut authorizer = sealed.authorizer().unwrap();
+/// authorizer.add_code("allow if user($u)").unwrap();
+/// authorizer.authorize().unwrap();
+/// ```
+///
+/// Appending a block does not compile:
+///
+/// ```compile_fail
+/// use biscuit_auth::{builder::BlockBuilder, Biscuit, KeyPair};
+///
+/// let sealed = Biscuit::builder().build(&KeyPair::new()).unwrap().into_sealed().unwrap();
+/// sealed.append(BlockBuilder::new());
+/// ```
+#[derive(Clone, Debug)]
+pub struct SealedBiscuit(Biscuit);
+
+impl SealedBiscuit {
+    /// deserializes a token and validates its signature using the root public key
+    ///
+    /// fails with [`error::Token::NotSealed`] if the token was not sealed
+    pub fn from<T, KP>(slice: T, key_provider: KP) -> Result<Self, error::Token>
+    where
+        T: AsRef<[u8]>,
+        KP: RootKeyProvider,
+    {
+        Self::try_from(Biscuit::from(slice, key_provider)?)
+    }
+
+    /// deserializes a token encoded in base64 and validates its signature
+    /// using the root public key
+    ///
+    /// fails with [`error::Token::NotSealed`] if the token was not sealed
+    pub fn from_base64<T, KP>(slice: T, key_provider: KP) -> Result<Self, error::Token>
+    where
+        T: AsRef<[u8]>,
+        KP: RootKeyProvider,
+    {
+        Self::try_from(Biscuit::from_base64(slice, key_provider)?)
+    }
+
+    /// serializes the token
+    pub fn to_vec(&self) -> Result<Vec<u8>, error::Token> {
+        self.0.to_vec()
+    }
+
+    /// serializes the token and encode it to a (URL safe) base64 string
+    pub fn to_base64(&self) -> Result<String, error::Token> {
+        self.0.to_base64()
+    }
+
+    /// creates an authorizer from the token, see [`Biscuit::authorizer`]
+    pub fn authorizer(&self) -> Result<Authorizer, error::Token> {
+        self.0.authorizer()
+    }
+
+    /// adds a counter signature, see [`Biscuit::counter_sign`]
+    pub fn counter_sign(&self, keypair: &KeyPair, time: SystemTime) -> Result<Self, error::Token> {
+        self.0.counter_sign(keypair, time).map(SealedBiscuit)
+    }
+
+    /// returns the counter signatures, see [`Biscuit::counter_signatures`]
+    pub fn counter_signatures(&self) -> Vec<CounterSignature> {
+        self.0.counter_signatures()
+    }
+
+    /// returns the list of revocation identifiers for each block, in order
+    pub fn revocation_identifiers(&self) -> Vec<Vec<u8>> {
+        self.0.revocation_identifiers()
+    }
+
+    /// returns the number of blocks (at least 1)
+    pub fn block_count(&self) -> usize {
+        self.0.block_count()
+    }
+
+    /// returns the underlying token, for the APIs taking a [`Biscuit`]
+    ///
+    /// appending a block to it fails with [`error::Token::AlreadySealed`]
+    pub fn as_biscuit(&self) -> &Biscuit {
+        &self.0
+    }
+
+    /// returns the underlying token
+    pub fn into_biscuit(self) -> Biscuit {
+        self.0
+    }
+}
+
+impl TryFrom<Biscuit> for SealedBiscuit {
+    type Error = error::Token;
+
+    /// fails with [`error::Token::NotSealed`] if the token was not sealed
+    fn try_from(token: Biscuit) -> Result<Self, Self::Error> {
+        if token.is_sealed() {
+            Ok(SealedBiscuit(token))
+        } else {
+            Err(error::Token::NotSealed)
+        }
+    }
+}
+
+impl From<SealedBiscuit> for Biscuit {
+    fn from(token: SealedBiscuit) -> Self {
+        token.0
+    }
+}
+
+impl fmt::Display for SealedBiscuit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(&self.0, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::builder::BlockBuilder;
+    use crate::AuthorizerLimits;
+    use std::time::Duration;
+
+    fn limits() -> AuthorizerLimits {
+        AuthorizerLimits {
+            max_time: Duration::from_secs(10),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sealed_biscuit() {
+        let root = KeyPair::new();
+        let mut builder = Biscuit::builder();
+        builder.add_fact(r#"user("alice")"#).unwrap();
+        let token = builder.build(&root).unwrap();
+        let token = token.append(BlockBuilder::new()).unwrap();
+
+        assert!(matches!(
+            SealedBiscuit::try_from(token.clone()),
+            Err(error::Token::NotSealed)
+        ));
+        assert!(matches!(
+            SealedBiscuit::from(token.to_vec().unwrap(), root.public()),
+            Err(error::Token::NotSealed)
+        ));
+
+        let sealed = token.into_sealed().unwrap();
+        assert_eq!(sealed.block_count(), 2);
+        assert_eq!(sealed.revocation_identifiers().len(), 2);
+
+        let sealed =
+            SealedBiscuit::from_base64(sealed.to_base64().unwrap(), root.public()).unwrap();
+        assert!(sealed.as_biscuit().is_sealed());
+        assert!(matches!(
+            sealed.as_biscuit().append(BlockBuilder::new()),
+            Err(error::Token::AlreadySealed)
+        ));
+
+        let verifier = KeyPair::new();
+        let signed = sealed
+            .counter_sign(&verifier, SystemTime::UNIX_EPOCH)
+            .unwrap();
+        assert_eq!(signed.counter_signatures().len(), 1);
+
+        let mut authorizer = signed.authorizer().unwrap();
+        authorizer.set_limits(limits());
+        authorizer.add_code("allow if user(\"alice\")").unwrap();
+        assert_eq!(authorizer.authorize(), Ok(0));
+    }
+}
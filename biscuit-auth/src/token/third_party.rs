@@ -1,25 +1,71 @@
-use ed25519_dalek::Signer;
+use std::collections::HashMap;
+use std::ops::{Bound, RangeBounds};
+
 use prost::Message;
 
 use crate::{
     builder::BlockBuilder,
-    crypto::PublicKey,
+    crypto::{PublicKey, Signer},
     datalog::SymbolTable,
     error,
     format::{convert::token_block_to_proto_block, schema, SerializedBiscuit},
-    KeyPair, PrivateKey,
+    Biscuit, KeyPair, PrivateKey,
 };
 
 use super::public_keys::PublicKeys;
+use super::{
+    is_supported_schema_version, unsupported_schema_version, EXTENSION_SCHEMA_VERSION_1,
+    MAX_SCHEMA_VERSION,
+};
 
 /// Third party block request
+///
+/// the request is sent to the third party service, which answers with a
+/// [`ThirdPartyBlock`]. Both are serialized with Protobuf: over HTTP, the binary
+/// form is sent with the [`ThirdPartyRequest::CONTENT_TYPE`] and
+/// [`ThirdPartyBlock::CONTENT_TYPE`] content types, the URL safe base64 form with
+/// the `text/plain` content type.
+///
+/// Both messages carry the version of the block format: the request states
+/// the highest version the token holder can read, and
+/// [`ThirdPartyRequest::create_block`] refuses to create newer blocks. Messages
+/// from older implementations, without a version, are still accepted.
+///
+/// ```rust
+/// use biscuit_auth::{builder::BlockBuilder, Biscuit, KeyPair, ThirdPartyBlock, ThirdPartyRequest};
+///
+/// let root = KeyPair::new();
+/// let token = Biscuit::builder().build(&root).unwrap();
+/// let request_body = token.third_party_request().unwrap().serialize().unwrap();
+///
+/// // on the third party service
+/// let external = KeyPair::new();
+/// let request = ThirdPartyRequest::deserialize(&request_body).unwrap();
+/// let mut builder = BlockBuilder::new();
+/// builder.add_fact("verified(true)").unwrap();
+/// let response_body = request
+///     .create_block(&external.private(), builder)
+///     .unwrap()
+///     .serialize()
+///     .unwrap();
+///
+/// // back on the token holder's side
+/// let block = ThirdPartyBlock::deserialize(&response_body).unwrap();
+/// let token = token
+///     .append_third_party(block.external_key().unwrap(), block)
+///     .unwrap();
+/// ```
 #[derive(Debug)]
 pub struct ThirdPartyRequest {
     pub(crate) previous_key: PublicKey,
     pub(crate) public_keys: PublicKeys,
+    pub(crate) version: u32,
 }
 
 impl ThirdPartyRequest {
+    /// content type of a serialized request in an HTTP body
+    pub const CONTENT_TYPE: &'static str = "application/vnd.biscuit.third-party-request+protobuf";
+
     pub(crate) fn from_container(
         container: &SerializedBiscuit,
     ) -> Result<ThirdPartyRequest, error::Token> {
@@ -57,9 +103,15 @@ impl ThirdPartyRequest {
         Ok(ThirdPartyRequest {
             previous_key,
             public_keys,
+            version: MAX_SCHEMA_VERSION,
         })
     }
 
+    /// highest block format version supported by the token holder
+    pub fn version(&self) -> u32 {
+        self.version
+    }
+
     pub fn serialize(&self) -> Result<Vec<u8>, error::Token> {
         let public_keys = self
             .public_keys
@@ -73,6 +125,7 @@ impl ThirdPartyRequest {
         let request = schema::ThirdPartyBlockRequest {
             previous_key,
             public_keys,
+            version: Some(self.version),
         };
         let mut v = Vec::new();
 
@@ -93,6 +146,11 @@ impl ThirdPartyRequest {
             error::Format::DeserializationError(format!("deserialization error: {:?}", e))
         })?;
 
+        // requests without a version come from implementations predating it,
+        // which can read the blocks created by this one
+        let version = data.version.unwrap_or(MAX_SCHEMA_VERSION);
+        check_third_party_version(version)?;
+
         let previous_key = PublicKey::from_proto(&data.previous_key)?;
 
         let mut public_keys = PublicKeys::new();
@@ -104,6 +162,7 @@ impl ThirdPartyRequest {
         Ok(ThirdPartyRequest {
             previous_key,
             public_keys,
+            version,
         })
     }
 
@@ -116,15 +175,50 @@ impl ThirdPartyRequest {
     }
 
     /// Creates a [`ThirdPartyBlock`] signed with the third party service's [`PrivateKey`]
+    ///
+    /// fails with [`error::Format::Version`] or [`error::Format::ExtensionVersion`]
+    /// if the block needs a newer format version than the one supported by the
+    /// token holder
     pub fn create_block(
         self,
         private_key: &PrivateKey,
         block_builder: BlockBuilder,
+    ) -> Result<ThirdPartyBlock, error::Token> {
+        self.create_block_with_signer(&KeyPair::from(private_key), block_builder)
+    }
+
+    /// Creates a [`ThirdPartyBlock`] signed by a [`Signer`], for third party
+    /// services that do not hold their private key in memory
+    ///
+    /// see [`ThirdPartyRequest::create_block`]
+    pub fn create_block_with_signer<S: Signer + ?Sized>(
+        self,
+        signer: &S,
+        block_builder: BlockBuilder,
     ) -> Result<ThirdPartyBlock, error::Token> {
         let mut symbols = SymbolTable::new();
         symbols.public_keys = self.public_keys.clone();
         let mut block = block_builder.build(symbols);
-        block.version = super::MAX_SCHEMA_VERSION;
+        // third party blocks were introduced in v4
+        block.version = block.version.max(THIRD_PARTY_MIN_VERSION);
+        if block.version > self.version {
+            // the maximum is below the minimum of the extensions if the
+            // token holder does not support them
+            return Err(if block.version >= EXTENSION_SCHEMA_VERSION_1 {
+                error::Format::ExtensionVersion {
+                    minimum: EXTENSION_SCHEMA_VERSION_1,
+                    maximum: self.version,
+                    actual: block.version,
+                }
+            } else {
+                error::Format::Version {
+                    minimum: THIRD_PARTY_MIN_VERSION,
+                    maximum: self.version,
+                    actual: block.version,
+                }
+            }
+            .into());
+        }
 
         let mut v = Vec::new();
         token_block_to_proto_block(&block)
@@ -134,24 +228,18 @@ impl ThirdPartyRequest {
             })?;
         let payload = v.clone();
 
-        v.extend(&(crate::format::schema::public_key::Algorithm::Ed25519 as i32).to_le_bytes());
-        v.extend(self.previous_key.to_bytes());
+        self.previous_key.write_signed_payload(&mut v);
 
-        let keypair = KeyPair::from(private_key);
-        let signature = keypair
-            .kp
-            .try_sign(&v)
-            .map_err(|s| s.to_string())
-            .map_err(error::Signature::InvalidSignatureGeneration)
-            .map_err(error::Format::Signature)?;
+        let signature = signer.sign(&v)?;
 
-        let public_key = keypair.public();
+        let public_key = signer.public();
         let content = schema::ThirdPartyBlockContents {
             payload,
             external_signature: schema::ExternalSignature {
                 signature: signature.to_bytes().to_vec(),
                 public_key: public_key.to_proto(),
             },
+            version: Some(block.version),
         };
 
         Ok(ThirdPartyBlock(content))
@@ -166,6 +254,20 @@ impl ThirdPartyRequest {
 pub struct ThirdPartyBlock(pub(crate) schema::ThirdPartyBlockContents);
 
 impl ThirdPartyBlock {
+    /// content type of a serialized block in an HTTP body
+    pub const CONTENT_TYPE: &'static str = "application/vnd.biscuit.third-party-block+protobuf";
+
+    /// block format version, if the block was created by an implementation
+    /// recording it
+    pub fn version(&self) -> Option<u32> {
+        self.0.version
+    }
+
+    /// public key of the third party service that signed the block
+    pub fn external_key(&self) -> Result<PublicKey, error::Format> {
+        PublicKey::from_proto(&self.0.external_signature.public_key)
+    }
+
     pub fn serialize(&self) -> Result<Vec<u8>, error::Token> {
         let mut buffer = vec![];
         self.0.encode(&mut buffer).map(|_| buffer).map_err(|e| {
@@ -179,4 +281,227 @@ impl ThirdPartyBlock {
     pub fn serialize_base64(&self) -> Result<String, error::Token> {
         Ok(base64::encode_config(self.serialize()?, base64::URL_SAFE))
     }
+
+    /// deserializes a block created by the third party service
+    ///
+    /// the signature is verified when the block is appended to the token
+    pub fn deserialize(slice: &[u8]) -> Result<Self, error::Token> {
+        let data = schema::ThirdPartyBlockContents::decode(slice).map_err(|e| {
+            error::Format::DeserializationError(format!("deserialization error: {:?}", e))
+        })?;
+        if let Some(version) = data.version {
+            check_third_party_version(version)?;
+        }
+        PublicKey::from_proto(&data.external_signature.public_key)?;
+
+        Ok(ThirdPartyBlock(data))
+    }
+
+    pub fn deserialize_base64<T>(slice: T) -> Result<Self, error::Token>
+    where
+        T: AsRef<[u8]>,
+    {
+        let decoded = base64::decode_config(slice, base64::URL_SAFE)?;
+        Self::deserialize(&decoded)
+    }
+}
+
+/// first block format version supporting third party blocks
+const THIRD_PARTY_MIN_VERSION: u32 = 4;
+
+fn check_third_party_version(version: u32) -> Result<(), error::Format> {
+    if version >= THIRD_PARTY_MIN_VERSION && is_supported_schema_version(version) {
+        Ok(())
+    } else {
+        Err(unsupported_schema_version(THIRD_PARTY_MIN_VERSION, version))
+    }
+}
+
+/// restrictions on the positions of third party blocks in a token
+///
+/// they are checked when deserializing with [`Biscuit::from_with_restrictions`](crate::Biscuit::from_with_restrictions).
+/// Block ids start at 0 for the authority block.
+///
+/// ```rust
+/// # use biscuit_auth::{KeyPair, ThirdPartyRestrictions};
+/// let partner = KeyPair::new();
+/// let restrictions = ThirdPartyRestrictions::new()
+///     // no third party block directly after the authority block
+///     .third_party_from(2)
+///     .allow_key(partner.public(), 2..=3)
+///     .deny_unknown_keys();
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ThirdPartyRestrictions {
+    first_block: usize,
+    // allowed block ids for each key, end excluded
+    keys: HashMap<PublicKey, (usize, Option<usize>)>,
+    deny_unknown_keys: bool,
+}
+
+impl ThirdPartyRestrictions {
+    /// accepts third party blocks signed by any key, at any position
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// rejects third party blocks before `block_id`
+    pub fn third_party_from(mut self, block_id: usize) -> Self {
+        self.first_block = block_id;
+        self
+    }
+
+    /// restricts the blocks signed by `key` to the block ids in `positions`
+    pub fn allow_key<R: RangeBounds<usize>>(mut self, key: PublicKey, positions: R) -> Self {
+        let start = match positions.start_bound() {
+            Bound::Included(i) => *i,
+            Bound::Excluded(i) => i.saturating_add(1),
+            Bound::Unbounded => 0,
+        };
+        let end = match positions.end_bound() {
+            Bound::Included(i) => Some(i.saturating_add(1)),
+            Bound::Excluded(i) => Some(*i),
+            Bound::Unbounded => None,
+        };
+        self.keys.insert(key, (start, end));
+        self
+    }
+
+    /// rejects third party blocks signed by keys not registered with [`ThirdPartyRestrictions::allow_key`]
+    pub fn deny_unknown_keys(mut self) -> Self {
+        self.deny_unknown_keys = true;
+        self
+    }
+
+    /// checks a mapping of third party public keys to block ids, as returned
+    /// by [`Biscuit::public_key_to_block_id`](crate::Biscuit::public_key_to_block_id)
+    ///
+    /// the blocks are checked in order, the error is for the first rejected block
+    pub fn check(&self, mapping: &HashMap<PublicKey, Vec<usize>>) -> Result<(), error::Format> {
+        let mut blocks = mapping
+            .iter()
+            .flat_map(|(key, block_ids)| block_ids.iter().map(move |block_id| (*block_id, key)))
+            .collect::<Vec<_>>();
+        blocks.sort_unstable_by_key(|(block_id, _)| *block_id);
+
+        for (block_id, key) in blocks {
+            let range = self.keys.get(key);
+            if range.is_none() && self.deny_unknown_keys {
+                return Err(error::Format::UnknownExternalKey);
+            }
+
+            let in_range = match range {
+                None => true,
+                Some((start, None)) => block_id >= *start,
+                Some((start, Some(end))) => block_id >= *start && block_id < *end,
+            };
+            if block_id < self.first_block || !in_range {
+                return Err(error::Format::InvalidBlockId(block_id));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// appends third party blocks from several services, one after the other
+///
+/// each hop receives a [`ThirdPartyRequest`] for the token as attenuated by
+/// the previous hops, and answers with a [`ThirdPartyBlock`], usually by
+/// calling a remote service. The block must be signed by the key registered
+/// for the hop. Errors are wrapped in [`error::Token::ThirdPartyHop`], with the
+/// position and name of the hop that failed.
+///
+/// ```rust
+/// use biscuit_auth::{builder::BlockBuilder, Biscuit, KeyPair, ThirdPartyPipeline};
+///
+/// let root = KeyPair::new();
+/// let token = Biscuit::builder().build(&root).unwrap();
+///
+/// let billing = KeyPair::new();
+/// let audit = KeyPair::new();
+/// let mut pipeline = ThirdPartyPipeline::new()
+///     .hop("billing", billing.public(), |request| {
+///         let mut block = BlockBuilder::new();
+///         block.add_fact("paid(true)")?;
+///         request.create_block(&billing.private(), block)
+///     })
+///     .hop("audit", audit.public(), |request| {
+///         let mut block = BlockBuilder::new();
+///         block.add_fact("audited(true)")?;
+///         request.create_block(&audit.private(), block)
+///     });
+///
+/// let token = pipeline.run(&token).unwrap();
+/// assert_eq!(token.block_count(), 3);
+/// ```
+#[derive(Default)]
+pub struct ThirdPartyPipeline<'a> {
+    hops: Vec<Hop<'a>>,
+}
+
+type SignBlock<'a> =
+    Box<dyn FnMut(ThirdPartyRequest) -> Result<ThirdPartyBlock, error::Token> + 'a>;
+
+struct Hop<'a> {
+    name: String,
+    external_key: PublicKey,
+    sign: SignBlock<'a>,
+}
+
+impl<'a> ThirdPartyPipeline<'a> {
+    /// creates a pipeline without hops
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// adds a hop, where `sign` returns a block signed by `external_key`
+    pub fn hop<F>(mut self, name: &str, external_key: PublicKey, sign: F) -> Self
+    where
+        F: FnMut(ThirdPartyRequest) -> Result<ThirdPartyBlock, error::Token> + 'a,
+    {
+        self.hops.push(Hop {
+            name: name.to_string(),
+            external_key,
+            sign: Box::new(sign),
+        });
+        self
+    }
+
+    /// number of hops in the pipeline
+    pub fn len(&self) -> usize {
+        self.hops.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hops.is_empty()
+    }
+
+    /// runs the hops in order, and returns the token with one more block per hop
+    pub fn run(&mut self, token: &Biscuit) -> Result<Biscuit, error::Token> {
+        let mut token = token.clone();
+
+        for (i, hop) in self.hops.iter_mut().enumerate() {
+            token = hop
+                .append(&token)
+                .map_err(|e| error::Token::ThirdPartyHop {
+                    hop: i,
+                    name: hop.name.clone(),
+                    error: Box::new(e),
+                })?;
+        }
+
+        Ok(token)
+    }
+}
+
+impl Hop<'_> {
+    fn append(&mut self, token: &Biscuit) -> Result<Biscuit, error::Token> {
+        let block = (self.sign)(token.third_party_request()?)?;
+        if block.external_key()? != self.external_key {
+            return Err(error::Format::UnknownExternalKey.into());
+        }
+
+        token.append_third_party(self.external_key, block)
+    }
 }
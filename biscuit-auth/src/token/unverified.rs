@@ -1,18 +1,23 @@
 use std::collections::HashMap;
-use std::convert::TryInto;
+use std::time::SystemTime;
 
-use super::{default_symbol_table, Biscuit, Block};
+use super::public_keys::PublicKeys;
+use super::{
+    block_public_keys, counter_signatures, default_symbol_table, expiration, schema_block,
+    symbol_report, Biscuit, Block, CounterSignature,
+};
 use crate::{
-    builder::BlockBuilder,
+    builder::{self, BlockBuilder, Convert},
     crypto,
     crypto::PublicKey,
-    datalog::SymbolTable,
+    datalog::{ApplicationSymbols, SymbolReport, SymbolTable},
     error,
     format::{convert::proto_block_to_token_block, schema, SerializedBiscuit},
     token::{ThirdPartyBlockContents, ThirdPartyRequest},
     KeyPair, RootKeyProvider,
 };
 use prost::Message;
+use rand_core::{CryptoRng, RngCore};
 
 /// A token that was parsed without cryptographic signature verification
 ///
@@ -62,6 +67,42 @@ impl UnverifiedBiscuit {
         KP: RootKeyProvider,
     {
         let key = key_provider.choose(self.root_key_id())?;
+        self.verify_with_key(key)
+    }
+
+    /// checks the signature of the token with the root public key chosen by
+    /// `resolver` from the token's root key id, and converts it to a [Biscuit]
+    ///
+    /// this is the same as [`UnverifiedBiscuit::verify`], for a provider that
+    /// is only known at runtime, like a set of keys that is rotated
+    pub fn verify_with_resolver(
+        self,
+        resolver: &dyn RootKeyProvider,
+    ) -> Result<Biscuit, error::Format> {
+        let key = resolver.choose(self.root_key_id())?;
+        self.verify_with_key(key)
+    }
+
+    /// checks the signature of the token with the root public key returned by
+    /// the future of `resolver`, called with the token's root key id
+    ///
+    /// this is meant for keys fetched from a remote store. The token is
+    /// parsed only once, before resolving the key.
+    #[cfg(feature = "async")]
+    #[cfg_attr(feature = "docsrs", doc(cfg(feature = "async")))]
+    pub async fn verify_with_resolver_async<F, Fut>(
+        self,
+        resolver: F,
+    ) -> Result<Biscuit, error::Format>
+    where
+        F: FnOnce(Option<u32>) -> Fut,
+        Fut: std::future::Future<Output = Result<PublicKey, error::Format>>,
+    {
+        let key = resolver(self.root_key_id()).await?;
+        self.verify_with_key(key)
+    }
+
+    fn verify_with_key(self, key: PublicKey) -> Result<Biscuit, error::Format> {
         self.container.verify(&key)?;
 
         Ok(Biscuit {
@@ -71,6 +112,7 @@ impl UnverifiedBiscuit {
             symbols: self.symbols,
             public_key_to_block_id: self.public_key_to_block_id,
             container: self.container,
+            verified_root_key: None,
         })
     }
 
@@ -79,7 +121,16 @@ impl UnverifiedBiscuit {
     /// since the public key is integrated into the token, the keypair can be
     /// discarded right after calling this function
     pub fn append(&self, block_builder: BlockBuilder) -> Result<Self, error::Token> {
-        let keypair = KeyPair::new_with_rng(&mut rand::rngs::OsRng);
+        self.append_with_rng(block_builder, &mut rand::rngs::OsRng)
+    }
+
+    /// adds a new block to the token, generating the next key pair with `rng`
+    pub fn append_with_rng<R: RngCore + CryptoRng>(
+        &self,
+        block_builder: BlockBuilder,
+        rng: &mut R,
+    ) -> Result<Self, error::Token> {
+        let keypair = KeyPair::new_with_algorithm_and_rng(self.container.next_key_algorithm(), rng);
         self.append_with_keypair(&keypair, block_builder)
     }
 
@@ -97,10 +148,35 @@ impl UnverifiedBiscuit {
     }
 
     /// deserializes from raw bytes with a custom symbol table
-    pub fn from_with_symbols(slice: &[u8], mut symbols: SymbolTable) -> Result<Self, error::Token> {
+    pub fn from_with_symbols(slice: &[u8], symbols: SymbolTable) -> Result<Self, error::Token> {
+        Self::from_with_symbols_and_application_symbols(slice, symbols, &[])
+    }
+
+    /// deserializes from raw bytes a token built with [`ApplicationSymbols`],
+    /// see [`Biscuit::from_with_application_symbols`]
+    pub fn from_with_application_symbols<T>(
+        slice: T,
+        application_symbols: &[ApplicationSymbols],
+    ) -> Result<Self, error::Token>
+    where
+        T: AsRef<[u8]>,
+    {
+        Self::from_with_symbols_and_application_symbols(
+            slice.as_ref(),
+            default_symbol_table(),
+            application_symbols,
+        )
+    }
+
+    fn from_with_symbols_and_application_symbols(
+        slice: &[u8],
+        mut symbols: SymbolTable,
+        application_symbols: &[ApplicationSymbols],
+    ) -> Result<Self, error::Token> {
         let container = SerializedBiscuit::deserialize(slice)?;
 
-        let (authority, blocks, public_key_to_block_id) = container.extract_blocks(&mut symbols)?;
+        let (authority, blocks, public_key_to_block_id) =
+            container.extract_blocks(&mut symbols, application_symbols)?;
 
         Ok(UnverifiedBiscuit {
             authority,
@@ -224,7 +300,90 @@ impl UnverifiedBiscuit {
         1 + self.container.blocks.len()
     }
 
+    /// returns the ids of the blocks signed by each third party public key
+    ///
+    /// block ids start at 0 for the authority block
+    pub fn public_key_to_block_id(&self) -> HashMap<PublicKey, Vec<usize>> {
+        let mut res: HashMap<PublicKey, Vec<usize>> = HashMap::new();
+        for (i, block) in self.container.blocks.iter().enumerate() {
+            if let Some(signature) = &block.external_signature {
+                res.entry(signature.public_key).or_default().push(i + 1);
+            }
+        }
+        res
+    }
+
+    /// returns the list of context elements of each block
+    ///
+    /// the context is a free form text field in which application specific data
+    /// can be stored
+    pub fn context(&self) -> Vec<Option<String>> {
+        let mut res = vec![self.authority.context.clone()];
+
+        for b in self.blocks.iter() {
+            res.push(b.context.clone());
+        }
+
+        res
+    }
+
+    /// returns the context of a block
+    ///
+    /// block ids start at 0 for the authority block
+    pub fn block_context(&self, index: usize) -> Result<Option<String>, error::Token> {
+        self.schema_block(index).map(|block| block.context.clone())
+    }
+
+    /// returns the earliest expiration date set by the token's blocks, see
+    /// [`Biscuit::expiration`]
+    ///
+    /// the token is not verified, so this must not be used to accept it
+    pub fn expiration(&self) -> Result<Option<SystemTime>, error::Token> {
+        expiration(
+            (0..self.block_count()).map(|i| self.block(i)),
+            &self.symbols,
+        )
+    }
+
+    /// returns the counter signatures, see [`Biscuit::counter_signatures`]
+    pub fn counter_signatures(&self) -> Vec<CounterSignature> {
+        counter_signatures(&self.container)
+    }
+
+    /// returns the size of the serialized token, in bytes
+    pub fn serialized_size(&self) -> Result<usize, error::Token> {
+        Ok(self.container.serialized_size())
+    }
+
+    /// returns the internal representation of the token
+    pub fn container(&self) -> &SerializedBiscuit {
+        &self.container
+    }
+
+    /// gets the list of symbols from a block
+    pub fn block_symbols(&self, index: usize) -> Result<Vec<String>, error::Token> {
+        self.schema_block(index).map(|block| block.symbols.clone())
+    }
+
+    /// lists the strings that are defined in more than one of the blocks'
+    /// symbol tables, or that are already part of the default symbols, see
+    /// [`Biscuit::symbol_report`]
+    pub fn symbol_report(&self) -> SymbolReport {
+        symbol_report(&self.authority, &self.blocks)
+    }
+
+    /// gets the list of public keys from a block
+    pub fn block_public_keys(&self, index: usize) -> Result<PublicKeys, error::Token> {
+        block_public_keys(self.schema_block(index)?)
+    }
+
+    fn schema_block(&self, index: usize) -> Result<&schema::Block, error::Token> {
+        schema_block(&self.authority, &self.blocks, index)
+    }
+
     /// prints the content of a block as Datalog source code
+    ///
+    /// the context of the block, if any, is printed first as a comment
     pub fn print_block_source(&self, index: usize) -> Result<String, error::Token> {
         self.block(index).map(|block| {
             let symbols = if block.external_key.is_some() {
@@ -236,6 +395,81 @@ impl UnverifiedBiscuit {
         })
     }
 
+    /// returns the facts of a block
+    pub fn block_facts(&self, index: usize) -> Result<Vec<builder::Fact>, error::Token> {
+        self.convert_block(index, |block, symbols| {
+            block
+                .facts
+                .iter()
+                .map(|f| builder::Fact::convert_from(f, symbols))
+                .collect()
+        })
+    }
+
+    /// returns the rules of a block
+    pub fn block_rules(&self, index: usize) -> Result<Vec<builder::Rule>, error::Token> {
+        self.convert_block(index, |block, symbols| {
+            block
+                .rules
+                .iter()
+                .map(|r| builder::Rule::convert_from(r, symbols))
+                .collect()
+        })
+    }
+
+    /// returns the checks of a block
+    pub fn block_checks(&self, index: usize) -> Result<Vec<builder::Check>, error::Token> {
+        self.convert_block(index, |block, symbols| {
+            block
+                .checks
+                .iter()
+                .map(|c| builder::Check::convert_from(c, symbols))
+                .collect()
+        })
+    }
+
+    /// returns the scopes trusted by default by the rules and checks of a block
+    pub fn block_scopes(&self, index: usize) -> Result<Vec<builder::Scope>, error::Token> {
+        self.convert_block(index, |block, symbols| {
+            block
+                .scopes
+                .iter()
+                .map(|s| builder::Scope::convert_from(s, symbols))
+                .collect()
+        })
+    }
+
+    /// converts the content of a block, with the symbols it was written with
+    pub(crate) fn convert_block<T, F>(&self, index: usize, convert: F) -> Result<T, error::Token>
+    where
+        F: FnOnce(&Block, &SymbolTable) -> Result<T, error::Format>,
+    {
+        let block = self.block(index)?;
+        let symbols = if block.external_key.is_some() {
+            &block.symbols
+        } else {
+            &self.symbols
+        };
+        convert(&block, symbols).map_err(error::Token::Format)
+    }
+
+    /// returns the public key that signed a third party block
+    pub fn block_external_key(&self, index: usize) -> Result<Option<PublicKey>, error::Token> {
+        let block = if index == 0 {
+            &self.container.authority
+        } else {
+            match self.container.blocks.get(index - 1) {
+                None => return Err(error::Token::Format(error::Format::InvalidBlockId(index))),
+                Some(block) => block,
+            }
+        };
+
+        Ok(block
+            .external_signature
+            .as_ref()
+            .map(|signature| signature.public_key))
+    }
+
     pub(crate) fn block(&self, index: usize) -> Result<Block, error::Token> {
         let mut block = if index == 0 {
             proto_block_to_token_block(
@@ -248,7 +482,7 @@ impl UnverifiedBiscuit {
             )
             .map_err(error::Token::Format)?
         } else {
-            if index > self.blocks.len() + 1 {
+            if index > self.blocks.len() {
                 return Err(error::Token::Format(
                     error::Format::BlockDeserializationError("invalid block index".to_string()),
                 ));
@@ -280,52 +514,45 @@ impl UnverifiedBiscuit {
         Ok(token)
     }
 
+    /// returns true if the token is sealed and cannot be attenuated
+    pub fn is_sealed(&self) -> bool {
+        self.container.proof.is_sealed()
+    }
+
     pub fn third_party_request(&self) -> Result<ThirdPartyRequest, error::Token> {
         ThirdPartyRequest::from_container(&self.container)
     }
 
     pub fn append_third_party(&self, slice: &[u8]) -> Result<Self, error::Token> {
-        let next_keypair = KeyPair::new_with_rng(&mut rand::rngs::OsRng);
+        let next_keypair = KeyPair::new_with_algorithm_and_rng(
+            self.container.next_key_algorithm(),
+            &mut rand::rngs::OsRng,
+        );
 
         let ThirdPartyBlockContents {
             payload,
             external_signature,
+            ..
         } = schema::ThirdPartyBlockContents::decode(slice).map_err(|e| {
             error::Format::DeserializationError(format!("deserialization error: {:?}", e))
         })?;
 
-        if external_signature.public_key.algorithm != schema::public_key::Algorithm::Ed25519 as i32
-        {
-            return Err(error::Token::Format(error::Format::DeserializationError(
-                format!(
-                    "deserialization error: unexpected key algorithm {}",
-                    external_signature.public_key.algorithm
-                ),
-            )));
-        }
-        let external_key =
-            PublicKey::from_bytes(&external_signature.public_key.key).map_err(|e| {
-                error::Format::BlockSignatureDeserializationError(format!(
-                    "block external public key deserialization error: {:?}",
-                    e
-                ))
-            })?;
-
-        let bytes: [u8; 64] = (&external_signature.signature[..])
-            .try_into()
-            .map_err(|_| error::Format::InvalidSignatureSize(external_signature.signature.len()))?;
-
-        let signature = ed25519_dalek::Signature::from_bytes(&bytes);
-        let previous_key = self
+        let external_key = PublicKey::from_proto(&external_signature.public_key).map_err(|e| {
+            error::Format::BlockSignatureDeserializationError(format!(
+                "block external public key deserialization error: {:?}",
+                e
+            ))
+        })?;
+
+        let signature = crypto::Signature::from_vec(external_signature.signature);
+        let previous_key = &self
             .container
             .blocks
             .last()
             .unwrap_or(&self.container.authority)
             .next_key;
         let mut to_verify = payload.clone();
-        to_verify
-            .extend(&(crate::format::schema::public_key::Algorithm::Ed25519 as i32).to_le_bytes());
-        to_verify.extend(&previous_key.to_bytes());
+        previous_key.write_signed_payload(&mut to_verify);
 
         let block = schema::Block::decode(&payload[..]).map_err(|e| {
             error::Token::Format(error::Format::DeserializationError(format!(
@@ -0,0 +1,480 @@
+//! trust configuration shared by issuers and verifiers
+//!
+//! A [`TrustConfig`] gathers the root keys, accepted algorithms, third party
+//! keys, execution limits and verification options of a deployment, so they
+//! can be versioned as a single TOML or JSON file:
+//!
+//! ```rust
+//! use biscuit_auth::{builder::BiscuitBuilder, trust::TrustConfig, KeyPair};
+//!
+//! let root = KeyPair::new();
+//! let config = TrustConfig::from_toml(&format!(r#"
+//! algorithms = ["ed25519"]
+//! issuer_key_id = 1
+//!
+//! [[root_keys]]
+//! id = 1
+//! public_key = "{}"
+//!
+//! [limits]
+//! max_time_ms = 5
+//!
+//! [verification]
+//! audiences = ["api.example.com"]
+//! "#, root.public())).unwrap();
+//!
+//! // issuer side
+//! let mut builder = BiscuitBuilder::new();
+//! builder.add_fact(r#"audience("api.example.com")"#).unwrap();
+//! builder.add_fact(r#"user("alice")"#).unwrap();
+//! config.configure_builder(&mut builder);
+//! let token = builder.build(&root).unwrap().to_vec().unwrap();
+//!
+//! // verifier side
+//! let token = config.verify(&token).unwrap();
+//! let mut authorizer = config.authorizer(&token).unwrap();
+//! authorizer.add_code("allow if user($u)").unwrap();
+//! authorizer.authorize().unwrap();
+//! ```
+use std::collections::{HashMap, HashSet};
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    builder::BiscuitBuilder, error, Authorizer, AuthorizerLimits, Biscuit, PublicKey,
+    RootKeyProvider,
+};
+
+/// signature algorithms supported by this version of the library
+pub const SUPPORTED_ALGORITHMS: &[&str] = &["ed25519", "p256"];
+
+/// complete trust setup of a deployment
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TrustConfig {
+    /// root key id written in the tokens created with [`TrustConfig::configure_builder`]
+    pub issuer_key_id: Option<u32>,
+    /// accepted signature algorithms, for the root keys, the keys of the token's
+    /// blocks and third party blocks
+    pub algorithms: Vec<String>,
+    /// public keys accepted for third party blocks, as `ed25519/<hex>` or
+    /// `p256/<hex>`. Tokens
+    /// containing blocks signed by other keys are rejected. If empty, any third
+    /// party block is accepted
+    pub third_party_keys: Vec<String>,
+    /// public keys accepted for the root signature
+    pub root_keys: Vec<RootKey>,
+    pub limits: Limits,
+    pub verification: Verification,
+}
+
+impl Default for TrustConfig {
+    fn default() -> Self {
+        TrustConfig {
+            issuer_key_id: None,
+            algorithms: SUPPORTED_ALGORITHMS.iter().map(|s| s.to_string()).collect(),
+            third_party_keys: Vec::new(),
+            root_keys: Vec::new(),
+            limits: Limits::default(),
+            verification: Verification::default(),
+        }
+    }
+}
+
+/// root public key
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RootKey {
+    /// key id, matched with [`Biscuit::root_key_id`]. A key without an id is used
+    /// for tokens that do not have a root key id
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<u32>,
+    /// public key, as `ed25519/<hex>` or `p256/<hex>`
+    pub public_key: String,
+}
+
+/// Datalog execution limits, see [`AuthorizerLimits`]
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    pub max_facts: u64,
+    pub max_iterations: u64,
+    /// maximum execution time, in milliseconds
+    pub max_time_ms: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_facts_per_block: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_iterations_per_block: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_results: Option<u64>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        AuthorizerLimits::default().into()
+    }
+}
+
+impl From<AuthorizerLimits> for Limits {
+    fn from(limits: AuthorizerLimits) -> Self {
+        Limits {
+            max_facts: limits.max_facts,
+            max_iterations: limits.max_iterations,
+            max_time_ms: limits.max_time.as_millis() as u64,
+            max_facts_per_block: limits.max_facts_per_block,
+            max_iterations_per_block: limits.max_iterations_per_block,
+            max_results: limits.max_results,
+        }
+    }
+}
+
+impl From<&Limits> for AuthorizerLimits {
+    fn from(limits: &Limits) -> Self {
+        AuthorizerLimits {
+            max_facts: limits.max_facts,
+            max_iterations: limits.max_iterations,
+            max_time: Duration::from_millis(limits.max_time_ms),
+            max_facts_per_block: limits.max_facts_per_block,
+            max_iterations_per_block: limits.max_iterations_per_block,
+            max_results: limits.max_results,
+        }
+    }
+}
+
+/// options applied to the authorizers created with [`TrustConfig::authorizer`]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Verification {
+    /// accepted audiences, see [`Authorizer::expect_audience`]
+    pub audiences: Vec<String>,
+    /// revoked identifiers, hex encoded, see [`Authorizer::revocation_check`]
+    pub revoked_ids: Vec<String>,
+    /// adds the current time to the authorizer
+    pub set_time: bool,
+}
+
+impl TrustConfig {
+    /// parses a configuration in TOML format
+    pub fn from_toml(source: &str) -> Result<Self, error::Format> {
+        toml::from_str(source).map_err(|e| error::Format::DeserializationError(e.to_string()))
+    }
+
+    /// serializes the configuration in TOML format
+    pub fn to_toml(&self) -> Result<String, error::Format> {
+        toml::to_string(self).map_err(|e| error::Format::SerializationError(e.to_string()))
+    }
+
+    /// parses a configuration in JSON format
+    pub fn from_json(source: &str) -> Result<Self, error::Format> {
+        serde_json::from_str(source).map_err(|e| error::Format::DeserializationError(e.to_string()))
+    }
+
+    /// serializes the configuration in JSON format
+    pub fn to_json(&self) -> Result<String, error::Format> {
+        serde_json::to_string_pretty(self)
+            .map_err(|e| error::Format::SerializationError(e.to_string()))
+    }
+
+    /// checks the keys, algorithms and revocation ids of the configuration
+    pub fn validate(&self) -> Result<(), error::Format> {
+        self.root_keys()?;
+        self.third_party_keys()?;
+        self.revoked_ids()?;
+        Ok(())
+    }
+
+    /// returns the root key provider built from the root keys
+    pub fn root_keys(&self) -> Result<RootKeys, error::Format> {
+        let mut keys = RootKeys::default();
+        for key in &self.root_keys {
+            let public_key = self.parse_key(&key.public_key)?;
+            let previous = match key.id {
+                Some(id) => keys.by_id.insert(id, public_key),
+                None => keys.default.replace(public_key),
+            };
+            if previous.is_some() {
+                return Err(error::Format::InvalidKey(format!(
+                    "multiple root keys with the id {:?}",
+                    key.id
+                )));
+            }
+        }
+        Ok(keys)
+    }
+
+    fn third_party_keys(&self) -> Result<HashSet<PublicKey>, error::Format> {
+        self.third_party_keys
+            .iter()
+            .map(|key| self.parse_key(key))
+            .collect()
+    }
+
+    fn revoked_ids(&self) -> Result<HashSet<Vec<u8>>, error::Format> {
+        self.verification
+            .revoked_ids
+            .iter()
+            .map(|id| {
+                hex::decode(id).map_err(|e| error::Format::DeserializationError(e.to_string()))
+            })
+            .collect()
+    }
+
+    fn parse_key(&self, key: &str) -> Result<PublicKey, error::Format> {
+        let (algorithm, _) = key
+            .split_once('/')
+            .ok_or_else(|| error::Format::InvalidKey(key.to_string()))?;
+        if !SUPPORTED_ALGORITHMS.contains(&algorithm) {
+            return Err(error::Format::InvalidKey(format!(
+                "unsupported algorithm `{}`",
+                algorithm
+            )));
+        }
+        self.check_algorithm(algorithm)?;
+
+        key.parse()
+            .map_err(|_| error::Format::InvalidKey(key.to_string()))
+    }
+
+    fn check_algorithm(&self, algorithm: &str) -> Result<(), error::Format> {
+        if self.algorithms.iter().any(|a| a == algorithm) {
+            Ok(())
+        } else {
+            Err(error::Format::InvalidKey(format!(
+                "algorithm `{}` is not accepted",
+                algorithm
+            )))
+        }
+    }
+
+    /// sets the root key id of a token, if `issuer_key_id` is set
+    pub fn configure_builder(&self, builder: &mut BiscuitBuilder) {
+        if let Some(id) = self.issuer_key_id {
+            builder.set_root_key_id(id);
+        }
+    }
+
+    /// deserializes a token, checking its root key, the algorithms of its keys
+    /// and its third party blocks
+    pub fn verify<T: AsRef<[u8]>>(&self, token: T) -> Result<Biscuit, error::Token> {
+        let biscuit = Biscuit::from(token, self.root_keys()?)?;
+
+        let container = &biscuit.container;
+        for block in std::iter::once(&container.authority).chain(&container.blocks) {
+            self.check_algorithm(&block.next_key.algorithm().to_string())?;
+            if let Some(signature) = &block.external_signature {
+                self.check_algorithm(&signature.public_key.algorithm().to_string())?;
+            }
+        }
+
+        if !self.third_party_keys.is_empty() {
+            let accepted = self.third_party_keys()?;
+            if biscuit
+                .external_public_keys()
+                .iter()
+                .flatten()
+                .any(|key| !accepted.contains(key))
+            {
+                return Err(error::Format::UnknownExternalKey.into());
+            }
+        }
+
+        Ok(biscuit)
+    }
+
+    /// creates an authorizer for the token, with the limits and verification options
+    pub fn authorizer(&self, token: &Biscuit) -> Result<Authorizer, error::Token> {
+        let mut authorizer = token.authorizer()?;
+        self.configure_authorizer(&mut authorizer)?;
+        Ok(authorizer)
+    }
+
+    /// applies the limits and verification options to an authorizer
+    pub fn configure_authorizer(&self, authorizer: &mut Authorizer) -> Result<(), error::Token> {
+        authorizer.set_limits((&self.limits).into());
+        for audience in &self.verification.audiences {
+            authorizer.expect_audience(audience);
+        }
+        let revoked_ids = self.revoked_ids()?;
+        if !revoked_ids.is_empty() {
+            authorizer.revocation_check(revoked_ids);
+        }
+        if self.verification.set_time {
+            authorizer.set_time();
+        }
+        Ok(())
+    }
+}
+
+/// root keys of a [`TrustConfig`]
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct RootKeys {
+    by_id: HashMap<u32, PublicKey>,
+    default: Option<PublicKey>,
+}
+
+impl RootKeyProvider for RootKeys {
+    fn choose(&self, key_id: Option<u32>) -> Result<PublicKey, error::Format> {
+        match key_id {
+            Some(id) => self.by_id.get(&id).copied(),
+            None => self.default,
+        }
+        .ok_or(error::Format::UnknownPublicKey)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        builder::{Algorithm, BlockBuilder},
+        KeyPair,
+    };
+
+    #[test]
+    fn trust_config() {
+        let root = KeyPair::new();
+        let old_root = KeyPair::new();
+        let partner = KeyPair::new();
+
+        let config = TrustConfig {
+            root_keys: vec![
+                RootKey {
+                    id: Some(2),
+                    public_key: root.public().to_string(),
+                },
+                RootKey {
+                    id: None,
+                    public_key: old_root.public().to_string(),
+                },
+            ],
+            issuer_key_id: Some(2),
+            third_party_keys: vec![partner.public().to_string()],
+            limits: Limits {
+                max_time_ms: 10,
+                ..Default::default()
+            },
+            verification: Verification {
+                audiences: vec!["api".to_string()],
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+        config.validate().unwrap();
+        assert_eq!(
+            TrustConfig::from_toml(&config.to_toml().unwrap()),
+            Ok(config.clone())
+        );
+        assert_eq!(
+            TrustConfig::from_json(&config.to_json().unwrap()),
+            Ok(config.clone())
+        );
+
+        let mut builder = BiscuitBuilder::new();
+        builder
+            .add_code(r#"audience("api"); user("alice");"#)
+            .unwrap();
+        config.configure_builder(&mut builder);
+        let biscuit = builder.build(&root).unwrap();
+        assert_eq!(biscuit.root_key_id(), Some(2));
+
+        let token = config.verify(biscuit.to_vec().unwrap()).unwrap();
+        let mut authorizer = config.authorizer(&token).unwrap();
+        authorizer.add_code("allow if user($u)").unwrap();
+        assert_eq!(authorizer.authorize(), Ok(0));
+
+        // tokens without key id use the key without id
+        let old = Biscuit::builder().build(&old_root).unwrap();
+        config.verify(old.to_vec().unwrap()).unwrap();
+
+        // third party blocks must be signed by an accepted key
+        let unknown = KeyPair::new();
+        let request = biscuit.third_party_request().unwrap();
+        let block = request
+            .create_block(&unknown.private(), BlockBuilder::new())
+            .unwrap();
+        let attenuated = biscuit.append_third_party(unknown.public(), block).unwrap();
+        assert_eq!(
+            config.verify(attenuated.to_vec().unwrap()).unwrap_err(),
+            error::Token::Format(error::Format::UnknownExternalKey)
+        );
+
+        let request = biscuit.third_party_request().unwrap();
+        let block = request
+            .create_block(&partner.private(), BlockBuilder::new())
+            .unwrap();
+        let attenuated = biscuit.append_third_party(partner.public(), block).unwrap();
+        config.verify(attenuated.to_vec().unwrap()).unwrap();
+
+        let config = TrustConfig {
+            algorithms: vec![],
+            ..config
+        };
+        assert!(config.validate().is_err());
+        assert!(TrustConfig::from_toml("root_keys = 1").is_err());
+    }
+
+    #[test]
+    fn p256_keys() {
+        let root = KeyPair::new_with_algorithm(Algorithm::P256);
+        let partner = KeyPair::new_with_algorithm(Algorithm::P256);
+        assert!(root.public().to_string().starts_with("p256/"));
+
+        let config = TrustConfig::from_toml(&format!(
+            r#"
+            algorithms = ["p256"]
+            third_party_keys = ["{}"]
+
+            [[root_keys]]
+            public_key = "{}"
+            "#,
+            partner.public(),
+            root.public()
+        ))
+        .unwrap();
+        config.validate().unwrap();
+
+        let biscuit = Biscuit::builder().build(&root).unwrap();
+        let request = biscuit.third_party_request().unwrap();
+        let block = request
+            .create_block(&partner.private(), BlockBuilder::new())
+            .unwrap();
+        let attenuated = biscuit.append_third_party(partner.public(), block).unwrap();
+        config.verify(attenuated.to_vec().unwrap()).unwrap();
+
+        // the keys of the blocks must use an accepted algorithm too
+        let attenuated = biscuit
+            .append_with_keypair(&KeyPair::new(), BlockBuilder::new())
+            .unwrap();
+        assert_eq!(
+            config.verify(attenuated.to_vec().unwrap()).unwrap_err(),
+            error::Token::Format(error::Format::InvalidKey(
+                "algorithm `ed25519` is not accepted".to_string()
+            ))
+        );
+
+        let ed25519 = KeyPair::new();
+        let block = biscuit
+            .third_party_request()
+            .unwrap()
+            .create_block(&ed25519.private(), BlockBuilder::new())
+            .unwrap();
+        let attenuated = biscuit.append_third_party(ed25519.public(), block).unwrap();
+        let any_third_party = TrustConfig {
+            third_party_keys: vec![],
+            ..config.clone()
+        };
+        assert!(any_third_party
+            .verify(attenuated.to_vec().unwrap())
+            .is_err());
+
+        // Ed25519 keys are not accepted by this configuration
+        let config = TrustConfig {
+            root_keys: vec![RootKey {
+                id: None,
+                public_key: KeyPair::new().public().to_string(),
+            }],
+            ..config
+        };
+        assert!(config.validate().is_err());
+    }
+}
@@ -1,9 +1,21 @@
 use biscuit_auth::builder;
+use biscuit_auth::macros::ambient;
+use biscuit_auth::AuthorizerLimits;
 use biscuit_quote::{
-    authorizer, authorizer_merge, biscuit, biscuit_merge, block, block_merge, check, fact, policy,
-    rule,
+    authorizer, authorizer_assert, authorizer_dyn, authorizer_from_file, authorizer_merge, biscuit,
+    biscuit_dyn, biscuit_from_file, biscuit_merge, block, block_dyn, block_from_file, block_merge,
+    check, fact, policy, rule, BiscuitFact, FromFact,
 };
-use std::collections::BTreeSet;
+use std::collections::{BTreeSet, HashSet};
+use std::convert::TryFrom;
+use std::time::{Duration, SystemTime};
+
+fn limits() -> AuthorizerLimits {
+    AuthorizerLimits {
+        max_time: Duration::from_secs(10),
+        ..Default::default()
+    }
+}
 
 #[test]
 fn block_macro() {
@@ -30,6 +42,38 @@ check if "my_value".starts_with("my");
     );
 }
 
+#[test]
+fn collection_params() {
+    let users: HashSet<String> = vec!["alice".to_string(), "bob".to_string()]
+        .into_iter()
+        .collect();
+    let ids = vec![3i64, 1, 2, 1];
+    let flags: BTreeSet<bool> = std::iter::once(true).collect();
+    let bytes = vec![0xaau8, 0xbb];
+
+    let b = block!(
+        r#"users({users});
+            ids({ids}, {flags}, {bytes});
+            check if user($u), {users}.contains($u);
+            "#,
+    );
+
+    assert_eq!(
+        b.to_string(),
+        r#"users(["alice", "bob"]);
+ids([1, 2, 3], [true], hex:aabb);
+check if user($u), ["alice", "bob"].contains($u);
+"#,
+    );
+
+    let names = vec!["alice", "bob"];
+    let f = fact!(r#"names({names})"#);
+    assert_eq!(f.to_string(), r#"names(["alice", "bob"])"#);
+    let users =
+        HashSet::<String>::try_from(builder::Term::from(vec!["alice", "bob", "alice"])).unwrap();
+    assert_eq!(users.len(), 2);
+}
+
 #[test]
 fn block_macro_trailing_comma() {
     let b = block!(r#"fact({my_key});"#, my_key = "test",);
@@ -167,6 +211,21 @@ fn rule_macro() {
         r.to_string(),
         r#"rule($0, true) <- fact($0, $1, $2, "my_value", [0]) trusting ed25519/6e9e6d5a75cf0c0e87ec1256b4dfed0ca3ba452912d213fcc70f8516583db9db"#,
     );
+
+    // scope parameters can be given as strings
+    let key = pubkey.to_string();
+    let r = rule!(r#"rule($0) <- fact($0) trusting {key}"#);
+    assert_eq!(
+        r.to_string(),
+        r#"rule($0) <- fact($0) trusting ed25519/6e9e6d5a75cf0c0e87ec1256b4dfed0ca3ba452912d213fcc70f8516583db9db"#,
+    );
+
+    let r = rule!(r#"total($user, sum($amount)) <- payment($user, $id, $amount)"#);
+    assert_eq!(r.aggregates, vec![(1, builder::Aggregate::Sum)]);
+    assert_eq!(
+        r.to_string(),
+        r#"total($user, sum($amount)) <- payment($user, $id, $amount)"#
+    );
 }
 
 #[test]
@@ -178,6 +237,41 @@ fn fact_macro() {
     assert_eq!(f.to_string(), r#"fact("my_value", [0])"#,);
 }
 
+#[test]
+fn uuid_literal() {
+    let f = fact!(r#"resource(uuid:67E55044-10B1-426F-9247-BB680E5FE0C8)"#);
+
+    assert_eq!(
+        f.to_string(),
+        r#"resource(uuid:67e55044-10b1-426f-9247-bb680e5fe0c8)"#,
+    );
+
+    let f = fact!(r#"resource({id})"#, id = builder::uuid([0xff; 16]));
+    assert_eq!(
+        f.to_string(),
+        r#"resource(uuid:ffffffff-ffff-ffff-ffff-ffffffffffff)"#,
+    );
+}
+
+#[cfg(feature = "uuid")]
+#[test]
+fn uuid_parameter() {
+    let id = uuid::Uuid::from_bytes([0xff; 16]);
+    let f = fact!(r#"resource({id})"#, id = id);
+    assert_eq!(
+        f.to_string(),
+        r#"resource(hex:ffffffffffffffffffffffffffffffff)"#,
+    );
+    assert_eq!(uuid::Uuid::try_from(builder::Term::from(id)), Ok(id));
+
+    let f = fact!(r#"resource({id})"#, id = builder::uuid(id.into_bytes()));
+    assert_eq!(
+        f.to_string(),
+        r#"resource(uuid:ffffffff-ffff-ffff-ffff-ffffffffffff)"#,
+    );
+    assert_eq!(uuid::Uuid::try_from(builder::uuid(id.into_bytes())), Ok(id));
+}
+
 #[test]
 fn check_macro() {
     use biscuit_auth::PublicKey;
@@ -196,6 +290,9 @@ fn check_macro() {
         c.to_string(),
         r#"check if fact("my_value", [0]) trusting ed25519/6e9e6d5a75cf0c0e87ec1256b4dfed0ca3ba452912d213fcc70f8516583db9db"#,
     );
+
+    let c = check!(r#"reject if operation({op})"#, op = "delete");
+    assert_eq!(c.to_string(), r#"reject if operation("delete")"#);
 }
 
 #[test]
@@ -217,3 +314,377 @@ fn policy_macro() {
         r#"allow if fact("my_value", [0]) trusting ed25519/6e9e6d5a75cf0c0e87ec1256b4dfed0ca3ba452912d213fcc70f8516583db9db"#,
     );
 }
+
+#[test]
+fn derive_biscuit_fact() {
+    use std::convert::TryFrom;
+
+    #[derive(BiscuitFact, Debug, PartialEq)]
+    struct UserRight {
+        user: String,
+        id: i64,
+        write: bool,
+    }
+
+    #[derive(BiscuitFact, Debug, PartialEq)]
+    #[biscuit(name = "resource")]
+    struct Resource(String);
+
+    let right = UserRight {
+        user: "alice".to_string(),
+        id: 1,
+        write: true,
+    };
+    let f: builder::Fact = right.into();
+    assert_eq!(f.to_string(), r#"user_right("alice", 1, true)"#);
+    assert_eq!(
+        UserRight::try_from(f).unwrap(),
+        UserRight {
+            user: "alice".to_string(),
+            id: 1,
+            write: true,
+        }
+    );
+
+    let f: builder::Fact = Resource("file1".to_string()).into();
+    assert_eq!(f.to_string(), r#"resource("file1")"#);
+    assert_eq!(
+        Resource::try_from(f).unwrap(),
+        Resource("file1".to_string())
+    );
+
+    assert_eq!(
+        Resource::try_from(fact!(r#"user("file1")"#)),
+        Err(biscuit_auth::error::Token::ConversionError(
+            "expected a `resource` fact, got `user`".to_string()
+        ))
+    );
+    assert_eq!(
+        Resource::try_from(fact!(r#"resource("file1", "read")"#)),
+        Err(biscuit_auth::error::Token::ConversionError(
+            "expected 1 terms in fact `resource`, got 2".to_string()
+        ))
+    );
+    assert!(UserRight::try_from(fact!(r#"user_right("alice", "1", true)"#)).is_err());
+
+    assert_eq!(
+        UserRight::query_rule().to_string(),
+        "user_right($user, $id, $write) <- user_right($user, $id, $write)"
+    );
+    assert_eq!(
+        Resource::query_rule().to_string(),
+        "resource($term0) <- resource($term0)"
+    );
+}
+
+#[test]
+fn derive_from_fact() {
+    use std::convert::TryFrom;
+
+    #[derive(FromFact, Debug, PartialEq)]
+    #[from_fact(query = r#"data($user, $id) <- user($user, $id), $id > 0"#)]
+    struct User {
+        name: String,
+        id: i64,
+    }
+
+    #[derive(FromFact, Debug, PartialEq)]
+    struct Right(String, String);
+
+    let root = biscuit_auth::KeyPair::new();
+    let token = biscuit!(r#"user("alice", 1); user("system", 0); right("file1", "read");"#)
+        .build(&root)
+        .unwrap();
+    let mut authorizer = token.authorizer().unwrap();
+    authorizer.set_limits(limits());
+
+    assert_eq!(
+        User::query_rule().to_string(),
+        "data($user, $id) <- user($user, $id), $id > 0"
+    );
+    let users: Vec<User> = authorizer.query(User::query_rule()).unwrap();
+    assert_eq!(
+        users,
+        vec![User {
+            name: "alice".to_string(),
+            id: 1
+        }]
+    );
+    let rights: Vec<Right> = authorizer.query("r($r, $o) <- right($r, $o)").unwrap();
+    assert_eq!(rights, vec![Right("file1".to_string(), "read".to_string())]);
+
+    assert_eq!(
+        Right::try_from(fact!(r#"right("file1")"#)),
+        Err(biscuit_auth::error::Token::ConversionError(
+            "expected 2 terms in fact `right`, got 1".to_string()
+        ))
+    );
+}
+
+#[test]
+fn ambient_macro() {
+    ambient! {
+        struct Context {
+            method: String,
+            port: i64,
+            secure: bool,
+        }
+    }
+
+    let context = Context {
+        method: "GET".to_string(),
+        port: 443,
+        secure: true,
+    };
+
+    let mut authorizer = biscuit_auth::Authorizer::new();
+    authorizer.set_limits(limits());
+    context.apply(&mut authorizer).unwrap();
+    authorizer
+        .add_code(r#"allow if method("GET"), port(443), secure(true)"#)
+        .unwrap();
+    assert_eq!(authorizer.authorize(), Ok(0));
+
+    let mut authorizer = context
+        .apply_to_builder(biscuit_auth::AuthorizerBuilder::new().limits(limits()))
+        .unwrap()
+        .code(r#"allow if method("GET"), port(443), secure(true)"#)
+        .unwrap()
+        .build_unauthenticated();
+    assert_eq!(authorizer.authorize(), Ok(0));
+}
+
+#[test]
+fn from_file_macros() {
+    let b = biscuit_from_file!("tests/policies/biscuit.datalog", user_id = "1234");
+    assert_eq!(
+        b.to_string(),
+        r#"// no root key id set
+user("1234");
+owner("1234", "file1");
+"#,
+    );
+
+    let b = block_from_file!("tests/policies/block.datalog");
+    assert_eq!(
+        b.to_string(),
+        r#"check if operation("read");
+"#,
+    );
+
+    let a = authorizer_from_file!(
+        "tests/policies/authorizer.datalog",
+        now = SystemTime::UNIX_EPOCH,
+        resource = "file1",
+        operation = "read",
+    );
+    assert_eq!(
+        a.dump_code(),
+        r#"time(1970-01-01T00:00:00Z);
+resource("file1");
+operation("read");
+
+right($resource, $operation) <- owner($user, $resource), operation($operation);
+
+allow if right("file1", "read");
+deny if true;
+"#,
+    );
+}
+
+#[test]
+fn dyn_macros() {
+    let resource_type = "document";
+    let b = biscuit_dyn!(
+        r#"{resource_type:ident}_owner({user}, "{resource_type:ident}");
+        check if {resource_type:ident}({id}) trusting {pubkey};
+        "#,
+        user = "1234",
+        id = 12,
+        pubkey = "ed25519/6e9e6d5a75cf0c0e87ec1256b4dfed0ca3ba452912d213fcc70f8516583db9db",
+    )
+    .unwrap();
+    assert_eq!(
+        b.to_string(),
+        r#"// no root key id set
+document_owner("1234", "{resource_type:ident}");
+check if document(12) trusting ed25519/6e9e6d5a75cf0c0e87ec1256b4dfed0ca3ba452912d213fcc70f8516583db9db;
+"#,
+    );
+
+    let operation = String::from("read");
+    let b = block_dyn!(r#"check if {operation:ident}({operation})"#).unwrap();
+    assert_eq!(b.to_string(), "check if read(\"read\");\n");
+
+    let mut results = vec![];
+    for relation in ["viewer", "editor"] {
+        let mut a =
+            authorizer_dyn!(r#"editor("alice"); allow if {relation:ident}("alice")"#).unwrap();
+        a.set_limits(limits());
+        results.push(a.authorize().is_ok());
+    }
+    assert_eq!(results, vec![false, true]);
+
+    // interpolated values cannot change the structure of the code
+    let operation = "read($x) <- true; allow if true; read";
+    assert!(block_dyn!(r#"check if {operation:ident}($x)"#).is_err());
+    let operation = "";
+    assert!(block_dyn!(r#"check if {operation:ident}($x)"#).is_err());
+
+    // a term parameter cannot be used as a public key
+    assert!(authorizer_dyn!(r#"allow if true trusting {key}"#, key = 12).is_err());
+}
+
+/// evaluates the case at compile time with `authorizer_assert!`, then with
+/// the runtime authorizer, which must give the same result
+macro_rules! assert_authorization {
+    ($code:tt, expect = $($expect:tt)+) => {
+        assert_authorization!($code, facts = "", expect = $($expect)+)
+    };
+    ($code:tt, facts = $facts:tt, expect = $($expect:tt)+) => {
+        authorizer_assert!($code, facts = $facts, expect = $($expect)+);
+        assert_runtime_authorization($code, $facts, stringify!($($expect)+));
+    };
+}
+
+fn assert_runtime_authorization(code: &str, facts: &str, expect: &str) {
+    use biscuit_auth::error::{FailedCheck, Logic, MatchedPolicy, Token};
+
+    let mut authorizer = biscuit_auth::Authorizer::new();
+    authorizer.add_code(facts).unwrap();
+    authorizer.add_code(code).unwrap();
+    authorizer.set_limits(limits());
+
+    let (policy, checks) = match authorizer.authorize() {
+        Ok(i) => (Some(MatchedPolicy::Allow(i)), vec![]),
+        Err(Token::FailedLogic(Logic::Unauthorized { policy, checks })) => (Some(policy), checks),
+        Err(Token::FailedLogic(Logic::NoMatchingPolicy { checks })) => (None, checks),
+        Err(e) => panic!("unexpected error for `{}`: {:?}", code, e),
+    };
+    let mut failed_checks: Vec<u32> = checks
+        .iter()
+        .map(|check| match check {
+            FailedCheck::Authorizer(check) => check.check_id,
+            FailedCheck::Block(_) => unreachable!(),
+        })
+        .collect();
+    failed_checks.sort_unstable();
+    let allowed = failed_checks.is_empty() && matches!(policy, Some(MatchedPolicy::Allow(_)));
+
+    let expect: String = expect.chars().filter(|c| !c.is_whitespace()).collect();
+    let expect = expect.trim_end_matches(',');
+    let index = |prefix: &str| -> Option<usize> {
+        expect
+            .strip_prefix(prefix)
+            .and_then(|s| s.strip_prefix('('))
+            .and_then(|s| s.strip_suffix(')'))
+            .map(|s| s.parse().unwrap())
+    };
+    let matches = match expect {
+        "allow" => allowed,
+        "deny" => !allowed,
+        "no_match" => policy.is_none(),
+        _ if expect.starts_with("allow(") => {
+            allowed && policy == Some(MatchedPolicy::Allow(index("allow").unwrap()))
+        }
+        _ if expect.starts_with("deny(") => {
+            policy == Some(MatchedPolicy::Deny(index("deny").unwrap()))
+        }
+        _ => {
+            let expected: Vec<u32> = expect
+                .strip_prefix("failed_checks(")
+                .and_then(|s| s.strip_suffix(')'))
+                .unwrap()
+                .split(',')
+                .map(|i| i.parse().unwrap())
+                .collect();
+            failed_checks == expected
+        }
+    };
+    assert!(
+        matches,
+        "the runtime authorizer does not agree with `{}` for `{}`: policy {:?}, failed checks {:?}",
+        expect, code, policy, failed_checks
+    );
+}
+
+#[test]
+fn authorizer_assert_macro() {
+    assert_authorization!(
+        r#"
+        check if operation($op), ["read", "write"].contains($op);
+        check all right($r, $op), $r.starts_with("/files/");
+        reject if revoked(true);
+        allow if admin($user), user($user);
+        allow if can_read($user);
+        deny if true;
+        "#,
+        facts = r#"
+        user("alice");
+        operation("read");
+        right("/files/a", "read");
+        can_read($user) <- user($user), right($r, "read"), $r.length() > 5;
+        "#,
+        expect = allow(1),
+    );
+    assert_authorization!(
+        r#"
+        check if operation("read");
+        reject if revoked(true);
+        allow if user($user);
+        "#,
+        facts = r#"user("alice"); operation("write"); revoked(true);"#,
+        expect = failed_checks(0, 1),
+    );
+    assert_authorization!(
+        r#"check if true; deny if user("bob"); allow if user($u)"#,
+        facts = r#"user("bob")"#,
+        expect = deny(0),
+    );
+    assert_authorization!(r#"allow if user("bob")"#, expect = no_match);
+    assert_authorization!(
+        r#"allow if 1 + 2 == 3, "a" + "b" == "ab", 2023-01-01T00:00:00Z < 2024-01-01T00:00:00Z"#,
+        expect = allow,
+    );
+    // a failed check denies the request even if an allow policy matches
+    assert_authorization!(
+        r#"check if operation("write"); allow if true"#,
+        facts = r#"operation("read")"#,
+        expect = deny,
+    );
+    assert_authorization!(
+        r#"check if operation("write"); allow if true"#,
+        facts = r#"operation("read")"#,
+        expect = failed_checks(0),
+    );
+    // rules are applied until no new fact is generated
+    assert_authorization!(
+        r#"allow if parent("a", "d"); deny if true"#,
+        facts = r#"
+        edge("a", "b"); edge("b", "c"); edge("c", "d");
+        parent($x, $y) <- edge($x, $y);
+        parent($x, $z) <- parent($x, $y), edge($y, $z);
+        "#,
+        expect = allow(0),
+    );
+    assert_authorization!(
+        r#"
+        check if group($g), ["dev", "ops"].intersection(["admin", "dev"]).contains($g);
+        check if level($l), $l * 2 == 12, $l != 4, $l - 10 < 0 || false;
+        check all value($v), $v >= 1 && $v <= 2;
+        allow if hex:aabb == hex:aabb, !false, "abc".ends_with("bc");
+        "#,
+        facts = r#"group("dev"); level(6); value(1); value(2); value(3);"#,
+        expect = failed_checks(2),
+    );
+    assert_authorization!(
+        r#"
+        check if right($r), ["a"].union(["b"]) == ["a", "b"], ["a", "b"].contains($r);
+        check if right($r) or admin(true);
+        deny if user("mallory");
+        allow if right("a");
+        "#,
+        facts = r#"right("a"); user("alice");"#,
+        expect = allow(1),
+    );
+}
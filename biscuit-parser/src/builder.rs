@@ -18,6 +18,10 @@ pub enum Term {
     Bool(bool),
     Set(BTreeSet<Term>),
     Parameter(String),
+    /// fixed point decimal number, as a mantissa and a number of fractional digits
+    Decimal(i128, u8),
+    /// UUID, as its 16 bytes in big endian order
+    Uuid([u8; 16]),
 }
 
 impl From<&Term> for Term {
@@ -31,6 +35,8 @@ impl From<&Term> for Term {
             Term::Bool(b) => Term::Bool(*b),
             Term::Set(ref s) => Term::Set(s.clone()),
             Term::Parameter(ref p) => Term::Parameter(p.clone()),
+            Term::Decimal(m, s) => Term::Decimal(*m, *s),
+            Term::Uuid(u) => Term::Uuid(*u),
         }
     }
 }
@@ -50,6 +56,8 @@ impl ToTokens for Term {
             Term::Str(v) => quote! { ::biscuit_auth::builder::Term::Str(#v.to_string()) },
             Term::Date(v) => quote! { ::biscuit_auth::builder::Term::Date(#v) },
             Term::Bool(v) => quote! { ::biscuit_auth::builder::Term::Bool(#v) },
+            Term::Decimal(m, s) => quote! { ::biscuit_auth::builder::Term::Decimal(#m, #s) },
+            Term::Uuid(u) => quote! { ::biscuit_auth::builder::Term::Uuid([ #(#u),*]) },
             Term::Parameter(v) => quote! { ::biscuit_auth::builder::Term::Parameter(#v.to_string()) },
             Term::Bytes(v) => quote! { ::biscuit_auth::builder::Term::Bytes(<[u8]>::into_vec(Box::new([ #(#v),*]))) },
             Term::Set(v) => {
@@ -77,9 +85,13 @@ impl ToTokens for Scope {
             Scope::Authority => quote! { ::biscuit_auth::builder::Scope::Authority},
             Scope::Previous => quote! { ::biscuit_auth::builder::Scope::Previous},
             Scope::PublicKey(pk) => {
-                let bytes = pk.iter();
+                let bytes = pk.key.iter();
+                let algorithm = match pk.algorithm {
+                    Algorithm::Ed25519 => quote! { ::biscuit_auth::builder::Algorithm::Ed25519 },
+                    Algorithm::P256 => quote! { ::biscuit_auth::builder::Algorithm::P256 },
+                };
                 quote! { ::biscuit_auth::builder::Scope::PublicKey(
-                  ::biscuit_auth::PublicKey::from_bytes(&[#(#bytes),*]).unwrap()
+                  ::biscuit_auth::PublicKey::from_bytes_with_algorithm(&[#(#bytes),*], #algorithm).unwrap()
                 )}
             }
             Scope::Parameter(v) => {
@@ -188,6 +200,8 @@ pub enum Unary {
     Negate,
     Parens,
     Length,
+    Lowercase,
+    Trim,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -213,6 +227,12 @@ pub enum Binary {
     BitwiseOr,
     BitwiseXor,
     NotEqual,
+    Difference,
+    SymmetricDifference,
+    IsSubset,
+    IsSuperset,
+    Split,
+    Capture,
 }
 
 #[cfg(feature = "datalog-macro")]
@@ -233,6 +253,8 @@ impl ToTokens for Unary {
             Unary::Negate => quote! {::biscuit_auth::datalog::Unary::Negate },
             Unary::Parens => quote! {::biscuit_auth::datalog::Unary::Parens },
             Unary::Length => quote! {::biscuit_auth::datalog::Unary::Length },
+            Unary::Lowercase => quote! {::biscuit_auth::datalog::Unary::Lowercase },
+            Unary::Trim => quote! {::biscuit_auth::datalog::Unary::Trim },
         });
     }
 }
@@ -262,11 +284,30 @@ impl ToTokens for Binary {
             Binary::BitwiseOr => quote! { ::biscuit_auth::datalog::Binary::BitwiseOr  },
             Binary::BitwiseXor => quote! { ::biscuit_auth::datalog::Binary::BitwiseXor  },
             Binary::NotEqual => quote! { ::biscuit_auth::datalog::Binary::NotEqual },
+            Binary::Difference => quote! { ::biscuit_auth::datalog::Binary::Difference },
+            Binary::SymmetricDifference => {
+                quote! { ::biscuit_auth::datalog::Binary::SymmetricDifference }
+            }
+            Binary::IsSubset => quote! { ::biscuit_auth::datalog::Binary::IsSubset },
+            Binary::IsSuperset => quote! { ::biscuit_auth::datalog::Binary::IsSuperset },
+            Binary::Split => quote! { ::biscuit_auth::datalog::Binary::Split },
+            Binary::Capture => quote! { ::biscuit_auth::datalog::Binary::Capture },
         });
     }
 }
 
-pub type PublicKey = Vec<u8>;
+/// signature algorithm of a public key
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq)]
+pub enum Algorithm {
+    Ed25519,
+    P256,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq)]
+pub struct PublicKey {
+    pub algorithm: Algorithm,
+    pub key: Vec<u8>,
+}
 
 /// Builder for a Datalog rule
 #[derive(Debug, Clone, PartialEq, Eq)]
@@ -277,6 +318,40 @@ pub struct Rule {
     pub parameters: Option<HashMap<String, Option<Term>>>,
     pub scopes: Vec<Scope>,
     pub scope_parameters: Option<HashMap<String, Option<PublicKey>>>,
+    /// aggregations applied to head terms, by position in the head
+    pub aggregates: Vec<(usize, Aggregate)>,
+}
+
+/// aggregation of the values of a variable in a rule head, like `count($session)`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Aggregate {
+    Count,
+    Sum,
+    Min,
+    Max,
+}
+
+impl Aggregate {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aggregate::Count => "count",
+            Aggregate::Sum => "sum",
+            Aggregate::Min => "min",
+            Aggregate::Max => "max",
+        }
+    }
+}
+
+#[cfg(feature = "datalog-macro")]
+impl ToTokens for Aggregate {
+    fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
+        tokens.extend(match self {
+            Aggregate::Count => quote! { ::biscuit_auth::builder::Aggregate::Count },
+            Aggregate::Sum => quote! { ::biscuit_auth::builder::Aggregate::Sum },
+            Aggregate::Min => quote! { ::biscuit_auth::builder::Aggregate::Min },
+            Aggregate::Max => quote! { ::biscuit_auth::builder::Aggregate::Max },
+        });
+    }
 }
 
 impl Rule {
@@ -324,6 +399,7 @@ impl Rule {
             parameters: Some(parameters),
             scopes,
             scope_parameters: Some(scope_parameters),
+            aggregates: vec![],
         }
     }
 
@@ -376,14 +452,29 @@ impl ToTokens for Rule {
         let body = self.body.iter();
         let expressions = self.expressions.iter();
         let scopes = self.scopes.iter();
-        tokens.extend(quote! {
+        let rule = quote! {
           ::biscuit_auth::builder::Rule::new(
             #head,
             <[::biscuit_auth::builder::Predicate]>::into_vec(Box::new([#(#body),*])),
             <[::biscuit_auth::builder::Expression]>::into_vec(Box::new([#(#expressions),*])),
             <[::biscuit_auth::builder::Scope]>::into_vec(Box::new([#(#scopes),*]))
           )
-        });
+        };
+
+        if self.aggregates.is_empty() {
+            tokens.extend(rule);
+        } else {
+            let (positions, aggregates): (Vec<_>, Vec<_>) = self.aggregates.iter().cloned().unzip();
+            tokens.extend(quote! {
+              {
+                let mut rule = #rule;
+                rule.aggregates = <[(usize, ::biscuit_auth::builder::Aggregate)]>::into_vec(
+                  Box::new([#((#positions, #aggregates)),*])
+                );
+                rule
+              }
+            });
+        }
     }
 }
 
@@ -392,12 +483,16 @@ impl ToTokens for Rule {
 pub struct Check {
     pub queries: Vec<Rule>,
     pub kind: CheckKind,
+    /// message returned in the error when the check fails
+    pub message: Option<String>,
 }
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum CheckKind {
     One,
     All,
+    /// `reject if`: fails if the query matches
+    Reject,
 }
 
 #[cfg(feature = "datalog-macro")]
@@ -405,10 +500,15 @@ impl ToTokens for Check {
     fn to_tokens(&self, tokens: &mut proc_macro2::TokenStream) {
         let queries = self.queries.iter();
         let kind = &self.kind;
+        let message = match &self.message {
+            Some(message) => quote! { ::core::option::Option::Some(#message.to_string()) },
+            None => quote! { ::core::option::Option::None },
+        };
         tokens.extend(quote! {
           ::biscuit_auth::builder::Check {
             queries: <[::biscuit_auth::builder::Rule]>::into_vec(Box::new([#(#queries),*])),
             kind: #kind,
+            message: #message,
           }
         });
     }
@@ -424,6 +524,9 @@ impl ToTokens for CheckKind {
             CheckKind::All => quote! {
               ::biscuit_auth::builder::CheckKind::All
             },
+            CheckKind::Reject => quote! {
+              ::biscuit_auth::builder::CheckKind::Reject
+            },
         });
     }
 }
@@ -523,6 +626,7 @@ pub fn check<P: AsRef<Predicate>>(predicates: &[P], kind: CheckKind) -> Check {
             vec![],
         )],
         kind,
+        message: None,
     }
 }
 
@@ -531,6 +635,17 @@ pub fn int(i: i64) -> Term {
     Term::Integer(i)
 }
 
+/// creates a decimal number, equal to `mantissa * 10^-scale`
+pub fn decimal(mantissa: i128, scale: u8) -> Term {
+    let (mantissa, scale) = crate::parser::normalize_decimal(mantissa, scale);
+    Term::Decimal(mantissa, scale)
+}
+
+/// creates a UUID from its 16 bytes, in big endian order
+pub fn uuid(bytes: [u8; 16]) -> Term {
+    Term::Uuid(bytes)
+}
+
 /// creates a string
 pub fn string(s: &str) -> Term {
     Term::Str(s.to_string())
@@ -18,18 +18,89 @@ pub struct ParseErrors {
     pub errors: Vec<ParseError>,
 }
 
+/// a Datalog parse error
+///
+/// fields may be added in future versions: create it with [`ParseError::new`]
 #[derive(Clone, Debug, PartialEq, Eq)]
 #[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
 pub struct ParseError {
     pub input: String,
     pub message: Option<String>,
+    /// position of the error in the source, when it is known
+    pub position: Option<Position>,
+}
+
+/// position of a parse error in the Datalog source
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub struct Position {
+    /// line number, starting at 1
+    pub line: usize,
+    /// column number in characters, starting at 1
+    pub column: usize,
+    /// the line containing the error
+    pub snippet: String,
+}
+
+impl ParseError {
+    /// creates an error for `input`, without position
+    pub fn new(input: String, message: Option<String>) -> Self {
+        ParseError {
+            input,
+            message,
+            position: None,
+        }
+    }
+}
+
+impl Position {
+    /// finds the position of `input` in `source`, if `input` is a slice of `source`
+    pub fn locate(source: &str, input: &str) -> Option<Position> {
+        let start = source.as_ptr() as usize;
+        let offset = (input.as_ptr() as usize).checked_sub(start)?;
+        if offset > source.len() || !source.is_char_boundary(offset) {
+            return None;
+        }
+
+        let before = &source[..offset];
+        let line_start = before.rfind('\n').map(|i| i + 1).unwrap_or(0);
+        let line_end = source[offset..]
+            .find('\n')
+            .map(|i| offset + i)
+            .unwrap_or(source.len());
+
+        Some(Position {
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+            snippet: source[line_start..line_end].trim_end().to_string(),
+        })
+    }
 }
 
 impl<'a> From<crate::parser::Error<'a>> for ParseError {
     fn from(e: crate::parser::Error<'a>) -> Self {
-        ParseError {
-            input: e.input.to_string(),
-            message: e.message,
+        ParseError::new(e.input.to_string(), e.message)
+    }
+}
+
+impl ParseErrors {
+    /// converts the errors returned by [`parse_source`](crate::parser::parse_source)
+    /// or [`parse_block_source`](crate::parser::parse_block_source), with their
+    /// position in the source
+    pub fn from_source(source: &str, errors: Vec<crate::parser::Error<'_>>) -> Self {
+        ParseErrors {
+            errors: errors
+                .into_iter()
+                .map(|e| {
+                    let position = Position::locate(source, e.input);
+                    ParseError {
+                        position,
+                        ..e.into()
+                    }
+                })
+                .collect(),
         }
     }
 }
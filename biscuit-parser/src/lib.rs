@@ -1,3 +1,4 @@
 pub mod builder;
 pub mod error;
+pub mod lint;
 pub mod parser;
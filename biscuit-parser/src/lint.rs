@@ -0,0 +1,265 @@
+//! static analysis of Datalog code, to find dead code in large policy files
+//!
+//! the analysis only looks at predicate names and arities: scopes, terms and
+//! expressions are ignored, so a lint is a hint and not a proof that the
+//! element is useless
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::builder::{CheckKind, Op, Rule, Term};
+use crate::error::{LanguageError, ParseErrors};
+use crate::parser::{parse_source, SourceResult};
+
+/// issue found in Datalog code by [`lint`]
+///
+/// elements are referenced by their source code
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde-error", derive(serde::Serialize, serde::Deserialize))]
+#[non_exhaustive]
+pub enum Lint {
+    /// a fact that no rule, check or policy reads
+    UnusedFact { fact: String },
+    /// a predicate generated by rules that no check or policy depends on
+    UnusedPredicate { name: String, arity: usize },
+    /// a rule that can never fire, because no fact or rule generates `predicate`
+    UnreachableRule { rule: String, predicate: String },
+    /// a check that always succeeds when the earlier check `by` succeeds
+    RedundantCheck { check: String, by: String },
+    /// a policy that is never reached, because the earlier policy `by`
+    /// matches whenever it matches
+    ShadowedPolicy { policy: String, by: String },
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Lint::UnusedFact { fact } => {
+                write!(f, "fact `{fact}` is not used by any rule, check or policy")
+            }
+            Lint::UnusedPredicate { name, arity } => write!(
+                f,
+                "predicate `{name}` with {arity} terms is not used by any check or policy"
+            ),
+            Lint::UnreachableRule { rule, predicate } => write!(
+                f,
+                "rule `{rule}` can never fire: no fact or rule generates `{predicate}`"
+            ),
+            Lint::RedundantCheck { check, by } => {
+                write!(f, "check `{check}` always succeeds after `{by}`")
+            }
+            Lint::ShadowedPolicy { policy, by } => {
+                write!(f, "policy `{policy}` is shadowed by `{by}`")
+            }
+        }
+    }
+}
+
+/// parses Datalog code and reports the issues found by [`lint`]
+pub fn lint_source(source: &str) -> Result<Vec<Lint>, LanguageError> {
+    let result = parse_source(source)
+        .map_err(|e| LanguageError::ParseError(ParseErrors::from_source(source, e)))?;
+    Ok(lint(&result))
+}
+
+/// reports unused facts and predicates, rules that can never fire, redundant
+/// checks and policies shadowed by earlier policies
+///
+/// facts and checks provided by tokens are unknown here, so their predicates
+/// can appear unused or unreachable
+pub fn lint(source: &SourceResult) -> Vec<Lint> {
+    let mut lints = Vec::new();
+
+    // predicates that checks and policies depend on, directly or through rules
+    let mut used: HashSet<(&str, usize)> = source
+        .checks
+        .iter()
+        .flat_map(|(_, c)| c.queries.iter())
+        .chain(source.policies.iter().flat_map(|(_, p)| p.queries.iter()))
+        .flat_map(body_predicates)
+        .collect();
+    loop {
+        let new: Vec<_> = source
+            .rules
+            .iter()
+            .filter(|(_, r)| used.contains(&head_predicate(r)))
+            .flat_map(|(_, r)| body_predicates(r))
+            .filter(|p| !used.contains(p))
+            .collect();
+        if new.is_empty() {
+            break;
+        }
+        used.extend(new);
+    }
+
+    for (input, fact) in &source.facts {
+        let predicate = (fact.predicate.name.as_str(), fact.predicate.terms.len());
+        if !used.contains(&predicate) {
+            lints.push(Lint::UnusedFact {
+                fact: input.trim().to_string(),
+            });
+        }
+    }
+
+    let mut reported = HashSet::new();
+    for (_, rule) in &source.rules {
+        let (name, arity) = head_predicate(rule);
+        if !used.contains(&(name, arity)) && reported.insert((name, arity)) {
+            lints.push(Lint::UnusedPredicate {
+                name: name.to_string(),
+                arity,
+            });
+        }
+    }
+
+    // predicates that facts and rules can generate
+    let mut generated: HashSet<(&str, usize)> = source
+        .facts
+        .iter()
+        .map(|(_, f)| (f.predicate.name.as_str(), f.predicate.terms.len()))
+        .collect();
+    loop {
+        let new: Vec<_> = source
+            .rules
+            .iter()
+            .filter(|(_, r)| body_predicates(r).all(|p| generated.contains(&p)))
+            .map(|(_, r)| head_predicate(r))
+            .filter(|p| !generated.contains(p))
+            .collect();
+        if new.is_empty() {
+            break;
+        }
+        generated.extend(new);
+    }
+
+    for (input, rule) in &source.rules {
+        if let Some((name, _)) = body_predicates(rule).find(|p| !generated.contains(p)) {
+            lints.push(Lint::UnreachableRule {
+                rule: input.trim().to_string(),
+                predicate: name.to_string(),
+            });
+        }
+    }
+
+    for (i, (input, check)) in source.checks.iter().enumerate() {
+        // with `check all` or `reject if`, adding queries does not make the check weaker
+        if check.kind != CheckKind::One {
+            continue;
+        }
+        let by = source.checks[..i].iter().find(|(_, earlier)| {
+            earlier.kind == CheckKind::One
+                && earlier
+                    .queries
+                    .iter()
+                    .all(|q| check.queries.iter().any(|q2| same_query(q, q2)))
+        });
+        if let Some((by, _)) = by {
+            lints.push(Lint::RedundantCheck {
+                check: input.trim().to_string(),
+                by: by.trim().to_string(),
+            });
+        }
+    }
+
+    for (i, (input, policy)) in source.policies.iter().enumerate() {
+        let by = source.policies[..i].iter().find(|(_, earlier)| {
+            earlier.queries.iter().any(unconditional)
+                || policy
+                    .queries
+                    .iter()
+                    .all(|q| earlier.queries.iter().any(|q2| same_query(q, q2)))
+        });
+        if let Some((by, _)) = by {
+            lints.push(Lint::ShadowedPolicy {
+                policy: input.trim().to_string(),
+                by: by.trim().to_string(),
+            });
+        }
+    }
+
+    lints
+}
+
+fn head_predicate(rule: &Rule) -> (&str, usize) {
+    (rule.head.name.as_str(), rule.head.terms.len())
+}
+
+fn body_predicates(rule: &Rule) -> impl Iterator<Item = (&str, usize)> {
+    rule.body.iter().map(|p| (p.name.as_str(), p.terms.len()))
+}
+
+fn same_query(a: &Rule, b: &Rule) -> bool {
+    a.body == b.body && a.expressions == b.expressions && a.scopes == b.scopes
+}
+
+/// a query that always matches, like `allow if true`
+fn unconditional(query: &Rule) -> bool {
+    query.body.is_empty()
+        && query
+            .expressions
+            .iter()
+            .all(|e| e.ops == [Op::Value(Term::Bool(true))])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn lints() {
+        let lints = lint_source(
+            r#"
+            resource("file1");
+            operation("read");
+            unused("x");
+            right("file1", "read");
+
+            can_read($f) <- right($f, "read");
+            can_write($f) <- right($f, "write"), resource($f);
+            orphan($f) <- missing($f);
+
+            check if resource($f), can_read($f);
+            check if resource($f), can_read($f) or operation("write");
+            check all operation($op), ["read"].contains($op);
+
+            allow if resource($f), can_read($f);
+            deny if orphan($f);
+            allow if true;
+            deny if operation("read");
+            allow if resource($f), can_read($f);
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            lints,
+            vec![
+                Lint::UnusedFact {
+                    fact: "unused(\"x\")".to_string()
+                },
+                Lint::UnusedPredicate {
+                    name: "can_write".to_string(),
+                    arity: 1
+                },
+                Lint::UnreachableRule {
+                    rule: "orphan($f) <- missing($f)".to_string(),
+                    predicate: "missing".to_string()
+                },
+                Lint::RedundantCheck {
+                    check: "check if resource($f), can_read($f) or operation(\"write\")"
+                        .to_string(),
+                    by: "check if resource($f), can_read($f)".to_string()
+                },
+                Lint::ShadowedPolicy {
+                    policy: "deny if operation(\"read\")".to_string(),
+                    by: "allow if true".to_string()
+                },
+                Lint::ShadowedPolicy {
+                    policy: "allow if resource($f), can_read($f)".to_string(),
+                    by: "allow if resource($f), can_read($f)".to_string()
+                },
+            ]
+        );
+
+        assert!(lint_source("allow if").is_err());
+    }
+}
@@ -1,7 +1,9 @@
 use crate::builder::{self, CheckKind};
 use nom::{
     branch::alt,
-    bytes::complete::{escaped_transform, tag, tag_no_case, take_until, take_while, take_while1},
+    bytes::complete::{
+        escaped_transform, tag, tag_no_case, take_until, take_while, take_while1, take_while_m_n,
+    },
     character::{
         complete::{char, digit1, multispace0 as space0},
         is_alphanumeric,
@@ -70,10 +72,30 @@ fn check_inner(i: &str) -> IResult<&str, builder::Check, Error> {
     let (i, kind) = alt((
         map(tag_no_case("check if"), |_| CheckKind::One),
         map(tag_no_case("check all"), |_| CheckKind::All),
+        map(tag_no_case("reject if"), |_| CheckKind::Reject),
     ))(i)?;
 
     let (i, queries) = cut(check_body)(i)?;
-    Ok((i, builder::Check { queries, kind }))
+    let (i, message) = opt(check_message)(i)?;
+    Ok((
+        i,
+        builder::Check {
+            queries,
+            kind,
+            message,
+        },
+    ))
+}
+
+/// parse the failure message of a check: `@msg("subscription expired")`
+fn check_message(i: &str) -> IResult<&str, String, Error> {
+    preceded(
+        preceded(space0, tag("@msg(")),
+        cut(terminated(
+            preceded(space0, parse_string),
+            preceded(space0, char(')')),
+        )),
+    )(i)
 }
 
 /// parse an allow or deny rule
@@ -186,7 +208,9 @@ pub fn rule_inner(i: &str) -> IResult<&str, builder::Rule, Error> {
         Ok((i, (head, body, expressions, scopes)))
     })(i)?;
 
-    let rule = builder::Rule::new(head, body, expressions, scopes);
+    let (head, aggregates) = head;
+    let mut rule = builder::Rule::new(head, body, expressions, scopes);
+    rule.aggregates = aggregates;
 
     if let Err(message) = rule.validate_variables() {
         return Err(nom::Err::Failure(Error {
@@ -291,26 +315,62 @@ fn predicate(i: &str) -> IResult<&str, builder::Predicate, Error> {
     ))
 }
 
-fn rule_head(i: &str) -> IResult<&str, builder::Predicate, Error> {
+type RuleHead = (builder::Predicate, Vec<(usize, builder::Aggregate)>);
+
+fn rule_head(i: &str) -> IResult<&str, RuleHead, Error> {
     let (i, _) = space0(i)?;
     let (i, fact_name) = name(i)?;
 
     let (i, _) = space0(i)?;
     let (i, terms) = delimited(
         char('('),
-        cut(separated_list0(preceded(space0, char(',')), cut(term))),
+        cut(separated_list0(
+            preceded(space0, char(',')),
+            cut(alt((
+                map(aggregate, |(aggregate, term)| (term, Some(aggregate))),
+                map(term, |term| (term, None)),
+            ))),
+        )),
         preceded(space0, char(')')),
     )(i)?;
 
+    let aggregates = terms
+        .iter()
+        .enumerate()
+        .filter_map(|(position, (_, aggregate))| aggregate.map(|a| (position, a)))
+        .collect();
+
     Ok((
         i,
-        builder::Predicate {
-            name: fact_name.to_string(),
-            terms,
-        },
+        (
+            builder::Predicate {
+                name: fact_name.to_string(),
+                terms: terms.into_iter().map(|(term, _)| term).collect(),
+            },
+            aggregates,
+        ),
     ))
 }
 
+/// aggregation of a variable in a rule head, like `count($session)`
+fn aggregate(i: &str) -> IResult<&str, (builder::Aggregate, builder::Term), Error<'_>> {
+    let (i, _) = space0(i)?;
+    let (i, aggregate) = alt((
+        value(builder::Aggregate::Count, tag("count")),
+        value(builder::Aggregate::Sum, tag("sum")),
+        value(builder::Aggregate::Min, tag("min")),
+        value(builder::Aggregate::Max, tag("max")),
+    ))(i)?;
+    let (i, _) = space0(i)?;
+    let (i, variable) = delimited(
+        char('('),
+        preceded(space0, variable),
+        preceded(space0, char(')')),
+    )(i)?;
+
+    Ok((i, (aggregate, variable)))
+}
+
 /// parse a Datalog rule body
 pub fn rule_body(
     i: &str,
@@ -379,7 +439,20 @@ fn scope(i: &str) -> IResult<&str, builder::Scope, Error> {
 }
 
 pub fn public_key(i: &str) -> IResult<&str, builder::PublicKey, Error> {
-    preceded(tag("ed25519/"), parse_hex)(i)
+    alt((
+        map(preceded(tag("ed25519/"), parse_hex), |key| {
+            builder::PublicKey {
+                algorithm: builder::Algorithm::Ed25519,
+                key,
+            }
+        }),
+        map(preceded(tag("p256/"), parse_hex), |key| {
+            builder::PublicKey {
+                algorithm: builder::Algorithm::P256,
+                key,
+            }
+        }),
+    ))(i)
 }
 
 #[derive(Debug, PartialEq)]
@@ -495,6 +568,12 @@ fn binary_op_8(i: &str) -> IResult<&str, builder::Binary, Error> {
         value(Binary::Regex, tag("matches")),
         value(Binary::Intersection, tag("intersection")),
         value(Binary::Union, tag("union")),
+        value(Binary::SymmetricDifference, tag("symmetric_difference")),
+        value(Binary::Difference, tag("difference")),
+        value(Binary::IsSubset, tag("is_subset")),
+        value(Binary::IsSuperset, tag("is_superset")),
+        value(Binary::Split, tag("split")),
+        value(Binary::Capture, tag("capture")),
     ))(i)
 }
 
@@ -667,7 +746,11 @@ fn binary_method(i: &str) -> IResult<&str, (builder::Binary, Expr), Error> {
 
 fn unary_method(i: &str) -> IResult<&str, builder::Unary, Error> {
     use builder::Unary;
-    let (i, op) = value(Unary::Length, tag("length"))(i)?;
+    let (i, op) = alt((
+        value(Unary::Length, tag("length")),
+        value(Unary::Lowercase, tag("to_lowercase")),
+        value(Unary::Trim, tag("trim")),
+    ))(i)?;
 
     let (i, _) = char('(')(i)?;
     let (i, _) = space0(i)?;
@@ -717,6 +800,47 @@ fn integer(i: &str) -> IResult<&str, builder::Term, Error> {
     parse_integer(i).map(|(i, n)| (i, builder::int(n)))
 }
 
+/// maximum number of fractional digits of a decimal number
+pub const MAX_DECIMAL_SCALE: u8 = 38;
+
+/// removes the trailing zeros of the fractional digits, so that equal decimal
+/// numbers have the same mantissa and scale: `1.50d` is `(15, 1)`
+pub fn normalize_decimal(mut mantissa: i128, mut scale: u8) -> (i128, u8) {
+    while scale > 0 && mantissa % 10 == 0 {
+        mantissa /= 10;
+        scale -= 1;
+    }
+    (mantissa, scale)
+}
+
+fn parse_decimal(i: &str) -> IResult<&str, (i128, u8), Error> {
+    map_res(
+        terminated(
+            recognize(tuple((
+                opt(char('-')),
+                digit1,
+                opt(pair(char('.'), digit1)),
+            ))),
+            char('d'),
+        ),
+        |s: &str| {
+            let (integer, fraction) = s.split_once('.').unwrap_or((s, ""));
+            let scale = u8::try_from(fraction.len())
+                .ok()
+                .filter(|scale| *scale <= MAX_DECIMAL_SCALE)
+                .ok_or("too many fractional digits")?;
+            let mantissa = format!("{}{}", integer, fraction)
+                .parse::<i128>()
+                .map_err(|_| "decimal number out of range")?;
+            Ok::<_, &str>(normalize_decimal(mantissa, scale))
+        },
+    )(i)
+}
+
+fn decimal(i: &str) -> IResult<&str, builder::Term, Error> {
+    parse_decimal(i).map(|(i, (mantissa, scale))| (i, builder::decimal(mantissa, scale)))
+}
+
 fn parse_date(i: &str) -> IResult<&str, u64, Error> {
     map_res(
         map_res(
@@ -749,6 +873,34 @@ fn bytes(i: &str) -> IResult<&str, builder::Term, Error> {
     parse_bytes(i).map(|(i, s)| (i, builder::Term::Bytes(s)))
 }
 
+fn parse_uuid(i: &str) -> IResult<&str, [u8; 16], Error> {
+    let hex_digits = |count| take_while_m_n(count, count, |c: char| c.is_ascii_hexdigit());
+    map_res(
+        preceded(
+            tag("uuid:"),
+            recognize(tuple((
+                hex_digits(8),
+                char('-'),
+                hex_digits(4),
+                char('-'),
+                hex_digits(4),
+                char('-'),
+                hex_digits(4),
+                char('-'),
+                hex_digits(12),
+            ))),
+        ),
+        |s: &str| {
+            let mut bytes = [0u8; 16];
+            hex::decode_to_slice(s.replace('-', ""), &mut bytes).map(|()| bytes)
+        },
+    )(i)
+}
+
+fn uuid(i: &str) -> IResult<&str, builder::Term, Error> {
+    parse_uuid(i).map(|(i, u)| (i, builder::uuid(u)))
+}
+
 fn variable(i: &str) -> IResult<&str, builder::Term, Error> {
     map(preceded(char('$'), name), builder::variable)(i)
 }
@@ -795,6 +947,8 @@ fn set(i: &str) -> IResult<&str, builder::Term, Error> {
                 }))
             }
             builder::Term::Parameter(_) => 7,
+            builder::Term::Decimal(..) => 8,
+            builder::Term::Uuid(_) => 9,
         };
 
         if let Some(k) = kind {
@@ -821,7 +975,7 @@ fn term(i: &str) -> IResult<&str, builder::Term, Error> {
     preceded(
         space0,
         alt((
-            parameter, string, date, variable, integer, bytes, boolean, set,
+            parameter, string, date, variable, decimal, integer, bytes, uuid, boolean, set,
         )),
     )(i)
 }
@@ -830,7 +984,9 @@ fn term_in_fact(i: &str) -> IResult<&str, builder::Term, Error> {
     preceded(
         space0,
         error(
-            alt((parameter, string, date, integer, bytes, boolean, set)),
+            alt((
+                parameter, string, date, decimal, integer, bytes, uuid, boolean, set,
+            )),
             |input| match input.chars().next() {
                 None | Some(',') | Some(')') => "missing term".to_string(),
                 Some('$') => "variables are not allowed in facts".to_string(),
@@ -845,7 +1001,9 @@ fn term_in_set(i: &str) -> IResult<&str, builder::Term, Error> {
     preceded(
         space0,
         error(
-            alt((parameter, string, date, integer, bytes, boolean)),
+            alt((
+                parameter, string, date, decimal, integer, bytes, uuid, boolean,
+            )),
             |input| match input.chars().next() {
                 None | Some(',') | Some(']') => "missing term".to_string(),
                 Some('$') => "variables are not allowed in sets".to_string(),
@@ -1219,6 +1377,7 @@ mod tests {
             super::date("2019-12-02T13:49:53Z"),
             Ok(("", builder::Term::Date(1575294593)))
         );
+        assert!(super::date("date:253402300800").is_err());
     }
 
     #[test]
@@ -1610,6 +1769,29 @@ mod tests {
             ))
         );
     }
+
+    #[test]
+    fn check_message() {
+        let (rest, check) = super::check(
+            r#"check if time($t), $t < 2030-01-01T00:00:00Z @msg("subscription expired")"#,
+        )
+        .unwrap();
+        assert_eq!(rest, "");
+        assert_eq!(check.queries.len(), 1);
+        assert_eq!(check.message, Some("subscription expired".to_string()));
+
+        let (_, check) = super::check(r#"check all operation($op) or admin(true)"#).unwrap();
+        assert_eq!(check.queries.len(), 2);
+        assert_eq!(check.message, None);
+
+        assert!(super::check(r#"check if admin(true) @msg("unclosed""#).is_err());
+        assert!(super::check(r#"check if admin(true) @msg(admin)"#).is_err());
+
+        let (_, check) =
+            super::check(r#"reject if operation("delete") @msg("read only")"#).unwrap();
+        assert_eq!(check.kind, builder::CheckKind::Reject);
+        assert_eq!(check.message, Some("read only".to_string()));
+    }
     /*
     #[test]
     fn rule() {
@@ -2245,4 +2427,178 @@ mod tests {
             ))
         );
     }
+
+    #[test]
+    fn set_operations() {
+        use builder::{int, set, Binary, Op};
+
+        assert_eq!(
+            super::expr("[1].difference([2]).symmetric_difference([3]).is_subset([4])")
+                .map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(set([int(1)].into_iter().collect())),
+                    Op::Value(set([int(2)].into_iter().collect())),
+                    Op::Binary(Binary::Difference),
+                    Op::Value(set([int(3)].into_iter().collect())),
+                    Op::Binary(Binary::SymmetricDifference),
+                    Op::Value(set([int(4)].into_iter().collect())),
+                    Op::Binary(Binary::IsSubset),
+                ],
+            ))
+        );
+
+        assert_eq!(
+            super::expr("[1].is_superset([2])").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(set([int(1)].into_iter().collect())),
+                    Op::Value(set([int(2)].into_iter().collect())),
+                    Op::Binary(Binary::IsSuperset),
+                ],
+            ))
+        );
+    }
+
+    #[test]
+    fn decimal() {
+        use builder::{decimal, int, Binary, Op};
+
+        assert_eq!(super::term("12.30d"), Ok(("", decimal(1230, 2))));
+        assert_eq!(super::term("-0.005d"), Ok(("", decimal(-5, 3))));
+        assert_eq!(super::term("42d"), Ok(("", decimal(42, 0))));
+        assert_eq!(super::parse_decimal("1.50d"), Ok(("", (15, 1))));
+        assert_eq!(super::parse_decimal("-2.000d"), Ok(("", (-2, 0))));
+        assert_eq!(super::parse_decimal("0.0d"), Ok(("", (0, 0))));
+        assert_eq!(super::term("42"), Ok(("", int(42))));
+        assert!(super::fact(&format!("a(0.{}d)", "1".repeat(38))).is_ok());
+        assert!(super::fact(&format!("a(0.{}d)", "1".repeat(39))).is_err());
+
+        assert_eq!(
+            super::expr("$price * 1.5d").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(builder::var("price")),
+                    Op::Value(decimal(15, 1)),
+                    Op::Binary(Binary::Mul),
+                ],
+            ))
+        );
+        assert!(super::fact("amounts([1.5d, 2])").is_err());
+    }
+
+    #[test]
+    fn error_position() {
+        use crate::error::{ParseErrors, Position};
+
+        let source =
+            "right(\"file1\");\n\ncheck if\n  resource($r),, operation(\"read\");\nallow if true;";
+        let errors = ParseErrors::from_source(source, super::parse_source(source).unwrap_err());
+        assert_eq!(errors.errors.len(), 1);
+        assert_eq!(
+            errors.errors[0].position,
+            Some(Position {
+                line: 4,
+                column: 16,
+                snippet: "  resource($r),, operation(\"read\");".to_string(),
+            })
+        );
+
+        assert_eq!(
+            Position::locate(source, &source[source.len()..]).map(|p| p.line),
+            Some(5)
+        );
+        assert_eq!(Position::locate(source, "resource($r)"), None);
+    }
+
+    #[test]
+    fn aggregate_rule() {
+        let mut expected = builder::Rule::new(
+            builder::pred(
+                "sessions",
+                &[builder::variable("user"), builder::variable("session")],
+            ),
+            vec![builder::pred(
+                "session",
+                &[builder::variable("user"), builder::variable("session")],
+            )],
+            vec![],
+            vec![],
+        );
+        expected.aggregates = vec![(1, builder::Aggregate::Count)];
+
+        assert_eq!(
+            super::rule("sessions($user, count( $session )) <- session($user, $session)"),
+            Ok(("", expected))
+        );
+
+        assert!(super::rule("sessions($user, count(1)) <- session($user, $session)").is_err());
+    }
+
+    #[test]
+    fn uuid() {
+        use builder::{uuid, Binary, Op};
+
+        let id = [
+            0x67, 0xe5, 0x50, 0x44, 0x10, 0xb1, 0x42, 0x6f, 0x92, 0x47, 0xbb, 0x68, 0x0e, 0x5f,
+            0xe0, 0xc8,
+        ];
+        assert_eq!(
+            super::term("uuid:67e55044-10b1-426f-9247-bb680e5fe0c8"),
+            Ok(("", uuid(id)))
+        );
+        assert_eq!(
+            super::term("uuid:67E55044-10B1-426F-9247-BB680E5FE0C8"),
+            Ok(("", uuid(id)))
+        );
+        assert!(super::fact("a(uuid:67e55044-10b1-426f-9247-bb680e5fe0c)").is_err());
+        assert!(super::fact("a(uuid:67e5504410b1426f9247bb680e5fe0c8)").is_err());
+
+        assert_eq!(
+            super::expr("$id < uuid:67e55044-10b1-426f-9247-bb680e5fe0c8")
+                .map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(builder::var("id")),
+                    Op::Value(uuid(id)),
+                    Op::Binary(Binary::LessThan),
+                ],
+            ))
+        );
+        assert!(super::fact("ids([uuid:67e55044-10b1-426f-9247-bb680e5fe0c8, hex:00])").is_err());
+    }
+
+    #[test]
+    fn string_methods() {
+        use builder::{string, var, Binary, Op, Unary};
+
+        assert_eq!(
+            super::expr("$path.trim().to_lowercase().split(\"/\")").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(var("path")),
+                    Op::Unary(Unary::Trim),
+                    Op::Unary(Unary::Lowercase),
+                    Op::Value(string("/")),
+                    Op::Binary(Binary::Split),
+                ],
+            ))
+        );
+        assert_eq!(
+            super::expr("$path.capture(\"^/([a-z]+)\")").map(|(i, o)| (i, o.opcodes())),
+            Ok((
+                "",
+                vec![
+                    Op::Value(var("path")),
+                    Op::Value(string("^/([a-z]+)")),
+                    Op::Binary(Binary::Capture),
+                ],
+            ))
+        );
+    }
 }
@@ -0,0 +1,419 @@
+//! Datalog evaluation at compile time, for `authorizer_assert!`
+//!
+//! This is a small, naive engine working directly on the parser's types.
+//! There is no token: every fact comes from the authorizer, so scopes
+//! do not change the results and are ignored. Expressions follow the
+//! semantics of biscuit-auth. Regular expressions, decimals, and
+//! aggregations are not supported and are reported as errors, so they
+//! are never silently evaluated differently than at runtime.
+//!
+//! The `authorizer_assert_macro` test of biscuit-auth runs each of its cases
+//! through this engine and the runtime authorizer, which must agree.
+use biscuit_parser::builder::{
+    Binary, Check, CheckKind, Expression, Op, Policy, PolicyKind, Predicate, Rule, Term, Unary,
+};
+use std::collections::{HashMap, HashSet};
+
+/// same limits as the default `AuthorizerLimits` in biscuit-auth
+const MAX_FACTS: usize = 1000;
+const MAX_ITERATIONS: usize = 100;
+
+type Bindings = HashMap<String, Term>;
+
+/// result of the authorization
+#[derive(Debug, Default)]
+pub struct Outcome {
+    /// indexes of the checks that failed
+    pub failed_checks: Vec<usize>,
+    /// first policy that matched, with its index
+    pub policy: Option<(PolicyKind, usize)>,
+}
+
+impl Outcome {
+    pub fn is_allowed(&self) -> bool {
+        self.failed_checks.is_empty() && matches!(self.policy, Some((PolicyKind::Allow, _)))
+    }
+}
+
+#[derive(Default)]
+pub struct World {
+    facts: HashSet<Predicate>,
+    rules: Vec<Rule>,
+}
+
+impl World {
+    pub fn add_fact(&mut self, fact: Predicate) -> Result<(), String> {
+        if let Some(term) = fact.terms.iter().find(|t| !is_ground(t)) {
+            return Err(format!(
+                "facts cannot contain variables or parameters, found {:?} in `{}`",
+                term, fact.name
+            ));
+        }
+        self.facts.insert(fact);
+        Ok(())
+    }
+
+    pub fn add_rule(&mut self, rule: Rule) -> Result<(), String> {
+        validate_rule(&rule)?;
+        self.rules.push(rule);
+        Ok(())
+    }
+
+    /// generates facts from the rules until no new fact appears
+    pub fn run(&mut self) -> Result<(), String> {
+        let mut iterations = 0;
+
+        loop {
+            let mut new_facts = HashSet::new();
+            for rule in &self.rules {
+                for bindings in self.combinations(&rule.body) {
+                    if satisfies(&rule.expressions, &bindings)? {
+                        let head = substitute(&rule.head, &bindings)?;
+                        if !self.facts.contains(&head) {
+                            new_facts.insert(head);
+                        }
+                    }
+                }
+            }
+
+            if new_facts.is_empty() {
+                return Ok(());
+            }
+            self.facts.extend(new_facts);
+
+            iterations += 1;
+            if iterations >= MAX_ITERATIONS {
+                return Err(format!(
+                    "the authorizer reaches the limit of {} iterations",
+                    MAX_ITERATIONS
+                ));
+            }
+            if self.facts.len() >= MAX_FACTS {
+                return Err(format!(
+                    "the authorizer reaches the limit of {} facts",
+                    MAX_FACTS
+                ));
+            }
+        }
+    }
+
+    /// runs the checks, then looks for the first matching policy
+    pub fn authorize(&mut self, checks: &[Check], policies: &[Policy]) -> Result<Outcome, String> {
+        for rule in checks
+            .iter()
+            .flat_map(|c| c.queries.iter())
+            .chain(policies.iter().flat_map(|p| p.queries.iter()))
+        {
+            validate_rule(rule)?;
+        }
+
+        self.run()?;
+
+        let mut outcome = Outcome::default();
+
+        for (i, check) in checks.iter().enumerate() {
+            // `reject if` succeeds unless one of its queries matches
+            let reject = check.kind == CheckKind::Reject;
+            let mut successful = reject;
+
+            for query in check.queries.iter() {
+                let matched = match check.kind {
+                    CheckKind::One | CheckKind::Reject => self.find_match(query)?,
+                    CheckKind::All => self.match_all(query)?,
+                };
+
+                if matched {
+                    successful = !reject;
+                    break;
+                }
+            }
+
+            if !successful {
+                outcome.failed_checks.push(i);
+            }
+        }
+
+        'policies: for (i, policy) in policies.iter().enumerate() {
+            for query in policy.queries.iter() {
+                if self.find_match(query)? {
+                    outcome.policy = Some((policy.kind.clone(), i));
+                    break 'policies;
+                }
+            }
+        }
+
+        Ok(outcome)
+    }
+
+    fn find_match(&self, rule: &Rule) -> Result<bool, String> {
+        for bindings in self.combinations(&rule.body) {
+            if satisfies(&rule.expressions, &bindings)? {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    fn match_all(&self, rule: &Rule) -> Result<bool, String> {
+        let mut found = false;
+        for bindings in self.combinations(&rule.body) {
+            found = true;
+            if !satisfies(&rule.expressions, &bindings)? {
+                return Ok(false);
+            }
+        }
+        Ok(found)
+    }
+
+    /// all the variable bindings matching the predicates of a rule body
+    fn combinations(&self, body: &[Predicate]) -> Vec<Bindings> {
+        let mut results = vec![Bindings::new()];
+
+        for predicate in body {
+            let mut next = Vec::new();
+            for bindings in &results {
+                for fact in &self.facts {
+                    if let Some(b) = unify(predicate, fact, bindings) {
+                        next.push(b);
+                    }
+                }
+            }
+            results = next;
+        }
+
+        results
+    }
+}
+
+fn is_ground(term: &Term) -> bool {
+    match term {
+        Term::Variable(_) | Term::Parameter(_) => false,
+        Term::Set(set) => set.iter().all(is_ground),
+        _ => true,
+    }
+}
+
+/// refuses the constructs that this engine cannot evaluate like biscuit-auth,
+/// even if they would never be reached
+fn validate_rule(rule: &Rule) -> Result<(), String> {
+    if !rule.aggregates.is_empty() {
+        return Err("aggregations are not supported in authorizer_assert!".to_string());
+    }
+
+    for op in rule.expressions.iter().flat_map(|e| e.ops.iter()) {
+        match op {
+            Op::Binary(binary @ (Binary::Regex | Binary::Capture | Binary::Split)) => {
+                return Err(format!(
+                    "{:?} is not supported in authorizer_assert!",
+                    binary
+                ))
+            }
+            Op::Value(Term::Decimal(..)) => {
+                return Err("decimals are not supported in authorizer_assert!".to_string())
+            }
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn unify(predicate: &Predicate, fact: &Predicate, bindings: &Bindings) -> Option<Bindings> {
+    if predicate.name != fact.name || predicate.terms.len() != fact.terms.len() {
+        return None;
+    }
+
+    let mut bindings = bindings.clone();
+    for (term, value) in predicate.terms.iter().zip(fact.terms.iter()) {
+        match term {
+            Term::Variable(name) => match bindings.get(name) {
+                Some(bound) if bound != value => return None,
+                Some(_) => {}
+                None => {
+                    bindings.insert(name.clone(), value.clone());
+                }
+            },
+            term if term != value => return None,
+            _ => {}
+        }
+    }
+
+    Some(bindings)
+}
+
+fn substitute(head: &Predicate, bindings: &Bindings) -> Result<Predicate, String> {
+    let terms = head
+        .terms
+        .iter()
+        .map(|term| match term {
+            Term::Variable(name) => bindings.get(name).cloned().ok_or_else(|| {
+                format!("unbound variable ${} in the head of `{}`", name, head.name)
+            }),
+            term => Ok(term.clone()),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Predicate {
+        name: head.name.clone(),
+        terms,
+    })
+}
+
+fn satisfies(expressions: &[Expression], bindings: &Bindings) -> Result<bool, String> {
+    for expression in expressions {
+        match evaluate(expression, bindings)? {
+            Term::Bool(true) => {}
+            Term::Bool(false) => return Ok(false),
+            _ => return Err("an expression did not return a boolean".to_string()),
+        }
+    }
+    Ok(true)
+}
+
+fn evaluate(expression: &Expression, bindings: &Bindings) -> Result<Term, String> {
+    let mut stack: Vec<Term> = Vec::new();
+
+    for op in expression.ops.iter() {
+        match op {
+            Op::Value(Term::Variable(name)) => match bindings.get(name) {
+                Some(term) => stack.push(term.clone()),
+                None => return Err(format!("unknown variable ${} in an expression", name)),
+            },
+            Op::Value(Term::Parameter(name)) => {
+                return Err(format!("unknown parameter {{{}}} in an expression", name))
+            }
+            Op::Value(term) => stack.push(term.clone()),
+            Op::Unary(unary) => match stack.pop() {
+                Some(term) => stack.push(evaluate_unary(unary, term)?),
+                None => return Err("invalid expression".to_string()),
+            },
+            Op::Binary(binary) => match (stack.pop(), stack.pop()) {
+                (Some(right), Some(left)) => stack.push(evaluate_binary(binary, left, right)?),
+                _ => return Err("invalid expression".to_string()),
+            },
+        }
+    }
+
+    if stack.len() == 1 {
+        Ok(stack.remove(0))
+    } else {
+        Err("invalid expression".to_string())
+    }
+}
+
+fn evaluate_unary(unary: &Unary, value: Term) -> Result<Term, String> {
+    match (unary, value) {
+        (Unary::Negate, Term::Bool(b)) => Ok(Term::Bool(!b)),
+        (Unary::Parens, i) => Ok(i),
+        (Unary::Length, Term::Str(s)) => Ok(Term::Integer(s.len() as i64)),
+        (Unary::Length, Term::Bytes(s)) => Ok(Term::Integer(s.len() as i64)),
+        (Unary::Length, Term::Set(s)) => Ok(Term::Integer(s.len() as i64)),
+        (Unary::Lowercase, Term::Str(s)) => Ok(Term::Str(s.to_lowercase())),
+        (Unary::Trim, Term::Str(s)) => Ok(Term::Str(s.trim().to_string())),
+        (unary, value) => Err(format!("invalid type {:?} for {:?}", value, unary)),
+    }
+}
+
+fn evaluate_binary(binary: &Binary, left: Term, right: Term) -> Result<Term, String> {
+    // facts can still bring decimals to expressions
+    if matches!(left, Term::Decimal(..)) || matches!(right, Term::Decimal(..)) {
+        return Err("decimals are not supported in authorizer_assert!".to_string());
+    }
+
+    match (binary, left, right) {
+        // integer
+        (Binary::LessThan, Term::Integer(i), Term::Integer(j)) => Ok(Term::Bool(i < j)),
+        (Binary::GreaterThan, Term::Integer(i), Term::Integer(j)) => Ok(Term::Bool(i > j)),
+        (Binary::LessOrEqual, Term::Integer(i), Term::Integer(j)) => Ok(Term::Bool(i <= j)),
+        (Binary::GreaterOrEqual, Term::Integer(i), Term::Integer(j)) => Ok(Term::Bool(i >= j)),
+        (Binary::Equal, Term::Integer(i), Term::Integer(j)) => Ok(Term::Bool(i == j)),
+        (Binary::NotEqual, Term::Integer(i), Term::Integer(j)) => Ok(Term::Bool(i != j)),
+        (Binary::Add, Term::Integer(i), Term::Integer(j)) => i
+            .checked_add(j)
+            .map(Term::Integer)
+            .ok_or_else(|| "integer overflow".to_string()),
+        (Binary::Sub, Term::Integer(i), Term::Integer(j)) => i
+            .checked_sub(j)
+            .map(Term::Integer)
+            .ok_or_else(|| "integer overflow".to_string()),
+        (Binary::Mul, Term::Integer(i), Term::Integer(j)) => i
+            .checked_mul(j)
+            .map(Term::Integer)
+            .ok_or_else(|| "integer overflow".to_string()),
+        (Binary::Div, Term::Integer(i), Term::Integer(j)) => i
+            .checked_div(j)
+            .map(Term::Integer)
+            .ok_or_else(|| "division by zero".to_string()),
+        (Binary::BitwiseAnd, Term::Integer(i), Term::Integer(j)) => Ok(Term::Integer(i & j)),
+        (Binary::BitwiseOr, Term::Integer(i), Term::Integer(j)) => Ok(Term::Integer(i | j)),
+        (Binary::BitwiseXor, Term::Integer(i), Term::Integer(j)) => Ok(Term::Integer(i ^ j)),
+
+        // string
+        (Binary::Prefix, Term::Str(s), Term::Str(p)) => Ok(Term::Bool(s.starts_with(&p))),
+        (Binary::Suffix, Term::Str(s), Term::Str(p)) => Ok(Term::Bool(s.ends_with(&p))),
+        (Binary::Contains, Term::Str(s), Term::Str(p)) => Ok(Term::Bool(s.contains(&p))),
+        (Binary::Add, Term::Str(s1), Term::Str(s2)) => Ok(Term::Str(s1 + &s2)),
+        (Binary::Equal, Term::Str(i), Term::Str(j)) => Ok(Term::Bool(i == j)),
+        (Binary::NotEqual, Term::Str(i), Term::Str(j)) => Ok(Term::Bool(i != j)),
+
+        // date
+        (Binary::LessThan, Term::Date(i), Term::Date(j)) => Ok(Term::Bool(i < j)),
+        (Binary::GreaterThan, Term::Date(i), Term::Date(j)) => Ok(Term::Bool(i > j)),
+        (Binary::LessOrEqual, Term::Date(i), Term::Date(j)) => Ok(Term::Bool(i <= j)),
+        (Binary::GreaterOrEqual, Term::Date(i), Term::Date(j)) => Ok(Term::Bool(i >= j)),
+        (Binary::Equal, Term::Date(i), Term::Date(j)) => Ok(Term::Bool(i == j)),
+        (Binary::NotEqual, Term::Date(i), Term::Date(j)) => Ok(Term::Bool(i != j)),
+
+        // byte array
+        (Binary::Equal, Term::Bytes(i), Term::Bytes(j)) => Ok(Term::Bool(i == j)),
+        (Binary::NotEqual, Term::Bytes(i), Term::Bytes(j)) => Ok(Term::Bool(i != j)),
+
+        // uuid
+        (Binary::LessThan, Term::Uuid(i), Term::Uuid(j)) => Ok(Term::Bool(i < j)),
+        (Binary::GreaterThan, Term::Uuid(i), Term::Uuid(j)) => Ok(Term::Bool(i > j)),
+        (Binary::LessOrEqual, Term::Uuid(i), Term::Uuid(j)) => Ok(Term::Bool(i <= j)),
+        (Binary::GreaterOrEqual, Term::Uuid(i), Term::Uuid(j)) => Ok(Term::Bool(i >= j)),
+        (Binary::Equal, Term::Uuid(i), Term::Uuid(j)) => Ok(Term::Bool(i == j)),
+        (Binary::NotEqual, Term::Uuid(i), Term::Uuid(j)) => Ok(Term::Bool(i != j)),
+
+        // set
+        (Binary::Equal, Term::Set(set), Term::Set(s)) => Ok(Term::Bool(set == s)),
+        (Binary::NotEqual, Term::Set(set), Term::Set(s)) => Ok(Term::Bool(set != s)),
+        (Binary::Intersection, Term::Set(set), Term::Set(s)) => {
+            Ok(Term::Set(set.intersection(&s).cloned().collect()))
+        }
+        (Binary::Union, Term::Set(set), Term::Set(s)) => {
+            Ok(Term::Set(set.union(&s).cloned().collect()))
+        }
+        (Binary::Difference, Term::Set(set), Term::Set(s)) => {
+            Ok(Term::Set(set.difference(&s).cloned().collect()))
+        }
+        (Binary::SymmetricDifference, Term::Set(set), Term::Set(s)) => {
+            Ok(Term::Set(set.symmetric_difference(&s).cloned().collect()))
+        }
+        (Binary::IsSubset, Term::Set(set), Term::Set(s)) => Ok(Term::Bool(set.is_subset(&s))),
+        (Binary::IsSuperset, Term::Set(set), Term::Set(s)) => Ok(Term::Bool(set.is_superset(&s))),
+        (Binary::Contains, Term::Set(set), Term::Set(s)) => Ok(Term::Bool(set.is_superset(&s))),
+        (
+            Binary::Contains,
+            Term::Set(set),
+            term @ (Term::Integer(_)
+            | Term::Date(_)
+            | Term::Bool(_)
+            | Term::Str(_)
+            | Term::Bytes(_)
+            | Term::Uuid(_)),
+        ) => Ok(Term::Bool(set.contains(&term))),
+
+        // boolean
+        (Binary::And, Term::Bool(i), Term::Bool(j)) => Ok(Term::Bool(i & j)),
+        (Binary::Or, Term::Bool(i), Term::Bool(j)) => Ok(Term::Bool(i | j)),
+        (Binary::Equal, Term::Bool(i), Term::Bool(j)) => Ok(Term::Bool(i == j)),
+        (Binary::NotEqual, Term::Bool(i), Term::Bool(j)) => Ok(Term::Bool(i != j)),
+
+        (binary, left, right) => Err(format!(
+            "invalid types {:?} and {:?} for {:?}",
+            left, right, binary
+        )),
+    }
+}
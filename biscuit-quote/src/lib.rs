@@ -1,12 +1,14 @@
 //! Procedural macros to build biscuit-auth tokens and authorizers
 
+mod eval;
+
 use biscuit_parser::{
-    builder::{Check, Fact, Policy, Rule},
+    builder::{Check, Fact, Policy, PolicyKind, Rule},
     error,
     parser::{parse_block_source, parse_source},
 };
 use proc_macro2::{Span, TokenStream};
-use proc_macro_error::{abort_call_site, proc_macro_error};
+use proc_macro_error::{abort, abort_call_site, proc_macro_error};
 use quote::{quote, ToTokens};
 use std::collections::{HashMap, HashSet};
 use syn::{
@@ -81,6 +83,52 @@ impl Parse for ParsedMerge {
     }
 }
 
+// parses "\"path/to/file.datalog\", foo = bar, baz = quux"
+struct ParsedFromFile {
+    path: LitStr,
+    parameters: HashMap<String, Expr>,
+}
+
+impl Parse for ParsedFromFile {
+    fn parse(input: ParseStream) -> parse::Result<Self> {
+        let path = input.parse::<LitStr>()?;
+        let parameters = input.parse::<ParsedParameters>()?;
+
+        Ok(Self {
+            path,
+            parameters: parameters.parameters,
+        })
+    }
+}
+
+impl ParsedFromFile {
+    /// reads the file, relative to the directory of the crate's `Cargo.toml`
+    fn read(&self) -> (String, String) {
+        let mut path = std::path::PathBuf::from(
+            std::env::var("CARGO_MANIFEST_DIR").unwrap_or_else(|_| ".".to_string()),
+        );
+        path.push(self.path.value());
+        let path = path.to_string_lossy().into_owned();
+
+        match std::fs::read_to_string(&path) {
+            Ok(datalog) => (path, datalog),
+            Err(e) => abort!(self.path, "could not read {}: {}", path, e),
+        }
+    }
+}
+
+// includes the file in the generated code, so that the crate is rebuilt
+// when the file changes
+fn from_file(path: String, builder: Builder) -> proc_macro::TokenStream {
+    quote! {
+        {
+            const _: &str = include_str!(#path);
+            #builder
+        }
+    }
+    .into()
+}
+
 /// Create a `BlockBuilder` from a datalog string and optional parameters.
 /// The datalog string is parsed at compile time and replaced by manual
 /// block building.
@@ -192,6 +240,274 @@ pub fn biscuit_merge(input: proc_macro::TokenStream) -> proc_macro::TokenStream
     builder.into_token_stream().into()
 }
 
+/// Create a `BlockBuilder` from a datalog file and optional parameters.
+/// The path is relative to the directory containing the crate's `Cargo.toml`.
+/// The file is read and parsed at compile time.
+#[proc_macro]
+#[proc_macro_error]
+pub fn block_from_file(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(input as ParsedFromFile);
+    let (path, datalog) = input.read();
+
+    let ty = syn::parse_quote!(::biscuit_auth::builder::BlockBuilder);
+    let builder = Builder::block_source(ty, None, datalog, input.parameters)
+        .unwrap_or_else(|e| abort!(input.path, "{}: {}", path, e));
+
+    from_file(path, builder)
+}
+
+/// Create an `Authorizer` from a datalog file and optional parameters.
+/// The path is relative to the directory containing the crate's `Cargo.toml`.
+/// The file is read and parsed at compile time.
+#[proc_macro]
+#[proc_macro_error]
+pub fn authorizer_from_file(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(input as ParsedFromFile);
+    let (path, datalog) = input.read();
+
+    let ty = syn::parse_quote!(::biscuit_auth::Authorizer);
+    let builder = Builder::source(ty, None, datalog, input.parameters)
+        .unwrap_or_else(|e| abort!(input.path, "{}: {}", path, e));
+
+    from_file(path, builder)
+}
+
+/// Create a `BiscuitBuilder` from a datalog file and optional parameters.
+/// The path is relative to the directory containing the crate's `Cargo.toml`.
+/// The file is read and parsed at compile time.
+#[proc_macro]
+#[proc_macro_error]
+pub fn biscuit_from_file(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(input as ParsedFromFile);
+    let (path, datalog) = input.read();
+
+    let ty = syn::parse_quote!(::biscuit_auth::builder::BiscuitBuilder);
+    let builder = Builder::block_source(ty, None, datalog, input.parameters)
+        .unwrap_or_else(|e| abort!(input.path, "{}: {}", path, e));
+
+    from_file(path, builder)
+}
+
+// parses "\"...\", facts = \"...\", expect = allow"
+struct ParsedAssert {
+    datalog: LitStr,
+    facts: Option<LitStr>,
+    expect: Expr,
+}
+
+impl Parse for ParsedAssert {
+    fn parse(input: ParseStream) -> parse::Result<Self> {
+        let datalog = input.parse::<LitStr>()?;
+        let mut parameters = input.parse::<ParsedParameters>()?.parameters;
+
+        let facts = match parameters.remove("facts") {
+            None => None,
+            Some(Expr::Lit(syn::ExprLit {
+                lit: syn::Lit::Str(facts),
+                ..
+            })) => Some(facts),
+            Some(e) => return Err(parse::Error::new_spanned(e, "expected a string literal")),
+        };
+        let expect = parameters
+            .remove("expect")
+            .ok_or_else(|| input.error("missing `expect = ...`"))?;
+        if let Some(key) = parameters.keys().next() {
+            return Err(input.error(format!("unknown argument `{}`", key)));
+        }
+
+        Ok(Self {
+            datalog,
+            facts,
+            expect,
+        })
+    }
+}
+
+// expected authorization result, like `allow`, `deny(1)` or `failed_checks(0, 2)`
+enum Expectation {
+    Allow(Option<usize>),
+    Deny(Option<usize>),
+    NoMatch,
+    FailedChecks(Vec<usize>),
+}
+
+impl Expectation {
+    fn parse(expr: &Expr) -> Self {
+        let (name, args) = match expr {
+            Expr::Path(p) => (p.path.get_ident(), Vec::new()),
+            Expr::Call(c) => match &*c.func {
+                Expr::Path(p) => (
+                    p.path.get_ident(),
+                    c.args
+                        .iter()
+                        .map(|arg| match arg {
+                            Expr::Lit(syn::ExprLit {
+                                lit: syn::Lit::Int(i),
+                                ..
+                            }) => i
+                                .base10_parse::<usize>()
+                                .unwrap_or_else(|e| abort!(i, "{}", e)),
+                            _ => abort!(arg, "expected an index"),
+                        })
+                        .collect(),
+                ),
+                _ => (None, Vec::new()),
+            },
+            _ => (None, Vec::new()),
+        };
+
+        match (name.map(|i| i.to_string()).as_deref(), args.as_slice()) {
+            (Some("allow"), []) => Expectation::Allow(None),
+            (Some("allow"), [i]) => Expectation::Allow(Some(*i)),
+            (Some("deny"), []) => Expectation::Deny(None),
+            (Some("deny"), [i]) => Expectation::Deny(Some(*i)),
+            (Some("no_match"), []) => Expectation::NoMatch,
+            (Some("failed_checks"), checks) if !checks.is_empty() => {
+                Expectation::FailedChecks(checks.to_vec())
+            }
+            _ => abort!(
+                expr,
+                "expected one of `allow`, `allow(policy)`, `deny`, `deny(policy)`, \
+                 `no_match` or `failed_checks(check, ...)`"
+            ),
+        }
+    }
+
+    fn matches(&self, outcome: &eval::Outcome) -> bool {
+        match self {
+            Expectation::Allow(None) => outcome.is_allowed(),
+            Expectation::Allow(Some(i)) => {
+                outcome.is_allowed() && outcome.policy == Some((PolicyKind::Allow, *i))
+            }
+            Expectation::Deny(None) => !outcome.is_allowed(),
+            Expectation::Deny(Some(i)) => outcome.policy == Some((PolicyKind::Deny, *i)),
+            Expectation::NoMatch => outcome.policy.is_none(),
+            Expectation::FailedChecks(checks) => &outcome.failed_checks == checks,
+        }
+    }
+}
+
+fn describe_outcome(
+    outcome: &eval::Outcome,
+    checks: &[(&str, Check)],
+    policies: &[(&str, Policy)],
+) -> String {
+    let mut res: Vec<String> = outcome
+        .failed_checks
+        .iter()
+        .map(|i| format!("check {} failed: `{}`", i, checks[*i].0.trim()))
+        .collect();
+
+    res.push(match &outcome.policy {
+        Some((PolicyKind::Allow, i)) => {
+            format!("allow policy {} matched: `{}`", i, policies[*i].0.trim())
+        }
+        Some((PolicyKind::Deny, i)) => {
+            format!("deny policy {} matched: `{}`", i, policies[*i].0.trim())
+        }
+        None => "no policy matched".to_string(),
+    });
+
+    res.join(", ")
+}
+
+fn has_parameters(rule: &Rule) -> bool {
+    rule.parameters.as_ref().map_or(0, |p| p.len()) > 0
+        || rule.scope_parameters.as_ref().map_or(0, |p| p.len()) > 0
+}
+
+/// Evaluate a static authorizer at compile time, and fail the build if the
+/// authorization result is not the expected one.
+///
+/// The first argument is the authorizer code. Facts and rules can be added
+/// with `facts = "..."`, as if they came from the token. The expected result
+/// is one of `allow`, `allow(policy)`, `deny`, `deny(policy)`, `no_match`
+/// or `failed_checks(check, ...)`, with policies and checks designated by
+/// their index. Nothing is generated, there is no runtime cost.
+#[proc_macro]
+#[proc_macro_error]
+pub fn authorizer_assert(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(input as ParsedAssert);
+    let expectation = Expectation::parse(&input.expect);
+
+    let datalog = input.datalog.value();
+    let source = parse_source(&datalog).unwrap_or_else(|e| {
+        abort!(
+            input.datalog,
+            "{}",
+            error::LanguageError::ParseError(error::ParseErrors::from_source(&datalog, e))
+        )
+    });
+
+    let facts_datalog = input.facts.as_ref().map(|f| f.value()).unwrap_or_default();
+    let facts_source = parse_block_source(&facts_datalog).unwrap_or_else(|e| {
+        abort!(
+            input.facts,
+            "{}",
+            error::LanguageError::ParseError(error::ParseErrors::from_source(&facts_datalog, e))
+        )
+    });
+    if let Some((check, _)) = facts_source.checks.first() {
+        abort!(
+            input.facts,
+            "`facts` only accepts facts and rules, found `{}`",
+            check
+        );
+    }
+
+    let rules = source.rules.iter().chain(facts_source.rules.iter());
+    let queries = source
+        .checks
+        .iter()
+        .flat_map(|(_, c)| c.queries.iter())
+        .chain(source.policies.iter().flat_map(|(_, p)| p.queries.iter()));
+    if source
+        .facts
+        .iter()
+        .chain(facts_source.facts.iter())
+        .any(|(_, f)| f.parameters.as_ref().map_or(0, |p| p.len()) > 0)
+        || rules
+            .clone()
+            .map(|(_, r)| r)
+            .chain(queries)
+            .any(has_parameters)
+    {
+        abort!(
+            input.datalog,
+            "authorizer_assert! does not accept parameters"
+        );
+    }
+
+    let mut world = eval::World::default();
+    for (_, fact) in source.facts.iter().chain(facts_source.facts.iter()) {
+        world
+            .add_fact(fact.predicate.clone())
+            .unwrap_or_else(|e| abort!(input.datalog, "{}", e));
+    }
+    for (_, rule) in rules {
+        world
+            .add_rule(rule.clone())
+            .unwrap_or_else(|e| abort!(input.datalog, "{}", e));
+    }
+
+    let checks: Vec<Check> = source.checks.iter().map(|(_, c)| c.clone()).collect();
+    let policies: Vec<Policy> = source.policies.iter().map(|(_, p)| p.clone()).collect();
+    let outcome = world
+        .authorize(&checks, &policies)
+        .unwrap_or_else(|e| abort!(input.datalog, "authorization failed: {}", e));
+
+    if !expectation.matches(&outcome) {
+        abort!(
+            input.expect,
+            "expected `{}`, but {}",
+            input.expect.to_token_stream(),
+            describe_outcome(&outcome, &source.checks, &source.policies)
+        );
+    }
+
+    proc_macro::TokenStream::new()
+}
+
 #[derive(Clone, Debug)]
 struct Builder {
     pub builder_type: TypePath,
@@ -239,7 +555,9 @@ impl Builder {
         parameters: HashMap<String, Expr>,
     ) -> Result<Builder, error::LanguageError> {
         let mut builder = Builder::new(builder_type, target, parameters);
-        let source = parse_block_source(source.as_ref())?;
+        let source = parse_block_source(source.as_ref()).map_err(|e| {
+            error::LanguageError::ParseError(error::ParseErrors::from_source(source.as_ref(), e))
+        })?;
 
         builder.facts(source.facts.into_iter().map(|(_name, fact)| fact));
         builder.rules(source.rules.into_iter().map(|(_name, rule)| rule));
@@ -256,7 +574,9 @@ impl Builder {
         parameters: HashMap<String, Expr>,
     ) -> Result<Builder, error::LanguageError> {
         let mut builder = Builder::new(builder_type, target, parameters);
-        let source = parse_source(source.as_ref())?;
+        let source = parse_source(source.as_ref()).map_err(|e| {
+            error::LanguageError::ParseError(error::ParseErrors::from_source(source.as_ref(), e))
+        })?;
 
         builder.facts(source.facts.into_iter().map(|(_name, fact)| fact));
         builder.rules(source.rules.into_iter().map(|(_name, rule)| rule));
@@ -770,3 +1090,473 @@ pub fn policy(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
     })
     .into()
 }
+
+/// a piece of the source of a `*_dyn!` macro
+enum Segment {
+    Source(String),
+    /// `{name:ident}`, replaced at runtime by the value of `name`
+    Fragment(String),
+}
+
+/// splits the source on `{name:ident}` fragments, outside of string literals
+fn split_fragments(datalog: &str) -> Vec<Segment> {
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    let mut in_string = false;
+    let mut rest = datalog;
+
+    while let Some(c) = rest.chars().next() {
+        if !in_string && c == '{' {
+            if let Some(end) = rest.find('}') {
+                if let Some(name) = rest[1..end].strip_suffix(":ident") {
+                    if !name.is_empty() && name.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                        segments.push(Segment::Source(std::mem::take(&mut current)));
+                        segments.push(Segment::Fragment(name.to_string()));
+                        rest = &rest[end + 1..];
+                        continue;
+                    }
+                }
+            }
+        }
+
+        if c == '"' {
+            in_string = !in_string;
+        } else if c == '\\' && in_string {
+            if let Some(escaped) = rest[1..].chars().next() {
+                current.push(c);
+                current.push(escaped);
+                rest = &rest[1 + escaped.len_utf8()..];
+                continue;
+            }
+        }
+        current.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+
+    segments.push(Segment::Source(current));
+    segments
+}
+
+struct DynBuilder {
+    builder_type: TypePath,
+    parameters: HashMap<String, Expr>,
+    segments: Vec<Segment>,
+    term_parameters: HashSet<String>,
+    scope_parameters: HashSet<String>,
+}
+
+impl DynBuilder {
+    /// checks the source at compile time, with each fragment replaced by a
+    /// placeholder name
+    fn new(
+        builder_type: TypePath,
+        datalog: String,
+        parameters: HashMap<String, Expr>,
+        block: bool,
+    ) -> Result<Self, error::LanguageError> {
+        let segments = split_fragments(&datalog);
+        let fragments: HashSet<String> = segments
+            .iter()
+            .filter_map(|s| match s {
+                Segment::Fragment(name) => Some(name.clone()),
+                Segment::Source(_) => None,
+            })
+            .collect();
+        let skeleton: String = segments
+            .iter()
+            .map(|s| match s {
+                Segment::Source(source) => source.clone(),
+                Segment::Fragment(name) => format!("__dyn_{name}"),
+            })
+            .collect();
+
+        let datalog_parameters = parameters
+            .iter()
+            .filter(|(name, _)| !fragments.contains(*name))
+            .map(|(name, expr)| (name.clone(), expr.clone()))
+            .collect();
+        let ty = builder_type.clone();
+        let builder = if block {
+            Builder::block_source(ty, None, skeleton, datalog_parameters)?
+        } else {
+            Builder::source(ty, None, skeleton, datalog_parameters)?
+        };
+
+        let mut term_parameters = HashSet::new();
+        let mut scope_parameters = HashSet::new();
+        let rules = builder
+            .rules
+            .iter()
+            .chain(builder.checks.iter().flat_map(|c| c.queries.iter()))
+            .chain(builder.policies.iter().flat_map(|p| p.queries.iter()));
+        for rule in rules {
+            term_parameters.extend(rule.parameters.iter().flatten().map(|(k, _)| k.clone()));
+            scope_parameters.extend(
+                rule.scope_parameters
+                    .iter()
+                    .flatten()
+                    .map(|(k, _)| k.clone()),
+            );
+        }
+        for fact in &builder.facts {
+            term_parameters.extend(fact.parameters.iter().flatten().map(|(k, _)| k.clone()));
+        }
+
+        Ok(DynBuilder {
+            builder_type,
+            parameters,
+            segments,
+            term_parameters,
+            scope_parameters,
+        })
+    }
+}
+
+impl ToTokens for DynBuilder {
+    fn to_tokens(&self, tokens: &mut TokenStream) {
+        let (ident, expr): (Vec<_>, Vec<_>) = self
+            .parameters
+            .iter()
+            .map(|(name, expr)| (Ident::new(name, Span::call_site()), expr))
+            .unzip();
+
+        let segments = self.segments.iter().map(|s| match s {
+            Segment::Source(source) => quote! {
+                ::biscuit_auth::macros::__dyn::Segment::Source(#source)
+            },
+            Segment::Fragment(name) => {
+                let ident = Ident::new(name, Span::call_site());
+                quote! {
+                    ::biscuit_auth::macros::__dyn::Segment::Fragment(#name, &#ident)
+                }
+            }
+        });
+
+        let params = |names: &HashSet<String>| {
+            let params = names.iter().map(|name| {
+                let ident = Ident::new(name, Span::call_site());
+                quote! {
+                    (#name, ::biscuit_auth::builder::ToAnyParam::to_any_param(&#ident))
+                }
+            });
+            quote! { ::std::vec![#(#params),*] }
+        };
+        let term_parameters = params(&self.term_parameters);
+        let scope_parameters = params(&self.scope_parameters);
+
+        let builder_type = &self.builder_type;
+        tokens.extend(quote! {
+            {
+                let (#(#ident),*) = (#(#expr),*);
+                ::biscuit_auth::macros::__dyn::build(
+                    <#builder_type>::new(),
+                    &[#(#segments),*],
+                    #term_parameters,
+                    #scope_parameters,
+                )
+            }
+        });
+    }
+}
+
+fn dyn_builder(
+    input: proc_macro::TokenStream,
+    builder_type: TypePath,
+    block: bool,
+) -> proc_macro::TokenStream {
+    let ParsedCreateNew {
+        datalog,
+        parameters,
+    } = syn::parse_macro_input!(input as ParsedCreateNew);
+
+    let builder = DynBuilder::new(builder_type, datalog, parameters, block)
+        .unwrap_or_else(|e| abort_call_site!(e.to_string()));
+
+    builder.into_token_stream().into()
+}
+
+/// Create a `BlockBuilder` from a datalog string with `{name:ident}` fragments,
+/// interpolated at runtime. The rest of the datalog string is checked at
+/// compile time, and the result is parsed at runtime.
+#[proc_macro]
+#[proc_macro_error]
+pub fn block_dyn(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    dyn_builder(
+        input,
+        syn::parse_quote!(::biscuit_auth::builder::BlockBuilder),
+        true,
+    )
+}
+
+/// Create an `Authorizer` from a datalog string with `{name:ident}` fragments,
+/// interpolated at runtime. The rest of the datalog string is checked at
+/// compile time, and the result is parsed at runtime.
+#[proc_macro]
+#[proc_macro_error]
+pub fn authorizer_dyn(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    dyn_builder(input, syn::parse_quote!(::biscuit_auth::Authorizer), false)
+}
+
+/// Create a `BiscuitBuilder` from a datalog string with `{name:ident}` fragments,
+/// interpolated at runtime. The rest of the datalog string is checked at
+/// compile time, and the result is parsed at runtime.
+#[proc_macro]
+#[proc_macro_error]
+pub fn biscuit_dyn(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    dyn_builder(
+        input,
+        syn::parse_quote!(::biscuit_auth::builder::BiscuitBuilder),
+        true,
+    )
+}
+
+/// Derive conversions between a struct and a `Fact`.
+///
+/// The struct fields are mapped, in order, to the terms of the fact. The
+/// predicate name defaults to the struct name in snake case, and can be set
+/// with `#[biscuit(name = "...")]`.
+///
+/// The generated `query_rule()` function returns a rule matching these facts,
+/// with a variable named after each field (`term0`, `term1`... for tuple structs).
+#[proc_macro_derive(BiscuitFact, attributes(biscuit))]
+#[proc_macro_error]
+pub fn derive_biscuit_fact(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(input as syn::DeriveInput);
+
+    let ident = &input.ident;
+    let name = fact_name(&input).unwrap_or_else(|| snake_case(&ident.to_string()));
+
+    let fields = match &input.data {
+        syn::Data::Struct(data) => &data.fields,
+        _ => abort_call_site!("BiscuitFact can only be derived for structs"),
+    };
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let count = fields.len();
+    let (term_values, construct) = map_fields(ident, fields);
+    let variables = fields
+        .iter()
+        .enumerate()
+        .map(|(i, field)| match &field.ident {
+            Some(ident) => ident.to_string().trim_start_matches("r#").to_string(),
+            None => format!("term{}", i),
+        });
+
+    (quote! {
+        impl #impl_generics #ident #ty_generics #where_clause {
+            /// rule querying the facts converted to this type
+            pub fn query_rule() -> ::biscuit_auth::builder::Rule {
+                let terms: [::biscuit_auth::builder::Term; #count] =
+                    [#(::biscuit_auth::builder::var(#variables)),*];
+                ::biscuit_auth::builder::rule(
+                    #name,
+                    &terms,
+                    &[::biscuit_auth::builder::pred(#name, &terms)],
+                )
+            }
+        }
+
+        impl #impl_generics ::core::convert::TryFrom<::biscuit_auth::builder::Fact> for #ident #ty_generics #where_clause {
+            type Error = ::biscuit_auth::error::Token;
+
+            fn try_from(fact: ::biscuit_auth::builder::Fact) -> ::core::result::Result<Self, Self::Error> {
+                if fact.predicate.name != #name {
+                    return ::core::result::Result::Err(::biscuit_auth::error::Token::ConversionError(
+                        ::std::format!("expected a `{}` fact, got `{}`", #name, fact.predicate.name),
+                    ));
+                }
+                if fact.predicate.terms.len() != #count {
+                    return ::core::result::Result::Err(::biscuit_auth::error::Token::ConversionError(
+                        ::std::format!(
+                            "expected {} terms in fact `{}`, got {}",
+                            #count,
+                            #name,
+                            fact.predicate.terms.len()
+                        ),
+                    ));
+                }
+
+                #[allow(unused_mut, unused_variables)]
+                let mut terms = fact.predicate.terms.into_iter();
+                ::core::result::Result::Ok(#construct)
+            }
+        }
+
+        impl #impl_generics ::core::convert::From<#ident #ty_generics> for ::biscuit_auth::builder::Fact #where_clause {
+            #[allow(unused_variables)]
+            fn from(value: #ident #ty_generics) -> Self {
+                ::biscuit_auth::builder::Fact::new(
+                    ::std::string::String::from(#name),
+                    ::std::vec![#term_values],
+                )
+            }
+        }
+    })
+    .into()
+}
+
+/// Derive a conversion from a `Fact` to a struct, to read query results.
+///
+/// The terms of the fact are mapped, in order, to the struct fields, whatever
+/// the predicate name. A query can be declared with `#[from_fact(query = "...")]`:
+/// the rule is parsed at compile time, its head must have one term per field,
+/// and it is returned by the generated `query_rule()` function.
+#[proc_macro_derive(FromFact, attributes(from_fact))]
+#[proc_macro_error]
+pub fn derive_from_fact(input: proc_macro::TokenStream) -> proc_macro::TokenStream {
+    let input = syn::parse_macro_input!(input as syn::DeriveInput);
+
+    let ident = &input.ident;
+    let fields = match &input.data {
+        syn::Data::Struct(data) => &data.fields,
+        _ => abort_call_site!("FromFact can only be derived for structs"),
+    };
+
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let count = fields.len();
+    let (_, construct) = map_fields(ident, fields);
+
+    let query_rule = from_fact_query(&input).map(|query| {
+        let rule = match biscuit_parser::parser::rule(&query.value()) {
+            Ok((remaining, rule)) if remaining.trim().is_empty() => rule,
+            Ok(_) | Err(_) => abort!(query, "invalid query rule"),
+        };
+        if let Err(message) = rule.validate_variables() {
+            abort!(query, "{}", message);
+        }
+        if Item::rule_params(&rule).next().is_some() {
+            abort!(query, "query rules cannot have parameters");
+        }
+        if rule.head.terms.len() != count {
+            abort!(
+                query,
+                "the query returns {} terms, but `{}` has {} fields",
+                rule.head.terms.len(),
+                ident,
+                count
+            );
+        }
+
+        quote! {
+            impl #impl_generics #ident #ty_generics #where_clause {
+                /// the query declared with `#[from_fact(query = "...")]`
+                pub fn query_rule() -> ::biscuit_auth::builder::Rule {
+                    #rule
+                }
+            }
+        }
+    });
+
+    (quote! {
+        impl #impl_generics ::core::convert::TryFrom<::biscuit_auth::builder::Fact> for #ident #ty_generics #where_clause {
+            type Error = ::biscuit_auth::error::Token;
+
+            fn try_from(fact: ::biscuit_auth::builder::Fact) -> ::core::result::Result<Self, Self::Error> {
+                if fact.predicate.terms.len() != #count {
+                    return ::core::result::Result::Err(::biscuit_auth::error::Token::ConversionError(
+                        ::std::format!(
+                            "expected {} terms in fact `{}`, got {}",
+                            #count,
+                            fact.predicate.name,
+                            fact.predicate.terms.len()
+                        ),
+                    ));
+                }
+
+                #[allow(unused_mut, unused_variables)]
+                let mut terms = fact.predicate.terms.into_iter();
+                ::core::result::Result::Ok(#construct)
+            }
+        }
+
+        #query_rule
+    })
+    .into()
+}
+
+// `term_values` reads each field from `value`, `construct` builds the struct from `terms`
+fn map_fields(ident: &Ident, fields: &syn::Fields) -> (TokenStream, TokenStream) {
+    match fields {
+        syn::Fields::Named(fields) => {
+            let idents = fields
+                .named
+                .iter()
+                .map(|f| f.ident.as_ref().unwrap())
+                .collect::<Vec<_>>();
+            (
+                quote! { #(::biscuit_auth::builder::Term::from(value.#idents)),* },
+                quote! {
+                    #ident {
+                        #(#idents: ::core::convert::TryFrom::try_from(terms.next().unwrap())?),*
+                    }
+                },
+            )
+        }
+        syn::Fields::Unnamed(fields) => {
+            let indexes = (0..fields.unnamed.len())
+                .map(syn::Index::from)
+                .collect::<Vec<_>>();
+            let next = indexes.iter().map(|_| {
+                quote! { ::core::convert::TryFrom::try_from(terms.next().unwrap())? }
+            });
+            (
+                quote! { #(::biscuit_auth::builder::Term::from(value.#indexes)),* },
+                quote! { #ident(#(#next),*) },
+            )
+        }
+        syn::Fields::Unit => (quote! {}, quote! { #ident }),
+    }
+}
+
+// reads `#[from_fact(query = "...")]`
+fn from_fact_query(input: &syn::DeriveInput) -> Option<LitStr> {
+    let attr = input.attrs.iter().find(|a| a.path.is_ident("from_fact"))?;
+
+    let list = match attr.parse_meta() {
+        Ok(syn::Meta::List(list)) => list,
+        _ => abort!(attr, "expected #[from_fact(query = \"...\")]"),
+    };
+
+    match list.nested.first() {
+        Some(syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+            path,
+            lit: syn::Lit::Str(query),
+            ..
+        }))) if list.nested.len() == 1 && path.is_ident("query") => Some(query.clone()),
+        _ => abort!(attr, "expected #[from_fact(query = \"...\")]"),
+    }
+}
+
+// reads `#[biscuit(name = "...")]`
+fn fact_name(input: &syn::DeriveInput) -> Option<String> {
+    let attr = input.attrs.iter().find(|a| a.path.is_ident("biscuit"))?;
+
+    let list = match attr.parse_meta() {
+        Ok(syn::Meta::List(list)) => list,
+        _ => abort_call_site!("expected #[biscuit(name = \"...\")]"),
+    };
+
+    match list.nested.first() {
+        Some(syn::NestedMeta::Meta(syn::Meta::NameValue(syn::MetaNameValue {
+            path,
+            lit: syn::Lit::Str(name),
+            ..
+        }))) if list.nested.len() == 1 && path.is_ident("name") => Some(name.value()),
+        _ => abort_call_site!("expected #[biscuit(name = \"...\")]"),
+    }
+}
+
+fn snake_case(s: &str) -> String {
+    let mut res = String::new();
+    for (i, c) in s.chars().enumerate() {
+        if c.is_uppercase() {
+            if i > 0 {
+                res.push('_');
+            }
+            res.extend(c.to_lowercase());
+        } else {
+            res.push(c);
+        }
+    }
+    res
+}